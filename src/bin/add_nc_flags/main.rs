@@ -9,6 +9,9 @@ use std::{
 
 use clap::{Args, Parser, Subcommand};
 use error_stack::ResultExt;
+use ggg_rs::nc_utils::NcArray;
+use ggg_rs::utils::{Combination, GreaterLess};
+use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 
 #[cfg(test)]
@@ -24,13 +27,17 @@ fn main() -> ExitCode {
 }
 
 fn main_inner() -> error_stack::Result<(), CliError> {
-    let args = Cli::parse();
+    run(Cli::parse())
+}
+
+fn run(args: Cli) -> error_stack::Result<(), CliError> {
     match args.command {
         Commands::Quick(quick_args) => {
             let no_filters_defined = quick_args.filtering.filter.no_filters();
             let output = quick_args.output.clone();
             let nc_file = quick_args.nc_file.clone();
-            let nchanged = driver(output, quick_args.into(), &nc_file)?;
+            let release_ack = quick_args.release_ack.i_am_caltech_staff;
+            let nchanged = driver(output, quick_args.into(), &nc_file, release_ack)?;
             if nchanged == 0 && no_filters_defined {
                 println!("Note: nothing flagged because you gave neither the --less-than nor --greater-than argument.");
             }
@@ -38,7 +45,12 @@ fn main_inner() -> error_stack::Result<(), CliError> {
         Commands::Toml(toml_args) => {
             let filter_set = toml_args.load_filters()?;
             let no_filters_defined = filter_set.no_filters();
-            let nchanged = driver(toml_args.output, filter_set, &toml_args.nc_file)?;
+            let nchanged = driver(
+                toml_args.output,
+                filter_set,
+                &toml_args.nc_file,
+                toml_args.release_ack.i_am_caltech_staff,
+            )?;
             if nchanged == 0 && no_filters_defined {
                 println!(
                     "Note: nothing flagged because no filters were defined in the given JSON file"
@@ -57,7 +69,23 @@ fn driver(
     output: OutputCli,
     filters: FilterSet,
     nc_file: &Path,
+    release_ack: bool,
 ) -> error_stack::Result<u64, CliError> {
+    if filters.flags.remove && matches!(filters.flags.flag_type, FlagType::Release) {
+        return Err(CliError::UserError(
+            "Removing flags is only supported for manual flags, not release flags".to_string(),
+        )
+        .into());
+    }
+
+    // Release flags have special meaning in the public files, so make sure they can't be
+    // set by accident before we touch any files.
+    if matches!(filters.flags.flag_type, FlagType::Release) && !release_ack {
+        return Err(CliError::UserError(
+            "Setting release flags requires the --i-am-caltech-staff flag. Most users should use manual flags instead.".to_string()
+        ).into());
+    }
+
     // One check - if we are outputting to a new path, make sure that isn't a directory but
     // that its parent directory exists. This way we can give a clearer error message.
     if let Some(out_file) = &output.output {
@@ -84,7 +112,10 @@ fn driver(
     }
 
     let data = load_flags_and_data(nc_file, &filters.filter_vars())?;
-    let (new_flags, nchanged) = update_flags(data, &filters, &filters.flags)?;
+    let (new_flags, nchanged, group_matches) = update_flags(data, &filters, &filters.flags)?;
+    if filters.groups.len() > 1 {
+        filters.print_group_matches(&group_matches);
+    }
 
     if nchanged == 0 {
         if output.in_place {
@@ -146,6 +177,8 @@ enum CliError {
     MissingReqVariable(&'static str),
     #[error("The filter variable '{0}' is not present in this file")]
     MissingFilterVariable(String),
+    #[error("The filter variable '{0}' is not numeric")]
+    NonNumericFilterVariable(String),
     #[error("The variable {0} was not {1}D")]
     WrongDimension(String, u8),
     #[error("The filter, timestamp, and flag variables are not all the same length")]
@@ -184,13 +217,29 @@ fn load_flags_and_data<S: AsRef<str> + ToString>(
 
     let mut filter_vars = HashMap::new();
     for varname in filter_varnames {
-        let data = ds.variable(varname.as_ref())
-            .ok_or_else(|| CliError::MissingFilterVariable(varname.to_string()))?
-            .get::<f32, _>(netcdf::Extents::All)
-            .change_context(CliError::NcError)
-            .attach_printable("This error may be caused by trying to filter on a variable that is not of type 'float'")?
-            .into_dimensionality::<ndarray::Ix1>()
-            .change_context_lazy(|| CliError::WrongDimension(varname.to_string(), 1))?;
+        let var = ds
+            .variable(varname.as_ref())
+            .ok_or_else(|| CliError::MissingFilterVariable(varname.to_string()))?;
+        // NcArray::get_from does not support strings or user-defined types, so reject
+        // anything that isn't a plain number first.
+        if !matches!(
+            var.vartype(),
+            netcdf::types::NcVariableType::Int(_) | netcdf::types::NcVariableType::Float(_)
+        ) {
+            return Err(CliError::NonNumericFilterVariable(varname.to_string()).into());
+        }
+        let data = match NcArray::get_from(&var).change_context(CliError::NcError)? {
+            NcArray::F32(arr) => FilterValues::F32(
+                arr.into_dimensionality::<ndarray::Ix1>()
+                    .change_context_lazy(|| CliError::WrongDimension(varname.to_string(), 1))?,
+            ),
+            arr => FilterValues::F64(
+                arr.to_f64()
+                    .ok_or_else(|| CliError::NonNumericFilterVariable(varname.to_string()))?
+                    .into_dimensionality::<ndarray::Ix1>()
+                    .change_context_lazy(|| CliError::WrongDimension(varname.to_string(), 1))?,
+            ),
+        };
         filter_vars.insert(varname.to_string(), data);
     }
 
@@ -201,9 +250,33 @@ fn load_flags_and_data<S: AsRef<str> + ToString>(
     })
 }
 
+/// The values of one filter variable. Single precision variables are kept as `f32` so that
+/// they are compared against the limits in their own precision; e.g. a value stored as
+/// `0.1f32` is slightly above `0.1f64` and would otherwise not be flagged by `--less-than 0.1`.
+#[derive(Debug)]
+enum FilterValues {
+    F32(ndarray::Array1<f32>),
+    F64(ndarray::Array1<f64>),
+}
+
+impl FilterValues {
+    fn len(&self) -> usize {
+        match self {
+            FilterValues::F32(arr) => arr.len(),
+            FilterValues::F64(arr) => arr.len(),
+        }
+    }
+}
+
+impl From<ndarray::Array1<f64>> for FilterValues {
+    fn from(value: ndarray::Array1<f64>) -> Self {
+        Self::F64(value)
+    }
+}
+
 #[derive(Debug)]
 struct TcconData {
-    filter_vars: HashMap<String, ndarray::Array1<f32>>,
+    filter_vars: HashMap<String, FilterValues>,
     timestamps: ndarray::Array1<f64>,
     flags: ndarray::Array1<i16>,
 }
@@ -214,17 +287,19 @@ impl TcconData {
     ) -> (
         ndarray::Array1<f64>,
         ndarray::Array1<i16>,
-        HashMap<String, ndarray::Array1<f32>>,
+        HashMap<String, FilterValues>,
     ) {
         (self.timestamps, self.flags, self.filter_vars)
     }
 }
 
+/// Compute the new flag values. Returns the flags, the number of them that changed,
+/// and how many observations (within the time limits) each filter group matched.
 fn update_flags(
     data: TcconData,
     filtering: &FilterSet,
     flagging: &Flags,
-) -> error_stack::Result<(ndarray::Array1<i16>, u64), CliError> {
+) -> error_stack::Result<(ndarray::Array1<i16>, u64, Vec<u64>), CliError> {
     let timestamp_check = GreaterLess {
         greater_than: filtering
             .timespan
@@ -236,6 +311,10 @@ fn update_flags(
             .map(|dt| dt.and_utc().timestamp() as f64),
         combination: filtering.timespan.time_mode,
     };
+    let since_cutoff = filtering
+        .timespan
+        .since
+        .and_then(|since| since.cutoff_timestamp(data.timestamps.view()));
 
     for data_arr in data.filter_vars.values() {
         if data_arr.len() != data.timestamps.len() || data_arr.len() != data.flags.len() {
@@ -244,6 +323,7 @@ fn update_flags(
     }
 
     let mut nchanged = 0;
+    let mut group_matches = vec![0; filtering.groups.len()];
     let (data_timestamps, mut data_flags, filter_data) = data.into_parts();
 
     for (i, f) in data_flags.iter_mut().enumerate() {
@@ -255,10 +335,18 @@ fn update_flags(
             continue;
         }
 
-        if filtering.do_flag(&filter_data, i) {
-            let new = flagging.flag_type.update_flag(*f, flagging.flag, flagging.existing_flags)
-                .change_context_lazy(|| CliError::FlagReplaceError(i))
-                .attach_printable("Suggestion: this may be because part of the file's timespan already has a manual or release flag set. Either use the date limits to work around that, or allow skipping/overwriting existing flags with the --existing-flags option.")?;
+        if since_cutoff.is_some_and(|cutoff| *t < cutoff) {
+            continue;
+        }
+
+        if filtering.do_flag(&filter_data, i, &mut group_matches) {
+            let new = if flagging.remove {
+                flagging.flag_type.remove_flag(*f, flagging.flag)
+            } else {
+                flagging.flag_type.update_flag(*f, flagging.flag, flagging.existing_flags)
+                    .change_context_lazy(|| CliError::FlagReplaceError(i))
+                    .attach_printable("Suggestion: this may be because part of the file's timespan already has a manual or release flag set. Either use the date limits to work around that, or allow skipping/overwriting existing flags with the --existing-flags option.")?
+            };
 
             if &new != f {
                 *f = new;
@@ -267,7 +355,7 @@ fn update_flags(
         }
     }
 
-    Ok((data_flags, nchanged))
+    Ok((data_flags, nchanged, group_matches))
 }
 
 /// Add manual or release flags in a TCCON private netCDF file.
@@ -307,6 +395,9 @@ struct QuickCli {
     #[command(flatten)]
     filtering: FilterCli,
 
+    #[command(flatten)]
+    release_ack: ReleaseAckCli,
+
     /// The path to the input netCDF file to add flags to.
     #[clap(long)]
     nc_file: PathBuf,
@@ -318,6 +409,9 @@ struct TomlCli {
     #[command(flatten)]
     output: OutputCli,
 
+    #[command(flatten)]
+    release_ack: ReleaseAckCli,
+
     /// Path to a TOML file containing the filter settings.
     toml_file: PathBuf,
 
@@ -364,6 +458,15 @@ struct OutputCli {
     always_copy: bool,
 }
 
+#[derive(Debug, Clone, Args)]
+struct ReleaseAckCli {
+    /// Acknowledge that you are Caltech staff setting release flags. This is required
+    /// when the flag type is "release" (whether from --flag-type or the TOML file);
+    /// without it, the program will exit with an error before modifying any files.
+    #[clap(long)]
+    i_am_caltech_staff: bool,
+}
+
 #[derive(Debug, Clone, Args, Deserialize, Serialize)]
 struct Flags {
     /// Value to use when flagging data. This must be a value between 1 and 9,
@@ -390,6 +493,14 @@ struct Flags {
     #[clap(long, default_value_t = FlagType::default())]
     #[serde(default)]
     flag_type: FlagType,
+
+    /// Instead of adding flags, remove manual flags from observations matching the
+    /// filters. Only flags whose value in the 1000s place equals --flag are removed;
+    /// other manual flags and all release flags are left as they are. --existing-flags
+    /// has no effect in this mode.
+    #[clap(long)]
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    remove: bool,
 }
 
 impl Default for Flags {
@@ -398,6 +509,7 @@ impl Default for Flags {
             flag: 9,
             existing_flags: Default::default(),
             flag_type: Default::default(),
+            remove: false,
         }
     }
 }
@@ -416,13 +528,13 @@ struct Filter {
     /// Negative values are allowed.
     #[clap(short = 'l', long, allow_negative_numbers = true)]
     #[serde(default)]
-    less_than: Option<f32>,
+    less_than: Option<f64>,
 
     /// For numeric variables, flag observations greater than this value.
     /// Negative values are allowed.
     #[clap(short = 'g', long, allow_negative_numbers = true)]
     #[serde(default)]
-    greater_than: Option<f32>,
+    greater_than: Option<f64>,
 
     /// If both --less-than and --greater-than are given, this determines
     /// whether the observation is flagged if VARIABLE has a value between
@@ -443,21 +555,31 @@ struct Filter {
 }
 
 impl Filter {
-    fn do_flag(&self, data: &HashMap<String, ndarray::Array1<f32>>, index: usize) -> bool {
-        let comp = GreaterLess {
-            less_than: self.less_than,
-            greater_than: self.greater_than,
-            combination: self.value_mode,
-        };
+    fn do_flag(&self, data: &HashMap<String, FilterValues>, index: usize) -> bool {
+        const LEN_MSG: &str =
+            "All filter variables should have the same number of elements as the flag variable";
 
-        let value = data
+        let values = data
             .get(&self.filter_var)
-            .expect("All filter variables should be loaded before filtering")
-            .get(index)
-            .expect(
-                "All filter variables should have the same number of elements as the flag variable",
-            );
-        comp.do_flag(value)
+            .expect("All filter variables should be loaded before filtering");
+        match values {
+            FilterValues::F32(arr) => {
+                let comp = GreaterLess {
+                    less_than: self.less_than.map(|v| v as f32),
+                    greater_than: self.greater_than.map(|v| v as f32),
+                    combination: self.value_mode,
+                };
+                comp.do_flag(arr.get(index).expect(LEN_MSG))
+            }
+            FilterValues::F64(arr) => {
+                let comp = GreaterLess {
+                    less_than: self.less_than,
+                    greater_than: self.greater_than,
+                    combination: self.value_mode,
+                };
+                comp.do_flag(arr.get(index).expect(LEN_MSG))
+            }
+        }
     }
 
     fn no_filters(&self) -> bool {
@@ -492,6 +614,90 @@ struct Timespan {
     #[clap(long, default_value_t = Combination::default())]
     #[serde(default)]
     time_mode: Combination,
+
+    /// To limit the flags to the most recent data, give a duration such as "30d" or
+    /// "12h" (units may be s, m, h, d, or w). Only measurements within this duration of
+    /// the LAST TIME IN THE FILE (not the current time) will be flagged. This is applied
+    /// in addition to --time-less-than and --time-greater-than, so whichever is more
+    /// restrictive wins.
+    #[clap(long)]
+    #[serde(default)]
+    since: Option<SinceDuration>,
+}
+
+/// A duration back in time from the end of a file's data, used by the `--since` option.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+struct SinceDuration {
+    value: u32,
+    unit: char,
+}
+
+impl SinceDuration {
+    fn num_seconds(&self) -> i64 {
+        let unit_seconds = match self.unit {
+            's' => 1,
+            'm' => 60,
+            'h' => 3600,
+            'd' => 86400,
+            'w' => 7 * 86400,
+            _ => unreachable!("SinceDuration should only be constructed with a valid unit"),
+        };
+        self.value as i64 * unit_seconds
+    }
+
+    /// Return the earliest timestamp (in seconds since the epoch) to flag, i.e.
+    /// this duration before the latest of `timestamps`. Returns `None` if there
+    /// are no valid timestamps.
+    fn cutoff_timestamp(&self, timestamps: ndarray::ArrayView1<f64>) -> Option<f64> {
+        let max_time = timestamps
+            .iter()
+            .copied()
+            .filter(|t| !t.is_nan())
+            .reduce(f64::max)?;
+        Some(max_time - self.num_seconds() as f64)
+    }
+}
+
+impl FromStr for SinceDuration {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let unit = s
+            .chars()
+            .last()
+            .ok_or_else(|| "Duration must not be empty".to_string())?;
+        if !matches!(unit, 's' | 'm' | 'h' | 'd' | 'w') {
+            return Err(format!(
+                "Duration '{s}' must end in one of the units s, m, h, d, or w"
+            ));
+        }
+        let value = s[..s.len() - 1]
+            .parse::<u32>()
+            .map_err(|e| format!("Could not parse the number in duration '{s}': {e}"))?;
+        Ok(Self { value, unit })
+    }
+}
+
+impl TryFrom<String> for SinceDuration {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<SinceDuration> for String {
+    fn from(value: SinceDuration) -> Self {
+        value.to_string()
+    }
+}
+
+impl Display for SinceDuration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.value, self.unit)
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -500,7 +706,7 @@ struct FilterAndGroup {
 }
 
 impl FilterAndGroup {
-    fn do_flag(&self, data: &HashMap<String, ndarray::Array1<f32>>, index: usize) -> bool {
+    fn do_flag(&self, data: &HashMap<String, FilterValues>, index: usize) -> bool {
         // Only flag if all of the filters say we should flag.
         self.filters.iter().all(|f| f.do_flag(data, index))
     }
@@ -571,6 +777,7 @@ impl FilterSet {
                     .unwrap(),
             ),
             time_mode: Combination::Inside,
+            since: None,
         };
         let flags = Flags::default();
 
@@ -590,8 +797,8 @@ impl FilterSet {
             "individual filters inside it return true.",
             "A filter must have 'filter_var' and one or both of 'less_than' and 'greater_than',",
             "value_mode is optional and defaults to 'inside'.",
-            "For timespan, if given, it should have one or both of 'time_less_than' and/or",
-            "'time_greater_than', 'time_mode' is optional.",
+            "For timespan, if given, it should have one or more of 'time_less_than',",
+            "'time_greater_than', and/or 'since', 'time_mode' is optional.",
             "All fields in 'flags' are optional.",
             "The meaning of fields in each individual filter, timespan, and flags mirrors the 'quick' CLI,",
             "see the quick CLI --help for details.",
@@ -607,9 +814,34 @@ impl FilterSet {
         Ok(())
     }
 
-    fn do_flag(&self, data: &HashMap<String, ndarray::Array1<f32>>, index: usize) -> bool {
-        // Flag if any of the groups says we should flag
-        self.groups.iter().any(|g| g.do_flag(data, index))
+    /// Flag if any of the groups says we should flag. Each group that matches has its
+    /// element of `group_matches` incremented, so all groups are checked even after one matches.
+    fn do_flag(
+        &self,
+        data: &HashMap<String, FilterValues>,
+        index: usize,
+        group_matches: &mut [u64],
+    ) -> bool {
+        let mut flag = false;
+        for (group, count) in self.groups.iter().zip(group_matches.iter_mut()) {
+            if group.do_flag(data, index) {
+                *count += 1;
+                flag = true;
+            }
+        }
+        flag
+    }
+
+    fn print_group_matches(&self, group_matches: &[u64]) {
+        println!("Observations matched by each filter group (an observation may match several):");
+        for (i, (group, count)) in self.groups.iter().zip(group_matches).enumerate() {
+            let varnames = group
+                .filters
+                .iter()
+                .map(|f| f.filter_var.as_str())
+                .join(", ");
+            println!("  Group {} ({varnames}): {count}", i + 1);
+        }
     }
 
     fn filter_vars(&self) -> Vec<&str> {
@@ -716,56 +948,6 @@ impl Default for ExistingFlag {
     }
 }
 
-#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
-#[serde(try_from = "String")]
-enum Combination {
-    Inside,
-    Outside,
-}
-
-impl Default for Combination {
-    fn default() -> Self {
-        Self::Inside
-    }
-}
-
-impl FromStr for Combination {
-    type Err = String;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s.to_ascii_lowercase().as_str() {
-            "in" | "inside" => Ok(Self::Inside),
-            "and" => {
-                log::warn!("'and' is deprecated as a keyword to combine limits, use 'in' or 'inside' instead");
-                Ok(Self::Inside)
-            }
-            "out" | "outside" => Ok(Self::Outside),
-            "or" => {
-                log::warn!("'or' is deprecated as a keyword to combine limits, use 'out' or 'outside' instead");
-                Ok(Self::Outside)
-            }
-            _ => Err(format!("'{s}' is not a valid combination variant")),
-        }
-    }
-}
-
-impl TryFrom<String> for Combination {
-    type Error = String;
-
-    fn try_from(value: String) -> Result<Self, Self::Error> {
-        value.parse()
-    }
-}
-
-impl Display for Combination {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Combination::Inside => write!(f, "inside"),
-            Combination::Outside => write!(f, "outside"),
-        }
-    }
-}
-
 #[derive(Debug)]
 struct FlagReplaceError {
     place: i16,
@@ -859,6 +1041,17 @@ impl FlagType {
         }
     }
 
+    /// Clear this flag type's place in `original_flag` if it currently holds
+    /// `flag_place_value`, otherwise return `original_flag` unchanged.
+    fn remove_flag(&self, original_flag: i16, flag_place_value: u8) -> i16 {
+        let place_value = self.value_in_place(original_flag);
+        if place_value == flag_place_value as i16 * self.flag_place() {
+            original_flag - place_value
+        } else {
+            original_flag
+        }
+    }
+
     fn flag_place(&self) -> i16 {
         match self {
             FlagType::Manual => 1000,
@@ -885,28 +1078,3 @@ impl Display for FlagType {
         }
     }
 }
-
-struct GreaterLess<T: PartialOrd> {
-    less_than: Option<T>,
-    greater_than: Option<T>,
-    combination: Combination,
-}
-
-impl<T: PartialOrd> GreaterLess<T> {
-    fn no_limits(&self) -> bool {
-        self.greater_than.is_none() && self.less_than.is_none()
-    }
-
-    fn do_flag(&self, value: &T) -> bool {
-        // println!("{value:?} in [{:?}, {:?}]", self.greater_than, self.less_than);
-        match (&self.greater_than, &self.less_than) {
-            (None, None) => false,
-            (None, Some(lim)) => value <= lim,
-            (Some(lim), None) => value >= lim,
-            (Some(gt_lim), Some(lt_lim)) => match self.combination {
-                Combination::Inside => value >= gt_lim && value <= lt_lim,
-                Combination::Outside => value >= gt_lim || value <= lt_lim,
-            },
-        }
-    }
-}
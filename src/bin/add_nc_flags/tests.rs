@@ -1,4 +1,4 @@
-use super::FilterSet;
+use super::*;
 use std::path::PathBuf;
 
 #[test]
@@ -19,3 +19,266 @@ fn test_book_examples() {
         );
     }
 }
+
+#[test]
+fn test_parse_since_duration() {
+    let dur: SinceDuration = "30d".parse().unwrap();
+    assert_eq!(dur.num_seconds(), 30 * 86400);
+    let dur: SinceDuration = "12h".parse().unwrap();
+    assert_eq!(dur.num_seconds(), 12 * 3600);
+    let dur: SinceDuration = "2w".parse().unwrap();
+    assert_eq!(dur.num_seconds(), 14 * 86400);
+    assert_eq!(dur.to_string(), "2w");
+
+    for bad in ["", "d", "30", "30x", "-1d", "1.5h"] {
+        assert!(
+            bad.parse::<SinceDuration>().is_err(),
+            "'{bad}' should not parse as a duration"
+        );
+    }
+}
+
+/// Make data with one value per day starting at 2024-01-01, all of which
+/// will be flagged by the filter, so that only the time limits matter.
+fn make_daily_data(ndays: usize) -> TcconData {
+    let start = chrono::NaiveDate::from_ymd_opt(2024, 1, 1)
+        .unwrap()
+        .and_hms_opt(0, 0, 0)
+        .unwrap()
+        .and_utc()
+        .timestamp() as f64;
+    let timestamps = ndarray::Array1::from_iter((0..ndays).map(|i| start + i as f64 * 86400.0));
+    let mut filter_vars = HashMap::new();
+    filter_vars.insert("x".to_string(), ndarray::Array1::<f64>::zeros(ndays).into());
+    TcconData {
+        filter_vars,
+        timestamps,
+        flags: ndarray::Array1::zeros(ndays),
+    }
+}
+
+fn make_since_filters(
+    since: Option<&str>,
+    time_greater_than: Option<&str>,
+    time_less_than: Option<&str>,
+) -> FilterSet {
+    FilterSet {
+        groups: vec![FilterAndGroup {
+            filters: vec![Filter {
+                less_than: Some(1.0),
+                greater_than: None,
+                value_mode: Combination::Inside,
+                filter_var: "x".to_string(),
+            }],
+        }],
+        timespan: Timespan {
+            time_less_than: time_less_than.map(|s| parse_cli_time_str(s).unwrap()),
+            time_greater_than: time_greater_than.map(|s| parse_cli_time_str(s).unwrap()),
+            time_mode: Combination::Inside,
+            since: since.map(|s| s.parse().unwrap()),
+        },
+        flags: Flags::default(),
+    }
+}
+
+#[test]
+fn test_since_flags_last_days() {
+    // Data runs 2024-01-01 to 2024-01-10, so 3 days back from the last
+    // time should include 2024-01-07 through 2024-01-10.
+    let filters = make_since_filters(Some("3d"), None, None);
+    let (flags, nchanged, _) = update_flags(make_daily_data(10), &filters, &filters.flags).unwrap();
+    assert_eq!(nchanged, 4);
+    assert!(flags.iter().take(6).all(|&f| f == 0));
+    assert!(flags.iter().skip(6).all(|&f| f == 9000));
+}
+
+#[test]
+fn test_since_composes_with_absolute_limits() {
+    // The absolute lower limit is more restrictive than --since
+    let filters = make_since_filters(Some("5d"), Some("2024-01-09"), None);
+    let (_, nchanged, _) = update_flags(make_daily_data(10), &filters, &filters.flags).unwrap();
+    assert_eq!(nchanged, 2);
+
+    // --since is more restrictive than the absolute lower limit
+    let filters = make_since_filters(Some("1d"), Some("2024-01-03"), None);
+    let (_, nchanged, _) = update_flags(make_daily_data(10), &filters, &filters.flags).unwrap();
+    assert_eq!(nchanged, 2);
+
+    // An upper limit also applies, leaving 2024-01-06 through 2024-01-08
+    let filters = make_since_filters(Some("4d"), None, Some("2024-01-08"));
+    let (_, nchanged, _) = update_flags(make_daily_data(10), &filters, &filters.flags).unwrap();
+    assert_eq!(nchanged, 3);
+}
+
+#[test]
+fn test_release_flags_require_acknowledgement() {
+    // The netCDF file does not exist; that is fine, since the acknowledgement
+    // must be checked before any file is opened.
+    let output_dir = ggg_rs::test_utils::output_dir("add-nc-flags-release");
+    let nc_file = output_dir.join("no-such-file.nc");
+    ggg_rs::test_utils::remove_file_if_exists(&nc_file).unwrap();
+    let base_args = [
+        "add_nc_flags",
+        "quick",
+        "--flag-type",
+        "release",
+        "--filter-var",
+        "xco2",
+        "--less-than",
+        "0",
+        "--in-place",
+        "--nc-file",
+        nc_file.to_str().unwrap(),
+    ];
+
+    let args = Cli::try_parse_from(base_args).unwrap();
+    let err = run(args).unwrap_err();
+    assert!(
+        matches!(err.current_context(), CliError::UserError(msg) if msg.contains("--i-am-caltech-staff")),
+        "{err:?}"
+    );
+
+    // With the acknowledgement, we get as far as trying to open the file.
+    let args = Cli::try_parse_from(base_args.into_iter().chain(["--i-am-caltech-staff"])).unwrap();
+    let err = run(args).unwrap_err();
+    assert!(
+        matches!(err.current_context(), CliError::NcError),
+        "{err:?}"
+    );
+}
+
+/// Write a minimal private-like file with a double and an int filter variable,
+/// both 0 to 4, plus a string variable that cannot be used as a filter.
+fn write_typed_filter_file(nc_file: &Path) {
+    let mut ds = netcdf::create(nc_file).unwrap();
+    ds.add_dimension("time", 5).unwrap();
+    ds.add_variable::<f64>("time", &["time"])
+        .unwrap()
+        .put_values(&[0.0, 1.0, 2.0, 3.0, 4.0], netcdf::Extents::All)
+        .unwrap();
+    ds.add_variable::<i16>("flag", &["time"])
+        .unwrap()
+        .put_values(&[0i16; 5], netcdf::Extents::All)
+        .unwrap();
+    ds.add_variable::<f64>("xco2_error", &["time"])
+        .unwrap()
+        .put_values(&[0.0, 1.0, 2.0, 3.0, 4.0], netcdf::Extents::All)
+        .unwrap();
+    ds.add_variable::<i32>("n_counts", &["time"])
+        .unwrap()
+        .put_values(&[0, 1, 2, 3, 4], netcdf::Extents::All)
+        .unwrap();
+    ds.add_variable::<f32>("xluft_error", &["time"])
+        .unwrap()
+        .put_values(&[0.05f32, 0.1, 0.2, 0.3, 0.4], netcdf::Extents::All)
+        .unwrap();
+    ds.add_string_variable("spectrum", &["time"]).unwrap();
+}
+
+fn make_filters(filter_var: &str, less_than: f64) -> FilterSet {
+    FilterSet {
+        groups: vec![FilterAndGroup {
+            filters: vec![Filter {
+                less_than: Some(less_than),
+                greater_than: None,
+                value_mode: Combination::Inside,
+                filter_var: filter_var.to_string(),
+            }],
+        }],
+        timespan: Timespan::default(),
+        flags: Flags::default(),
+    }
+}
+
+#[test]
+fn test_filter_limits_keep_double_precision() {
+    // 0.1 rounded to single precision is ~0.10000000149, which would wrongly
+    // flag the second value if the limit lost precision.
+    let mut data = make_daily_data(2);
+    data.filter_vars
+        .insert("x".to_string(), ndarray::arr1(&[0.1, 0.1 + 1e-12]).into());
+    let filters = make_filters("x", 0.1);
+    let (flags, nchanged, _) = update_flags(data, &filters, &filters.flags).unwrap();
+    assert_eq!(nchanged, 1);
+    assert_eq!(flags.to_vec(), [9000, 0]);
+}
+
+#[test]
+fn test_non_float_filter_variables() {
+    let output_dir = ggg_rs::test_utils::output_dir("add-nc-flags-typed");
+    let nc_file = output_dir.join("typed-filters.nc");
+    write_typed_filter_file(&nc_file);
+
+    for varname in ["xco2_error", "n_counts"] {
+        let filters = make_filters(varname, 1.5);
+        let data = load_flags_and_data(&nc_file, &filters.filter_vars()).unwrap();
+        let (flags, nchanged, _) = update_flags(data, &filters, &filters.flags).unwrap();
+        assert_eq!(nchanged, 2, "wrong number flagged for {varname}");
+        assert_eq!(flags.to_vec(), [9000, 9000, 0, 0, 0], "for {varname}");
+    }
+
+    let err = load_flags_and_data(&nc_file, &["spectrum"]).unwrap_err();
+    assert!(
+        matches!(err.current_context(), CliError::NonNumericFilterVariable(_)),
+        "{err:?}"
+    );
+}
+
+#[test]
+fn test_single_precision_filter_at_limit() {
+    // 0.1f32 is slightly greater than 0.1f64, so this only flags the value equal
+    // to the limit if the comparison happens in the variable's own precision.
+    let output_dir = ggg_rs::test_utils::output_dir("add-nc-flags-f32");
+    let nc_file = output_dir.join("typed-filters.nc");
+    write_typed_filter_file(&nc_file);
+
+    let filters = make_filters("xluft_error", 0.1);
+    let data = load_flags_and_data(&nc_file, &filters.filter_vars()).unwrap();
+    assert!(matches!(
+        data.filter_vars["xluft_error"],
+        FilterValues::F32(_)
+    ));
+    let (flags, nchanged, _) = update_flags(data, &filters, &filters.flags).unwrap();
+    assert_eq!(nchanged, 2);
+    assert_eq!(flags.to_vec(), [9000, 9000, 0, 0, 0]);
+}
+
+#[test]
+fn test_remove_flags_round_trip() {
+    // Include an existing release flag and a different manual flag, both of
+    // which removing our flag must leave alone.
+    let mut data = make_daily_data(6);
+    data.flags[2] = 10_000;
+    data.flags[3] = 8000;
+    data.flags[4] = 10_005;
+    let original_flags = data.flags.clone();
+
+    let mut filters = make_since_filters(None, None, None);
+    filters.flags.existing_flags = ExistingFlag::Skip;
+    let (flagged, nchanged, _) = update_flags(data, &filters, &filters.flags).unwrap();
+    assert_eq!(nchanged, 5);
+    assert_eq!(flagged.to_vec(), [9000, 9000, 19_000, 8000, 19_005, 9000]);
+
+    let mut data = make_daily_data(6);
+    data.flags = flagged;
+    filters.flags.remove = true;
+    let (unflagged, nchanged, _) = update_flags(data, &filters, &filters.flags).unwrap();
+    assert_eq!(nchanged, 5);
+    assert_eq!(unflagged, original_flags);
+}
+
+#[test]
+fn test_group_match_counts() {
+    // The first group matches the first 3 days and the second the first 5 days; since
+    // the groups are ORed, 5 observations get flagged but each group reports its own count.
+    let mut data = make_daily_data(10);
+    let x = ndarray::Array1::from_iter((0..10).map(|i| i as f64));
+    data.filter_vars.insert("x".to_string(), x.clone().into());
+    data.filter_vars.insert("y".to_string(), x.into());
+
+    let mut filters = make_filters("x", 2.5);
+    filters.groups.push(make_filters("y", 4.5).groups.remove(0));
+    let (_, nchanged, group_matches) = update_flags(data, &filters, &filters.flags).unwrap();
+    assert_eq!(nchanged, 5);
+    assert_eq!(group_matches, [3, 5]);
+}
@@ -34,6 +34,11 @@ pub(crate) struct TomlCli {
     /// The path to the input netCDF file to add flags to
     #[clap(long)]
     pub(crate) nc_file: PathBuf,
+
+    /// Also write the number of values replaced with fills in each variable
+    /// to this file as JSON, keyed by variable name.
+    #[clap(long)]
+    pub(crate) summary_json: Option<PathBuf>,
 }
 
 /// Create an example of the fill definition file
@@ -48,7 +53,7 @@ pub(crate) struct TemplateCli {
 /// debug the code doing something unexpected.
 #[derive(Debug, Clone, Args)]
 pub(crate) struct DebugCli {
-    /// Path to write the template to
+    /// Path to the TOML file to parse
     pub(crate) template_file: PathBuf,
 }
 
@@ -6,19 +6,27 @@ use approx::ulps_ne;
 use chrono::{NaiveDate, NaiveDateTime};
 use error_stack::ResultExt;
 use ggg_rs::nc_utils;
-use ndarray::{ArrayD, ArrayViewD, Zip};
+use ggg_rs::utils::{Combination, GreaterLess};
+use indexmap::IndexMap;
+use ndarray::{ArrayD, ArrayViewD, Axis, Zip};
+use netcdf::types::{FloatType, IntType, NcVariableType};
 use netcdf::{Extents, NcTypeDescriptor};
 use serde::{Deserialize, Serialize};
 
 use crate::CliError;
 
+/// The number of values replaced with fills in each variable, in the order the
+/// variables were first filtered. Variables that were checked but had no values
+/// replaced have a count of 0; variables not in the file are omitted.
+pub(crate) type FillSummary = IndexMap<String, usize>;
+
 /// Main function that applies the filters to replace fill values,
 /// as defined in `config`, to the netCDF dataset `ds`. That dataset
-/// is modified in-place.
+/// is modified in-place. Returns how many values were replaced in each variable.
 pub(crate) fn apply_filters(
     ds: &mut netcdf::FileMut,
     config: &FilterConfig,
-) -> error_stack::Result<(), CliError> {
+) -> error_stack::Result<FillSummary, CliError> {
     let times = ds
         .variable("time")
         .ok_or_else(|| CliError::missing_variable("time"))?
@@ -26,85 +34,165 @@ pub(crate) fn apply_filters(
         .change_context_lazy(|| CliError::context(format!("Error getting 'time' values")))?
         .mapv(|ts| nc_utils::convert_nc_timestamp(ts).naive_utc());
 
-    let nfilter = config.replace.len();
+    let mut summary = FillSummary::new();
+    let nfilter = config.replace.len() + config.time_range.len();
     for (ifilter, filter) in config.replace.iter().enumerate() {
         log::info!("Applying filter {} of {nfilter}", ifilter + 1);
-        match filter.matches {
+        let Some(varname) = resolve_varname(ds, &filter.varname)? else {
+            log::warn!(
+                "A group in the path of variable {} not found in file, skipping",
+                filter.varname
+            );
+            continue;
+        };
+        let n_changed = match filter.matches {
             Matches::Approx { approx } => {
-                filter_variable_approx(ds, &filter.varname, times.view(), approx, filter)?;
+                filter_variable_approx(ds, &varname, times.view(), approx, filter)?
             }
             Matches::Between { gt, lt } => {
-                filter_variable_between(ds, &filter.varname, times.view(), gt, lt, filter)?;
-            }
-            Matches::Equal { eq } => {
-                filter_variable_equal(ds, &filter.varname, times.view(), eq, filter)?;
+                filter_variable_between(ds, &varname, times.view(), gt, lt, filter)?
             }
+            Matches::Equal { eq } => filter_variable_equal(ds, &varname, times.view(), eq, filter)?,
+        };
+        if let Some(n) = n_changed {
+            *summary.entry(varname).or_default() += n;
         }
     }
-    Ok(())
+
+    let nreplace = config.replace.len();
+    for (ifilter, range) in config.time_range.iter().enumerate() {
+        log::info!("Applying filter {} of {nfilter}", nreplace + ifilter + 1);
+        fill_time_range(ds, times.view(), range, &mut summary)?;
+    }
+    Ok(summary)
+}
+
+/// Format `summary` as a table of variable names and counts, for logging.
+pub(crate) fn format_fill_summary(summary: &FillSummary) -> String {
+    if summary.is_empty() {
+        return "No variables were filtered".to_string();
+    }
+
+    let width = summary.keys().map(|k| k.len()).max().unwrap_or(0);
+    let mut lines = vec!["Number of values replaced with fills per variable:".to_string()];
+    for (varname, n) in summary.iter() {
+        lines.push(format!("  {varname:width$}  {n}"));
+    }
+    lines.join("\n")
 }
 
 /// Overall configuration structure used to (de)serialize the TOML configuration.
 #[derive(Debug, Serialize, Deserialize)]
 pub(crate) struct FilterConfig {
+    #[serde(default)]
     replace: Vec<FillReplacement>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    time_range: Vec<TimeRangeFill>,
 }
 
 impl FilterConfig {
     /// Create an example configuration file
     pub(crate) fn write_template_example(example_file: &Path) -> error_stack::Result<(), CliError> {
-        let comments = [
+        let header = [
             "This is an example TOML file to specify what value to change to netCDF fills.",
             "Each [[replace]] entry defines one rule for what to replace. A rule specifies",
             "what variable to replace values in, how to determine if a value should be replaced",
             "and, optionally, what time range it affects.",
             "",
-            "Each entry will define one of several ways to select values to replace with fills.",
-            "The fields present will determine which selection method is used:",
-            "  - If the entry has the 'approx' field, then values within floating-point error of",
-            "    that value are replaced with fills. The variable in the netCDF file must be a float.",
-            "  - If the entry has the 'gt' and 'lt' fields, then values in that range (i.e., any",
-            "    value x that satisfieds gt <= x <= lt) will be replaced. The netCDF variable must",
-            "    be a float.",
-            "  - If the entry has the 'eq' field, then values exactly equal to that value are replace.",
-            "    The netCDF variable must be an integer",
-            "You cannot mix fields, so having an entry with 'approx' and 'eq' will give an error.",
+            "The fields in each entry are:",
+            "  - varname (required): the name of the variable in the netCDF file to replace values in.",
+            "    Variables not in the file are skipped with a warning. For variables in a group, give",
+            "    the group and variable name separated by a slash, e.g. insb_experimental/xco2.",
+            "  - approx: values within floating-point error of this value are replaced with fills.",
+            "    The netCDF variable must be a float.",
+            "  - gt and lt: values in this range (i.e., any value x that satisfies gt <= x <= lt)",
+            "    are replaced with fills. Both must be given. The netCDF variable must be a float.",
+            "  - eq: values exactly equal to this value are replaced. The netCDF variable must be",
+            "    an integer.",
+            "  - time_greater_than (optional): only replace values at or after this time.",
+            "  - time_less_than (optional): only replace values at or before this time.",
             "",
-            r#"In the examples below, the section with varname = "tins" will look for any value in"#,
-            "the tins variable that is within floating point error of 60.0 and replace that with the",
-            "correct fill value for that netCDF variable. This will affect the whole file, since a",
-            "time range is not specified.",
+            "Each entry must have exactly one of 'approx', 'gt' + 'lt', or 'eq'; you cannot mix them,",
+            "so having an entry with 'approx' and 'eq' will give an error. Times must be given as",
+            "YYYY-MM-DDThh:mm:ss, e.g. 2010-01-01T00:00:00, and are compared to the 'time' variable.",
             "",
-            r#"The second example with varname = "pout" shows an example of filtering by time. While"#,
-            "this example specifies both ends of a time range, you can also specify just time_greater_than",
-            "or time_less_than to limit to all times after or before, respectively, that time.",
+            "The rules are applied in the order they are given. The fill value used is the _FillValue",
+            "attribute of each variable. You can check how this file is parsed with the 'debug' subcommand.",
             "",
-            "The third example shows the use of 'gt' and 'lt'. Any value between those values",
-            "will be replaced with fill values.",
+            "To replace all values in a time period (e.g. when the instrument was known to be malfunctioning),",
+            "use [[time_range]] entries instead. These are applied after all [[replace]] entries. See the",
+            "example at the end of this file.",
         ];
-        let template = Self::template_example();
+        let entry_comments: [&[&str]; 4] = [
+            &[
+                "This rule replaces any value of the inside temperature (tins) within floating point",
+                "error of 60.0 with fills. Since no time range is given, it affects the whole file.",
+            ],
+            &[
+                "This rule replaces outside pressures (pout) of -99 with fills, but only for data between",
+                "1 Jan 2010 and 12 Jun 2015.",
+            ],
+            &[
+                "This rule replaces any solar intensity variation (fvsi) between -1.5 and -0.5 with fills.",
+            ],
+            &[
+                "This rule replaces wind directions (wdir) of -99 with fills for data before 1 Mar 2012.",
+                "Only one end of the time range is needed; data after that time is not changed.",
+            ],
+        ];
+        let eq_example = [
+            "Integer variables must use 'eq' instead of 'approx'. For example, if your file had an",
+            "integer variable 'met_source' using -1 for missing values, you could uncomment this rule:",
+            "",
+            "[[replace]]",
+            r#"varname = "met_source""#,
+            "eq = -1",
+        ];
+        let time_range_example = [
+            "Each [[time_range]] entry replaces every value in a time period with fills. The fields are:",
+            "  - time_greater_than and time_less_than: the time period, at least one must be given.",
+            "  - time_mode (optional): 'inside' (the default) fills times between time_greater_than and",
+            "    time_less_than, 'outside' fills times before time_greater_than or after time_less_than.",
+            "  - varnames (optional): the variables to fill. If omitted, every variable along the time",
+            "    dimension is filled (including in groups), except 'time' itself and variables without a",
+            "    fill value.",
+            "Listed variables that have no fill value defined cause an error. For example, this rule would",
+            "fill xco2 and xch4 for all of 3 Jul 2011:",
+            "",
+            "[[time_range]]",
+            "time_greater_than = 2011-07-03T00:00:00",
+            "time_less_than = 2011-07-03T23:59:59",
+            r#"varnames = ["xco2", "xch4"]"#,
+        ];
+
         let mut f = std::fs::File::create(example_file).change_context_lazy(|| {
             CliError::context(
                 "Error occurred while opening the output file for the filter template",
             )
         })?;
-
-        for line in comments {
-            writeln!(f, "# {line}").change_context_lazy(|| {
-                CliError::context(format!(
-                    "Error writing to template file: {}",
-                    example_file.display()
-                ))
-            })?;
-        }
-        let s = toml::to_string_pretty(&template)
-            .expect("The example template should be able to be serialized (this is a bug)");
-        write!(f, "{s}").change_context_lazy(|| {
+        let write_err = || {
             CliError::context(format!(
                 "Error writing to template file: {}",
                 example_file.display()
             ))
-        })?;
+        };
+
+        write_comment_block(&mut f, &header).change_context_lazy(write_err)?;
+        let template = Self::template_example();
+        for (entry, comments) in template.replace.into_iter().zip(entry_comments) {
+            writeln!(f).change_context_lazy(write_err)?;
+            write_comment_block(&mut f, comments).change_context_lazy(write_err)?;
+            let s = toml::to_string_pretty(&Self {
+                replace: vec![entry],
+                time_range: vec![],
+            })
+            .expect("The example template should be able to be serialized (this is a bug)");
+            write!(f, "{s}").change_context_lazy(write_err)?;
+        }
+        writeln!(f).change_context_lazy(write_err)?;
+        write_comment_block(&mut f, &eq_example).change_context_lazy(write_err)?;
+        writeln!(f).change_context_lazy(write_err)?;
+        write_comment_block(&mut f, &time_range_example).change_context_lazy(write_err)?;
 
         Ok(())
     }
@@ -146,10 +234,38 @@ impl FilterConfig {
         };
         variables.push(fvsi);
 
-        Self { replace: variables }
+        let wdir = FillReplacement {
+            varname: "wdir".to_string(),
+            matches: Matches::Approx { approx: -99.0 },
+            time_greater_than: None,
+            time_less_than: Some(
+                NaiveDate::from_ymd_opt(2012, 3, 1)
+                    .unwrap()
+                    .and_hms_opt(0, 0, 0)
+                    .unwrap(),
+            ),
+        };
+        variables.push(wdir);
+
+        Self {
+            replace: variables,
+            time_range: vec![],
+        }
     }
 }
 
+/// Write each line in `lines` as a TOML comment; empty lines are written as a bare "#".
+fn write_comment_block<W: Write>(f: &mut W, lines: &[&str]) -> std::io::Result<()> {
+    for line in lines {
+        if line.is_empty() {
+            writeln!(f, "#")?;
+        } else {
+            writeln!(f, "# {line}")?;
+        }
+    }
+    Ok(())
+}
+
 /// A single definition for data to be replaced with fill values.
 ///
 /// Note, since the `matches` field is a flattened, untagged enum,
@@ -178,6 +294,31 @@ pub(crate) enum Matches {
     Equal { eq: i64 },
 }
 
+/// A definition of a time period in which to replace all values with fills.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct TimeRangeFill {
+    time_greater_than: Option<NaiveDateTime>,
+    time_less_than: Option<NaiveDateTime>,
+    #[serde(default)]
+    time_mode: Combination,
+    /// Variables to fill; if empty, all variables with time as their first dimension.
+    #[serde(default)]
+    varnames: Vec<String>,
+}
+
+impl TimeRangeFill {
+    /// Return `true` if time `t` is in the period to fill. This uses the same
+    /// rules as the time limits in `add_nc_flags`, see [`GreaterLess`].
+    fn contains(&self, t: &NaiveDateTime) -> bool {
+        GreaterLess {
+            less_than: self.time_less_than,
+            greater_than: self.time_greater_than,
+            combination: self.time_mode,
+        }
+        .do_flag(t)
+    }
+}
+
 /// Helper function that returns `true` if time `t` is outside the
 /// time bounds specified and should be skipped.
 fn outside_time_bounds(
@@ -197,6 +338,7 @@ fn outside_time_bounds(
 }
 
 /// Helper function that applies the "approximate" filtering to a variable.
+/// Returns the number of values replaced, or `None` if the variable is not in the file.
 ///
 /// This, [`filter_variable_between`], and [`filter_variable_equal`]
 /// were written as separate functions because of the different combinations
@@ -207,14 +349,14 @@ fn filter_variable_approx(
     times: ArrayViewD<NaiveDateTime>,
     approx: f32,
     filter: &FillReplacement,
-) -> error_stack::Result<(), CliError> {
+) -> error_stack::Result<Option<usize>, CliError> {
     let opt_arr = get_var_values_opt::<f32>(ds, varname)
         .change_context_lazy(|| CliError::context("Error reading variable as float"))?;
     let mut values = if let Some(arr) = opt_arr {
         arr
     } else {
         log::warn!("Variable {varname} not found in file, skipping");
-        return Ok(());
+        return Ok(None);
     };
 
     let nc_fill = get_var_fill::<f32>(ds, varname)?;
@@ -233,7 +375,7 @@ fn filter_variable_approx(
         log::info!("No values in {varname} approximately equal to {approx}, variable not modified");
     }
 
-    Ok(())
+    Ok(Some(n_changed))
 }
 
 /// Helper function that applies the "approximate" filtering to an in-memory array.
@@ -269,14 +411,14 @@ fn filter_variable_between(
     gt: f32,
     lt: f32,
     filter: &FillReplacement,
-) -> error_stack::Result<(), CliError> {
+) -> error_stack::Result<Option<usize>, CliError> {
     let opt_arr = get_var_values_opt::<f32>(ds, varname)
         .change_context_lazy(|| CliError::context("Error reading variable as float"))?;
     let mut values = if let Some(arr) = opt_arr {
         arr
     } else {
         log::warn!("Variable {varname} not found in file, skipping");
-        return Ok(());
+        return Ok(None);
     };
     let nc_fill = get_var_fill::<f32>(ds, varname)?;
     let n_changed = filter_between(
@@ -295,7 +437,7 @@ fn filter_variable_between(
         log::info!("No values in {varname} between {gt} and {lt}, variable not modified");
     }
 
-    Ok(())
+    Ok(Some(n_changed))
 }
 
 /// Helper function that applies the "between" filtering to an in-memory array.
@@ -335,14 +477,14 @@ fn filter_variable_equal(
     times: ArrayViewD<NaiveDateTime>,
     eq: i64,
     filter: &FillReplacement,
-) -> error_stack::Result<(), CliError> {
+) -> error_stack::Result<Option<usize>, CliError> {
     let opt_arr = get_var_values_opt::<i64>(ds, varname)
         .change_context_lazy(|| CliError::context("Error reading variable as float"))?;
     let mut values = if let Some(arr) = opt_arr {
         arr
     } else {
         log::warn!("Variable {varname} not found in file, skipping");
-        return Ok(());
+        return Ok(None);
     };
     let nc_fill = get_var_fill::<i64>(ds, varname)?;
     let n_changed = filter_equal(
@@ -359,7 +501,7 @@ fn filter_variable_equal(
     } else {
         log::info!("No values in {varname} equal to {eq}, variable not modified");
     }
-    Ok(())
+    Ok(Some(n_changed))
 }
 
 /// Helper function that applies the "equal" filtering to an in-memory array.
@@ -387,6 +529,207 @@ fn filter_equal(
     n_changed
 }
 
+/// Helper function that replaces all values of the variables in `range` at times in
+/// that range with their fill values.
+fn fill_time_range(
+    ds: &mut netcdf::FileMut,
+    times: ArrayViewD<NaiveDateTime>,
+    range: &TimeRangeFill,
+    summary: &mut FillSummary,
+) -> error_stack::Result<(), CliError> {
+    if range.time_greater_than.is_none() && range.time_less_than.is_none() {
+        return Err(CliError::context(
+            "A [[time_range]] entry must have time_greater_than, time_less_than, or both",
+        )
+        .into());
+    }
+
+    let in_range = times.iter().map(|t| range.contains(t)).collect::<Vec<_>>();
+    if !in_range.iter().any(|&b| b) {
+        log::info!("No times in the time range {range:?}, no variables modified");
+        return Ok(());
+    }
+
+    // If the user named the variables, any that cannot be filled is an error. Otherwise,
+    // we skip variables that cannot be filled, as many are not meant to be (e.g. "year").
+    let explicit = !range.varnames.is_empty();
+    let varnames = if explicit {
+        let mut varnames = vec![];
+        for name in range.varnames.iter() {
+            if let Some(varname) = resolve_varname(ds, name)? {
+                varnames.push(varname);
+            } else {
+                log::warn!("A group in the path of variable {name} not found in file, skipping");
+            }
+        }
+        varnames
+    } else {
+        let mut varnames = ds
+            .variables()
+            .filter(|v| v.name() != "time" && has_time_first_dim(v))
+            .map(|v| v.name())
+            .collect::<Vec<_>>();
+        let groups = ds
+            .groups()
+            .change_context_lazy(|| CliError::context("Error listing groups in the file"))?;
+        for grp in groups {
+            let name = grp.name();
+            collect_time_variables(&grp, &name, &mut varnames);
+        }
+        varnames
+    };
+
+    for varname in varnames {
+        let Some(var) = ds.variable(&varname) else {
+            log::warn!("Variable {varname} not found in file, skipping");
+            continue;
+        };
+        if !has_time_first_dim(&var) {
+            return Err(CliError::context(format!(
+                "Variable {varname} does not have time as its first dimension, so cannot be filled by time range"
+            ))
+            .into());
+        }
+
+        let res = match var.vartype() {
+            NcVariableType::Float(FloatType::F32) => {
+                fill_time_range_typed::<f32>(ds, &varname, &in_range)
+            }
+            NcVariableType::Float(FloatType::F64) => {
+                fill_time_range_typed::<f64>(ds, &varname, &in_range)
+            }
+            NcVariableType::Int(IntType::I8) => {
+                fill_time_range_typed::<i8>(ds, &varname, &in_range)
+            }
+            NcVariableType::Int(IntType::I16) => {
+                fill_time_range_typed::<i16>(ds, &varname, &in_range)
+            }
+            NcVariableType::Int(IntType::I32) => {
+                fill_time_range_typed::<i32>(ds, &varname, &in_range)
+            }
+            NcVariableType::Int(IntType::I64) => {
+                fill_time_range_typed::<i64>(ds, &varname, &in_range)
+            }
+            NcVariableType::Int(IntType::U8) => {
+                fill_time_range_typed::<u8>(ds, &varname, &in_range)
+            }
+            NcVariableType::Int(IntType::U16) => {
+                fill_time_range_typed::<u16>(ds, &varname, &in_range)
+            }
+            NcVariableType::Int(IntType::U32) => {
+                fill_time_range_typed::<u32>(ds, &varname, &in_range)
+            }
+            NcVariableType::Int(IntType::U64) => {
+                fill_time_range_typed::<u64>(ds, &varname, &in_range)
+            }
+            _ if explicit => {
+                return Err(CliError::context(format!(
+                    "Variable {varname} is not a numeric type, so cannot be filled"
+                ))
+                .into())
+            }
+            _ => {
+                log::debug!("Skipping non-numeric variable {varname}");
+                continue;
+            }
+        };
+
+        match res {
+            Ok(n_changed) => {
+                log::info!("Replaced {n_changed} values in {varname} within the time range");
+                *summary.entry(varname).or_default() += n_changed;
+            }
+            Err(e) if !explicit && matches!(e.current_context(), CliError::NoFillDef(_)) => {
+                log::debug!("Skipping variable {varname} with no fill value defined");
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(())
+}
+
+/// Helper function that adds the paths (e.g. "insb_experimental/xco2") of all variables in
+/// group `grp` and its subgroups that have time as their first dimension to `varnames`.
+/// `grp_path` must be the path to `grp` from the root group.
+fn collect_time_variables(grp: &netcdf::Group, grp_path: &str, varnames: &mut Vec<String>) {
+    for var in grp.variables() {
+        if has_time_first_dim(&var) {
+            varnames.push(format!("{grp_path}/{}", var.name()));
+        }
+    }
+    for subgrp in grp.groups() {
+        let subgrp_path = format!("{grp_path}/{}", subgrp.name());
+        collect_time_variables(&subgrp, &subgrp_path, varnames);
+    }
+}
+
+/// Helper function that resolves a variable name that may include groups, e.g.
+/// "insb_experimental/xco2" or "/insb_experimental/xco2", into the path used to
+/// look up the variable in `ds`. Returns `None` if one of the groups does not exist.
+/// Whether the variable itself exists is not checked.
+fn resolve_varname(
+    ds: &netcdf::File,
+    varname: &str,
+) -> error_stack::Result<Option<String>, CliError> {
+    let path = varname.trim_start_matches('/');
+    let Some((grp_path, _)) = path.rsplit_once('/') else {
+        return Ok(Some(path.to_string()));
+    };
+
+    // Check each level in turn, since looking up a group whose parent
+    // does not exist is an error rather than `None`.
+    let mut end = 0;
+    for part in grp_path.split('/') {
+        end += part.len();
+        let found = ds
+            .group(&grp_path[..end])
+            .change_context_lazy(|| {
+                CliError::context(format!(
+                    "Error looking up the groups for variable {varname}"
+                ))
+            })?
+            .is_some();
+        if !found {
+            return Ok(None);
+        }
+        end += 1;
+    }
+
+    Ok(Some(path.to_string()))
+}
+
+/// Helper function that returns `true` if the first dimension of `var` is time.
+fn has_time_first_dim(var: &netcdf::Variable) -> bool {
+    var.dimensions().first().is_some_and(|d| d.name() == "time")
+}
+
+/// Helper function that replaces the values of `varname` with its fill value for each
+/// index along the first (time) dimension where `in_range` is `true`. Returns the number
+/// of values replaced.
+fn fill_time_range_typed<T: NcTypeDescriptor + Copy>(
+    ds: &mut netcdf::FileMut,
+    varname: &str,
+    in_range: &[bool],
+) -> error_stack::Result<usize, CliError> {
+    let nc_fill = get_var_fill::<T>(ds, varname)?;
+    let mut values =
+        get_var_values_opt::<T>(ds, varname)?.ok_or_else(|| CliError::missing_variable(varname))?;
+
+    let mut n_changed = 0;
+    for (mut slice, &fill) in values.axis_iter_mut(Axis(0)).zip(in_range) {
+        if fill {
+            slice.fill(nc_fill);
+            n_changed += slice.len();
+        }
+    }
+
+    if n_changed > 0 {
+        put_var_values(ds, varname, values.view())?;
+    }
+    Ok(n_changed)
+}
+
 /// Helper function that returns the values array of a netCDF variable.
 /// If the variable doesn't exist in the given dataset, it returns `None`.
 fn get_var_values_opt<T: NcTypeDescriptor + Copy>(
@@ -420,16 +763,293 @@ fn put_var_values<T: NcTypeDescriptor + Copy>(
 }
 
 /// Helper function that gets the defined fill value for a given netCDF variable.
+///
+/// Returns a [`CliError::NoFillDef`] error if the variable does not have a `_FillValue`
+/// attribute. This must be checked separately, since netCDF reports the default fill
+/// value for the type when a variable has no `_FillValue` of its own.
 fn get_var_fill<T: NcTypeDescriptor + Copy>(
     ds: &netcdf::File,
     varname: &str,
 ) -> error_stack::Result<T, CliError> {
-    ds.variable(&varname)
-        .ok_or_else(|| CliError::missing_variable(varname))?
-        .fill_value::<T>()
+    let var = ds
+        .variable(&varname)
+        .ok_or_else(|| CliError::missing_variable(varname))?;
+    if var.attribute("_FillValue").is_none() {
+        return Err(CliError::no_fill_def(varname).into());
+    }
+    var.fill_value::<T>()
         .transpose()
         .ok_or_else(|| CliError::no_fill_def(varname))?
         .change_context_lazy(|| {
             CliError::context(format!("Error reading fill value from variable: {varname}"))
         })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_template_round_trip() {
+        let output_dir = ggg_rs::test_utils::output_dir("apply-nc-fills-template");
+        let template_file = output_dir.join("apply-nc-fills-template.toml");
+        FilterConfig::write_template_example(&template_file).unwrap();
+        let text = std::fs::read_to_string(&template_file).unwrap();
+
+        let config: FilterConfig = toml::from_str(&text).unwrap();
+        let varnames: Vec<_> = config.replace.iter().map(|r| r.varname.as_str()).collect();
+        assert_eq!(varnames, ["tins", "pout", "fvsi", "wdir"]);
+        assert!(text.contains("# varname = \"met_source\""), "{text}");
+        assert!(config.time_range.is_empty());
+    }
+
+    #[test]
+    fn test_format_fill_summary() {
+        let summary = FillSummary::from([
+            ("tins".to_string(), 12),
+            ("insb_experimental/xco2".to_string(), 0),
+        ]);
+        assert_eq!(
+            format_fill_summary(&summary),
+            "Number of values replaced with fills per variable:\n  tins                    12\n  insb_experimental/xco2  0"
+        );
+        assert_eq!(
+            format_fill_summary(&FillSummary::new()),
+            "No variables were filtered"
+        );
+    }
+
+    #[test]
+    fn test_time_range_contains() {
+        let t = |d| {
+            NaiveDate::from_ymd_opt(2011, 7, d)
+                .unwrap()
+                .and_hms_opt(12, 0, 0)
+                .unwrap()
+        };
+        let config: FilterConfig = toml::from_str(
+            r#"
+            [[time_range]]
+            time_greater_than = 2011-07-03T00:00:00
+            time_less_than = 2011-07-04T00:00:00
+            varnames = ["xco2"]
+
+            [[time_range]]
+            time_greater_than = 2011-07-04T00:00:00
+            time_less_than = 2011-07-03T00:00:00
+            time_mode = "outside"
+
+            [[time_range]]
+            time_less_than = 2011-07-04T00:00:00
+            time_mode = "Out"
+            "#,
+        )
+        .unwrap();
+        assert!(config.replace.is_empty());
+
+        let inside: Vec<_> = (2..=4)
+            .map(|d| config.time_range[0].contains(&t(d)))
+            .collect();
+        let outside: Vec<_> = (2..=4)
+            .map(|d| config.time_range[1].contains(&t(d)))
+            .collect();
+        let before: Vec<_> = (2..=4)
+            .map(|d| config.time_range[2].contains(&t(d)))
+            .collect();
+        assert_eq!(inside, [false, true, false]);
+        assert_eq!(outside, [true, false, true]);
+        assert_eq!(before, [true, true, false]);
+        assert_eq!(config.time_range[0].varnames, ["xco2"]);
+        assert!(config.time_range[1].varnames.is_empty());
+    }
+
+    /// Write a minimal private-like file with five daily times starting on 2011-07-01,
+    /// a root and a group `xco2` variable with fill values, and a `year` variable without one.
+    fn write_time_range_file(nc_file: &Path) {
+        let t0 = NaiveDate::from_ymd_opt(2011, 7, 1)
+            .unwrap()
+            .and_hms_opt(12, 0, 0)
+            .unwrap()
+            .and_utc()
+            .timestamp() as f64;
+        let times: Vec<f64> = (0..5).map(|i| t0 + i as f64 * 86400.0).collect();
+
+        let mut ds = netcdf::create(nc_file).unwrap();
+        ds.add_dimension("time", 5).unwrap();
+        ds.add_variable::<f64>("time", &["time"])
+            .unwrap()
+            .put_values(&times, Extents::All)
+            .unwrap();
+        ds.add_variable::<i32>("year", &["time"])
+            .unwrap()
+            .put_values(&[2011; 5], Extents::All)
+            .unwrap();
+        let mut var = ds.add_variable::<f32>("xco2", &["time"]).unwrap();
+        var.set_fill_value(-999.0f32).unwrap();
+        var.put_values(&[400.0f32; 5], Extents::All).unwrap();
+
+        let mut grp = ds.add_group("insb_experimental").unwrap();
+        let mut var = grp.add_variable::<f64>("xco2", &["time"]).unwrap();
+        var.set_fill_value(9.96921e36f64).unwrap();
+        var.put_values(&[401.0f64; 5], Extents::All).unwrap();
+    }
+
+    fn time_range_config(toml_str: &str) -> TimeRangeFill {
+        let mut config: FilterConfig = toml::from_str(toml_str).unwrap();
+        config.time_range.remove(0)
+    }
+
+    #[test]
+    fn test_fill_time_range_netcdf() {
+        let output_dir = ggg_rs::test_utils::output_dir("apply-nc-fills-time-range");
+        let nc_file = output_dir.join("time-range.nc");
+        write_time_range_file(&nc_file);
+
+        // No varnames: every time variable with a fill value is filled, including
+        // those in groups, but "year" (no fill value) and "time" are skipped.
+        let range = time_range_config(
+            r#"
+            [[time_range]]
+            time_greater_than = 2011-07-02T00:00:00
+            time_less_than = 2011-07-04T00:00:00
+            "#,
+        );
+        let mut ds = netcdf::append(&nc_file).unwrap();
+        let times = ds
+            .variable("time")
+            .unwrap()
+            .get::<f64, _>(Extents::All)
+            .unwrap()
+            .mapv(|ts| nc_utils::convert_nc_timestamp(ts).naive_utc());
+        let mut summary = FillSummary::new();
+        fill_time_range(&mut ds, times.view(), &range, &mut summary).unwrap();
+        assert_eq!(
+            summary,
+            FillSummary::from([
+                ("xco2".to_string(), 2),
+                ("insb_experimental/xco2".to_string(), 2)
+            ])
+        );
+
+        let xco2: Vec<f32> = ds
+            .variable("xco2")
+            .unwrap()
+            .get_values(Extents::All)
+            .unwrap();
+        assert_eq!(xco2, [400.0, -999.0, -999.0, 400.0, 400.0]);
+        let insb_xco2: Vec<f64> = ds
+            .variable("insb_experimental/xco2")
+            .unwrap()
+            .get_values(Extents::All)
+            .unwrap();
+        assert_eq!(insb_xco2, [401.0, 9.96921e36, 9.96921e36, 401.0, 401.0]);
+        let year: Vec<i32> = ds
+            .variable("year")
+            .unwrap()
+            .get_values(Extents::All)
+            .unwrap();
+        assert_eq!(year, [2011; 5]);
+
+        // Naming a group variable fills only that variable.
+        let range = time_range_config(
+            r#"
+            [[time_range]]
+            time_greater_than = 2011-07-05T00:00:00
+            varnames = ["insb_experimental/xco2"]
+            "#,
+        );
+        let mut summary = FillSummary::new();
+        fill_time_range(&mut ds, times.view(), &range, &mut summary).unwrap();
+        assert_eq!(
+            summary,
+            FillSummary::from([("insb_experimental/xco2".to_string(), 1)])
+        );
+        let xco2: Vec<f32> = ds
+            .variable("xco2")
+            .unwrap()
+            .get_values(Extents::All)
+            .unwrap();
+        assert_eq!(xco2, [400.0, -999.0, -999.0, 400.0, 400.0]);
+
+        // Naming a variable with no fill value is an error rather than skipped.
+        let range = time_range_config(
+            r#"
+            [[time_range]]
+            time_less_than = 2011-07-02T00:00:00
+            varnames = ["year"]
+            "#,
+        );
+        let mut summary = FillSummary::new();
+        let err = fill_time_range(&mut ds, times.view(), &range, &mut summary).unwrap_err();
+        assert!(
+            matches!(err.current_context(), CliError::NoFillDef(name) if name == "year"),
+            "{err:?}"
+        );
+    }
+
+    /// Write a file with three times, an "InSb" group containing `xco2` (with one -99 value)
+    /// and an "InSb/experimental" subgroup containing `xch4`.
+    fn write_group_file(nc_file: &Path) {
+        let mut ds = netcdf::create(nc_file).unwrap();
+        ds.add_dimension("time", 3).unwrap();
+        ds.add_variable::<f64>("time", &["time"])
+            .unwrap()
+            .put_values(&[0.0, 3600.0, 7200.0], Extents::All)
+            .unwrap();
+
+        let mut grp = ds.add_group("InSb").unwrap();
+        let mut var = grp.add_variable::<f32>("xco2", &["time"]).unwrap();
+        var.set_fill_value(9.96921e36f32).unwrap();
+        var.put_values(&[400.0f32, -99.0, 401.0], Extents::All)
+            .unwrap();
+        let mut subgrp = grp.add_group("experimental").unwrap();
+        let mut var = subgrp.add_variable::<f32>("xch4", &["time"]).unwrap();
+        var.set_fill_value(9.96921e36f32).unwrap();
+        var.put_values(&[1.8f32; 3], Extents::All).unwrap();
+    }
+
+    #[test]
+    fn test_group_variable_names() {
+        let output_dir = ggg_rs::test_utils::output_dir("apply-nc-fills-groups");
+        let nc_file = output_dir.join("groups.nc");
+        write_group_file(&nc_file);
+        let mut ds = netcdf::append(&nc_file).unwrap();
+
+        let resolve = |name| resolve_varname(&ds, name).unwrap();
+        assert_eq!(resolve("InSb/xco2").as_deref(), Some("InSb/xco2"));
+        assert_eq!(resolve("/InSb/xco2").as_deref(), Some("InSb/xco2"));
+        assert_eq!(
+            resolve("InSb/experimental/xch4").as_deref(),
+            Some("InSb/experimental/xch4")
+        );
+        assert_eq!(resolve("xco2").as_deref(), Some("xco2"));
+        assert_eq!(resolve("InGaAs/xco2"), None);
+        assert_eq!(resolve("InSb/missing/xch4"), None);
+
+        let grp = ds.group("InSb").unwrap().unwrap();
+        let mut varnames = vec![];
+        collect_time_variables(&grp, "InSb", &mut varnames);
+        assert_eq!(varnames, ["InSb/xco2", "InSb/experimental/xch4"]);
+
+        let config: FilterConfig = toml::from_str(
+            r#"
+            [[replace]]
+            varname = "/InSb/xco2"
+            approx = -99.0
+
+            [[replace]]
+            varname = "InSb/missing/xch4"
+            approx = 1.8
+            "#,
+        )
+        .unwrap();
+        let summary = apply_filters(&mut ds, &config).unwrap();
+        assert_eq!(summary, FillSummary::from([("InSb/xco2".to_string(), 1)]));
+        let xco2: Vec<f32> = ds
+            .variable("InSb/xco2")
+            .unwrap()
+            .get_values(Extents::All)
+            .unwrap();
+        assert_eq!(xco2, [400.0, 9.96921e36, 401.0]);
+    }
+}
@@ -36,7 +36,23 @@ fn main_inner(clargs: cli::Cli) -> error_stack::Result<(), CliError> {
 fn fill_driver(cli: cli::TomlCli) -> error_stack::Result<(), CliError> {
     let mut ds = setup_output_file(&cli.nc_file, &cli.output)?;
     let config = load_config(&cli.toml_file)?;
-    fills::apply_filters(&mut ds, &config)?;
+    let summary = fills::apply_filters(&mut ds, &config)?;
+    log::info!("{}", fills::format_fill_summary(&summary));
+
+    if let Some(json_file) = cli.summary_json.as_deref() {
+        let f = std::fs::File::create(json_file).change_context_lazy(|| {
+            CliError::context(format!(
+                "Error creating summary JSON file: {}",
+                json_file.display()
+            ))
+        })?;
+        serde_json::to_writer_pretty(f, &summary).change_context_lazy(|| {
+            CliError::context(format!(
+                "Error writing summary JSON file: {}",
+                json_file.display()
+            ))
+        })?;
+    }
     Ok(())
 }
 
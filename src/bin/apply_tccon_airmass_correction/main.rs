@@ -1,21 +1,26 @@
-use std::{collections::HashMap, path::PathBuf, process::ExitCode};
+use std::{path::PathBuf, process::ExitCode};
 
 use clap::Parser;
 
+use clap_verbosity_flag::{InfoLevel, Verbosity};
 use error_stack::{Report, ResultExt};
-use fortformat::FortFormat;
 use ggg_rs::{
-    readers::{postproc_files::open_and_iter_postproc_file, ProgramVersion},
-    tccon::input_config::{self, AdcfRow},
-    writers::postproc_files::write_postproc_header,
+    logging::init_logging,
+    readers::postproc_files::open_and_iter_postproc_file,
+    tccon::{
+        corrections::{
+            add_adcf_header_lines, airmass_correction_version, apply_airmass_correction,
+            check_all_adcfs_present, find_o2_window, input_is_averaged, AirmassCorrectionOptions,
+            GasShapeDefault, ShapeDefaults, ShapeDefaultsFile, DEFAULT_G, DEFAULT_P,
+        },
+        input_config,
+    },
+    writers::postproc_files::{PostprocHeaderInfo, PostprocWriter},
 };
-use indexmap::IndexMap;
-
-const DEFAULT_G: f64 = 0.0;
-const DEFAULT_P: f64 = 0.0;
 
 fn main() -> ExitCode {
     let clargs = AirmassCorrCli::parse();
+    init_logging(clargs.verbosity.log_level_filter());
     if let Err(e) = driver(clargs) {
         eprintln!("ERROR: {e:?}");
         ExitCode::FAILURE
@@ -38,6 +43,38 @@ struct AirmassCorrCli {
     /// file will be saved to the same directory as the upstream file.
     #[clap(short = 'o', long)]
     output_dir: Option<PathBuf>,
+
+    /// Value of g to use for any gas that does not define one in the
+    /// correction file or with --gas-default.
+    #[clap(long, default_value_t = DEFAULT_G)]
+    default_g: f64,
+
+    /// Value of p to use for any gas that does not define one in the
+    /// correction file or with --gas-default.
+    #[clap(long, default_value_t = DEFAULT_P)]
+    default_p: f64,
+
+    /// Default g and p for one gas, given as GAS=G,P (e.g. "xco2=15,4").
+    /// GAS may be a full Xgas window name or just the Xgas (the part before
+    /// the first underscore), in which case it applies to all windows for
+    /// that gas. Values in the correction file take precedence. May be repeated.
+    #[clap(long = "gas-default")]
+    gas_defaults: Vec<GasShapeDefault>,
+
+    /// TOML file giving default g and p values per gas or window, and optionally
+    /// for the O2 window. See the book for its format. Defaults given with
+    /// --gas-default take precedence over ones in this file.
+    #[clap(long)]
+    shape_defaults_file: Option<PathBuf>,
+
+    /// Stop with an error if any retrieved window in the upstream file does not
+    /// have an entry in the correction file. Without this, such windows are not
+    /// airmass corrected.
+    #[clap(long)]
+    require_all_adcf: bool,
+
+    #[command(flatten)]
+    verbosity: Verbosity<InfoLevel>,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -84,31 +121,23 @@ fn driver(clargs: AirmassCorrCli) -> error_stack::Result<(), CliError> {
     });
     let out_file = out_dir.join(new_name);
 
-    let extension = clargs
-        .upstream_file
-        .extension()
-        .unwrap_or_default()
-        .to_str()
-        .unwrap_or_default();
-    let input_is_averaged = if extension.ends_with("sw") {
-        false
-    } else if extension.ends_with("av") {
-        true
-    } else {
-        return Err(CliError::custom(format!(
-            "apply_tccon_airmass_correction received a file with unknown extension: {extension}"
-        ))
-        .into());
-    };
-
     // Read in the appropriate airmass correction file
     let adcfs = input_config::read_adcf_file(&clargs.correction_file)
         .change_context_lazy(|| CliError::ReadError(clargs.correction_file.clone()))?;
+    let mut shape_defaults =
+        ShapeDefaults::new(clargs.default_g, clargs.default_p, &clargs.gas_defaults);
+    if let Some(toml_file) = &clargs.shape_defaults_file {
+        let file_defaults = ShapeDefaultsFile::read(toml_file)
+            .change_context_lazy(|| CliError::ReadError(toml_file.clone()))?;
+        shape_defaults = shape_defaults.with_file_defaults(file_defaults);
+    }
 
     // Read in the header of the previous postproc file, add the airmass correction factors
     // to it. Write out to a temporary file to avoid confusion with a completed *.ada file.
     let (mut header, rows) = open_and_iter_postproc_file(&clargs.upstream_file)
         .change_context_lazy(|| CliError::ReadError(clargs.upstream_file.to_path_buf()))?;
+    let input_is_averaged =
+        input_is_averaged(&header, &clargs.upstream_file).map_err(CliError::custom)?;
 
     // Make sure we found a number of auxiliary columns.
     let naux = header.naux;
@@ -117,46 +146,32 @@ fn driver(clargs: AirmassCorrCli) -> error_stack::Result<(), CliError> {
     let missing_value = header.missing_value;
     let mut col_names = header.column_names.clone();
 
-    // Before we edit the column names, find the O2 window. This looks complicated, but all it's doing
-    // is finding the first window name that starts with "o2" and is not a column error amount or
-    // the O2 mean DMF auxiliary column.
-    let o2_window = col_names[naux - 1..]
-        .iter()
-        .fold(None, |acc, name| {
-            if !name.starts_with("o2") | name.ends_with("_error") | name.ends_with("dmf") {
-                acc
-            } else if name.starts_with("o2") && acc.is_none() {
-                Some(Ok(name.to_string()))
-            } else if acc.as_ref().is_some_and(|r| r.is_ok()) {
-                let other = acc.unwrap().unwrap();
-                Some(Err(CliError::custom(format!(
-                    "multiple O2 windows found: '{name}' and '{other}'"
-                ))))
-            } else {
-                acc
-            }
-        })
-        .ok_or_else(|| CliError::custom("could not find O2 window"))??;
+    // Before we edit the column names, find the O2 window.
+    let o2_window = find_o2_window(&col_names, naux).map_err(CliError::custom)?;
+    shape_defaults.set_o2_window(&o2_window);
 
     // Change the column names to prepend an "x" to all of the retrieved columns.
     for name in col_names[naux..].iter_mut() {
         name.insert(0, 'x');
     }
 
+    if clargs.require_all_adcf {
+        check_all_adcfs_present(&col_names[naux..], &adcfs).map_err(CliError::custom)?;
+    }
+
     // Handle replacing the "a1" column that we retain for backwards compatibility with
     // older runlog formats - this can't go in the format string because it represents a
     // commenting-out character that we don't have a field for.
     // let format_str = format_spec.fmt_string(1).replacen("1x", "a1", 1);
-    let writer_format_spec = header.fformat_without_comment();
     let format_str = header.fformat.fmt_string(1);
 
     // Add the airmass corrections to the file header
-    add_adcf_header_lines(&mut header.extra_lines, &adcfs).change_context_lazy(|| {
-        CliError::WriteError {
+    add_adcf_header_lines(&mut header.extra_lines, &adcfs, &shape_defaults).change_context_lazy(
+        || CliError::WriteError {
             path: out_file.clone(),
             cause: "writing the ADCF values in the header failed.".to_string(),
-        }
-    })?;
+        },
+    )?;
 
     // Write out the file header, this way we can do one row at a time and not have to
     // load the whole file into memory.
@@ -164,20 +179,21 @@ fn driver(clargs: AirmassCorrCli) -> error_stack::Result<(), CliError> {
         path: out_file.to_path_buf(),
         cause: "creating file failed".to_string(),
     })?;
-    let mut fw = std::io::BufWriter::new(fw);
+    let fw = std::io::BufWriter::new(fw);
 
     let mut program_versions = Vec::from_iter(header.program_versions.values().cloned());
-    program_versions.insert(0, program_version());
-    write_postproc_header(
-        &mut fw,
-        col_names.len(),
-        nrow,
-        naux,
-        &program_versions,
-        &header.extra_lines,
-        missing_value,
-        &format_str,
-        &col_names,
+    program_versions.insert(0, airmass_correction_version());
+    let mut writer = PostprocWriter::new(
+        fw,
+        PostprocHeaderInfo {
+            nrow,
+            naux,
+            program_versions: &program_versions,
+            extra_lines: &header.extra_lines,
+            missing_value,
+            format_str: &format_str,
+            column_names: &col_names,
+        },
     )
     .change_context_lazy(|| CliError::WriteError {
         path: out_file.clone(),
@@ -185,165 +201,63 @@ fn driver(clargs: AirmassCorrCli) -> error_stack::Result<(), CliError> {
     })?;
 
     // Read each row, apply airmass corrections, and write out the Xgas values.
-    // We want to allow skipped fields in case we are reading a file that omitted
-    // fields allowed to have defaults in the auxiliary columns.
-    let settings = fortformat::ser::SerSettings::default()
-        .align_left_str(true)
-        .allow_skipped_fields(true);
-    let missing_value = header.missing_value;
-
+    let correction_opts = AirmassCorrectionOptions {
+        adcfs: &adcfs,
+        shape_defaults: &shape_defaults,
+        o2_window: &o2_window,
+        missing_value,
+        is_avg: input_is_averaged,
+    };
     for (irow, row) in rows.enumerate() {
         let mut row = row.change_context_lazy(|| CliError::ReadErrorAtLine {
             file: clargs.upstream_file.clone(),
             line: header.nhead + irow + 1,
         })?;
 
-        let this_o2_dmf = row.auxiliary.o2dmf;
-        row.retrieved = apply_correction(
+        row.retrieved = apply_airmass_correction(
             &row.retrieved,
-            &adcfs,
-            &o2_window,
-            this_o2_dmf,
+            row.auxiliary.o2dmf,
             row.auxiliary.solzen,
-            missing_value,
-            input_is_averaged,
-        )?;
-
-        fortformat::ser::to_writer_custom(
-            row,
-            &writer_format_spec,
-            Some(&col_names),
-            &settings,
-            &mut fw,
+            &correction_opts,
         )
+        .map_err(CliError::custom)?;
+
+        writer
+            .write_row(&row)
+            .change_context_lazy(|| CliError::WriteError {
+                path: out_file.clone(),
+                cause: format!("error serializing data line {}", irow + 1),
+            })?;
+    }
+
+    writer
+        .finish()
         .change_context_lazy(|| CliError::WriteError {
             path: out_file.clone(),
-            cause: format!("error serializing data line {}", irow + 1),
+            cause: "error finishing the file".to_string(),
         })?;
-    }
-
     Ok(())
 }
 
-fn add_adcf_header_lines(
-    lines_out: &mut Vec<String>,
-    adcfs: &IndexMap<String, AdcfRow>,
-) -> Result<(), fortformat::SError> {
-    let nrow = adcfs.len();
-    lines_out.push(format!(" Airmass-Dependent Correction Factors: {nrow} 5"));
-
-    let ff = FortFormat::parse("(a22,2(1x,f9.5),2(1x,f6.2))").unwrap();
-    let settings = fortformat::ser::SerSettings::default().align_left_str(true);
-    for corr in adcfs.values() {
-        let values = (
-            &corr.gas_or_window,
-            corr.adcf,
-            corr.adcf_error,
-            corr.g.unwrap_or(DEFAULT_G),
-            corr.p.unwrap_or(DEFAULT_P),
-        );
-        let s = fortformat::ser::to_string_custom::<_, &str>(values, &ff, None, &settings)?;
-        lines_out.push(s);
-    }
-
-    Ok(())
-}
-
-fn apply_correction(
-    row: &HashMap<String, f64>,
-    adcfs: &IndexMap<String, AdcfRow>,
-    o2_window: &str,
-    o2_dmf: f64,
-    sza: f64,
-    missing_value: f64,
-    is_avg: bool,
-) -> Result<HashMap<String, f64>, CliError> {
-    let o2_window_error = format!("{o2_window}_error");
-
-    let o2_col = *row.get(o2_window).ok_or_else(|| {
-        CliError::custom(format!(
-            "row does not contain the {o2_window} window for O2"
-        ))
-    })?;
-    let o2_col_err = *row.get(&o2_window_error).ok_or_else(|| {
-        CliError::custom(format!(
-            "row does not contain the O2 column error, '{o2_window_error}'"
-        ))
-    })?;
-
-    let all_windows = row.keys().filter(|k| !k.ends_with("_error"));
-
-    let mut new_row = HashMap::new();
-    let col_dry_air = o2_col / o2_dmf;
-
-    for window in all_windows {
-        let window_error = format!("{window}_error");
-        let col_val = *row.get(window).unwrap(); // we know this will be in the row, b/c we're iterating over the row's keys
-        let col_err_val = *row.get(&window_error).ok_or_else(|| {
-            CliError::custom(format!("row does not contain the column '{window_error}' for the error value corresponding to '{window}'"))
-        })?;
-
-        // For most gases, if we're doing individual windows (opposed to window averages), we don't
-        // want to add in the O2 uncertainty to the Xgas value just yet. If we did, then it would
-        // get counted multiple times when average_results operates on the Xgas values. However,
-        // we want to calculate the proper XO2 error here, so that average_results can use it.
-        let gas_frac_uncert = if window == o2_window || !is_avg {
-            col_err_val
-        } else {
-            let v = col_err_val.powi(2) + (col_val * o2_col_err / o2_col).powi(2);
-            f64::sqrt(v)
-        };
-
-        let xgas_key = format!("x{window}");
-        let xgas_error_key = format!("x{window}_error");
-
-        let xgas_adcf = adcfs.get(&xgas_key);
-        let cf = xgas_adcf.map(|a| a.adcf).unwrap_or(0.0);
-        let p = xgas_adcf.map(|a| a.p).flatten().unwrap_or(DEFAULT_P);
-        let g = xgas_adcf.map(|a| a.g).flatten().unwrap_or(DEFAULT_G);
-        let sbf = symmetric_basis_function(sza, p, g);
-
-        if approx::abs_diff_eq!(col_val, missing_value) {
-            new_row.insert(xgas_key, missing_value);
-        } else {
-            let xgas = col_val / col_dry_air / (1.0 + cf * sbf);
-            new_row.insert(xgas_key, xgas);
-        }
-
-        if approx::abs_diff_eq!(col_err_val, missing_value) {
-            new_row.insert(xgas_error_key, missing_value);
-        } else {
-            let xgas_error = gas_frac_uncert / col_dry_air / (1.0 + cf * sbf);
-            new_row.insert(xgas_error_key, xgas_error);
-        }
-    }
-
-    Ok(new_row)
-}
-
-fn symmetric_basis_function(sza: f64, p: f64, g: f64) -> f64 {
-    ((sza + g) / (90.0 + g)).powf(p) - ((45.0 + g) / (90.0 + g)).powf(p)
-}
-
-fn program_version() -> ProgramVersion {
-    ProgramVersion {
-        program: "apply_tccon_airmass_correction".to_string(),
-        version: "Version 1.0".to_string(),
-        date: "2024-09-30".to_string(),
-        authors: "JLL".to_string(),
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use std::path::PathBuf;
 
-    use crate::{driver, AirmassCorrCli};
-    use ggg_rs::test_utils::{compare_output_text_files, remove_file_if_exists};
+    use clap_verbosity_flag::Verbosity;
+    use ggg_rs::tccon::corrections::check_all_adcfs_present;
+
+    use crate::{driver, AirmassCorrCli, DEFAULT_G, DEFAULT_P};
+    use ggg_rs::test_utils::{
+        assert_postproc_file_consistent, compare_output_text_files, remove_file_if_exists,
+    };
+    use ggg_rs::utils::GggCompatibility;
 
     #[test]
     fn test_airmass_correct_pa_benchmark() {
-        test_airmass_correct_pa_benchmark_inner("apply-tccon-airmass-correction");
+        test_airmass_correct_pa_benchmark_inner(
+            "apply-tccon-airmass-correction",
+            GggCompatibility::Current,
+        );
     }
 
     #[test]
@@ -351,10 +265,13 @@ mod tests {
         // This tests that the airmass correction code correctly handles upstream files from
         // the last GGG release. We don't need to specify the compatibility setting because
         // this program will keep the same auxiliary columns that are in the upstream file.
-        test_airmass_correct_pa_benchmark_inner("apply-tccon-airmass-correction-stable");
+        test_airmass_correct_pa_benchmark_inner(
+            "apply-tccon-airmass-correction-stable",
+            GggCompatibility::GGG2020,
+        );
     }
 
-    fn test_airmass_correct_pa_benchmark_inner(subdir: &str) {
+    fn test_airmass_correct_pa_benchmark_inner(subdir: &str, upstream_compat: GggCompatibility) {
         let crate_root = env!("CARGO_MANIFEST_DIR");
         let out_file_name = "pa_ggg_benchmark.vsw.ada";
 
@@ -377,10 +294,63 @@ mod tests {
             correction_file: input_dir.join("corrections_airmass_preavg.dat"),
             upstream_file: input_dir.join("pa_ggg_benchmark.vsw"),
             output_dir: Some(output_dir.clone()),
+            default_g: DEFAULT_G,
+            default_p: DEFAULT_P,
+            gas_defaults: vec![],
+            shape_defaults_file: None,
+            require_all_adcf: false,
+            verbosity: Verbosity::new(0, 0),
         };
 
         driver(clargs).expect("Running the airmass correction should not fail.");
 
         compare_output_text_files(&expected_dir, &output_dir, out_file_name);
+        assert_postproc_file_consistent(&output_dir.join(out_file_name), upstream_compat);
+    }
+
+    #[test]
+    fn test_require_all_adcf() {
+        let input_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("test-data/inputs/apply-tccon-airmass-correction");
+        let adcfs = ggg_rs::tccon::input_config::read_adcf_file(
+            &input_dir.join("corrections_airmass_preavg.dat"),
+        )
+        .unwrap();
+
+        let covered = [
+            "xco2_6220",
+            "xco2_6220_error",
+            "xch4_5938",
+            "xch4_5938_error",
+        ]
+        .map(String::from);
+        check_all_adcfs_present(&covered, &adcfs).unwrap();
+
+        let uncovered = ["xco2_6220", "xhf_4038", "xhf_4038_error", "xo2_7885"].map(String::from);
+        let msg = check_all_adcfs_present(&uncovered, &adcfs)
+            .unwrap_err()
+            .to_string();
+        assert!(
+            msg.ends_with("2 window(s) do not have an airmass correction: xhf_4038, xo2_7885"),
+            "{msg}"
+        );
+
+        // The full driver should refuse to write the output
+        let output_dir = ggg_rs::test_utils::output_dir("apply-tccon-airmass-correction-strict");
+        remove_file_if_exists(&output_dir.join("pa_ggg_benchmark.vsw.ada")).unwrap();
+        let clargs = AirmassCorrCli {
+            correction_file: input_dir.join("corrections_airmass_preavg.dat"),
+            upstream_file: input_dir.join("pa_ggg_benchmark.vsw"),
+            output_dir: Some(output_dir.clone()),
+            default_g: DEFAULT_G,
+            default_p: DEFAULT_P,
+            gas_defaults: vec![],
+            shape_defaults_file: None,
+            require_all_adcf: true,
+            verbosity: Verbosity::new(0, 0),
+        };
+        let err = driver(clargs).unwrap_err();
+        assert!(format!("{err:?}").contains("xhf_4038"), "{err:?}");
+        assert!(!output_dir.join("pa_ggg_benchmark.vsw.ada").exists());
     }
 }
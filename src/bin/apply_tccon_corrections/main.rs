@@ -0,0 +1,374 @@
+use std::{path::PathBuf, process::ExitCode};
+
+use clap::Parser;
+use clap_verbosity_flag::{InfoLevel, Verbosity};
+use error_stack::ResultExt;
+use ggg_rs::{
+    logging::init_logging,
+    readers::postproc_files::open_and_iter_postproc_file,
+    tccon::{
+        corrections::{
+            add_adcf_header_lines, add_aicf_header_lines, airmass_correction_version,
+            apply_airmass_correction, apply_insitu_correction, check_all_adcfs_present,
+            find_o2_window, input_is_averaged, insitu_correction_version, unused_aicf_entries,
+            AirmassCorrectionOptions, GasShapeDefault, ShapeDefaults, ShapeDefaultsFile, DEFAULT_G,
+            DEFAULT_P,
+        },
+        input_config,
+    },
+    writers::postproc_files::{PostprocHeaderInfo, PostprocWriter},
+};
+
+fn main() -> ExitCode {
+    let clargs = CorrectionsCli::parse();
+    init_logging(clargs.verbosity.log_level_filter());
+    if let Err(e) = driver(clargs) {
+        eprintln!("ERROR: {e:?}");
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// Apply the airmass and then the in situ corrections to a post processing file in one
+/// pass. The output is the same as running apply_tccon_airmass_correction followed by
+/// apply_tccon_insitu_correction, but without the intermediate `.ada` file.
+#[derive(Debug, Parser)]
+struct CorrectionsCli {
+    /// What file to read the airmass corrections from.
+    airmass_correction_file: PathBuf,
+
+    /// What file to read the in situ corrections from.
+    insitu_correction_file: PathBuf,
+
+    /// Path the to post processing file containing column densities
+    /// to correct and convert to column averages. In most cases, this
+    /// will be a `.vav` file.
+    upstream_file: PathBuf,
+
+    /// Directory in which to save the output file. If omitted, the output
+    /// file will be saved to the same directory as the upstream file.
+    #[clap(short = 'o', long)]
+    output_dir: Option<PathBuf>,
+
+    /// Value of g to use for any gas that does not define one in the
+    /// airmass correction file or with --gas-default.
+    #[clap(long, default_value_t = DEFAULT_G)]
+    default_g: f64,
+
+    /// Value of p to use for any gas that does not define one in the
+    /// airmass correction file or with --gas-default.
+    #[clap(long, default_value_t = DEFAULT_P)]
+    default_p: f64,
+
+    /// Default g and p for one gas, given as GAS=G,P (e.g. "xco2=15,4").
+    /// See apply_tccon_airmass_correction for details. May be repeated.
+    #[clap(long = "gas-default")]
+    gas_defaults: Vec<GasShapeDefault>,
+
+    /// TOML file giving default g and p values per gas or window, and optionally
+    /// for the O2 window. See apply_tccon_airmass_correction for details.
+    #[clap(long)]
+    shape_defaults_file: Option<PathBuf>,
+
+    /// Stop with an error if any retrieved window in the upstream file does not
+    /// have an entry in the airmass correction file. Without this, such windows are
+    /// not airmass corrected.
+    #[clap(long)]
+    require_all_adcf: bool,
+
+    /// Stop with an error if any entry in the in situ correction file does not match
+    /// a column in the upstream file. By default, these are only warned about.
+    #[clap(long)]
+    strict: bool,
+
+    #[command(flatten)]
+    verbosity: Verbosity<InfoLevel>,
+}
+
+#[derive(Debug, thiserror::Error)]
+enum CliError {
+    #[error("Error reading {}", .0.display())]
+    ReadError(PathBuf),
+    #[error("Error reading line {line} of {}", .file.display())]
+    ReadErrorAtLine { file: PathBuf, line: usize },
+    #[error("Error writing output {}, {cause}", .path.display())]
+    WriteError { path: PathBuf, cause: String },
+    #[error("{0}")]
+    Custom(String),
+}
+
+impl CliError {
+    fn custom<S: ToString>(msg: S) -> Self {
+        Self::Custom(msg.to_string())
+    }
+}
+
+fn driver(clargs: CorrectionsCli) -> error_stack::Result<(), CliError> {
+    let mut new_name = clargs
+        .upstream_file
+        .file_name()
+        .expect("upstream file should have a base name")
+        .to_os_string();
+    new_name.push(".ada.aia");
+
+    let out_dir = clargs.output_dir.as_deref().unwrap_or_else(|| {
+        clargs
+            .upstream_file
+            .parent()
+            .expect("upstream file should have a parent directory")
+    });
+    let out_file = out_dir.join(new_name);
+
+    // Read in both correction files
+    let adcfs = input_config::read_adcf_file(&clargs.airmass_correction_file)
+        .change_context_lazy(|| CliError::ReadError(clargs.airmass_correction_file.clone()))?;
+    let aicfs = input_config::read_aicf_file(&clargs.insitu_correction_file)
+        .change_context_lazy(|| CliError::ReadError(clargs.insitu_correction_file.clone()))?;
+    let mut shape_defaults =
+        ShapeDefaults::new(clargs.default_g, clargs.default_p, &clargs.gas_defaults);
+    if let Some(toml_file) = &clargs.shape_defaults_file {
+        let file_defaults = ShapeDefaultsFile::read(toml_file)
+            .change_context_lazy(|| CliError::ReadError(toml_file.clone()))?;
+        shape_defaults = shape_defaults.with_file_defaults(file_defaults);
+    }
+
+    let (mut header, rows) = open_and_iter_postproc_file(&clargs.upstream_file)
+        .change_context_lazy(|| CliError::ReadError(clargs.upstream_file.to_path_buf()))?;
+    let input_is_averaged =
+        input_is_averaged(&header, &clargs.upstream_file).map_err(CliError::custom)?;
+
+    let naux = header.naux;
+    let mut col_names = header.column_names.clone();
+    let o2_window = find_o2_window(&col_names, naux).map_err(CliError::custom)?;
+    shape_defaults.set_o2_window(&o2_window);
+
+    // Change the column names to prepend an "x" to all of the retrieved columns.
+    for name in col_names[naux..].iter_mut() {
+        name.insert(0, 'x');
+    }
+
+    if clargs.require_all_adcf {
+        check_all_adcfs_present(&col_names[naux..], &adcfs).map_err(CliError::custom)?;
+    }
+
+    // Correction file entries that don't match any column are most likely typos or stale
+    // gases, which would otherwise silently do nothing.
+    let unused = unused_aicf_entries(&col_names[naux..], &aicfs);
+    if !unused.is_empty() {
+        let msg = format!(
+            "{} entries in {} do not match any column in {}: {}",
+            unused.len(),
+            clargs.insitu_correction_file.display(),
+            clargs.upstream_file.display(),
+            unused.join(", ")
+        );
+        if clargs.strict {
+            return Err(CliError::custom(msg).into());
+        } else {
+            log::warn!("{msg}");
+        }
+    }
+
+    // Both sets of correction factors go in the header, in the order they are applied.
+    add_adcf_header_lines(&mut header.extra_lines, &adcfs, &shape_defaults).change_context_lazy(
+        || CliError::WriteError {
+            path: out_file.clone(),
+            cause: "writing the ADCF values in the header failed.".to_string(),
+        },
+    )?;
+    add_aicf_header_lines(&mut header.extra_lines, &aicfs).change_context_lazy(|| {
+        CliError::WriteError {
+            path: out_file.clone(),
+            cause: "writing the AICF values in the header failed.".to_string(),
+        }
+    })?;
+
+    let fw = std::fs::File::create(&out_file).change_context_lazy(|| CliError::WriteError {
+        path: out_file.to_path_buf(),
+        cause: "creating file failed".to_string(),
+    })?;
+    let fw = std::io::BufWriter::new(fw);
+
+    // Record both correction programs, as if they had been run one after the other, so that
+    // downstream programs see the same history either way.
+    let format_str = header.fformat_without_comment().fmt_string(1);
+    let mut program_versions = Vec::from_iter(header.program_versions.values().cloned());
+    program_versions.insert(0, airmass_correction_version());
+    program_versions.insert(0, insitu_correction_version());
+    let mut writer = PostprocWriter::new(
+        fw,
+        PostprocHeaderInfo {
+            nrow: header.nrec,
+            naux,
+            program_versions: &program_versions,
+            extra_lines: &header.extra_lines,
+            missing_value: header.missing_value,
+            format_str: &format_str,
+            column_names: &col_names,
+        },
+    )
+    .change_context_lazy(|| CliError::WriteError {
+        path: out_file.clone(),
+        cause: "error occurred while writing the file header".to_string(),
+    })?;
+
+    let missing_value = header.missing_value;
+    let correction_opts = AirmassCorrectionOptions {
+        adcfs: &adcfs,
+        shape_defaults: &shape_defaults,
+        o2_window: &o2_window,
+        missing_value,
+        is_avg: input_is_averaged,
+    };
+
+    for (irow, row) in rows.enumerate() {
+        let mut row = row.change_context_lazy(|| CliError::ReadErrorAtLine {
+            file: clargs.upstream_file.clone(),
+            line: header.nhead + irow + 1,
+        })?;
+
+        let xgas_row = apply_airmass_correction(
+            &row.retrieved,
+            row.auxiliary.o2dmf,
+            row.auxiliary.solzen,
+            &correction_opts,
+        )
+        .map_err(CliError::custom)?;
+        row.retrieved =
+            apply_insitu_correction(&xgas_row, &aicfs, missing_value).map_err(CliError::custom)?;
+
+        writer
+            .write_row(&row)
+            .change_context_lazy(|| CliError::WriteError {
+                path: out_file.clone(),
+                cause: format!("error serializing data line {}", irow + 1),
+            })?;
+    }
+
+    writer
+        .finish()
+        .change_context_lazy(|| CliError::WriteError {
+            path: out_file.clone(),
+            cause: "error finishing the file".to_string(),
+        })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use clap_verbosity_flag::Verbosity;
+
+    use crate::{driver, CorrectionsCli, DEFAULT_G, DEFAULT_P};
+    use ggg_rs::readers::postproc_files::open_and_iter_postproc_file;
+    use ggg_rs::test_utils::{assert_postproc_file_consistent, remove_file_if_exists};
+    use ggg_rs::utils::GggCompatibility;
+
+    #[test]
+    fn test_combined_corrections_pa_benchmark() {
+        let crate_root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let input_dir = crate_root.join("test-data/inputs/apply-tccon-airmass-correction");
+        let expected_ada = crate_root
+            .join("test-data/expected/apply-tccon-airmass-correction/pa_ggg_benchmark.vsw.ada");
+        let output_dir = ggg_rs::test_utils::output_dir("apply-tccon-corrections");
+        let out_file = output_dir.join("pa_ggg_benchmark.vsw.ada.aia");
+        remove_file_if_exists(&out_file).unwrap();
+
+        // The benchmark .vsw has per-window Xgas names, so use an in situ correction file
+        // that names one of them.
+        let aicf_file = output_dir.join("corrections_insitu_test.dat");
+        std::fs::write(
+            &aicf_file,
+            "3 4\n Test in situ corrections\n Gas AICF AICF_Err WMO_Scale\n\"xco2_6220\" 1.0101 0.0005 \"WMO CO2 X2007\"\n",
+        )
+        .unwrap();
+
+        let clargs = CorrectionsCli {
+            airmass_correction_file: input_dir.join("corrections_airmass_preavg.dat"),
+            insitu_correction_file: aicf_file,
+            upstream_file: input_dir.join("pa_ggg_benchmark.vsw"),
+            output_dir: Some(output_dir.clone()),
+            default_g: DEFAULT_G,
+            default_p: DEFAULT_P,
+            gas_defaults: vec![],
+            shape_defaults_file: None,
+            require_all_adcf: false,
+            strict: true,
+            verbosity: Verbosity::new(0, 0),
+        };
+        driver(clargs).expect("Running the combined corrections should not fail.");
+        assert_postproc_file_consistent(&out_file, GggCompatibility::Current);
+
+        // The values should match the airmass-corrected benchmark, divided by the AICF
+        // for the one corrected window.
+        let (header, rows) = open_and_iter_postproc_file(&out_file).unwrap();
+        assert_eq!(
+            header.program_versions.keys().take(2).collect::<Vec<_>>(),
+            [
+                "apply_tccon_insitu_correction",
+                "apply_tccon_airmass_correction"
+            ]
+        );
+        let (_, expected_rows) = open_and_iter_postproc_file(&expected_ada).unwrap();
+        let mut nrow = 0;
+        for (row, expected) in rows.zip(expected_rows) {
+            let (row, expected) = (row.unwrap(), expected.unwrap());
+            for (key, &e) in expected.retrieved.iter() {
+                let e = if key.starts_with("xco2_6220") {
+                    e / 1.0101
+                } else {
+                    e
+                };
+                approx::assert_relative_eq!(row.retrieved[key], e, max_relative = 1e-4);
+            }
+            nrow += 1;
+        }
+        assert_eq!(nrow, header.nrec);
+    }
+
+    #[test]
+    fn test_combined_corrections_checks() {
+        let crate_root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let input_dir = crate_root.join("test-data/inputs/apply-tccon-airmass-correction");
+        // Use a separate directory so this does not race with the benchmark test
+        let output_dir = ggg_rs::test_utils::output_dir("apply-tccon-corrections-checks");
+        let out_file = output_dir.join("pa_ggg_benchmark.vsw.ada.aia");
+
+        let aicf_file = output_dir.join("corrections_insitu_unused.dat");
+        std::fs::write(
+            &aicf_file,
+            "3 4\n Test in situ corrections\n Gas AICF AICF_Err WMO_Scale\n\"xc02\" 1.0101 0.0005 \"WMO CO2 X2007\"\n",
+        )
+        .unwrap();
+        let make_clargs = |require_all_adcf, strict| CorrectionsCli {
+            airmass_correction_file: input_dir.join("corrections_airmass_preavg.dat"),
+            insitu_correction_file: aicf_file.clone(),
+            upstream_file: input_dir.join("pa_ggg_benchmark.vsw"),
+            output_dir: Some(output_dir.clone()),
+            default_g: DEFAULT_G,
+            default_p: DEFAULT_P,
+            gas_defaults: vec![],
+            shape_defaults_file: None,
+            require_all_adcf,
+            strict,
+            verbosity: Verbosity::new(0, 0),
+        };
+
+        // The benchmark has windows without an airmass correction
+        remove_file_if_exists(&out_file).unwrap();
+        let err = driver(make_clargs(true, false)).unwrap_err();
+        assert!(format!("{err:?}").contains("xhf_4038"), "{err:?}");
+        assert!(!out_file.exists());
+
+        // The in situ correction file has an entry that matches no column
+        let err = driver(make_clargs(false, true)).unwrap_err();
+        assert!(format!("{err:?}").contains("xc02"), "{err:?}");
+        assert!(!out_file.exists());
+
+        // Without either flag, both are allowed
+        driver(make_clargs(false, false)).unwrap();
+        assert!(out_file.exists());
+    }
+}
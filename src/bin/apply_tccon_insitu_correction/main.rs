@@ -1,17 +1,24 @@
-use std::{collections::HashMap, path::PathBuf, process::ExitCode};
+use std::{path::PathBuf, process::ExitCode};
 
 use clap::Parser;
+use clap_verbosity_flag::{InfoLevel, Verbosity};
 use error_stack::ResultExt;
-use fortformat::FortFormat;
 use ggg_rs::{
-    readers::{postproc_files::open_and_iter_postproc_file, ProgramVersion},
-    tccon::input_config::{self, AicfRow},
-    writers::postproc_files::write_postproc_header,
+    logging::init_logging,
+    readers::postproc_files::open_and_iter_postproc_file,
+    tccon::{
+        corrections::{
+            add_aicf_header_lines, apply_insitu_correction, insitu_correction_version,
+            unused_aicf_entries,
+        },
+        input_config,
+    },
+    writers::postproc_files::{PostprocHeaderInfo, PostprocWriter},
 };
-use indexmap::IndexMap;
 
 fn main() -> ExitCode {
     let clargs = InsituCorrCli::parse();
+    init_logging(clargs.verbosity.log_level_filter());
     if let Err(e) = driver(clargs) {
         eprintln!("ERROR: {e:?}");
         ExitCode::FAILURE
@@ -34,6 +41,14 @@ struct InsituCorrCli {
     /// file will be saved to the same directory as the upstream file.
     #[clap(short = 'o', long)]
     output_dir: Option<PathBuf>,
+
+    /// Stop with an error if any entry in the correction file does not match
+    /// a column in the upstream file. By default, these are only warned about.
+    #[clap(long)]
+    strict: bool,
+
+    #[command(flatten)]
+    verbosity: Verbosity<InfoLevel>,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -77,6 +92,25 @@ fn driver(clargs: InsituCorrCli) -> error_stack::Result<(), CliError> {
     // Read in the header of the previous postproc file, add the in situ correction factors
     let (mut header, rows) = open_and_iter_postproc_file(&clargs.upstream_file)
         .change_context_lazy(|| CliError::ReadError(clargs.upstream_file.to_path_buf()))?;
+
+    // Correction file entries that don't match any column are most likely typos or stale
+    // gases, which would otherwise silently do nothing.
+    let unused = unused_aicf_entries(&header.column_names[header.naux..], &aicfs);
+    if !unused.is_empty() {
+        let msg = format!(
+            "{} entries in {} do not match any column in {}: {}",
+            unused.len(),
+            clargs.correction_file.display(),
+            clargs.upstream_file.display(),
+            unused.join(", ")
+        );
+        if clargs.strict {
+            return Err(CliError::custom(msg).into());
+        } else {
+            log::warn!("{msg}");
+        }
+    }
+
     add_aicf_header_lines(&mut header.extra_lines, &aicfs).change_context_lazy(|| {
         CliError::WriteError {
             path: out_file.clone(),
@@ -89,139 +123,80 @@ fn driver(clargs: InsituCorrCli) -> error_stack::Result<(), CliError> {
         path: out_file.to_path_buf(),
         cause: "creating file failed".to_string(),
     })?;
-    let mut fw = std::io::BufWriter::new(fw);
+    let fw = std::io::BufWriter::new(fw);
 
+    // The original apply_insitu_correction switches the second field from "a1" to "1x".
+    // We'll keep that behavior for backwards compatibility for now. Eventually we could
+    // probably make that change back in collate_tccon_results, once all the post processing
+    // programs can support that.
     let format_str = header.fformat_without_comment().fmt_string(1);
     let mut program_versions = Vec::from_iter(header.program_versions.values().cloned());
-    program_versions.insert(0, program_version());
-
-    write_postproc_header(
-        &mut fw,
-        header.column_names.len(),
-        header.nrec,
-        header.naux,
-        &program_versions,
-        &header.extra_lines,
-        header.missing_value,
-        &format_str,
-        &header.column_names,
+    program_versions.insert(0, insitu_correction_version());
+
+    let mut writer = PostprocWriter::new(
+        fw,
+        PostprocHeaderInfo {
+            nrow: header.nrec,
+            naux: header.naux,
+            program_versions: &program_versions,
+            extra_lines: &header.extra_lines,
+            missing_value: header.missing_value,
+            format_str: &format_str,
+            column_names: &header.column_names,
+        },
     )
     .change_context_lazy(|| CliError::WriteError {
         path: out_file.clone(),
         cause: "error occurred while writing the file header".to_string(),
     })?;
 
-    // Read each row, apply airmass corrections, and write out the Xgas values.
-    let settings = fortformat::ser::SerSettings::default()
-        .align_left_str(true)
-        .allow_skipped_fields(true);
+    // Read each row, apply in situ corrections, and write out the Xgas values.
     let missing_value = header.missing_value;
-
-    // The original apply_insitu_correction switches the second field from "a1" to "1x".
-    // We'll keep that behavior for backwards compatibility for now. Eventually we could
-    // probably make that change back in collate_tccon_results, once all the post processing
-    // programs can support that.
-    let writer_fformat = header.fformat_without_comment();
     for (irow, row) in rows.enumerate() {
         let mut row = row.change_context_lazy(|| CliError::ReadErrorAtLine {
             file: clargs.upstream_file.clone(),
             line: header.nhead + irow + 1,
         })?;
 
-        row.retrieved = apply_correction(&row.retrieved, &aicfs, missing_value)?;
+        row.retrieved = apply_insitu_correction(&row.retrieved, &aicfs, missing_value)
+            .map_err(CliError::custom)?;
 
-        fortformat::ser::to_writer_custom(
-            row,
-            &writer_fformat,
-            Some(&header.column_names),
-            &settings,
-            &mut fw,
-        )
+        writer
+            .write_row(&row)
+            .change_context_lazy(|| CliError::WriteError {
+                path: out_file.clone(),
+                cause: format!("error serializing data line {}", irow + 1),
+            })?;
+    }
+
+    writer
+        .finish()
         .change_context_lazy(|| CliError::WriteError {
             path: out_file.clone(),
-            cause: format!("error serializing data line {}", irow + 1),
+            cause: "error finishing the file".to_string(),
         })?;
-    }
-
     Ok(())
 }
 
-fn add_aicf_header_lines(
-    lines_out: &mut Vec<String>,
-    aicfs: &IndexMap<String, AicfRow>,
-) -> Result<(), fortformat::SError> {
-    let nrow = aicfs.len();
-    lines_out.push(format!(
-        " Airmass-Independent/In-Situ Correction Factors: {nrow} 4"
-    ));
-    let ff = FortFormat::parse("(a23,2f9.4,1x,a1,a,a1)").unwrap();
-    let settings = fortformat::ser::SerSettings::default().align_left_str(true);
-    for corr in aicfs.values() {
-        let values = (
-            &corr.gas,
-            corr.aicf,
-            corr.aicf_error,
-            '"',
-            &corr.wmo_scale,
-            '"',
-        );
-        let s = fortformat::ser::to_string_custom::<_, &str>(values, &ff, None, &settings)?;
-        lines_out.push(s);
-    }
-
-    Ok(())
-}
-
-fn apply_correction(
-    row: &HashMap<String, f64>,
-    aicfs: &IndexMap<String, AicfRow>,
-    missing_value: f64,
-) -> Result<HashMap<String, f64>, CliError> {
-    let all_xgases = row.keys().filter(|k| !k.ends_with("_error"));
-
-    let mut new_row = HashMap::new();
-    for xgas in all_xgases {
-        let xgas_error = format!("{xgas}_error");
-        let col_val = *row.get(xgas).unwrap(); // we know this will be in the row, b/c we're iterating over the row's keys
-        let col_err_val = *row.get(&xgas_error).ok_or_else(|| {
-            CliError::custom(format!("row does not contain the column '{xgas_error}' for the error value corresponding to '{xgas}'"))
-        })?;
-
-        let cf = aicfs.get(xgas).map(|r| r.aicf).unwrap_or(1.0);
-        if approx::abs_diff_eq!(col_val, missing_value) {
-            new_row.insert(xgas.to_owned(), missing_value);
-        } else {
-            new_row.insert(xgas.to_owned(), col_val / cf);
-        }
-
-        if approx::abs_diff_eq!(col_err_val, missing_value) {
-            new_row.insert(xgas_error, missing_value);
-        } else {
-            new_row.insert(xgas_error, col_err_val / cf);
-        }
-    }
-    Ok(new_row)
-}
-
-fn program_version() -> ProgramVersion {
-    ProgramVersion {
-        program: "apply_tccon_insitu_correction".to_string(),
-        version: "Version 1.0".to_string(),
-        date: "2025-03-31".to_string(),
-        authors: "JLL".to_string(),
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use std::path::PathBuf;
 
+    use clap_verbosity_flag::Verbosity;
+    use ggg_rs::tccon::corrections::unused_aicf_entries;
+
     use crate::{driver, InsituCorrCli};
-    use ggg_rs::test_utils::{compare_output_text_files, remove_file_if_exists};
+    use ggg_rs::test_utils::{
+        assert_postproc_file_consistent, compare_output_text_files, remove_file_if_exists,
+    };
+    use ggg_rs::utils::GggCompatibility;
 
     #[test]
     fn test_insitu_correct_pa_benchmark() {
-        test_insitu_correct_pa_benchmark_inner("apply-tccon-insitu-correction");
+        test_insitu_correct_pa_benchmark_inner(
+            "apply-tccon-insitu-correction",
+            GggCompatibility::Current,
+        );
     }
 
     #[test]
@@ -229,10 +204,13 @@ mod tests {
         // This tests that the in situ correction code correctly handles upstream files from
         // the last GGG release. We don't need to specify the compatibility setting because
         // this program will keep the same auxiliary columns that are in the upstream file.
-        test_insitu_correct_pa_benchmark_inner("apply-tccon-insitu-correction-stable");
+        test_insitu_correct_pa_benchmark_inner(
+            "apply-tccon-insitu-correction-stable",
+            GggCompatibility::GGG2020,
+        );
     }
 
-    fn test_insitu_correct_pa_benchmark_inner(subdir: &str) {
+    fn test_insitu_correct_pa_benchmark_inner(subdir: &str, upstream_compat: GggCompatibility) {
         let crate_root = env!("CARGO_MANIFEST_DIR");
         let out_file_name = "pa_ggg_benchmark.vav.ada.aia";
         let input_dir = PathBuf::from(crate_root)
@@ -254,10 +232,29 @@ mod tests {
             correction_file: input_dir.join("corrections_insitu_postavg.dat"),
             upstream_file: input_dir.join("pa_ggg_benchmark.vav.ada"),
             output_dir: Some(output_dir.clone()),
+            strict: true,
+            verbosity: Verbosity::new(0, 0),
         };
 
         driver(clargs).expect("Running the airmass correction should not fail.");
 
         compare_output_text_files(&expected_dir, &output_dir, out_file_name);
+        assert_postproc_file_consistent(&output_dir.join(out_file_name), upstream_compat);
+    }
+
+    #[test]
+    fn test_unused_aicf_entries() {
+        let input_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("test-data/inputs/apply-tccon-insitu-correction");
+        let aicfs = ggg_rs::tccon::input_config::read_aicf_file(
+            &input_dir.join("corrections_insitu_postavg.dat"),
+        )
+        .unwrap();
+        let mut columns = aicfs.keys().cloned().collect::<Vec<_>>();
+        assert!(unused_aicf_entries(&columns, &aicfs).is_empty());
+
+        let removed = columns.remove(0);
+        columns.push("xc02".to_string());
+        assert_eq!(unused_aicf_entries(&columns, &aicfs), [removed.as_str()]);
     }
 }
@@ -9,11 +9,16 @@ use clap::Parser;
 use error_stack::ResultExt;
 use ggg_rs::{
     self,
+    interpolation::{InterpolationError, InterpolationMethod, LinearInterp},
+    nc_utils::get_string_attr,
     opus::Spectrum,
-    readers::runlogs::{Runlog, RunlogDataRec},
+    readers::runlogs::{FallibleRunlog, Runlog, RunlogDataRec},
+    units::{cf_unit, long_name_with_unit},
     utils::{self, GggError},
 };
+use itertools::Itertools;
 use netcdf::Extents;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
 
 /// Generate netCDF versions of binary TCCON spectra listed in a given runlog
 ///
@@ -32,20 +37,82 @@ struct Cli {
     output: PathBuf,
 
     /// Set this flag to output a single file containing all spectra, rather than separate files
-    /// for each spectrum. Note that this requires all spectra to have the same frequency grid.
+    /// for each spectrum. Spectra from the same detector on different frequency grids are put
+    /// in separate groups unless --resample is given.
     #[clap(short = 's', long = "single-file")]
     single_file: bool,
 
+    /// Set this flag in single-file mode to add the spectra to an existing file previously
+    /// written by bin2nc, rather than overwriting it. Spectra continue from the next index in
+    /// each group; ones that do not fit an existing group are put in new groups. Files written
+    /// with --resample cannot be appended to.
+    #[clap(long, requires = "single_file", conflicts_with = "resample")]
+    append: bool,
+
     /// Set this flag to output the full path to each spectrum, rather than its name, as the
     /// "spectrum" variable in a multiple-spectrum file. In a single-spectrum file, this will
     /// be added as a root-level attribute.
     #[clap(short = 'f', long)]
     full_spec_paths: bool,
 
+    /// Set this flag in single-file mode to linearly interpolate all spectra from the same
+    /// detector onto a common frequency grid, rather than storing each one on its native grid.
+    /// The common grid spans the frequency ranges of all those spectra; points outside a given
+    /// spectrum's range will be NaNs. Use --grid-spacing to set the grid's point spacing,
+    /// otherwise the finest spacing of any of the spectra is used.
+    #[clap(long, requires = "single_file")]
+    resample: bool,
+
+    /// The point spacing in cm-1 of the common frequency grid used by --resample.
+    #[clap(long, requires = "resample")]
+    grid_spacing: Option<f64>,
+
+    /// The maximum number of spectra to read at once. The default is to use one per CPU.
+    /// When writing individual files, each spectrum is also written by its own thread;
+    /// with --single-file, spectra are read in parallel but written one at a time, in
+    /// runlog order.
+    #[clap(short = 'j', long)]
+    jobs: Option<usize>,
+
+    /// Compress the frequency and intensity variables with this zlib deflate level (1 to 9).
+    /// The default is to leave them uncompressed, which is faster to write; level 4 is a good
+    /// balance of speed and file size.
+    #[clap(long, value_parser = clap::value_parser!(i32).range(1..=9))]
+    compression: Option<i32>,
+
+    /// Store a variable in chunks of this many frequency points, given as VARIABLE=SIZE,
+    /// e.g. "intensity=4096". VARIABLE may be "frequency" or "intensity". Can be given
+    /// once for each. The default is to let the netCDF library choose the chunk sizes.
+    #[clap(long, value_parser = parse_chunk_size)]
+    chunk_size: Vec<(String, usize)>,
+
+    /// Write the instrument pressure as "instrumnent_pressure", the misspelled name used by
+    /// earlier versions, for programs that still expect it. This will be removed in a
+    /// future release.
+    #[clap(long)]
+    legacy_varnames: bool,
+
+    /// Where to get the time of each spectrum from. "runlog" uses the zero path difference
+    /// time from the runlog. "header" uses the DAT and TIM parameters in the spectrum's Opus
+    /// header converted to UTC, which is useful to cross-check the runlog times. Note that
+    /// this is the time the measurement started, not the ZPD time. Spectra whose header
+    /// lacks them fall back on the runlog time.
+    #[clap(long, value_enum, default_value_t = TimeSource::Runlog)]
+    time_source: TimeSource,
+
     #[clap(flatten)]
     data_part_args: utils::DataPartArgs,
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+enum TimeSource {
+    /// Compute the time from the year, day, and hour in the runlog
+    #[default]
+    Runlog,
+    /// Read the measurement start time from the spectrum's Opus header
+    Header,
+}
+
 #[derive(Debug, thiserror::Error)]
 enum CliError {
     #[error("Error occurred while reading {}", .0.display())]
@@ -80,7 +147,32 @@ fn main() -> ExitCode {
     }
 }
 
+fn parse_chunk_size(s: &str) -> Result<(String, usize), String> {
+    let (varname, size) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected VARIABLE=SIZE, got '{s}'"))?;
+    if !OutputOptions::STORAGE_VARIABLES.contains(&varname) {
+        return Err(format!(
+            "chunk sizes can only be set for {}, not '{varname}'",
+            OutputOptions::STORAGE_VARIABLES.join(" or ")
+        ));
+    }
+    let size: usize = size
+        .parse()
+        .map_err(|e| format!("invalid chunk size '{size}': {e}"))?;
+    if size == 0 {
+        return Err("chunk size must be at least 1".to_string());
+    }
+    Ok((varname.to_string(), size))
+}
+
 fn driver(clargs: Cli) -> error_stack::Result<(), CliError> {
+    let opts = OutputOptions {
+        compression: clargs.compression,
+        chunk_sizes: clargs.chunk_size.iter().cloned().collect(),
+        legacy_varnames: clargs.legacy_varnames,
+        time_source: clargs.time_source,
+    };
     let data_part = clargs
         .data_part_args
         .get_data_partition()
@@ -94,44 +186,219 @@ fn driver(clargs: Cli) -> error_stack::Result<(), CliError> {
     if clargs.single_file {
         let runlog_clone = ggg_rs::readers::runlogs::Runlog::open(&clargs.runlog)
             .change_context_lazy(|| CliError::read_error(&clargs.runlog))?;
-        let writer = MultipleNcWriter::new_with_default_map(
-            &data_part,
-            clargs.output.clone(),
-            runlog_clone,
-            true,
-        )
+        let resample = match (clargs.resample, clargs.grid_spacing) {
+            (false, _) => None,
+            (true, None) => Some(ResampleGrid::FinestSpacing),
+            (true, Some(spacing)) if spacing > 0.0 => Some(ResampleGrid::Spacing(spacing)),
+            (true, Some(spacing)) => {
+                return Err(CliError::custom(format!(
+                    "--grid-spacing must be positive, got {spacing}"
+                ))
+                .into())
+            }
+        };
+        let writer = if clargs.append {
+            MultipleNcWriter::open_for_append(&data_part, clargs.output.clone(), runlog_clone, opts)
+        } else {
+            MultipleNcWriter::new_with_default_map(
+                &data_part,
+                clargs.output.clone(),
+                runlog_clone,
+                true,
+                resample,
+                opts,
+            )
+        }
         .change_context_lazy(|| CliError::write_error(&clargs.output))?;
-        writer_loop(writer, runlog, &data_part, clargs.full_spec_paths)?;
+        writer_loop(
+            writer,
+            runlog,
+            &data_part,
+            clargs.full_spec_paths,
+            clargs.jobs,
+        )?;
     } else {
-        let writer = IndividualNcWriter::new(clargs.output).unwrap();
-        writer_loop(writer, runlog, &data_part, clargs.full_spec_paths)?;
+        let writer = IndividualNcWriter::new(clargs.output, opts).unwrap();
+        parallel_writer_loop(
+            &writer,
+            runlog,
+            &data_part,
+            clargs.full_spec_paths,
+            clargs.jobs,
+        )?;
     }
 
     Ok(())
 }
 
+/// Convert each spectrum to its own netCDF file, using up to `jobs` threads.
+///
+/// Unlike [`writer_loop`], a spectrum that fails to convert does not stop the others;
+/// all failures are reported once every spectrum has been attempted.
+///
+/// Each thread writes its own file. This is only safe because netcdf-rs holds a global lock
+/// around every call into the netCDF library, which is not itself thread safe; it also means
+/// that only the reading of spectra actually runs in parallel.
+fn parallel_writer_loop(
+    writer: &IndividualNcWriter,
+    runlog: Runlog,
+    data_part: &utils::DataPartition,
+    full_spec_paths: bool,
+    jobs: Option<usize>,
+) -> error_stack::Result<(), CliError> {
+    let pool = build_thread_pool(jobs)?;
+
+    let data_recs: Vec<RunlogDataRec> = runlog.into_iter().collect();
+    let n_spectra = data_recs.len();
+
+    let failures: Vec<(String, error_stack::Report<CliError>)> = pool.install(|| {
+        data_recs
+            .into_par_iter()
+            .filter_map(|data_rec| {
+                match convert_one_spectrum(writer, &data_rec, data_part, full_spec_paths) {
+                    Ok(()) => {
+                        println!("Wrote spectrum {} as netCDF", data_rec.spectrum_name);
+                        None
+                    }
+                    Err(e) => Some((data_rec.spectrum_name, e)),
+                }
+            })
+            .collect()
+    });
+
+    if failures.is_empty() {
+        return Ok(());
+    }
+
+    for (spectrum_name, err) in failures.iter() {
+        eprintln!("Failed to convert spectrum {spectrum_name}:\n{err:?}\n");
+    }
+
+    let failed_names = failures
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .sorted()
+        .join(", ");
+    Err(CliError::custom(format!(
+        "{} of {n_spectra} spectra could not be converted ({failed_names}), see above for details",
+        failures.len()
+    ))
+    .into())
+}
+
+fn convert_one_spectrum(
+    writer: &IndividualNcWriter,
+    data_rec: &RunlogDataRec,
+    data_part: &utils::DataPartition,
+    full_spec_paths: bool,
+) -> error_stack::Result<(), CliError> {
+    let spec = ggg_rs::opus::read_spectrum_from_runlog_rec(data_rec, data_part)
+        .change_context_lazy(|| CliError::custom("Error while reading spectrum from the runlog"))?;
+    writer
+        .write_spectrum_file(data_rec, &spec, full_spec_paths)
+        .change_context_lazy(|| {
+            CliError::custom(format!(
+                "Error while writing spectrum {} to the output file",
+                spec.path.display()
+            ))
+        })
+}
+
+/// Write all spectra in `runlog` into the single output file managed by `writer`.
+///
+/// Spectra are read in batches, with each batch read in parallel using up to `jobs` threads.
+/// Writing happens on this thread only, since the netCDF handle for the one output file is not
+/// shared between threads, and in runlog order, so each spectrum gets the same index within
+/// its group as it would if read serially.
 fn writer_loop<W: NcWriter>(
     mut writer: W,
     runlog: Runlog,
     data_part: &utils::DataPartition,
     full_spec_paths: bool,
+    jobs: Option<usize>,
 ) -> error_stack::Result<(), CliError> {
-    for data_rec in runlog.into_iter() {
-        let spec = ggg_rs::opus::read_spectrum_from_runlog_rec(&data_rec, data_part)
-            .change_context_lazy(|| CliError::custom("Error while reading line from the runlog"))?;
-        writer
-            .add_spectrum(&data_rec, &spec, full_spec_paths)
-            .change_context_lazy(|| {
+    let pool = build_thread_pool(jobs)?;
+    let batch_size = pool.current_num_threads() * SPECTRA_PER_THREAD;
+    let mut data_recs = FallibleRunlog::from(runlog);
+
+    loop {
+        let batch: Vec<RunlogDataRec> = data_recs
+            .by_ref()
+            .take(batch_size)
+            .collect::<Result<_, _>>()
+            .change_context_lazy(|| CliError::custom("Error while reading the runlog"))?;
+        if batch.is_empty() {
+            break;
+        }
+
+        // Collecting an indexed parallel iterator keeps the runlog order
+        let spectra: Vec<(RunlogDataRec, Result<Spectrum, GggError>)> = pool.install(|| {
+            batch
+                .into_par_iter()
+                .map(|data_rec| {
+                    let spec = ggg_rs::opus::read_spectrum_from_runlog_rec(&data_rec, data_part);
+                    (data_rec, spec)
+                })
+                .collect()
+        });
+
+        for (data_rec, spec) in spectra {
+            let spec = spec.change_context_lazy(|| {
                 CliError::custom(format!(
-                    "Error while writing spectrum {} to the output file",
-                    spec.path.display()
+                    "Error while reading spectrum {} listed in the runlog",
+                    data_rec.spectrum_name
                 ))
             })?;
-        println!("Wrote spectrum {} as netCDF", data_rec.spectrum_name);
+            writer
+                .add_spectrum(&data_rec, &spec, full_spec_paths)
+                .change_context_lazy(|| {
+                    CliError::custom(format!(
+                        "Error while writing spectrum {} to the output file",
+                        spec.path.display()
+                    ))
+                })?;
+            println!("Wrote spectrum {} as netCDF", data_rec.spectrum_name);
+        }
     }
     Ok(())
 }
 
+/// How many spectra per thread [`writer_loop`] reads before writing them out.
+const SPECTRA_PER_THREAD: usize = 4;
+
+fn build_thread_pool(jobs: Option<usize>) -> error_stack::Result<rayon::ThreadPool, CliError> {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(jobs.unwrap_or(0))
+        .build()
+        .change_context_lazy(|| CliError::custom("Could not set up the thread pool"))
+}
+
+/// Get the time of a spectrum from `time_source`.
+///
+/// For [`TimeSource::Runlog`], this is the ZPD time. For [`TimeSource::Header`], this is
+/// the measurement start time in the Opus header, converted to UTC using the GMT offset
+/// recorded with it. A header that cannot be read or lacks the DAT and TIM parameters
+/// falls back on the runlog time, with a warning.
+fn spectrum_time(
+    data_rec: &RunlogDataRec,
+    spectrum: &Spectrum,
+    time_source: TimeSource,
+) -> Option<chrono::DateTime<chrono::Utc>> {
+    if time_source == TimeSource::Header {
+        let header_time = ggg_rs::opus::read_spectrum_header(&spectrum.path)
+            .ok()
+            .and_then(|header| header.measurement_time());
+        match header_time {
+            Some(time) => return Some(time.to_utc()),
+            None => eprintln!(
+                "Warning: could not get the measurement time from the header of {}, using the runlog time instead",
+                spectrum.path.display()
+            ),
+        }
+    }
+    data_rec.zpd_time()
+}
+
 trait NcWriter {
     fn add_spectrum(
         &mut self,
@@ -154,6 +421,7 @@ trait NcWriter {
         data: &ndarray::Array1<f32>,
         units: &str,
         description: &str,
+        opts: &OutputOptions,
     ) -> error_stack::Result<netcdf::VariableMut<'f>, CliError>;
 
     fn freq_dim() -> &'static str {
@@ -167,6 +435,7 @@ trait NcWriter {
         out_file: &Path,
         spec_idx: usize,
         write_freq: bool,
+        opts: &OutputOptions,
     ) -> error_stack::Result<(), CliError> {
         // Create the main variables (frequency and intensity)
         let dimname = Self::freq_dim();
@@ -179,6 +448,7 @@ trait NcWriter {
                 &spectrum.freq,
                 "cm-1",
                 "Frequency in wavenumbers of the measured intensity",
+                opts,
             )
             .change_context_lazy(|| CliError::write_error(out_file))?;
         }
@@ -190,11 +460,12 @@ trait NcWriter {
             &spectrum.spec,
             "AU",
             "Measured radiance intensity in arbitrary units",
+            opts,
         )
         .change_context_lazy(|| CliError::write_error(out_file))?;
 
         // Create the ancillary variables from the runlog that we actually care about
-        let timestamp = data_rec.zpd_time()
+        let timestamp = spectrum_time(data_rec, spectrum, opts.time_source)
             .ok_or_else(|| CliError::custom(format!(
                 "Error getting the ZPD time for spectrum {}, calculated ZPD time was not a valid time", data_rec.spectrum_name
             )))?.timestamp();
@@ -204,7 +475,12 @@ trait NcWriter {
             spec_idx,
             timestamp,
             "seconds since 1970-01-01",
-            "Zero path difference time for this spectrum",
+            match opts.time_source {
+                TimeSource::Runlog => "Zero path difference time for this spectrum",
+                TimeSource::Header => {
+                    "Measurement start time for this spectrum from its Opus header"
+                }
+            },
         )
         .change_context_lazy(|| CliError::write_error(out_file))?;
 
@@ -383,15 +659,20 @@ trait NcWriter {
         )
         .change_context_lazy(|| CliError::write_error(out_file))?;
 
-        Self::write_0d_var(
+        let mut pins_var = Self::write_0d_var(
             nc,
-            "instrumnent_pressure",
+            opts.instrument_pressure_varname(),
             spec_idx,
             data_rec.pins,
             "mbar",
             "Pressure inside the instrument",
         )
         .change_context_lazy(|| CliError::write_error(out_file))?;
+        if spec_idx == 0 {
+            pins_var
+                .put_attribute("long_name", "instrument pressure")
+                .change_context_lazy(|| CliError::write_error(out_file))?;
+        }
 
         Self::write_0d_var(
             nc,
@@ -483,10 +764,11 @@ trait NcWriter {
 
 struct IndividualNcWriter {
     save_dir: PathBuf,
+    opts: OutputOptions,
 }
 
 impl IndividualNcWriter {
-    fn new(out_path: PathBuf) -> Result<Self, GggError> {
+    fn new(out_path: PathBuf, opts: OutputOptions) -> Result<Self, GggError> {
         if !out_path.is_dir() {
             return Err(GggError::CouldNotWrite {
                 path: out_path,
@@ -494,13 +776,16 @@ impl IndividualNcWriter {
             });
         }
 
-        Ok(Self { save_dir: out_path })
+        Ok(Self {
+            save_dir: out_path,
+            opts,
+        })
     }
-}
 
-impl NcWriter for IndividualNcWriter {
-    fn add_spectrum(
-        &mut self,
+    /// Write one spectrum to its own file. This only needs a shared reference,
+    /// so it can be called from multiple threads at once.
+    fn write_spectrum_file(
+        &self,
         data_rec: &RunlogDataRec,
         spectrum: &Spectrum,
         full_spec_paths: bool,
@@ -528,7 +813,20 @@ impl NcWriter for IndividualNcWriter {
             root.add_attribute("full_spectrum_path", spec_path.as_str())
                 .change_context_lazy(|| CliError::write_error(&out_file))?;
         }
-        Self::write_spectrum_values(&mut root, data_rec, spectrum, &out_file, 0, true)
+        Self::write_spectrum_values(
+            &mut root, data_rec, spectrum, &out_file, 0, true, &self.opts,
+        )
+    }
+}
+
+impl NcWriter for IndividualNcWriter {
+    fn add_spectrum(
+        &mut self,
+        data_rec: &RunlogDataRec,
+        spectrum: &Spectrum,
+        full_spec_paths: bool,
+    ) -> error_stack::Result<(), CliError> {
+        self.write_spectrum_file(data_rec, spectrum, full_spec_paths)
     }
 
     fn write_0d_var<'f, T: netcdf::NcTypeDescriptor>(
@@ -539,6 +837,8 @@ impl NcWriter for IndividualNcWriter {
         units: &str,
         description: &str,
     ) -> error_stack::Result<netcdf::VariableMut<'f>, CliError> {
+        let (units, unit_long_name) = cf_unit(units);
+        let description = long_name_with_unit(description, unit_long_name);
         let mut var = nc
             .add_variable::<T>(varname, &[])
             .map_err(|e| CliError::custom(format!("error creating variable '{varname}': {e}")))?;
@@ -569,11 +869,21 @@ impl NcWriter for IndividualNcWriter {
         data: &ndarray::Array1<f32>,
         units: &str,
         description: &str,
+        opts: &OutputOptions,
     ) -> error_stack::Result<netcdf::VariableMut<'f>, CliError> {
+        let (units, unit_long_name) = cf_unit(units);
+        let description = long_name_with_unit(description, unit_long_name);
         let mut var = nc
             .add_variable::<f32>(varname, &[Self::freq_dim()])
             .map_err(|e| CliError::custom(format!("error creating variable '{varname}': {e}")))?;
 
+        opts.apply_storage(&mut var, varname, None, data.len())
+            .map_err(|e| {
+                CliError::custom(format!(
+                    "error setting compression or chunking for variable '{varname}': {e}"
+                ))
+            })?;
+
         let data_slice = data
             .as_slice()
             .ok_or_else(|| CliError::custom("Could not convert frequency to a slice"))?;
@@ -598,12 +908,144 @@ impl NcWriter for IndividualNcWriter {
     }
 }
 
+/// Settings for how the output variables are named and stored
+#[derive(Debug, Clone, Default)]
+struct OutputOptions {
+    /// zlib deflate level for the frequency and intensity variables, `None` to leave them uncompressed
+    compression: Option<i32>,
+    /// Chunk length along the frequency dimension for each variable that has one set
+    chunk_sizes: HashMap<String, usize>,
+    /// Use the variable names from older versions of bin2nc, for programs that depend on them
+    legacy_varnames: bool,
+    /// Where to get the ZPD time of each spectrum from
+    time_source: TimeSource,
+}
+
+impl OutputOptions {
+    /// The variables that compression and chunking apply to
+    const STORAGE_VARIABLES: [&'static str; 2] = ["frequency", "intensity"];
+
+    /// Name of the instrument pressure variable. Older versions misspelled this, so
+    /// `legacy_varnames` keeps that spelling.
+    fn instrument_pressure_varname(&self) -> &'static str {
+        if self.legacy_varnames {
+            "instrumnent_pressure"
+        } else {
+            "instrument_pressure"
+        }
+    }
+
+    /// Set the compression and chunking for `var`, which must not have had any data written yet.
+    /// `spec_chunk` is the chunk length along the spectrum dimension if `var` has one, and
+    /// `freq_len` is the length of its frequency dimension, which limits the chunk size.
+    fn apply_storage(
+        &self,
+        var: &mut netcdf::VariableMut,
+        varname: &str,
+        spec_chunk: Option<usize>,
+        freq_len: usize,
+    ) -> Result<(), netcdf::Error> {
+        if !Self::STORAGE_VARIABLES.contains(&varname) {
+            return Ok(());
+        }
+
+        if let Some(level) = self.compression {
+            var.set_compression(level, true)?;
+        }
+
+        if let Some(&size) = self.chunk_sizes.get(varname) {
+            let freq_chunk = size.min(freq_len).max(1);
+            match spec_chunk {
+                Some(n) => var.set_chunking(&[n, freq_chunk])?,
+                None => var.set_chunking(&[freq_chunk])?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How to choose the point spacing of the common frequency grid when resampling spectra
+#[derive(Debug, Clone, Copy)]
+enum ResampleGrid {
+    /// Use the finest point spacing of any spectrum in the group
+    FinestSpacing,
+    /// Use this point spacing (in cm-1)
+    Spacing(f64),
+}
+
+/// A common frequency grid that all spectra in one group are interpolated onto
+#[derive(Debug)]
+struct CommonFreqGrid {
+    freq: ndarray::Array1<f32>,
+    spacing: f64,
+}
+
+impl CommonFreqGrid {
+    fn new(freq_min: f64, freq_max: f64, spacing: f64) -> Self {
+        // The small tolerance ensures we include the last point if floating point
+        // error makes the range slightly less than an integer multiple of the spacing.
+        let npts = ((freq_max - freq_min) / spacing + 1e-6).floor() as usize + 1;
+        let freq =
+            ndarray::Array1::from_iter((0..npts).map(|i| (freq_min + spacing * i as f64) as f32));
+        Self { freq, spacing }
+    }
+
+    fn description(&self) -> String {
+        let n = self.freq.len();
+        format!(
+            "Linearly interpolated onto a common frequency grid from {} to {} cm-1 with {} cm-1 spacing; points outside the measured range of a spectrum are NaN",
+            self.freq[0],
+            self.freq[n - 1],
+            self.spacing
+        )
+    }
+
+    fn resample(&self, spectrum: &Spectrum) -> error_stack::Result<Spectrum, CliError> {
+        let interpolator = LinearInterp::new(false);
+        let in_freq = spectrum.freq.as_slice().ok_or_else(|| {
+            CliError::custom("Could not convert spectrum frequency to a slice for resampling")
+        })?;
+        let in_spec = spectrum.spec.as_slice().ok_or_else(|| {
+            CliError::custom("Could not convert spectrum intensity to a slice for resampling")
+        })?;
+
+        let mut spec = ndarray::Array1::from_elem(self.freq.len(), f32::NAN);
+        for (i, &f) in self.freq.iter().enumerate() {
+            match interpolator.interp1d(in_freq, in_spec, f) {
+                Ok(v) => spec[i] = v,
+                Err(InterpolationError::OutOfDomain { .. }) => (),
+                Err(e) => {
+                    return Err(e).change_context_lazy(|| {
+                        CliError::custom(format!(
+                            "Could not resample spectrum {}",
+                            spectrum.path.display()
+                        ))
+                    })
+                }
+            }
+        }
+
+        Ok(Spectrum {
+            path: spectrum.path.clone(),
+            freq: self.freq.clone(),
+            spec,
+        })
+    }
+}
+
 #[derive(Debug)]
 struct SpecGroupDef {
     detector_code: char,
     max_spec_length: usize,
     group_name: String,
     curr_idx: Cell<usize>,
+    freq_min: f64,
+    freq_max: f64,
+    min_spacing: f64,
+    common_grid: Option<CommonFreqGrid>,
+    /// The first point index and point spacing that all spectra in this group must share,
+    /// or `None` if they will be resampled and so may have different grids.
+    native_grid: Option<(usize, f64)>,
 }
 
 impl SpecGroupDef {
@@ -611,6 +1053,7 @@ impl SpecGroupDef {
         runlog_entry: &RunlogDataRec,
         data_part: &utils::DataPartition,
         detector_mapping: &HashMap<char, String>,
+        will_resample: bool,
     ) -> Result<Self, GggError> {
         let rl_det_code = Self::get_spectrum_det_code(&runlog_entry.spectrum_name)?;
         let group_name = detector_mapping
@@ -631,12 +1074,40 @@ impl SpecGroupDef {
         .try_into()
         .expect("Cannot fit spectrum length into system usize");
 
-        Ok(Self {
+        let mut me = Self {
             detector_code: rl_det_code,
             group_name,
             max_spec_length: spec_length,
             curr_idx: Cell::new(0),
-        })
+            freq_min: f64::INFINITY,
+            freq_max: f64::NEG_INFINITY,
+            min_spacing: f64::INFINITY,
+            common_grid: None,
+            native_grid: (!will_resample).then_some((runlog_entry.ifirst, runlog_entry.delta_nu)),
+        };
+        me.update_freq_extent(runlog_entry, spec_length);
+        Ok(me)
+    }
+
+    /// Expand the frequency range covered by this group to include a new spectrum with `npts` points.
+    fn update_freq_extent(&mut self, runlog_entry: &RunlogDataRec, npts: usize) {
+        let dnu = runlog_entry.delta_nu;
+        let first = runlog_entry.ifirst as f64 * dnu;
+        let last = (runlog_entry.ifirst + npts.saturating_sub(1)) as f64 * dnu;
+        self.freq_min = self.freq_min.min(first);
+        self.freq_max = self.freq_max.max(last);
+        self.min_spacing = self.min_spacing.min(dnu);
+    }
+
+    /// Set up the common frequency grid for this group, which also sets the length of its frequency dimension.
+    fn set_common_grid(&mut self, resample: ResampleGrid) {
+        let spacing = match resample {
+            ResampleGrid::FinestSpacing => self.min_spacing,
+            ResampleGrid::Spacing(s) => s,
+        };
+        let grid = CommonFreqGrid::new(self.freq_min, self.freq_max, spacing);
+        self.max_spec_length = grid.freq.len();
+        self.common_grid = Some(grid);
     }
 
     fn get_spectrum_det_code(spectrum_name: &str) -> Result<char, GggError> {
@@ -657,7 +1128,18 @@ impl SpecGroupDef {
 
     fn entry_matches_group(&self, runlog_entry: &RunlogDataRec) -> Result<bool, GggError> {
         let rl_det_code = Self::get_spectrum_det_code(&runlog_entry.spectrum_name)?;
-        Ok(rl_det_code == self.detector_code)
+        Ok(rl_det_code == self.detector_code && self.entry_matches_grid(runlog_entry))
+    }
+
+    /// Whether a spectrum's frequency grid starts at the same point and has the same spacing as
+    /// this group's, so that its frequencies line up with the other spectra in the group.
+    fn entry_matches_grid(&self, runlog_entry: &RunlogDataRec) -> bool {
+        match self.native_grid {
+            Some((ifirst, delta_nu)) => {
+                runlog_entry.ifirst == ifirst && runlog_entry.delta_nu == delta_nu
+            }
+            None => true,
+        }
     }
 
     fn get_next_index(&self) -> usize {
@@ -667,10 +1149,18 @@ impl SpecGroupDef {
     }
 }
 
+/// Group attribute holding the detector character from the spectrum names in that group
+const DETECTOR_CODE_ATTR: &str = "detector_code";
+/// Group attribute holding the index of the first frequency point shared by all spectra in that group
+const FIRST_POINT_ATTR: &str = "first_point_index";
+/// Group attribute holding the point spacing (cm-1) shared by all spectra in that group
+const POINT_SPACING_ATTR: &str = "point_spacing";
+
 struct MultipleNcWriter {
     save_file: PathBuf,
     group_defs: Vec<SpecGroupDef>,
     nc_file: netcdf::FileMut,
+    opts: OutputOptions,
 }
 
 impl MultipleNcWriter {
@@ -680,6 +1170,8 @@ impl MultipleNcWriter {
         output_file: PathBuf,
         runlog: Runlog,
         clobber: bool,
+        resample: Option<ResampleGrid>,
+        opts: OutputOptions,
     ) -> Result<Self, GggError> {
         if output_file.is_dir() {
             return Err(GggError::CouldNotWrite {
@@ -700,12 +1192,20 @@ impl MultipleNcWriter {
             reason: format!("Could not create netCDF file: {e}"),
         })?;
 
-        let group_defs = Self::make_group_defs(runlog, data_part, &detector_mapping, &mut nc_file)?;
+        let group_defs = Self::make_group_defs(
+            runlog,
+            data_part,
+            &detector_mapping,
+            &mut nc_file,
+            resample,
+            &opts,
+        )?;
 
         Ok(Self {
             save_file: output_file,
             group_defs,
             nc_file,
+            opts,
         })
     }
 
@@ -714,9 +1214,113 @@ impl MultipleNcWriter {
         output_file: PathBuf,
         runlog: Runlog,
         clobber: bool,
+        resample: Option<ResampleGrid>,
+        opts: OutputOptions,
     ) -> Result<Self, GggError> {
         let mapping = Self::default_mapping();
-        Self::new(data_part, mapping, output_file, runlog, clobber)
+        Self::new(
+            data_part,
+            mapping,
+            output_file,
+            runlog,
+            clobber,
+            resample,
+            opts,
+        )
+    }
+
+    /// Open an existing multi-spectrum file to add the spectra in `runlog` to it.
+    ///
+    /// Spectra that match one of the groups already in the file are written after the ones
+    /// already there; any that do not get new groups, as when creating a new file.
+    fn open_for_append(
+        data_part: &utils::DataPartition,
+        output_file: PathBuf,
+        runlog: Runlog,
+        opts: OutputOptions,
+    ) -> Result<Self, GggError> {
+        let mut nc_file = netcdf::append(&output_file).map_err(|e| GggError::CouldNotOpen {
+            descr: "netCDF file".to_owned(),
+            path: output_file.clone(),
+            reason: format!("could not open it to append to: {e}"),
+        })?;
+
+        let existing = Self::read_group_defs(&nc_file, &output_file)?;
+        let n_existing = existing.len();
+        let group_defs =
+            Self::assign_groups(runlog, data_part, &Self::default_mapping(), None, existing)?;
+
+        for group in group_defs[n_existing..].iter() {
+            Self::create_group(&mut nc_file, group, &opts)?;
+        }
+
+        Ok(Self {
+            save_file: output_file,
+            group_defs,
+            nc_file,
+            opts,
+        })
+    }
+
+    /// Rebuild the definitions of the groups already in a file written by [`MultipleNcWriter`],
+    /// so that writing can continue at the next index of each group's spectrum dimension.
+    fn read_group_defs(
+        nc_file: &netcdf::File,
+        nc_path: &Path,
+    ) -> Result<Vec<SpecGroupDef>, GggError> {
+        let groups = nc_file
+            .groups()
+            .map_err(|e| GggError::could_not_read(nc_path.to_path_buf(), e))?;
+
+        let mut group_defs = Vec::new();
+        for grp in groups {
+            let group_name = grp.name();
+            let missing_attr = |attr: &str| {
+                GggError::could_not_read(
+                    nc_path.to_path_buf(),
+                    format!("group '{group_name}' is missing the '{attr}' attribute, it may have been written by an older version of bin2nc or be a resampled file, neither of which can be appended to"),
+                )
+            };
+
+            let detector_code = get_string_attr(&grp, DETECTOR_CODE_ATTR)
+                .map_err(|_| missing_attr(DETECTOR_CODE_ATTR))?
+                .chars()
+                .next()
+                .ok_or_else(|| missing_attr(DETECTOR_CODE_ATTR))?;
+            let ifirst: u64 = grp
+                .attribute_value(FIRST_POINT_ATTR)
+                .and_then(|v| v.ok())
+                .and_then(|v| v.try_into().ok())
+                .ok_or_else(|| missing_attr(FIRST_POINT_ATTR))?;
+            let delta_nu: f64 = grp
+                .attribute_value(POINT_SPACING_ATTR)
+                .and_then(|v| v.ok())
+                .and_then(|v| v.try_into().ok())
+                .ok_or_else(|| missing_attr(POINT_SPACING_ATTR))?;
+
+            let dim_len = |dimname: &str| {
+                grp.dimension(dimname).map(|d| d.len()).ok_or_else(|| {
+                    GggError::could_not_read(
+                        nc_path.to_path_buf(),
+                        format!("group '{group_name}' is missing the '{dimname}' dimension"),
+                    )
+                })
+            };
+
+            group_defs.push(SpecGroupDef {
+                detector_code,
+                max_spec_length: dim_len(Self::freq_dim())?,
+                group_name: group_name.clone(),
+                curr_idx: Cell::new(dim_len(Self::spec_dim())?),
+                freq_min: f64::INFINITY,
+                freq_max: f64::NEG_INFINITY,
+                min_spacing: f64::INFINITY,
+                common_grid: None,
+                native_grid: Some((ifirst as usize, delta_nu)),
+            });
+        }
+
+        Ok(group_defs)
     }
 
     // Don't need this right now, but may in the future.
@@ -727,12 +1331,22 @@ impl MultipleNcWriter {
         output_file: PathBuf,
         runlog: Runlog,
         clobber: bool,
+        resample: Option<ResampleGrid>,
+        opts: OutputOptions,
     ) -> Result<Self, GggError> {
         let mut mapping = Self::default_mapping();
         for (k, v) in map_overrides.into_iter() {
             mapping.insert(k, v);
         }
-        Self::new(data_part, mapping, output_file, runlog, clobber)
+        Self::new(
+            data_part,
+            mapping,
+            output_file,
+            runlog,
+            clobber,
+            resample,
+            opts,
+        )
     }
 
     fn default_mapping() -> HashMap<char, String> {
@@ -752,14 +1366,43 @@ impl MultipleNcWriter {
         data_part: &utils::DataPartition,
         detector_mapping: &HashMap<char, String>,
         nc_file: &mut netcdf::FileMut,
+        resample: Option<ResampleGrid>,
+        opts: &OutputOptions,
+    ) -> Result<Vec<SpecGroupDef>, GggError> {
+        let mut groups =
+            Self::assign_groups(runlog, data_part, detector_mapping, resample, Vec::new())?;
+
+        if let Some(resample) = resample {
+            for group in groups.iter_mut() {
+                group.set_common_grid(resample);
+            }
+        }
+
+        for group in groups.iter() {
+            Self::create_group(nc_file, group, opts)?;
+        }
+        Ok(groups)
+    }
+
+    /// Work out which group each spectrum in `runlog` goes in, adding new groups to `groups` as needed.
+    ///
+    /// Any groups passed in are taken to already exist in the output file, so their frequency
+    /// dimension cannot grow; a spectrum too long to fit in one of them is an error.
+    fn assign_groups(
+        runlog: Runlog,
+        data_part: &utils::DataPartition,
+        detector_mapping: &HashMap<char, String>,
+        resample: Option<ResampleGrid>,
+        mut groups: Vec<SpecGroupDef>,
     ) -> Result<Vec<SpecGroupDef>, GggError> {
-        let mut groups: Vec<SpecGroupDef> = Vec::new();
+        let n_existing = groups.len();
 
         for data_rec in runlog {
             let spec_grp = groups
                 .iter_mut()
-                .find(|g| g.entry_matches_group(&data_rec).unwrap_or(false));
-            if let Some(spec_grp) = spec_grp {
+                .enumerate()
+                .find(|(_, g)| g.entry_matches_group(&data_rec).unwrap_or(false));
+            if let Some((grp_idx, spec_grp)) = spec_grp {
                 if let Ok(size) = ggg_rs::opus::get_spectrum_num_points(
                     &data_rec.spectrum_name,
                     data_part,
@@ -769,19 +1412,43 @@ impl MultipleNcWriter {
                     let size: usize = size
                         .try_into()
                         .expect("Could not fit number of spectrum points into system usize");
+                    if grp_idx < n_existing && spec_grp.max_spec_length < size {
+                        return Err(GggError::DataError {
+                            path: PathBuf::from(&data_rec.spectrum_name),
+                            cause: format!(
+                                "spectrum has {size} points, which does not fit in the existing '{}' dimension of length {} in group '{}'",
+                                Self::freq_dim(),
+                                spec_grp.max_spec_length,
+                                spec_grp.group_name
+                            ),
+                        });
+                    }
                     if spec_grp.max_spec_length < size {
                         spec_grp.max_spec_length = size;
                     }
+                    spec_grp.update_freq_extent(&data_rec, size);
                 }
             } else {
-                let new_group = SpecGroupDef::new(&data_rec, data_part, detector_mapping)?;
+                let mut new_group =
+                    SpecGroupDef::new(&data_rec, data_part, detector_mapping, resample.is_some())?;
+                // Spectra from the same detector on a different frequency grid get their own group
+                // rather than silently mixing grids in one frequency variable.
+                let n_same_detector = groups
+                    .iter()
+                    .filter(|g| g.detector_code == new_group.detector_code)
+                    .count();
+                if n_same_detector > 0 {
+                    new_group.group_name =
+                        format!("{}_{}", new_group.group_name, n_same_detector + 1);
+                    println!(
+                        "Spectrum {} has a different frequency grid (ifirst = {}, delta_nu = {}) than earlier spectra from the same detector, writing it and others on that grid to group '{}'",
+                        data_rec.spectrum_name, data_rec.ifirst, data_rec.delta_nu, new_group.group_name
+                    );
+                }
                 groups.push(new_group);
             }
         }
 
-        for group in groups.iter() {
-            Self::create_group(nc_file, group)?;
-        }
         Ok(groups)
     }
 
@@ -818,6 +1485,7 @@ impl MultipleNcWriter {
     fn create_group(
         nc_file: &mut netcdf::FileMut,
         group_def: &SpecGroupDef,
+        opts: &OutputOptions,
     ) -> Result<(), GggError> {
         let nc_path = nc_file.path().unwrap_or_else(|_| PathBuf::from("?"));
         // This creates the new spectrum group, with an unlimited dimension for time so that we can append new spectra.
@@ -837,8 +1505,29 @@ impl MultipleNcWriter {
             &mut grp,
             &group_def.group_name,
             group_def.max_spec_length,
+            opts,
         )?;
 
+        // Record how spectra were assigned to this group, so that more can be appended later
+        let attr_err = |e: netcdf::Error| GggError::CouldNotWrite {
+            path: nc_path.clone(),
+            reason: format!(
+                "Could not add attributes to group '{}': {e}",
+                group_def.group_name
+            ),
+        };
+        grp.add_attribute(
+            DETECTOR_CODE_ATTR,
+            group_def.detector_code.to_string().as_str(),
+        )
+        .map_err(attr_err)?;
+        if let Some((ifirst, delta_nu)) = group_def.native_grid {
+            grp.add_attribute(FIRST_POINT_ATTR, ifirst as u64)
+                .map_err(attr_err)?;
+            grp.add_attribute(POINT_SPACING_ATTR, delta_nu)
+                .map_err(attr_err)?;
+        }
+
         Ok(())
     }
 
@@ -847,6 +1536,7 @@ impl MultipleNcWriter {
         grp: &mut netcdf::GroupMut,
         group_name: &str,
         max_spec_length: usize,
+        opts: &OutputOptions,
     ) -> Result<(), GggError> {
         grp.add_dimension(Self::spec_dim(), 0)
             .map_err(|e| GggError::CouldNotWrite {
@@ -871,6 +1561,13 @@ impl MultipleNcWriter {
                 reason: format!("Could not create frequency variable in group '{group_name}': {e}"),
             })?;
 
+        opts
+            .apply_storage(&mut freq_var, Self::freq_dim(), Some(1), max_spec_length)
+            .map_err(|e| GggError::CouldNotWrite {
+                path: nc_path.to_owned(),
+                reason: format!("Could not set compression or chunking for the frequency variable in group '{group_name}': {e}"),
+            })?;
+
         freq_var.put_attribute("units", "cm-1")
         .map_err(|e| GggError::CouldNotWrite { 
             path: nc_path.to_owned(), 
@@ -935,10 +1632,15 @@ impl NcWriter for MultipleNcWriter {
         // If there's an issue (i.e. the spectrum should go in a certain group based on its detector code but has a different
         // frequency grid) either crash or skip that spectrum.
 
-        let (group_name, next_idx) = {
+        let (group_name, next_idx, resampled) = {
             let grp_def = self.find_spectrum_group(data_rec)?;
             let spec_idx = grp_def.get_next_index();
-            (grp_def.group_name.to_owned(), spec_idx)
+            let resampled = if let Some(grid) = &grp_def.common_grid {
+                Some((grid.resample(spectrum)?, grid.description()))
+            } else {
+                None
+            };
+            (grp_def.group_name.to_owned(), spec_idx, resampled)
         };
 
         let mut grp = self
@@ -969,6 +1671,11 @@ impl NcWriter for MultipleNcWriter {
                 "Spectrum name",
             )?;
         }
+        let (spectrum, resample_note) = match &resampled {
+            Some((spec, note)) => (spec, Some(note)),
+            None => (spectrum, None),
+        };
+
         Self::write_spectrum_values(
             &mut grp,
             data_rec,
@@ -976,7 +1683,18 @@ impl NcWriter for MultipleNcWriter {
             &self.save_file,
             next_idx,
             true,
-        )
+            &self.opts,
+        )?;
+
+        // Record how the intensities were resampled once, when the variable is first created.
+        if let (Some(note), 0) = (resample_note, next_idx) {
+            if let Some(mut var) = grp.variable_mut("intensity") {
+                var.put_attribute("resampling", note.as_str())
+                    .change_context_lazy(|| CliError::write_error(&self.save_file))?;
+            }
+        }
+
+        Ok(())
     }
 
     fn write_0d_var<'f, T: netcdf::NcTypeDescriptor>(
@@ -987,6 +1705,8 @@ impl NcWriter for MultipleNcWriter {
         units: &str,
         description: &str,
     ) -> error_stack::Result<netcdf::VariableMut<'f>, CliError> {
+        let (units, unit_long_name) = cf_unit(units);
+        let description = long_name_with_unit(description, unit_long_name);
         let group_name = nc.name();
 
         let mut var = if nc.variable(varname).is_some() {
@@ -1032,8 +1752,15 @@ impl NcWriter for MultipleNcWriter {
         data: &ndarray::Array1<f32>,
         units: &str,
         description: &str,
+        opts: &OutputOptions,
     ) -> error_stack::Result<netcdf::VariableMut<'f>, CliError> {
+        let (units, unit_long_name) = cf_unit(units);
+        let description = long_name_with_unit(description, unit_long_name);
         let group_name = nc.name();
+        let freq_len = nc
+            .dimension(Self::freq_dim())
+            .map(|d| d.len())
+            .unwrap_or(data.len());
 
         let mut var = if nc.variable(varname).is_some() {
             nc.variable_mut(varname).unwrap()
@@ -1046,6 +1773,12 @@ impl NcWriter for MultipleNcWriter {
                     ))
                 })?;
 
+            opts
+                .apply_storage(&mut v, varname, Some(1), freq_len)
+                .change_context_lazy(|| CliError::custom(format!(
+                    "Could not set compression or chunking for variable '{varname}' in group '{group_name}'"
+                )))?;
+
             v.put_attribute("units", units)
                 .change_context_lazy(|| CliError::custom(format!(
                     "Could not add 'units' attribute to variable '{varname}' in group '{group_name}'"
@@ -1073,3 +1806,622 @@ impl NcWriter for MultipleNcWriter {
         Ok(var)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ggg_rs::test_utils::{remove_file_if_exists, write_test_opus_spectrum};
+
+    /// Write `npts` values after `pointer` bytes of padding, which is enough for reading
+    /// the spectrum through its runlog record.
+    fn write_fake_spectrum(path: &Path, pointer: i32, npts: usize, offset: f32) {
+        let mut buf = vec![0u8; pointer as usize];
+        for i in 0..npts {
+            buf.extend((offset + i as f32).to_le_bytes());
+        }
+        std::fs::write(path, buf).unwrap();
+    }
+
+    /// The InGaAs spectrum that [`write_shifted_grid_inputs`] moves to a different grid.
+    const SHIFTED_SPECTRUM: &str = "pa20041222saaaaa.020";
+
+    /// Write a copy of the benchmark runlog to `output_dir` with the start of the last InGaAs
+    /// spectrum ([`SHIFTED_SPECTRUM`]) shifted by one point, plus fake spectra with `npts`
+    /// values equal to their point index. Returns the runlog path and a data partition that
+    /// finds the spectra.
+    fn write_shifted_grid_inputs(
+        output_dir: &Path,
+        npts: usize,
+    ) -> (PathBuf, utils::DataPartition) {
+        let bench_runlog = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("test-data/inputs/collate-tccon-results/pa_ggg_benchmark.grl");
+        let data_dir = output_dir.join("spectra");
+        std::fs::create_dir_all(&data_dir).unwrap();
+
+        // Keep the column width so the runlog still parses
+        let runlog_text = std::fs::read_to_string(&bench_runlog)
+            .unwrap()
+            .lines()
+            .map(|line| {
+                if line.contains(SHIFTED_SPECTRUM) {
+                    line.replacen(" 530991 ", " 530992 ", 1)
+                } else {
+                    line.to_string()
+                }
+            })
+            .join("\n");
+        let runlog_path = output_dir.join("shifted_grid.grl");
+        std::fs::write(&runlog_path, runlog_text + "\n").unwrap();
+
+        for data_rec in Runlog::open(&runlog_path).unwrap() {
+            write_fake_spectrum(
+                &data_dir.join(&data_rec.spectrum_name),
+                data_rec.pointer,
+                npts,
+                0.0,
+            );
+        }
+        let mut data_part = utils::DataPartition::new_empty();
+        data_part.add_path(data_dir);
+        (runlog_path, data_part)
+    }
+
+    fn write_single_file(
+        runlog_path: &Path,
+        data_part: &utils::DataPartition,
+        out_file: PathBuf,
+        jobs: usize,
+    ) {
+        let writer = MultipleNcWriter::new_with_default_map(
+            data_part,
+            out_file,
+            Runlog::open(runlog_path).unwrap(),
+            true,
+            None,
+            OutputOptions::default(),
+        )
+        .unwrap();
+        writer_loop(
+            writer,
+            Runlog::open(runlog_path).unwrap(),
+            data_part,
+            false,
+            Some(jobs),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_parallel_read_matches_serial() {
+        let runlog_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("test-data/inputs/collate-tccon-results/pa_ggg_benchmark.grl");
+        let output_dir = ggg_rs::test_utils::output_dir("bin2nc-parallel");
+        let data_dir = output_dir.join("spectra");
+        std::fs::create_dir_all(&data_dir).unwrap();
+
+        // Give each spectrum a different length and values so that any reordering shows up
+        for (i, data_rec) in Runlog::open(&runlog_path).unwrap().enumerate() {
+            write_fake_spectrum(
+                &data_dir.join(&data_rec.spectrum_name),
+                data_rec.pointer,
+                10 + i,
+                100.0 * i as f32,
+            );
+        }
+        let mut data_part = utils::DataPartition::new_empty();
+        data_part.add_path(data_dir.clone());
+
+        let serial_file = output_dir.join("serial.nc");
+        let parallel_file = output_dir.join("parallel.nc");
+        write_single_file(&runlog_path, &data_part, serial_file.clone(), 1);
+        write_single_file(&runlog_path, &data_part, parallel_file.clone(), 3);
+
+        let serial = netcdf::open(&serial_file).unwrap();
+        let parallel = netcdf::open(&parallel_file).unwrap();
+        let serial_groups = serial.groups().unwrap().collect::<Vec<_>>();
+        assert!(!serial_groups.is_empty());
+        for s_grp in serial_groups {
+            let p_grp = parallel.group(&s_grp.name()).unwrap().unwrap();
+            let nspec = s_grp.dimension(MultipleNcWriter::spec_dim()).unwrap().len();
+            assert_eq!(
+                p_grp.dimension(MultipleNcWriter::spec_dim()).unwrap().len(),
+                nspec
+            );
+
+            let s_names = s_grp.variable("spectrum").unwrap();
+            let p_names = p_grp.variable("spectrum").unwrap();
+            for i in 0..nspec {
+                assert_eq!(
+                    s_names.get_string(i).unwrap(),
+                    p_names.get_string(i).unwrap()
+                );
+            }
+
+            // Compare bits so that unfilled points count as equal
+            let s_intensity = s_grp
+                .variable("intensity")
+                .unwrap()
+                .get_values::<f32, _>(..)
+                .unwrap();
+            let p_intensity = p_grp
+                .variable("intensity")
+                .unwrap()
+                .get_values::<f32, _>(..)
+                .unwrap();
+            assert_eq!(
+                s_intensity.iter().map(|v| v.to_bits()).collect::<Vec<_>>(),
+                p_intensity.iter().map(|v| v.to_bits()).collect::<Vec<_>>()
+            );
+        }
+    }
+
+    #[test]
+    #[ignore = "Timing comparison; run with --ignored --nocapture on a multi-core machine"]
+    fn test_parallel_read_timing() {
+        let runlog_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("test-data/inputs/collate-tccon-results/pa_ggg_benchmark.grl");
+        let output_dir = ggg_rs::test_utils::output_dir("bin2nc-parallel-timing");
+        let data_dir = output_dir.join("spectra");
+        let individual_dir = output_dir.join("individual");
+        std::fs::create_dir_all(&data_dir).unwrap();
+        std::fs::create_dir_all(&individual_dir).unwrap();
+
+        // About the size of a full InGaAs spectrum, so reading is a realistic part of the run
+        for data_rec in Runlog::open(&runlog_path).unwrap() {
+            write_fake_spectrum(
+                &data_dir.join(&data_rec.spectrum_name),
+                data_rec.pointer,
+                500_000,
+                0.0,
+            );
+        }
+        let mut data_part = utils::DataPartition::new_empty();
+        data_part.add_path(data_dir.clone());
+
+        // Take the fastest of several runs to reduce the effect of file caching and other load
+        fn fastest_of<F: FnMut()>(mut run: F) -> f64 {
+            (0..5)
+                .map(|_| {
+                    let start = std::time::Instant::now();
+                    run();
+                    start.elapsed().as_secs_f64()
+                })
+                .fold(f64::INFINITY, f64::min)
+        }
+
+        let max_jobs = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let out_file = output_dir.join("timing.nc");
+        let writer = IndividualNcWriter::new(individual_dir, OutputOptions::default()).unwrap();
+        println!("jobs  single file (s)  individual files (s)");
+        let mut timings = vec![];
+        for jobs in [1, max_jobs] {
+            let single =
+                fastest_of(|| write_single_file(&runlog_path, &data_part, out_file.clone(), jobs));
+            let individual = fastest_of(|| {
+                parallel_writer_loop(
+                    &writer,
+                    Runlog::open(&runlog_path).unwrap(),
+                    &data_part,
+                    false,
+                    Some(jobs),
+                )
+                .unwrap()
+            });
+            println!("{jobs:>4}  {single:>15.3}  {individual:>20.3}");
+            timings.push((single, individual));
+        }
+
+        // Running in parallel should never be much slower than running serially
+        if max_jobs > 1 {
+            let (serial_single, serial_individual) = timings[0];
+            let (parallel_single, parallel_individual) = timings[1];
+            assert!(parallel_single < 1.2 * serial_single);
+            assert!(parallel_individual < 1.2 * serial_individual);
+        }
+    }
+
+    #[test]
+    fn test_parallel_individual_files_report_failures() {
+        let crate_root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let runlog_path =
+            crate_root.join("test-data/inputs/collate-tccon-results/pa_ggg_benchmark.grl");
+        let output_dir = ggg_rs::test_utils::output_dir("bin2nc-jobs");
+        let data_dir = output_dir.join("spectra");
+        std::fs::create_dir_all(&data_dir).unwrap();
+
+        let data_recs = Runlog::open(&runlog_path).unwrap().collect_vec();
+        let bad_spectrum = data_recs[1].spectrum_name.clone();
+        for data_rec in data_recs.iter() {
+            let spec_path = data_dir.join(&data_rec.spectrum_name);
+            if data_rec.spectrum_name == bad_spectrum {
+                // A partial word after the header cannot be converted to a spectrum
+                std::fs::write(&spec_path, vec![0u8; data_rec.pointer as usize + 3]).unwrap();
+            } else {
+                write_fake_spectrum(&spec_path, data_rec.pointer, 10, 0.0);
+            }
+            let nc_file = output_dir.join(format!("{}.nc", data_rec.spectrum_name));
+            remove_file_if_exists(&nc_file).unwrap();
+        }
+        let mut data_part = utils::DataPartition::new_empty();
+        data_part.add_path(data_dir.clone());
+
+        let writer = IndividualNcWriter::new(output_dir.clone(), OutputOptions::default()).unwrap();
+        let err = parallel_writer_loop(
+            &writer,
+            Runlog::open(&runlog_path).unwrap(),
+            &data_part,
+            false,
+            Some(3),
+        )
+        .expect_err("converting a corrupt spectrum should be reported as an error");
+        let msg = err.current_context().to_string();
+        assert!(
+            msg.contains(&format!("1 of {} spectra", data_recs.len())),
+            "{msg}"
+        );
+        assert!(msg.contains(&bad_spectrum), "{msg}");
+
+        // The failure must not stop the other spectra from being written
+        for data_rec in data_recs.iter() {
+            let nc_file = output_dir.join(format!("{}.nc", data_rec.spectrum_name));
+            assert_eq!(
+                nc_file.exists(),
+                data_rec.spectrum_name != bad_spectrum,
+                "{}",
+                nc_file.display()
+            );
+        }
+    }
+
+    #[test]
+    fn test_compressed_output() {
+        let runlog_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("test-data/inputs/collate-tccon-results/pa_ggg_benchmark.grl");
+        let output_dir = ggg_rs::test_utils::output_dir("bin2nc-compression");
+        let data_dir = output_dir.join("spectra");
+        std::fs::create_dir_all(&data_dir).unwrap();
+
+        let data_rec = Runlog::open(&runlog_path).unwrap().next().unwrap();
+        write_fake_spectrum(
+            &data_dir.join(&data_rec.spectrum_name),
+            data_rec.pointer,
+            1000,
+            0.5,
+        );
+        let mut data_part = utils::DataPartition::new_empty();
+        data_part.add_path(data_dir.clone());
+        let spectrum = ggg_rs::opus::read_spectrum_from_runlog_rec(&data_rec, &data_part).unwrap();
+
+        let opts = OutputOptions {
+            compression: Some(4),
+            chunk_sizes: HashMap::from([("intensity".to_string(), 256)]),
+            ..Default::default()
+        };
+        let writer = IndividualNcWriter::new(output_dir.clone(), opts).unwrap();
+        writer
+            .write_spectrum_file(&data_rec, &spectrum, false)
+            .unwrap();
+
+        let nc_file = output_dir.join(format!("{}.nc", data_rec.spectrum_name));
+        let ds = netcdf::open(&nc_file).unwrap();
+        let intensity = ds
+            .variable("intensity")
+            .unwrap()
+            .get_values::<f32, _>(..)
+            .unwrap();
+        assert_eq!(intensity, spectrum.spec.to_vec());
+        let freq = ds
+            .variable("frequency")
+            .unwrap()
+            .get_values::<f32, _>(..)
+            .unwrap();
+        assert_eq!(freq, spectrum.freq.to_vec());
+    }
+
+    #[test]
+    fn test_split_groups_by_grid() {
+        let output_dir = ggg_rs::test_utils::output_dir("bin2nc-grid-split");
+        let (runlog_path, data_part) = write_shifted_grid_inputs(&output_dir, 10);
+
+        let writer = MultipleNcWriter::new_with_default_map(
+            &data_part,
+            output_dir.join("native.nc"),
+            Runlog::open(&runlog_path).unwrap(),
+            true,
+            None,
+            OutputOptions::default(),
+        )
+        .unwrap();
+        let group_names = writer
+            .group_defs
+            .iter()
+            .map(|g| g.group_name.as_str())
+            .collect_vec();
+        assert_eq!(group_names, ["InGaAs", "Si", "InGaAs_2"]);
+        let odd_rec = Runlog::open(&runlog_path)
+            .unwrap()
+            .find(|rec| rec.spectrum_name == SHIFTED_SPECTRUM)
+            .unwrap();
+        assert!(writer.group_defs[2].entry_matches_group(&odd_rec).unwrap());
+        assert!(!writer.group_defs[0].entry_matches_group(&odd_rec).unwrap());
+        drop(writer);
+
+        // With resampling, differing grids are fine in one group
+        let writer = MultipleNcWriter::new_with_default_map(
+            &data_part,
+            output_dir.join("resampled.nc"),
+            Runlog::open(&runlog_path).unwrap(),
+            true,
+            Some(ResampleGrid::FinestSpacing),
+            OutputOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(writer.group_defs.len(), 2);
+        drop(writer);
+    }
+
+    #[test]
+    fn test_resample_to_common_grid() {
+        let output_dir = ggg_rs::test_utils::output_dir("bin2nc-resample");
+        // With values equal to the point index, interpolated values are easy to predict
+        let (runlog_path, data_part) = write_shifted_grid_inputs(&output_dir, 10);
+
+        let odd_rec = Runlog::open(&runlog_path)
+            .unwrap()
+            .find(|rec| rec.spectrum_name == SHIFTED_SPECTRUM)
+            .unwrap();
+        let dnu = odd_rec.delta_nu;
+        let grid_spacing = 2.0 * dnu;
+
+        let out_file = output_dir.join("resampled.nc");
+        let writer = MultipleNcWriter::new_with_default_map(
+            &data_part,
+            out_file.clone(),
+            Runlog::open(&runlog_path).unwrap(),
+            true,
+            Some(ResampleGrid::Spacing(grid_spacing)),
+            OutputOptions::default(),
+        )
+        .unwrap();
+        writer_loop(
+            writer,
+            Runlog::open(&runlog_path).unwrap(),
+            &data_part,
+            false,
+            Some(1),
+        )
+        .unwrap();
+
+        let ds = netcdf::open(&out_file).unwrap();
+        assert_eq!(ds.groups().unwrap().count(), 2);
+        let grp = ds.group("InGaAs").unwrap().unwrap();
+        let nspec = grp.dimension(MultipleNcWriter::spec_dim()).unwrap().len();
+        let nfreq = grp.dimension(MultipleNcWriter::freq_dim()).unwrap().len();
+
+        // The InGaAs spectra span 11 native points, so a grid twice as coarse has 6
+        assert_eq!(nfreq, 6);
+        let freq = grp
+            .variable("frequency")
+            .unwrap()
+            .get_values::<f32, _>(..)
+            .unwrap();
+        let common_grid = &freq[..nfreq];
+        approx::assert_abs_diff_eq!(common_grid[0] as f64, 530991.0 * dnu, epsilon = 1e-3);
+        for (i, f) in common_grid.iter().enumerate() {
+            approx::assert_abs_diff_eq!(
+                *f as f64 - common_grid[0] as f64,
+                i as f64 * grid_spacing,
+                epsilon = 1e-3
+            );
+        }
+        for i in 1..nspec {
+            assert_eq!(&freq[i * nfreq..(i + 1) * nfreq], common_grid);
+        }
+
+        let intensity_var = grp.variable("intensity").unwrap();
+        let note = get_string_attr(&intensity_var, "resampling").unwrap();
+        assert!(note.contains(&grid_spacing.to_string()), "{note}");
+
+        let names = grp.variable("spectrum").unwrap();
+        let intensity = intensity_var.get_values::<f32, _>(..).unwrap();
+        let mut found_odd = false;
+        for i in 0..nspec {
+            let row = &intensity[i * nfreq..(i + 1) * nfreq];
+            if names.get_string(i).unwrap() == SHIFTED_SPECTRUM {
+                // The first grid point is before this spectrum starts
+                found_odd = true;
+                assert!(row[0].is_nan());
+                approx::assert_abs_diff_eq!(row[1], 1.0, epsilon = 0.1);
+                approx::assert_abs_diff_eq!(row[3], 5.0, epsilon = 0.1);
+            } else {
+                approx::assert_abs_diff_eq!(row[1], 2.0, epsilon = 0.1);
+                approx::assert_abs_diff_eq!(row[3], 6.0, epsilon = 0.1);
+            }
+        }
+        assert!(found_odd);
+    }
+
+    #[test]
+    fn test_append_matches_single_write() {
+        let bench_runlog = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("test-data/inputs/collate-tccon-results/pa_ggg_benchmark.grl");
+        let output_dir = ggg_rs::test_utils::output_dir("bin2nc-append");
+        let data_dir = output_dir.join("spectra");
+        std::fs::create_dir_all(&data_dir).unwrap();
+
+        for (i, data_rec) in Runlog::open(&bench_runlog).unwrap().enumerate() {
+            write_fake_spectrum(
+                &data_dir.join(&data_rec.spectrum_name),
+                data_rec.pointer,
+                10,
+                10.0 * i as f32,
+            );
+        }
+        let mut data_part = utils::DataPartition::new_empty();
+        data_part.add_path(data_dir.clone());
+
+        // Split the runlog in two, each keeping the header
+        let runlog_text = std::fs::read_to_string(&bench_runlog).unwrap();
+        let lines = runlog_text.lines().collect_vec();
+        let nhead = 4;
+        let nsplit = nhead + 4;
+        let first_runlog = output_dir.join("first.grl");
+        let second_runlog = output_dir.join("second.grl");
+        std::fs::write(&first_runlog, lines[..nsplit].join("\n") + "\n").unwrap();
+        let second_text = lines[..nhead]
+            .iter()
+            .chain(lines[nsplit..].iter())
+            .join("\n");
+        std::fs::write(&second_runlog, second_text + "\n").unwrap();
+
+        let full_file = output_dir.join("full.nc");
+        let appended_file = output_dir.join("appended.nc");
+        write_single_file(&bench_runlog, &data_part, full_file.clone(), 1);
+        write_single_file(&first_runlog, &data_part, appended_file.clone(), 1);
+
+        let writer = MultipleNcWriter::open_for_append(
+            &data_part,
+            appended_file.clone(),
+            Runlog::open(&second_runlog).unwrap(),
+            OutputOptions::default(),
+        )
+        .unwrap();
+        writer_loop(
+            writer,
+            Runlog::open(&second_runlog).unwrap(),
+            &data_part,
+            false,
+            Some(1),
+        )
+        .unwrap();
+
+        let full = netcdf::open(&full_file).unwrap();
+        let appended = netcdf::open(&appended_file).unwrap();
+        let full_groups = full.groups().unwrap().collect_vec();
+        assert_eq!(appended.groups().unwrap().count(), full_groups.len());
+        for f_grp in full_groups {
+            let a_grp = appended.group(&f_grp.name()).unwrap().unwrap();
+            let nspec = f_grp.dimension(MultipleNcWriter::spec_dim()).unwrap().len();
+            assert_eq!(
+                a_grp.dimension(MultipleNcWriter::spec_dim()).unwrap().len(),
+                nspec
+            );
+            for i in 0..nspec {
+                assert_eq!(
+                    f_grp.variable("spectrum").unwrap().get_string(i).unwrap(),
+                    a_grp.variable("spectrum").unwrap().get_string(i).unwrap()
+                );
+            }
+            assert_eq!(
+                f_grp
+                    .variable("intensity")
+                    .unwrap()
+                    .get_values::<f32, _>(..)
+                    .unwrap(),
+                a_grp
+                    .variable("intensity")
+                    .unwrap()
+                    .get_values::<f32, _>(..)
+                    .unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_instrument_pressure_varname() {
+        let runlog_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("test-data/inputs/collate-tccon-results/pa_ggg_benchmark.grl");
+        let output_dir = ggg_rs::test_utils::output_dir("bin2nc-varname");
+        let data_dir = output_dir.join("spectra");
+        std::fs::create_dir_all(&data_dir).unwrap();
+
+        let data_rec = Runlog::open(&runlog_path).unwrap().next().unwrap();
+        write_fake_spectrum(
+            &data_dir.join(&data_rec.spectrum_name),
+            data_rec.pointer,
+            10,
+            0.0,
+        );
+        let mut data_part = utils::DataPartition::new_empty();
+        data_part.add_path(data_dir.clone());
+        let spectrum = ggg_rs::opus::read_spectrum_from_runlog_rec(&data_rec, &data_part).unwrap();
+        let nc_file = output_dir.join(format!("{}.nc", data_rec.spectrum_name));
+
+        for (legacy_varnames, expected, absent) in [
+            (false, "instrument_pressure", "instrumnent_pressure"),
+            (true, "instrumnent_pressure", "instrument_pressure"),
+        ] {
+            let opts = OutputOptions {
+                legacy_varnames,
+                ..Default::default()
+            };
+            let writer = IndividualNcWriter::new(output_dir.clone(), opts).unwrap();
+            writer
+                .write_spectrum_file(&data_rec, &spectrum, false)
+                .unwrap();
+
+            let ds = netcdf::open(&nc_file).unwrap();
+            let var = ds.variable(expected).unwrap();
+            assert_eq!(var.get_value::<f64, _>(..).unwrap(), data_rec.pins);
+            assert_eq!(
+                get_string_attr(&var, "long_name").unwrap(),
+                "instrument pressure"
+            );
+            assert!(ds.variable(absent).is_none());
+        }
+    }
+
+    #[test]
+    fn test_header_time_fallback() {
+        let runlog_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("test-data/inputs/collate-tccon-results/pa_ggg_benchmark.grl");
+        let output_dir = ggg_rs::test_utils::output_dir("bin2nc-time-source");
+
+        let data_rec = Runlog::open(&runlog_path).unwrap().next().unwrap();
+        let spec_path = output_dir.join(&data_rec.spectrum_name);
+        write_fake_spectrum(&spec_path, data_rec.pointer, 10, 0.0);
+        let spectrum = Spectrum {
+            path: spec_path,
+            freq: ndarray::Array1::zeros(10),
+            spec: ndarray::Array1::zeros(10),
+        };
+
+        // The fake spectrum has no valid Opus header, so both sources should give the runlog time
+        let runlog_time = data_rec.zpd_time().unwrap();
+        assert_eq!(
+            spectrum_time(&data_rec, &spectrum, TimeSource::Runlog),
+            Some(runlog_time)
+        );
+        assert_eq!(
+            spectrum_time(&data_rec, &spectrum, TimeSource::Header),
+            Some(runlog_time)
+        );
+
+        // A valid header gives its time converted to UTC
+        write_test_opus_spectrum(&spectrum.path, "18:40:12.500 (GMT+2)");
+        let header_time = chrono::NaiveDate::from_ymd_opt(2009, 2, 6)
+            .unwrap()
+            .and_hms_milli_opt(16, 40, 12, 500)
+            .unwrap()
+            .and_utc();
+        assert_eq!(
+            spectrum_time(&data_rec, &spectrum, TimeSource::Header),
+            Some(header_time)
+        );
+        assert_eq!(
+            spectrum_time(&data_rec, &spectrum, TimeSource::Runlog),
+            Some(runlog_time)
+        );
+    }
+
+    #[test]
+    fn test_parse_chunk_size() {
+        assert_eq!(
+            parse_chunk_size("intensity=4096").unwrap(),
+            ("intensity".to_string(), 4096)
+        );
+        assert!(parse_chunk_size("intensity").is_err());
+        assert!(parse_chunk_size("time=10").is_err());
+        assert!(parse_chunk_size("frequency=0").is_err());
+    }
+}
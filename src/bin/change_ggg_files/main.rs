@@ -29,11 +29,15 @@ fn main() -> ExitCode {
 fn driver(args: Cli) -> error_stack::Result<(), CliError> {
     let files_to_change = args.expand_change_targets()?;
 
+    for warning in window_pattern_warnings(&args) {
+        eprintln!("WARNING: {warning}");
+    }
+
     let start_time = chrono::Local::now();
     let backup_suffix = OsString::from(format!(".bak.{}", start_time.format("%Y%m%dT%H%M%S")));
 
     for file in files_to_change {
-        if !args.no_backup {
+        if !args.no_backup && !args.dry_run {
             utils::make_backup(&file, &backup_suffix, false)
                 .change_context_lazy(|| CliError::IoError)?;
         }
@@ -77,6 +81,11 @@ struct Cli {
     /// Don't backup files before changing them
     #[clap(short = 'n', long)]
     no_backup: bool,
+
+    /// Print the AK and SPT lines that would change in each file as a diff,
+    /// without changing any files, making backups, or creating directories.
+    #[clap(long)]
+    dry_run: bool,
 }
 
 impl Cli {
@@ -155,8 +164,6 @@ fn modify_ggg_file(ggg_file: &Path, args: &Cli) -> error_stack::Result<(), CliEr
         .change_context_lazy(|| CliError::IoError)
         .attach_printable_lazy(|| format!("Could not read lines from {}", ggg_file.display()))?;
 
-    let mut out = std::fs::File::create(ggg_file).change_context_lazy(|| CliError::IoError)?;
-
     // This uses an unsafe operation, but since we only split the bytes of the file name on an ASCII .,
     // there is no reason that the slice of bytes leading up to that should be an invalid OsStr.
     let window = ggg_file
@@ -183,27 +190,52 @@ fn modify_ggg_file(ggg_file: &Path, args: &Cli) -> error_stack::Result<(), CliEr
         )
     })?;
 
-    for (i, line) in lines.into_iter().enumerate() {
-        let new_line = if i == 14 {
-            // AK line
-            make_output_line(
-                &window,
-                &line,
-                args.ak_output_pattern.as_deref(),
-                args.ak_output_limit,
-                args.make_output_dirs,
-            )
-            .change_context_lazy(|| CliError::InFile(ggg_file.to_path_buf()))?
-        } else if i == 15 {
-            // Spectral fit line
-            make_output_line(
-                &window,
-                &line,
-                args.spt_output_pattern.as_deref(),
-                args.spt_output_limit,
-                args.make_output_dirs,
+    let (ak_line_idx, spt_line_idx) = find_output_lines(&lines)
+        .change_context_lazy(|| CliError::InFile(ggg_file.to_path_buf()))?;
+
+    // Don't create any directories for a dry run, since nothing will be written to them.
+    // If we would create them, there's no point checking that they're writable.
+    let dir_action = match (args.make_output_dirs, args.dry_run) {
+        (true, false) => OutputDirAction::Create,
+        (true, true) => OutputDirAction::Skip,
+        (false, _) => OutputDirAction::CheckWritable,
+    };
+    let new_ak_line = make_output_line(
+        &window,
+        &lines[ak_line_idx],
+        args.ak_output_pattern.as_deref(),
+        args.ak_output_limit,
+        dir_action,
+    )
+    .change_context_lazy(|| CliError::InFile(ggg_file.to_path_buf()))?;
+    let new_spt_line = make_output_line(
+        &window,
+        &lines[spt_line_idx],
+        args.spt_output_pattern.as_deref(),
+        args.spt_output_limit,
+        dir_action,
+    )
+    .change_context_lazy(|| CliError::InFile(ggg_file.to_path_buf()))?;
+
+    if args.dry_run {
+        print!(
+            "{}",
+            format_preview(
+                ggg_file,
+                ak_line_idx,
+                &lines[ak_line_idx..=spt_line_idx],
+                &[&new_ak_line, &new_spt_line]
             )
-            .change_context_lazy(|| CliError::InFile(ggg_file.to_path_buf()))?
+        );
+        return Ok(());
+    }
+
+    let mut out = std::fs::File::create(ggg_file).change_context_lazy(|| CliError::IoError)?;
+    for (i, line) in lines.iter().enumerate() {
+        let new_line = if i == ak_line_idx {
+            &new_ak_line
+        } else if i == spt_line_idx {
+            &new_spt_line
         } else {
             line
         };
@@ -222,12 +254,185 @@ fn modify_ggg_file(ggg_file: &Path, args: &Cli) -> error_stack::Result<(), CliEr
     Ok(())
 }
 
+/// Format the change to the lines starting at index `first_idx` as a unified diff hunk.
+fn format_preview<S: AsRef<str>>(
+    ggg_file: &Path,
+    first_idx: usize,
+    old_lines: &[String],
+    new_lines: &[S],
+) -> String {
+    let mut preview = format!("--- {}\n+++ {}\n", ggg_file.display(), ggg_file.display());
+    let unchanged = old_lines
+        .iter()
+        .zip(new_lines.iter())
+        .all(|(old, new)| old == new.as_ref());
+    if unchanged {
+        preview.push_str("(no changes)\n");
+        return preview;
+    }
+
+    let first_line = first_idx + 1;
+    preview.push_str(&format!(
+        "@@ -{first_line},{} +{first_line},{} @@\n",
+        old_lines.len(),
+        new_lines.len()
+    ));
+    for old in old_lines {
+        preview.push_str(&format!("-{old}\n"));
+    }
+    for new in new_lines {
+        preview.push_str(&format!("+{}\n", new.as_ref()));
+    }
+    preview
+}
+
+/// Find the indices of the averaging kernel and spectral fit output lines in the lines
+/// of a .ggg file. These are the two consecutive lines that each have only a path (or file
+/// prefix) and a maximum number of files, with the AK line first. We identify them this way, rather
+/// than by position, since the number of lines before them has changed between GGG versions.
+///
+/// If more than one pair of lines looks like output lines, the pair whose file prefixes
+/// are GGG's defaults ("k" for AKs, "z" for spectral fits) is used; if that doesn't
+/// narrow it down to one pair, an error is returned rather than risk editing the wrong lines.
+fn find_output_lines(lines: &[String]) -> error_stack::Result<(usize, usize), CliError> {
+    let candidates = lines
+        .iter()
+        .tuple_windows()
+        .enumerate()
+        .filter(|(_, (ak, spt))| is_output_line(ak) && is_output_line(spt))
+        .map(|(i, (ak, spt))| (i, ak, spt))
+        .collect_vec();
+
+    let i = match candidates.as_slice() {
+        [] => {
+            return Err(CliError::FileFormatError(
+                "could not find the AK and SPT output lines (two consecutive lines with a path and a file limit)".to_string(),
+            )
+            .into())
+        }
+        [(i, _, _)] => *i,
+        _ => {
+            let default_prefixes = candidates
+                .iter()
+                .filter(|(_, ak, spt)| {
+                    output_file_prefix(ak).starts_with('k')
+                        && output_file_prefix(spt).starts_with('z')
+                })
+                .collect_vec();
+            if let [(i, _, _)] = default_prefixes.as_slice() {
+                *i
+            } else {
+                let line_numbers = candidates.iter().map(|(i, _, _)| i + 1).join(", ");
+                return Err(CliError::FileFormatError(format!(
+                    "could not tell which lines are the AK and SPT output lines; lines starting at {line_numbers} all could be"
+                ))
+                .into());
+            }
+        }
+    };
+
+    Ok((i, i + 1))
+}
+
+/// Whether `line` has the form of an AK or SPT output line: a path and a maximum number of files.
+/// The path may be a bare file prefix (e.g. "k 0"), which writes to the current directory, so
+/// it only needs to be something other than a number.
+fn is_output_line(line: &str) -> bool {
+    let parts = line.split_ascii_whitespace().collect_vec();
+    if let [path, limit] = parts.as_slice() {
+        path.parse::<f64>().is_err() && limit.parse::<u64>().is_ok()
+    } else {
+        false
+    }
+}
+
+/// The file name prefix from an AK or SPT output line, i.e. anything after the last path separator.
+fn output_file_prefix(line: &str) -> &str {
+    let path = line.split_ascii_whitespace().next().unwrap_or_default();
+    path.rsplit(std::path::MAIN_SEPARATOR)
+        .next()
+        .unwrap_or_default()
+}
+
+/// What to do with the output directory for a new AK/SPT output path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OutputDirAction {
+    /// Create the directory if it does not exist
+    Create,
+    /// Check that the directory exists and is writable
+    CheckWritable,
+    /// Do nothing with the directory
+    Skip,
+}
+
+/// Warnings for output patterns that do not contain "{WINDOW}", since every
+/// window would then write its files to the same place.
+fn window_pattern_warnings(args: &Cli) -> Vec<String> {
+    [
+        ("--ak-output-pattern", args.ak_output_pattern.as_deref()),
+        ("--spt-output-pattern", args.spt_output_pattern.as_deref()),
+    ]
+    .into_iter()
+    .filter_map(|(option, pattern)| {
+        let pattern = pattern?;
+        if pattern.contains("{WINDOW}") {
+            None
+        } else {
+            Some(format!("{option} '{pattern}' does not contain {{WINDOW}}, so all windows will write their files to the same directory"))
+        }
+    })
+    .collect()
+}
+
+/// The directory part of an AK/SPT output path.
+fn output_dir(output_path: &str) -> error_stack::Result<PathBuf, CliError> {
+    // We need this check because GGG allows you to input a string like "./spt/co2_6220/z" to mean
+    // "write the SPT files to ./spt/co2_6220 with a prefix of z for each file."  But because GGG
+    // doesn't automatically insert a trailing /, we know that if the path ends in a /, then there
+    // is no file prefix. If not, we need to remove the file prefix from the path to avoid creating
+    // a directory named e.g. "z".
+    if output_path.trim().ends_with(std::path::MAIN_SEPARATOR_STR) {
+        Ok(PathBuf::from(output_path))
+    } else {
+        let dir = PathBuf::from(output_path).parent()
+            .ok_or_else(|| CliError::UserError("Could not determine SPT/AK output directory - do not pass an empty string as the directory".to_string()))?
+            .to_path_buf();
+        Ok(dir)
+    }
+}
+
+/// Check that `dir` exists and that we can create files in it, by creating and removing a file.
+fn check_dir_writable(dir: &Path) -> error_stack::Result<(), CliError> {
+    let dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    if !dir.is_dir() {
+        return Err(CliError::PathError(
+            dir.to_path_buf(),
+            "output directory does not exist; use --make-output-dirs to create it".to_string(),
+        )
+        .into());
+    }
+
+    let probe = dir.join(".change_ggg_files_write_test");
+    std::fs::File::create(&probe)
+        .and_then(|_| std::fs::remove_file(&probe))
+        .change_context_lazy(|| {
+            CliError::PathError(
+                dir.to_path_buf(),
+                "output directory is not writable".to_string(),
+            )
+        })
+}
+
 fn make_output_line(
     window: &str,
     orig_line: &str,
     output_pattern: Option<&str>,
     max_num_file: Option<u64>,
-    mkdir: bool,
+    dir_action: OutputDirAction,
 ) -> error_stack::Result<String, CliError> {
     let mut orig_parts = orig_line.split_ascii_whitespace();
     let orig_path = orig_parts.next().ok_or_else(|| {
@@ -243,22 +448,18 @@ fn make_output_line(
         orig_path.to_string()
     };
 
-    if mkdir {
-        // We need this check because GGG allows you to input a string like "./spt/co2_6220/z" to mean
-        // "write the SPT files to ./spt/co2_6220 with a prefix of z for each file."  But because GGG
-        // doesn't automatically insert a trailing /, we know that if the path ends in a /, then there
-        // is no file prefix. If not, we need to remove the file prefix from the path to avoid creating
-        // a directory named e.g. "z".
-        let dir = if new_line.trim().ends_with(std::path::MAIN_SEPARATOR_STR) {
-            PathBuf::from(&new_line)
-        } else {
-            PathBuf::from(&new_line).parent()
-                .ok_or_else(|| CliError::UserError("Could not determine SPT/AK output directory - do not pass an empty string as the directory".to_string()))?
-                .to_path_buf()
-        };
-        if !dir.exists() {
-            std::fs::create_dir_all(&dir).change_context_lazy(|| CliError::IoError)?;
+    match dir_action {
+        OutputDirAction::Create => {
+            let dir = output_dir(&new_line)?;
+            if !dir.exists() {
+                std::fs::create_dir_all(&dir).change_context_lazy(|| CliError::IoError)?;
+            }
+        }
+        // Only check new paths; if the original path is kept, that's not a change we made
+        OutputDirAction::CheckWritable if output_pattern.is_some() => {
+            check_dir_writable(&output_dir(&new_line)?)?;
         }
+        OutputDirAction::CheckWritable | OutputDirAction::Skip => {}
     }
 
     if let Some(n) = max_num_file {
@@ -271,3 +472,148 @@ fn make_output_line(
 
     Ok(new_line)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_lines(contents: &str) -> Vec<String> {
+        contents.lines().map(|l| l.to_string()).collect()
+    }
+
+    /// A .ggg file laid out as for GGG2020, with the AK and SPT output lines at
+    /// lines 15 and 16 (the positions this program previously assumed).
+    fn ggg2020_header() -> String {
+        let ggg_file = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("test-data/inputs/ggg-files/co2_6220.pa_ggg_benchmark.ggg");
+        std::fs::read_to_string(ggg_file).unwrap()
+    }
+
+    #[test]
+    fn test_find_output_lines_ggg2020() {
+        let lines = to_lines(&ggg2020_header());
+        assert_eq!(find_output_lines(&lines).unwrap(), (14, 15));
+    }
+
+    #[test]
+    fn test_find_output_lines_shifted() {
+        // A hypothetical layout with an extra line before the output lines and
+        // non-default output prefixes.
+        let mut lines = to_lines(&ggg2020_header());
+        lines.insert(3, "/home/user/ggg/config/extra_input.dat".to_string());
+        lines[15] = "./ak/co2_6220/a 10".to_string();
+        lines[16] = "./spt/co2_6220/b 0".to_string();
+        assert_eq!(find_output_lines(&lines).unwrap(), (15, 16));
+    }
+
+    #[test]
+    fn test_dry_run_preview() {
+        let lines = to_lines(&ggg2020_header());
+        let ggg_file = PathBuf::from("co2_6220.pa_ggg_benchmark.ggg");
+        let new_ak = make_output_line(
+            "co2_6220",
+            &lines[14],
+            None,
+            Some(10),
+            OutputDirAction::Skip,
+        )
+        .unwrap();
+        let new_spt = make_output_line(
+            "co2_6220",
+            &lines[15],
+            Some("./spt/{WINDOW}/z"),
+            None,
+            OutputDirAction::Skip,
+        )
+        .unwrap();
+
+        let preview = format_preview(&ggg_file, 14, &lines[14..=15], &[&new_ak, &new_spt]);
+        assert_eq!(
+            preview,
+            "--- co2_6220.pa_ggg_benchmark.ggg\n\
+             +++ co2_6220.pa_ggg_benchmark.ggg\n\
+             @@ -15,2 +15,2 @@\n\
+             -/home/user/ggg/ak/k 0\n\
+             -/home/user/ggg/spt/z 150\n\
+             +/home/user/ggg/ak/k 10\n\
+             +./spt/co2_6220/z 150\n"
+        );
+
+        let preview = format_preview(&ggg_file, 14, &lines[14..=15], &lines[14..=15]);
+        assert!(preview.ends_with("(no changes)\n"), "{preview}");
+    }
+
+    #[test]
+    fn test_output_dir_checks() {
+        let args = Cli::parse_from([
+            "change_ggg_files",
+            "--ak-output-pattern",
+            "./ak/{WINDOW}/k",
+            "--spt-output-pattern",
+            "./spt/z",
+        ]);
+        let warnings = window_pattern_warnings(&args);
+        assert_eq!(warnings.len(), 1);
+        assert!(
+            warnings[0].starts_with("--spt-output-pattern"),
+            "{warnings:?}"
+        );
+
+        let output_dir = ggg_rs::test_utils::output_dir("change-ggg-files-output-dirs");
+        let pattern = format!("{}/z", output_dir.display());
+        let line = "/home/user/ggg/spt/z 150";
+        let new_line = make_output_line(
+            "co2_6220",
+            line,
+            Some(&pattern),
+            None,
+            OutputDirAction::CheckWritable,
+        )
+        .unwrap();
+        assert_eq!(new_line, format!("{pattern} 150"));
+
+        let missing = format!("{}/no-such-dir/{{WINDOW}}/z", output_dir.display());
+        let result = make_output_line(
+            "co2_6220",
+            line,
+            Some(&missing),
+            None,
+            OutputDirAction::CheckWritable,
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_find_output_lines_ambiguous() {
+        // A second pair of lines that look like output lines should only be accepted
+        // if the default prefixes pick out one pair.
+        let mut lines = to_lines(&ggg2020_header());
+        lines.insert(3, "/data/a 1".to_string());
+        lines.insert(4, "/data/b 2".to_string());
+        assert_eq!(find_output_lines(&lines).unwrap(), (16, 17));
+
+        lines[16] = "./ak/co2_6220/a 10".to_string();
+        assert!(find_output_lines(&lines).is_err());
+
+        let no_output_lines = to_lines("4.80\n/home/user/ggg/config/data_part.lst\n");
+        assert!(find_output_lines(&no_output_lines).is_err());
+    }
+
+    #[test]
+    fn test_find_output_lines_without_separator() {
+        // GGG accepts a bare file prefix to write to the current directory.
+        let mut lines = to_lines(&ggg2020_header());
+        lines[14] = "k 0".to_string();
+        lines[15] = "z 150".to_string();
+        assert!(is_output_line(&lines[14]) && is_output_line(&lines[15]));
+        assert_eq!(find_output_lines(&lines).unwrap(), (14, 15));
+
+        // Another pair with the same shape is resolved by the default prefixes.
+        lines.insert(3, "a 1".to_string());
+        lines.insert(4, "b 2".to_string());
+        assert_eq!(find_output_lines(&lines).unwrap(), (16, 17));
+
+        // Two numbers are not an output line.
+        assert!(!is_output_line("6220.00 80"));
+    }
+}
@@ -1,4 +1,8 @@
-use std::{collections::HashMap, path::PathBuf, process::ExitCode};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
 
 use clap::Parser;
 use clap_verbosity_flag::{InfoLevel, Verbosity};
@@ -6,9 +10,10 @@ use error_stack::ResultExt;
 use ggg_rs::{
     cit_spectrum_name::{CitDetector, CitSpectrumName, NoDetectorSpecName},
     collation::{
-        collate_results, CollationError, CollationIndexer, CollationMode, CollationResult,
+        collate_results, CollationError, CollationIndexer, CollationMode, CollationOptions,
+        CollationOutput, CollationResult, CollationSummary,
     },
-    logging::init_logging,
+    logging::{init_logging_with_format, LogFormatCli},
     o2_dmf::{make_boxed_o2_dmf_provider, O2DmfCli},
     readers::{
         runlogs::{FallibleRunlog, RunlogDataRec},
@@ -22,7 +27,10 @@ fn main() -> ExitCode {
     let clargs = CollateCli::parse();
     // This moved outside main_inner() for now to avoid panics during tests
     // calling main_inner and trying to initialize logging more than once.
-    init_logging(clargs.verbosity.log_level_filter());
+    init_logging_with_format(
+        clargs.verbosity.log_level_filter(),
+        clargs.log_format.log_format,
+    );
     if let Err(e) = main_inner(clargs) {
         eprintln!("ERROR: {e:?}");
         ExitCode::FAILURE
@@ -42,7 +50,11 @@ fn main_inner(clargs: CollateCli) -> error_stack::Result<(), CollationError> {
         date: "2024-04-28".to_string(),
         authors: "JLL".to_string(),
     };
-    let indexer = TcconColIndexer::new(clargs.primary_detector);
+    let indexer = if clargs.em27 {
+        TcconColIndexer::new_single_detector()
+    } else {
+        TcconColIndexer::new(clargs.primary_detector)
+    };
 
     // I think eventually we will require a prefix file. But for now, I want to be able to use
     // this without needing a prefix file.
@@ -66,24 +78,48 @@ fn main_inner(clargs: CollateCli) -> error_stack::Result<(), CollationError> {
             )
         })?;
 
-    collate_results(
-        &multiggg_file,
-        indexer,
-        prefixer,
-        o2_provider,
-        clargs.mode,
+    let nts_file = clargs.nts_file.or_else(|| {
+        clargs.write_nts.then(|| {
+            clargs
+                .output_dir
+                .as_deref()
+                .unwrap_or(run_dir)
+                .join("collate_results.nts")
+        })
+    });
+
+    let opts = CollationOptions::new(&multiggg_file, clargs.mode, clargs.compatibility.into())
+        .with_runlog(clargs.runlog.as_deref())
+        .with_windows(clargs.windows.as_deref());
+    let output = CollationOutput {
         collate_version,
-        clargs.output_dir.as_deref(),
-        clargs.write_nts,
-        clargs.compatibility.into(),
-    )
+        output_dir: clargs.output_dir.as_deref(),
+        neg_timesteps_file: nts_file.as_deref(),
+    };
+    let summary = collate_results(&opts, output, indexer, prefixer, o2_provider)?;
+
+    if let Some(summary_file) = clargs.summary_json {
+        write_summary_json(&summary, &summary_file)?;
+    }
+    Ok(())
+}
+
+fn write_summary_json(
+    summary: &CollationSummary,
+    summary_file: &Path,
+) -> error_stack::Result<(), CollationError> {
+    let f = std::fs::File::create(summary_file)
+        .change_context_lazy(|| CollationError::could_not_write(summary_file))?;
+    serde_json::to_writer_pretty(std::io::BufWriter::new(f), summary)
+        .change_context_lazy(|| CollationError::could_not_write(summary_file))
 }
 
 #[derive(Debug, clap::Parser)]
 struct CollateCli {
     /// What quantity to collate: 'v' will compute vertical
-    /// column densities, 't' will extract the VSFs. (Other
-    /// modes not yet implemented.)
+    /// column densities, 't' will extract the VSFs, and 'e'
+    /// will write only the vertical column errors (to a .vse
+    /// file). (Other modes not yet implemented.)
     mode: CollationMode,
 
     /// Which multiggg.sh file that defines the windows to process.
@@ -93,6 +129,18 @@ struct CollateCli {
     #[clap(short = 'm', long, default_value = "./multiggg.sh")]
     multiggg_file: PathBuf,
 
+    /// Path to the runlog to take the spectrum order and auxiliary data from.
+    /// If not given, the runlog listed in the .col file headers is used. Use
+    /// this if the run directory has more than one runlog or the runlog has moved.
+    #[clap(long)]
+    runlog: Option<PathBuf>,
+
+    /// Only collate these windows, given as a comma-separated list of window names
+    /// (e.g. "co2_6220,co2_6339"). Each must be listed in the multiggg file. The
+    /// output still has a row for every spectrum in the runlog.
+    #[clap(long, value_delimiter = ',')]
+    windows: Option<Vec<String>>,
+
     /// Which detector is considered the "primary" detector; this will affect
     /// which auxiliary values (year, day, hour, zmin, met data, etc.) are written.
     /// For such values, those associated with the primary detector will take precedence
@@ -106,12 +154,30 @@ struct CollateCli {
     #[clap(short='p', long, default_value_t = CitDetector::InGaAs)]
     primary_detector: CitDetector,
 
+    /// Treat every spectrum in the runlog as coming from a single detector, as for
+    /// EM27 data. Each runlog line becomes its own row in the output, spectra are matched
+    /// by their full name, and spectrum names do not need to follow the TCCON convention.
+    /// --primary-detector has no effect in this mode.
+    #[clap(long)]
+    em27: bool,
+
     /// Write out "collate_results.nts" listing spectra with a ZPD time earlier than
-    /// the preceding spectrum in the runlog. This is not written by default, because
+    /// the preceding spectrum in the runlog, along with both spectra's ZPD times. This is not written by default, because
     /// collate_tccon_results does not rely on the runlog to be time-ordered.
     #[clap(short = 'n', long)]
     write_nts: bool,
 
+    /// Write the negative time step report (see --write-nts) to this path instead
+    /// of "collate_results.nts" in the output directory. Implies --write-nts.
+    #[clap(long)]
+    nts_file: Option<PathBuf>,
+
+    /// Write a JSON summary of the collation (number of windows and spectra, negative
+    /// time steps, and windows with missing values) to this path. See `CollationSummary`
+    /// in the ggg_rs::collation module for the meaning of each key.
+    #[clap(long)]
+    summary_json: Option<PathBuf>,
+
     /// Path to the file that defines the specie's prefixes for different frequency
     /// ranges. If not given, will use the file at $GGGPATH/tccon/secondary_prefixes.dat
     /// if it exists. Giving a path to this argument that does not exist is an error.
@@ -131,25 +197,58 @@ struct CollateCli {
 
     #[command(flatten)]
     verbosity: Verbosity<InfoLevel>,
+
+    #[command(flatten)]
+    log_format: LogFormatCli,
+}
+
+/// How spectra are matched to output rows.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum RowKey {
+    /// TCCON convention: spectra differing only in their detector go in the same row.
+    NoDetector(NoDetectorSpecName),
+    /// Single-detector (EM27) convention: every spectrum gets its own row.
+    FullName(String),
 }
 
 #[derive(Debug)]
 struct TcconColIndexer {
-    index_map: HashMap<NoDetectorSpecName, usize>,
+    index_map: HashMap<RowKey, usize>,
     runlog_data: Vec<RunlogDataRec>,
     neg_timesteps: Vec<(RunlogDataRec, RunlogDataRec)>,
-    primary_detector: CitDetector,
+    /// `None` if all spectra should be treated as coming from one detector.
+    primary_detector: Option<CitDetector>,
 }
 
 impl TcconColIndexer {
     fn new(primary_detector: CitDetector) -> Self {
         Self {
-            primary_detector,
+            primary_detector: Some(primary_detector),
             index_map: HashMap::new(),
             neg_timesteps: vec![],
             runlog_data: vec![],
         }
     }
+
+    fn new_single_detector() -> Self {
+        Self {
+            primary_detector: None,
+            index_map: HashMap::new(),
+            neg_timesteps: vec![],
+            runlog_data: vec![],
+        }
+    }
+
+    fn row_key(&self, spectrum: &str) -> CollationResult<RowKey> {
+        if self.primary_detector.is_none() {
+            return Ok(RowKey::FullName(spectrum.to_string()));
+        }
+
+        let nd_spec = NoDetectorSpecName::new(spectrum).map_err(|e| {
+            CollationError::custom(format!("Could not parse spectrum name '{spectrum}': {e}"))
+        })?;
+        Ok(RowKey::NoDetector(nd_spec))
+    }
 }
 
 impl CollationIndexer for TcconColIndexer {
@@ -185,25 +284,21 @@ impl CollationIndexer for TcconColIndexer {
                 }
             }
 
-            let nd_spec = NoDetectorSpecName::new(&rec.spectrum_name).map_err(|e| {
-                CollationError::custom(format!(
-                    "Could not parse spectrum name '{}': {e}",
-                    rec.spectrum_name
-                ))
-            })?;
+            let key = self.row_key(&rec.spectrum_name)?;
 
-            if Some(&nd_spec) == last_spec.as_ref() {
+            if Some(&key) == last_spec.as_ref() && self.primary_detector.is_some() {
                 // ignore this spectrum; it's a second detector for the same observation as the last one
-            } else if self.index_map.contains_key(&nd_spec) {
+            } else if self.index_map.contains_key(&key) {
                 return Err(CollationError::custom(format!(
-                    "Spectrum '{}' (ignoring the detector) shows up in two separate places in the runlog, this is not allowed.",
-                    rec.spectrum_name
+                    "Spectrum '{}'{} shows up in two separate places in the runlog, this is not allowed.",
+                    rec.spectrum_name,
+                    if self.primary_detector.is_some() { " (ignoring the detector)" } else { "" }
                 )));
             } else {
-                self.index_map.insert(nd_spec.clone(), idx);
+                self.index_map.insert(key.clone(), idx);
                 self.runlog_data.push(rec.clone());
                 idx += 1;
-                last_spec = Some(nd_spec);
+                last_spec = Some(key);
             }
 
             prev_rec = Some(rec);
@@ -213,16 +308,19 @@ impl CollationIndexer for TcconColIndexer {
     }
 
     fn get_row_index(&self, spectrum: &str) -> CollationResult<usize> {
-        let nd_spec = NoDetectorSpecName::new(spectrum).map_err(|e| {
-            CollationError::custom(format!("Could not parse spectrum name '{}': {e}", spectrum))
-        })?;
+        let key = self.row_key(spectrum)?;
 
         self.index_map
-            .get(&nd_spec)
+            .get(&key)
             .ok_or_else(|| {
                 CollationError::custom(format!(
-                    "Cannot find spectrum '{}' in the runlog (ignoring the detector).",
-                    spectrum
+                    "Cannot find spectrum '{}' in the runlog{}.",
+                    spectrum,
+                    if self.primary_detector.is_some() {
+                        " (ignoring the detector)"
+                    } else {
+                        ""
+                    }
                 ))
             })
             .map(|i| *i)
@@ -247,12 +345,18 @@ impl CollationIndexer for TcconColIndexer {
         )
         .contains(&column_name)
         {
+            // With a single detector, each row has only one spectrum, so that spectrum
+            // always provides the auxiliary values.
+            let Some(primary_detector) = self.primary_detector else {
+                return Ok(true);
+            };
+
             let new_spectrum: CitSpectrumName = new_spectrum.parse().map_err(|e| {
                 CollationError::parsing_error(format!(
                     "could not parse spectrum name '{new_spectrum}': {e}"
                 ))
             })?;
-            if new_spectrum.detector() == self.primary_detector {
+            if new_spectrum.detector() == primary_detector {
                 Ok(true)
             } else {
                 Ok(false)
@@ -265,9 +369,14 @@ impl CollationIndexer for TcconColIndexer {
 
 #[cfg(test)]
 mod tests {
+    use ggg_rs::collation::collate_data;
+    use ggg_rs::logging::LogFormat;
     use ggg_rs::o2_dmf::DEFAULT_O2_DMF;
-    use ggg_rs::test_utils::{compare_output_text_files, remove_file_if_exists};
-    use ggg_rs::utils::GggCompatibilityInput;
+    use ggg_rs::readers::postproc_files::open_and_iter_postproc_file;
+    use ggg_rs::test_utils::{
+        assert_postproc_file_consistent, compare_output_text_files, remove_file_if_exists,
+    };
+    use ggg_rs::utils::{GggCompatibility, GggCompatibilityInput};
 
     use super::*;
 
@@ -311,7 +420,252 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_collate_pa_benchmark_vse() {
+        test_inner(
+            CollationMode::ColumnErrors,
+            GggCompatibilityInput::Current,
+            "pa_ggg_benchmark.vse",
+        );
+    }
+
+    #[test]
+    fn test_collate_pa_benchmark_explicit_runlog() {
+        test_inner_with_runlog(
+            CollationMode::VerticalColumns,
+            GggCompatibilityInput::Current,
+            "pa_ggg_benchmark.vsw",
+            Some("pa_ggg_benchmark.grl"),
+        );
+    }
+
+    #[test]
+    fn test_collate_missing_explicit_runlog() {
+        let crate_root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let input_dir = crate_root.join("test-data/inputs/collate-tccon-results");
+        let output_dir = ggg_rs::test_utils::output_dir("collate-tccon-results-missing-runlog");
+        let out_file = output_dir.join("pa_ggg_benchmark.vsw");
+        remove_file_if_exists(&out_file).unwrap();
+
+        let clargs = CollateCli {
+            runlog: Some(input_dir.join("no_such_runlog.grl")),
+            ..default_clargs(&input_dir, &output_dir)
+        };
+
+        let err = main_inner(clargs).unwrap_err();
+        assert!(format!("{err:?}").contains("no_such_runlog.grl"), "{err:?}");
+        assert!(!out_file.exists());
+    }
+
+    #[test]
+    fn test_collate_unsorted_runlog_reports() {
+        let crate_root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let input_dir = crate_root.join("test-data/inputs/collate-tccon-results");
+        let output_dir = ggg_rs::test_utils::output_dir("collate-tccon-results-nts");
+
+        // Put the second observation (both detectors) before the first so that
+        // the runlog has one negative time step
+        let runlog_text = std::fs::read_to_string(input_dir.join("pa_ggg_benchmark.grl")).unwrap();
+        let lines = runlog_text.lines().collect::<Vec<_>>();
+        let nhead = 4;
+        let mut reordered = lines[..nhead].to_vec();
+        reordered.extend_from_slice(&lines[nhead + 2..nhead + 4]);
+        reordered.extend_from_slice(&lines[nhead..nhead + 2]);
+        reordered.extend_from_slice(&lines[nhead + 4..]);
+        let runlog = output_dir.join("pa_ggg_benchmark_unsorted.grl");
+        std::fs::write(&runlog, reordered.join("\n") + "\n").unwrap();
+
+        let nts_file = output_dir.join("unsorted.nts");
+        remove_file_if_exists(&nts_file).unwrap();
+        let summary_file = output_dir.join("summary.json");
+        remove_file_if_exists(&summary_file).unwrap();
+
+        let clargs = CollateCli {
+            runlog: Some(runlog),
+            nts_file: Some(nts_file.clone()),
+            summary_json: Some(summary_file.clone()),
+            ..default_clargs(&input_dir, &output_dir)
+        };
+        main_inner(clargs).expect("running collation should succeed");
+
+        let report = std::fs::read_to_string(&nts_file).unwrap();
+        let report_lines = report.lines().collect::<Vec<_>>();
+        assert_eq!(report_lines.len(), 1, "{report}");
+        let line = report_lines[0];
+        assert!(
+            line.contains("pa20040721saaaaa.043") && line.contains("after pa20040721saaaab.119"),
+            "{line}"
+        );
+        assert!(line.contains("ZPD times 2004-07-21T"), "{line}");
+
+        let summary: serde_json::Value =
+            serde_json::from_reader(std::fs::File::open(&summary_file).unwrap()).unwrap();
+        assert_eq!(summary["schema_version"], 1);
+        assert_eq!(summary["num_windows"], 57);
+        assert_eq!(summary["num_spectra"], 4);
+        assert_eq!(summary["num_negative_timesteps"], 1);
+        assert_eq!(summary["missing_windows"], serde_json::json!({}));
+    }
+
+    #[test]
+    fn test_collate_window_subset() {
+        let crate_root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let input_dir = crate_root.join("test-data/inputs/collate-tccon-results");
+        let output_dir = ggg_rs::test_utils::output_dir("collate-tccon-results-windows");
+        let out_file = output_dir.join("pa_ggg_benchmark.vsw");
+        remove_file_if_exists(&out_file).unwrap();
+
+        let make_clargs = |windows: &[&str]| CollateCli {
+            windows: Some(windows.iter().map(|w| w.to_string()).collect()),
+            ..default_clargs(&input_dir, &output_dir)
+        };
+
+        let err = main_inner(make_clargs(&["co2_6220", "co2_9999"])).unwrap_err();
+        assert!(format!("{err:?}").contains("co2_9999"), "{err:?}");
+        assert!(!out_file.exists());
+
+        main_inner(make_clargs(&["co2_6339", "co2_6220"]))
+            .expect("running collation should succeed");
+        let text = std::fs::read_to_string(&out_file).unwrap();
+        let nhead: usize = text.split_whitespace().next().unwrap().parse().unwrap();
+        let lines = text.lines().collect::<Vec<_>>();
+        let columns = lines[nhead - 1].split_whitespace().collect::<Vec<_>>();
+        assert_eq!(
+            &columns[columns.len() - 4..],
+            ["co2_6220", "co2_6220_error", "co2_6339", "co2_6339_error"]
+        );
+        assert_eq!(lines.len() - nhead, 4);
+    }
+
+    #[test]
+    fn test_collate_data_in_memory() {
+        let crate_root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let input_dir = crate_root.join("test-data/inputs/collate-tccon-results");
+        let expected_file =
+            crate_root.join("test-data/expected/collate-tccon-results/pa_ggg_benchmark.vsw");
+
+        let mut indexer = TcconColIndexer::new(CitDetector::InGaAs);
+        let prefixer = TcconWindowPrefixes::new(&input_dir.join("secondary_prefixes.dat")).unwrap();
+        let o2_args = O2DmfCli {
+            fixed_o2_dmf: Some(DEFAULT_O2_DMF),
+            o2_dmf_file: None,
+        };
+        let o2_provider = make_boxed_o2_dmf_provider(&o2_args, &input_dir).unwrap();
+        let multiggg_file = input_dir.join("multiggg.sh");
+        let opts = CollationOptions::new(
+            &multiggg_file,
+            CollationMode::VerticalColumns,
+            GggCompatibility::Current,
+        );
+        let data = collate_data(&opts, &mut indexer, Some(prefixer), o2_provider.as_ref())
+            .expect("collating in memory should succeed");
+
+        let (header, expected_rows) = open_and_iter_postproc_file(&expected_file).unwrap();
+        assert_eq!(data.columns, header.column_names);
+        assert_eq!(data.naux, header.naux);
+
+        let values = data.retrieved_array();
+        assert_eq!(values.dim(), (4, data.retrieved_columns().len()));
+        for (i, expected) in expected_rows.enumerate() {
+            let expected = expected.unwrap();
+            let aux = data.aux_records().nth(i).unwrap();
+            assert_eq!(aux.spectrum, expected.auxiliary.spectrum);
+            for (j, colname) in data.retrieved_columns().iter().enumerate() {
+                let (v, e) = (values[[i, j]], expected.retrieved[colname]);
+                assert!(
+                    (v - e).abs() <= 1e-4 * e.abs(),
+                    "{colname} row {i}: {v} != {e}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_single_detector_indexer() {
+        let runlog = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("test-data/inputs/collate-tccon-results/pa_ggg_benchmark.grl");
+
+        let mut tccon = TcconColIndexer::new(CitDetector::InGaAs);
+        tccon.parse_runlog(&runlog).unwrap();
+        assert_eq!(tccon.get_runlog_data().unwrap().len(), 4);
+        assert_eq!(tccon.get_row_index("pa20040721saaaab.043").unwrap(), 0);
+
+        let mut em27 = TcconColIndexer::new_single_detector();
+        em27.parse_runlog(&runlog).unwrap();
+        assert_eq!(em27.get_runlog_data().unwrap().len(), 8);
+        assert_eq!(em27.get_row_index("pa20040721saaaaa.043").unwrap(), 0);
+        assert_eq!(em27.get_row_index("pa20040721saaaab.043").unwrap(), 1);
+        assert!(em27.get_row_index("not_in_runlog.001").is_err());
+        assert!(em27
+            .do_replace_value("pa20040721saaaaa.043", "zmin")
+            .unwrap());
+        assert!(em27
+            .do_replace_value("pa20040721saaaaa.043", "co2_6220")
+            .is_err());
+    }
+
+    #[test]
+    fn test_collate_pa_benchmark_em27() {
+        // Treating the benchmark as single-detector data should give one row per runlog
+        // line, each with its own auxiliary values.
+        let crate_root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let input_dir = crate_root.join("test-data/inputs/collate-tccon-results");
+        let output_dir = ggg_rs::test_utils::output_dir("collate-tccon-results-em27");
+        let out_file = output_dir.join("pa_ggg_benchmark.vsw");
+        remove_file_if_exists(&out_file).expect("Should be able to delete existing output file");
+
+        let clargs = CollateCli {
+            em27: true,
+            ..default_clargs(&input_dir, &output_dir)
+        };
+        main_inner(clargs).expect("running EM27 collation should succeed");
+
+        assert_postproc_file_consistent(&out_file, GggCompatibility::Current);
+        let (_, rows) = open_and_iter_postproc_file(&out_file).unwrap();
+        let spectra: Vec<String> = rows.map(|r| r.unwrap().auxiliary.spectrum).collect();
+        assert_eq!(spectra.len(), 8);
+        assert_eq!(spectra[0], "pa20040721saaaaa.043");
+        assert_eq!(spectra[1], "pa20040721saaaab.043");
+    }
+
+    /// Command line arguments to collate a .vsw file from the benchmark inputs in `input_dir`
+    /// into `output_dir` with the current GGG compatibility. Tests should override the fields
+    /// they need with struct update syntax.
+    fn default_clargs(input_dir: &Path, output_dir: &Path) -> CollateCli {
+        CollateCli {
+            mode: CollationMode::VerticalColumns,
+            multiggg_file: input_dir.join("multiggg.sh"),
+            runlog: None,
+            windows: None,
+            primary_detector: CitDetector::InGaAs,
+            em27: false,
+            write_nts: false,
+            nts_file: None,
+            summary_json: None,
+            prefix_file: Some(input_dir.join("secondary_prefixes.dat")),
+            o2_dmf_args: O2DmfCli {
+                fixed_o2_dmf: Some(DEFAULT_O2_DMF),
+                o2_dmf_file: None,
+            },
+            output_dir: Some(output_dir.to_path_buf()),
+            compatibility: GggCompatibilityCli::new(GggCompatibilityInput::Current),
+            verbosity: Verbosity::new(0, 0),
+            log_format: LogFormatCli {
+                log_format: LogFormat::Text,
+            },
+        }
+    }
+
     fn test_inner(mode: CollationMode, compat: GggCompatibilityInput, out_file_name: &str) {
+        test_inner_with_runlog(mode, compat, out_file_name, None);
+    }
+
+    fn test_inner_with_runlog(
+        mode: CollationMode,
+        compat: GggCompatibilityInput,
+        out_file_name: &str,
+        runlog: Option<&str>,
+    ) {
         let subdir = match compat {
             GggCompatibilityInput::Current => "collate-tccon-results",
             GggCompatibilityInput::Stable => "collate-tccon-results-stable",
@@ -327,29 +681,32 @@ mod tests {
             .join("test-data")
             .join("expected")
             .join(subdir);
+        // Use a separate output directory when giving the runlog explicitly so that
+        // those tests do not race with the default tests writing the same file.
+        let output_subdir = if runlog.is_some() {
+            format!("{subdir}-runlog")
+        } else {
+            subdir.to_string()
+        };
         let output_dir = PathBuf::from(crate_root)
             .join("test-data")
             .join("outputs")
-            .join(subdir);
+            .join(output_subdir);
         remove_file_if_exists(&output_dir.join(out_file_name))
             .expect("Should be able to delete existing output file");
 
         let clargs = CollateCli {
             mode,
-            multiggg_file: input_dir.join("multiggg.sh"),
-            primary_detector: CitDetector::InGaAs,
-            write_nts: false,
-            prefix_file: Some(input_dir.join("secondary_prefixes.dat")),
-            o2_dmf_args: O2DmfCli {
-                fixed_o2_dmf: Some(DEFAULT_O2_DMF),
-                o2_dmf_file: None,
-            },
-            output_dir: Some(output_dir.clone()),
+            runlog: runlog.map(|r| input_dir.join(r)),
             compatibility: GggCompatibilityCli::new(compat),
-            verbosity: Verbosity::new(0, 0),
+            ..default_clargs(&input_dir, &output_dir)
         };
         main_inner(clargs).expect("running collation should succeed");
 
         compare_output_text_files(&expected_dir, &output_dir, out_file_name);
+        assert_postproc_file_consistent(
+            &output_dir.join(out_file_name),
+            GggCompatibility::from(compat),
+        );
     }
 }
@@ -22,6 +22,10 @@ pub(crate) enum CalcError {
         base_gas: String,
         other_gas: String,
     },
+    #[error("More than one AK file for {gas} has spectra in the SZA bin(s) centered at {bins:?}")]
+    OverlappingBins { gas: String, bins: Vec<f64> },
+    #[error("The {gas} AK bin centers are not unique and strictly increasing: {bins:?}")]
+    NonMonotonicBins { gas: String, bins: Vec<f64> },
     #[error("Error occurred while creating the table for gas '{0}'")]
     GasContext(String),
 }
@@ -93,15 +97,22 @@ impl SpectrumAks {
 
 /// Create the collection of binned averaging kernels
 /// and check that all gases are on the same vertical grid.
+///
+/// `ak_infos` has the AKs read from each `.all` file for each gas. A gas may have
+/// more than one file, but no two files for the same gas may have spectra in the
+/// same bin.
 pub(crate) fn make_ak_tables(
-    ak_infos: HashMap<String, Vec<AkInfo>>,
+    ak_infos: HashMap<String, Vec<Vec<AkInfo>>>,
 ) -> error_stack::Result<AkTableSet, CalcError> {
     // First we handle reshaping the list of AKs from the .all files into proper 2D arrays,
     // with spectrum and level as dimensions.
     let mut spectrum_aks = HashMap::new();
-    for (gas, ak_infos) in ak_infos {
+    for (gas, file_ak_infos) in ak_infos {
         log::info!("Tabulating {gas} AKs");
-        let this_gas_aks = make_one_gas_ak_table(&ak_infos)
+        let this_gas_aks = file_ak_infos
+            .iter()
+            .map(|ak_infos| make_one_gas_ak_table(ak_infos))
+            .collect::<Result<Vec<_>, _>>()
             .change_context_lazy(|| CalcError::gas_context(gas.clone()))?;
         spectrum_aks.insert(gas, this_gas_aks);
     }
@@ -116,11 +127,16 @@ pub(crate) fn make_ak_tables(
     let mut tables = HashMap::new();
     let bin_edges = ndarray::Array1::range(0.0, 91., 5.0);
     let n_edge = bin_edges.len();
-    let bin_centers =
+    let bin_centers: Array1<f64> =
         0.5 * (bin_edges.slice(s![..n_edge - 1]).to_owned() + bin_edges.slice(s![1..]));
     for (gas, gas_spec_aks) in spectrum_aks.into_iter() {
         log::info!("Binning {gas} AKs");
-        let ak_array = bin_by_sza(&gas_spec_aks, bin_edges.view());
+        check_bins_monotonic(&gas, bin_centers.view())?;
+        let file_tables = gas_spec_aks
+            .iter()
+            .map(|spec_aks| bin_by_sza(spec_aks, bin_edges.view()))
+            .collect::<Vec<_>>();
+        let ak_array = merge_binned_tables(&gas, file_tables, bin_centers.view())?;
         tables.insert(
             gas,
             AkTable {
@@ -243,13 +259,16 @@ fn count_levels_by_spec(aks: &[AkInfo]) -> HashMap<usize, usize> {
 }
 
 fn check_level_coords(
-    spectrum_aks: &HashMap<String, SpectrumAks>,
+    spectrum_aks: &HashMap<String, Vec<SpectrumAks>>,
 ) -> Result<(Array1<f64>, Array1<f64>), CalcError> {
     let mut opt_expected_z = None;
     let mut opt_expected_p = None;
     let mut base_gas = None;
 
-    for (gas, table) in spectrum_aks.iter() {
+    let all_tables = spectrum_aks
+        .iter()
+        .flat_map(|(gas, tables)| tables.iter().map(move |t| (gas, t)));
+    for (gas, table) in all_tables {
         if opt_expected_p.is_none() {
             opt_expected_p = Some(&table.p);
             opt_expected_z = Some(&table.z);
@@ -313,6 +332,69 @@ fn bin_by_sza(spec_aks: &SpectrumAks, bin_edges: ArrayView1<f64>) -> Array2<f64>
     table
 }
 
+/// Check that the bin centers of one gas's table are strictly increasing, so that
+/// each bin is unique.
+///
+/// Every gas is currently binned on the same fixed SZA bins (5 degrees wide, from 0 to 90),
+/// so this cannot fail for tables made by [`make_ak_tables`]; it only guards against a future
+/// change to how the bins are defined. Files for one gas that fill the same bin are caught by
+/// [`merge_binned_tables`], and bins that differ from those already in a table file being
+/// appended to are caught when writing.
+fn check_bins_monotonic(gas: &str, bin_centers: ArrayView1<f64>) -> Result<(), CalcError> {
+    let is_increasing = bin_centers
+        .iter()
+        .zip(bin_centers.iter().skip(1))
+        .all(|(a, b)| a < b);
+    if is_increasing {
+        Ok(())
+    } else {
+        Err(CalcError::NonMonotonicBins {
+            gas: gas.to_string(),
+            bins: bin_centers.to_vec(),
+        })
+    }
+}
+
+/// Combine the binned AKs from several files for one gas into a single table. Bins
+/// with no spectra are NaN, so a bin is "filled" by a file if its column has any
+/// non-NaN value. Returns an error listing the bin centers if more than one file
+/// fills the same bin.
+fn merge_binned_tables(
+    gas: &str,
+    file_tables: Vec<Array2<f64>>,
+    bin_centers: ArrayView1<f64>,
+) -> Result<Array2<f64>, CalcError> {
+    let mut file_tables = file_tables.into_iter();
+    let Some(mut merged) = file_tables.next() else {
+        return Err(CalcError::EmptyVec);
+    };
+
+    let mut overlapping = vec![];
+    for table in file_tables {
+        for (ibin, column) in table.columns().into_iter().enumerate() {
+            if column.iter().all(|v| v.is_nan()) {
+                continue;
+            }
+            if merged.column(ibin).iter().any(|v| !v.is_nan()) {
+                overlapping.push(bin_centers[ibin]);
+            } else {
+                merged.column_mut(ibin).assign(&column);
+            }
+        }
+    }
+
+    if overlapping.is_empty() {
+        Ok(merged)
+    } else {
+        overlapping.sort_by(|a, b| a.total_cmp(b));
+        overlapping.dedup();
+        Err(CalcError::OverlappingBins {
+            gas: gas.to_string(),
+            bins: overlapping,
+        })
+    }
+}
+
 fn find_bin_index(sza: f64, bin_edges: ArrayView1<f64>) -> Option<usize> {
     for i in 0..bin_edges.len() - 1 {
         if sza >= bin_edges[i] && sza < bin_edges[i + 1] {
@@ -321,3 +403,46 @@ fn find_bin_index(sza: f64, bin_edges: ArrayView1<f64>) -> Option<usize> {
     }
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+
+    use super::*;
+
+    #[test]
+    fn test_merge_binned_tables() {
+        let nan = f64::NAN;
+        let centers = array![2.5, 7.5, 12.5];
+        let first = array![[1.0, nan, nan], [2.0, nan, nan]];
+        let second = array![[nan, nan, 3.0], [nan, nan, 4.0]];
+        let merged =
+            merge_binned_tables("co2", vec![first.clone(), second], centers.view()).unwrap();
+        assert_eq!(merged[(1, 0)], 2.0);
+        assert!(merged.column(1).iter().all(|v| v.is_nan()));
+        assert_eq!(merged[(0, 2)], 3.0);
+
+        let overlap = array![[0.5, nan, 0.5], [0.5, nan, 0.5]];
+        let err = merge_binned_tables("co2", vec![first, overlap], centers.view()).unwrap_err();
+        assert!(
+            matches!(&err, CalcError::OverlappingBins { gas, bins } if gas == "co2" && bins == &[2.5]),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn test_check_bins_monotonic() {
+        assert!(check_bins_monotonic("co2", array![2.5, 7.5, 12.5].view()).is_ok());
+        for bins in [
+            array![2.5, 7.5, 7.5, 12.5],
+            array![2.5, 12.5, 7.5],
+            array![12.5, 7.5, 2.5],
+        ] {
+            let err = check_bins_monotonic("co2", bins.view()).unwrap_err();
+            assert!(
+                matches!(&err, CalcError::NonMonotonicBins { gas, bins: b } if gas == "co2" && b == &bins.to_vec()),
+                "{err:?}"
+            );
+        }
+    }
+}
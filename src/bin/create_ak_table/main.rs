@@ -1,9 +1,16 @@
-use std::{collections::HashMap, fmt::Display, path::PathBuf, process::ExitCode, str::FromStr};
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    str::FromStr,
+};
 
 use clap::Parser;
 use clap_verbosity_flag::{InfoLevel, Verbosity};
 use error_stack::ResultExt;
-use ggg_rs::logging::init_logging;
+use ggg_rs::{logging::init_logging, readers::ProgramVersion};
+use itertools::Itertools;
 
 mod calc_aks;
 mod naming;
@@ -31,7 +38,17 @@ fn main() -> ExitCode {
 struct Cli {
     /// A sequence of paths to files named "k0_GAS_*.all", that is, the
     /// result of running `avg_ker` on the concatenated Jacobian files.
-    ak_files: Vec<PathBuf>,
+    /// The gas is inferred from the file name, unless the path is followed
+    /// by "=GAS" (e.g. "renamed_aks.txt=co2"). The "=GAS" suffix is only
+    /// recognized for gases GGG retrieves; any other "=" is part of the path.
+    #[clap(required_unless_present = "list_gases")]
+    ak_files: Vec<read_aks::AkInput>,
+
+    /// The gas that the AK file is for, overriding the one inferred from
+    /// its file name. Only allowed with a single AK file given without
+    /// "=GAS"; use the FILE=GAS form to set the gas for several files.
+    #[clap(long)]
+    gas: Option<String>,
 
     /// File to write the AK table to.
     /// Overwritten by default, use --append to change that behavior.
@@ -47,6 +64,11 @@ struct Cli {
     #[clap(short, long, default_value_t = AppendMode::No)]
     append: AppendMode,
 
+    /// Instead of creating a table, print the gases that already have AK
+    /// tables in this file, with the number of levels and bins for each.
+    #[clap(long, conflicts_with_all = ["ak_files", "gas"])]
+    list_gases: Option<PathBuf>,
+
     #[command(flatten)]
     verbosity: Verbosity<InfoLevel>,
 }
@@ -95,9 +117,11 @@ enum CliError {
     Input(String),
     #[error("An error occurred while opening the output file for writing: {0}")]
     OpenOutput(#[from] netcdf::Error),
+    #[error("An error occurred while opening the AK table file for reading: {0}")]
+    OpenInput(netcdf::Error),
     #[error("An averaging kernel table for {gas} already exists in the output file {}", .output_file.display())]
     ExistingAk { output_file: PathBuf, gas: String },
-    #[error("An error occurred while checking if the new AKs exist in the output file")]
+    #[error("An error occurred while checking which AKs exist in the table file")]
     AkCheck,
     #[error("An error occurred while reading an AK .all file")]
     ReadError,
@@ -108,6 +132,12 @@ enum CliError {
 }
 
 fn driver(clargs: Cli) -> error_stack::Result<(), CliError> {
+    if let Some(table_file) = &clargs.list_gases {
+        return print_existing_gases(table_file);
+    }
+
+    let ak_inputs = resolve_ak_gases(&clargs)?;
+
     let mut table_ds = match clargs.append {
         AppendMode::No => {
             log::info!(
@@ -127,7 +157,8 @@ fn driver(clargs: Cli) -> error_stack::Result<(), CliError> {
             "Checking if new gases conflict with existing AKs in {}",
             clargs.output.display()
         );
-        if let Err(e) = read_aks::check_existing_gases(&table_ds, &clargs.ak_files) {
+        let gases = ak_inputs.iter().map(|(_, gas)| gas).collect_vec();
+        if let Err(e) = read_aks::check_existing_gases(&table_ds, &gases) {
             if let Some(gas) = e.current_context().existing_ak_gas() {
                 let err = CliError::ExistingAk {
                     output_file: clargs.output.clone(),
@@ -142,16 +173,114 @@ fn driver(clargs: Cli) -> error_stack::Result<(), CliError> {
     }
 
     let mut all_gas_aks = HashMap::new();
-    for akall_file in clargs.ak_files.iter() {
-        log::info!("Reading AKs from {}", akall_file.display());
-        let gas = read_aks::gas_name_from_path(&akall_file).change_context(CliError::ReadError)?;
+    let mut all_gas_sources = HashMap::new();
+    for (akall_file, gas) in ak_inputs {
+        log::info!("Reading {gas} AKs from {}", akall_file.display());
         let aks = read_aks::read_akall_file(&akall_file).change_context(CliError::ReadError)?;
-        all_gas_aks.insert(gas, aks);
+        // A gas may have more than one file; make_ak_tables checks that they don't overlap.
+        all_gas_sources
+            .entry(gas.clone())
+            .or_insert_with(Vec::new)
+            .push(akall_file);
+        all_gas_aks.entry(gas).or_insert_with(Vec::new).push(aks);
     }
     let all_gas_tables =
         calc_aks::make_ak_tables(all_gas_aks).change_context(CliError::CalcError)?;
     log::info!("Writing AK tables to {}", clargs.output.display());
-    write_aks::write_aks_to_dset(&mut table_ds, &all_gas_tables, &clargs.append)
-        .change_context(CliError::WriteError)?;
+    write_aks::write_aks_to_dset(
+        &mut table_ds,
+        &all_gas_tables,
+        &all_gas_sources,
+        &program_version(),
+        &clargs.append,
+    )
+    .change_context(CliError::WriteError)?;
     Ok(())
 }
+
+/// Print the gases with AK tables in `table_file` without modifying it.
+fn print_existing_gases(table_file: &Path) -> error_stack::Result<(), CliError> {
+    let ds = netcdf::open(table_file).map_err(CliError::OpenInput)?;
+    let tables = read_aks::list_existing_gases(&ds).change_context(CliError::AkCheck)?;
+    if tables.is_empty() {
+        println!("No AK tables found in {}", table_file.display());
+        return Ok(());
+    }
+
+    println!("AK tables in {}:", table_file.display());
+    for table in tables {
+        println!(
+            "  {}: {} pressure levels, {} {} bins",
+            table.gas, table.n_levels, table.n_bins, table.bin_dim
+        );
+    }
+    Ok(())
+}
+
+/// Pair each input AK file with its gas, applying the --gas override if given.
+fn resolve_ak_gases(clargs: &Cli) -> error_stack::Result<Vec<(PathBuf, String)>, CliError> {
+    if clargs.gas.is_some() && clargs.ak_files.len() != 1 {
+        return Err(CliError::Input(format!(
+            "--gas can only be used with a single AK file, but {} were given; use FILE=GAS instead",
+            clargs.ak_files.len()
+        ))
+        .into());
+    }
+
+    if let (Some(gas), [input]) = (&clargs.gas, clargs.ak_files.as_slice()) {
+        if let Some(file_gas) = &input.gas {
+            return Err(CliError::Input(format!(
+                "--gas {gas} cannot be combined with {}={file_gas}; give the gas only once",
+                input.path.display()
+            ))
+            .into());
+        }
+    }
+
+    clargs
+        .ak_files
+        .iter()
+        .map(|input| {
+            let gas = if let Some(gas) = &clargs.gas {
+                gas.clone()
+            } else {
+                input.gas().change_context(CliError::ReadError)?
+            };
+            Ok((input.path.clone(), gas))
+        })
+        .collect()
+}
+
+fn program_version() -> ProgramVersion {
+    ProgramVersion {
+        program: "create_ak_table".to_string(),
+        version: "Version 1.0".to_string(),
+        date: "2025-01-01".to_string(),
+        authors: "JLL".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_ak_gases() {
+        let clargs = Cli::parse_from(["create_ak_table", "k0_co2_run=2.all", "renamed.txt=ch4"]);
+        let inputs = resolve_ak_gases(&clargs).unwrap();
+        assert_eq!(
+            inputs,
+            [
+                (PathBuf::from("k0_co2_run=2.all"), "co2".to_string()),
+                (PathBuf::from("renamed.txt"), "ch4".to_string())
+            ]
+        );
+
+        let clargs = Cli::parse_from(["create_ak_table", "--gas", "n2o", "renamed.txt"]);
+        let inputs = resolve_ak_gases(&clargs).unwrap();
+        assert_eq!(inputs, [(PathBuf::from("renamed.txt"), "n2o".to_string())]);
+
+        let clargs = Cli::parse_from(["create_ak_table", "--gas", "n2o", "renamed.txt=ch4"]);
+        assert!(resolve_ak_gases(&clargs).is_err());
+    }
+}
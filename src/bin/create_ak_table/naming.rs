@@ -5,3 +5,11 @@ pub(crate) static PRES_VAR_NAME: &'static str = "pressure";
 pub(crate) fn ak_varname(gas: &str) -> String {
     format!("x{gas}_aks")
 }
+
+/// The inverse of [`ak_varname`]: returns the gas if `varname` is an AK variable name.
+pub(crate) fn gas_from_ak_varname(varname: &str) -> Option<&str> {
+    varname
+        .strip_prefix('x')
+        .and_then(|s| s.strip_suffix("_aks"))
+        .filter(|gas| !gas.is_empty())
+}
@@ -1,4 +1,8 @@
-use std::{io::BufRead, path::Path};
+use std::{
+    io::BufRead,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
 use error_stack::ResultExt;
 use itertools::Itertools;
@@ -99,6 +103,56 @@ pub(crate) fn read_akall_file(path: &Path) -> error_stack::Result<Vec<AkInfo>, R
     Ok(aks)
 }
 
+/// One AK .all file given on the command line, optionally with the gas it is for.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct AkInput {
+    pub(crate) path: PathBuf,
+    pub(crate) gas: Option<String>,
+}
+
+impl AkInput {
+    /// The gas this file has AKs for: the one given explicitly, if any,
+    /// otherwise the one inferred from the file name.
+    pub(crate) fn gas(&self) -> error_stack::Result<String, ReadError> {
+        if let Some(gas) = &self.gas {
+            Ok(gas.clone())
+        } else {
+            gas_name_from_path(&self.path)
+        }
+    }
+}
+
+/// Gases that GGG retrieves and that may be given after the "=" in a "FILE=GAS" input.
+pub(crate) static KNOWN_AK_GASES: &[&str] = &[
+    "co2", "wco2", "lco2", "fco2", "zco2", "ch4", "n2o", "co", "h2o", "hdo", "hf", "hcl", "o2",
+    "o3", "luft", "nh3", "ocs", "c2h6",
+];
+
+impl FromStr for AkInput {
+    type Err = String;
+
+    /// Parse either "FILE" or "FILE=GAS". The text after the last "=" is only taken
+    /// as the gas if it is one of [`KNOWN_AK_GASES`]; otherwise the whole string is
+    /// the path, so that e.g. "k0_co2_run=2.all" is read as a file name.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.rsplit_once('=') {
+            Some((path, gas)) if KNOWN_AK_GASES.contains(&gas) => {
+                if path.is_empty() {
+                    return Err(format!("AK input '{s}' must be given as FILE or FILE=GAS"));
+                }
+                Ok(Self {
+                    path: PathBuf::from(path),
+                    gas: Some(gas.to_string()),
+                })
+            }
+            _ => Ok(Self {
+                path: PathBuf::from(s),
+                gas: None,
+            }),
+        }
+    }
+}
+
 pub(crate) fn gas_name_from_path(path: &Path) -> error_stack::Result<String, ReadError> {
     let base_name = path
         .file_name()
@@ -117,16 +171,114 @@ pub(crate) fn gas_name_from_path(path: &Path) -> error_stack::Result<String, Rea
     Ok(gas.to_string())
 }
 
-pub(crate) fn check_existing_gases<P: AsRef<Path>>(
+pub(crate) fn check_existing_gases<S: AsRef<str>>(
     ds: &netcdf::File,
-    ak_all_files: &[P],
+    gases: &[S],
 ) -> error_stack::Result<(), ReadError> {
-    for p in ak_all_files {
-        let gas = gas_name_from_path(p.as_ref())?;
-        let ak_varname = naming::ak_varname(&gas);
+    for gas in gases {
+        let ak_varname = naming::ak_varname(gas.as_ref());
         if ds.variable(&ak_varname).is_some() {
-            return Err(ReadError::ExistingAk(gas).into());
+            return Err(ReadError::ExistingAk(gas.as_ref().to_string()).into());
         }
     }
     Ok(())
 }
+
+/// An AK table already present in an output file, as reported by `--list-gases`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ExistingAkTable {
+    pub(crate) gas: String,
+    pub(crate) n_levels: usize,
+    pub(crate) n_bins: usize,
+    pub(crate) bin_dim: String,
+}
+
+/// List the gases that have AK tables in `ds`, along with the sizes of their level
+/// and bin dimensions, sorted by gas.
+pub(crate) fn list_existing_gases(
+    ds: &netcdf::File,
+) -> error_stack::Result<Vec<ExistingAkTable>, ReadError> {
+    let mut tables = vec![];
+    for var in ds.variables() {
+        let varname = var.name();
+        let Some(gas) = naming::gas_from_ak_varname(&varname) else {
+            continue;
+        };
+        let dims = var.dimensions();
+        if dims.len() != 2 || dims[0].name() != naming::Z_DIM_NAME {
+            return Err(ReadError::context(format!(
+                "reading the AK table '{varname}': expected dimensions ({}, <bin dimension>)",
+                naming::Z_DIM_NAME
+            ))
+            .into());
+        }
+        tables.push(ExistingAkTable {
+            gas: gas.to_string(),
+            n_levels: dims[0].len(),
+            n_bins: dims[1].len(),
+            bin_dim: dims[1].name(),
+        });
+    }
+    tables.sort_by(|a, b| a.gas.cmp(&b.gas));
+    Ok(tables)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_list_existing_gases() {
+        let output_dir = ggg_rs::test_utils::output_dir("create-ak-table-list-gases");
+        let nc_path = output_dir.join("ak-list-gases-test.nc");
+        {
+            let mut ds = netcdf::create(&nc_path).unwrap();
+            ds.add_dimension(naming::Z_DIM_NAME, 51).unwrap();
+            ds.add_dimension(naming::SZA_DIM_NAME, 18).unwrap();
+            ds.add_variable::<f64>(naming::PRES_VAR_NAME, &[naming::Z_DIM_NAME])
+                .unwrap();
+            for gas in ["n2o", "co2"] {
+                ds.add_variable::<f64>(
+                    &naming::ak_varname(gas),
+                    &[naming::Z_DIM_NAME, naming::SZA_DIM_NAME],
+                )
+                .unwrap();
+            }
+        }
+
+        let ds = netcdf::open(&nc_path).unwrap();
+        let tables = list_existing_gases(&ds).unwrap();
+
+        let gases = tables.iter().map(|t| t.gas.as_str()).collect_vec();
+        assert_eq!(gases, ["co2", "n2o"]);
+        assert!(tables
+            .iter()
+            .all(|t| t.n_levels == 51 && t.n_bins == 18 && t.bin_dim == naming::SZA_DIM_NAME));
+    }
+
+    #[test]
+    fn test_ak_input_gas() {
+        let inferred: AkInput = "aks/k0_co2_benchmark.all".parse().unwrap();
+        assert_eq!(inferred.path, PathBuf::from("aks/k0_co2_benchmark.all"));
+        assert_eq!(inferred.gas().unwrap(), "co2");
+
+        let explicit: AkInput = "aks/renamed_kernels.txt=ch4".parse().unwrap();
+        assert_eq!(explicit.path, PathBuf::from("aks/renamed_kernels.txt"));
+        assert_eq!(explicit.gas().unwrap(), "ch4");
+
+        // An "=" in a directory name is part of the path, not a gas
+        let in_dir: AkInput = format!("run=1{}k0_n2o_x.all", std::path::MAIN_SEPARATOR)
+            .parse()
+            .unwrap();
+        assert_eq!(in_dir.gas, None);
+        assert_eq!(in_dir.gas().unwrap(), "n2o");
+
+        // So is one before something that is not a gas
+        let in_name: AkInput = "k0_co2_run=2.all".parse().unwrap();
+        assert_eq!(in_name.path, PathBuf::from("k0_co2_run=2.all"));
+        assert_eq!(in_name.gas, None);
+        assert_eq!(in_name.gas().unwrap(), "co2");
+
+        assert!("=co2".parse::<AkInput>().is_err());
+    }
+}
@@ -1,4 +1,7 @@
+use std::{collections::HashMap, path::PathBuf};
+
 use error_stack::ResultExt;
+use ggg_rs::readers::ProgramVersion;
 use netcdf::Extents;
 
 use crate::{
@@ -11,6 +14,8 @@ use crate::{
 pub(crate) enum WriteError {
     #[error("An error occurred while {0}")]
     Context(String),
+    #[error("The SZA bins in the existing table file ({existing:?}) differ from those of the new AKs ({new:?})")]
+    BinMismatch { existing: Vec<f64>, new: Vec<f64> },
 }
 
 impl WriteError {
@@ -19,15 +24,25 @@ impl WriteError {
     }
 }
 
+/// Write the AK tables in `aks` to `ds`.
 ///
+/// `sources` maps each gas to the `.all` file(s) its AKs were read from; those paths,
+/// their modification times, and `version` are recorded as attributes on the gas's
+/// AK variable (comma-separated if there is more than one file). Gases already in
+/// `ds` are left untouched when appending, so their provenance attributes are preserved.
 pub(crate) fn write_aks_to_dset(
     ds: &mut netcdf::FileMut,
     aks: &AkTableSet,
+    sources: &HashMap<String, Vec<PathBuf>>,
+    version: &ProgramVersion,
     append_mode: &AppendMode,
 ) -> error_stack::Result<(), WriteError> {
     add_dims(ds, aks)?;
     for (gas, gas_aks) in aks.tables.iter() {
-        add_ak(ds, gas, gas_aks, append_mode)?;
+        let source = sources.get(gas).ok_or_else(|| {
+            WriteError::context(format!("looking up the source .all file for {gas}"))
+        })?;
+        add_ak(ds, gas, gas_aks, source, version, append_mode)?;
     }
     Ok(())
 }
@@ -75,6 +90,22 @@ fn add_dims(ds: &mut netcdf::FileMut, aks: &AkTableSet) -> error_stack::Result<(
             .change_context_lazy(|| WriteError::context("writing the pressure variable"))?;
     }
 
+    // When appending, the new tables must use the bins already in the file
+    if let Some(sza_var) = ds.variable(SZA_DIM_NAME) {
+        let existing = sza_var
+            .get_values::<f64, _>(Extents::All)
+            .change_context_lazy(|| WriteError::context("reading the existing SZA bins"))?;
+        let new = aks.sza_bin_centers.to_vec();
+        let same_bins = existing.len() == new.len()
+            && existing
+                .iter()
+                .zip(new.iter())
+                .all(|(a, b)| (a - b).abs() < 1e-6);
+        if !same_bins {
+            return Err(WriteError::BinMismatch { existing, new }.into());
+        }
+    }
+
     // Writing the SZA bin variable
     if ds.dimension(SZA_DIM_NAME).is_none() {
         ds.add_dimension(SZA_DIM_NAME, aks.sza_bin_centers.len())
@@ -107,6 +138,8 @@ fn add_ak(
     ds: &mut netcdf::FileMut,
     gas: &str,
     table: &AkTable,
+    source: &[PathBuf],
+    version: &ProgramVersion,
     append_mode: &AppendMode,
 ) -> error_stack::Result<(), WriteError> {
     let varname = ak_varname(gas);
@@ -132,7 +165,162 @@ fn add_ak(
                 "adding the 'description' attribute to the '{varname}' variable"
             ))
         })?;
+    add_provenance_attrs(&mut var, source, version).change_context_lazy(|| {
+        WriteError::context(format!(
+            "adding the provenance attributes to the '{varname}' variable"
+        ))
+    })?;
     var.put(table.aks.view(), Extents::All)
         .change_context_lazy(|| WriteError::context(format!("writing the '{varname}' variable")))?;
     Ok(())
 }
+
+fn add_provenance_attrs(
+    var: &mut netcdf::VariableMut,
+    sources: &[PathBuf],
+    version: &ProgramVersion,
+) -> error_stack::Result<(), WriteError> {
+    let mut source_paths = vec![];
+    let mut mtimes = vec![];
+    for source in sources {
+        // Canonicalizing is only for clarity in the attribute, so fall back on the
+        // path as given if it fails.
+        let source_path = source.canonicalize().unwrap_or_else(|_| source.clone());
+        source_paths.push(source_path.display().to_string());
+
+        let mtime = std::fs::metadata(source)
+            .and_then(|m| m.modified())
+            .change_context_lazy(|| {
+                WriteError::context(format!(
+                    "getting the modification time of {}",
+                    source.display()
+                ))
+            })?;
+        let mtime = chrono::DateTime::<chrono::Utc>::from(mtime);
+        mtimes.push(mtime.format("%Y-%m-%dT%H:%M:%SZ").to_string());
+    }
+
+    var.put_attribute("source_file", source_paths.join(", "))
+        .change_context_lazy(|| WriteError::context("adding the 'source_file' attribute"))?;
+    var.put_attribute("source_file_modification_time", mtimes.join(", "))
+        .change_context_lazy(|| {
+            WriteError::context("adding the 'source_file_modification_time' attribute")
+        })?;
+
+    var.put_attribute("created_by", version.to_string().trim_end())
+        .change_context_lazy(|| WriteError::context("adding the 'created_by' attribute"))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{Array1, Array2};
+
+    use super::*;
+    use crate::calc_aks::AkBinType;
+
+    #[test]
+    fn test_provenance_attributes() {
+        let output_dir = ggg_rs::test_utils::output_dir("create-ak-table-provenance");
+        let source = output_dir.join("k0_co2_provenance_test.all");
+        let nc_path = output_dir.join("ak-provenance-test.nc");
+        std::fs::write(&source, "placeholder").unwrap();
+
+        let mut tables = HashMap::new();
+        tables.insert(
+            "co2".to_string(),
+            AkTable {
+                bins: AkBinType::SZA,
+                aks: Array2::ones((3, 2)),
+            },
+        );
+        let aks = AkTableSet {
+            tables,
+            sza_bin_centers: Array1::from_vec(vec![2.5, 7.5]),
+            pressure: Array1::from_vec(vec![1000.0, 500.0, 100.0]),
+            altitude: Array1::from_vec(vec![0.0, 5.0, 15.0]),
+        };
+        let sources = HashMap::from([("co2".to_string(), vec![source.clone()])]);
+
+        {
+            let mut ds = netcdf::create(&nc_path).unwrap();
+            write_aks_to_dset(
+                &mut ds,
+                &aks,
+                &sources,
+                &crate::program_version(),
+                &AppendMode::No,
+            )
+            .unwrap();
+        }
+
+        let ds = netcdf::open(&nc_path).unwrap();
+        let var = ds.variable(&ak_varname("co2")).unwrap();
+        let get_str = |name: &str| -> String {
+            match var.attribute_value(name).unwrap().unwrap() {
+                netcdf::AttributeValue::Str(s) => s,
+                v => panic!("expected '{name}' to be a string attribute, got {v:?}"),
+            }
+        };
+        assert_eq!(
+            get_str("source_file"),
+            source.canonicalize().unwrap().display().to_string()
+        );
+        let mtime = get_str("source_file_modification_time");
+        assert!(
+            chrono::NaiveDateTime::parse_from_str(&mtime, "%Y-%m-%dT%H:%M:%SZ").is_ok(),
+            "unexpected modification time format: {mtime}"
+        );
+        assert!(get_str("created_by").starts_with("create_ak_table"));
+    }
+    #[test]
+    fn test_append_with_different_bins() {
+        let output_dir = ggg_rs::test_utils::output_dir("create-ak-table-append-bins");
+        let source = output_dir.join("k0_co2_append_test.all");
+        let nc_path = output_dir.join("ak-append-bins-test.nc");
+        std::fs::write(&source, "placeholder").unwrap();
+
+        let make_aks = |gas: &str, bin_centers: Vec<f64>| {
+            let tables = HashMap::from([(
+                gas.to_string(),
+                AkTable {
+                    bins: AkBinType::SZA,
+                    aks: Array2::ones((3, bin_centers.len())),
+                },
+            )]);
+            AkTableSet {
+                tables,
+                sza_bin_centers: Array1::from_vec(bin_centers),
+                pressure: Array1::from_vec(vec![1000.0, 500.0, 100.0]),
+                altitude: Array1::from_vec(vec![0.0, 5.0, 15.0]),
+            }
+        };
+        let sources = HashMap::from([
+            ("co2".to_string(), vec![source.clone()]),
+            ("ch4".to_string(), vec![source.clone()]),
+        ]);
+        let version = crate::program_version();
+
+        {
+            let mut ds = netcdf::create(&nc_path).unwrap();
+            let aks = make_aks("co2", vec![2.5, 7.5]);
+            write_aks_to_dset(&mut ds, &aks, &sources, &version, &AppendMode::No).unwrap();
+        }
+
+        let mut ds = netcdf::append(&nc_path).unwrap();
+        let aks = make_aks("ch4", vec![5.0, 10.0]);
+        let err =
+            write_aks_to_dset(&mut ds, &aks, &sources, &version, &AppendMode::Keep).unwrap_err();
+        assert!(
+            matches!(err.current_context(), WriteError::BinMismatch { existing, new }
+                if existing == &[2.5, 7.5] && new == &[5.0, 10.0]),
+            "{err:?}"
+        );
+        assert!(ds.variable(&ak_varname("ch4")).is_none());
+
+        // Tables on the same bins can still be appended
+        let aks = make_aks("ch4", vec![2.5, 7.5]);
+        write_aks_to_dset(&mut ds, &aks, &sources, &version, &AppendMode::Keep).unwrap();
+        assert!(ds.variable(&ak_varname("ch4")).is_some());
+    }
+}
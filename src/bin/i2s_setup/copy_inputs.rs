@@ -1,8 +1,14 @@
-use std::{collections::HashMap, fmt::Display, io::Write, path::Path, str::FromStr};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    io::Write,
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+};
 
 use error_stack::ResultExt;
 use ggg_rs::{
-    i2s::{iter_i2s_lines, I2SLineType, I2SVersion},
+    i2s::{iter_i2s_lines, split_catalog_row, I2SLineType, I2SVersion},
     utils,
 };
 
@@ -59,23 +65,96 @@ impl FromStr for ParamMap {
     }
 }
 
+/// How to handle header parameters that are paths when copying them between input files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum PathRebase {
+    /// Copy path parameters exactly as they appear in SRC_FILE.
+    #[default]
+    Verbatim,
+    /// Rewrite relative paths so that they point to the same location
+    /// relative to the directory of the output file. This assumes that I2S
+    /// is run from the directory containing each input file.
+    Relative,
+    /// Rewrite relative paths as absolute paths.
+    Absolute,
+}
+
+impl Display for PathRebase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PathRebase::Verbatim => write!(f, "verbatim"),
+            PathRebase::Relative => write!(f, "relative"),
+            PathRebase::Absolute => write!(f, "absolute"),
+        }
+    }
+}
+
+/// How to combine the catalog in SRC_FILE with the one in DEST_FILE.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum CatalogMode {
+    /// Replace the catalog in DEST_FILE with the one from SRC_FILE.
+    Replace,
+    /// Add all catalog rows from SRC_FILE after those in DEST_FILE.
+    Append,
+    /// Add catalog rows from SRC_FILE after those in DEST_FILE, skipping
+    /// any whose spectrum name is already in the catalog.
+    Dedup,
+}
+
+impl Display for CatalogMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CatalogMode::Replace => write!(f, "replace"),
+            CatalogMode::Append => write!(f, "append"),
+            CatalogMode::Dedup => write!(f, "dedup"),
+        }
+    }
+}
+
+/// Settings controlling how [`driver`] copies from SRC_FILE to DEST_FILE.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct CopyOptions {
+    pub(crate) src_i2s_version: I2SVersion,
+    pub(crate) dest_i2s_version: I2SVersion,
+    /// How to combine the catalogs, or `None` to leave DEST_FILE's catalog unchanged.
+    pub(crate) catalog_mode: Option<CatalogMode>,
+    pub(crate) rebase_paths: PathRebase,
+}
+
 pub(crate) fn driver(
     src_file: &Path,
     dest_file: &Path,
     output_cfg: utils::OutputOptCli,
     top_params: &[ParamMap],
-    src_i2s_version: I2SVersion,
-    dest_i2s_version: I2SVersion,
-    copy_catalog: bool,
+    opts: CopyOptions,
 ) -> error_stack::Result<(), CliError> {
+    let CopyOptions {
+        src_i2s_version,
+        dest_i2s_version,
+        catalog_mode,
+        rebase_paths,
+    } = opts;
     let mut writer = output_cfg
         .setup_output(dest_file)
         .change_context_lazy(|| CliError::WriteError(dest_file.to_path_buf()))?;
     let out_file = writer.output_path().to_path_buf();
-    let copy_params = load_params_to_copy(src_file, top_params, src_i2s_version, dest_i2s_version)?;
+    let mut copy_params =
+        load_params_to_copy(src_file, top_params, src_i2s_version, dest_i2s_version)?;
+
+    if rebase_paths != PathRebase::Verbatim {
+        let src_dir = abs_parent_dir(src_file)?;
+        let dest_dir = abs_parent_dir(&out_file)?;
+        for (param_num, line) in copy_params.iter_mut() {
+            if dest_i2s_version.param_is_path(*param_num) {
+                *line = rebase_path_line(line, &src_dir, &dest_dir, rebase_paths);
+            }
+        }
+    }
 
     let dest_iter = iter_i2s_lines(dest_file, dest_i2s_version)
         .change_context_lazy(|| CliError::ReadError(dest_file.to_path_buf()))?;
+    let mut dest_spectra = HashSet::new();
+    let mut ends_with_newline = true;
 
     for element in dest_iter {
         let (line_type, orig_line) =
@@ -85,15 +164,18 @@ pub(crate) fn driver(
             I2SLineType::HeaderParam(i) => copy_params.get(&i),
             I2SLineType::HeaderLine => None,
             I2SLineType::CatalogRow | I2SLineType::Other => {
-                // If we need to copy the catalog from SRC to DEST, then we need to break out of this
-                // loop, as it will copy the catalog of DEST
-                if copy_catalog {
+                // If we need to replace the catalog of DEST with the one from SRC, then we need to
+                // break out of this loop, as it will copy the catalog of DEST
+                if catalog_mode == Some(CatalogMode::Replace) {
                     break;
-                } else {
-                    None
                 }
+                if matches!(line_type, I2SLineType::CatalogRow) {
+                    dest_spectra.insert(catalog_spectrum_name(&orig_line).to_string());
+                }
+                None
             }
         };
+        ends_with_newline = orig_line.ends_with('\n');
 
         if let Some(new_line) = new_line {
             write!(&mut writer, "{new_line}")
@@ -106,20 +188,24 @@ pub(crate) fn driver(
 
     // This was cleaner to do as a separate loop; if we wanted to copy this in the main loop, we'd have to
     // have some way to change how much that loop iterates.
-    if copy_catalog {
-        let src_iter = iter_i2s_lines(src_file, src_i2s_version)
-            .change_context_lazy(|| CliError::ReadError(src_file.to_path_buf()))?;
-
-        for element in src_iter {
-            let (src_line_type, src_line) =
-                element.change_context_lazy(|| CliError::ReadError(src_file.to_path_buf()))?;
-            match src_line_type {
-                I2SLineType::HeaderParam(_) | I2SLineType::HeaderLine => (),
-                I2SLineType::CatalogRow | I2SLineType::Other => {
-                    write!(&mut writer, "{src_line}")
-                        .change_context_lazy(|| CliError::WriteError(out_file.to_path_buf()))?;
-                }
-            }
+    if let Some(mode) = catalog_mode {
+        if mode != CatalogMode::Replace && !ends_with_newline {
+            writeln!(&mut writer)
+                .change_context_lazy(|| CliError::WriteError(out_file.to_path_buf()))?;
+        }
+        let nskipped = copy_catalog(
+            src_file,
+            src_i2s_version,
+            mode,
+            &mut dest_spectra,
+            &mut writer,
+        )
+        .change_context_lazy(|| CliError::WriteError(out_file.to_path_buf()))?;
+        if nskipped > 0 {
+            eprintln!(
+                "Skipped {nskipped} catalog row(s) from {} with spectra already in the catalog",
+                src_file.display()
+            );
         }
     }
 
@@ -130,6 +216,57 @@ pub(crate) fn driver(
     Ok(())
 }
 
+/// Write the catalog from `src_file` to `writer`, returning the number of rows skipped.
+///
+/// For [`CatalogMode::Replace`], every line after the header of `src_file` is written, including
+/// comments. For the other modes, only catalog rows are written, since they will follow an existing
+/// catalog. With [`CatalogMode::Dedup`], rows whose spectrum name is already in `seen_spectra` are
+/// skipped; each row written is added to `seen_spectra`, so duplicates within `src_file` are also
+/// skipped.
+fn copy_catalog<W: Write>(
+    src_file: &Path,
+    src_i2s_version: I2SVersion,
+    mode: CatalogMode,
+    seen_spectra: &mut HashSet<String>,
+    writer: &mut W,
+) -> error_stack::Result<usize, CliError> {
+    let src_iter = iter_i2s_lines(src_file, src_i2s_version)
+        .change_context_lazy(|| CliError::ReadError(src_file.to_path_buf()))?;
+
+    let mut nskipped = 0;
+    for element in src_iter {
+        let (src_line_type, src_line) =
+            element.change_context_lazy(|| CliError::ReadError(src_file.to_path_buf()))?;
+        let keep = match (src_line_type, mode) {
+            (I2SLineType::HeaderParam(_) | I2SLineType::HeaderLine, _) => false,
+            (_, CatalogMode::Replace) => true,
+            (I2SLineType::Other, _) => false,
+            (I2SLineType::CatalogRow, CatalogMode::Append) => true,
+            (I2SLineType::CatalogRow, CatalogMode::Dedup) => {
+                let is_new = seen_spectra.insert(catalog_spectrum_name(&src_line).to_string());
+                if !is_new {
+                    nskipped += 1;
+                }
+                is_new
+            }
+        };
+
+        if keep {
+            write!(writer, "{src_line}").change_context(CliError::IoError)?;
+        }
+    }
+
+    Ok(nskipped)
+}
+
+/// Get the spectrum (i.e. interferogram) name from a catalog row, which is its first column.
+fn catalog_spectrum_name(line: &str) -> &str {
+    split_catalog_row(utils::remove_comment(line))
+        .first()
+        .copied()
+        .unwrap_or("")
+}
+
 fn load_params_to_copy(
     src_file: &Path,
     top_params: &[ParamMap],
@@ -176,3 +313,177 @@ fn get_output_param(top_params: &[ParamMap], line_type: I2SLineType) -> Option<u
 
     None
 }
+
+/// Get the absolute, lexically normalized directory containing `file`.
+fn abs_parent_dir(file: &Path) -> error_stack::Result<PathBuf, CliError> {
+    let abs_file = std::path::absolute(file).change_context_lazy(|| {
+        CliError::BadInput(format!("Could not make path {} absolute", file.display()))
+    })?;
+    let dir = abs_file.parent().unwrap_or(Path::new("/"));
+    Ok(normalize_path(dir))
+}
+
+/// Rebase the path value in an I2S parameter line from `src_dir` to `dest_dir`.
+///
+/// `line` is the full line from the input file, including any inline comment and
+/// trailing newline; both are preserved. Absolute paths are always left unchanged,
+/// as are lines without a value. Trailing slashes on directories are kept, since I2S
+/// concatenates directory paths and file names directly.
+fn rebase_path_line(line: &str, src_dir: &Path, dest_dir: &Path, mode: PathRebase) -> String {
+    let (value_part, comment_part) = match line.split_once(':') {
+        Some((v, c)) => (v, Some(c)),
+        None => (line, None),
+    };
+
+    let old_path = value_part.trim();
+    if old_path.is_empty() || Path::new(old_path).is_absolute() || mode == PathRebase::Verbatim {
+        return line.to_string();
+    }
+
+    let target = normalize_path(&src_dir.join(old_path));
+    let new_path = match mode {
+        PathRebase::Verbatim => unreachable!("verbatim paths returned early"),
+        PathRebase::Absolute => target,
+        PathRebase::Relative => relative_path(&target, dest_dir),
+    };
+
+    let mut new_path = new_path.to_string_lossy().to_string();
+    if old_path.ends_with('/') && !new_path.ends_with('/') {
+        new_path.push('/');
+    }
+
+    // Keep any whitespace around the value (and the newline if there is no comment)
+    // so that the inline comments stay roughly aligned.
+    let start = value_part.find(old_path).unwrap_or(0);
+    let end = start + old_path.len();
+    let mut new_line = format!("{}{new_path}{}", &value_part[..start], &value_part[end..]);
+    if let Some(comment) = comment_part {
+        new_line.push(':');
+        new_line.push_str(comment);
+    }
+    new_line
+}
+
+/// Lexically resolve "." and ".." components in a path without touching the file system.
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for comp in path.components() {
+        match comp {
+            Component::CurDir => (),
+            Component::ParentDir => {
+                out.pop();
+            }
+            _ => out.push(comp),
+        }
+    }
+    out
+}
+
+/// Compute the path to `target` relative to `base`; both must be absolute and normalized.
+fn relative_path(target: &Path, base: &Path) -> PathBuf {
+    let target_comps = target.components().collect::<Vec<_>>();
+    let base_comps = base.components().collect::<Vec<_>>();
+    let n_common = target_comps
+        .iter()
+        .zip(base_comps.iter())
+        .take_while(|(t, b)| t == b)
+        .count();
+
+    let mut rel = PathBuf::new();
+    for _ in n_common..base_comps.len() {
+        rel.push("..");
+    }
+    for comp in &target_comps[n_common..] {
+        rel.push(comp);
+    }
+
+    if rel.as_os_str().is_empty() {
+        PathBuf::from(".")
+    } else {
+        rel
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rebase_relative_dir_param() {
+        let src_dir = Path::new("/data/site/run1");
+        let dest_dir = Path::new("/data/site/run2/inputs");
+        let line = "./igms/                  : Path to interferograms\n";
+
+        let rel = rebase_path_line(line, src_dir, dest_dir, PathRebase::Relative);
+        assert_eq!(
+            rel,
+            "../../run1/igms/                  : Path to interferograms\n"
+        );
+
+        let abs = rebase_path_line(line, src_dir, dest_dir, PathRebase::Absolute);
+        assert_eq!(
+            abs,
+            "/data/site/run1/igms/                  : Path to interferograms\n"
+        );
+
+        let verbatim = rebase_path_line(line, src_dir, dest_dir, PathRebase::Verbatim);
+        assert_eq!(verbatim, line);
+    }
+
+    #[test]
+    fn test_copy_catalog_modes() {
+        let output_dir = ggg_rs::test_utils::output_dir("i2s-setup-copy-catalog");
+        let src_file = output_dir.join("i2s-copy-catalog-test.in");
+        let mut contents = String::new();
+        for param in 1..=I2SVersion::I2S2014.num_header_params() {
+            contents.push_str(&format!("value{param}   : parameter {param}\n"));
+        }
+        contents.push_str(":name\nxx20240101s0e00a.0001 2024 1 1 1\n");
+        contents.push_str(":xx20240101s0e00a.0002 2024 1 1 2\n");
+        contents.push_str("xx20240101s0e00a.0002 2024 1 1 2\n");
+        contents.push_str("xx20240101s0e00a.0002 2024 1 1 2\n");
+        std::fs::write(&src_file, contents).unwrap();
+
+        let run = |mode| {
+            let mut seen = HashSet::from(["xx20240101s0e00a.0001".to_string()]);
+            let mut out = vec![];
+            let nskipped =
+                copy_catalog(&src_file, I2SVersion::I2S2014, mode, &mut seen, &mut out).unwrap();
+            (String::from_utf8(out).unwrap(), nskipped)
+        };
+        let (replaced, _) = run(CatalogMode::Replace);
+        let (appended, nskip_append) = run(CatalogMode::Append);
+        let (deduped, nskip_dedup) = run(CatalogMode::Dedup);
+
+        assert_eq!(replaced.lines().count(), 4);
+        assert_eq!(appended.lines().count(), 3);
+        assert_eq!(nskip_append, 0);
+        assert_eq!(deduped, "xx20240101s0e00a.0002 2024 1 1 2\n");
+        assert_eq!(nskip_dedup, 2);
+    }
+
+    #[test]
+    fn test_catalog_spectrum_name() {
+        assert_eq!(
+            catalog_spectrum_name("xx20240101s0e00a.0001 2024 1 1 1\n"),
+            "xx20240101s0e00a.0001"
+        );
+        assert_eq!(
+            catalog_spectrum_name("\"xx20240101 s0e00a.0001\" 2024 1 1 1 : with a comment\n"),
+            "xx20240101 s0e00a.0001"
+        );
+        assert_eq!(catalog_spectrum_name(": only a comment\n"), "");
+    }
+
+    #[test]
+    fn test_rebase_keeps_absolute_param() {
+        let line = "/data/igms/\n";
+        let rel = rebase_path_line(
+            line,
+            Path::new("/data/run1"),
+            Path::new("/other"),
+            PathRebase::Relative,
+        );
+        assert_eq!(rel, line);
+    }
+}
@@ -0,0 +1,162 @@
+use std::path::Path;
+
+use error_stack::ResultExt;
+use ggg_rs::i2s::{iter_i2s_lines, I2SInputModifcations, I2SLineType, I2SVersion};
+use ggg_rs::utils::remove_comment;
+
+use crate::CliError;
+
+pub(crate) fn driver(
+    old_file: &Path,
+    new_file: &Path,
+    i2s_version: I2SVersion,
+    output_file: Option<&Path>,
+) -> error_stack::Result<(), CliError> {
+    let old = read_i2s_input(old_file, i2s_version)?;
+    let new = read_i2s_input(new_file, i2s_version)?;
+
+    if old.catalog != new.catalog {
+        eprintln!(
+            "Warning: the catalogs in {} and {} differ; only header parameter changes are included in the edit JSON.",
+            old_file.display(),
+            new_file.display()
+        );
+    }
+
+    let edits = header_edits(&old.params, &new.params);
+    let json = serde_json::to_string_pretty(&edits)
+        .expect("I2S input modifications should always serialize to JSON");
+
+    if let Some(out) = output_file {
+        std::fs::write(out, format!("{json}\n"))
+            .change_context_lazy(|| CliError::WriteError(out.to_path_buf()))?;
+    } else {
+        println!("{json}");
+    }
+
+    Ok(())
+}
+
+/// The header parameter values and catalog rows of an I2S input file, with comments removed.
+struct I2SInputContents {
+    params: Vec<String>,
+    catalog: Vec<String>,
+}
+
+fn read_i2s_input(
+    input_file: &Path,
+    i2s_version: I2SVersion,
+) -> error_stack::Result<I2SInputContents, CliError> {
+    let lines = iter_i2s_lines(input_file, i2s_version)
+        .change_context_lazy(|| CliError::ReadError(input_file.to_path_buf()))?;
+
+    let mut params = vec![];
+    let mut catalog = vec![];
+    for res in lines {
+        let (line_type, line) =
+            res.change_context_lazy(|| CliError::ReadError(input_file.to_path_buf()))?;
+        match line_type {
+            I2SLineType::HeaderParam(_) => params.push(param_value(&line)),
+            I2SLineType::CatalogRow => catalog.push(param_value(&line)),
+            I2SLineType::HeaderLine | I2SLineType::Other => (),
+        }
+    }
+
+    let nparams = i2s_version.num_header_params();
+    if params.len() < nparams {
+        return Err(CliError::BadInput(format!(
+            "{} has only {} of the {nparams} header parameters expected for I2S {i2s_version}",
+            input_file.display(),
+            params.len()
+        ))
+        .into());
+    }
+
+    Ok(I2SInputContents { params, catalog })
+}
+
+/// Get the value of a parameter from its line(s) in the input file.
+///
+/// Unlike the values yielded by [`ggg_rs::i2s::iter_i2s_header_params`], multi-line
+/// parameters keep a newline between each line, so that the value can be written back
+/// in place of the original lines.
+fn param_value(lines: &str) -> String {
+    let values: Vec<&str> = lines
+        .lines()
+        .map(|l| remove_comment(l).trim_end())
+        .filter(|v| !v.trim().is_empty())
+        .collect();
+    values.join("\n")
+}
+
+/// Create the edits needed to change the header parameters `old` into `new`. Both slices
+/// are in parameter order, starting with parameter #1.
+fn header_edits(old: &[String], new: &[String]) -> I2SInputModifcations {
+    let mut edits = I2SInputModifcations::default();
+    for (i, (old_value, new_value)) in old.iter().zip(new.iter()).enumerate() {
+        if old_value != new_value {
+            edits.set_parameter_change(i + 1, new_value.clone());
+        }
+    }
+    edits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modify_input::edit_i2s_input_file;
+    use ggg_rs::utils::OptInplaceWriter;
+
+    fn write_i2s_input(path: &Path, changed: &[(usize, &str)]) {
+        let mut contents = String::new();
+        for param in 1..=I2SVersion::I2S2020.num_header_params() {
+            let value = changed
+                .iter()
+                .find_map(|&(p, v)| (p == param).then(|| v.to_string()))
+                .unwrap_or_else(|| match param {
+                    17 => "0.005 0.005   : min values\n1.000 1.000".to_string(),
+                    _ => format!("value{param}"),
+                });
+            contents.push_str(&format!("{value}   : parameter {param}\n"));
+        }
+        contents.push_str(":Catalog\nxx20240101s0e00a.0001 2024 1 1 1\n");
+        std::fs::write(path, contents).unwrap();
+    }
+
+    #[test]
+    fn test_diff_round_trip() {
+        let output_dir = ggg_rs::test_utils::output_dir("i2s-setup-diff");
+        let old_file = output_dir.join("i2s-diff-test-old.in");
+        let new_file = output_dir.join("i2s-diff-test-new.in");
+        let edited_file = output_dir.join("i2s-diff-test-edited.in");
+        write_i2s_input(&old_file, &[]);
+        write_i2s_input(
+            &new_file,
+            &[
+                (1, "./igms/"),
+                (17, "0.010 0.010\n2.000 2.000"),
+                (20, "value20"),
+            ],
+        );
+
+        let old = read_i2s_input(&old_file, I2SVersion::I2S2020).unwrap();
+        let new = read_i2s_input(&new_file, I2SVersion::I2S2020).unwrap();
+        let edits = header_edits(&old.params, &new.params);
+        let found: Vec<_> = edits
+            .header
+            .iter()
+            .map(|e| (e.parameter, e.value.as_str()))
+            .collect();
+        assert_eq!(found, [(1, "./igms/"), (17, "0.010 0.010\n2.000 2.000")]);
+
+        // Applying the edits to the old file should give the same parameters as the new one
+        let json = serde_json::to_string(&edits).unwrap();
+        let edits: I2SInputModifcations = serde_json::from_str(&json).unwrap();
+        let writer = OptInplaceWriter::new_separate(edited_file.clone()).unwrap();
+        edit_i2s_input_file(&old_file, writer, I2SVersion::I2S2020, edits).unwrap();
+        let edited = read_i2s_input(&edited_file, I2SVersion::I2S2020).unwrap();
+
+        assert_eq!(edited.params, new.params);
+        assert_eq!(edited.catalog, old.catalog);
+    }
+}
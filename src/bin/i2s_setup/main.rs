@@ -5,21 +5,30 @@ use ggg_rs::{i2s, i2s::I2SVersion, utils};
 use merge_inputs::ParamWhitespaceEq;
 
 mod copy_inputs;
+mod diff;
 mod merge_inputs;
 mod modify_input;
+mod validate;
 
 fn main() -> ExitCode {
     let clargs = Cli::parse();
     let res = match clargs.command {
-        Commands::MergeInputs(args) => merge_inputs::driver(
-            &args.input_files,
-            &args.output_file,
-            args.i2s_version,
-            args.whitespace_eq,
-            args.skip_param_check,
-            args.edits_json.as_deref(),
-            args.top_edit,
-        ),
+        Commands::MergeInputs(args) => {
+            let opts = merge_inputs::MergeOptions {
+                i2s_version: args.i2s_version,
+                whitespace_method: args.whitespace_eq,
+                skip_check_params: args.skip_param_check,
+                report_all_mismatches: args.report_all_mismatches,
+                dry_run: args.dry_run,
+            };
+            merge_inputs::driver(
+                &args.input_files,
+                &args.output_file,
+                args.edits_json.as_deref(),
+                args.top_edit,
+                opts,
+            )
+        }
 
         Commands::ModifyInput(args) => modify_input::driver(
             args.input_file,
@@ -36,17 +45,36 @@ fn main() -> ExitCode {
                 );
             }
 
+            let catalog_mode = if args.copy_catalog {
+                Some(copy_inputs::CatalogMode::Replace)
+            } else {
+                args.catalog_mode
+            };
+
+            let opts = copy_inputs::CopyOptions {
+                src_i2s_version: args.src_i2s_version,
+                dest_i2s_version: args.dest_i2s_version,
+                catalog_mode,
+                rebase_paths: args.rebase_paths,
+            };
             copy_inputs::driver(
                 &args.src_file,
                 &args.dest_file,
                 args.outputs,
                 &args.top_param,
-                args.src_i2s_version,
-                args.dest_i2s_version,
-                args.copy_catalog,
+                opts,
             )
         }
 
+        Commands::Validate(args) => validate::driver(&args.input_file, args.i2s_version),
+
+        Commands::Diff(args) => diff::driver(
+            &args.old_file,
+            &args.new_file,
+            args.i2s_version,
+            args.output_file.as_deref(),
+        ),
+
         Commands::EditJsonExample => {
             println!("Here is an example of an I2S edit JSON:\n");
             println!("{}", i2s::I2SInputModifcations::example_json_string(true));
@@ -73,6 +101,8 @@ enum Commands {
     MergeInputs(MergeInputsCli),
     ModifyInput(ModifyInputCli),
     CopyInputs(CopyInputsCli),
+    Validate(ValidateCli),
+    Diff(DiffCli),
     /// Print an example of the JSON format used by modify-input
     EditJsonExample,
 }
@@ -118,6 +148,16 @@ struct MergeInputsCli {
     /// take precedence over the JSON values from --edits-json.
     #[clap(long, action=clap::ArgAction::Append)]
     top_edit: Vec<modify_input::HeaderEditCli>,
+
+    /// Instead of stopping at the first header parameter that differs between
+    /// input files, check all the files and print a table of every differing
+    /// parameter before exiting with an error.
+    #[clap(long)]
+    report_all_mismatches: bool,
+
+    /// Print the merged file to stdout instead of writing it to OUTPUT_FILE.
+    #[clap(long)]
+    dry_run: bool,
 }
 
 /// Modify parameters in an I2S input file.
@@ -181,9 +221,72 @@ struct CopyInputsCli {
     #[clap(short, long, action=clap::ArgAction::Append)]
     top_param: Vec<copy_inputs::ParamMap>,
 
-    /// Set this flag to copy the whole catalog from SRC_FILE to DEST_FILE
+    /// Set this flag to copy the whole catalog from SRC_FILE to DEST_FILE.
+    /// This is the same as --catalog-mode=replace.
     #[clap(short, long)]
     copy_catalog: bool,
+
+    /// How to combine the catalog of SRC_FILE with the catalog of DEST_FILE.
+    /// 'replace' replaces DEST_FILE's catalog with SRC_FILE's, 'append' adds
+    /// SRC_FILE's catalog rows after DEST_FILE's, and 'dedup' does the same
+    /// as 'append' but skips rows for spectra already in the catalog. If not
+    /// given, DEST_FILE's catalog is left unchanged.
+    #[clap(long, value_enum, conflicts_with = "copy_catalog")]
+    catalog_mode: Option<copy_inputs::CatalogMode>,
+
+    /// How to copy header parameters that are paths (e.g. the
+    /// interferogram directory). 'verbatim' copies them unchanged,
+    /// 'relative' rewrites relative paths to point to the same location
+    /// relative to the output file, and 'absolute' rewrites relative
+    /// paths as absolute paths. I2S resolves relative paths against the
+    /// directory it is run from, so rebasing assumes that I2S is run from
+    /// the directory containing SRC_FILE when using SRC_FILE, and from the
+    /// directory containing the output file when using that.
+    #[clap(long, value_enum, default_value_t = copy_inputs::PathRebase::default())]
+    rebase_paths: copy_inputs::PathRebase,
+}
+
+/// Check an I2S input file for problems before running I2S.
+///
+/// This checks that all header parameters are present, that the
+/// interferogram and spectrum directories and the frequency limits
+/// file exist, and that each catalog row is well-formed and refers
+/// to an existing interferogram. Relative paths are interpreted
+/// relative to the directory containing INPUT_FILE.
+#[derive(Debug, Args)]
+struct ValidateCli {
+    input_file: PathBuf,
+
+    /// Which I2S version this input file is for (needed to
+    /// determine the number of header parameters). Options are
+    /// '2014' or '2020'; '2020' is the default.
+    #[clap(short, long, default_value_t=I2SVersion::default())]
+    i2s_version: I2SVersion,
+}
+
+/// Compare two I2S input files and print the edit JSON that turns OLD_FILE into NEW_FILE.
+///
+/// The output can be given to modify-input or merge-inputs with --edits-json.
+/// Only header parameters are compared, since edit JSONs cannot change the
+/// catalog; a warning is printed if the catalogs differ. Comments are ignored.
+#[derive(Debug, Args)]
+struct DiffCli {
+    /// The I2S input file to start from.
+    old_file: PathBuf,
+
+    /// The I2S input file with the changes to capture.
+    new_file: PathBuf,
+
+    /// Which I2S version these input files are for (needed to
+    /// determine the number of header parameters). Options are
+    /// '2014' or '2020'; '2020' is the default.
+    #[clap(short, long, default_value_t=I2SVersion::default())]
+    i2s_version: I2SVersion,
+
+    /// Path to write the edit JSON to. If not given, it is printed
+    /// to stdout.
+    #[clap(short, long)]
+    output_file: Option<PathBuf>,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -202,6 +305,10 @@ enum CliError {
         v2: String,
         param: usize,
     },
+    #[error("{nparams} parameter(s) differ across {nfiles} input file(s) compared to the first one; see the table above")]
+    ParamMismatches { nparams: usize, nfiles: usize },
+    #[error("Found {0} error(s) in the I2S input file")]
+    ValidationFailed(usize),
     #[error("Error in arguments: {0}")]
     BadInput(String),
 }
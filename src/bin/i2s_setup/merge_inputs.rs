@@ -10,15 +10,33 @@ use itertools::Itertools;
 
 use crate::CliError;
 
+/// Settings controlling how [`driver`] checks and writes the merged file.
+#[derive(Debug, Clone)]
+pub(crate) struct MergeOptions {
+    pub(crate) i2s_version: I2SVersion,
+    pub(crate) whitespace_method: ParamWhitespaceEq,
+    /// Parameter numbers (1-based) that may differ between the input files.
+    pub(crate) skip_check_params: Vec<usize>,
+    /// Check every file and report all differing parameters, rather than stopping at the first.
+    pub(crate) report_all_mismatches: bool,
+    /// Print the merged file to stdout instead of writing it.
+    pub(crate) dry_run: bool,
+}
+
 pub(crate) fn driver(
     input_files: &[PathBuf],
     output_file: &Path,
-    i2s_version: I2SVersion,
-    whitespace_method: ParamWhitespaceEq,
-    mut skip_check_params: Vec<usize>,
     edits_json: Option<&Path>,
     cli_edits: Vec<crate::modify_input::HeaderEditCli>,
+    opts: MergeOptions,
 ) -> error_stack::Result<(), CliError> {
+    let MergeOptions {
+        i2s_version,
+        whitespace_method,
+        mut skip_check_params,
+        report_all_mismatches,
+        dry_run,
+    } = opts;
     let edits = crate::modify_input::edits_from_json_and_cli(edits_json, cli_edits)
         .change_context_lazy(|| {
             CliError::BadInput("Could not set up input file edits".to_string())
@@ -33,16 +51,40 @@ pub(crate) fn driver(
     }
 
     // Verify that all the input files have the same top parameters, except those we've said are okay to differ
-    check_inputs_match(
+    let mismatches = find_param_mismatches(
         input_files,
         i2s_version,
         whitespace_method,
         &skip_check_params,
+        !report_all_mismatches,
     )?;
+    if report_all_mismatches && !mismatches.is_empty() {
+        eprintln!("{}", mismatch_table(&input_files[0], &mismatches));
+        let nparams = mismatches.iter().map(|m| m.param).unique().count();
+        return Err(CliError::ParamMismatches {
+            nparams,
+            nfiles: mismatches.iter().map(|m| &m.file).unique().count(),
+        }
+        .into());
+    } else if let Some(m) = mismatches.into_iter().next() {
+        return Err(CliError::ParamMismatch {
+            f1: input_files[0].clone(),
+            v1: m.first_value,
+            f2: m.file,
+            v2: m.value,
+            param: m.param,
+        }
+        .into());
+    }
 
-    // Copy the header from the first file and the catalogs from all the files
-    let mut outf = std::fs::File::create(output_file)
-        .change_context_lazy(|| CliError::WriteError(output_file.to_path_buf()))?;
+    // Copy the header from the first file and the catalogs from all the files,
+    // making any requested edits to the header as we go.
+    let mut outf = if dry_run {
+        OptInplaceWriter::new_dry_run(output_file.to_path_buf())
+    } else {
+        OptInplaceWriter::new_separate(output_file.to_path_buf())
+            .change_context_lazy(|| CliError::WriteError(output_file.to_path_buf()))?
+    };
 
     for (ifile, input_path) in input_files.into_iter().enumerate() {
         let line_iter = iter_i2s_lines(&input_path, i2s_version)
@@ -51,6 +93,13 @@ pub(crate) fn driver(
         for line in line_iter {
             let (line_type, line) =
                 line.change_context_lazy(|| CliError::ReadError(input_path.to_path_buf()))?;
+            if ifile == 0 {
+                if let Some(new_line) = edits.change_line_opt(line_type) {
+                    writeln!(outf, "{new_line}")
+                        .change_context_lazy(|| CliError::WriteError(output_file.to_path_buf()))?;
+                    continue;
+                }
+            }
             if !line_type.is_header_line() || ifile == 0 {
                 write!(outf, "{line}")
                     .change_context_lazy(|| CliError::WriteError(output_file.to_path_buf()))?;
@@ -58,26 +107,35 @@ pub(crate) fn driver(
         }
     }
 
-    // If edits were requested, make them now.
-    outf.flush()
+    outf.finalize()
         .change_context_lazy(|| CliError::WriteError(output_file.to_path_buf()))?;
-
-    if edits.has_changes() {
-        let writer = OptInplaceWriter::new_in_place(output_file.to_path_buf())
-            .change_context_lazy(|| CliError::WriteError(output_file.to_path_buf()))?;
-        crate::modify_input::edit_i2s_input_file(&output_file, writer, i2s_version, edits)?;
-    }
     Ok(())
 }
 
-fn check_inputs_match(
+/// A header parameter whose value in one input file differs from the first input file.
+#[derive(Debug, Clone, PartialEq)]
+struct ParamMismatch {
+    param: usize,
+    file: PathBuf,
+    value: String,
+    first_value: String,
+}
+
+/// Placeholder value for a parameter that one of the files does not have.
+const MISSING_VALUE: &str = "--MISSING--";
+
+/// Compare the header parameters of each input file against the first one, returning
+/// the ones that differ. If `stop_at_first` is `true`, return as soon as one is found.
+fn find_param_mismatches(
     input_files: &[PathBuf],
     i2s_version: I2SVersion,
     whitespace_method: ParamWhitespaceEq,
     skip_params: &[usize],
-) -> error_stack::Result<(), CliError> {
+    stop_at_first: bool,
+) -> error_stack::Result<Vec<ParamMismatch>, CliError> {
+    let mut mismatches = vec![];
     if input_files.len() < 2 {
-        return Ok(());
+        return Ok(mismatches);
     }
 
     for i in 1..input_files.len() {
@@ -97,45 +155,66 @@ fn check_inputs_match(
                     if !skip_params.contains(&num1)
                         && !whitespace_method.params_eq(&val1, &val2, num1, i2s_version)
                     {
-                        return Err(CliError::ParamMismatch {
-                            f1: input_files[0].clone(),
-                            v1: val1,
-                            f2: input_files[1].clone(),
-                            v2: val2,
+                        mismatches.push(ParamMismatch {
                             param: num1,
-                        }
-                        .into());
+                            file: input_files[i].clone(),
+                            value: val2,
+                            first_value: val1,
+                        });
                     }
                 }
                 itertools::EitherOrBoth::Left(value) => {
                     let (num, val) = value
                         .change_context_lazy(|| CliError::ReadError(input_files[0].clone()))?;
-                    return Err(CliError::ParamMismatch {
-                        f1: input_files[0].clone(),
-                        v1: val,
-                        f2: input_files[i].clone(),
-                        v2: "--MISSING--".to_string(),
+                    mismatches.push(ParamMismatch {
                         param: num,
-                    }
-                    .into());
+                        file: input_files[i].clone(),
+                        value: MISSING_VALUE.to_string(),
+                        first_value: val,
+                    });
                 }
                 itertools::EitherOrBoth::Right(value) => {
                     let (num, val) = value
                         .change_context_lazy(|| CliError::ReadError(input_files[i].clone()))?;
-                    return Err(CliError::ParamMismatch {
-                        f1: input_files[0].clone(),
-                        v1: "--MISSING--".to_string(),
-                        f2: input_files[i].clone(),
-                        v2: val,
+                    mismatches.push(ParamMismatch {
                         param: num,
-                    }
-                    .into());
+                        file: input_files[i].clone(),
+                        value: val,
+                        first_value: MISSING_VALUE.to_string(),
+                    });
                 }
             }
+
+            if stop_at_first && !mismatches.is_empty() {
+                return Ok(mismatches);
+            }
         }
     }
 
-    Ok(())
+    Ok(mismatches)
+}
+
+/// Make a table of all the mismatched parameters, ordered by parameter number.
+fn mismatch_table(first_file: &Path, mismatches: &[ParamMismatch]) -> String {
+    let mut builder = tabled::builder::Builder::new();
+    builder.push_record([
+        "Param #".to_string(),
+        format!("Value in {}", first_file.display()),
+        "File".to_string(),
+        "Value in file".to_string(),
+    ]);
+    for m in mismatches.iter().sorted_by_key(|m| m.param) {
+        builder.push_record([
+            m.param.to_string(),
+            m.first_value.clone(),
+            m.file.display().to_string(),
+            m.value.clone(),
+        ]);
+    }
+
+    let mut table = builder.build();
+    table.with(tabled::settings::style::Style::psql());
+    table.to_string()
 }
 
 /// Parameter equality check
@@ -244,3 +323,77 @@ impl ParamWhitespaceEq {
         return !unsafe_param_nums.contains(&param_num);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_i2s_input(path: &Path, changed: &[(usize, &str)]) {
+        let mut contents = String::new();
+        for param in 1..=I2SVersion::I2S2014.num_header_params() {
+            let value = changed
+                .iter()
+                .find_map(|&(p, v)| (p == param).then(|| v.to_string()))
+                .unwrap_or_else(|| format!("value{param}"));
+            contents.push_str(&format!("{value}   : parameter {param}\n"));
+        }
+        std::fs::write(path, contents).unwrap();
+    }
+
+    #[test]
+    fn test_report_all_mismatches() {
+        let output_dir = ggg_rs::test_utils::output_dir("i2s-setup-mismatch");
+        let files = (0..3)
+            .map(|i| output_dir.join(format!("i2s-mismatch-test-{i}.in")))
+            .collect_vec();
+        write_i2s_input(&files[0], &[]);
+        write_i2s_input(&files[1], &[(3, "other3"), (10, "other10")]);
+        write_i2s_input(&files[2], &[(10, "another10")]);
+
+        let first_only = find_param_mismatches(
+            &files,
+            I2SVersion::I2S2014,
+            ParamWhitespaceEq::Default,
+            &[],
+            true,
+        )
+        .unwrap();
+        let all = find_param_mismatches(
+            &files,
+            I2SVersion::I2S2014,
+            ParamWhitespaceEq::Default,
+            &[],
+            false,
+        )
+        .unwrap();
+        let skipped = find_param_mismatches(
+            &files,
+            I2SVersion::I2S2014,
+            ParamWhitespaceEq::Default,
+            &[10],
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(first_only.len(), 1);
+        assert_eq!(first_only[0].param, 3);
+
+        let found = all
+            .iter()
+            .map(|m| (m.param, m.file.clone(), m.value.trim()))
+            .collect_vec();
+        assert_eq!(
+            found,
+            [
+                (3, files[1].clone(), "other3"),
+                (10, files[1].clone(), "other10"),
+                (10, files[2].clone(), "another10"),
+            ]
+        );
+        assert_eq!(skipped.len(), 1);
+
+        let table = mismatch_table(&files[0], &all);
+        assert!(table.contains("another10"), "{table}");
+        assert_eq!(table.lines().count(), 5, "{table}");
+    }
+}
@@ -0,0 +1,271 @@
+use std::fmt::Display;
+use std::path::Path;
+
+use error_stack::ResultExt;
+use ggg_rs::i2s::{iter_i2s_lines, split_catalog_row, I2SLineType, I2SVersion, OpusCatalogueEntry};
+use ggg_rs::utils::remove_comment;
+
+use crate::CliError;
+
+pub(crate) fn driver(
+    input_file: &Path,
+    i2s_version: I2SVersion,
+) -> error_stack::Result<(), CliError> {
+    let problems = validate_input_file(input_file, i2s_version)?;
+    let nerr = problems
+        .iter()
+        .filter(|p| p.severity == Severity::Error)
+        .count();
+
+    if problems.is_empty() {
+        println!("{} is valid for I2S {i2s_version}", input_file.display());
+        return Ok(());
+    }
+
+    for problem in problems.iter() {
+        println!("{problem}");
+    }
+
+    if nerr > 0 {
+        Err(CliError::ValidationFailed(nerr).into())
+    } else {
+        println!(
+            "{} has no errors, but see the warnings above",
+            input_file.display()
+        );
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Severity {
+    Error,
+    Warning,
+}
+
+/// One problem found in an I2S input file; `line` is the 1-based line number, if the
+/// problem can be tied to a single line.
+#[derive(Debug)]
+pub(crate) struct Problem {
+    pub(crate) severity: Severity,
+    pub(crate) line: Option<usize>,
+    pub(crate) message: String,
+}
+
+impl Problem {
+    fn error(line: Option<usize>, message: String) -> Self {
+        Self {
+            severity: Severity::Error,
+            line,
+            message,
+        }
+    }
+
+    fn warning(line: Option<usize>, message: String) -> Self {
+        Self {
+            severity: Severity::Warning,
+            line,
+            message,
+        }
+    }
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let sev = match self.severity {
+            Severity::Error => "ERROR",
+            Severity::Warning => "WARNING",
+        };
+        if let Some(line) = self.line {
+            write!(f, "{sev} (line {line}): {}", self.message)
+        } else {
+            write!(f, "{sev}: {}", self.message)
+        }
+    }
+}
+
+/// Check an I2S input file for problems that would stop or spoil an I2S run.
+///
+/// This checks that all the header parameters are present, that the interferogram and
+/// spectrum directories (#1 and #2) and the frequency limits file (#4) exist, that each
+/// catalog row has the expected columns, and that each interferogram in the catalog
+/// exists. Relative paths are interpreted relative to the directory containing `input_file`.
+pub(crate) fn validate_input_file(
+    input_file: &Path,
+    i2s_version: I2SVersion,
+) -> error_stack::Result<Vec<Problem>, CliError> {
+    let base_dir = input_file.parent().unwrap_or(Path::new("."));
+    let lines = iter_i2s_lines(input_file, i2s_version)
+        .change_context_lazy(|| CliError::ReadError(input_file.to_path_buf()))?;
+
+    let mut problems = vec![];
+    let mut last_param = 0;
+    let mut igram_dir = None;
+    let mut n_catalog_rows = 0;
+    let mut line_num = 1;
+
+    for res in lines {
+        let (line_type, line) =
+            res.change_context_lazy(|| CliError::ReadError(input_file.to_path_buf()))?;
+        let value = remove_comment(&line).trim();
+
+        match line_type {
+            I2SLineType::HeaderParam(param) => {
+                last_param = param;
+                if i2s_version.param_is_path(param) {
+                    let path = base_dir.join(value);
+                    if let Some(msg) = check_path_param(param, &path) {
+                        problems.push(Problem::error(Some(line_num), msg));
+                    } else if param == 1 {
+                        igram_dir = Some(path);
+                    }
+                }
+            }
+            I2SLineType::CatalogRow => {
+                n_catalog_rows += 1;
+                problems.extend(
+                    check_catalog_row(value, igram_dir.as_deref())
+                        .into_iter()
+                        .map(|msg| Problem::error(Some(line_num), msg)),
+                );
+            }
+            I2SLineType::HeaderLine | I2SLineType::Other => (),
+        }
+
+        line_num += line.matches('\n').count();
+    }
+
+    let nparams = i2s_version.num_header_params();
+    if last_param < nparams {
+        problems.push(Problem::error(
+            None,
+            format!("only {last_param} of the {nparams} header parameters are present"),
+        ));
+    } else if n_catalog_rows == 0 {
+        problems.push(Problem::warning(
+            None,
+            "the catalog is empty, I2S will not process any interferograms".to_string(),
+        ));
+    }
+
+    Ok(problems)
+}
+
+/// Check that path parameter `param` points to something that exists. Parameters #1 and
+/// #2 must be directories, all others must be files.
+fn check_path_param(param: usize, path: &Path) -> Option<String> {
+    let want_dir = param == 1 || param == 2;
+    if want_dir && !path.is_dir() {
+        Some(format!(
+            "parameter #{param} should be an existing directory, but {} is not",
+            path.display()
+        ))
+    } else if !want_dir && !path.is_file() {
+        Some(format!(
+            "parameter #{param} should be an existing file, but {} is not",
+            path.display()
+        ))
+    } else {
+        None
+    }
+}
+
+/// Check one catalog row, returning a message for each problem found. If `igram_dir` is
+/// given, the interferogram named in the row must exist in it.
+fn check_catalog_row(row: &str, igram_dir: Option<&Path>) -> Vec<String> {
+    let ncol = <OpusCatalogueEntry as tabled::Tabled>::LENGTH;
+    let fields = split_catalog_row(row);
+    if fields.len() != ncol {
+        return vec![format!(
+            "catalog row has {} columns, expected {ncol}",
+            fields.len()
+        )];
+    }
+
+    let mut msgs = vec![];
+    let name = fields[0];
+    let int_fields = ["year", "month", "day", "run"];
+    let mut ints = [0; 4];
+    for (i, (&field, col)) in fields[1..5].iter().zip(int_fields).enumerate() {
+        match field.parse::<i32>() {
+            Ok(v) => ints[i] = v,
+            Err(_) => msgs.push(format!("{name}: {col} '{field}' is not an integer")),
+        }
+    }
+    if msgs.is_empty() {
+        let [_, month, day, _] = ints;
+        if !(1..=12).contains(&month) {
+            msgs.push(format!("{name}: month {month} is out of range"));
+        }
+        if !(1..=31).contains(&day) {
+            msgs.push(format!("{name}: day {day} is out of range"));
+        }
+    }
+
+    for (icol, field) in fields.iter().enumerate().skip(5) {
+        if field.parse::<f64>().is_err() {
+            msgs.push(format!(
+                "{name}: column {} ('{field}') is not a number",
+                icol + 1
+            ));
+        }
+    }
+
+    if let Some(dir) = igram_dir {
+        let igram = dir.join(name);
+        if !igram.is_file() {
+            msgs.push(format!("interferogram {} does not exist", igram.display()));
+        }
+    }
+
+    msgs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_input_file() {
+        let output_dir = ggg_rs::test_utils::output_dir("i2s-setup-validate");
+        let igram_dir = output_dir.join("igms");
+        std::fs::create_dir_all(&igram_dir).unwrap();
+        std::fs::write(igram_dir.join("xx20240101s0e00a.0001"), "").unwrap();
+        std::fs::write(igram_dir.join("xx20240101 s0e00a.0004"), "").unwrap();
+        std::fs::write(output_dir.join("flimit.i2s"), "").unwrap();
+
+        let mut contents = String::new();
+        for param in 1..=I2SVersion::I2S2014.num_header_params() {
+            let value = match param {
+                1 => "./igms/".to_string(),
+                2 => "./spectra/".to_string(),
+                4 => "flimit.i2s".to_string(),
+                _ => format!("value{param}"),
+            };
+            contents.push_str(&format!("{value}   : parameter {param}\n"));
+        }
+        contents.push_str(":Catalog\n");
+        contents.push_str("xx20240101s0e00a.0001 2024 1 1 1 45.0 -90.0 0.2 20.0 990.0 30.0 21.0 990.0 50.0 200.0 0.5 1.5 180.0\n");
+        contents.push_str("xx20240101s0e00a.0002 2024 13 1 2 45.0 -90.0 0.2 20.0 990.0 30.0 21.0 990.0 50.0 200.0 0.5 1.5\n");
+        contents.push_str("xx20240101s0e00a.0003 2024 1 x 3 45.0 -90.0 0.2 20.0 990.0 30.0 21.0 990.0 50.0 200.0 0.5 1.5 nan\n");
+        // A quoted name with a space is one column
+        contents.push_str("\"xx20240101 s0e00a.0004\" 2024 1 1 4 45.0 -90.0 0.2 20.0 990.0 30.0 21.0 990.0 50.0 200.0 0.5 1.5 180.0\n");
+        let input_file = output_dir.join("opus-i2s.in");
+        std::fs::write(&input_file, contents).unwrap();
+
+        let problems = validate_input_file(&input_file, I2SVersion::I2S2014).unwrap();
+
+        let found: Vec<_> = problems
+            .iter()
+            .map(|p| (p.severity, p.line, p.message.as_str()))
+            .collect();
+        assert_eq!(found.len(), 4, "{found:?}");
+        assert_eq!(found[0].0, Severity::Error);
+        assert_eq!(found[0].1, Some(2));
+        assert!(found[0].2.starts_with("parameter #2"), "{found:?}");
+        assert_eq!(found[1].1, Some(30));
+        assert!(found[1].2.contains("has 17 columns"), "{found:?}");
+        assert!(found[2].2.contains("day 'x'"), "{found:?}");
+        assert!(found[3].2.contains("0003 does not exist"), "{found:?}");
+    }
+}
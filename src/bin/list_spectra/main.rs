@@ -2,7 +2,7 @@ use std::path::PathBuf;
 
 use clap::Parser;
 
-use ggg_rs::tccon::sort_spectra;
+use ggg_rs::tccon::sort_spectra::{self, SpectrumOrder};
 use glob::glob;
 use itertools::Itertools;
 
@@ -16,8 +16,17 @@ fn main() {
             .map(|s| PathBuf::from(s))
             .collect_vec()
     };
-    let spectra_names = sort_spectra::sort_spectrum_names(&spectra)
+    let order = if args.group_by_detector {
+        SpectrumOrder::DetectorThenScan
+    } else {
+        SpectrumOrder::ScanThenDetector
+    };
+    let mut spectra_names = sort_spectra::sort_spectrum_names_by(&spectra, order)
         .expect("Was not able to extract the base names of all given spectra");
+    if args.unique {
+        // The names are sorted, so any duplicates will be next to each other
+        spectra_names.dedup();
+    }
 
     for name in spectra_names {
         println!("{name}");
@@ -29,7 +38,8 @@ fn main() {
 /// This will print just the spectrum names (no leading paths) in alphanumeric
 /// order, except that the detector character is considered last. The result
 /// is a list of spectra where all detectors for scan .0001 are printed first,
-/// then .0002, and so on.
+/// then .0002, and so on. Spectra from different sites or dates are kept
+/// grouped, so each site and day is listed in full before the next.
 ///
 /// Example:
 ///
@@ -58,6 +68,16 @@ struct Cli {
     /// line arguments allowed), you will need to quote any glob patterns.
     #[clap(short = 'g', long)]
     expand_globs: bool,
+
+    /// Only print each spectrum name once, even if spectra with the same name
+    /// were found in more than one directory.
+    #[clap(short = 'u', long)]
+    unique: bool,
+
+    /// Print all of one detector's spectra before the next detector's (within
+    /// each site and date), rather than interleaving the detectors by scan.
+    #[clap(long)]
+    group_by_detector: bool,
 }
 
 fn expand_glob_patterns(patterns: &[String]) -> Result<Vec<PathBuf>, String> {
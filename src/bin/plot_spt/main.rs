@@ -4,8 +4,8 @@ use ggg_rs::{
     utils::{self, GggError},
 };
 use plotly::{
-    common::{Line, Mode, Title},
-    layout::Axis,
+    common::{DashType, Line, Mode, Title},
+    layout::{Annotation, Axis, Shape, ShapeLine, ShapeType},
     ImageFormat, Layout, Plot, Scatter,
 };
 use std::{
@@ -13,26 +13,26 @@ use std::{
     collections::{HashMap, HashSet},
     convert::Infallible,
     hash::Hash,
-    io::BufRead,
+    io::{BufRead, BufWriter, Write},
     path::{Path, PathBuf},
     str::FromStr,
 };
 
 struct SptData {
-    _header: SptHeader,
+    header: SptHeader,
     columns: Vec<String>,
     data: HashMap<String, Vec<f32>>,
 }
 
 struct SptHeader {
-    _first_freq: f32,
-    _last_freq: f32,
+    first_freq: f32,
+    last_freq: f32,
     _num_freq: usize,
     _effective_spec_resolution: f32,
     _sza: f32,
     _obs_alt: f32,
     _zmin: f32,
-    _fit_rms: f32,
+    fit_rms: f32,
     _effective_pressure: f32,
     _solar_disk_frac_obs: f32,
     zero_offset: f32,
@@ -53,14 +53,14 @@ impl SptHeader {
         }
 
         Ok(Self {
-            _first_freq: parse_field(split[0], 0, line)?,
-            _last_freq: parse_field(split[1], 1, line)?,
+            first_freq: parse_field(split[0], 0, line)?,
+            last_freq: parse_field(split[1], 1, line)?,
             _num_freq: parse_field(split[2], 2, line)?,
             _effective_spec_resolution: parse_field(split[3], 3, line)?,
             _sza: parse_field(split[4], 4, line)?,
             _obs_alt: parse_field(split[5], 5, line)?,
             _zmin: parse_field(split[6], 6, line)?,
-            _fit_rms: parse_field(split[7], 7, line)?,
+            fit_rms: parse_field(split[7], 7, line)?,
             _effective_pressure: parse_field(split[8], 8, line)?,
             _solar_disk_frac_obs: parse_field(split[9], 9, line)?,
             zero_offset: parse_field(split[10], 10, line)?,
@@ -68,7 +68,11 @@ impl SptHeader {
     }
 }
 
-fn read_spt_file(spt_file: &Path) -> Result<SptData, GggError> {
+/// Read an SPT file, converting Tm and Tc to transmittances using the continuum
+/// and zero offset unless `raw` is `true`. If the file has no Cont column and `raw`
+/// is `false`, Tm and Tc cannot be converted, so they are dropped with a warning
+/// instead of being mixed in with the other transmittances.
+fn read_spt_file(spt_file: &Path, raw: bool) -> Result<SptData, GggError> {
     let mut fbuf = utils::FileBuf::open(spt_file)?;
     let (nhead, ncol) = utils::get_nhead_ncol(&mut fbuf)?;
 
@@ -131,7 +135,9 @@ fn read_spt_file(spt_file: &Path) -> Result<SptData, GggError> {
     }
 
     // Finally convert the Tm and Tc to be compatible with the other transmittances (see GGG FAQs on the wiki)
-    if let Some(continuum) = data_map.remove("Cont") {
+    if raw {
+        // Leave Tm and Tc as they are in the file
+    } else if let Some(continuum) = data_map.remove("Cont") {
         if let Some(mut tm) = data_map.remove("Tm") {
             for (i, &c) in continuum.iter().enumerate() {
                 tm[i] = (tm[i] / c - header.zero_offset) / (1.0 - header.zero_offset);
@@ -148,12 +154,15 @@ fn read_spt_file(spt_file: &Path) -> Result<SptData, GggError> {
 
         // We had to remove continuum to avoid holding an immutable ref to the data map, so put it back
         data_map.insert("Cont".to_owned(), continuum);
-    } else {
-        eprintln!("Warning: cannot convert Tc and Tm to proper transmittances because the Cont (continuum) column could not be found");
+    } else if data_map.remove("Tm").is_some() | data_map.remove("Tc").is_some() {
+        eprintln!(
+            "Warning: {} has no Cont (continuum) column, so Tm and Tc cannot be converted to transmittances and will not be plotted. Use --raw to plot them unconverted.",
+            spt_file.display()
+        );
     }
 
     Ok(SptData {
-        _header: header,
+        header,
         columns: col_names.into_iter().map(|el| el.to_owned()).collect(),
         data: data_map,
     })
@@ -161,16 +170,536 @@ fn read_spt_file(spt_file: &Path) -> Result<SptData, GggError> {
 
 #[derive(Debug, Parser)]
 struct Cli {
-    /// Path to the SPT file to plot.
-    spt_file: PathBuf,
+    /// Path to the SPT file to plot. If more than one is given, they are overlaid
+    /// for comparison with one color per file. When both Tm and Tc are plotted,
+    /// the residuals (Tm - Tc) are shown in a second panel.
+    #[clap(required = true)]
+    spt_files: Vec<PathBuf>,
 
-    /// File to save the plot to. If not given, uses the same name as the SPT file plus ".png".
+    /// File to save the plot to. If not given, uses the same name as the (first) SPT file plus
+    /// the extension for --format (".png" or ".html").
     #[clap(short = 'o', long = "output-file")]
     output_file: Option<PathBuf>,
 
+    /// Only plot frequencies at or above this value (in cm-1). If below the first
+    /// frequency in the SPT file, the first frequency is used instead.
+    #[clap(long)]
+    freq_min: Option<f32>,
+
+    /// Only plot frequencies at or below this value (in cm-1). If above the last
+    /// frequency in the SPT file, the last frequency is used instead.
+    #[clap(long)]
+    freq_max: Option<f32>,
+
+    /// Plot Tm and Tc as they are in the SPT file, without converting them to
+    /// transmittances. Needed to plot Tm and Tc from files without a Cont column.
+    #[clap(long)]
+    raw: bool,
+
+    /// What kind of file to write the plot to.
+    #[clap(short = 'f', long, value_enum, default_value_t = OutputFormat::default())]
+    format: OutputFormat,
+
     /// Columns from the SPT file to plot. If omitted, all are plotted.
     #[clap(short = 'c', long = "columns", value_parser = comma_list, default_value = "")]
     columns: OptionalSet<String>, // HashSet was easier to parse to, when this was a Vec, clap expected the parser to return a String, not Vec<String>
+
+    /// Draw vertical lines at the edges and center of a retrieval window, given
+    /// as MIN:MAX in wavenumbers. May be repeated to mark multiple windows.
+    #[clap(long = "mark-window")]
+    mark_window: Vec<WindowMarker>,
+
+    /// Mark the retrieval window defined in a .ggg or .col file, in addition to any
+    /// given with --mark-window. Give the file as --mark-window-from-ggg=FILE, or
+    /// give just --mark-window-from-ggg to use the file matching the (first) SPT
+    /// file from its directory or the two above it. This finds the run directory's
+    /// files when the SPT files are in its spt/ or spt/<window>/ subdirectory.
+    #[clap(long = "mark-window-from-ggg", num_args = 0..=1, require_equals = true)]
+    mark_window_from_ggg: Option<Option<PathBuf>>,
+
+    /// Also write the frequency and each plotted trace (with Tm and Tc converted
+    /// to transmittances) to this path as a CSV file. Only allowed with a single
+    /// SPT file.
+    #[clap(long = "dump-csv")]
+    dump_csv: Option<PathBuf>,
+}
+
+/// The range of frequencies to plot, from --freq-min and --freq-max.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct FreqRange {
+    min: Option<f32>,
+    max: Option<f32>,
+}
+
+impl FreqRange {
+    fn from_cli(clargs: &Cli) -> Result<Self, GggError> {
+        if let (Some(min), Some(max)) = (clargs.freq_min, clargs.freq_max) {
+            if min >= max {
+                return Err(GggError::Custom(format!(
+                    "--freq-min ({min}) must be less than --freq-max ({max})"
+                )));
+            }
+        }
+        Ok(Self {
+            min: clargs.freq_min,
+            max: clargs.freq_max,
+        })
+    }
+
+    /// Remove the points outside this range from `freq` and each trace. Limits beyond the
+    /// data are clamped to the first or last frequency, with a warning naming `label`.
+    fn crop(&self, label: &str, freq: &mut Vec<f32>, traces: &mut [PlottedTrace]) {
+        let (Some(&first), Some(&last)) = (freq.first(), freq.last()) else {
+            return;
+        };
+        let (data_min, data_max) = (first.min(last), first.max(last));
+
+        let min = match self.min {
+            Some(min) if min < data_min => {
+                eprintln!("Warning: --freq-min {min} is below the first frequency in {label}, using {data_min}");
+                data_min
+            }
+            Some(min) if min > data_max => {
+                eprintln!("Warning: --freq-min {min} is above the last frequency in {label}, using {data_max}");
+                data_max
+            }
+            Some(min) => min,
+            None => data_min,
+        };
+        let max = match self.max {
+            Some(max) if max > data_max => {
+                eprintln!("Warning: --freq-max {max} is above the last frequency in {label}, using {data_max}");
+                data_max
+            }
+            Some(max) if max < data_min => {
+                eprintln!("Warning: --freq-max {max} is below the first frequency in {label}, using {data_min}");
+                data_min
+            }
+            Some(max) => max,
+            None => data_max,
+        };
+
+        let keep = freq
+            .iter()
+            .map(|&f| f >= min && f <= max)
+            .collect::<Vec<_>>();
+        let retain = |values: &mut Vec<f32>| {
+            let mut flags = keep.iter();
+            values.retain(|_| *flags.next().unwrap_or(&false));
+        };
+        retain(freq);
+        for trace in traces.iter_mut() {
+            retain(&mut trace.values);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+enum OutputFormat {
+    /// A static PNG image
+    #[default]
+    Png,
+    /// An interactive HTML page, which allows zooming and toggling traces
+    Html,
+}
+
+impl OutputFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Png => "png",
+            OutputFormat::Html => "html",
+        }
+    }
+
+    /// The output file to use when one is not given: the SPT file name with
+    /// this format's extension appended.
+    fn default_output_file(&self, spt_file: &Path) -> PathBuf {
+        let mut name = spt_file
+            .file_name()
+            .expect("Expecting input SPT file to have a path component after the final slash")
+            .to_owned();
+        name.push(".");
+        name.push(self.extension());
+        spt_file.with_file_name(name)
+    }
+}
+
+/// One column from the SPT file to draw, after any transmittance conversion.
+struct PlottedTrace {
+    column: String,
+    values: Vec<f32>,
+}
+
+impl PlottedTrace {
+    fn to_scatter(&self, freq: &[f32]) -> Box<Scatter<f32, f32>> {
+        match self.column.as_str() {
+            "Tm" => Scatter::new(freq.to_vec(), self.values.clone())
+                .name("Measured")
+                .mode(Mode::Lines)
+                .line(Line::new().color("black")),
+            "Tc" => Scatter::new(freq.to_vec(), self.values.clone())
+                .name("Total calc.")
+                .mode(Mode::Lines)
+                .line(Line::new().color("gray").dash(DashType::Dash)),
+            key => Scatter::new(freq.to_vec(), self.values.clone())
+                .name(key)
+                .mode(Mode::Lines),
+        }
+    }
+}
+
+/// Colors cycled through to distinguish files when comparing several SPT files.
+static COMPARISON_COLORS: [&str; 8] = [
+    "black", "red", "blue", "green", "orange", "purple", "brown", "magenta",
+];
+
+/// The traces read from one of several SPT files being compared.
+struct ComparedFile {
+    label: String,
+    fit_rms: f32,
+    freq: Vec<f32>,
+    traces: Vec<PlottedTrace>,
+}
+
+impl ComparedFile {
+    fn read(
+        spt_file: &Path,
+        columns: &OptionalSet<String>,
+        freq_range: FreqRange,
+        raw: bool,
+    ) -> Result<Self, GggError> {
+        let mut spt = read_spt_file(spt_file, raw)?;
+        let mut freq = spt.data.remove("Freq").ok_or_else(|| GggError::DataError {
+            path: spt_file.to_owned(),
+            cause: "Could not find the 'Freq' column".to_owned(),
+        })?;
+        let label = spt_file
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| spt_file.display().to_string());
+        let mut traces = take_plotted_traces(&mut spt, columns);
+        freq_range.crop(&label, &mut freq, &mut traces);
+        Ok(Self {
+            label,
+            fit_rms: spt.header.fit_rms,
+            freq,
+            traces,
+        })
+    }
+
+    fn residual(&self) -> Option<Vec<f32>> {
+        residual_values(&self.traces)
+    }
+
+    /// Add the traces for this file to `plot`, all in `color`. Tm is solid, Tc dashed,
+    /// and other columns dotted; the residual (if any) goes on the second y-axis.
+    fn add_to_plot(&self, plot: &mut Plot, color: &'static str) {
+        for trace in self.traces.iter() {
+            let (name, dash) = match trace.column.as_str() {
+                "Tm" => ("Measured", DashType::Solid),
+                "Tc" => ("Total calc.", DashType::Dash),
+                key => (key, DashType::Dot),
+            };
+            plot.add_trace(
+                Scatter::new(self.freq.clone(), trace.values.clone())
+                    .name(format!("{}: {name}", self.label))
+                    .legend_group(&self.label)
+                    .mode(Mode::Lines)
+                    .line(Line::new().color(color).dash(dash)),
+            );
+        }
+
+        if let Some(resid) = self.residual() {
+            plot.add_trace(
+                Scatter::new(self.freq.clone(), resid)
+                    .name(format!("{}: Tm - Tc (RMS {:.4})", self.label, self.fit_rms))
+                    .legend_group(&self.label)
+                    .mode(Mode::Lines)
+                    .y_axis("y2")
+                    .line(Line::new().color(color)),
+            );
+        }
+    }
+}
+
+/// The measured minus calculated transmittance, if both are plotted. Since the traces
+/// have already been converted to transmittances, this uses the converted Tm and Tc.
+fn residual_values(traces: &[PlottedTrace]) -> Option<Vec<f32>> {
+    let find = |column: &str| traces.iter().find(|t| t.column == column);
+    let tm = find("Tm")?;
+    let tc = find("Tc")?;
+    Some(
+        tm.values
+            .iter()
+            .zip(tc.values.iter())
+            .map(|(m, c)| m - c)
+            .collect(),
+    )
+}
+
+/// Split the figure into a transmittance panel on top and a residual panel below
+/// it; both share the x-axis. Residual traces must use the "y2" axis.
+fn with_residual_panel(layout: Layout) -> Layout {
+    layout
+        .y_axis(
+            Axis::new()
+                .title(Title::new("Transmittance (AU)"))
+                .domain(&[0.3, 1.0]),
+        )
+        .y_axis2(
+            Axis::new()
+                .title(Title::new("Tm - Tc"))
+                .domain(&[0.0, 0.25])
+                .anchor("x"),
+        )
+}
+
+/// Take the columns to plot out of `spt`, in the order they are drawn: Tm, Tc,
+/// then the remaining columns in file order.
+fn take_plotted_traces(spt: &mut SptData, columns: &OptionalSet<String>) -> Vec<PlottedTrace> {
+    let mut traces = Vec::new();
+
+    // Tm and Tc may be missing if they could not be converted to transmittances
+    if columns.contains("Tm") {
+        if let Some(tm) = spt.data.remove("Tm") {
+            traces.push(PlottedTrace {
+                column: "Tm".to_owned(),
+                values: tm,
+            });
+        }
+    }
+
+    if columns.contains("Tc") {
+        if let Some(tc) = spt.data.remove("Tc") {
+            traces.push(PlottedTrace {
+                column: "Tc".to_owned(),
+                values: tc,
+            });
+        }
+    }
+
+    // Iterate over columns to retain the order. Okay that Tm and Tc are in the columns; they are already removed from the
+    // HashMap, so the if let Some(_) check will skip them.
+    for key in spt.columns.iter() {
+        if !columns.contains(key) {
+            continue;
+        }
+
+        if let Some(values) = spt.data.remove(key) {
+            traces.push(PlottedTrace {
+                column: key.to_owned(),
+                values,
+            });
+        }
+    }
+
+    traces
+}
+
+fn write_traces_csv<W: Write>(
+    mut writer: W,
+    freq: &[f32],
+    traces: &[PlottedTrace],
+) -> std::io::Result<()> {
+    write!(writer, "Freq")?;
+    for trace in traces {
+        write!(writer, ",{}", trace.column)?;
+    }
+    writeln!(writer)?;
+
+    for (i, f) in freq.iter().enumerate() {
+        write!(writer, "{f}")?;
+        for trace in traces {
+            write!(writer, ",{}", trace.values[i])?;
+        }
+        writeln!(writer)?;
+    }
+    writer.flush()
+}
+
+fn dump_traces_csv(csv_file: &Path, freq: &[f32], traces: &[PlottedTrace]) -> Result<(), GggError> {
+    let f = std::fs::File::create(csv_file).map_err(|e| GggError::CouldNotOpen {
+        descr: "CSV output file".to_owned(),
+        path: csv_file.to_owned(),
+        reason: e.to_string(),
+    })?;
+    write_traces_csv(BufWriter::new(f), freq, traces).map_err(|e| GggError::CouldNotWrite {
+        path: csv_file.to_owned(),
+        reason: e.to_string(),
+    })
+}
+
+/// Whether a .col file has a row for `spectrum`. Files that cannot be read are
+/// treated as not having it.
+fn col_file_has_spectrum(col_file: &Path, spectrum: &str) -> bool {
+    std::fs::read_to_string(col_file).is_ok_and(|contents| {
+        contents
+            .lines()
+            .any(|line| line.split_ascii_whitespace().next() == Some(spectrum))
+    })
+}
+
+/// The edges of a retrieval window to mark on the plot.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct WindowMarker {
+    min: f32,
+    max: f32,
+}
+
+impl WindowMarker {
+    fn center(&self) -> f32 {
+        0.5 * (self.min + self.max)
+    }
+
+    /// Read the window from a .ggg file. The window is defined on the last line
+    /// with a colon, which begins with the center frequency and width.
+    fn from_ggg_file(ggg_file: &Path) -> Result<Self, GggError> {
+        let contents = std::fs::read_to_string(ggg_file).map_err(|e| GggError::CouldNotRead {
+            path: ggg_file.to_owned(),
+            reason: e.to_string(),
+        })?;
+
+        let window_line = contents
+            .lines()
+            .rev()
+            .find(|l| l.contains(':'))
+            .ok_or_else(|| GggError::DataError {
+                path: ggg_file.to_owned(),
+                cause: "Could not find the window definition line".to_owned(),
+            })?;
+
+        let mut fields = window_line.split_ascii_whitespace();
+        let mut next_value = |name: &str| -> Result<f32, GggError> {
+            fields
+                .next()
+                .and_then(|s| s.parse::<f32>().ok())
+                .ok_or_else(|| GggError::DataError {
+                    path: ggg_file.to_owned(),
+                    cause: format!("Could not parse the window {name} from line '{window_line}'"),
+                })
+        };
+        let center = next_value("center")?;
+        let width = next_value("width")?;
+        Ok(Self {
+            min: center - 0.5 * width,
+            max: center + 0.5 * width,
+        })
+    }
+
+    /// Find the window of an SPT file from the .col or .ggg files of the run that wrote it.
+    ///
+    /// These are looked for in the SPT file's directory and the two above it. A .col file
+    /// only matches if it lists the spectrum (the SPT file name without its leading "z").
+    /// Of the files whose window contains the middle of the SPT file's frequencies, the
+    /// one whose window center is closest to it is used.
+    fn find_for_spt(spt_file: &Path, header: &SptHeader) -> Result<Self, GggError> {
+        let spt_name = spt_file
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let spectrum = spt_name.strip_prefix('z').unwrap_or(&spt_name);
+        let spt_center = 0.5 * (header.first_freq + header.last_freq);
+
+        let mut best: Option<(f32, Self)> = None;
+        for dir in spt_file.ancestors().skip(1).take(3) {
+            let dir = if dir.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                dir
+            };
+            let Ok(entries) = dir.read_dir() else {
+                continue;
+            };
+
+            for path in entries.filter_map(|e| e.ok().map(|e| e.path())) {
+                let is_col_file = match path.extension().and_then(|ext| ext.to_str()) {
+                    Some("col") => true,
+                    Some("ggg") => false,
+                    _ => continue,
+                };
+                if is_col_file && !col_file_has_spectrum(&path, spectrum) {
+                    continue;
+                }
+                // Other files that happen to have a .ggg or .col extension are not a problem
+                let Ok(window) = Self::from_ggg_file(&path) else {
+                    continue;
+                };
+                if spt_center < window.min || spt_center > window.max {
+                    continue;
+                }
+                let distance = (window.center() - spt_center).abs();
+                if best.as_ref().is_none_or(|&(d, _)| distance < d) {
+                    best = Some((distance, window));
+                }
+            }
+        }
+
+        best.map(|(_, window)| window)
+            .ok_or_else(|| GggError::DataError {
+                path: spt_file.to_owned(),
+                cause: "Could not find a .col or .ggg file with a window matching this SPT file in its directory or the two above it; give the file with --mark-window-from-ggg=FILE instead".to_owned(),
+            })
+    }
+
+    fn add_to_layout(&self, layout: &mut Layout) {
+        let markers = [
+            (self.min, format!("{:.2}", self.min), DashType::Dash),
+            (
+                self.center(),
+                format!("center {:.2}", self.center()),
+                DashType::Dot,
+            ),
+            (self.max, format!("{:.2}", self.max), DashType::Dash),
+        ];
+
+        for (freq, label, dash) in markers {
+            let freq = freq as f64;
+            layout.add_shape(
+                Shape::new()
+                    .shape_type(ShapeType::Line)
+                    .x_ref("x")
+                    .y_ref("paper")
+                    .x0(freq)
+                    .x1(freq)
+                    .y0(0.0)
+                    .y1(1.0)
+                    .line(ShapeLine::new().color("red").dash(dash)),
+            );
+            layout.add_annotation(
+                Annotation::new()
+                    .x_ref("x")
+                    .y_ref("paper")
+                    .x(freq)
+                    .y(1.0)
+                    .text(label)
+                    .show_arrow(false),
+            );
+        }
+    }
+}
+
+impl FromStr for WindowMarker {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (min, max) = s
+            .split_once(':')
+            .ok_or_else(|| format!("Window '{s}' must be given as MIN:MAX"))?;
+        let min = min
+            .trim()
+            .parse::<f32>()
+            .map_err(|e| format!("Could not parse window minimum '{min}': {e}"))?;
+        let max = max
+            .trim()
+            .parse::<f32>()
+            .map_err(|e| format!("Could not parse window maximum '{max}': {e}"))?;
+        if min >= max {
+            return Err(format!(
+                "Window minimum ({min}) must be less than its maximum ({max})"
+            ));
+        }
+        Ok(Self { min, max })
+    }
 }
 
 fn comma_list(arg: &str) -> Result<OptionalSet<String>, Infallible> {
@@ -209,71 +738,435 @@ where
 
 fn main() -> Result<(), GggError> {
     let clargs = Cli::parse();
-    let mut spt = read_spt_file(&clargs.spt_file)?;
+    let output_file = clargs
+        .output_file
+        .clone()
+        .unwrap_or_else(|| clargs.format.default_output_file(&clargs.spt_files[0]));
+
+    let (mut plot, mut layout) = if clargs.spt_files.len() == 1 {
+        plot_single_file(&clargs)?
+    } else {
+        plot_comparison(&clargs)?
+    };
 
-    let spt_basename = clargs
-        .spt_file
+    let mut windows = clargs.mark_window;
+    match &clargs.mark_window_from_ggg {
+        Some(Some(ggg_file)) => windows.push(WindowMarker::from_ggg_file(ggg_file)?),
+        Some(None) => {
+            let spt_file = &clargs.spt_files[0];
+            let header = read_spt_file(spt_file, true)?.header;
+            windows.push(WindowMarker::find_for_spt(spt_file, &header)?);
+        }
+        None => (),
+    }
+    for window in windows {
+        window.add_to_layout(&mut layout);
+    }
+    plot.set_layout(layout);
+
+    match clargs.format {
+        OutputFormat::Png => plot.write_image(&output_file, ImageFormat::PNG, 2400, 600, 1.0),
+        OutputFormat::Html => plot.write_html(&output_file),
+    }
+    Ok(())
+}
+
+fn plot_single_file(clargs: &Cli) -> Result<(Plot, Layout), GggError> {
+    let spt_file = &clargs.spt_files[0];
+    let mut spt = read_spt_file(spt_file, clargs.raw)?;
+    let spt_basename = spt_file
         .file_name()
         .expect("Expecting input SPT file to have a path component after the final slash");
-    let output_file = clargs.output_file.unwrap_or_else(|| {
-        let mut tmp = spt_basename.to_owned();
-        tmp.push(".png");
-        clargs.spt_file.with_file_name(tmp)
-    });
-
-    let freq = spt.data.remove("Freq").ok_or_else(|| GggError::DataError {
-        path: clargs.spt_file.clone(),
+
+    let mut freq = spt.data.remove("Freq").ok_or_else(|| GggError::DataError {
+        path: spt_file.clone(),
         cause: "Could not find the 'Freq' column".to_owned(),
     })?;
 
+    let mut traces = take_plotted_traces(&mut spt, &clargs.columns);
+    FreqRange::from_cli(clargs)?.crop(&spt_basename.to_string_lossy(), &mut freq, &mut traces);
+    if let Some(csv_file) = &clargs.dump_csv {
+        dump_traces_csv(csv_file, &freq, &traces)?;
+    }
+
     let mut plot = Plot::new();
+    for trace in traces.iter() {
+        plot.add_trace(trace.to_scatter(&freq));
+    }
 
-    if clargs.columns.contains("Tm") {
-        let tm = spt.data.remove("Tm").unwrap();
-        let trace = Scatter::new(freq.clone(), tm)
-            .name("Measured")
-            .mode(Mode::Lines);
-        let trace = trace.line(Line::new().color("black"));
-        plot.add_trace(trace);
-    }
-
-    if clargs.columns.contains("Tc") {
-        let tc = spt
-            .data
-            .remove("Tc")
-            .expect("Did not find the Tc column in the spt file");
-        let trace = Scatter::new(freq.clone(), tc)
-            .name("Total calc.")
-            .mode(Mode::Lines);
-        let trace = trace.line(
-            Line::new()
-                .color("gray")
-                .dash(plotly::common::DashType::Dash),
+    let title = format!(
+        "{} (fit RMS = {:.4})",
+        spt_basename.to_string_lossy(),
+        spt.header.fit_rms
+    );
+    let layout = Layout::new()
+        .title(Title::new(&title))
+        .x_axis(Axis::new().title(Title::new("Frequency (cm-1)")));
+
+    let layout = if let Some(resid) = residual_values(&traces) {
+        plot.add_trace(
+            Scatter::new(freq, resid)
+                .name("Tm - Tc")
+                .mode(Mode::Lines)
+                .y_axis("y2")
+                .line(Line::new().color("black")),
         );
-        plot.add_trace(trace);
+        with_residual_panel(layout)
+    } else {
+        layout.y_axis(Axis::new().title(Title::new("Transmittance (AU)")))
+    };
+    Ok((plot, layout))
+}
+
+fn plot_comparison(clargs: &Cli) -> Result<(Plot, Layout), GggError> {
+    if let Some(csv_file) = &clargs.dump_csv {
+        return Err(GggError::CouldNotWrite {
+            path: csv_file.to_owned(),
+            reason: "--dump-csv can only be used with a single SPT file".to_owned(),
+        });
     }
 
-    // Iterate over columns to retain the order. Okay that Tm and Tc are in the columns; they are already removed from the
-    // HashMap, so the if let Some(_) check will skip them.
-    for key in spt.columns.iter() {
-        if !clargs.columns.contains(key) {
-            continue;
+    let freq_range = FreqRange::from_cli(clargs)?;
+    let files = clargs
+        .spt_files
+        .iter()
+        .map(|f| ComparedFile::read(f, &clargs.columns, freq_range, clargs.raw))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut plot = Plot::new();
+    let mut any_residuals = false;
+    for (file, color) in files.iter().zip(COMPARISON_COLORS.iter().cycle()) {
+        any_residuals |= file.residual().is_some();
+        file.add_to_plot(&mut plot, color);
+    }
+
+    let title = files.iter().map(|f| f.label.as_str()).collect::<Vec<_>>();
+    let mut layout = Layout::new()
+        .title(Title::new(&title.join(" vs. ")))
+        .x_axis(Axis::new().title(Title::new("Frequency (cm-1)")));
+    if any_residuals {
+        layout = with_residual_panel(layout);
+    } else {
+        layout = layout.y_axis(Axis::new().title(Title::new("Transmittance (AU)")));
+    }
+    Ok((plot, layout))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv_matches_plotted_traces() {
+        let output_dir = ggg_rs::test_utils::output_dir("plot-spt-csv");
+        let spt_file = output_dir.join("plot-spt-csv-test.spt");
+        std::fs::write(
+            &spt_file,
+            " 3 6\n\
+             4000.0 4000.2 3 0.01 30.0 0.1 0.1 0.5 1.0 1.0 0.02\n\
+             Freq Tm Tc Cont co2 h2o\n\
+             4000.0 0.90 0.88 0.95 0.97 0.99\n\
+             4000.1 0.80 0.81 0.96 0.93 0.98\n\
+             4000.2 0.70 0.72 0.97 0.91 0.97\n",
+        )
+        .unwrap();
+
+        let mut spt = read_spt_file(&spt_file, false).unwrap();
+        let freq = spt.data.remove("Freq").unwrap();
+        let columns = comma_list("co2,Tc,Tm").unwrap();
+        let traces = take_plotted_traces(&mut spt, &columns);
+
+        let mut buf = Vec::new();
+        write_traces_csv(&mut buf, &freq, &traces).unwrap();
+        let csv = String::from_utf8(buf).unwrap();
+        let mut lines = csv.lines();
+
+        assert_eq!(lines.next(), Some("Freq,Tm,Tc,co2"));
+        for (i, line) in lines.enumerate() {
+            let values: Vec<f32> = line.split(',').map(|v| v.parse().unwrap()).collect();
+            assert_eq!(values[0], freq[i]);
+            for (j, trace) in traces.iter().enumerate() {
+                assert_eq!(values[j + 1], trace.values[i]);
+            }
         }
 
-        if let Some(value) = spt.data.remove(key) {
-            let trace = Scatter::new(freq.clone(), value)
-                .name(key)
-                .mode(Mode::Lines);
-            plot.add_trace(trace);
+        // Tm should have been converted using the continuum and zero offset
+        let expected_tm = (0.90f32 / 0.95 - 0.02) / (1.0 - 0.02);
+        approx::assert_abs_diff_eq!(traces[0].values[0], expected_tm);
+
+        // and the residual should use the converted Tm and Tc
+        assert_eq!(spt.header.fit_rms, 0.5);
+        let expected_tc = (0.88f32 / 0.95 - 0.02) / (1.0 - 0.02);
+        let resid = residual_values(&traces).unwrap();
+        approx::assert_abs_diff_eq!(resid[0], expected_tm - expected_tc);
+    }
+
+    #[test]
+    fn test_spt_without_continuum() {
+        let spt_file =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test-data/inputs/plot-spt/no_cont.spt");
+        let all_columns = comma_list("").unwrap();
+
+        // Without the continuum, Tm and Tc can't be converted, so they should be dropped
+        let mut spt = read_spt_file(&spt_file, false).unwrap();
+        spt.data.remove("Freq");
+        let traces = take_plotted_traces(&mut spt, &all_columns);
+        let columns = traces.iter().map(|t| t.column.as_str()).collect::<Vec<_>>();
+        assert_eq!(columns, ["co2", "h2o"]);
+
+        // unless we ask for them unconverted
+        let mut spt = read_spt_file(&spt_file, true).unwrap();
+        spt.data.remove("Freq");
+        let traces = take_plotted_traces(&mut spt, &all_columns);
+        let columns = traces.iter().map(|t| t.column.as_str()).collect::<Vec<_>>();
+        assert_eq!(columns, ["Tm", "Tc", "co2", "h2o"]);
+        assert_eq!(traces[0].values, [1250.0, 1100.0, 980.0]);
+    }
+
+    #[test]
+    fn test_freq_range_crop() {
+        let make_traces = || {
+            vec![PlottedTrace {
+                column: "Tm".to_owned(),
+                values: vec![0.9, 0.8, 0.7, 0.6],
+            }]
+        };
+        let all_freq = vec![4000.0, 4000.1, 4000.2, 4000.3];
+
+        let range = FreqRange {
+            min: Some(4000.05),
+            max: Some(4000.25),
+        };
+        let (mut freq, mut traces) = (all_freq.clone(), make_traces());
+        range.crop("test", &mut freq, &mut traces);
+        assert_eq!(freq, [4000.1, 4000.2]);
+        assert_eq!(traces[0].values, [0.8, 0.7]);
+
+        // Limits outside the data clamp to its edges rather than removing everything
+        let range = FreqRange {
+            min: Some(3000.0),
+            max: Some(4000.1),
+        };
+        let (mut freq, mut traces) = (all_freq.clone(), make_traces());
+        range.crop("test", &mut freq, &mut traces);
+        assert_eq!(freq, [4000.0, 4000.1]);
+        assert_eq!(traces[0].values, [0.9, 0.8]);
+
+        let range = FreqRange {
+            min: Some(5000.0),
+            max: None,
+        };
+        let (mut freq, mut traces) = (all_freq, make_traces());
+        range.crop("test", &mut freq, &mut traces);
+        assert_eq!(freq, [4000.3]);
+        assert_eq!(traces[0].values, [0.6]);
+    }
+
+    #[test]
+    fn test_default_output_file() {
+        let spt_file = PathBuf::from("/data/spt/za2013.0101.abc.spt");
+        assert_eq!(
+            OutputFormat::Png.default_output_file(&spt_file),
+            PathBuf::from("/data/spt/za2013.0101.abc.spt.png")
+        );
+        assert_eq!(
+            OutputFormat::Html.default_output_file(&spt_file),
+            PathBuf::from("/data/spt/za2013.0101.abc.spt.html")
+        );
+    }
+
+    #[test]
+    fn test_window_marker_from_str() {
+        let marker: WindowMarker = "6180.0:6260.0".parse().unwrap();
+        assert_eq!(
+            marker,
+            WindowMarker {
+                min: 6180.0,
+                max: 6260.0
+            }
+        );
+        assert_eq!(marker.center(), 6220.0);
+        let marker: WindowMarker = " 6180 : 6260 ".parse().unwrap();
+        assert_eq!(marker.max, 6260.0);
+
+        let err = "6180.0".parse::<WindowMarker>().unwrap_err();
+        assert!(err.contains("MIN:MAX"), "{err}");
+        let err = "abc:6260".parse::<WindowMarker>().unwrap_err();
+        assert!(err.contains("minimum 'abc'"), "{err}");
+        let err = "6180:".parse::<WindowMarker>().unwrap_err();
+        assert!(err.contains("maximum ''"), "{err}");
+        let err = "6260:6180".parse::<WindowMarker>().unwrap_err();
+        assert!(err.contains("must be less than"), "{err}");
+    }
+
+    fn benchmark_ggg_file() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("test-data/inputs/ggg-files/co2_6220.pa_ggg_benchmark.ggg")
+    }
+
+    /// Write an SPT file for `spectrum` in `spt_dir` covering the CO2 6220 window.
+    fn write_co2_6220_spt(spt_dir: &Path, spectrum: &str) -> PathBuf {
+        std::fs::create_dir_all(spt_dir).unwrap();
+        let spt_file = spt_dir.join(format!("z{spectrum}"));
+        std::fs::write(
+            &spt_file,
+            " 3 3\n\
+             6180.0 6260.0 2 0.01 30.0 0.1 0.1 0.5 1.0 1.0 0.0\n\
+             Freq Tm Tc\n\
+             6180.0 0.90 0.88\n\
+             6260.0 0.80 0.81\n",
+        )
+        .unwrap();
+        spt_file
+    }
+
+    #[test]
+    fn test_window_marker_from_ggg_file() {
+        let header = std::fs::read_to_string(benchmark_ggg_file()).unwrap();
+        let output_dir = ggg_rs::test_utils::output_dir("plot-spt-window-marker");
+
+        let marker = WindowMarker::from_ggg_file(&benchmark_ggg_file()).unwrap();
+        assert_eq!(
+            marker,
+            WindowMarker {
+                min: 6180.0,
+                max: 6260.0
+            }
+        );
+
+        let no_window_file = output_dir.join("no_window.ggg");
+        let (no_window, _) = header.rsplit_once("6220.00").unwrap();
+        std::fs::write(&no_window_file, no_window).unwrap();
+        let err = WindowMarker::from_ggg_file(&no_window_file).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("Could not find the window definition line"),
+            "{err}"
+        );
+
+        let bad_width_file = output_dir.join("bad_width.ggg");
+        std::fs::write(
+            &bad_width_file,
+            header.replace("6220.00 80.00 :", "6220.00 wide :"),
+        )
+        .unwrap();
+        let err = WindowMarker::from_ggg_file(&bad_width_file).unwrap_err();
+        assert!(
+            err.to_string().contains("Could not parse the window width"),
+            "{err}"
+        );
+
+        let missing_file = output_dir.join("does_not_exist.ggg");
+        ggg_rs::test_utils::remove_file_if_exists(&missing_file).unwrap();
+        assert!(WindowMarker::from_ggg_file(&missing_file).is_err());
+    }
+
+    #[test]
+    fn test_window_marker_find_for_spt() {
+        let co2_6220 = WindowMarker {
+            min: 6180.0,
+            max: 6260.0,
+        };
+        let benchmark_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("test-data/inputs/collate-tccon-results");
+        let output_dir = ggg_rs::test_utils::output_dir("plot-spt-find-window");
+
+        // A run directory with the .col files for two windows and the SPT files in spt/
+        let run_dir = output_dir.join("col-run");
+        let spt_file = write_co2_6220_spt(&run_dir.join("spt"), "pa20040721saaaaa.043");
+        for window in ["co2_6220", "co2_6339"] {
+            let col_name = format!("{window}.pa_ggg_benchmark.col");
+            std::fs::copy(benchmark_dir.join(&col_name), run_dir.join(&col_name)).unwrap();
         }
+        let header = read_spt_file(&spt_file, true).unwrap().header;
+        assert_eq!(
+            WindowMarker::find_for_spt(&spt_file, &header).unwrap(),
+            co2_6220
+        );
+
+        // The .col files only match spectra they list
+        let other_spt = write_co2_6220_spt(&run_dir.join("spt"), "xx20000101saaaaa.001");
+        let err = WindowMarker::find_for_spt(&other_spt, &header).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("Could not find a .col or .ggg file"),
+            "{err}"
+        );
+
+        // A run directory with only the .ggg file and the SPT files in spt/<window>/
+        let run_dir = output_dir.join("ggg-run");
+        let spt_file = write_co2_6220_spt(
+            &run_dir.join("spt").join("co2_6220"),
+            "pa20040721saaaaa.043",
+        );
+        std::fs::copy(
+            benchmark_ggg_file(),
+            run_dir.join("co2_6220.pa_ggg_benchmark.ggg"),
+        )
+        .unwrap();
+        assert_eq!(
+            WindowMarker::find_for_spt(&spt_file, &header).unwrap(),
+            co2_6220
+        );
     }
 
-    let layout = Layout::new()
-        .title(Title::new(spt_basename.to_string_lossy().as_ref()))
-        .x_axis(Axis::new().title(Title::new("Frequency (cm-1)")))
-        .y_axis(Axis::new().title(Title::new("Transmittance (AU)")));
-    plot.set_layout(layout);
+    #[test]
+    fn test_mark_window_from_ggg_args() {
+        let parse = |args: &[&str]| {
+            let cl = ["plot_spt", "zpa20040721saaaaa.043"].iter().chain(args);
+            Cli::try_parse_from(cl).unwrap().mark_window_from_ggg
+        };
+        assert_eq!(parse(&[]), None);
+        assert_eq!(parse(&["--mark-window-from-ggg"]), Some(None));
+        assert_eq!(
+            parse(&["--mark-window-from-ggg=co2_6220.pa_ggg_benchmark.ggg"]),
+            Some(Some(PathBuf::from("co2_6220.pa_ggg_benchmark.ggg")))
+        );
 
-    plot.write_image(&output_file, ImageFormat::PNG, 2400, 600, 1.0);
-    Ok(())
+        // Without the "=", a following argument is an SPT file, not the .ggg file
+        let cl =
+            Cli::try_parse_from(["plot_spt", "--mark-window-from-ggg", "a.spt", "b.spt"]).unwrap();
+        assert_eq!(cl.mark_window_from_ggg, Some(None));
+        assert_eq!(cl.spt_files.len(), 2);
+    }
+
+    #[test]
+    fn test_compared_file_residuals() {
+        let output_dir = ggg_rs::test_utils::output_dir("plot-spt-compare");
+        let spt_file = output_dir.join("plot-spt-compare-test.spt");
+        std::fs::write(
+            &spt_file,
+            " 3 4\n\
+             4000.0 4000.1 2 0.01 30.0 0.1 0.1 0.5 1.0 1.0 0.0\n\
+             Freq Tm Tc Cont\n\
+             4000.0 0.90 0.88 1.0\n\
+             4000.1 0.80 0.81 1.0\n",
+        )
+        .unwrap();
+
+        let all_columns = comma_list("").unwrap();
+        let file =
+            ComparedFile::read(&spt_file, &all_columns, FreqRange::default(), false).unwrap();
+        let tm_only = ComparedFile::read(
+            &spt_file,
+            &comma_list("Tm").unwrap(),
+            FreqRange::default(),
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(file.label, "plot-spt-compare-test.spt");
+        let resid = file.residual().unwrap();
+        approx::assert_abs_diff_eq!(resid[0], 0.02, epsilon = 1e-6);
+        approx::assert_abs_diff_eq!(resid[1], -0.01, epsilon = 1e-6);
+        // Tm, Tc, Cont, and the residual
+        let mut plot = Plot::new();
+        file.add_to_plot(&mut plot, "red");
+        assert_eq!(plot.data().len(), 4);
+
+        assert!(tm_only.residual().is_none());
+        let mut plot = Plot::new();
+        tm_only.add_to_plot(&mut plot, "red");
+        assert_eq!(plot.data().len(), 1);
+    }
 }
@@ -1,8 +1,11 @@
-use std::{fmt::Display, path::PathBuf, process::ExitCode};
+use std::{fmt::Display, path::PathBuf, process::ExitCode, str::FromStr};
 
-use clap::Parser;
+use clap::{ArgAction, Parser};
 use error_stack::ResultExt;
-use ggg_rs::readers::postproc_files::open_and_iter_postproc_file;
+use ggg_rs::{
+    readers::postproc_files::{open_and_iter_postproc_file, PostprocRow, PostprocRowIter},
+    utils::{sniff_file_kind, FileKind},
+};
 
 fn main() -> ExitCode {
     if let Err(e) = main_inner() {
@@ -15,11 +18,103 @@ fn main() -> ExitCode {
 
 fn main_inner() -> error_stack::Result<(), CliError> {
     let clargs = Cli::parse();
-    let (_, it) = open_and_iter_postproc_file(&clargs.file)
+    let kind = sniff_file_kind(&clargs.file).change_context_lazy(|| "Error opening file".into())?;
+    match kind {
+        FileKind::OpusBinary => {
+            return Err(CliError(format!(
+                "{} looks like a binary OPUS spectrum, not a text GGG file",
+                clargs.file.display()
+            ))
+            .into())
+        }
+        FileKind::OtherBinary => {
+            return Err(CliError(format!(
+                "{} is a binary file; this does not look like a text GGG file",
+                clargs.file.display()
+            ))
+            .into())
+        }
+        FileKind::Text => (),
+    }
+
+    if clargs.format == OutputFormat::Csv && clargs.width.is_some() {
+        return Err(CliError::from("--width cannot be used with --format csv").into());
+    }
+
+    let filters = RowFilter::from_args(&clargs.where_)?;
+    let (header, it) = open_and_iter_postproc_file(&clargs.file)
         .change_context_lazy(|| "Error opening file".into())?;
 
+    let missing_cols = match clargs.format {
+        OutputFormat::Table => print_table(&clargs, &filters, it)?,
+        OutputFormat::Csv => print_csv(
+            &clargs.columns,
+            &filters,
+            header.missing_value,
+            clargs.precision,
+            it,
+        )?,
+    };
+
+    if !missing_cols.is_empty() {
+        eprintln!(
+            "Warning: {} columns were absent from the header: {}",
+            missing_cols.len(),
+            missing_cols.join(", ")
+        );
+    }
+
+    Ok(())
+}
+
+/// The value of one column in a row.
+#[derive(Debug, PartialEq)]
+enum Cell<'r> {
+    Number(f64),
+    Text(&'r str),
+    Absent,
+}
+
+impl<'r> Cell<'r> {
+    fn from_row(row: &'r PostprocRow, col: &str) -> Self {
+        if let Some(s) = row.get_string_field(col) {
+            Self::Text(s)
+        } else if let Some(v) = row.get_numeric_field(col) {
+            Self::Number(v)
+        } else {
+            Self::Absent
+        }
+    }
+}
+
+/// Get the cells for `columns` from `row`, adding any columns not in the row to `missing_cols`.
+fn row_cells<'r, 'c>(
+    row: &'r PostprocRow,
+    columns: &'c [String],
+    missing_cols: &mut Vec<&'c str>,
+) -> Vec<Cell<'r>> {
+    columns
+        .iter()
+        .map(|col| {
+            let cell = Cell::from_row(row, col);
+            if cell == Cell::Absent && !missing_cols.contains(&col.as_str()) {
+                missing_cols.push(col.as_str());
+            }
+            cell
+        })
+        .collect()
+}
+
+/// Print the requested columns as fixed-width text, returning any columns not found in the file.
+fn print_table<'c>(
+    clargs: &'c Cli,
+    filters: &[RowFilter],
+    it: PostprocRowIter,
+) -> error_stack::Result<Vec<&'c str>, CliError> {
+    let width = clargs.width.unwrap_or(DEFAULT_WIDTH);
+    let col_width = width + 3;
     for col in clargs.columns.iter() {
-        print!("{col:15}");
+        print!("{col:col_width$}");
     }
     println!("");
 
@@ -28,40 +123,215 @@ fn main_inner() -> error_stack::Result<(), CliError> {
         let row = row.change_context_lazy(|| {
             format!("Error reading data row {} from file", irow + 1).into()
         })?;
-        for col in clargs.columns.iter() {
-            if let Some(val) = row.get_numeric_field(col) {
-                if val.abs() < 1e-3 || val.abs() > 1e4 {
-                    print!("{val:<12E}   ");
-                } else {
-                    // "{val:<12}" can produce strings longer than 12
-                    // characters; 12 is only a minimum. So we have to
-                    // make the string and then slice it to restrict floats
-                    // to 12 characters in width. This doesn't seem to
-                    // affect scientific notation.
-                    let tmp = format!("{val:<12}");
-                    print!("{}   ", &tmp[..12]);
-                }
-            } else {
-                if !missing_cols.contains(&col.as_str()) {
-                    missing_cols.push(col.as_str());
-                }
-                print!("{:15}", "N/A");
+        if !row_matches(filters, &row)? {
+            continue;
+        }
+        let cells = row_cells(&row, &clargs.columns, &mut missing_cols);
+        println!("{}", format_table_row(&cells, width, clargs.precision));
+    }
+    Ok(missing_cols)
+}
+
+/// Format one row of cells as fixed-width text. Strings are left-aligned and
+/// may overflow the column rather than be cut off.
+fn format_table_row(cells: &[Cell], width: usize, precision: Option<usize>) -> String {
+    let col_width = width + 3;
+    let mut line = String::new();
+    for cell in cells {
+        match cell {
+            Cell::Number(val) => {
+                line.push_str(&format_value(*val, width, precision));
+                line.push_str("   ");
             }
+            Cell::Text(s) => line.push_str(&format!("{s:<width$}   ")),
+            Cell::Absent => line.push_str(&format!("{:col_width$}", "N/A")),
         }
-        println!("");
     }
+    line
+}
 
-    if !missing_cols.is_empty() {
-        eprintln!(
-            "Warning: {} columns were absent from the header: {}",
-            missing_cols.len(),
-            missing_cols.join(", ")
-        );
+/// Print the requested columns as CSV, returning any columns not found in the file.
+/// Both missing values and absent columns are written as empty cells.
+fn print_csv<'c>(
+    columns: &'c [String],
+    filters: &[RowFilter],
+    missing_value: f64,
+    precision: Option<usize>,
+    it: PostprocRowIter,
+) -> error_stack::Result<Vec<&'c str>, CliError> {
+    let header = columns.iter().map(|c| csv_field(c)).collect::<Vec<_>>();
+    println!("{}", header.join(","));
+
+    let mut missing_cols: Vec<&str> = vec![];
+    for (irow, row) in it.enumerate() {
+        let row = row.change_context_lazy(|| {
+            format!("Error reading data row {} from file", irow + 1).into()
+        })?;
+        if !row_matches(filters, &row)? {
+            continue;
+        }
+        let cells = row_cells(&row, columns, &mut missing_cols);
+        println!("{}", format_csv_row(&cells, missing_value, precision));
     }
+    Ok(missing_cols)
+}
 
-    Ok(())
+/// Format one row of cells as CSV. Values are written with `precision` significant
+/// figures if given, otherwise with enough digits to round trip; absent cells and
+/// values equal to `missing_value` are left empty.
+fn format_csv_row(cells: &[Cell], missing_value: f64, precision: Option<usize>) -> String {
+    let cells = cells
+        .iter()
+        .map(|cell| match cell {
+            Cell::Number(v) if !approx::abs_diff_eq!(*v, missing_value) => match precision {
+                Some(_) => format_value(*v, 0, precision),
+                None => v.to_string(),
+            },
+            Cell::Text(s) => csv_field(s),
+            _ => String::new(),
+        })
+        .collect::<Vec<_>>();
+    cells.join(",")
+}
+
+/// Quote a CSV field if it contains a character that would otherwise break the row.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
 }
 
+/// A comparison operator for `--where`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CompareOp {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+impl FromStr for CompareOp {
+    type Err = CliError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lt" => Ok(Self::Lt),
+            "le" => Ok(Self::Le),
+            "gt" => Ok(Self::Gt),
+            "ge" => Ok(Self::Ge),
+            "eq" => Ok(Self::Eq),
+            "ne" => Ok(Self::Ne),
+            _ => Err(format!(
+                "Unknown --where operator '{s}', expected one of lt, le, gt, ge, eq, or ne"
+            )
+            .into()),
+        }
+    }
+}
+
+impl CompareOp {
+    fn compare(&self, lhs: f64, rhs: f64) -> bool {
+        match self {
+            CompareOp::Lt => lhs < rhs,
+            CompareOp::Le => lhs <= rhs,
+            CompareOp::Gt => lhs > rhs,
+            CompareOp::Ge => lhs >= rhs,
+            CompareOp::Eq => lhs == rhs,
+            CompareOp::Ne => lhs != rhs,
+        }
+    }
+}
+
+/// One `--where COL OP VALUE` condition that a row must meet to be printed.
+#[derive(Debug, PartialEq)]
+struct RowFilter {
+    column: String,
+    op: CompareOp,
+    value: f64,
+}
+
+impl RowFilter {
+    /// Parse the flattened `--where` arguments, which clap gives as groups of three.
+    fn from_args(args: &[String]) -> Result<Vec<Self>, CliError> {
+        args.chunks(3)
+            .map(|chunk| {
+                let [column, op, value] = chunk else {
+                    return Err("--where requires three values: COL OP VALUE".into());
+                };
+                let value = value.parse::<f64>().map_err(|_| {
+                    CliError(format!(
+                        "Could not parse --where value '{value}' as a number"
+                    ))
+                })?;
+                Ok(Self {
+                    column: column.to_string(),
+                    op: op.parse()?,
+                    value,
+                })
+            })
+            .collect()
+    }
+
+    fn matches(&self, row: &PostprocRow) -> Result<bool, CliError> {
+        let val = row.get_numeric_field(&self.column).ok_or_else(|| {
+            CliError(format!(
+                "--where column '{}' is not a numeric column in the file",
+                self.column
+            ))
+        })?;
+        Ok(self.op.compare(val, self.value))
+    }
+}
+
+/// Check whether `row` meets all the `--where` conditions.
+fn row_matches(filters: &[RowFilter], row: &PostprocRow) -> error_stack::Result<bool, CliError> {
+    for filt in filters {
+        if !filt.matches(row)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Format a value to print in a column `width` characters wide.
+///
+/// Very large or small values are printed in scientific notation. If `precision`
+/// is given, values are printed with that many significant figures and will
+/// overflow the column rather than lose digits. Otherwise, values not in scientific
+/// notation are truncated to fit the column.
+fn format_value(val: f64, width: usize, precision: Option<usize>) -> String {
+    let use_sci = val.abs() < 1e-3 || val.abs() > 1e4;
+    match precision {
+        Some(_) if !val.is_finite() => format!("{val:<width$}"),
+        Some(prec) if use_sci => {
+            let prec = prec.saturating_sub(1);
+            format!("{val:<width$.prec$E}")
+        }
+        Some(prec) => {
+            let magnitude = val.abs().log10().floor() as i64;
+            let decimals = (prec as i64 - 1 - magnitude).max(0) as usize;
+            format!("{val:<width$.decimals$}")
+        }
+        None if use_sci => format!("{val:<width$E}"),
+        None => {
+            // "{val:<width$}" can produce strings longer than `width`
+            // characters; it is only a minimum. So we have to
+            // make the string and then slice it to restrict floats
+            // to `width` characters. This doesn't seem to
+            // affect scientific notation.
+            let tmp = format!("{val:<width$}");
+            tmp[..width].to_string()
+        }
+    }
+}
+
+/// Column width used for table output when --width is not given.
+const DEFAULT_WIDTH: usize = 12;
+
 /// Print specific numeric columns from a GGG output file.
 #[derive(Debug, Parser)]
 struct Cli {
@@ -70,6 +340,33 @@ struct Cli {
     /// Columns from the data in the file to read from.
     /// May be repeated to show multiple columns
     columns: Vec<String>,
+    /// Number of significant figures to print values with. If not
+    /// given, values are truncated to fit the column width.
+    #[clap(short, long)]
+    precision: Option<usize>,
+    /// Minimum width of each column, not counting the spaces between columns.
+    /// Defaults to 12. Not allowed with --format csv.
+    #[clap(short, long)]
+    width: Option<usize>,
+    /// How to print the columns: "table" for aligned text, or "csv" for
+    /// comma-separated values. With "csv", missing values are written as empty
+    /// cells and values are not padded, though --precision still applies.
+    #[clap(long, value_enum, default_value_t = OutputFormat::Table)]
+    format: OutputFormat,
+    /// Only print rows where the numeric column COL compares to VALUE according
+    /// to OP, which may be "lt", "le", "gt", "ge", "eq", or "ne". For example,
+    /// `--where solzen lt 60`. May be repeated, in which case a row must meet all
+    /// the conditions to be printed.
+    #[clap(long = "where", num_args = 3, value_names = ["COL", "OP", "VALUE"], action = ArgAction::Append)]
+    where_: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum OutputFormat {
+    /// Fixed-width columns, meant to be read by people
+    Table,
+    /// Comma-separated values, for loading into other programs
+    Csv,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -92,3 +389,103 @@ impl From<&str> for CliError {
         Self(value.to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_format() {
+        assert_eq!(format_value(1.23456789012345, 12, None), "1.2345678901");
+        assert_eq!(format_value(0.5, 12, None), "0.5         ");
+        assert_eq!(format_value(12345.0, 12, None), "1.2345E4    ");
+        assert_eq!(format_value(1.23456789, 6, None), "1.2345");
+    }
+
+    #[test]
+    fn test_precision_format() {
+        assert_eq!(format_value(0.987654321, 12, Some(5)), "0.98765     ");
+        assert_eq!(format_value(123.456, 8, Some(4)), "123.5   ");
+        assert_eq!(format_value(1234.5678, 4, Some(6)), "1234.57");
+        assert_eq!(format_value(1.5e-5, 10, Some(3)), "1.50E-5   ");
+        assert_eq!(format_value(f64::NAN, 5, Some(3)), "NaN  ");
+    }
+
+    #[test]
+    fn test_csv_format() {
+        let cells = [
+            Cell::Number(1.5),
+            Cell::Number(9.8765e35),
+            Cell::Absent,
+            Cell::Number(-2.0e-7),
+            Cell::Text("pa20040721saaaaa.043"),
+        ];
+        let row = format_csv_row(&cells, 9.8765e35, None);
+        assert_eq!(row, "1.5,,,-0.0000002,pa20040721saaaaa.043");
+        let row = format_csv_row(&cells, 9.8765e35, Some(3));
+        assert_eq!(row, "1.50,,,-2.00E-7,pa20040721saaaaa.043");
+        assert_eq!(csv_field("xco2_ppm"), "xco2_ppm");
+        assert_eq!(csv_field("a,\"b\""), "\"a,\"\"b\"\"\"");
+    }
+
+    #[test]
+    fn test_string_and_numeric_columns() {
+        let aia_file = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("test-data")
+            .join("expected")
+            .join("apply-tccon-insitu-correction")
+            .join("pa_ggg_benchmark.vav.ada.aia");
+        let (_, mut it) = open_and_iter_postproc_file(&aia_file).unwrap();
+        let row = it.next().unwrap().unwrap();
+
+        let columns = vec!["spectrum".to_string(), "xluft".to_string()];
+        let mut missing_cols = vec![];
+        let cells = row_cells(&row, &columns, &mut missing_cols);
+        assert!(missing_cols.is_empty());
+        assert_eq!(
+            format_table_row(&cells, 12, Some(6)),
+            "pa20040721saaaaa.043   0.994768       "
+        );
+    }
+
+    #[test]
+    fn test_where_filters() {
+        let args = ["solzen", "lt", "60", "xluft", "ge", "0.99"]
+            .map(String::from)
+            .to_vec();
+        let filters = RowFilter::from_args(&args).unwrap();
+        assert_eq!(
+            filters[0],
+            RowFilter {
+                column: "solzen".to_string(),
+                op: CompareOp::Lt,
+                value: 60.0
+            }
+        );
+        assert!(RowFilter::from_args(&["xluft", "gte", "1"].map(String::from)).is_err());
+        assert!(RowFilter::from_args(&["xluft", "gt", "one"].map(String::from)).is_err());
+
+        let aia_file = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("test-data")
+            .join("expected")
+            .join("apply-tccon-insitu-correction")
+            .join("pa_ggg_benchmark.vav.ada.aia");
+        let (_, it) = open_and_iter_postproc_file(&aia_file).unwrap();
+        let rows = it.map(|r| r.unwrap()).collect::<Vec<_>>();
+        let nmatch = rows
+            .iter()
+            .filter(|row| row_matches(&filters, row).unwrap())
+            .count();
+        let nexpected = rows
+            .iter()
+            .filter(|row| {
+                row.auxiliary.solzen < 60.0 && row.get_numeric_field("xluft").unwrap() >= 0.99
+            })
+            .count();
+        assert!(nmatch > 0 && nmatch < rows.len());
+        assert_eq!(nmatch, nexpected);
+
+        let bad_filter = RowFilter::from_args(&["not_a_col", "gt", "1"].map(String::from)).unwrap();
+        assert!(row_matches(&bad_filter, &rows[0]).is_err());
+    }
+}
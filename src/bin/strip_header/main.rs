@@ -1,6 +1,7 @@
 use std::{
-    io::{BufRead, Seek},
-    path::PathBuf,
+    fs::File,
+    io::{BufRead, BufReader, Seek},
+    path::{Path, PathBuf},
     process::ExitCode,
 };
 
@@ -10,7 +11,7 @@ use ggg_rs::utils;
 
 fn main() -> ExitCode {
     let clargs = Cli::parse();
-    if let Err(e) = driver(clargs.file, clargs.invert) {
+    if let Err(e) = driver(clargs) {
         eprintln!("{e:?}");
         ExitCode::FAILURE
     } else {
@@ -18,11 +19,71 @@ fn main() -> ExitCode {
     }
 }
 
-fn driver(file: PathBuf, invert: bool) -> error_stack::Result<(), CliError> {
-    let mut fh =
-        utils::FileBuf::open(&file).change_context_lazy(|| CliError::ReadError(file.clone()))?;
-    let nhead =
-        utils::get_nhead(&mut fh).change_context_lazy(|| CliError::ReadError(file.clone()))?;
+fn driver(clargs: Cli) -> error_stack::Result<(), CliError> {
+    if clargs.count {
+        let (nhead, ndata) = count_lines(&clargs.file, clargs.nhead)?;
+        println!("header lines: {nhead}");
+        println!("data lines: {ndata}");
+        Ok(())
+    } else {
+        print_lines(clargs.file, clargs.invert, clargs.nhead)
+    }
+}
+
+/// Return the number of header lines and the number of data lines in `file`.
+fn count_lines(
+    file: &Path,
+    nhead_override: Option<usize>,
+) -> error_stack::Result<(usize, usize), CliError> {
+    let mut fh = open_text_file(file)?;
+    let (nhead, nread) = read_nhead(&mut fh, nhead_override)?;
+    for _ in nread..nhead {
+        fh.read_header_line()
+            .change_context_lazy(|| CliError::ReadError(file.into()))?;
+    }
+
+    let mut ndata = 0;
+    for line in fh.into_reader().lines() {
+        line.change_context_lazy(|| CliError::ReadError(file.into()))?;
+        ndata += 1;
+    }
+    Ok((nhead, ndata))
+}
+
+/// Get the number of header lines, either `nhead_override` or the count on the first
+/// line of the file. Also returns the number of lines read from `fh` to get it.
+fn read_nhead(
+    fh: &mut utils::FileBuf<BufReader<File>>,
+    nhead_override: Option<usize>,
+) -> error_stack::Result<(usize, usize), CliError> {
+    if let Some(nhead) = nhead_override {
+        Ok((nhead, 0))
+    } else {
+        let nhead =
+            utils::get_nhead(fh).change_context_lazy(|| CliError::ReadError(fh.path.clone()))?;
+        Ok((nhead, 1))
+    }
+}
+
+fn open_text_file(file: &Path) -> error_stack::Result<utils::FileBuf<BufReader<File>>, CliError> {
+    let kind =
+        utils::sniff_file_kind(file).change_context_lazy(|| CliError::ReadError(file.into()))?;
+    match kind {
+        utils::FileKind::OpusBinary => return Err(CliError::NotText(file.into()).into()),
+        utils::FileKind::OtherBinary => return Err(CliError::Binary(file.into()).into()),
+        utils::FileKind::Text => (),
+    }
+
+    utils::FileBuf::open(file).change_context_lazy(|| CliError::ReadError(file.into()))
+}
+
+fn print_lines(
+    file: PathBuf,
+    invert: bool,
+    nhead_override: Option<usize>,
+) -> error_stack::Result<(), CliError> {
+    let mut fh = open_text_file(&file)?;
+    let (nhead, nread) = read_nhead(&mut fh, nhead_override)?;
 
     if invert {
         let mut rdr = fh.into_reader();
@@ -37,7 +98,7 @@ fn driver(file: PathBuf, invert: bool) -> error_stack::Result<(), CliError> {
             print!("{buf}");
         }
     } else {
-        for _ in 1..nhead {
+        for _ in nread..nhead {
             fh.read_header_line()
                 .change_context_lazy(|| CliError::ReadError(file.clone()))?;
         }
@@ -53,7 +114,8 @@ fn driver(file: PathBuf, invert: bool) -> error_stack::Result<(), CliError> {
 
 /// Print the contents of a GGG file without its header, or only the header.
 /// Useful as part of a CLI pipeline for concatentating files. The file given
-/// must include the number of lines in the header as part of the first line.
+/// must include the number of lines in the header as part of the first line,
+/// unless the number of header lines is given with --nhead.
 #[derive(Debug, Parser)]
 struct Cli {
     /// The file to print without its header
@@ -63,10 +125,59 @@ struct Cli {
     /// the header.
     #[clap(short, long)]
     invert: bool,
+
+    /// Instead of printing the file, print the number of header lines and
+    /// the number of data lines (everything after the header).
+    #[clap(short, long, conflicts_with = "invert")]
+    count: bool,
+
+    /// Treat this many lines at the start of the file as the header, instead
+    /// of reading the number of header lines from the first line. When given,
+    /// the first line is not interpreted at all, so this can be used with files
+    /// that do not have the header count.
+    #[clap(short, long)]
+    nhead: Option<usize>,
 }
 
 #[derive(Debug, thiserror::Error)]
 enum CliError {
     #[error("Error opening/reading file: {0}")]
     ReadError(PathBuf),
+    #[error("{0} looks like a binary OPUS spectrum, not a text GGG file")]
+    NotText(PathBuf),
+    #[error("{0} is a binary file; this does not look like a text GGG file")]
+    Binary(PathBuf),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count_lines() {
+        let output_dir = ggg_rs::test_utils::output_dir("strip-header-count");
+        let file = output_dir.join("strip-header-count-test.txt");
+        std::fs::write(&file, "3 2\nheader line\na b\n1 2\n3 4\n5 6\n7 8\n").unwrap();
+        let counts = count_lines(&file, None).unwrap();
+        let override_counts = count_lines(&file, Some(1)).unwrap();
+        assert_eq!(counts, (3, 4));
+        assert_eq!(override_counts, (1, 6));
+    }
+
+    #[test]
+    fn test_reject_binary_files() {
+        let output_dir = ggg_rs::test_utils::output_dir("strip-header-binary");
+        let file = output_dir.join("not-text.dat");
+        std::fs::write(&file, [0x00, 0x01, 0x02, 0xff, 0xfe, 0x00, 0x10, 0x80]).unwrap();
+        let err = open_text_file(&file).err().unwrap();
+        assert!(
+            matches!(err.current_context(), CliError::Binary(_)),
+            "{err:?}"
+        );
+        assert!(
+            err.to_string()
+                .contains("this does not look like a text GGG file"),
+            "{err}"
+        );
+    }
 }
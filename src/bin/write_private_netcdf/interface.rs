@@ -11,7 +11,9 @@ use std::{
 use crate::errors::{CliError, ReadError, VarError, WriteError};
 use error_stack::ResultExt;
 use ggg_rs::{
-    collation::get_window_from_col_file, tccon::input_config::TcconWindowPrefixes,
+    collation::get_window_from_col_file,
+    tccon::input_config::TcconWindowPrefixes,
+    units::{cf_unit, long_name_with_unit},
     utils::parse_window_name,
 };
 use indicatif::ProgressBar;
@@ -374,8 +376,13 @@ impl<T: NcTypeDescriptor> VarToBe for ConcreteVarToBe<T> {
         let mut ncvar = ncgrp.add_variable::<T>(&full_name, &self.dimensions)?;
         ncvar.set_compression(9, true)?;
         ncvar.put(self.data.view(), netcdf::Extents::All)?;
-        ncvar.put_attribute("long_name", self.long_name.as_str())?;
-        ncvar.put_attribute("units", self.units.as_str())?;
+        // Units come from GGG files (e.g. the qc.dat file), so make sure they are CF compliant.
+        let (units, unit_long_name) = cf_unit(&self.units);
+        ncvar.put_attribute(
+            "long_name",
+            long_name_with_unit(&self.long_name, unit_long_name),
+        )?;
+        ncvar.put_attribute("units", units)?;
         ncvar.put_attribute("source_file_name", self.source_file_name.as_str())?;
         ncvar.put_attribute("source_file_sha256", self.source_file_sha256.as_str())?;
         for (attname, attvalue) in self.extra_attrs.iter() {
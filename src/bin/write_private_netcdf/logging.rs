@@ -1,19 +1,26 @@
 use std::{
+    io::IsTerminal,
     path::Path,
     sync::{Arc, Mutex},
 };
 
-use indicatif::MultiProgress;
+use ggg_rs::logging::LogFormat;
+use indicatif::{MultiProgress, ProgressDrawTarget};
 use tracing::Level;
 use tracing_subscriber::{fmt::writer::MakeWriterExt, prelude::*, Registry};
 
 /// Set up logging to both stderr and "write_netcdf.log" in the given run directory.
 ///
+/// `format` only affects the stderr output; the log files always use their own formats.
+/// With JSON output, the progress bars are hidden unless stderr is a terminal so that
+/// they never end up mixed into the JSON records.
+///
 /// Note that any previous write_netcdf.log is overwritten. Panics if setting up the logger
 /// fails, usually because it cannot write to the log file.
 pub(crate) fn init_logging(
     run_dir: &Path,
     verbosity_level: clap_verbosity_flag::LevelFilter,
+    format: LogFormat,
     mpbar: Arc<MultiProgress>,
 ) {
     // TODO: Possibly integrate with indicatif to use its
@@ -33,12 +40,19 @@ pub(crate) fn init_logging(
     // Log to the screen with the user-requested verbosity
     // The Mutex is required by tracing_subscriber to make something that implements
     // std::io::Write implement tracing_subscriber::writer::MakeWriter.
+    if format == LogFormat::Json && !std::io::stderr().is_terminal() {
+        mpbar.set_draw_target(ProgressDrawTarget::hidden());
+    }
     let stderr = Mutex::new(ConsoleLogger::new(mpbar)).with_max_level(level);
     let stderr_layer = tracing_subscriber::fmt::layer()
         .with_level(true)
         .with_file(true)
         .with_line_number(true)
         .with_writer(stderr);
+    let stderr_layer = match format {
+        LogFormat::Text => stderr_layer.boxed(),
+        LogFormat::Json => stderr_layer.json().boxed(),
+    };
 
     // Log the same things to a write_netcdf.log file, but enforce that this file is always recording
     // detailed logs (at least DEBUG)
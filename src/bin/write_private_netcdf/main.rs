@@ -4,6 +4,7 @@ use std::{
     path::{Path, PathBuf},
     process::ExitCode,
     sync::Arc,
+    time::Instant,
 };
 
 use calculators::FlagCalculator;
@@ -11,9 +12,11 @@ use clap::Parser;
 use error_stack::ResultExt;
 use errors::{CliError, WriteError};
 use ggg_rs::utils::GggCompatibilityCli;
+use indexmap::IndexMap;
 use interface::{
     DataCalculator, DataProvider, GroupSelector, SpectrumIndexer, StdGroupSelector, StdGroupWriter,
 };
+use itertools::Itertools;
 use providers::{AiaFile, MavFile, PostprocFile, RunlogProvider};
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use tracing::{error, info};
@@ -27,6 +30,7 @@ mod progress;
 mod providers;
 mod qc;
 mod setup;
+mod summary;
 
 fn main() -> ExitCode {
     let clargs = WritePrivateCli::parse();
@@ -37,6 +41,7 @@ fn main() -> ExitCode {
     logging::init_logging(
         &clargs.run_dir,
         clargs.verbosity.log_level_filter(),
+        clargs.log_format.log_format,
         Arc::clone(&mpbar),
     );
     info!("Logging initialized");
@@ -62,12 +67,25 @@ struct WritePrivateCli {
 
     #[command(flatten)]
     verbosity: clap_verbosity_flag::Verbosity<clap_verbosity_flag::InfoLevel>,
+
+    #[command(flatten)]
+    log_format: ggg_rs::logging::LogFormatCli,
+
+    /// Print a summary of the finished netCDF file (number of spectra, windows,
+    /// groups, dimensions, inputs, and elapsed time) at the end of the run.
+    #[clap(long)]
+    summary: bool,
+
+    /// Also write the summary to this file. Implies --summary.
+    #[clap(long)]
+    summary_file: Option<PathBuf>,
 }
 
 fn driver(
     clargs: WritePrivateCli,
     mpbar: Arc<indicatif::MultiProgress>,
 ) -> error_stack::Result<(), CliError> {
+    let start_time = Instant::now();
     let file_paths = setup::InputFiles::from_run_dir(&clargs.run_dir)?;
     let runlog_name = file_paths
         .runlog
@@ -84,12 +102,7 @@ fn driver(
         .change_context_lazy(|| CliError::input_error("error occurred while reading the runlog"))?;
     let spec_indexer = Arc::new(spec_indexer);
 
-    // Since we allow the .vsw.ada file to be missing, check if it is present. Eventually whether
-    // this is an error will depend on whether we are in TCCON or EM27 mode.
-    let vsw_ada_file = file_paths
-        .vsw_ada_file
-        .ok_or_else(|| CliError::input_error("expected .vsw.ada file ({}) does not exist"))?;
-    let providers: Vec<Box<dyn DataProvider>> = vec![
+    let mut providers: Vec<Box<dyn DataProvider>> = vec![
         Box::new(runlog),
         Box::new(MavFile::new(file_paths.mav_file)?),
         Box::new(AiaFile::new(
@@ -109,13 +122,28 @@ fn driver(
             file_paths.tav_file,
             clargs.compat.into(),
         )?),
-        Box::new(PostprocFile::new(vsw_ada_file, clargs.compat.into())?),
-        Box::new(PostprocFile::new(
-            file_paths.vav_ada_file,
-            clargs.compat.into(),
-        )?),
     ];
 
+    // The .vsw.ada file is allowed to be missing (the EM27s don't generate it), so only
+    // read it if it is present. Eventually whether this is an error will depend on whether
+    // we are in TCCON or EM27 mode.
+    let optional_inputs = vec![(
+        ".vsw.ada file".to_string(),
+        file_paths.vsw_ada_file.is_some(),
+    )];
+    if let Some(vsw_ada_file) = file_paths.vsw_ada_file {
+        providers.push(Box::new(PostprocFile::new(
+            vsw_ada_file,
+            clargs.compat.into(),
+        )?));
+    } else {
+        info!("No .vsw.ada file found, the netCDF file will not include its variables");
+    }
+    providers.push(Box::new(PostprocFile::new(
+        file_paths.vav_ada_file,
+        clargs.compat.into(),
+    )?));
+
     // Set up our calculators as well
     let calculators: Vec<Box<dyn DataCalculator>> =
         vec![Box::new(FlagCalculator::new(&file_paths.qc_file)?)];
@@ -125,24 +153,23 @@ fn driver(
         CliError::runtime_error("error occurred while initializing netCDF file")
     })?;
 
-    // Create all dimensions first
+    // Create all dimensions first, after making sure the providers agree on their lengths
     let mut known_dims = vec![];
-    for provider in providers.iter() {
-        let provided_dimensions = provider.dimension_lengths();
-        for (dimname, dimlength) in provided_dimensions.iter() {
-            nc_dset
-                .add_dimension(&dimname, *dimlength)
-                .change_context_lazy(|| {
-                    CliError::runtime_error(format!(
-                        "error occurred while creating the '{dimname}' dimension"
-                    ))
-                })?;
-            known_dims.push(dimname.to_string());
-        }
+    for (dimname, dimlength) in collect_dimension_lengths(&providers)? {
+        nc_dset
+            .add_dimension(dimname, dimlength)
+            .change_context_lazy(|| {
+                CliError::runtime_error(format!(
+                    "error occurred while creating the '{dimname}' dimension"
+                ))
+            })?;
+        known_dims.push(dimname.to_string());
     }
 
     // Check that all the dimensions we need were written
     report_missing_dimensions(&known_dims, &providers)?;
+    let input_names = providers.iter().map(|p| p.to_string()).collect_vec();
+    let num_windows = file_paths.selected_col_files.len();
 
     // Create the type that determines which group variables go in. This uses only the .col
     // files for windows listed in the multiggg.sh file because those should be the only ones
@@ -190,7 +217,29 @@ fn driver(
 
     let curr_nc_path = temporary_nc_path(&clargs.run_dir);
     // TODO: compute the file name from the times by default.
-    finalize_nc_file(&curr_nc_path, runlog_name)?;
+    let final_nc_path = finalize_nc_file(&curr_nc_path, runlog_name)?;
+
+    if clargs.summary || clargs.summary_file.is_some() {
+        let summary = summary::WriteSummary::from_nc_file(
+            &final_nc_path,
+            num_windows,
+            input_names,
+            optional_inputs,
+            start_time.elapsed(),
+        )
+        .change_context_lazy(|| {
+            CliError::runtime_error("failed to read the finished netCDF file for the summary")
+        })?;
+        println!("{summary}");
+        if let Some(summary_file) = &clargs.summary_file {
+            std::fs::write(summary_file, format!("{summary}\n")).change_context_lazy(|| {
+                CliError::runtime_error(format!(
+                    "failed to write the summary to {}",
+                    summary_file.display()
+                ))
+            })?;
+        }
+    }
 
     Ok(())
 }
@@ -235,10 +284,11 @@ fn execute_providers_and_calculators(
     Ok(())
 }
 
+/// Mark the netCDF file as complete and move it to its final name, returning the new path.
 fn finalize_nc_file(
     nc_path: &Path,
     mut final_name_stem: OsString,
-) -> error_stack::Result<(), CliError> {
+) -> error_stack::Result<PathBuf, CliError> {
     // Does this work? If not, I don't see a way to edit attributes, which is weird.
     // In that case, we'll have to just not add this attribute until writing is completed,
     // it's absence will indicate failure.
@@ -257,15 +307,61 @@ fn finalize_nc_file(
         .change_context_lazy(|| CliError::runtime_error("failed to close completed netCDF file"))?;
     final_name_stem.push(".private.nc");
     let out_path = nc_path.with_file_name(final_name_stem);
-    std::fs::rename(nc_path, out_path).change_context_lazy(|| {
+    std::fs::rename(nc_path, &out_path).change_context_lazy(|| {
         CliError::runtime_error("failed to rename netCDF file during finalization")
-    })
+    })?;
+    Ok(out_path)
 }
 
 fn temporary_nc_path(run_dir: &Path) -> PathBuf {
     run_dir.join("temporary.private.nc")
 }
 
+/// Gather the dimension lengths given by all the providers, with each dimension
+/// listed once. Returns an error naming the providers and lengths if any providers
+/// disagree on the length of a dimension.
+fn collect_dimension_lengths(
+    providers: &[Box<dyn DataProvider>],
+) -> Result<Vec<(&'static str, usize)>, CliError> {
+    // For each dimension, track which providers gave each length.
+    let mut dim_lengths: IndexMap<&'static str, IndexMap<usize, Vec<String>>> = IndexMap::new();
+    for provider in providers.iter() {
+        for &(dimname, dimlength) in provider.dimension_lengths().iter() {
+            dim_lengths
+                .entry(dimname)
+                .or_default()
+                .entry(dimlength)
+                .or_default()
+                .push(provider.to_string());
+        }
+    }
+
+    let mut msg = String::new();
+    for (dimname, lengths) in dim_lengths.iter().filter(|(_, l)| l.len() > 1) {
+        let by_provider = lengths
+            .iter()
+            .map(|(len, provs)| format!("{len} (from the {})", provs.join(", ")))
+            .join(", ");
+        msg.push_str(&format!("\n- {dimname}: {by_provider}"));
+    }
+
+    if msg.is_empty() {
+        Ok(dim_lengths
+            .into_iter()
+            .map(|(dimname, lengths)| {
+                let (&len, _) = lengths
+                    .first()
+                    .expect("each dimension should have at least one length");
+                (dimname, len)
+            })
+            .collect())
+    } else {
+        Err(CliError::input_error(format!(
+            "The input files disagree on the length of the following dimension(s):{msg}"
+        )))
+    }
+}
+
 fn report_missing_dimensions(
     known_dimensions: &[String],
     providers: &[Box<dyn DataProvider>],
@@ -297,3 +393,78 @@ fn report_missing_dimensions(
         Err(CliError::internal_error(msg))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{borrow::Cow, fmt::Display};
+
+    use indicatif::ProgressBar;
+
+    use super::*;
+    use crate::dimensions::{PRIOR_ALT_DIM_NAME, TIME_DIM_NAME};
+
+    struct DimOnlyProvider {
+        name: &'static str,
+        lengths: Vec<(&'static str, usize)>,
+    }
+
+    impl Display for DimOnlyProvider {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{}", self.name)
+        }
+    }
+
+    impl DataProvider for DimOnlyProvider {
+        fn dimension_lengths(&self) -> Cow<'_, [(&'static str, usize)]> {
+            Cow::Borrowed(&self.lengths)
+        }
+
+        fn dimensions_required(&self) -> Cow<'_, [&'static str]> {
+            Cow::Borrowed(&[])
+        }
+
+        fn write_data_to_nc(
+            &self,
+            _spec_indexer: &SpectrumIndexer,
+            _writer: &dyn interface::GroupWriter,
+            _group_selector: &dyn GroupSelector,
+            _pb: ProgressBar,
+        ) -> error_stack::Result<(), WriteError> {
+            // These tests only check the dimension lengths, so there is no data to write.
+            Ok(())
+        }
+    }
+
+    fn make_provider(
+        name: &'static str,
+        lengths: Vec<(&'static str, usize)>,
+    ) -> Box<dyn DataProvider> {
+        Box::new(DimOnlyProvider { name, lengths })
+    }
+
+    #[test]
+    fn test_agreeing_dimension_lengths() {
+        let providers = vec![
+            make_provider("runlog", vec![(TIME_DIM_NAME, 10)]),
+            make_provider(".mav file", vec![(PRIOR_ALT_DIM_NAME, 51)]),
+            make_provider(".vsw file", vec![(TIME_DIM_NAME, 10)]),
+        ];
+        let dims = collect_dimension_lengths(&providers).unwrap();
+        assert_eq!(dims, vec![(TIME_DIM_NAME, 10), (PRIOR_ALT_DIM_NAME, 51)]);
+    }
+
+    #[test]
+    fn test_conflicting_dimension_lengths() {
+        let providers = vec![
+            make_provider("runlog", vec![(TIME_DIM_NAME, 10)]),
+            make_provider(".vsw file", vec![(TIME_DIM_NAME, 12)]),
+        ];
+        let err = collect_dimension_lengths(&providers).unwrap_err();
+        let msg = err.to_string();
+        assert!(matches!(err, CliError::InputError(_)));
+        assert!(
+            msg.contains("time: 10 (from the runlog), 12 (from the .vsw file)"),
+            "{msg}"
+        );
+    }
+}
@@ -31,10 +31,12 @@ impl MavFile {
                 CliError::input_error("failed to read the first block from the .mav file")
             })?;
 
-        let height = first_mav_block.data.get("Height").ok_or_else(|| {
+        let height = first_mav_block.altitude().ok_or_else(|| {
             CliError::input_error("did not find a column named 'Height' in the first .mav block")
         })?;
-        let ncell = height.mapv(|z| if z < -1.0 { 1 } else { 0 }).sum() as usize;
+        let ncell = first_mav_block
+            .num_cell_levels()
+            .expect("num_cell_levels should not be None if the block has a Height column");
         let nlev = height.len() - ncell;
         Ok(Self {
             mav_file_path,
@@ -78,8 +80,7 @@ impl MavFile {
 
         // The altitude vector should not change, so we just get it from the first block
         let atmosphere_alts = curr_block
-            .data
-            .get("Height")
+            .altitude()
             .ok_or_else(|| {
                 WriteError::custom("Expected the first .mav block to contain a 'Height' column")
             })?
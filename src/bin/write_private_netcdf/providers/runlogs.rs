@@ -9,7 +9,7 @@ use chrono::{DateTime, Utc};
 use error_stack::ResultExt;
 use ggg_rs::{
     cit_spectrum_name::{CitSpectrumName, NoDetectorSpecName},
-    readers::runlogs::FallibleRunlog,
+    readers::runlogs::{DuplicateAction, FallibleRunlog},
 };
 use indicatif::ProgressBar;
 use ndarray::Array1;
@@ -46,33 +46,21 @@ impl RunlogProvider {
     fn get_times_and_indexer(
         runlog: &Path,
     ) -> error_stack::Result<(SpectrumIndexer, Array1<DateTime<Utc>>), InputError> {
-        let runlog_handle = FallibleRunlog::open(runlog)
+        // Spectrum names must be unique, so have the runlog reader error on any duplicates.
+        let runlog_handle = FallibleRunlog::open_checked(runlog, DuplicateAction::Error)
             .change_context_lazy(|| InputError::error_reading_file(runlog))?;
 
-        // Get the number of lines in the header so that we can give the correct line number in errors
-        let nhead = runlog_handle.header().nhead;
-
         let mut curr_spec_index: usize = 0;
         let mut spec_inds = HashMap::new();
         let mut times = vec![];
         let mut last_nodet_spec = None;
 
-        for (i_data_line, res) in runlog_handle.into_line_iter() {
-            let line_num = i_data_line + nhead + 1; // want line number in reports to be 1-based
-
-            // Handle the case where reading & parsing the next line of the runlog fails
+        for (line_num, res) in runlog_handle.into_line_iter() {
+            // Handle the case where reading & parsing the next line of the runlog fails,
+            // including if the spectrum is a duplicate of one earlier in the file.
             let rl_rec =
                 res.change_context_lazy(|| InputError::error_reading_at_line(runlog, line_num))?;
 
-            // If the spectrum was already encountered, this is an invalid runlog. Spectrum names must be unique.
-            if spec_inds.contains_key(&rl_rec.spectrum_name) {
-                return Err(InputError::custom(format!(
-                    "Spectrum '{}' at line {line_num} of runlog {} is a duplicate of a spectrum earlier in the file. Duplicate spectra are not allowed.",
-                    rl_rec.spectrum_name,
-                    runlog.display()
-                )).into());
-            }
-
             // We need information about the spectrum and ZPD time - make sure we can get that successfully
             let nodet_spectrum = CitSpectrumName::from_str(&rl_rec.spectrum_name)
                 .map(NoDetectorSpecName::from)
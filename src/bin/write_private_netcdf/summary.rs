@@ -0,0 +1,197 @@
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::dimensions::TIME_DIM_NAME;
+
+/// A short report of what went into and came out of writing a private netCDF file.
+pub(crate) struct WriteSummary {
+    pub(crate) nc_path: PathBuf,
+    pub(crate) num_spectra: usize,
+    pub(crate) num_windows: usize,
+    /// Each group in the file (the root group as "/") and how many variables it holds.
+    pub(crate) groups: Vec<(String, usize)>,
+    pub(crate) dimensions: Vec<(String, usize)>,
+    /// The inputs read by the providers, and optional inputs with whether they were found.
+    pub(crate) inputs: Vec<String>,
+    pub(crate) optional_inputs: Vec<(String, bool)>,
+    pub(crate) elapsed: Duration,
+}
+
+impl WriteSummary {
+    /// Build the summary from the finished netCDF file at `nc_path`; the dimensions,
+    /// groups, and number of spectra all come from that file.
+    pub(crate) fn from_nc_file(
+        nc_path: &Path,
+        num_windows: usize,
+        inputs: Vec<String>,
+        optional_inputs: Vec<(String, bool)>,
+        elapsed: Duration,
+    ) -> Result<Self, netcdf::Error> {
+        let ds = netcdf::open(nc_path)?;
+
+        let dimensions = ds
+            .dimensions()
+            .map(|dim| (dim.name(), dim.len()))
+            .collect::<Vec<_>>();
+        let num_spectra = dimensions
+            .iter()
+            .find_map(|(name, len)| (name == TIME_DIM_NAME).then_some(*len))
+            .unwrap_or(0);
+
+        let mut groups = vec![("/".to_string(), ds.variables().count())];
+        for grp in ds.groups()? {
+            groups.push((grp.name(), grp.variables().count()));
+        }
+
+        Ok(Self {
+            nc_path: nc_path.to_path_buf(),
+            num_spectra,
+            num_windows,
+            groups,
+            dimensions,
+            inputs,
+            optional_inputs,
+            elapsed,
+        })
+    }
+}
+
+impl Display for WriteSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Summary of {}", self.nc_path.display())?;
+        writeln!(f, "  Number of spectra: {}", self.num_spectra)?;
+        writeln!(f, "  Number of windows: {}", self.num_windows)?;
+        writeln!(f, "  Groups ({}):", self.groups.len())?;
+        for (name, nvar) in self.groups.iter() {
+            writeln!(f, "    {name}: {nvar} variables")?;
+        }
+        writeln!(f, "  Dimensions ({}):", self.dimensions.len())?;
+        for (name, len) in self.dimensions.iter() {
+            writeln!(f, "    {name}: {len}")?;
+        }
+        writeln!(f, "  Inputs read:")?;
+        for input in self.inputs.iter() {
+            writeln!(f, "    {input}")?;
+        }
+        writeln!(f, "  Optional inputs:")?;
+        for (input, present) in self.optional_inputs.iter() {
+            let status = if *present { "present" } else { "absent" };
+            writeln!(f, "    {input}: {status}")?;
+        }
+        write!(f, "  Elapsed time: {:.1} s", self.elapsed.as_secs_f64())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use ggg_rs::{
+        readers::{postproc_files::open_and_iter_postproc_file, runlogs::Runlog},
+        utils::{get_windows_from_multiggg, GggCompatibility},
+    };
+    use itertools::Itertools;
+
+    use super::*;
+    use crate::{
+        interface::{DataProvider, StdGroupSelector},
+        providers::{PostprocFile, RunlogProvider},
+    };
+
+    /// The run directory of the GGG benchmark (runlog pa_ggg_benchmark.grl).
+    fn benchmark_run_dir() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("test-data")
+            .join("inputs")
+            .join("collate-tccon-results")
+    }
+
+    #[test]
+    fn test_summary_counts() {
+        // The benchmark outputs in test-data stop at the .vav.ada file, so write the runlog
+        // times and the .vav.ada variables, which is enough to check what the summary counts.
+        let run_dir = benchmark_run_dir();
+        let vav_ada_file = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("test-data")
+            .join("inputs")
+            .join("apply-tccon-insitu-correction")
+            .join("pa_ggg_benchmark.vav.ada");
+        let out_dir = ggg_rs::test_utils::output_dir("write-private-netcdf-summary");
+
+        let (runlog, spec_indexer) =
+            RunlogProvider::new(run_dir.join("pa_ggg_benchmark.grl")).unwrap();
+        let providers: Vec<Box<dyn DataProvider>> = vec![
+            Box::new(runlog),
+            Box::new(PostprocFile::new(vav_ada_file.clone(), GggCompatibility::Current).unwrap()),
+        ];
+        let input_names = providers.iter().map(|p| p.to_string()).collect_vec();
+
+        let mut nc_dset = crate::init_nc_file(&out_dir).unwrap();
+        for (dimname, dimlength) in crate::collect_dimension_lengths(&providers).unwrap() {
+            nc_dset.add_dimension(dimname, dimlength).unwrap();
+        }
+        let col_files = get_windows_from_multiggg(&run_dir.join("multiggg.sh"), true)
+            .unwrap()
+            .into_iter()
+            .map(|basename| run_dir.join(format!("{basename}.col")))
+            .collect_vec();
+        let group_selector =
+            StdGroupSelector::new(&run_dir.join("secondary_prefixes.dat"), &col_files).unwrap();
+        crate::execute_providers_and_calculators(
+            nc_dset,
+            &group_selector,
+            providers,
+            vec![],
+            Arc::new(spec_indexer),
+            Arc::new(indicatif::MultiProgress::new()),
+        )
+        .unwrap();
+        let nc_path = crate::finalize_nc_file(
+            &crate::temporary_nc_path(&out_dir),
+            "pa_ggg_benchmark".into(),
+        )
+        .unwrap();
+
+        let summary = WriteSummary::from_nc_file(
+            &nc_path,
+            col_files.len(),
+            input_names,
+            vec![(".vsw.ada file".to_string(), false)],
+            Duration::from_secs(2),
+        )
+        .unwrap();
+
+        // Every benchmark window is between 4000 and 11000 cm-1, so has no prefix in
+        // secondary_prefixes.dat and all variables go in the root group: the time plus
+        // one variable for each retrieved column of the .vav.ada file.
+        let num_spectra = Runlog::open(&run_dir.join("pa_ggg_benchmark.grl"))
+            .unwrap()
+            .count();
+        let (header, _) = open_and_iter_postproc_file(&vav_ada_file).unwrap();
+        let aux_fields = ggg_rs::readers::postproc_files::AuxData::postproc_fields_str(
+            GggCompatibility::Current,
+        );
+        let num_retrieved = header
+            .column_names
+            .iter()
+            .filter(|c| !aux_fields.contains(&c.as_str()))
+            .count();
+        assert_eq!(num_retrieved, 30);
+
+        assert_eq!(summary.num_spectra, num_spectra);
+        assert_eq!(summary.num_windows, 57);
+        assert_eq!(summary.groups, vec![("/".to_string(), 1 + num_retrieved)]);
+        assert_eq!(
+            summary.dimensions,
+            vec![(TIME_DIM_NAME.to_string(), num_spectra)]
+        );
+        let text = summary.to_string();
+        assert!(text.contains("Groups (1):"), "{text}");
+        assert!(text.contains("    /: 31 variables"), "{text}");
+        assert!(text.contains(".vav.ada file"), "{text}");
+        assert!(text.contains(".vsw.ada file: absent"), "{text}");
+    }
+}
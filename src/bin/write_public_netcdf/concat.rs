@@ -0,0 +1,510 @@
+//! Checking that several private netCDF files from one site can go into a single public file.
+//!
+//! The public file is not written from a combined copy of the private files. Instead, each
+//! public variable is written from each private file in turn (in time order of the private
+//! files), with each file's data going into its own slab along the public `time` dimension.
+//! Only one variable from one private file needs to be in memory at a time, and the prior
+//! profiles are expanded with each file's own `prior_index`, so the index needs no offset.
+//! Variables without a `time` dimension are copied from the first private file, so they
+//! must be identical in all the private files. Groups are checked the same way as the root group.
+use std::path::{Path, PathBuf};
+
+use error_stack::ResultExt;
+use ggg_rs::nc_utils::NcArray;
+use itertools::Itertools;
+use netcdf::Extents;
+
+use crate::constants::{PRIOR_TIME_DIM_NAME, TIME_DIM_NAME};
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum ConcatError {
+    #[error("Private files {first} and {other} appear to be from different sites ('{first_site}' vs. '{other_site}')")]
+    SiteMismatch {
+        first: String,
+        other: String,
+        first_site: String,
+        other_site: String,
+    },
+    #[error("Private file {other} does not have the same variables as {first}: {details}")]
+    VariableMismatch {
+        first: String,
+        other: String,
+        details: String,
+    },
+    #[error("Dimension '{dimname}' has length {other_len} in {other}, but length {first_len} in {first}")]
+    DimMismatch {
+        dimname: String,
+        first: String,
+        other: String,
+        first_len: usize,
+        other_len: usize,
+    },
+    #[error("Variable '{varname}' differs between {first} and {other}; variables without a time dimension must be the same in all private files")]
+    InconsistentVariable {
+        varname: String,
+        first: String,
+        other: String,
+    },
+    #[error("The time ranges of private files {0} and {1} overlap")]
+    Overlap(String, String),
+    #[error("Private file {0} does not contain any valid times")]
+    NoTimes(String),
+    #[error("{0}")]
+    Context(String),
+}
+
+impl ConcatError {
+    fn context<S: ToString>(ctx: S) -> Self {
+        Self::Context(ctx.to_string())
+    }
+}
+
+/// One of the private files to combine, with the time range it covers.
+struct PrivateFile {
+    path: PathBuf,
+    ds: netcdf::File,
+    first_time: f64,
+    last_time: f64,
+}
+
+impl PrivateFile {
+    fn open(path: &Path) -> error_stack::Result<Self, ConcatError> {
+        let ds = netcdf::open(path).change_context_lazy(|| {
+            ConcatError::context(format!("opening private file {}", path.display()))
+        })?;
+        let times = ds
+            .variable(TIME_DIM_NAME)
+            .ok_or_else(|| netcdf::Error::NotFound(format!("variable '{TIME_DIM_NAME}'")))
+            .and_then(|var| var.get::<f64, _>(Extents::All))
+            .change_context_lazy(|| {
+                ConcatError::context(format!("reading times from {}", path.display()))
+            })?;
+        let (first_time, last_time) = match times.iter().filter(|t| t.is_finite()).minmax() {
+            itertools::MinMaxResult::NoElements => {
+                return Err(ConcatError::NoTimes(path.display().to_string()).into())
+            }
+            itertools::MinMaxResult::OneElement(&t) => (t, t),
+            itertools::MinMaxResult::MinMax(&ta, &tb) => (ta, tb),
+        };
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            ds,
+            first_time,
+            last_time,
+        })
+    }
+
+    fn name(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    fn site_id(&self) -> String {
+        self.path
+            .file_name()
+            .map(|name| name.to_string_lossy().chars().take(2).collect())
+            .unwrap_or_default()
+    }
+
+    fn dim_len(&self, dimname: &str) -> Option<usize> {
+        self.ds.dimension(dimname).map(|dim| dim.len())
+    }
+
+    /// The paths of all groups in this file, parents before children. The root group is "".
+    fn group_paths(&self) -> error_stack::Result<Vec<String>, ConcatError> {
+        let root = self.ds.root().ok_or_else(|| {
+            ConcatError::context(format!(
+                "reading groups from {}, only netCDF-4 private files can be combined",
+                self.name()
+            ))
+        })?;
+        let mut paths = vec![];
+        add_group_paths(&root, "", &mut paths);
+        Ok(paths)
+    }
+
+    /// The paths of all variables in this file, including those in groups.
+    fn variable_paths(&self) -> error_stack::Result<Vec<String>, ConcatError> {
+        let mut paths = vec![];
+        for group in self.group_paths()? {
+            paths.extend(
+                self.group(&group)
+                    .variables()
+                    .map(|v| join_path(&group, &v.name())),
+            );
+        }
+        Ok(paths)
+    }
+
+    fn group(&self, path: &str) -> netcdf::Group<'_> {
+        if path.is_empty() {
+            self.ds.root()
+        } else {
+            self.ds.group(path).ok().flatten()
+        }
+        .expect("group paths should only come from group_paths")
+    }
+
+    fn variable(&self, path: &str) -> netcdf::Variable<'_> {
+        self.ds
+            .variable(path)
+            .expect("variable paths were already checked to match")
+    }
+}
+
+fn add_group_paths(group: &netcdf::Group, path: &str, paths: &mut Vec<String>) {
+    paths.push(path.to_string());
+    for child in group.groups() {
+        add_group_paths(&child, &join_path(path, &child.name()), paths);
+    }
+}
+
+/// Join a group path and a name; the root group has the path "".
+fn join_path(group: &str, name: &str) -> String {
+    if group.is_empty() {
+        name.to_string()
+    } else {
+        format!("{group}/{name}")
+    }
+}
+
+/// Open the private files and check that they can go into one public file. The files are
+/// returned with their paths, ordered by their first time. It is an error for their time
+/// ranges to overlap, for them to be from different sites, or for them to have different
+/// groups, variables, or values for any variable without a `time` or `prior_time` dimension.
+pub(crate) fn open_private_files(
+    paths: &[PathBuf],
+) -> error_stack::Result<Vec<(PathBuf, netcdf::File)>, ConcatError> {
+    let mut inputs = paths
+        .iter()
+        .map(|p| PrivateFile::open(p))
+        .collect::<Result<Vec<_>, _>>()?;
+    inputs.sort_by(|a, b| a.first_time.total_cmp(&b.first_time));
+    check_inputs_compatible(&inputs)?;
+    Ok(inputs.into_iter().map(|inp| (inp.path, inp.ds)).collect())
+}
+
+fn check_inputs_compatible(inputs: &[PrivateFile]) -> error_stack::Result<(), ConcatError> {
+    let first = &inputs[0];
+    let first_groups = first.group_paths()?;
+    let first_vars = first.variable_paths()?;
+
+    for (prev, other) in inputs.iter().tuple_windows() {
+        if other.first_time <= prev.last_time {
+            return Err(ConcatError::Overlap(prev.name(), other.name()).into());
+        }
+    }
+
+    for other in inputs.iter().skip(1) {
+        if other.site_id() != first.site_id() {
+            return Err(ConcatError::SiteMismatch {
+                first: first.name(),
+                other: other.name(),
+                first_site: first.site_id(),
+                other_site: other.site_id(),
+            }
+            .into());
+        }
+
+        let other_groups = other.group_paths()?;
+        let other_vars = other.variable_paths()?;
+        let missing = first_groups
+            .iter()
+            .filter(|g| !other_groups.contains(g))
+            .chain(first_vars.iter().filter(|v| !other_vars.contains(v)))
+            .join(", ");
+        let extra = other_groups
+            .iter()
+            .filter(|g| !first_groups.contains(g))
+            .chain(other_vars.iter().filter(|v| !first_vars.contains(v)))
+            .join(", ");
+        if !missing.is_empty() || !extra.is_empty() {
+            return Err(ConcatError::VariableMismatch {
+                first: first.name(),
+                other: other.name(),
+                details: format!("missing [{missing}], extra [{extra}]"),
+            }
+            .into());
+        }
+
+        for group in first_groups.iter() {
+            for dim in first.group(group).dimensions() {
+                let dimname = dim.name();
+                if dimname == TIME_DIM_NAME || dimname == PRIOR_TIME_DIM_NAME {
+                    continue;
+                }
+                let dimpath = join_path(group, &dimname);
+                let other_len = other.dim_len(&dimpath).unwrap_or(0);
+                if other_len != dim.len() {
+                    return Err(ConcatError::DimMismatch {
+                        dimname: dimpath,
+                        first: first.name(),
+                        other: other.name(),
+                        first_len: dim.len(),
+                        other_len,
+                    }
+                    .into());
+                }
+            }
+        }
+
+        for varpath in first_vars.iter() {
+            let first_var = first.variable(varpath);
+            let other_var = other.variable(varpath);
+            let inconsistent = || ConcatError::InconsistentVariable {
+                varname: varpath.clone(),
+                first: first.name(),
+                other: other.name(),
+            };
+
+            let first_dims = first_var
+                .dimensions()
+                .iter()
+                .map(|d| d.name())
+                .collect_vec();
+            let other_dims = other_var
+                .dimensions()
+                .iter()
+                .map(|d| d.name())
+                .collect_vec();
+            if first_dims != other_dims || first_var.vartype() != other_var.vartype() {
+                return Err(inconsistent().into());
+            }
+
+            if !varies_in_time(&first_var) {
+                let first_data = NcArray::get_from(&first_var)
+                    .change_context_lazy(|| ConcatError::context(format!("reading {varpath}")))?;
+                let other_data = NcArray::get_from(&other_var)
+                    .change_context_lazy(|| ConcatError::context(format!("reading {varpath}")))?;
+                if !nc_arrays_equal(&first_data, &other_data) {
+                    return Err(inconsistent().into());
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether a variable has a `time` or `prior_time` dimension, so that its values are expected
+/// to differ between private files.
+fn varies_in_time(var: &netcdf::Variable) -> bool {
+    var.dimensions()
+        .iter()
+        .any(|d| d.name() == TIME_DIM_NAME || d.name() == PRIOR_TIME_DIM_NAME)
+}
+
+/// Compare two arrays, treating NaNs in the same place as equal.
+fn nc_arrays_equal(a: &NcArray, b: &NcArray) -> bool {
+    match (a, b) {
+        (NcArray::Char(a), NcArray::Char(b)) => a == b,
+        (NcArray::String(a), NcArray::String(b)) => a == b,
+        _ => match (a.to_f64(), b.to_f64()) {
+            (Some(a), Some(b)) => {
+                a.shape() == b.shape()
+                    && a.iter()
+                        .zip(b.iter())
+                        .all(|(x, y)| x == y || (x.is_nan() && y.is_nan()))
+            }
+            _ => false,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::constants::PRIOR_INDEX_VARNAME;
+    use crate::copying::{
+        AuxVarCopy, CopyOptions, CopySet, PriorProfCopy, PrivateInput, Subsetter,
+    };
+
+    use super::*;
+
+    fn write_test_private_file(
+        path: &Path,
+        times: &[f64],
+        flags: &[i32],
+        prior_index: &[i32],
+        nprior: usize,
+    ) {
+        let mut ds = netcdf::create(path).unwrap();
+        ds.add_dimension(TIME_DIM_NAME, times.len()).unwrap();
+        ds.add_dimension(PRIOR_TIME_DIM_NAME, nprior).unwrap();
+        ds.add_dimension("prior_altitude", 2).unwrap();
+
+        let mut var = ds.add_variable::<f64>("time", &[TIME_DIM_NAME]).unwrap();
+        var.put_attribute("units", "seconds since 1970-01-01 00:00:00")
+            .unwrap();
+        var.put(ndarray::arr1(times).view(), Extents::All).unwrap();
+
+        let mut var = ds.add_variable::<i32>("flag", &[TIME_DIM_NAME]).unwrap();
+        var.put_values(flags, Extents::All).unwrap();
+
+        let mut var = ds
+            .add_variable::<i32>(PRIOR_INDEX_VARNAME, &[TIME_DIM_NAME])
+            .unwrap();
+        var.put(ndarray::arr1(prior_index).view(), Extents::All)
+            .unwrap();
+
+        let prior_values = ndarray::Array2::from_shape_fn((nprior, 2), |(i, j)| {
+            ((times[0] + i as f64) * 10.0 + j as f64) as f32
+        });
+        let mut var = ds
+            .add_variable::<f32>("prior_1co2", &[PRIOR_TIME_DIM_NAME, "prior_altitude"])
+            .unwrap();
+        var.put_attribute("units", "ppm").unwrap();
+        var.put(prior_values.view(), Extents::All).unwrap();
+
+        let mut var = ds
+            .add_variable::<f32>("prior_altitude", &["prior_altitude"])
+            .unwrap();
+        var.put(ndarray::arr1(&[0.0f32, 1.0]).view(), Extents::All)
+            .unwrap();
+
+        // Every real private file has this string variable
+        let mut var = ds
+            .add_string_variable("flagged_var_name", &[TIME_DIM_NAME])
+            .unwrap();
+        for (i, &t) in times.iter().enumerate() {
+            var.put_string(&format!("var{t}"), i).unwrap();
+        }
+
+        let mut grp = ds.add_group("insb_experimental").unwrap();
+        grp.add_attribute("description", "InSb detector results")
+            .unwrap();
+        let mut var = grp
+            .add_variable::<f32>("xco2_insb", &[TIME_DIM_NAME])
+            .unwrap();
+        let xco2 = times.iter().map(|&t| t as f32 / 100.0).collect_vec();
+        var.put_values(&xco2, Extents::All).unwrap();
+        let mut var = grp.add_string_variable("insb_version", &[]).unwrap();
+        var.put_string("insb v1", Extents::All).unwrap();
+    }
+
+    /// Write the private files used by the tests in this module, returning their paths:
+    /// two that can be combined (in time order) and one from a different site.
+    fn write_test_private_files() -> [PathBuf; 3] {
+        let output_dir = ggg_rs::test_utils::output_dir("write-public-netcdf-concat");
+        let path_a = output_dir.join("xx20200101_20200102.concat-test.private.nc");
+        let path_b = output_dir.join("xx20200103_20200104.concat-test.private.nc");
+        let path_c = output_dir.join("yy20200105_20200106.concat-test.private.nc");
+        write_test_private_file(&path_a, &[100.0, 101.0, 102.0], &[0, 1, 0], &[0, 0, 1], 2);
+        write_test_private_file(&path_b, &[200.0, 201.0], &[0, 0], &[0, 1], 2);
+        write_test_private_file(&path_c, &[300.0], &[0], &[0], 1);
+        [path_a, path_b, path_c]
+    }
+
+    #[test]
+    fn test_open_private_files() {
+        let [path_a, path_b, path_c] = write_test_private_files();
+
+        // Give the files out of order to check that they are sorted by time
+        let files = open_private_files(&[path_b.clone(), path_a.clone()]).unwrap();
+        let paths = files.iter().map(|(p, _)| p.clone()).collect_vec();
+        assert_eq!(paths, [path_a.clone(), path_b.clone()]);
+
+        let err = open_private_files(&[path_a.clone(), path_a.clone()])
+            .err()
+            .unwrap();
+        assert!(
+            matches!(err.current_context(), ConcatError::Overlap(_, _)),
+            "{err:?}"
+        );
+        let err = open_private_files(&[path_a.clone(), path_c.clone()])
+            .err()
+            .unwrap();
+        assert!(
+            matches!(err.current_context(), ConcatError::SiteMismatch { .. }),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn test_copy_from_several_private_files() {
+        let [path_a, path_b, _] = write_test_private_files();
+        let files = open_private_files(&[path_a.clone(), path_b]).unwrap();
+
+        let mut time_offset = 0;
+        let mut inputs = vec![];
+        for (_, ds) in files.iter() {
+            let flags = ds
+                .variable("flag")
+                .unwrap()
+                .get::<i32, _>(Extents::All)
+                .unwrap()
+                .into_dimensionality::<ndarray::Ix1>()
+                .unwrap();
+            let time_subsetter = Subsetter::from_flag(flags.view());
+            let ntime = time_subsetter.len();
+            inputs.push(PrivateInput {
+                ds,
+                time_subsetter,
+                time_offset,
+            });
+            time_offset += ntime;
+        }
+
+        let public_path = path_a.with_extension("public.nc");
+        let mut public_ds = netcdf::create(&public_path).unwrap();
+        public_ds.add_dimension(TIME_DIM_NAME, time_offset).unwrap();
+        let copy_sets: Vec<Box<dyn CopySet>> = vec![
+            Box::new(AuxVarCopy::new("time", "time", true)),
+            Box::new(AuxVarCopy::new(
+                "flagged_var_name",
+                "flagged variable",
+                true,
+            )),
+            Box::new(AuxVarCopy::new("prior_altitude", "altitude", true)),
+            Box::new(
+                AuxVarCopy::new("insb_experimental/xco2_insb", "InSb XCO2", true)
+                    .with_public_name("xco2_insb"),
+            ),
+            Box::new(PriorProfCopy::new("prior_1co2", "CO2 prior", true)),
+        ];
+        for copy_set in copy_sets.iter() {
+            copy_set
+                .copy(&inputs, &mut public_ds, &CopyOptions::default())
+                .unwrap();
+        }
+
+        // The flagged spectrum (time = 101) is left out of the first file's slab
+        let times = public_ds
+            .variable("time")
+            .unwrap()
+            .get_values::<f64, _>(Extents::All)
+            .unwrap();
+        assert_eq!(times, vec![100.0, 102.0, 200.0, 201.0]);
+
+        let flagged_var = public_ds.variable("flagged_var_name").unwrap();
+        let flagged_names = (0..4)
+            .map(|i| flagged_var.get_string(i).unwrap())
+            .collect_vec();
+        assert_eq!(flagged_names, ["var100", "var102", "var200", "var201"]);
+
+        let xco2 = public_ds
+            .variable("xco2_insb")
+            .unwrap()
+            .get_values::<f32, _>(Extents::All)
+            .unwrap();
+        assert_eq!(xco2, vec![1.0, 1.02, 2.0, 2.01]);
+
+        let altitude = public_ds
+            .variable("prior_altitude")
+            .unwrap()
+            .get_values::<f32, _>(Extents::All)
+            .unwrap();
+        assert_eq!(altitude, vec![0.0, 1.0]);
+
+        // Each file's priors are expanded with its own prior index, so the second
+        // file's spectra get its own priors without offsetting the index.
+        let priors = public_ds
+            .variable("prior_1co2")
+            .unwrap()
+            .get_values::<f32, _>(Extents::All)
+            .unwrap();
+        assert_eq!(
+            priors,
+            vec![1000.0, 1001.0, 1010.0, 1011.0, 2000.0, 2001.0, 2010.0, 2011.0]
+        );
+    }
+}
@@ -12,11 +12,14 @@ use std::{
 use figment::{providers::Format, Figment};
 use indexmap::IndexMap;
 use itertools::Itertools;
+use netcdf::AttributeValue;
 use serde::Deserialize;
 
 use crate::{
     constants::DEFAULT_GAS_LONG_NAMES,
-    copying::{CopyGlobalAttr, PriorProfCopy, XgasAncInferOptions, XgasAncillary},
+    copying::{
+        check_classic_attr_value, CopyGlobalAttr, PriorProfCopy, XgasAncInferOptions, XgasAncillary,
+    },
     discovery::{AncillaryDiscoveryMethod, XgasMatchMethod, XgasMatchRule},
     AuxVarCopy, ComputedVariable, XgasCopy,
 };
@@ -39,6 +42,10 @@ pub(crate) enum ConfigError {
     StringDeser(toml::de::Error),
     #[error("Error deserializing file {p}: {e}")]
     Deserialization { p: String, e: toml::de::Error },
+    #[error("Configuration cannot be written as netCDF-4 classic: {0}")]
+    NotClassic(String),
+    #[error("Global attribute '{attr}' uses an unknown template token '{token}'")]
+    UnknownTemplateToken { attr: String, token: String },
 }
 
 impl ConfigError {
@@ -102,6 +109,12 @@ pub(crate) struct Config {
     #[serde(default)]
     pub(crate) extra_extension: Option<String>,
 
+    /// The fill value to use for missing data in all copied and computed
+    /// variables that do not specify their own. If `None`, the netCDF default
+    /// fill value for each variable's type is used.
+    #[serde(default)]
+    pub(crate) fill_value: Option<f64>,
+
     /// Toggles for whether to add default values to each section.
     ///
     /// # Developer note
@@ -200,6 +213,41 @@ impl Config {
         Ok(sources)
     }
 
+    /// Check that every attribute override in this configuration can be written
+    /// to a netCDF-4 classic model file.
+    ///
+    /// Note that TOML integers and booleans deserialize to 64-bit and unsigned integer
+    /// attributes, respectively, neither of which are allowed in the classic model.
+    /// Configurations meant for classic output should use floats instead.
+    pub(crate) fn check_classic_compatible(&self) -> Result<(), ConfigError> {
+        for (name, value) in self.iter_attr_overrides() {
+            check_classic_attr_value(name, value)
+                .map_err(|e| ConfigError::NotClassic(e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    /// Iterate over every attribute override in this configuration, from the auxiliary
+    /// variables, extra priors, Xgases, and discovery rules.
+    fn iter_attr_overrides(&self) -> impl Iterator<Item = (&String, &AttributeValue)> {
+        let aux_overrides = self.aux.iter().flat_map(|v| v.attr_overrides.iter());
+        let prior_overrides = self
+            .extra_priors
+            .iter()
+            .flat_map(|v| v.attr_overrides.iter());
+        let xgas_overrides = self.xgas.iter().flat_map(|v| v.iter_attr_overrides());
+        let rule_overrides = self
+            .discovery
+            .rule
+            .iter()
+            .flat_map(|r| r.iter_attr_overrides());
+
+        aux_overrides
+            .chain(prior_overrides)
+            .chain(xgas_overrides)
+            .chain(rule_overrides)
+    }
+
     fn finalize(&mut self) {
         if self.defaults.gas_long_names && !self.defaults.disable_all {
             add_default_gas_long_names(self);
@@ -228,6 +276,7 @@ impl Default for Config {
             discovery: Default::default(),
             global_attributes: Default::default(),
             extra_extension: Default::default(),
+            fill_value: Default::default(),
             defaults: Default::default(),
             include: Default::default(),
         };
@@ -255,8 +304,35 @@ pub(crate) struct XgasDiscoveryConfig {
 
 #[derive(Debug, Default, Deserialize)]
 pub(crate) struct AttributeConfig {
+    #[serde(default)]
     must_copy: Vec<String>,
+    #[serde(default)]
     copy_if_present: Vec<String>,
+    /// Attributes to write with the given values rather than copying from the private
+    /// file. The values may contain the tokens listed in [`AttrTemplateValues`].
+    #[serde(default)]
+    set: IndexMap<String, String>,
+}
+
+/// Values that can be substituted into the global attributes in the `set` table,
+/// written as e.g. `{site_id}` in the attribute value.
+pub(crate) struct AttrTemplateValues {
+    pub(crate) site_id: String,
+    pub(crate) start_date: String,
+    pub(crate) end_date: String,
+    pub(crate) ggg_rs_version: String,
+}
+
+impl AttrTemplateValues {
+    fn get(&self, token: &str) -> Option<&str> {
+        match token {
+            "site_id" => Some(&self.site_id),
+            "start_date" => Some(&self.start_date),
+            "end_date" => Some(&self.end_date),
+            "ggg_rs_version" => Some(&self.ggg_rs_version),
+            _ => None,
+        }
+    }
 }
 
 impl AttributeConfig {
@@ -274,6 +350,42 @@ impl AttributeConfig {
         }
         attrs
     }
+
+    pub(crate) fn has_set_attrs(&self) -> bool {
+        !self.set.is_empty()
+    }
+
+    /// Resolve the template tokens in the `set` attributes, returning the attribute
+    /// names and final values. `history` is skipped, since the writer always manages it.
+    pub(crate) fn render_set_attrs(
+        &self,
+        values: &AttrTemplateValues,
+    ) -> Result<Vec<(String, String)>, ConfigError> {
+        let token_re = regex::Regex::new(r"\{(\w+)\}").expect("token regex should compile");
+        let mut attrs = vec![];
+        for (name, template) in self.set.iter() {
+            if name == "history" {
+                log::warn!("The 'history' global attribute cannot be set in the configuration, ignoring it");
+                continue;
+            }
+
+            if let Some(token) = token_re
+                .captures_iter(template)
+                .map(|c| c[1].to_string())
+                .find(|t| values.get(t).is_none())
+            {
+                return Err(ConfigError::UnknownTemplateToken {
+                    attr: name.to_string(),
+                    token,
+                });
+            }
+            let value = token_re.replace_all(template, |c: &regex::Captures| {
+                values.get(&c[1]).unwrap_or_default().to_string()
+            });
+            attrs.push((name.to_string(), value.into_owned()));
+        }
+        Ok(attrs)
+    }
 }
 
 #[derive(Debug, Deserialize, serde::Serialize)]
@@ -464,6 +576,53 @@ mod tests {
         assert_eq!(cfg.gas_long_names.len(), 0);
     }
 
+    #[test]
+    fn test_fill_value_config() {
+        let cfg = Config::from_toml_str("").expect("deserialization should not fail");
+        assert_eq!(cfg.fill_value, None);
+
+        let toml_str = r#"fill_value = -999.0
+        [[aux]]
+        private_name = "pout"
+        long_name = "surface pressure"
+        fill_value = -9999.0
+        "#;
+        let cfg = Config::from_toml_str(toml_str).expect("deserialization should not fail");
+        assert_eq!(cfg.fill_value, Some(-999.0));
+        assert_eq!(cfg.aux[0].fill_value, Some(-9999.0));
+    }
+
+    #[test]
+    fn test_classic_config_check() {
+        let cfg = Config::from_toml_str(STANDARD_TCCON_TOML).unwrap();
+        cfg.check_classic_compatible()
+            .expect("standard TCCON config should be classic compatible");
+
+        let toml_str = r#"[[aux]]
+        private_name = "pout"
+        long_name = "surface pressure"
+        attr_overrides = { valid_min = 0.0, note = "classic ok" }
+        "#;
+        let cfg = Config::from_toml_str(toml_str).unwrap();
+        cfg.check_classic_compatible()
+            .expect("float and string overrides should be classic compatible");
+
+        for bad_override in ["valid_min = 0", "flags = [\"a\", \"b\"]", "is_good = true"] {
+            let toml_str = format!(
+                r#"[[aux]]
+                private_name = "pout"
+                long_name = "surface pressure"
+                attr_overrides = {{ {bad_override} }}
+                "#
+            );
+            let cfg = Config::from_toml_str(&toml_str).unwrap();
+            assert!(
+                cfg.check_classic_compatible().is_err(),
+                "override '{bad_override}' should not be classic compatible"
+            );
+        }
+    }
+
     #[test]
     fn test_book_examples() {
         let crate_root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
@@ -498,4 +657,78 @@ mod tests {
         Config::from_toml_str(EXTENDED_TCCON_TOML)
             .expect("deserializing the extended TCCON configuration should not fail");
     }
+
+    #[test]
+    fn test_included_configs_cf_units() {
+        for (name, toml_str) in [
+            ("standard TCCON", STANDARD_TCCON_TOML),
+            ("extended TCCON", EXTENDED_TCCON_TOML),
+            ("standard EM27/SUN", STANDARD_EM27_TOML),
+        ] {
+            let cfg = Config::from_toml_str(toml_str).unwrap();
+            for (attr, value) in cfg.iter_attr_overrides() {
+                if let ("units", AttributeValue::Str(units)) = (attr.as_str(), value) {
+                    assert!(
+                        ggg_rs::units::is_udunits_unit(units),
+                        "{name} configuration overrides units to '{units}', which UDUNITS would not accept"
+                    );
+                }
+            }
+
+            let attrs = cfg
+                .global_attributes
+                .render_set_attrs(&AttrTemplateValues {
+                    site_id: "pa".to_string(),
+                    start_date: "2004-07-21".to_string(),
+                    end_date: "2004-07-22".to_string(),
+                    ggg_rs_version: "1.0.0".to_string(),
+                })
+                .unwrap();
+            assert!(
+                attrs.contains(&("Conventions".to_string(), "CF-1.8".to_string())),
+                "{name} configuration should set the CF Conventions attribute"
+            );
+        }
+    }
+
+    #[test]
+    fn test_set_global_attributes() {
+        let toml_str = r#"
+        [global_attributes]
+        must_copy = ["source"]
+        [global_attributes.set]
+        Conventions = "CF-1.8"
+        title = "TCCON data from {site_id}, {start_date} to {end_date}"
+        history = "should be ignored"
+        "#;
+        let cfg = Config::from_toml_str(toml_str).expect("deserialization should not fail");
+        let values = AttrTemplateValues {
+            site_id: "pa".to_string(),
+            start_date: "2004-07-21".to_string(),
+            end_date: "2004-07-22".to_string(),
+            ggg_rs_version: "1.0.0".to_string(),
+        };
+        let attrs = cfg.global_attributes.render_set_attrs(&values).unwrap();
+        assert_eq!(
+            attrs,
+            vec![
+                ("Conventions".to_string(), "CF-1.8".to_string()),
+                (
+                    "title".to_string(),
+                    "TCCON data from pa, 2004-07-21 to 2004-07-22".to_string()
+                ),
+            ]
+        );
+
+        let toml_str = r#"
+        [global_attributes.set]
+        source = "ggg-rs {version}"
+        "#;
+        let cfg = Config::from_toml_str(toml_str).expect("deserialization should not fail");
+        let err = cfg.global_attributes.render_set_attrs(&values).unwrap_err();
+        assert!(
+            matches!(err, ConfigError::UnknownTemplateToken { ref token, .. } if token == "version"),
+            "{err}"
+        );
+    }
 }
@@ -1,5 +1,6 @@
 pub(crate) static PROGRAM_NAME: &'static str = "write_public_netcdf";
 pub(crate) static TIME_DIM_NAME: &'static str = "time";
+pub(crate) static PRIOR_TIME_DIM_NAME: &'static str = "prior_time";
 pub(crate) static PRIOR_INDEX_VARNAME: &'static str = "prior_index";
 pub(crate) static PRIOR_PRESSURE_VARNAME: &'static str = "prior_pressure";
 pub(crate) static AK_PRESSURE_VARNAME: &'static str = "ak_pressure";
@@ -1,5 +1,5 @@
 use chrono::NaiveDate;
-use compute_helpers::add_geos_version_variable;
+use compute_helpers::{add_geos_version_variable, summarize_geos_versions};
 use error_stack::ResultExt;
 use ggg_rs::{
     nc_utils::{get_string_attr, GetNcAttr, NcArray},
@@ -9,7 +9,7 @@ use ggg_rs::{
 use indexmap::IndexMap;
 use itertools::Itertools;
 use ndarray::{ArrayD, ArrayView1, ArrayViewD, Axis};
-use netcdf::AttributeValue;
+use netcdf::{AttributeValue, Extent, Extents};
 use num_traits::Zero;
 use serde::{Deserialize, Deserializer};
 
@@ -18,8 +18,10 @@ use crate::{
     constants::{PRIOR_INDEX_VARNAME, PRIOR_PRESSURE_VARNAME, PROGRAM_NAME, TIME_DIM_NAME},
     discovery::{Rename, XgasMatchRule},
 };
-use copy_helpers::{copy_variable_general, copy_variable_new_data, copy_vmr_variable_from_dset};
-use copy_utils::{add_needed_dims, add_needed_new_dims, find_subset_dim};
+use copy_helpers::{
+    copy_variable_general, copy_variable_new_data, copy_vmr_variable_from_dset, PublicVarSpec,
+};
+use copy_utils::{add_needed_new_dims, find_subset_dim};
 use xgas_helpers::{
     convert_array_units, expand_prior_profiles_from_file, expand_slant_xgas_binned_aks_from_file,
     get_traceability_scale, write_extrapolation_flags,
@@ -44,16 +46,6 @@ pub(crate) enum CopyError {
     #[error("Private file is missing the required attribute '{attr}' under '{parent}'")]
     MissingReqAttr { parent: String, attr: String },
 
-    /// Indicates that a dimension shared among multiple variables has a different
-    /// expected length for one variable than it was defined with.
-    #[error("Dimension '{dimname}' has length {dim_len_in_file} in the public file, but the variable '{varname}' expects it to have length {dim_len_in_var}")]
-    DimLenMismatch {
-        dimname: String,
-        varname: String,
-        dim_len_in_file: usize,
-        dim_len_in_var: usize,
-    },
-
     #[error("Variable '{varname}' has an inconsistent value at index {index} along dimension {dimension} (both 0-based)")]
     InconsistentValue {
         varname: String,
@@ -61,6 +53,13 @@ pub(crate) enum CopyError {
         index: usize,
     },
 
+    /// Indicates that a variable or attribute has a data type that cannot be stored
+    /// in a netCDF-4 classic model file.
+    #[error(
+        "{what} has type {type_name}, which cannot be written to a netCDF-4 classic model file"
+    )]
+    NotClassicType { what: String, type_name: String },
+
     /// This is a wrapper error used to provide more context to an underlying error.    
     #[error("An error occurred while {0}")]
     Context(String),
@@ -78,20 +77,6 @@ impl CopyError {
         }
     }
 
-    fn dim_len_mismatch<D: ToString, V: ToString>(
-        dimname: D,
-        varname: V,
-        len_in_file: usize,
-        len_in_var: usize,
-    ) -> Self {
-        Self::DimLenMismatch {
-            dimname: dimname.to_string(),
-            varname: varname.to_string(),
-            dim_len_in_file: len_in_file,
-            dim_len_in_var: len_in_var,
-        }
-    }
-
     fn inconsistent_value<V: ToString>(varname: V, dimension: usize, index: usize) -> Self {
         Self::InconsistentValue {
             varname: varname.to_string(),
@@ -110,14 +95,219 @@ impl CopyError {
 }
 
 pub(crate) trait CopySet {
+    /// Copy the variable(s) from the private file(s) to the public file.
+    ///
+    /// Which variables to copy and their attributes are decided from the first of `inputs`;
+    /// the data from each input are then written to its slab of the public variables.
+    ///
+    /// `opts.fill_value` is the default fill value from the top level of the configuration;
+    /// implementors should prefer their own `fill_value` setting if it was given. If
+    /// neither is given, the netCDF default fill value for the variable type is used.
     fn copy(
         &self,
-        private_file: &netcdf::File,
+        inputs: &[PrivateInput],
         public_file: &mut netcdf::FileMut,
-        time_subsetter: &Subsetter,
+        opts: &CopyOptions,
+    ) -> error_stack::Result<(), CopyError>;
+
+    /// Add the public variables that `copy` would create to `planned`, without writing
+    /// anything. `planned` includes the variables from earlier copy sets, which takes
+    /// the place of the public file when deciding if "only if first" variables are needed.
+    fn plan(
+        &self,
+        private_file: &netcdf::File,
+        planned: &mut Vec<PlannedVar>,
     ) -> error_stack::Result<(), CopyError>;
 }
 
+/// A variable that would be written to the public file, as reported by [`CopySet::plan`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct PlannedVar {
+    pub(crate) public_name: String,
+    /// The private variable that the public one is copied or computed from.
+    pub(crate) private_name: String,
+}
+
+impl PlannedVar {
+    fn new<P: ToString, Q: ToString>(public_name: P, private_name: Q) -> Self {
+        Self {
+            public_name: public_name.to_string(),
+            private_name: private_name.to_string(),
+        }
+    }
+}
+
+fn is_planned(planned: &[PlannedVar], public_name: &str) -> bool {
+    planned.iter().any(|v| v.public_name == public_name)
+}
+
+/// Settings that apply to all variables copied into the public file.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct CopyOptions {
+    /// The fill value to use for variables that do not specify their own.
+    pub(crate) fill_value: Option<f64>,
+    /// Whether the public file uses the netCDF-4 classic model, which restricts
+    /// which data and attribute types may be written.
+    pub(crate) classic_model: bool,
+}
+
+impl CopyOptions {
+    fn with_fill_value(self, fill_value: Option<f64>) -> Self {
+        Self { fill_value, ..self }
+    }
+}
+
+/// Limits on a variable's values, outside of which (after subsetting) values are
+/// treated as missing and replaced with the fill value, the same as points equal
+/// to the private file's fill value or NaNs.
+///
+/// The limits are compared to the values as stored in the private file, i.e. before
+/// any unit conversion.
+#[derive(Debug, Clone, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ValueCutoff {
+    /// Values less than this are treated as missing.
+    #[serde(default)]
+    pub(crate) min: Option<f64>,
+    /// Values greater than this are treated as missing.
+    #[serde(default)]
+    pub(crate) max: Option<f64>,
+    /// Values exactly equal to any of these are treated as missing.
+    #[serde(default)]
+    pub(crate) missing_values: Vec<f64>,
+}
+
+impl ValueCutoff {
+    pub(crate) fn is_cut(&self, value: f64) -> bool {
+        self.min.is_some_and(|m| value < m)
+            || self.max.is_some_and(|m| value > m)
+            || self.missing_values.contains(&value)
+    }
+}
+
+/// Masking values with a cutoff requires something to replace them with, so return
+/// an error if there is a cutoff but no fill value.
+fn check_cutoff_has_fill(
+    cutoff: Option<&ValueCutoff>,
+    fill_value: Option<f64>,
+    public_varname: &str,
+) -> Result<(), CopyError> {
+    if cutoff.is_some() && fill_value.is_none() {
+        Err(CopyError::custom(format!(
+            "Variable '{public_varname}' has a cutoff, so requires a fill value (either its own or the top level fill_value)"
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+/// Return an error if the private variable's type cannot be written to a netCDF-4
+/// classic model file. The classic model only allows signed 8/16/32-bit integers,
+/// 32/64-bit floats, and characters.
+fn check_classic_var_type(private_var: &netcdf::Variable) -> error_stack::Result<(), CopyError> {
+    use netcdf::types::{FloatType, IntType, NcVariableType};
+    let type_name = match private_var.vartype() {
+        NcVariableType::Char
+        | NcVariableType::Int(IntType::I8 | IntType::I16 | IntType::I32)
+        | NcVariableType::Float(FloatType::F32 | FloatType::F64) => return Ok(()),
+        NcVariableType::Int(IntType::I64) => "int64",
+        NcVariableType::Int(IntType::U8) => "uint8",
+        NcVariableType::Int(IntType::U16) => "uint16",
+        NcVariableType::Int(IntType::U32) => "uint32",
+        NcVariableType::Int(IntType::U64) => "uint64",
+        NcVariableType::String => "string",
+        NcVariableType::Compound(_) => "compound",
+        NcVariableType::Opaque(_) => "opaque",
+        NcVariableType::Enum(_) => "enum",
+        NcVariableType::Vlen(_) => "vlen",
+    };
+    Err(CopyError::NotClassicType {
+        what: format!("Variable '{}'", private_var.name()),
+        type_name: type_name.to_string(),
+    }
+    .into())
+}
+
+/// Return an error if an attribute value cannot be written to a netCDF-4 classic
+/// model file. Besides the restrictions on numeric types, the classic model has no
+/// string type, so only a single string (stored as characters) is allowed.
+pub(crate) fn check_classic_attr_value(
+    attr_name: &str,
+    value: &AttributeValue,
+) -> Result<(), CopyError> {
+    let type_name = match value {
+        AttributeValue::Schar(_)
+        | AttributeValue::Schars(_)
+        | AttributeValue::Short(_)
+        | AttributeValue::Shorts(_)
+        | AttributeValue::Int(_)
+        | AttributeValue::Ints(_)
+        | AttributeValue::Float(_)
+        | AttributeValue::Floats(_)
+        | AttributeValue::Double(_)
+        | AttributeValue::Doubles(_)
+        | AttributeValue::Str(_) => return Ok(()),
+        AttributeValue::Uchar(_) | AttributeValue::Uchars(_) => "uint8",
+        AttributeValue::Ushort(_) | AttributeValue::Ushorts(_) => "uint16",
+        AttributeValue::Uint(_) | AttributeValue::Uints(_) => "uint32",
+        AttributeValue::Longlong(_) | AttributeValue::Longlongs(_) => "int64",
+        AttributeValue::Ulonglong(_) | AttributeValue::Ulonglongs(_) => "uint64",
+        AttributeValue::Strs(_) => "string array",
+    };
+    Err(CopyError::NotClassicType {
+        what: format!("Attribute '{attr_name}'"),
+        type_name: type_name.to_string(),
+    })
+}
+
+/// One private file to copy from, with the subset of its times to copy and where they go
+/// in the public file. When several private files go into one public file, each one's data
+/// are written as a contiguous slab along the public `time` dimension, starting at `time_offset`.
+pub(crate) struct PrivateInput<'a> {
+    pub(crate) ds: &'a netcdf::File,
+    pub(crate) time_subsetter: Subsetter,
+    pub(crate) time_offset: usize,
+}
+
+impl PrivateInput<'_> {
+    fn variable(&self, varname: &str) -> Result<netcdf::Variable<'_>, CopyError> {
+        self.ds
+            .variable(varname)
+            .ok_or_else(|| CopyError::MissingReqVar(varname.to_string()))
+    }
+
+    /// The extents of the public variable to write this input's `shape`d data to:
+    /// offset along `time_axis` (if the variable has one) and complete along the others.
+    fn public_extents(&self, shape: &[usize], time_axis: Option<usize>) -> Extents {
+        shape
+            .iter()
+            .enumerate()
+            .map(|(i, &n)| {
+                if Some(i) == time_axis {
+                    Extent::from(self.time_offset..self.time_offset + n)
+                } else {
+                    Extent::from(0..n)
+                }
+            })
+            .collect_vec()
+            .into()
+    }
+}
+
+/// The inputs to copy a variable from: all of them if the variable has a time dimension,
+/// otherwise just the first, since variables without a time dimension must be the same
+/// in all the private files.
+fn inputs_to_copy<'i, 'a>(
+    inputs: &'i [PrivateInput<'a>],
+    time_axis: Option<usize>,
+) -> &'i [PrivateInput<'a>] {
+    if time_axis.is_some() {
+        inputs
+    } else {
+        &inputs[..inputs.len().min(1)]
+    }
+}
+
 pub(crate) struct Subsetter {
     keep_inds: Vec<usize>,
 }
@@ -206,52 +396,13 @@ impl Subsetter {
         arr: &NcArray,
         along_axis: usize,
     ) -> Result<NcArray, CopyError> {
-        match arr {
-            NcArray::I8(arr) => {
-                let arr = self.subset_nd_array(arr.view(), along_axis)?;
-                Ok(NcArray::I8(arr))
-            }
-            NcArray::I16(arr) => {
-                let arr = self.subset_nd_array(arr.view(), along_axis)?;
-                Ok(NcArray::I16(arr))
-            }
-            NcArray::I32(arr) => {
-                let arr = self.subset_nd_array(arr.view(), along_axis)?;
-                Ok(NcArray::I32(arr))
-            }
-            NcArray::I64(arr) => {
-                let arr = self.subset_nd_array(arr.view(), along_axis)?;
-                Ok(NcArray::I64(arr))
-            }
-            NcArray::U8(arr) => {
-                let arr = self.subset_nd_array(arr.view(), along_axis)?;
-                Ok(NcArray::U8(arr))
-            }
-            NcArray::U16(arr) => {
-                let arr = self.subset_nd_array(arr.view(), along_axis)?;
-                Ok(NcArray::U16(arr))
-            }
-            NcArray::U32(arr) => {
-                let arr = self.subset_nd_array(arr.view(), along_axis)?;
-                Ok(NcArray::U32(arr))
-            }
-            NcArray::U64(arr) => {
-                let arr = self.subset_nd_array(arr.view(), along_axis)?;
-                Ok(NcArray::U64(arr))
-            }
-            NcArray::F32(arr) => {
-                let arr = self.subset_nd_array(arr.view(), along_axis)?;
-                Ok(NcArray::F32(arr))
-            }
-            NcArray::F64(arr) => {
-                let arr = self.subset_nd_array(arr.view(), along_axis)?;
-                Ok(NcArray::F64(arr))
-            }
-            NcArray::Char(arr) => {
-                let arr = self.subset_nd_array(arr.view(), along_axis)?;
-                Ok(NcArray::U8(arr))
-            }
+        if along_axis >= arr.shape().len() {
+            return Err(CopyError::custom(format!(
+                "cannot subset along axis {along_axis} of a {}-D array",
+                arr.shape().len()
+            )));
         }
+        Ok(arr.select(along_axis, &self.keep_inds))
     }
 }
 
@@ -282,6 +433,15 @@ pub(crate) struct AuxVarCopy {
     /// not present in the source file
     #[serde(default = "crate::config::default_true")]
     pub(crate) required: bool,
+
+    /// Fill value to use for missing data in this variable, overriding the
+    /// top level `fill_value` setting.
+    #[serde(default)]
+    pub(crate) fill_value: Option<f64>,
+
+    /// Limits on the values; those outside them are replaced with the fill value.
+    #[serde(default)]
+    pub(crate) cutoff: Option<ValueCutoff>,
 }
 
 impl AuxVarCopy {
@@ -297,6 +457,8 @@ impl AuxVarCopy {
             attr_overrides: IndexMap::new(),
             attr_to_remove: crate::config::default_attr_remove(),
             required,
+            fill_value: None,
+            cutoff: None,
         }
     }
 
@@ -313,6 +475,8 @@ impl AuxVarCopy {
             attr_overrides: IndexMap::new(),
             attr_to_remove: vec![],
             required,
+            fill_value: None,
+            cutoff: None,
         }
     }
 
@@ -338,18 +502,30 @@ impl AuxVarCopy {
         self.attr_to_remove.push(attr_name);
         self
     }
+
+    #[allow(dead_code)] // needed at least for testing
+    pub(crate) fn with_fill_value(mut self, fill_value: f64) -> Self {
+        self.fill_value = Some(fill_value);
+        self
+    }
+
+    #[allow(dead_code)] // needed at least for testing
+    pub(crate) fn with_cutoff(mut self, cutoff: ValueCutoff) -> Self {
+        self.cutoff = Some(cutoff);
+        self
+    }
 }
 
 impl CopySet for AuxVarCopy {
     fn copy(
         &self,
-        private_file: &netcdf::File,
+        inputs: &[PrivateInput],
         public_file: &mut netcdf::FileMut,
-        time_subsetter: &Subsetter,
+        opts: &CopyOptions,
     ) -> error_stack::Result<(), CopyError> {
         // Will need to create a variable with the same dimensions, then copy the good subset of values
         // and the attributes.
-        let private_var = if let Some(var) = private_file.variable(&self.private_name) {
+        let private_var = if let Some(var) = inputs[0].ds.variable(&self.private_name) {
             var
         } else if self.required {
             return Err(CopyError::MissingReqVar(self.private_name.clone()).into());
@@ -362,16 +538,35 @@ impl CopySet for AuxVarCopy {
         };
 
         let public_name = self.public_name.as_deref().unwrap_or(&self.private_name);
+        if opts.classic_model {
+            check_classic_var_type(&private_var)?;
+        }
+        let fill_value = self.fill_value.or(opts.fill_value);
+        check_cutoff_has_fill(self.cutoff.as_ref(), fill_value, public_name)?;
+
+        let spec = PublicVarSpec {
+            public_varname: public_name,
+            long_name: &self.long_name,
+            attr_overrides: &self.attr_overrides,
+            attr_to_remove: &self.attr_to_remove,
+            fill_value,
+            cutoff: self.cutoff.as_ref(),
+        };
+        copy_variable_general(inputs, public_file, &self.private_name, &spec)
+    }
 
-        copy_variable_general(
-            public_file,
-            &private_var,
-            public_name,
-            time_subsetter,
-            &self.long_name,
-            &self.attr_overrides,
-            &self.attr_to_remove,
-        )
+    fn plan(
+        &self,
+        private_file: &netcdf::File,
+        planned: &mut Vec<PlannedVar>,
+    ) -> error_stack::Result<(), CopyError> {
+        if private_file.variable(&self.private_name).is_some() {
+            let public_name = self.public_name.as_deref().unwrap_or(&self.private_name);
+            planned.push(PlannedVar::new(public_name, &self.private_name));
+        } else if self.required {
+            return Err(CopyError::MissingReqVar(self.private_name.clone()).into());
+        }
+        Ok(())
     }
 }
 
@@ -407,6 +602,11 @@ pub(crate) struct PriorProfCopy {
     /// not present in the source file
     #[serde(default = "crate::config::default_true")]
     pub(crate) required: bool,
+
+    /// Fill value to use for missing data in this variable, overriding the
+    /// top level `fill_value` setting.
+    #[serde(default)]
+    pub(crate) fill_value: Option<f64>,
 }
 
 impl PriorProfCopy {
@@ -423,6 +623,7 @@ impl PriorProfCopy {
             attr_overrides: IndexMap::new(),
             attr_to_remove: crate::config::default_attr_remove(),
             required,
+            fill_value: None,
         }
     }
 
@@ -448,13 +649,13 @@ impl PriorProfCopy {
 impl CopySet for PriorProfCopy {
     fn copy(
         &self,
-        private_file: &netcdf::File,
+        inputs: &[PrivateInput],
         public_file: &mut netcdf::FileMut,
-        time_subsetter: &Subsetter,
+        opts: &CopyOptions,
     ) -> error_stack::Result<(), CopyError> {
         let public_name = self.public_name.as_deref().unwrap_or(&self.private_name);
 
-        let prior_var = if let Some(var) = private_file.variable(&self.private_name) {
+        let prior_var = if let Some(var) = inputs[0].ds.variable(&self.private_name) {
             var
         } else if self.required {
             return Err(CopyError::MissingReqVar(self.private_name.clone()).into());
@@ -466,16 +667,6 @@ impl CopySet for PriorProfCopy {
             return Ok(());
         };
 
-        let prior_data = expand_prior_profiles_from_file(
-            private_file,
-            &self.private_name,
-            PRIOR_INDEX_VARNAME,
-            self.target_vmr_unit
-                .as_deref()
-                .map(|unit| (Quantity::DMF, unit)),
-            time_subsetter,
-        )?;
-
         let mut new_dims = prior_var
             .dimensions()
             .iter()
@@ -508,18 +699,48 @@ impl CopySet for PriorProfCopy {
             }
         }
 
+        let spec = PublicVarSpec {
+            public_varname: &public_name,
+            long_name: &self.long_name,
+            attr_overrides: &attr_overrides,
+            attr_to_remove: &self.attr_to_remove,
+            fill_value: self.fill_value.or(opts.fill_value),
+            cutoff: None,
+        };
         copy_variable_new_data(
+            inputs,
             public_file,
-            &prior_var,
-            &public_name,
-            prior_data.view(),
+            &self.private_name,
             new_dims,
-            &self.long_name,
-            &attr_overrides,
-            &self.attr_to_remove,
+            &spec,
+            |input| {
+                expand_prior_profiles_from_file(
+                    input.ds,
+                    &self.private_name,
+                    PRIOR_INDEX_VARNAME,
+                    self.target_vmr_unit
+                        .as_deref()
+                        .map(|unit| (Quantity::DMF, unit)),
+                    &input.time_subsetter,
+                )
+            },
         )?;
         Ok(())
     }
+
+    fn plan(
+        &self,
+        private_file: &netcdf::File,
+        planned: &mut Vec<PlannedVar>,
+    ) -> error_stack::Result<(), CopyError> {
+        if private_file.variable(&self.private_name).is_some() {
+            let public_name = self.public_name.as_deref().unwrap_or(&self.private_name);
+            planned.push(PlannedVar::new(public_name, &self.private_name));
+        } else if self.required {
+            return Err(CopyError::MissingReqVar(self.private_name.clone()).into());
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Deserialize, PartialEq)]
@@ -592,6 +813,24 @@ pub(crate) struct XgasCopy {
     /// single attribute.
     #[serde(default = "crate::config::default_ancillary_infer")]
     traceability_scale: XgasAncillary,
+
+    /// Fill value to use for missing data in the Xgas and its ancillary
+    /// variables, overriding the top level `fill_value` setting.
+    #[serde(default)]
+    fill_value: Option<f64>,
+
+    /// Limits on the Xgas values; those outside them are replaced with the fill value.
+    #[serde(default)]
+    cutoff: Option<ValueCutoff>,
+
+    /// Limits on the Xgas error values; those outside them are replaced with the fill value.
+    #[serde(default)]
+    xgas_error_cutoff: Option<ValueCutoff>,
+
+    /// Set by discovery when another Xgas retrieves the same gas; the public
+    /// AK name is then inferred from the (suffixed) public Xgas name.
+    #[serde(skip)]
+    public_suffix: Option<String>,
 }
 
 impl XgasCopy {
@@ -632,6 +871,10 @@ impl XgasCopy {
             ak_attr_overrides: IndexMap::new(),
             slant_bin: XgasAncillary::Inferred(XgasAncInferOptions::new_required(None)),
             traceability_scale: XgasAncillary::Inferred(XgasAncInferOptions::new_required(None)),
+            fill_value: None,
+            cutoff: None,
+            xgas_error_cutoff: None,
+            public_suffix: None,
         }
     }
 
@@ -705,9 +948,25 @@ impl XgasCopy {
             ak_attr_overrides: rule.ak_attr_overrides.clone(),
             slant_bin,
             traceability_scale,
+            fill_value: rule.fill_value,
+            cutoff: rule.cutoff.clone(),
+            xgas_error_cutoff: rule.xgas_error_cutoff.clone(),
+            public_suffix: None,
         }
     }
 
+    /// Append `_{suffix}` to the public name of this Xgas, and make its inferred
+    /// AK public name follow the new Xgas name.
+    pub(crate) fn add_public_suffix(&mut self, suffix: &str) {
+        let public_name = self.xgas_public.as_deref().unwrap_or(&self.xgas);
+        self.xgas_public = Some(format!("{public_name}_{suffix}"));
+        self.public_suffix = Some(suffix.to_string());
+    }
+
+    pub(crate) fn xgas_public_varname(&self) -> &str {
+        self.xgas_public.as_deref().unwrap_or(&self.xgas)
+    }
+
     pub(crate) fn xgas_varname(&self) -> &str {
         &self.xgas
     }
@@ -734,6 +993,16 @@ impl XgasCopy {
         self.gas_long = name;
     }
 
+    /// Iterate over all the attribute overrides for this Xgas and its ancillary variables.
+    pub(crate) fn iter_attr_overrides(&self) -> impl Iterator<Item = (&String, &AttributeValue)> {
+        self.xgas_attr_overrides
+            .iter()
+            .chain(self.xgas_error_attr_overrides.iter())
+            .chain(self.prior_profile_attr_overrides.iter())
+            .chain(self.prior_xgas_attr_overrides.iter())
+            .chain(self.ak_attr_overrides.iter())
+    }
+
     fn airmass_name(&self) -> &str {
         "o2_7885_am_o2"
     }
@@ -773,10 +1042,15 @@ impl XgasCopy {
         (private_name, public_name)
     }
 
-    fn infer_ak_names(&self) -> (String, String) {
-        // these should be the same in the standard case
+    pub(crate) fn infer_ak_names(&self) -> (String, String) {
+        // these should be the same in the standard case, unless discovery
+        // had to add a suffix to tell apart two Xgases for the same gas
         let private_name = format!("ak_{}", self.xgas);
-        let public_name = private_name.clone();
+        let public_name = if self.public_suffix.is_some() {
+            format!("ak_{}", self.xgas_public_varname())
+        } else {
+            private_name.clone()
+        };
         (private_name, public_name)
     }
 
@@ -802,7 +1076,7 @@ impl XgasCopy {
 
     fn maybe_add_traceability_scale_attr(
         &self,
-        private_file: &netcdf::File,
+        inputs: &[PrivateInput],
         public_file: &netcdf::File,
         attr_overrides: &mut IndexMap<String, AttributeValue>,
     ) -> error_stack::Result<(), CopyError> {
@@ -812,7 +1086,7 @@ impl XgasCopy {
 
         let scale_varnames =
             self.traceability_scale
-                .get_var_names_opt(private_file, public_file, || {
+                .get_var_names_opt(inputs[0].ds, public_file, || {
                     self.infer_traceability_names()
                 });
 
@@ -821,7 +1095,17 @@ impl XgasCopy {
                 "Getting {} traceability scale from {private_scale_name}",
                 self.xgas
             );
-            let scale = get_traceability_scale(private_file, &private_scale_name)?;
+            // The scale becomes one attribute, so it must be the same in every private file.
+            let scale = get_traceability_scale(inputs[0].ds, &private_scale_name)?;
+            for input in &inputs[1..] {
+                let other_scale = get_traceability_scale(input.ds, &private_scale_name)?;
+                if other_scale != scale {
+                    return Err(CopyError::custom(format!(
+                        "Traceability scale variable '{private_scale_name}' differs between the private files ('{scale}' vs. '{other_scale}')"
+                    ))
+                    .into());
+                }
+            }
             if !scale.is_empty() {
                 attr_overrides.insert("wmo_or_analogous_scale".to_string(), scale.into());
             }
@@ -839,12 +1123,15 @@ impl XgasCopy {
 impl CopySet for XgasCopy {
     fn copy(
         &self,
-        private_file: &netcdf::File,
+        inputs: &[PrivateInput],
         public_file: &mut netcdf::FileMut,
-        time_subsetter: &Subsetter,
+        opts: &CopyOptions,
     ) -> error_stack::Result<(), CopyError> {
         // Copy the xgas and its error, get the WMO scale and make it an attribute, copy the prior profile,
         // prior Xgas, and averaging kernels.
+        let fill_value = self.fill_value.or(opts.fill_value);
+        // Which variables to copy is decided from the first private file
+        let private_file = inputs[0].ds;
 
         // Grab the units from the Xgas variable - we will need them to ensure that the
         // prior profile and prior Xgas are in the same units. Also go ahead and get+subset
@@ -903,39 +1190,47 @@ impl CopySet for XgasCopy {
             let new_desc = xgas_helpers::update_xgas_description(&xgas_var, self.gas_from_xgas())?;
             attr_overrides.insert("description".to_string(), new_desc);
         }
-        self.maybe_add_traceability_scale_attr(private_file, public_file, &mut attr_overrides)?;
+        self.maybe_add_traceability_scale_attr(inputs, public_file, &mut attr_overrides)?;
 
         // Now copy the Xgas itself
-        let public_xgas_name = self.xgas_public.as_deref().unwrap_or(&self.xgas);
-        copy_vmr_variable_from_dset::<f32, _>(
-            private_file,
-            public_file,
-            &self.xgas,
-            public_xgas_name,
-            time_subsetter,
-            &format!("column average {} mole fraction", self.gas_long),
-            attr_overrides,
-            &attr_to_remove,
-            &gas_units,
-        )
-        .change_context_lazy(|| {
-            CopyError::context(format!("copying Xgas variable '{}'", self.xgas))
-        })?;
+        let public_xgas_name = self.xgas_public_varname();
+        check_cutoff_has_fill(self.cutoff.as_ref(), fill_value, public_xgas_name)?;
+        let spec = PublicVarSpec {
+            public_varname: public_xgas_name,
+            long_name: &format!("column average {} mole fraction", self.gas_long),
+            attr_overrides: &attr_overrides,
+            attr_to_remove: &attr_to_remove,
+            fill_value,
+            cutoff: self.cutoff.as_ref(),
+        };
+        copy_vmr_variable_from_dset::<f32>(inputs, public_file, &self.xgas, &spec, &gas_units)
+            .change_context_lazy(|| {
+                CopyError::context(format!("copying Xgas variable '{}'", self.xgas))
+            })?;
 
         // And its error value
         let error_names_opt = self
             .xgas_error
             .get_var_names_opt(private_file, public_file, || self.infer_xgas_error_names());
         if let Some((private_error_name, public_error_name)) = error_names_opt {
-            copy_vmr_variable_from_dset::<f32, _>(
-                private_file,
+            check_cutoff_has_fill(
+                self.xgas_error_cutoff.as_ref(),
+                fill_value,
+                &public_error_name,
+            )?;
+            let spec = PublicVarSpec {
+                public_varname: &public_error_name,
+                long_name: &format!("column average {} mole fraction error", self.gas_long),
+                attr_overrides: &self.xgas_error_attr_overrides,
+                attr_to_remove: &attr_to_remove,
+                fill_value,
+                cutoff: self.xgas_error_cutoff.as_ref(),
+            };
+            copy_vmr_variable_from_dset::<f32>(
+                inputs,
                 public_file,
                 &private_error_name,
-                &public_error_name,
-                time_subsetter,
-                &format!("column average {} mole fraction error", self.gas_long),
-                self.xgas_error_attr_overrides.clone(),
-                &attr_to_remove,
+                &spec,
                 &gas_units,
             )
             .change_context_lazy(|| {
@@ -963,15 +1258,19 @@ impl CopySet for XgasCopy {
                 )
                 .into(),
             );
-            copy_vmr_variable_from_dset::<f32, _>(
-                private_file,
+            let spec = PublicVarSpec {
+                public_varname: &public_prxgas_name,
+                long_name: &format!("a priori {} column average", self.gas_long),
+                attr_overrides: &attr_overrides,
+                attr_to_remove: &attr_to_remove,
+                fill_value,
+                cutoff: None,
+            };
+            copy_vmr_variable_from_dset::<f32>(
+                inputs,
                 public_file,
                 &private_prxgas_name,
-                &public_prxgas_name,
-                time_subsetter,
-                &format!("a priori {} column average", self.gas_long),
-                attr_overrides,
-                &attr_to_remove,
+                &spec,
                 &gas_units,
             )
             .change_context_lazy(|| {
@@ -1008,7 +1307,7 @@ impl CopySet for XgasCopy {
                 .set_attr_overrides(attr_overrides);
 
             prior_copier
-                .copy(private_file, public_file, time_subsetter)
+                .copy(inputs, public_file, &opts.with_fill_value(fill_value))
                 .change_context_lazy(|| {
                     CopyError::context(format!(
                         "copying prior profile variable '{private_prior_name}' for Xgas {}",
@@ -1022,26 +1321,6 @@ impl CopySet for XgasCopy {
             .ak
             .get_var_names_opt(private_file, &public_file, || self.infer_ak_names());
         if let Some((private_ak_name, public_ak_name)) = opt {
-            let ak_var = private_file
-                .variable(&private_ak_name)
-                .ok_or_else(|| CopyError::MissingReqVar(private_ak_name.clone()))?;
-
-            let (expanded_aks, ak_extrap_flags) = expand_slant_xgas_binned_aks_from_file(
-                private_file,
-                &self.xgas,
-                self.airmass_name(),
-                &private_ak_name,
-                &self.slant_bin_name(),
-                time_subsetter,
-                Some(500),
-            )
-            .change_context_lazy(|| {
-                CopyError::context(format!(
-                    "expanding AK variable '{private_ak_name}' for Xgas {}",
-                    self.xgas
-                ))
-            })?;
-
             let extrap_flag_varname = format!("extrapolation_flags_{public_ak_name}");
 
             // Try to get the prior pressure level dimension to replace the AK level dim name,
@@ -1064,15 +1343,42 @@ impl CopySet for XgasCopy {
                 log::warn!("The 'ancillary_variables' attribute cannot be overridden for public variable {public_ak_name}")
             }
 
+            let spec = PublicVarSpec {
+                public_varname: &public_ak_name,
+                long_name: &format!("{} averaging kernel", self.gas_long),
+                attr_overrides: &attr_overrides,
+                attr_to_remove: &attr_to_remove,
+                fill_value,
+                cutoff: None,
+            };
+            // The extrapolation flags are only one value per spectrum, so they can be kept
+            // for all the private files and written at the end.
+            let mut ak_extrap_flags = vec![];
             copy_variable_new_data(
+                inputs,
                 public_file,
-                &ak_var,
-                &public_ak_name,
-                expanded_aks.into_dyn().view(),
+                &private_ak_name,
                 vec![TIME_DIM_NAME.to_string(), level_dim_name],
-                &format!("{} averaging kernel", self.gas_long),
-                &attr_overrides,
-                &attr_to_remove,
+                &spec,
+                |input| {
+                    let (expanded_aks, extrap_flags) = expand_slant_xgas_binned_aks_from_file(
+                        input.ds,
+                        &self.xgas,
+                        self.airmass_name(),
+                        &private_ak_name,
+                        &self.slant_bin_name(),
+                        &input.time_subsetter,
+                        Some(500),
+                    )
+                    .change_context_lazy(|| {
+                        CopyError::context(format!(
+                            "expanding AK variable '{private_ak_name}' for Xgas {}",
+                            self.xgas
+                        ))
+                    })?;
+                    ak_extrap_flags.extend(extrap_flags);
+                    Ok(expanded_aks.into_dyn())
+                },
             )
             .change_context_lazy(|| {
                 CopyError::context(format!(
@@ -1085,7 +1391,7 @@ impl CopySet for XgasCopy {
                 public_file,
                 &public_ak_name,
                 &extrap_flag_varname,
-                ak_extrap_flags.view(),
+                ArrayView1::from(&ak_extrap_flags),
             )
             .change_context_lazy(|| {
                 CopyError::context(format!(
@@ -1111,6 +1417,45 @@ impl CopySet for XgasCopy {
 
         Ok(())
     }
+
+    fn plan(
+        &self,
+        private_file: &netcdf::File,
+        planned: &mut Vec<PlannedVar>,
+    ) -> error_stack::Result<(), CopyError> {
+        if private_file.variable(&self.xgas).is_none() {
+            if self.required {
+                return Err(CopyError::MissingReqVar(self.xgas.clone()).into());
+            }
+            return Ok(());
+        }
+        planned.push(PlannedVar::new(self.xgas_public_varname(), &self.xgas));
+
+        // Same order as in `copy`, so that "only if first" variables are attributed
+        // to the same Xgas. The AKs also get a variable for their extrapolation flags.
+        type InferNames = fn(&XgasCopy) -> (String, String);
+        let ancillaries: [(&XgasAncillary, InferNames, bool); 4] = [
+            (&self.xgas_error, Self::infer_xgas_error_names, false),
+            (&self.prior_xgas, Self::infer_prior_xgas_names, false),
+            (&self.prior_profile, Self::infer_prior_prof_names, false),
+            (&self.ak, Self::infer_ak_names, true),
+        ];
+        for (anc, infer_names, has_extrap_flags) in ancillaries {
+            let names = anc.get_var_names_opt_with(
+                private_file,
+                |name| is_planned(planned, name),
+                || infer_names(self),
+            );
+            if let Some((private_name, public_name)) = names {
+                let extrap_flags = has_extrap_flags.then(|| {
+                    PlannedVar::new(format!("extrapolation_flags_{public_name}"), &private_name)
+                });
+                planned.push(PlannedVar::new(public_name, private_name));
+                planned.extend(extrap_flags);
+            }
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Deserialize, PartialEq, Eq, Clone)]
@@ -1176,7 +1521,26 @@ impl XgasAncillary {
     where
         F: Fn() -> (String, String),
     {
-        if !self.do_copy(private_file, public_file, || infer_names_fxn()) {
+        self.get_var_names_opt_with(
+            private_file,
+            |name| public_file.variable(name).is_some(),
+            infer_names_fxn,
+        )
+    }
+
+    /// Like `get_var_names_opt`, but `is_in_pub_file` decides whether a public variable
+    /// already exists, so this can be used when there is no public file.
+    fn get_var_names_opt_with<P, F>(
+        &self,
+        private_file: &netcdf::File,
+        is_in_pub_file: P,
+        infer_names_fxn: F,
+    ) -> Option<(String, String)>
+    where
+        P: Fn(&str) -> bool,
+        F: Fn() -> (String, String),
+    {
+        if !self.do_copy(private_file, is_in_pub_file, || infer_names_fxn()) {
             None
         } else {
             Some(self.get_var_names(infer_names_fxn))
@@ -1187,13 +1551,14 @@ impl XgasAncillary {
     /// This checks if the variable should always be copied,
     /// never be copied, or if that depends on whether it was
     /// previously copied,
-    fn do_copy<F>(
+    fn do_copy<P, F>(
         &self,
         private_file: &netcdf::File,
-        public_file: &netcdf::File,
+        is_in_pub_file: P,
         infer_names_fxn: F,
     ) -> bool
     where
+        P: Fn(&str) -> bool,
         F: Fn() -> (String, String),
     {
         match self {
@@ -1208,8 +1573,7 @@ impl XgasAncillary {
                     return false;
                 }
 
-                let is_in_pub_file = public_file.variable(&public_name).is_some();
-                if opts.only_if_first && is_in_pub_file {
+                if opts.only_if_first && is_in_pub_file(&public_name) {
                     log::debug!("Not copying variable '{private_name}' as public variable '{public_name}' was already copied");
                     return false;
                 }
@@ -1222,8 +1586,7 @@ impl XgasAncillary {
                 public_name,
             } => {
                 let public_name = public_name.as_deref().unwrap_or(&private_name);
-                let is_in_pub_file = public_file.variable(public_name).is_some();
-                if *only_if_first && is_in_pub_file {
+                if *only_if_first && is_in_pub_file(public_name) {
                     log::debug!("Not copying variable '{private_name}' as public variable '{public_name}' was already copied");
                     false
                 } else {
@@ -1281,33 +1644,107 @@ impl XgasAncInferOptions {
 #[derive(Debug, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
 pub(crate) enum ComputedVariable {
-    PriorSource { public_name: Option<String> },
+    PriorSource {
+        public_name: Option<String>,
+        /// Fill value for this variable, overriding the top level `fill_value` setting.
+        /// If neither is given, this defaults to the minimum value of an 8-bit integer.
+        #[serde(default)]
+        fill_value: Option<f64>,
+    },
+}
+
+impl ComputedVariable {
+    /// The name of this variable in the public file.
+    pub(crate) fn public_name(&self) -> &str {
+        match self {
+            ComputedVariable::PriorSource { public_name, .. } => {
+                public_name.as_deref().unwrap_or("apriori_data_source")
+            }
+        }
+    }
+
+    /// Make the global attribute that summarizes this variable for the whole file, once it
+    /// has been written to `public_file`. Returns `None` if the variable was not written or
+    /// has no summary.
+    pub(crate) fn summary_global_attr(
+        &self,
+        public_file: &netcdf::File,
+    ) -> error_stack::Result<Option<(String, String)>, CopyError> {
+        match self {
+            ComputedVariable::PriorSource { .. } => {
+                let summary = summarize_geos_versions(public_file, self.public_name())?;
+                Ok(summary.map(|s| ("apriori_data_sources".to_string(), s)))
+            }
+        }
+    }
 }
 
 impl CopySet for ComputedVariable {
     fn copy(
         &self,
-        private_file: &netcdf::File,
+        inputs: &[PrivateInput],
         public_file: &mut netcdf::FileMut,
-        time_subsetter: &Subsetter,
+        opts: &CopyOptions,
+    ) -> error_stack::Result<(), CopyError> {
+        match self {
+            ComputedVariable::PriorSource {
+                fill_value: var_fill_value,
+                ..
+            } => add_geos_version_variable(
+                inputs,
+                public_file,
+                self.public_name(),
+                var_fill_value.or(opts.fill_value),
+                opts.classic_model,
+            ),
+        }
+    }
+
+    fn plan(
+        &self,
+        _private_file: &netcdf::File,
+        planned: &mut Vec<PlannedVar>,
     ) -> error_stack::Result<(), CopyError> {
         match self {
-            ComputedVariable::PriorSource { public_name } => {
-                let pubname = public_name.as_deref().unwrap_or("apriori_data_source");
-                add_geos_version_variable(private_file, public_file, pubname, time_subsetter)
+            ComputedVariable::PriorSource { .. } => {
+                planned.push(PlannedVar::new(self.public_name(), PRIOR_INDEX_VARNAME));
             }
         }
+        Ok(())
     }
 }
 
+/// Summarize the traceability scales of the variables in the root group of `public_file`
+/// as "variable: scale" for each variable with a `wmo_or_analogous_scale` attribute,
+/// separated by semicolons. Returns `None` if no variable has that attribute.
+pub(crate) fn summarize_traceability_scales(
+    public_file: &netcdf::File,
+) -> error_stack::Result<Option<String>, CopyError> {
+    let mut scales = vec![];
+    for var in public_file.variables() {
+        if var.attribute("wmo_or_analogous_scale").is_some() {
+            let scale = get_string_attr(&var, "wmo_or_analogous_scale")
+                .change_context_lazy(|| CopyError::context("summarizing traceability scales"))?;
+            scales.push(format!("{}: {scale}", var.name()));
+        }
+    }
+
+    if scales.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(scales.join("; ")))
+    }
+}
+
+/// Copy the global attributes from the first of `inputs` and add to its history.
 pub(crate) fn copy_attributes(
-    private_file: &netcdf::File,
+    inputs: &[PrivateInput],
     public_file: &mut netcdf::FileMut,
     attributes: &[CopyGlobalAttr],
 ) -> error_stack::Result<(), CopyError> {
-    add_history_attr(private_file, public_file)?;
+    add_history_attr(inputs, public_file)?;
     for attr in attributes {
-        attr.copy(private_file, public_file)?;
+        attr.copy(inputs[0].ds, public_file)?;
     }
     Ok(())
 }
@@ -1350,9 +1787,10 @@ impl CopyGlobalAttr {
 }
 
 fn add_history_attr(
-    private_file: &netcdf::File,
+    inputs: &[PrivateInput],
     public_file: &mut netcdf::FileMut,
 ) -> error_stack::Result<(), CopyError> {
+    let private_file = inputs[0].ds;
     let mut history = if private_file.attribute("history").is_none() {
         "".to_string()
     } else {
@@ -1362,17 +1800,29 @@ fn add_history_attr(
         s
     };
 
-    let priv_name = private_file
-        .path()
-        .change_context_lazy(|| CopyError::context("getting path to the private file"))?;
-    let priv_name = priv_name
-        .file_name()
-        .ok_or_else(|| CopyError::custom("Could not get file base name of the private file"))?
-        .to_string_lossy();
+    let mut priv_names = vec![];
+    for input in inputs {
+        let priv_path = input
+            .ds
+            .path()
+            .change_context_lazy(|| CopyError::context("getting path to the private file"))?;
+        let priv_name = priv_path
+            .file_name()
+            .ok_or_else(|| CopyError::custom("Could not get file base name of the private file"))?
+            .to_string_lossy()
+            .to_string();
+        priv_names.push(priv_name);
+    }
+    let what = if priv_names.len() == 1 {
+        "file"
+    } else {
+        "files"
+    };
+    let priv_names = priv_names.join(", ");
     let program_version = env!("CARGO_PKG_VERSION");
     let now = chrono::Utc::now();
     history.push_str(&format!(
-        "{}: generated public file from private/engineering file {priv_name} with {PROGRAM_NAME} from GGG-RS v{program_version}",
+        "{}: generated public file from private/engineering {what} {priv_names} with {PROGRAM_NAME} from GGG-RS v{program_version}",
         now.format("%Y-%m-%d %H:%M:%S %Z")
     ));
     public_file
@@ -1424,6 +1874,65 @@ where
 mod tests {
     use super::*;
 
+    /// A private file to copy variables from and a public file to copy them into.
+    struct CopyFixture {
+        private_path: std::path::PathBuf,
+        public_path: std::path::PathBuf,
+    }
+
+    impl CopyFixture {
+        /// Write `<name>-test.private.nc` under `test-data/outputs/write-public-netcdf-<name>`
+        /// with a time dimension of length `ntime`; add variables with [`CopyFixture::with_var`].
+        fn new(name: &str, ntime: usize) -> Self {
+            let output_dir = ggg_rs::test_utils::output_dir(&format!("write-public-netcdf-{name}"));
+            let fixture = Self {
+                private_path: output_dir.join(format!("{name}-test.private.nc")),
+                public_path: output_dir.join(format!("{name}-test.public.nc")),
+            };
+            let mut private_ds = netcdf::create(&fixture.private_path).unwrap();
+            private_ds.add_dimension(TIME_DIM_NAME, ntime).unwrap();
+            fixture
+        }
+
+        /// Add a time variable to the private file, with a `_FillValue` if `fill_value` is given.
+        fn with_var<T: netcdf::NcTypeDescriptor + Copy>(
+            self,
+            varname: &str,
+            data: &[T],
+            fill_value: Option<T>,
+        ) -> Self {
+            let mut private_ds = netcdf::append(&self.private_path).unwrap();
+            let mut var = private_ds
+                .add_variable::<T>(varname, &[TIME_DIM_NAME])
+                .unwrap();
+            if let Some(fill) = fill_value {
+                var.set_fill_value(fill).unwrap();
+            }
+            var.put_values(data, netcdf::Extents::All).unwrap();
+            self
+        }
+
+        fn open_private(&self) -> netcdf::File {
+            netcdf::open(&self.private_path).unwrap()
+        }
+
+        /// Create the public file with a time dimension of length `ntime`.
+        fn create_public(&self, ntime: usize, options: netcdf::Options) -> netcdf::FileMut {
+            let mut public_ds = netcdf::create_with(&self.public_path, options).unwrap();
+            public_ds.add_dimension(TIME_DIM_NAME, ntime).unwrap();
+            public_ds
+        }
+    }
+
+    /// Copy from only `private_ds`, keeping the times where `flags` is 0.
+    fn single_input<'a>(private_ds: &'a netcdf::File, flags: &[i32]) -> PrivateInput<'a> {
+        PrivateInput {
+            ds: private_ds,
+            time_subsetter: Subsetter::from_flag(ndarray::arr1(flags).view()),
+            time_offset: 0,
+        }
+    }
+
     #[test]
     fn test_de_aux_var() {
         let toml_str = r#"private_name = "time"
@@ -1449,13 +1958,13 @@ mod tests {
     fn test_de_aux_var_attrs() {
         let toml_str = r#"private_name = "day"
         long_name = "day of year"
-        attr_overrides = {units = "Julian day", description = "1-based day of year"}
+        attr_overrides = {units = "1", description = "1-based day of year"}
         attr_to_remove = ["vmin", "vmax"]
         "#;
         let aux_de: AuxVarCopy = toml::from_str(toml_str).expect("deserialization should work");
 
         let aux_val = AuxVarCopy::new_keep_attrs("day", "day of year", true)
-            .with_attr_override("units", "Julian day")
+            .with_attr_override("units", "1")
             .with_attr_override("description", "1-based day of year")
             .with_attr_remove("vmin")
             .with_attr_remove("vmax");
@@ -1475,6 +1984,206 @@ mod tests {
         assert_eq!(aux_de, aux_val);
     }
 
+    #[test]
+    fn test_de_aux_var_fill_value() {
+        let toml_str = r#"private_name = "pout"
+        long_name = "surface pressure"
+        fill_value = -9999.0
+        "#;
+        let aux_de: AuxVarCopy = toml::from_str(toml_str).expect("deserialization should work");
+        let aux_val = AuxVarCopy::new("pout", "surface pressure", true).with_fill_value(-9999.0);
+        assert_eq!(aux_de, aux_val);
+    }
+
+    #[test]
+    fn test_aux_var_fill_value() {
+        let data = [1000.0f32, -1.0, f32::NAN, 990.0];
+        let fixture = CopyFixture::new("fill-value", 4)
+            .with_var("pout", &data, Some(-1.0f32))
+            .with_var("tout", &data, Some(-1.0f32));
+        let private_ds = fixture.open_private();
+        let mut public_ds = fixture.create_public(4, netcdf::Options::NETCDF4);
+        let inputs = [single_input(&private_ds, &[0, 0, 0, 0])];
+
+        // The first should use the global fill value, the second its own.
+        let opts = CopyOptions {
+            fill_value: Some(-999.0),
+            classic_model: false,
+        };
+        AuxVarCopy::new("pout", "surface pressure", true)
+            .copy(&inputs, &mut public_ds, &opts)
+            .unwrap();
+        AuxVarCopy::new("tout", "surface temperature", true)
+            .with_fill_value(-9999.0)
+            .copy(&inputs, &mut public_ds, &opts)
+            .unwrap();
+
+        for (varname, fill) in [("pout", -999.0f32), ("tout", -9999.0)] {
+            let var = public_ds.variable(varname).unwrap();
+            assert_eq!(var.fill_value::<f32>().unwrap(), Some(fill));
+            let data = var.get_values::<f32, _>(netcdf::Extents::All).unwrap();
+            assert_eq!(data, vec![1000.0, fill, fill, 990.0]);
+        }
+    }
+
+    #[test]
+    fn test_aux_var_cutoff() {
+        let cutoff: ValueCutoff = toml::from_str("min = 0.0\nmissing_values = [5.0]").unwrap();
+        assert_eq!(
+            cutoff,
+            ValueCutoff {
+                min: Some(0.0),
+                max: None,
+                missing_values: vec![5.0]
+            }
+        );
+
+        let fixture = CopyFixture::new("cutoff", 5).with_var(
+            "hout",
+            &[1.0f64, -999.0, 5.0, 1e30, 50.0],
+            None,
+        );
+        let private_ds = fixture.open_private();
+        let mut public_ds = fixture.create_public(4, netcdf::Options::NETCDF4);
+        // Drop the last point to check that the cutoff is applied after subsetting
+        let inputs = [single_input(&private_ds, &[0, 0, 0, 0, 1])];
+        let cutoff = ValueCutoff {
+            min: Some(0.0),
+            max: Some(1e29),
+            missing_values: vec![],
+        };
+
+        let opts = CopyOptions {
+            fill_value: None,
+            classic_model: false,
+        };
+        let err = AuxVarCopy::new("hout", "surface humidity", true)
+            .with_cutoff(cutoff.clone())
+            .copy(&inputs, &mut public_ds, &opts)
+            .expect_err("a cutoff without a fill value should be an error");
+        assert!(err.to_string().contains("requires a fill value"), "{err}");
+
+        let opts = opts.with_fill_value(Some(-9999.0));
+        AuxVarCopy::new("hout", "surface humidity", true)
+            .with_cutoff(cutoff)
+            .copy(&inputs, &mut public_ds, &opts)
+            .unwrap();
+
+        let data = public_ds
+            .variable("hout")
+            .unwrap()
+            .get_values::<f64, _>(netcdf::Extents::All)
+            .unwrap();
+        assert_eq!(data, vec![1.0, -9999.0, 5.0, -9999.0]);
+    }
+
+    #[test]
+    fn test_plan_output_vars() {
+        let fixture = [
+            "solzen",
+            "xco2",
+            "xco2_error",
+            "prior_xco2",
+            "prior_1co2",
+            "ak_xco2",
+            "xco2_x2019",
+            "xco2_error_x2019",
+            "prior_xco2_x2019",
+            "ak_xco2_x2019",
+        ]
+        .into_iter()
+        .fold(CopyFixture::new("plan", 1), |fixture, varname| {
+            fixture.with_var(varname, &[0.0f32], None)
+        });
+        let private_ds = fixture.open_private();
+
+        let mut planned = vec![];
+        AuxVarCopy::new("solzen", "solar zenith angle", true)
+            .plan(&private_ds, &mut planned)
+            .unwrap();
+        AuxVarCopy::new("azim", "solar azimuth angle", false)
+            .plan(&private_ds, &mut planned)
+            .unwrap();
+        XgasCopy::new("xco2", "co2", "carbon dioxide")
+            .plan(&private_ds, &mut planned)
+            .unwrap();
+        // The second CO2 Xgas should not repeat the prior profile
+        XgasCopy::new("xco2_x2019", "co2", "carbon dioxide")
+            .plan(&private_ds, &mut planned)
+            .unwrap();
+        let err = AuxVarCopy::new("azim", "solar azimuth angle", true)
+            .plan(&private_ds, &mut planned)
+            .unwrap_err();
+        assert!(
+            matches!(err.current_context(), CopyError::MissingReqVar(v) if v == "azim"),
+            "{err:?}"
+        );
+        let names = planned
+            .iter()
+            .map(|v| (v.public_name.as_str(), v.private_name.as_str()))
+            .collect_vec();
+        assert_eq!(
+            names,
+            vec![
+                ("solzen", "solzen"),
+                ("xco2", "xco2"),
+                ("xco2_error", "xco2_error"),
+                ("prior_xco2", "prior_xco2"),
+                ("prior_co2", "prior_1co2"),
+                ("ak_xco2", "ak_xco2"),
+                ("extrapolation_flags_ak_xco2", "ak_xco2"),
+                ("xco2_x2019", "xco2_x2019"),
+                ("xco2_error_x2019", "xco2_error_x2019"),
+                ("prior_xco2_x2019", "prior_xco2_x2019"),
+                ("ak_xco2_x2019", "ak_xco2_x2019"),
+                ("extrapolation_flags_ak_xco2_x2019", "ak_xco2_x2019"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_aux_var_classic_model() {
+        let fixture = CopyFixture::new("classic", 2)
+            .with_var("flag", &[0i32, 1], None)
+            .with_var("count", &[1u16, 2], None);
+        let private_ds = fixture.open_private();
+        {
+            let mut public_ds =
+                fixture.create_public(2, netcdf::Options::NETCDF4 | netcdf::Options::CLASSIC);
+            let inputs = [single_input(&private_ds, &[0, 0])];
+            let opts = CopyOptions {
+                fill_value: None,
+                classic_model: true,
+            };
+
+            AuxVarCopy::new("flag", "flag", true)
+                .copy(&inputs, &mut public_ds, &opts)
+                .expect("an i32 variable should be allowed in a classic file");
+            let err = AuxVarCopy::new("count", "count", true)
+                .copy(&inputs, &mut public_ds, &opts)
+                .expect_err("a u16 variable should not be allowed in a classic file");
+            assert!(matches!(
+                err.current_context(),
+                CopyError::NotClassicType { .. }
+            ));
+
+            // The classic model must also be enforced by the file itself
+            assert!(public_ds
+                .add_attribute("bad", AttributeValue::Strs(vec!["a".into(), "b".into()]))
+                .is_err());
+        }
+
+        // The file should still open and hold the classic-compatible variable
+        let public_ds = netcdf::open(&fixture.public_path).unwrap();
+        let data = public_ds
+            .variable("flag")
+            .unwrap()
+            .get_values::<i32, _>(netcdf::Extents::All)
+            .unwrap();
+        assert_eq!(data, vec![0, 1]);
+        assert!(public_ds.variable("count").is_none());
+    }
+
     #[test]
     fn test_de_xgas_anc_inferred() {
         let toml_str = r#"type = "inferred""#;
@@ -1627,4 +2336,30 @@ mod tests {
         xgas_expected.ak = XgasAncillary::Omit;
         assert_eq!(xgas_de, xgas_expected);
     }
+
+    #[test]
+    fn test_xgas_from_discovery_cutoff() {
+        let toml_str = r#"suffix = "mir"
+        fill_value = 9.9e35
+        cutoff = { min = 0.0 }
+        xgas_error_cutoff = { max = 1.0 }"#;
+        let rule: XgasMatchRule =
+            toml::from_str(toml_str).expect("deserialization should not fail");
+        let xgas =
+            XgasCopy::new_from_discovery("xco2_mir", None::<&str>, "co2", "carbon dioxide", &rule);
+        assert_eq!(
+            xgas.cutoff,
+            Some(ValueCutoff {
+                min: Some(0.0),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            xgas.xgas_error_cutoff,
+            Some(ValueCutoff {
+                max: Some(1.0),
+                ..Default::default()
+            })
+        );
+    }
 }
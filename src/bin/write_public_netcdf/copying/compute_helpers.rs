@@ -14,7 +14,9 @@ use crate::{
     TIME_DIM_NAME,
 };
 
-use super::{copy_utils::NcChar, CopyError, Subsetter};
+use super::{
+    copy_helpers::cast_fill_value, copy_utils::NcChar, CopyError, PrivateInput, Subsetter,
+};
 
 const FPIT_MET_FPIT_CHM: i8 = 0;
 const IT_MET_IT_CHM: i8 = 1;
@@ -35,12 +37,15 @@ static GEOS_FLAG_MEANINGS: &'static [&'static str] = &[
 ];
 
 pub(super) fn add_geos_version_variable(
-    private_file: &netcdf::File,
+    inputs: &[PrivateInput],
     public_file: &mut netcdf::FileMut,
     public_varname: &str,
-    time_subsetter: &Subsetter,
+    fill_value: Option<f64>,
+    classic_model: bool,
 ) -> error_stack::Result<(), CopyError> {
-    let source_flags = make_geos_version_array(private_file, PRIOR_INDEX_VARNAME, time_subsetter)?;
+    let fill_value = fill_value
+        .and_then(|fv| cast_fill_value::<i8>(fv, public_varname))
+        .unwrap_or(i8::MIN);
 
     let mut var = public_file
         .add_variable::<i8>(public_varname, &[TIME_DIM_NAME])
@@ -49,7 +54,7 @@ pub(super) fn add_geos_version_variable(
                 "creating GEOS version variable, '{public_varname}'"
             ))
         })?;
-    var.set_fill_value(i8::MIN).change_context_lazy(|| {
+    var.set_fill_value(fill_value).change_context_lazy(|| {
         CopyError::context(format!(
             "setting fill value for GEOS version variable, '{public_varname}'"
         ))
@@ -77,24 +82,68 @@ pub(super) fn add_geos_version_variable(
             ))
         })?;
 
-    var.put_attribute("flag_meanings", GEOS_FLAG_MEANINGS)
-        .change_context_lazy(|| {
-            CopyError::context(format!(
-                "adding 'flag_meanings' attribute to GEOS version variable, '{public_varname}'"
-            ))
-        })?;
+    // The classic model has no string type, so the meanings must go in one character attribute.
+    let flag_meanings_result = if classic_model {
+        var.put_attribute("flag_meanings", GEOS_FLAG_MEANINGS.join(" "))
+    } else {
+        var.put_attribute("flag_meanings", GEOS_FLAG_MEANINGS)
+    };
+    flag_meanings_result.change_context_lazy(|| {
+        CopyError::context(format!(
+            "adding 'flag_meanings' attribute to GEOS version variable, '{public_varname}'"
+        ))
+    })?;
 
-    // Write data
-    var.put(source_flags.view(), Extents::All)
+    // Write data, one private file at a time
+    for input in inputs {
+        let source_flags =
+            make_geos_version_array(input.ds, PRIOR_INDEX_VARNAME, &input.time_subsetter)?;
+        var.put(
+            source_flags.view(),
+            input.public_extents(source_flags.shape(), Some(0)),
+        )
         .change_context_lazy(|| {
             CopyError::context(format!(
                 "writing data to GEOS version variable '{public_varname}'"
             ))
         })?;
+    }
 
     Ok(())
 }
 
+/// Summarize the GEOS version variable `public_varname` in the public file as the meaning of
+/// each a priori source flag it contains, followed by the number of spectra with that flag,
+/// e.g. "all a priori from GEOS IT (120 spectra)". Returns `None` if the variable is not in
+/// the public file or has no valid flags.
+pub(super) fn summarize_geos_versions(
+    public_file: &netcdf::File,
+    public_varname: &str,
+) -> error_stack::Result<Option<String>, CopyError> {
+    let Some(var) = public_file.variable(public_varname) else {
+        return Ok(None);
+    };
+    let flags = var
+        .get_values::<i8, _>(Extents::All)
+        .change_context_lazy(|| {
+            CopyError::context(format!("reading GEOS version variable '{public_varname}'"))
+        })?;
+
+    let summary = GEOS_FLAGS
+        .iter()
+        .zip(GEOS_FLAG_MEANINGS)
+        .filter_map(|(flag, meaning)| {
+            let n = flags.iter().filter(|&f| f == flag).count();
+            (n > 0).then(|| format!("{meaning} ({n} spectra)"))
+        })
+        .join("; ");
+    if summary.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(summary))
+    }
+}
+
 fn make_geos_version_array(
     private_file: &netcdf::File,
     prior_index_varname: &str,
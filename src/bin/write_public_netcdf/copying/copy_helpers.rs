@@ -2,71 +2,110 @@
 use std::ops::Mul;
 
 use error_stack::ResultExt;
-use ggg_rs::nc_utils::NcArray;
+use ggg_rs::{
+    nc_utils::{add_needed_dims, copy_attributes_except, NcArray},
+    units::{long_name_with_unit, unit_from_long_name},
+};
 use indexmap::IndexMap;
 use itertools::Itertools;
-use ndarray::ArrayViewD;
-use netcdf::{AttributeValue, Extents, NcTypeDescriptor};
-use num_traits::Zero;
+use ndarray::{Array, ArrayD, Dimension};
+use netcdf::{types::NcVariableType, AttributeValue, Extents, NcTypeDescriptor};
+use num_traits::{NumCast, Zero};
 
 use crate::TIME_DIM_NAME;
 
 use super::{
-    add_needed_dims, add_needed_new_dims, convert_array_units, find_subset_dim, get_string_attr,
-    CopyError, Subsetter,
+    add_needed_new_dims, convert_array_units, find_subset_dim, get_string_attr, inputs_to_copy,
+    CopyError, PrivateInput, ValueCutoff,
 };
 
+/// How to write one public variable: its name, attributes, and which values to treat as missing.
+pub(super) struct PublicVarSpec<'a> {
+    pub(super) public_varname: &'a str,
+    /// Value for the `long_name` attribute.
+    pub(super) long_name: &'a str,
+    /// Attributes to add, or to replace the private variable's values with.
+    pub(super) attr_overrides: &'a IndexMap<String, AttributeValue>,
+    /// Private variable attributes not to copy.
+    pub(super) attr_to_remove: &'a [String],
+    /// Value to replace missing points with. If `None`, the netCDF default fill
+    /// value is left in place and no points are masked.
+    pub(super) fill_value: Option<f64>,
+    /// Limits outside of which values are also treated as missing.
+    pub(super) cutoff: Option<&'a ValueCutoff>,
+}
+
 /// Helper function that copies a variable with mole fraction data.
 /// This ensures that the units match `target_unit`, which should
 /// normally be the unit that the Xgas values are in.
 pub(super) fn copy_vmr_variable_from_dset<
-    T: Copy + Zero + NcTypeDescriptor + Mul<Output = T> + From<f32>,
-    S: AsRef<str>,
+    T: Copy + Zero + NcTypeDescriptor + Mul<Output = T> + From<f32> + PartialOrd + NumCast,
 >(
-    private_file: &netcdf::File,
+    inputs: &[PrivateInput],
     public_file: &mut netcdf::FileMut,
     private_varname: &str,
-    public_varname: &str,
-    time_subsetter: &Subsetter,
-    long_name: &str,
-    mut attr_overrides: IndexMap<String, AttributeValue>,
-    attr_to_remove: &[S],
+    spec: &PublicVarSpec,
     target_unit: &str,
 ) -> error_stack::Result<(), CopyError> {
+    let public_varname = spec.public_varname;
     log::debug!(
         "Copying private variable '{private_varname}' to public variable '{public_varname}'"
     );
-    let private_var = private_file
-        .variable(private_varname)
-        .ok_or_else(|| CopyError::MissingReqVar(private_varname.to_string()))?;
-    let var_unit = get_string_attr(&private_var, "units").change_context_lazy(|| {
-        CopyError::context(format!(
-            "getting units for {private_varname} to scale to the primary Xgas variable unit"
-        ))
-    })?;
-    let var_unit = if var_unit.is_empty() {
-        log::info!(
-            "Units for {private_varname} were an empty string, assuming this should be unscaled mole fraction",
-        );
-        "parts"
-    } else {
-        &var_unit
-    };
+    let first_var = inputs[0].variable(private_varname)?;
+    let time_axis = find_subset_dim(&first_var, TIME_DIM_NAME);
+    let public_fill = spec
+        .fill_value
+        .and_then(|fv| cast_fill_value::<T>(fv, public_varname));
+    let mut public_var =
+        copy_var_pre_write_helper::<T>(public_file, &first_var, public_varname, None)?;
+    set_public_fill_value(&mut public_var, public_fill)?;
 
-    let data = private_var
-        .get::<T, _>(Extents::All)
-        .change_context_lazy(|| {
-            CopyError::context(format!("reading variable '{private_varname}'"))
+    for input in inputs_to_copy(inputs, time_axis) {
+        let private_var = input.variable(private_varname)?;
+        let var_unit = get_string_attr(&private_var, "units").change_context_lazy(|| {
+            CopyError::context(format!(
+                "getting units for {private_varname} to scale to the primary Xgas variable unit"
+            ))
         })?;
-    let do_subset_along = find_subset_dim(&private_var, TIME_DIM_NAME);
-    let data = if let Some(idim) = do_subset_along {
-        time_subsetter.subset_nd_array(data.view(), idim)?
-    } else {
-        data
-    };
+        let var_unit = if var_unit.is_empty() {
+            log::info!(
+                "Units for {private_varname} were an empty string, assuming this should be unscaled mole fraction",
+            );
+            "parts"
+        } else {
+            &var_unit
+        };
+
+        let data = private_var
+            .get::<T, _>(Extents::All)
+            .change_context_lazy(|| {
+                CopyError::context(format!("reading variable '{private_varname}'"))
+            })?;
+        let data = if let Some(idim) = time_axis {
+            input.time_subsetter.subset_nd_array(data.view(), idim)?
+        } else {
+            data
+        };
 
-    let data = convert_array_units(data, &var_unit, ggg_rs::units::Quantity::DMF, target_unit)
-           .change_context_lazy(|| CopyError::context(format!("getting conversion factor for {private_varname} to scale to the primary Xgas variable unit")))?;
+        // Find the masked points before the unit conversion, otherwise the private fill
+        // values will have been scaled and will no longer be recognized.
+        let mask = public_fill
+            .map(|_| find_masked_points(&data, private_fill_value(&private_var), spec.cutoff));
+
+        let mut data = convert_array_units(data, &var_unit, ggg_rs::units::Quantity::DMF, target_unit)
+            .change_context_lazy(|| CopyError::context(format!("getting conversion factor for {private_varname} to scale to the primary Xgas variable unit")))?;
+        if let (Some(fill), Some(mask)) = (public_fill, mask) {
+            fill_masked_points(&mut data, &mask, fill);
+        }
+
+        public_var
+            .put(data.view(), input.public_extents(data.shape(), time_axis))
+            .change_context_lazy(|| {
+                CopyError::context(format!("writing variable '{public_varname}'"))
+            })?;
+    }
+
+    let mut attr_overrides = spec.attr_overrides.clone();
     if attr_overrides
         .insert("units".to_string(), target_unit.into())
         .is_some()
@@ -75,212 +114,234 @@ pub(super) fn copy_vmr_variable_from_dset<
             "The 'units' attribute cannot be overridden for public variable {public_varname}"
         )
     }
-
-    let mut public_var =
-        copy_var_pre_write_helper::<T>(public_file, &private_var, public_varname, None)?;
-    public_var
-        .put(data.view(), Extents::All)
-        .change_context_lazy(|| {
-            CopyError::context(format!("writing variable '{public_varname}'"))
-        })?;
-
     copy_var_attr_write_helper(
-        &private_var,
+        &first_var,
         &mut public_var,
-        long_name,
+        spec.long_name,
         &attr_overrides,
-        attr_to_remove,
+        spec.attr_to_remove,
     )?;
 
     Ok(())
 }
 
-pub(super) fn copy_variable_new_data<S: AsRef<str>>(
+/// Write data computed from the private variable `private_varname` to a new public variable
+/// with dimensions `dims`. `compute` is called for each private file in turn and must return
+/// that file's subset of the data. The cutoff in `spec` is not used, since the data are no
+/// longer the private values.
+pub(super) fn copy_variable_new_data<F>(
+    inputs: &[PrivateInput],
     public_file: &mut netcdf::FileMut,
-    private_var: &netcdf::Variable,
-    public_varname: &str,
-    data: ArrayViewD<f32>,
+    private_varname: &str,
     dims: Vec<String>,
-    long_name: &str,
-    attr_overrides: &IndexMap<String, AttributeValue>,
-    attr_to_remove: &[S],
-) -> error_stack::Result<(), CopyError> {
+    spec: &PublicVarSpec,
+    mut compute: F,
+) -> error_stack::Result<(), CopyError>
+where
+    F: FnMut(&PrivateInput) -> error_stack::Result<ArrayD<f32>, CopyError>,
+{
+    let public_varname = spec.public_varname;
     log::debug!(
-        "Transforming private variable '{}' into public variable '{public_varname}'",
-        private_var.name()
+        "Transforming private variable '{private_varname}' into public variable '{public_varname}'"
     );
+    let first_var = inputs[0].variable(private_varname)?;
+    let time_axis = dims.iter().position(|d| d == TIME_DIM_NAME);
+    let public_fill = spec
+        .fill_value
+        .and_then(|fv| cast_fill_value::<f32>(fv, public_varname));
     let mut public_var =
-        copy_var_pre_write_helper::<f32>(public_file, private_var, public_varname, Some(dims))?;
-    public_var.put(data, Extents::All).change_context_lazy(|| {
-        CopyError::context(format!("writing variable '{public_varname}'"))
-    })?;
+        copy_var_pre_write_helper::<f32>(public_file, &first_var, public_varname, Some(dims))?;
+    set_public_fill_value(&mut public_var, public_fill)?;
+
+    for input in inputs_to_copy(inputs, time_axis) {
+        let private_var = input.variable(private_varname)?;
+        let mut data = compute(input)?;
+        if let Some(fill) = public_fill {
+            let mask = find_masked_points(&data, private_fill_value(&private_var), None);
+            fill_masked_points(&mut data, &mask, fill);
+        }
+        public_var
+            .put(data.view(), input.public_extents(data.shape(), time_axis))
+            .change_context_lazy(|| {
+                CopyError::context(format!("writing variable '{public_varname}'"))
+            })?;
+    }
+
     copy_var_attr_write_helper(
-        private_var,
+        &first_var,
         &mut public_var,
-        long_name,
-        attr_overrides,
-        attr_to_remove,
+        spec.long_name,
+        spec.attr_overrides,
+        spec.attr_to_remove,
     )?;
     Ok(())
 }
 
 /// Helper function to copy variable data generically. Unlike `copy_vmr_variable_from_dset`,
 /// this does not need to know the variable type ahead of time.
-pub(super) fn copy_variable_general<S: AsRef<str>>(
+pub(super) fn copy_variable_general(
+    inputs: &[PrivateInput],
     public_file: &mut netcdf::FileMut,
-    private_var: &netcdf::Variable,
-    public_varname: &str,
-    time_subsetter: &Subsetter,
-    long_name: &str,
-    attr_overrides: &IndexMap<String, AttributeValue>,
-    attr_to_remove: &[S],
+    private_varname: &str,
+    spec: &PublicVarSpec,
 ) -> error_stack::Result<(), CopyError> {
-    let private_varname = private_var.name();
+    let public_varname = spec.public_varname;
     log::debug!(
         "Copying private variable '{private_varname}' to public variable '{public_varname}'"
     );
-
-    let generic_array = NcArray::get_from(private_var).change_context_lazy(|| {
-        CopyError::context(format!("copying variable '{private_varname}'"))
-    })?;
+    let first_var = inputs[0].variable(private_varname)?;
     // Find the time dimension, assuming it does not occur more than once.
-    let do_subset_along = find_subset_dim(private_var, TIME_DIM_NAME);
-    let generic_array = if let Some(idim) = do_subset_along {
-        time_subsetter.subset_generic_array(&generic_array, idim)?
-    } else {
-        generic_array
-    };
+    let time_axis = find_subset_dim(&first_var, TIME_DIM_NAME);
+    let mut public_var = create_public_var(
+        public_file,
+        &first_var,
+        public_varname,
+        None,
+        &first_var.vartype(),
+    )?;
 
-    let mut public_var = match generic_array {
-        NcArray::I8(arr) => {
-            let mut pubv =
-                copy_var_pre_write_helper::<i8>(public_file, private_var, public_varname, None)?;
-            pubv.put(arr.view(), Extents::All).change_context_lazy(|| {
-                CopyError::context(format!("writing variable '{public_varname}'"))
-            })?;
-            pubv
-        }
-        NcArray::I16(arr) => {
-            let mut pubv =
-                copy_var_pre_write_helper::<i16>(public_file, private_var, public_varname, None)?;
-            pubv.put(arr.view(), Extents::All).change_context_lazy(|| {
-                CopyError::context(format!("writing variable '{public_varname}'"))
-            })?;
-            pubv
-        }
-        NcArray::I32(arr) => {
-            let mut pubv =
-                copy_var_pre_write_helper::<i32>(public_file, private_var, public_varname, None)?;
-            pubv.put(arr.view(), Extents::All).change_context_lazy(|| {
-                CopyError::context(format!("writing variable '{public_varname}'"))
-            })?;
-            pubv
-        }
-        NcArray::I64(arr) => {
-            let mut pubv =
-                copy_var_pre_write_helper::<i64>(public_file, private_var, public_varname, None)?;
-            pubv.put(arr.view(), Extents::All).change_context_lazy(|| {
-                CopyError::context(format!("writing variable '{public_varname}'"))
-            })?;
-            pubv
-        }
-        NcArray::U8(arr) => {
-            let mut pubv =
-                copy_var_pre_write_helper::<u8>(public_file, private_var, public_varname, None)?;
-            pubv.put(arr.view(), Extents::All).change_context_lazy(|| {
-                CopyError::context(format!("writing variable '{public_varname}'"))
-            })?;
-            pubv
-        }
-        NcArray::U16(arr) => {
-            let mut pubv =
-                copy_var_pre_write_helper::<u16>(public_file, private_var, public_varname, None)?;
-            pubv.put(arr.view(), Extents::All).change_context_lazy(|| {
-                CopyError::context(format!("writing variable '{public_varname}'"))
-            })?;
-            pubv
-        }
-        NcArray::U32(arr) => {
-            let mut pubv =
-                copy_var_pre_write_helper::<u32>(public_file, private_var, public_varname, None)?;
-            pubv.put(arr.view(), Extents::All).change_context_lazy(|| {
-                CopyError::context(format!("writing variable '{public_varname}'"))
-            })?;
-            pubv
-        }
-        NcArray::U64(arr) => {
-            let mut pubv =
-                copy_var_pre_write_helper::<u64>(public_file, private_var, public_varname, None)?;
-            pubv.put(arr.view(), Extents::All).change_context_lazy(|| {
-                CopyError::context(format!("writing variable '{public_varname}'"))
-            })?;
-            pubv
-        }
-        NcArray::F32(arr) => {
-            let mut pubv =
-                copy_var_pre_write_helper::<f32>(public_file, private_var, public_varname, None)?;
-            pubv.put(arr.view(), Extents::All).change_context_lazy(|| {
-                CopyError::context(format!("writing variable '{public_varname}'"))
-            })?;
-            pubv
-        }
-        NcArray::F64(arr) => {
-            let mut pubv =
-                copy_var_pre_write_helper::<f64>(public_file, private_var, public_varname, None)?;
-            pubv.put(arr.view(), Extents::All).change_context_lazy(|| {
-                CopyError::context(format!("writing variable '{public_varname}'"))
-            })?;
-            pubv
-        }
-        NcArray::Char(arr) => {
-            let mut pubv =
-                copy_var_pre_write_helper::<u8>(public_file, private_var, public_varname, None)?;
-            pubv.put(arr.view(), Extents::All).change_context_lazy(|| {
-                CopyError::context(format!("writing variable '{public_varname}'"))
-            })?;
-            pubv
+    for (i, input) in inputs_to_copy(inputs, time_axis).iter().enumerate() {
+        let private_var = input.variable(private_varname)?;
+        let generic_array = NcArray::get_from(&private_var).change_context_lazy(|| {
+            CopyError::context(format!("copying variable '{private_varname}'"))
+        })?;
+        let generic_array = if let Some(idim) = time_axis {
+            input
+                .time_subsetter
+                .subset_generic_array(&generic_array, idim)?
+        } else {
+            generic_array
+        };
+
+        let extents = input.public_extents(generic_array.shape(), time_axis);
+        // The fill value must be set before the first data are written
+        let set_fill = i == 0;
+        let pv = &mut public_var;
+        match generic_array {
+            NcArray::I8(arr) => write_general_slab(pv, &private_var, arr, extents, spec, set_fill)?,
+            NcArray::I16(arr) => {
+                write_general_slab(pv, &private_var, arr, extents, spec, set_fill)?
+            }
+            NcArray::I32(arr) => {
+                write_general_slab(pv, &private_var, arr, extents, spec, set_fill)?
+            }
+            NcArray::I64(arr) => {
+                write_general_slab(pv, &private_var, arr, extents, spec, set_fill)?
+            }
+            NcArray::U8(arr) => write_general_slab(pv, &private_var, arr, extents, spec, set_fill)?,
+            NcArray::U16(arr) => {
+                write_general_slab(pv, &private_var, arr, extents, spec, set_fill)?
+            }
+            NcArray::U32(arr) => {
+                write_general_slab(pv, &private_var, arr, extents, spec, set_fill)?
+            }
+            NcArray::U64(arr) => {
+                write_general_slab(pv, &private_var, arr, extents, spec, set_fill)?
+            }
+            NcArray::F32(arr) => {
+                write_general_slab(pv, &private_var, arr, extents, spec, set_fill)?
+            }
+            NcArray::F64(arr) => {
+                write_general_slab(pv, &private_var, arr, extents, spec, set_fill)?
+            }
+            // Character and string arrays hold text, so a numeric fill value does not apply to them
+            text @ (NcArray::Char(_) | NcArray::String(_)) => {
+                text.put_into(pv, extents).change_context_lazy(|| {
+                    CopyError::context(format!("writing variable '{public_varname}'"))
+                })?
+            }
         }
-    };
+    }
 
     copy_var_attr_write_helper(
-        private_var,
+        &first_var,
         &mut public_var,
-        long_name,
-        attr_overrides,
-        attr_to_remove,
+        spec.long_name,
+        spec.attr_overrides,
+        spec.attr_to_remove,
     )?;
     Ok(())
 }
 
+/// Writes one private file's array from [`copy_variable_general`] to its part of the public
+/// variable, applying the fill value (if given) first. If `set_fill` is `true`, this also sets
+/// the public variable's fill value, so it must be `true` for the first write only.
+fn write_general_slab<T: Copy + Zero + NcTypeDescriptor + PartialOrd + NumCast>(
+    public_var: &mut netcdf::VariableMut,
+    private_var: &netcdf::Variable,
+    mut arr: ArrayD<T>,
+    extents: Extents,
+    spec: &PublicVarSpec,
+    set_fill: bool,
+) -> error_stack::Result<(), CopyError> {
+    let public_varname = spec.public_varname;
+    let public_fill = apply_fill_value(
+        private_var,
+        &mut arr,
+        spec.fill_value,
+        spec.cutoff,
+        public_varname,
+    );
+    if set_fill {
+        set_public_fill_value(public_var, public_fill)?;
+    }
+    public_var
+        .put(arr.view(), extents)
+        .change_context_lazy(|| {
+            CopyError::context(format!("writing variable '{public_varname}'"))
+        })?;
+    Ok(())
+}
+
 /// Centralizes the logic before writing data: adds needed dimensions and creates the public variable.
 pub(super) fn copy_var_pre_write_helper<'v, T: Copy + Zero + NcTypeDescriptor>(
     public_file: &'v mut netcdf::FileMut,
     private_var: &netcdf::Variable,
     public_varname: &str,
     new_dims: Option<Vec<String>>,
+) -> error_stack::Result<netcdf::VariableMut<'v>, CopyError> {
+    create_public_var(
+        public_file,
+        private_var,
+        public_varname,
+        new_dims,
+        &T::type_descriptor(),
+    )
+}
+
+/// The non-generic part of [`copy_var_pre_write_helper`], which creates the public variable
+/// with type `vartype`.
+fn create_public_var<'v>(
+    public_file: &'v mut netcdf::FileMut,
+    private_var: &netcdf::Variable,
+    public_varname: &str,
+    new_dims: Option<Vec<String>>,
+    vartype: &NcVariableType,
 ) -> error_stack::Result<netcdf::VariableMut<'v>, CopyError> {
     let dims = if let Some(dims) = new_dims {
         add_needed_new_dims(public_file, private_var, &dims)?;
         dims
     } else {
-        let dims = private_var
+        // Create the variable, which needs its dimensions created first.
+        // Handling missing dimensions here is easier than trying to collect a list of
+        // all dimensions that we need. Time is subset to flag == 0 data, so it was created
+        // with its public length before any variables and must be checked against that.
+        let lens = private_var
             .dimensions()
             .iter()
-            .map(|dim| dim.name())
+            .map(|dim| match public_file.dimension(TIME_DIM_NAME) {
+                Some(time_dim) if dim.name() == TIME_DIM_NAME => time_dim.len(),
+                _ => dim.len(),
+            })
             .collect_vec();
-
-        // Create the variable, which needs its dimensions created first.
-        // Handling missing dimensions here is easier than trying to collect a list of
-        // all dimensions that we need.
-        add_needed_dims(public_file, &private_var).change_context_lazy(|| {
+        add_needed_dims(public_file, private_var, None, &lens).change_context_lazy(|| {
             CopyError::context(format!("creating public variable '{public_varname}'"))
-        })?;
-        dims
+        })?
     };
     let dims_str = dims.iter().map(|dim| dim.as_str()).collect_vec();
 
     let mut public_var = public_file
-        .add_variable::<T>(public_varname, &dims_str)
+        .add_variable_with_type(public_varname, &dims_str, vartype)
         .change_context_lazy(|| {
             CopyError::context(format!("creating public variable '{public_varname}'"))
         })?;
@@ -300,6 +361,10 @@ pub(super) fn copy_var_pre_write_helper<'v, T: Copy + Zero + NcTypeDescriptor>(
 
 /// Centralizes logic for attributes: adds "long_name" and copies/writes attributes based
 /// on the overrides and `attr_to_remove` values.
+///
+/// If the units are copied from the private variable, then any unit the private writer noted
+/// at the end of the private long name (e.g. "(arbitrary units)" for units of "1") is kept at
+/// the end of the public long name as well.
 pub(super) fn copy_var_attr_write_helper<S: AsRef<str>>(
     private_var: &netcdf::Variable,
     public_var: &mut netcdf::VariableMut,
@@ -309,8 +374,15 @@ pub(super) fn copy_var_attr_write_helper<S: AsRef<str>>(
 ) -> error_stack::Result<(), CopyError> {
     let private_varname = private_var.name();
     let public_varname = public_var.name();
+    let unit_long_name = if attr_overrides.contains_key("units") {
+        None
+    } else {
+        get_string_attr(private_var, "long_name")
+            .ok()
+            .and_then(|private_long_name| unit_from_long_name(&private_long_name))
+    };
     public_var
-        .put_attribute("long_name", long_name)
+        .put_attribute("long_name", long_name_with_unit(long_name, unit_long_name))
         .change_context_lazy(|| {
             CopyError::context(format!(
                 "adding 'long_name' attribute to public variable '{public_varname}'"
@@ -325,22 +397,99 @@ pub(super) fn copy_var_attr_write_helper<S: AsRef<str>>(
                 ))
             })?;
     }
-    for att in private_var.attributes() {
-        let att_name = att.name();
-        if att_name != "long_name"
-            && !attr_overrides.contains_key(att_name)
-            && !attr_to_remove.iter().any(|a| a.as_ref() == att_name)
-        {
-            let att_value = att.value()
-                .change_context_lazy(|| CopyError::context(format!("getting original value of attribute '{att_name}' from private variable '{private_varname}'")))?;
-            public_var
-                .put_attribute(att_name, att_value)
-                .change_context_lazy(|| {
-                    CopyError::context(format!(
-                        "adding '{att_name}' to public variable '{public_varname}'"
-                    ))
-                })?;
-        }
+    // If we set a new fill value, the public variable will already have its own
+    // `_FillValue` attribute, which must not be replaced by the private one.
+    let has_fill_attr = public_var.attribute("_FillValue").is_some();
+    copy_attributes_except(private_var, public_var, |att_name| {
+        att_name == "long_name"
+            || (has_fill_attr && att_name == "_FillValue")
+            || attr_overrides.contains_key(att_name)
+            || attr_to_remove.iter().any(|a| a.as_ref() == att_name)
+    })
+    .change_context_lazy(|| {
+        CopyError::context(format!(
+            "copying attributes from private variable '{private_varname}' to public variable '{public_varname}'"
+        ))
+    })?;
+    Ok(())
+}
+
+/// Replace masked points in `data` with the configured fill value, if there is one
+/// and it can be represented as type `T`. Returns the fill value converted to the data type, which should then be passed to
+/// [`set_public_fill_value`].
+pub(super) fn apply_fill_value<T: Copy + PartialOrd + NumCast + NcTypeDescriptor, D: Dimension>(
+    private_var: &netcdf::Variable,
+    data: &mut Array<T, D>,
+    fill_value: Option<f64>,
+    cutoff: Option<&ValueCutoff>,
+    public_varname: &str,
+) -> Option<T> {
+    let fill = cast_fill_value::<T>(fill_value?, public_varname)?;
+    let mask = find_masked_points(data, private_fill_value(private_var), cutoff);
+    fill_masked_points(data, &mask, fill);
+    Some(fill)
+}
+
+/// Set the fill value for a public variable. This must be called before any data
+/// is written to the variable. If `fill` is `None`, the netCDF default is left in place.
+pub(super) fn set_public_fill_value<T: NcTypeDescriptor>(
+    public_var: &mut netcdf::VariableMut,
+    fill: Option<T>,
+) -> error_stack::Result<(), CopyError> {
+    if let Some(fill) = fill {
+        public_var.set_fill_value(fill).change_context_lazy(|| {
+            CopyError::context(format!(
+                "setting fill value for public variable '{}'",
+                public_var.name()
+            ))
+        })?;
     }
     Ok(())
 }
+
+/// Convert the configured fill value to the variable's data type. If it cannot
+/// be represented in that type, a warning is logged and `None` returned, so that
+/// the variable keeps its default fill value.
+pub(super) fn cast_fill_value<T: NumCast>(fill_value: f64, public_varname: &str) -> Option<T> {
+    let fill = NumCast::from(fill_value);
+    if fill.is_none() {
+        log::warn!(
+            "Fill value {fill_value} cannot be represented in the data type of public variable '{public_varname}', the default fill value will be used instead"
+        );
+    }
+    fill
+}
+
+/// Get the fill value of the private variable, if it has one and is stored as type `T`.
+fn private_fill_value<T: NcTypeDescriptor + Copy>(private_var: &netcdf::Variable) -> Option<T> {
+    // A type mismatch means we read the data as a different type than it is stored in
+    // the private file, so its fill value cannot be compared directly. Points that are
+    // NaNs will still be recognized as masked.
+    private_var.fill_value::<T>().ok().flatten()
+}
+
+/// Identify masked points: those equal to the private fill value, that are NaNs,
+/// or that fall outside the `cutoff` (if given).
+fn find_masked_points<T: Copy + PartialOrd + NumCast, D: Dimension>(
+    data: &Array<T, D>,
+    private_fill: Option<T>,
+    cutoff: Option<&ValueCutoff>,
+) -> Array<bool, D> {
+    data.mapv(|v| {
+        v.partial_cmp(&v).is_none()
+            || Some(v) == private_fill
+            || cutoff.is_some_and(|c| v.to_f64().is_some_and(|v| c.is_cut(v)))
+    })
+}
+
+fn fill_masked_points<T: Copy, D: Dimension>(
+    data: &mut Array<T, D>,
+    mask: &Array<bool, D>,
+    fill: T,
+) {
+    data.zip_mut_with(mask, |v, &m| {
+        if m {
+            *v = fill;
+        }
+    });
+}
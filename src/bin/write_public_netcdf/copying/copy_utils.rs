@@ -1,9 +1,8 @@
 //! Intermediate level utility functions to support copying variables
-use std::ops::Add;
-
 use error_stack::ResultExt;
+use ggg_rs::nc_utils::add_dim_if_needed;
 use ndarray::{Array, ArrayView1, Dimension};
-use netcdf::{types::NcVariableType, Extents, NcTypeDescriptor};
+use netcdf::{Extents, NcTypeDescriptor};
 use num_traits::Zero;
 
 use crate::TIME_DIM_NAME;
@@ -83,128 +82,32 @@ pub(super) fn find_subset_dim(var: &netcdf::Variable, dimname: &str) -> Option<u
         })
 }
 
-/// Check if the dimensions named in `private_var` exist in `public_file`,
-/// if not, create them. Note that they are created with the same length,
-/// so if you need a different length (e.g., like "time" does because of
-/// subsetting), best if you create those dimensions before copying any variables.
-pub(super) fn add_needed_dims(
-    public_file: &mut netcdf::FileMut,
-    private_var: &netcdf::Variable,
-) -> error_stack::Result<(), CopyError> {
-    for var_dim in private_var.dimensions() {
-        if !check_dim_exists(var_dim, public_file, &private_var.name())? {
-            public_file
-                .add_dimension(&var_dim.name(), var_dim.len())
-                .change_context_lazy(|| {
-                    CopyError::context(format!("creating dimension '{}'", var_dim.name()))
-                })?;
-        }
-    }
-    Ok(())
-}
-
+/// Create the dimensions of `private_var` named in `dimnames` in `public_file`, or check
+/// that their lengths match if they already exist. Use [`ggg_rs::nc_utils::add_needed_dims`]
+/// instead if the public variable has the same dimensions as the private one.
+///
+/// Note: "time" is assumed to always exist, since it is subset in the public files.
 pub(super) fn add_needed_new_dims<S: AsRef<str>>(
     public_file: &mut netcdf::FileMut,
     private_var: &netcdf::Variable,
     dimnames: &[S],
 ) -> error_stack::Result<(), CopyError> {
+    let varname = private_var.name();
     for dim in private_var.dimensions() {
-        if dimnames.iter().any(|n| n.as_ref() == dim.name().as_str())
-            && !check_dim_exists(dim, public_file, &private_var.name())?
-        {
-            public_file
-                .add_dimension(&dim.name(), dim.len())
-                .change_context_lazy(|| {
-                    CopyError::context(format!("creating dimension '{}'", dim.name()))
-                })?;
-        }
-    }
-    Ok(())
-}
-
-/// Return `true` if `var_dim` exists in `public_file`, `false` otherwise.
-/// Also checks that the lengths are equal for variables that already exist.
-/// `varname` is only used in an error message for clarity.
-///
-/// Note: "time" is assumed to always exist, since it is subset in the public files.
-pub(super) fn check_dim_exists(
-    var_dim: &netcdf::Dimension,
-    public_file: &netcdf::File,
-    varname: &str,
-) -> Result<bool, CopyError> {
-    if var_dim.name() == "time" {
+        let dimname = dim.name();
         // Special case: time shrinks because we select flag == 0 data, so it
         // will be written at the beginning of the run
-        return Ok(true);
-    }
-
-    for extant_dim in public_file.dimensions() {
-        if extant_dim.name() == var_dim.name() {
-            if extant_dim.len() != var_dim.len() {
-                return Err(CopyError::dim_len_mismatch(
-                    var_dim.name(),
-                    varname,
-                    extant_dim.len(),
-                    var_dim.len(),
-                ));
-            } else {
-                return Ok(true);
-            }
+        if dimname == TIME_DIM_NAME || !dimnames.iter().any(|n| n.as_ref() == dimname) {
+            continue;
         }
+        add_dim_if_needed(public_file, &dimname, dim.len(), false, &varname).change_context_lazy(
+            || CopyError::context(format!("creating dimension '{dimname}'")),
+        )?;
     }
-    Ok(false)
-}
-
-/// Wrapper around unsigned bytes to represent a netCDF character type
-///
-/// From <https://docs.rs/netcdf/0.11.0/netcdf/trait.NcTypeDescriptor.html#char-type>,
-/// in netCDF v0.11, i8 and u8 are not considered equivalent to an NC_CHAR type.
-/// Therefore, to read an NC_CHAR-type variable, we create this structure to
-/// hold a byte as a character.
-#[repr(transparent)]
-#[derive(Clone, Copy, PartialEq, Eq)]
-pub(super) struct NcChar(u8);
-unsafe impl NcTypeDescriptor for NcChar {
-    fn type_descriptor() -> NcVariableType {
-        NcVariableType::Char
-    }
-}
-
-impl From<NcChar> for u8 {
-    fn from(value: NcChar) -> Self {
-        value.0
-    }
-}
-
-impl From<&NcChar> for u8 {
-    fn from(value: &NcChar) -> Self {
-        value.0
-    }
-}
-
-impl From<&NcChar> for char {
-    fn from(value: &NcChar) -> Self {
-        char::from(value.0)
-    }
-}
-
-impl Zero for NcChar {
-    fn zero() -> Self {
-        Self(0)
-    }
-
-    fn is_zero(&self) -> bool {
-        self.0 == 0
-    }
+    Ok(())
 }
 
-impl Add for NcChar {
-    type Output = NcChar;
-
-    fn add(self, rhs: Self) -> Self::Output {
-        Self(self.0 + rhs.0)
-    }
-}
+pub(super) use ggg_rs::nc_utils::NcChar;
 
 pub(super) fn chars_to_string(char_arr: ArrayView1<NcChar>) -> String {
     let byte_it = char_arr.into_iter().map(|c| char::from(c));
@@ -7,7 +7,7 @@ use netcdf::AttributeValue;
 use regex::Regex;
 use serde::Deserialize;
 
-use crate::copying::{de_attribute_overrides, XgasAncInferOptions, XgasAncillary};
+use crate::copying::{de_attribute_overrides, ValueCutoff, XgasAncInferOptions, XgasAncillary};
 use crate::XgasCopy;
 
 #[derive(Debug, thiserror::Error)]
@@ -328,6 +328,19 @@ pub(crate) struct XgasMatchRule {
     pub(crate) slant_bin: Option<AncillaryDiscoveryMethod>,
     #[serde(default)]
     pub(crate) traceability_scale: Option<AncillaryDiscoveryMethod>,
+    #[serde(default)]
+    pub(crate) fill_value: Option<f64>,
+    /// Limits on the discovered Xgas values; those outside them are replaced with the fill value.
+    #[serde(default)]
+    pub(crate) cutoff: Option<ValueCutoff>,
+    /// Limits on the discovered Xgas error values; those outside them are replaced with the fill value.
+    #[serde(default)]
+    pub(crate) xgas_error_cutoff: Option<ValueCutoff>,
+    /// If another Xgas to be copied (defined or discovered) retrieves the same gas,
+    /// append "_" plus this suffix to the public names of Xgases found by this rule
+    /// and their averaging kernels, so that e.g. near- and mid-IR CO2 are distinct.
+    #[serde(default)]
+    pub(crate) same_gas_suffix: Option<String>,
 }
 
 impl XgasMatchRule {
@@ -345,9 +358,23 @@ impl XgasMatchRule {
             ak_attr_overrides: IndexMap::new(),
             slant_bin: None,
             traceability_scale: None,
+            fill_value: None,
+            cutoff: None,
+            xgas_error_cutoff: None,
+            same_gas_suffix: None,
         }
     }
 
+    /// Iterate over all the attribute overrides this rule would apply.
+    pub(crate) fn iter_attr_overrides(&self) -> impl Iterator<Item = (&String, &AttributeValue)> {
+        self.xgas_attr_overrides
+            .iter()
+            .chain(self.xgas_error_attr_overrides.iter())
+            .chain(self.prior_profile_attr_overrides.iter())
+            .chain(self.prior_xgas_attr_overrides.iter())
+            .chain(self.ak_attr_overrides.iter())
+    }
+
     pub(crate) fn is_given_regex(&self, pattern: &str) -> bool {
         match &self.pattern {
             XgasMatchMethod::Suffix {
@@ -405,6 +432,7 @@ pub(crate) fn discover_xgas_vars<G: AsRef<str>, V: AsRef<str>>(
     let private_varnames = private_file.variables().map(|var| var.name()).collect_vec();
 
     let mut xgas_vars = vec![];
+    let mut xgas_suffixes = vec![];
     for varname in private_varnames.iter() {
         if let Some((rule, gas)) = should_add_xgas_var(
             discovery_rules,
@@ -422,6 +450,30 @@ pub(crate) fn discover_xgas_vars<G: AsRef<str>, V: AsRef<str>>(
             let new_xgas = XgasCopy::new_from_discovery(varname, xgas_public, gas, long_name, rule);
 
             xgas_vars.push(new_xgas);
+            xgas_suffixes.push(rule.same_gas_suffix.as_deref());
+        }
+    }
+
+    // Only once we know all the Xgases can we tell which ones share a gas and need disambiguating.
+    let gas_shared = xgas_vars
+        .iter()
+        .map(|xgas| {
+            defined_xgas_vars
+                .iter()
+                .chain(xgas_vars.iter())
+                .any(|other| {
+                    other.gas() == xgas.gas() && other.xgas_varname() != xgas.xgas_varname()
+                })
+        })
+        .collect_vec();
+    for ((xgas, suffix), shared) in xgas_vars.iter_mut().zip(xgas_suffixes).zip(gas_shared) {
+        if let (Some(suffix), true) = (suffix, shared) {
+            log::debug!(
+                "Variable '{}' shares its gas ({}) with another Xgas, adding suffix '{suffix}' to its public names",
+                xgas.xgas_varname(),
+                xgas.gas()
+            );
+            xgas.add_public_suffix(suffix);
         }
     }
 
@@ -473,7 +525,8 @@ mod tests {
 
     use crate::discovery::{AncillaryDiscoveryMethod, XgasMatchMethod};
 
-    use super::XgasMatchRule;
+    use super::{discover_xgas_vars, XgasMatchRule};
+    use crate::XgasCopy;
 
     #[test]
     fn test_de_simple_rules() {
@@ -527,7 +580,55 @@ mod tests {
             ak_attr_overrides: IndexMap::new(),
             slant_bin: None,
             traceability_scale: Some(AncillaryDiscoveryMethod::Omit),
+            fill_value: None,
+            cutoff: None,
+            xgas_error_cutoff: None,
+            same_gas_suffix: None,
         };
         assert_eq!(rule, expected);
     }
+
+    #[test]
+    fn test_same_gas_suffix() {
+        let output_dir = ggg_rs::test_utils::output_dir("write-public-netcdf-same-gas-suffix");
+        let nc_path = output_dir.join("discovery-same-gas-suffix.private.nc");
+        {
+            let mut ds = netcdf::create(&nc_path).unwrap();
+            ds.add_dimension("time", 1).unwrap();
+            for varname in ["xco2", "xco2_mir", "xch4_mir"] {
+                ds.add_variable::<f64>(varname, &["time"]).unwrap();
+            }
+        }
+        let private_ds = netcdf::open(&nc_path).unwrap();
+
+        let toml_str = r#"regex = '^x(?<gas>[a-z][a-z0-9]*)_mir$'
+        rep_pattern = 'x$gas'
+        same_gas_suffix = "midir""#;
+        let rule: XgasMatchRule =
+            toml::from_str(toml_str).expect("deserialization should not fail");
+        let defined = vec![XgasCopy::new("xco2", "co2", "carbon dioxide")];
+        let discovered = discover_xgas_vars(
+            &defined,
+            &[rule],
+            &[] as &[&str],
+            &[] as &[&str],
+            &IndexMap::new(),
+            &private_ds,
+        )
+        .unwrap();
+
+        assert_eq!(discovered.len(), 2);
+        let co2 = discovered.iter().find(|x| x.gas() == "co2").unwrap();
+        assert_eq!(co2.xgas_public_varname(), "xco2_midir");
+        assert_eq!(
+            co2.infer_ak_names(),
+            ("ak_xco2_mir".to_string(), "ak_xco2_midir".to_string())
+        );
+        assert_ne!(co2.infer_ak_names().1, defined[0].infer_ak_names().1);
+
+        // CH4 is only retrieved in one window, so needs no suffix
+        let ch4 = discovered.iter().find(|x| x.gas() == "ch4").unwrap();
+        assert_eq!(ch4.xgas_public_varname(), "xch4");
+        assert_eq!(ch4.infer_ak_names().1, "ak_xch4_mir");
+    }
 }
@@ -3,21 +3,29 @@ use std::{
     process::ExitCode,
 };
 
-use chrono::NaiveDate;
+use chrono::{DateTime, NaiveDate, Utc};
 use clap::Parser;
 use clap_verbosity_flag::{InfoLevel, Verbosity};
-use config::{Config, ConfigError, EXTENDED_TCCON_TOML, STANDARD_TCCON_TOML};
+use config::{AttrTemplateValues, Config, ConfigError, EXTENDED_TCCON_TOML, STANDARD_TCCON_TOML};
 use constants::TIME_DIM_NAME;
-use copying::{copy_attributes, AuxVarCopy, ComputedVariable, CopySet, Subsetter, XgasCopy};
+use copying::{
+    copy_attributes, summarize_traceability_scales, AuxVarCopy, ComputedVariable, CopyError,
+    CopyOptions, CopySet, PlannedVar, PrivateInput, Subsetter, XgasCopy,
+};
 use discovery::discover_xgas_vars;
 use error_stack::ResultExt;
-use ggg_rs::{logging::init_logging, nc_utils, utils::nctime_to_datetime};
+use ggg_rs::{
+    logging::{init_logging_with_format, LogFormatCli},
+    nc_utils,
+    utils::nctime_to_datetime,
+};
 use itertools::Itertools;
 use ndarray::Ix1;
 use netcdf::{AttributeValue, Extents};
 
 use crate::config::STANDARD_EM27_TOML;
 
+mod concat;
 mod config;
 mod constants;
 mod copying;
@@ -33,14 +41,17 @@ mod discovery;
 //      4c. Add rename option to Xgas discovery [x - tentative]
 //      4d. Make a subset of the ancillary variable specs allowed for Xgas discovery, and From<THAT> for the regular ancillary spec [x - tentative]
 //      4e. Use https://docs.rs/figment/latest/figment/ to handle merging configurations.
-//      4f. Make the inferred AK names include suffixes or however we choose to distinguish the mid-IR gases' AKs
+//      4f. Make the inferred AK names include suffixes or however we choose to distinguish the mid-IR gases' AKs [x - tentative, same_gas_suffix]
 //   5. Data latency
-//   6. Global attributes
+//   6. Global attributes [x]
 
 fn main() -> ExitCode {
     let clargs = Cli::parse();
 
-    init_logging(clargs.verbosity.log_level_filter());
+    init_logging_with_format(
+        clargs.verbosity.log_level_filter(),
+        clargs.log_format.log_format,
+    );
 
     match driver(clargs) {
         Ok(_) => ExitCode::SUCCESS,
@@ -53,6 +64,11 @@ fn main() -> ExitCode {
 
 fn driver(clargs: Cli) -> error_stack::Result<(), CliError> {
     let config = load_config(&clargs.config).change_context(CliError::ReadingConfig)?;
+    if clargs.netcdf_format == PublicNcFormat::Classic {
+        config
+            .check_classic_compatible()
+            .change_context(CliError::ReadingConfig)?;
+    }
 
     if clargs.check_config_only {
         println!("Loaded configuration:\n{config:#?}");
@@ -61,13 +77,40 @@ fn driver(clargs: Cli) -> error_stack::Result<(), CliError> {
 
     let opt_end_date = clargs.get_release_lag_date()?;
 
-    let private_nc_file = clargs
-        .private_nc_file
-        .expect("If --check-config-only not given, a private netCDF file must be given");
-    let private_ds = netcdf::open(&private_nc_file).change_context(CliError::OpeningPrivateFile)?;
+    // With several private files, check that they can go into one public file and sort
+    // them by time; anything that depends on the private file name uses the earliest one.
+    let private_files = if clargs.private_nc_files.len() > 1 {
+        concat::open_private_files(&clargs.private_nc_files)
+            .change_context(CliError::CombiningPrivateFiles)?
+    } else {
+        let file = clargs
+            .private_nc_files
+            .first()
+            .expect("If --check-config-only not given, a private netCDF file must be given");
+        let ds = netcdf::open(file).change_context(CliError::OpeningPrivateFile)?;
+        vec![(file.clone(), ds)]
+    };
+    let private_nc_file = private_files[0].0.as_path();
+    let inputs = make_private_inputs(&private_files, opt_end_date, !clargs.no_order_by_time)?;
+
+    let copy_opts = CopyOptions {
+        fill_value: config.fill_value,
+        classic_model: clargs.netcdf_format == PublicNcFormat::Classic,
+    };
+
+    if clargs.list_output_vars {
+        let mut planned = vec![];
+        add_variables(
+            &config,
+            &inputs,
+            &mut PublicOutput::List(&mut planned),
+            &copy_opts,
+        )?;
+        print_planned_vars(&planned);
+        return Ok(());
+    }
 
-    let time_subsetter = make_time_subsetter(&private_ds, opt_end_date, !clargs.no_order_by_time)?;
-    let private_file_name = &private_nc_file;
+    let private_file_name = private_nc_file;
     let public_file_name = if let Some(out_file) = clargs.output_file.as_deref() {
         out_file.to_path_buf()
     } else if clargs.no_rename_by_dates {
@@ -78,8 +121,8 @@ fn driver(clargs: Cli) -> error_stack::Result<(), CliError> {
         )?
     } else {
         make_public_name_from_dates(
+            &inputs,
             private_file_name,
-            &time_subsetter,
             config.extra_extension.as_deref(),
             clargs.output_dir.as_deref(),
         )?
@@ -106,15 +149,19 @@ fn driver(clargs: Cli) -> error_stack::Result<(), CliError> {
     }
 
     log::info!("Will write to {}", public_file_name.display());
-    let mut public_ds =
-        netcdf::create(&public_file_name).change_context(CliError::OpeningPublicFile)?;
-
-    add_time_dim(&mut public_ds, &time_subsetter)?;
-    add_aux_vars(&config, &private_ds, &mut public_ds, &time_subsetter)?;
-    add_computed_vars(&config, &private_ds, &mut public_ds, &time_subsetter)?;
-    add_extra_priors(&config, &private_ds, &mut public_ds, &time_subsetter)?;
-    add_xgas_vars(&config, &private_ds, &mut public_ds, &time_subsetter)?;
-    add_global_attributes(&config, &private_ds, &mut public_ds)?;
+    let mut public_ds = clargs
+        .netcdf_format
+        .create(&public_file_name)
+        .change_context(CliError::OpeningPublicFile)?;
+
+    add_time_dim(&mut public_ds, &inputs)?;
+    add_variables(
+        &config,
+        &inputs,
+        &mut PublicOutput::File(&mut public_ds),
+        &copy_opts,
+    )?;
+    add_global_attributes(&config, &inputs, &mut public_ds, private_file_name)?;
     Ok(())
 }
 
@@ -127,9 +174,12 @@ struct Cli {
     /// configuration file.
     config: String,
 
-    /// The private netCDF file to copy.
+    /// The private netCDF file(s) to copy. If more than one is given, they must be
+    /// from the same site and not overlap in time; they will be combined in time order
+    /// into one public file. Each public variable is written from one private file at
+    /// a time, so the private files do not all need to fit in memory at once.
     #[clap(required_unless_present("check_config_only"))]
-    private_nc_file: Option<PathBuf>,
+    private_nc_files: Vec<PathBuf>,
 
     /// Do not rename the output file to match the time span of the
     /// data retained after flagging and data latency.
@@ -160,6 +210,13 @@ struct Cli {
     #[clap(long)]
     print_public_name_only: bool,
 
+    /// Will run Xgas discovery on the private file and print every variable
+    /// (auxiliary, computed, extra prior, and Xgas) that would be written to the
+    /// public file, along with the private variable it comes from, then exit
+    /// without creating the public file.
+    #[clap(long)]
+    list_output_vars: bool,
+
     /// Specify a number of days back in time from today to withhold
     /// data from the public files. For example, if run with
     /// --data-latency-days=30 on 31 Jan 2025, then no data after midnight,
@@ -187,9 +244,20 @@ struct Cli {
     #[clap(long)]
     no_order_by_time: bool,
 
+    /// Which netCDF data model to write the public file with. "netcdf4" allows
+    /// the full set of netCDF-4 data types, while "classic" restricts the file to
+    /// the netCDF-4 classic model for compatibility with older tools. Writing will
+    /// stop with an error if the configuration or a copied variable needs a type
+    /// the classic model does not support.
+    #[clap(long, value_enum, default_value_t = PublicNcFormat::Netcdf4)]
+    netcdf_format: PublicNcFormat,
+
     // config_file: Option<PathBuf>,
     #[command(flatten)]
     verbosity: Verbosity<InfoLevel>,
+
+    #[command(flatten)]
+    log_format: LogFormatCli,
 }
 
 impl Cli {
@@ -243,8 +311,8 @@ impl Cli {
         let metadata =
             nc_utils::read_nc_site_metadata(dlf).change_context(CliError::SiteMetadata)?;
 
-        let private_nc_filename = self.private_nc_file
-            .as_deref()
+        let private_nc_filename = self.private_nc_files
+            .first()
             .expect("get_site_metadata should not be called if no private netCDF file is given as an argument")
             .file_name()
             .ok_or_else(|| CliError::custom("Could not get the file base name of the private netCDF file"))?
@@ -263,6 +331,26 @@ impl Cli {
     }
 }
 
+/// The data model to use for the public netCDF file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum PublicNcFormat {
+    /// The netCDF-4 classic model: HDF5 storage, but only the netCDF-3 data types and no groups.
+    Classic,
+    /// The full netCDF-4 (extended) model.
+    Netcdf4,
+}
+
+impl PublicNcFormat {
+    fn create(&self, path: &Path) -> netcdf::Result<netcdf::FileMut> {
+        match self {
+            Self::Classic => {
+                netcdf::create_with(path, netcdf::Options::NETCDF4 | netcdf::Options::CLASSIC)
+            }
+            Self::Netcdf4 => netcdf::create(path),
+        }
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 enum CliError {
     #[error("An error occurred while reading the configuration")]
@@ -271,6 +359,8 @@ enum CliError {
     SiteMetadata,
     #[error("An error occurred while opening the private file")]
     OpeningPrivateFile,
+    #[error("An error occurred while combining the private files")]
+    CombiningPrivateFiles,
     #[error("An error occurred while opening the public file for writing")]
     OpeningPublicFile,
     #[error("An error occurred while subsetting data")]
@@ -291,6 +381,8 @@ enum CliError {
     WritingComputed,
     #[error("An error occurred while writing the global attributes to the public file")]
     WritingGlobalAttrs,
+    #[error("An error occurred while determining the time range of the data")]
+    ReadingTimes,
     #[error("{0}")]
     Custom(String),
 }
@@ -346,29 +438,78 @@ fn make_time_subsetter(
     Ok(subsetter)
 }
 
-fn make_public_name_from_dates(
-    private_filename: &Path,
+/// Set up copying from each of the private files, in order: which of their times to keep,
+/// and where those times start along the public time dimension.
+fn make_private_inputs<'a>(
+    private_files: &'a [(PathBuf, netcdf::File)],
+    opt_end_date: Option<NaiveDate>,
+    order_by_time: bool,
+) -> error_stack::Result<Vec<PrivateInput<'a>>, CliError> {
+    let mut time_offset = 0;
+    let mut inputs = vec![];
+    for (_, ds) in private_files {
+        let time_subsetter = make_time_subsetter(ds, opt_end_date, order_by_time)?;
+        let ntime = time_subsetter.len();
+        inputs.push(PrivateInput {
+            ds,
+            time_subsetter,
+            time_offset,
+        });
+        time_offset += ntime;
+    }
+    Ok(inputs)
+}
+
+/// Get the first and last times of the data that will go into the public file.
+fn subset_time_range(
+    inputs: &[PrivateInput],
+) -> error_stack::Result<(DateTime<Utc>, DateTime<Utc>), CliError> {
+    let mut first_last: Option<(f64, f64)> = None;
+    let mut time_units = String::new();
+    for input in inputs {
+        let (file_range, units) = subset_time_range_one_file(input.ds, &input.time_subsetter)?;
+        if let Some((file_first, file_last)) = file_range {
+            first_last = Some(match first_last {
+                Some((first, last)) => (first.min(file_first), last.max(file_last)),
+                None => (file_first, file_last),
+            });
+        }
+        time_units = units;
+    }
+
+    let Some((first_time, last_time)) = first_last else {
+        let error_msg = "Could not determine the time range of the data, no times left after subsetting for flag == 0";
+        return Err(CliError::custom(error_msg).into());
+    };
+    let first_time =
+        nctime_to_datetime(first_time, &time_units).change_context(CliError::ReadingTimes)?;
+    let last_time =
+        nctime_to_datetime(last_time, &time_units).change_context(CliError::ReadingTimes)?;
+    Ok((first_time, last_time))
+}
+
+/// Get the first and last times (as netCDF time values) kept from one private file, if any
+/// are left after subsetting, along with the time units.
+fn subset_time_range_one_file(
+    ds: &netcdf::File,
     time_subsetter: &Subsetter,
-    extra_extension: Option<&str>,
-    output_dir: Option<&Path>,
-) -> error_stack::Result<PathBuf, CliError> {
+) -> error_stack::Result<(Option<(f64, f64)>, String), CliError> {
     // Load the times, subset them, and find the first and last times.
     // Yeah, this is painfully long for what should be simple...
-    let ds = netcdf::open(private_filename).change_context(CliError::MakePubName)?;
     let time_var = ds
         .variable("time")
         .ok_or_else(|| netcdf::Error::NotFound("variable 'time'".to_string()))
-        .change_context(CliError::MakePubName)?;
+        .change_context(CliError::ReadingTimes)?;
     let times = time_var
         .get::<f64, _>(Extents::All)
-        .change_context(CliError::MakePubName)?;
+        .change_context(CliError::ReadingTimes)?;
 
     let time_units = time_var
         .attribute("units")
         .ok_or_else(|| netcdf::Error::NotFound("attribute 'units' on variable 'time'".to_string()))
-        .change_context(CliError::MakePubName)?
+        .change_context(CliError::ReadingTimes)?
         .value()
-        .change_context(CliError::MakePubName)?;
+        .change_context(CliError::ReadingTimes)?;
     let time_units = if let AttributeValue::Str(u) = time_units {
         u
     } else {
@@ -379,19 +520,23 @@ fn make_public_name_from_dates(
 
     let times = time_subsetter
         .subset_nd_array(times.view(), 0)
-        .change_context(CliError::MakePubName)?;
-    let (first_time, last_time) = match times.iter().minmax() {
-        itertools::MinMaxResult::NoElements => {
-            let error_msg = "Could not determine times for file name, no times left after subsetting for flag == 0";
-            return Err(CliError::custom(error_msg).into());
-        }
-        itertools::MinMaxResult::OneElement(&t) => (t, t),
-        itertools::MinMaxResult::MinMax(&ta, &tb) => (ta, tb),
+        .change_context(CliError::ReadingTimes)?;
+    let range = match times.iter().minmax() {
+        itertools::MinMaxResult::NoElements => None,
+        itertools::MinMaxResult::OneElement(&t) => Some((t, t)),
+        itertools::MinMaxResult::MinMax(&ta, &tb) => Some((ta, tb)),
     };
-    let first_time =
-        nctime_to_datetime(first_time, &time_units).change_context(CliError::MakePubName)?;
-    let last_time =
-        nctime_to_datetime(last_time, &time_units).change_context(CliError::MakePubName)?;
+    Ok((range, time_units))
+}
+
+fn make_public_name_from_dates(
+    inputs: &[PrivateInput],
+    private_filename: &Path,
+    extra_extension: Option<&str>,
+    output_dir: Option<&Path>,
+) -> error_stack::Result<PathBuf, CliError> {
+    let (first_time, last_time) =
+        subset_time_range(inputs).change_context(CliError::MakePubName)?;
 
     // Get the site ID, current file extension, and parent directory
     let private_base_name = private_filename
@@ -460,23 +605,58 @@ fn make_public_name_from_stem(
 
 fn add_time_dim(
     public_ds: &mut netcdf::FileMut,
-    time_subsetter: &Subsetter,
+    inputs: &[PrivateInput],
 ) -> error_stack::Result<(), CliError> {
-    let ntime = time_subsetter.len();
+    let ntime = inputs.iter().map(|inp| inp.time_subsetter.len()).sum();
     public_ds
         .add_dimension(TIME_DIM_NAME, ntime)
         .change_context(CliError::WritingDim)?;
     Ok(())
 }
 
+/// Where the `add_*` functions send the variables: either into the public file,
+/// or (for `--list-output-vars`) into a list of what would be written.
+enum PublicOutput<'a> {
+    File(&'a mut netcdf::FileMut),
+    List(&'a mut Vec<PlannedVar>),
+}
+
+impl PublicOutput<'_> {
+    fn add<C: CopySet>(
+        &mut self,
+        var: &C,
+        inputs: &[PrivateInput],
+        copy_opts: &CopyOptions,
+    ) -> error_stack::Result<(), CopyError> {
+        match self {
+            PublicOutput::File(public_ds) => var.copy(inputs, public_ds, copy_opts),
+            PublicOutput::List(planned) => var.plan(inputs[0].ds, planned),
+        }
+    }
+}
+
+fn add_variables(
+    config: &Config,
+    inputs: &[PrivateInput],
+    output: &mut PublicOutput,
+    copy_opts: &CopyOptions,
+) -> error_stack::Result<(), CliError> {
+    add_aux_vars(config, inputs, output, copy_opts)?;
+    add_computed_vars(config, inputs, output, copy_opts)?;
+    add_extra_priors(config, inputs, output, copy_opts)?;
+    add_xgas_vars(config, inputs, output, copy_opts)?;
+    Ok(())
+}
+
 fn add_aux_vars(
     config: &Config,
-    private_ds: &netcdf::File,
-    public_ds: &mut netcdf::FileMut,
-    time_subsetter: &Subsetter,
+    inputs: &[PrivateInput],
+    output: &mut PublicOutput,
+    copy_opts: &CopyOptions,
 ) -> error_stack::Result<(), CliError> {
     for var in config.aux.iter() {
-        var.copy(private_ds, public_ds, time_subsetter)
+        output
+            .add(var, inputs, copy_opts)
             .change_context(CliError::WritingAux)?;
     }
 
@@ -485,12 +665,13 @@ fn add_aux_vars(
 
 fn add_extra_priors(
     config: &Config,
-    private_ds: &netcdf::File,
-    public_ds: &mut netcdf::FileMut,
-    time_subsetter: &Subsetter,
+    inputs: &[PrivateInput],
+    output: &mut PublicOutput,
+    copy_opts: &CopyOptions,
 ) -> error_stack::Result<(), CliError> {
     for var in config.extra_priors.iter() {
-        var.copy(private_ds, public_ds, time_subsetter)
+        output
+            .add(var, inputs, copy_opts)
             .change_context(CliError::WritingExtraPriors)?;
     }
     Ok(())
@@ -498,9 +679,9 @@ fn add_extra_priors(
 
 fn add_xgas_vars(
     config: &Config,
-    private_ds: &netcdf::File,
-    public_ds: &mut netcdf::FileMut,
-    time_subsetter: &Subsetter,
+    inputs: &[PrivateInput],
+    output: &mut PublicOutput,
+    copy_opts: &CopyOptions,
 ) -> error_stack::Result<(), CliError> {
     let defined_xgases = &config.xgas;
     let discovered_xgases = discover_xgas_vars(
@@ -509,7 +690,7 @@ fn add_xgas_vars(
         &config.discovery.excluded_gases,
         &config.discovery.excluded_xgas_variables,
         &config.gas_long_names,
-        private_ds,
+        inputs[0].ds,
     )
     .change_context(CliError::WritingXgas)?;
 
@@ -517,7 +698,8 @@ fn add_xgas_vars(
 
     for var in it {
         log::trace!("Xgas variable: {var:?}");
-        var.copy(private_ds, public_ds, time_subsetter)
+        output
+            .add(var, inputs, copy_opts)
             .change_context(CliError::WritingXgas)?;
     }
 
@@ -526,24 +708,451 @@ fn add_xgas_vars(
 
 fn add_computed_vars(
     config: &Config,
-    private_ds: &netcdf::File,
-    public_ds: &mut netcdf::FileMut,
-    time_subsetter: &Subsetter,
+    inputs: &[PrivateInput],
+    output: &mut PublicOutput,
+    copy_opts: &CopyOptions,
 ) -> error_stack::Result<(), CliError> {
     for var in config.computed.iter() {
-        var.copy(private_ds, public_ds, time_subsetter)
+        output
+            .add(var, inputs, copy_opts)
             .change_context(CliError::WritingComputed)?;
     }
 
     Ok(())
 }
 
+fn print_planned_vars(planned: &[PlannedVar]) {
+    let width = planned
+        .iter()
+        .map(|v| v.public_name.len())
+        .max()
+        .unwrap_or(0);
+    println!(
+        "Public variables that would be written ({}):",
+        planned.len()
+    );
+    for var in planned {
+        println!("  {:width$} <- {}", var.public_name, var.private_name);
+    }
+}
+
 fn add_global_attributes(
     config: &Config,
-    private_ds: &netcdf::File,
+    inputs: &[PrivateInput],
     public_ds: &mut netcdf::FileMut,
+    private_filename: &Path,
 ) -> error_stack::Result<(), CliError> {
     let attrs = config.global_attributes.make_attr_list();
-    copy_attributes(private_ds, public_ds, &attrs).change_context(CliError::WritingGlobalAttrs)?;
+    copy_attributes(inputs, public_ds, &attrs).change_context(CliError::WritingGlobalAttrs)?;
+
+    // Summarize the traceability scales and a priori sources of the whole file at the root, so
+    // users do not need to check every variable. These go before the `set` attributes, so
+    // the configuration can replace them.
+    let mut summary_attrs = vec![];
+    if let Some(scales) =
+        summarize_traceability_scales(public_ds).change_context(CliError::WritingGlobalAttrs)?
+    {
+        summary_attrs.push(("wmo_or_analogous_scales".to_string(), scales));
+    }
+    for var in config.computed.iter() {
+        if let Some(attr) = var
+            .summary_global_attr(public_ds)
+            .change_context(CliError::WritingGlobalAttrs)?
+        {
+            summary_attrs.push(attr);
+        }
+    }
+    for (name, value) in summary_attrs {
+        public_ds
+            .add_attribute(&name, value)
+            .change_context(CliError::WritingGlobalAttrs)
+            .attach_printable_lazy(|| format!("writing global attribute {name}"))?;
+    }
+
+    if config.global_attributes.has_set_attrs() {
+        let private_base_name = private_filename
+            .file_name()
+            .ok_or_else(|| CliError::custom("private file name does not have a basename!"))?
+            .to_string_lossy();
+        let (first_time, last_time) =
+            subset_time_range(inputs).change_context(CliError::WritingGlobalAttrs)?;
+        let values = AttrTemplateValues {
+            site_id: private_base_name.chars().take(2).collect(),
+            start_date: first_time.format("%Y-%m-%d").to_string(),
+            end_date: last_time.format("%Y-%m-%d").to_string(),
+            ggg_rs_version: env!("CARGO_PKG_VERSION").to_string(),
+        };
+
+        let set_attrs = config
+            .global_attributes
+            .render_set_attrs(&values)
+            .change_context(CliError::WritingGlobalAttrs)?;
+        for (name, value) in set_attrs {
+            public_ds
+                .add_attribute(&name, value)
+                .change_context(CliError::WritingGlobalAttrs)
+                .attach_printable_lazy(|| format!("writing global attribute {name}"))?;
+        }
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use ggg_rs::{
+        nc_utils::NcChar,
+        readers::postproc_files::{open_and_iter_postproc_file, PostprocRow},
+        units::{cf_unit, long_name_with_unit, unit_conv_factor, Quantity},
+    };
+
+    use super::*;
+    use crate::constants::{PRIOR_INDEX_VARNAME, PRIOR_TIME_DIM_NAME};
+
+    /// The configuration used to write the public files in these tests.
+    const TEST_CONFIG: &str = r#"
+[defaults]
+disable_all = true
+
+[[aux]]
+private_name = "time"
+long_name = "zero path difference time"
+
+[[aux]]
+private_name = "solzen"
+long_name = "solar zenith angle"
+
+[[aux]]
+private_name = "sia"
+long_name = "average solar intensity"
+
+[[aux]]
+private_name = "vsw_luft_6146"
+long_name = "dry air column density from the 6146 cm-1 window"
+
+[[xgas]]
+xgas = "xluft"
+gas = "luft"
+gas_long = "dry air"
+prior_profile = { type = "omit" }
+prior_xgas = { type = "omit" }
+ak = { type = "omit" }
+traceability_scale = { type = "omit" }
+
+[[xgas]]
+xgas = "xco2"
+gas = "co2"
+gas_long = "carbon dioxide"
+prior_profile = { type = "omit" }
+prior_xgas = { type = "omit" }
+ak = { type = "omit" }
+traceability_scale = { type = "omit" }
+xgas_attr_overrides = { wmo_or_analogous_scale = "WMO CO2 X2019" }
+
+[[computed]]
+type = "prior_source"
+
+[global_attributes.set]
+Conventions = "CF-1.8"
+title = "TCCON data from {site_id}, {start_date} to {end_date}"
+source = "write_public_netcdf from GGG-RS v{ggg_rs_version}"
+"#;
+
+    fn read_benchmark_rows(path: &Path) -> Vec<PostprocRow> {
+        let (_, rows) = open_and_iter_postproc_file(path).unwrap();
+        rows.collect::<Result<_, _>>().unwrap()
+    }
+
+    /// Add a variable along the time dimension with the units and long name that
+    /// write_private_netcdf gives a variable whose unit in the GGG files is `ggg_unit`.
+    fn add_private_var(
+        ds: &mut netcdf::FileMut,
+        varname: &str,
+        long_name: &str,
+        ggg_unit: &str,
+        values: &[f32],
+    ) {
+        let (units, unit_long_name) = cf_unit(ggg_unit);
+        let mut var = ds.add_variable::<f32>(varname, &[TIME_DIM_NAME]).unwrap();
+        var.put_attribute("long_name", long_name_with_unit(long_name, unit_long_name))
+            .unwrap();
+        var.put_attribute("units", units).unwrap();
+        var.put_values(values, Extents::All).unwrap();
+    }
+
+    /// Write a private netCDF file in `output_dir` with some of the auxiliary, Xgas, and
+    /// column variables from the GGG benchmark's .vav.ada.aia and .vsw files, returning its path.
+    fn write_benchmark_private_file(output_dir: &Path) -> PathBuf {
+        let crate_root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let aia_rows =
+            read_benchmark_rows(&crate_root.join(
+                "test-data/expected/apply-tccon-insitu-correction/pa_ggg_benchmark.vav.ada.aia",
+            ));
+        let vsw_rows = read_benchmark_rows(
+            &crate_root.join("test-data/expected/collate-tccon-results/pa_ggg_benchmark.vsw"),
+        );
+
+        let private_path = output_dir.join("pa_ggg_benchmark.private.nc");
+        let mut ds = netcdf::create(&private_path).unwrap();
+        ds.add_dimension(TIME_DIM_NAME, aia_rows.len()).unwrap();
+
+        let times = aia_rows
+            .iter()
+            .map(|row| {
+                let aux = &row.auxiliary;
+                let date = NaiveDate::from_yo_opt(aux.year as i32, aux.day as u32).unwrap();
+                let midnight = date.and_hms_opt(0, 0, 0).unwrap().and_utc();
+                midnight.timestamp() as f64 + aux.hour * 3600.0
+            })
+            .collect_vec();
+        let mut var = ds.add_variable::<f64>("time", &[TIME_DIM_NAME]).unwrap();
+        var.put_attribute("long_name", "time").unwrap();
+        var.put_attribute("units", "seconds since 1970-01-01 00:00:00")
+            .unwrap();
+        var.put_values(&times, Extents::All).unwrap();
+
+        let mut var = ds.add_variable::<i32>("flag", &[TIME_DIM_NAME]).unwrap();
+        var.put_attribute("long_name", "quality flag").unwrap();
+        var.put_values(&vec![0; aia_rows.len()], Extents::All)
+            .unwrap();
+
+        let aux_values =
+            |get: fn(&PostprocRow) -> f64| aia_rows.iter().map(|row| get(row) as f32).collect_vec();
+        let solzen = aux_values(|row| row.auxiliary.solzen);
+        add_private_var(&mut ds, "solzen", "solar zenith angle", "deg", &solzen);
+        let sia = aux_values(|row| row.auxiliary.sia);
+        add_private_var(&mut ds, "sia", "solar intensity average", "AU", &sia);
+
+        // The .aia file has mole fractions in parts; write_private_netcdf scales them
+        // by the factors in the qc.dat file, which for CO2 gives ppm.
+        for (xgas, ggg_unit, scale) in [("xluft", "parts", 1.0), ("xco2", "ppm", 1e6)] {
+            for varname in [xgas.to_string(), format!("{xgas}_error")] {
+                let values = aia_rows
+                    .iter()
+                    .map(|row| (row.retrieved[&varname] * scale) as f32)
+                    .collect_vec();
+                add_private_var(&mut ds, &varname, &varname, ggg_unit, &values);
+            }
+            let gas = xgas.trim_start_matches('x');
+            ds.variable_mut(xgas)
+                .unwrap()
+                .put_attribute("description", format!("0.2095*column_{gas}/column_o2"))
+                .unwrap();
+        }
+
+        let vsw_luft = vsw_rows
+            .iter()
+            .map(|row| row.retrieved["luft_6146"] as f32)
+            .collect_vec();
+        add_private_var(
+            &mut ds,
+            "vsw_luft_6146",
+            "luft total column density from the window centered at 6146 cm-1",
+            "molecules.cm^-2",
+            &vsw_luft,
+        );
+
+        // The first two spectra use GEOS FP-IT a priori and the last two GEOS IT.
+        ds.add_dimension(PRIOR_TIME_DIM_NAME, 2).unwrap();
+        ds.add_dimension("geos_version_strlen", 32).unwrap();
+        let prior_index = (0..aia_rows.len())
+            .map(|i| (2 * i / aia_rows.len()) as i32)
+            .collect_vec();
+        let mut var = ds
+            .add_variable::<i32>(PRIOR_INDEX_VARNAME, &[TIME_DIM_NAME])
+            .unwrap();
+        var.put_attribute("long_name", "prior index").unwrap();
+        var.put_values(&prior_index, Extents::All).unwrap();
+        for key in ["met2d", "met3d", "chm3d"] {
+            let mut versions = ndarray::Array2::from_elem((2, 32), NcChar(0));
+            for (mut row, version) in versions.rows_mut().into_iter().zip(["fpit", "it"]) {
+                for (c, b) in row.iter_mut().zip(version.bytes()) {
+                    *c = NcChar(b);
+                }
+            }
+            let mut var = ds
+                .add_variable::<NcChar>(
+                    &format!("geos_{key}_version"),
+                    &[PRIOR_TIME_DIM_NAME, "geos_version_strlen"],
+                )
+                .unwrap();
+            var.put(versions.view(), Extents::All).unwrap();
+        }
+
+        private_path
+    }
+
+    /// Write the test configuration and a private file from the benchmark in the
+    /// `name` output directory, then run the writer on them with the extra command
+    /// line arguments `args`. Returns the private and public file paths.
+    fn write_benchmark_public_file(name: &str, args: &[&str]) -> (PathBuf, PathBuf) {
+        let output_dir = ggg_rs::test_utils::output_dir(name);
+        let config_path = output_dir.join("test-config.toml");
+        std::fs::write(&config_path, TEST_CONFIG).unwrap();
+        let private_path = write_benchmark_private_file(&output_dir);
+        let public_path = output_dir.join("pa_ggg_benchmark.public.nc");
+
+        let mut cl = vec![
+            "write_public_netcdf".to_string(),
+            config_path.display().to_string(),
+            private_path.display().to_string(),
+            "--output-file".to_string(),
+            public_path.display().to_string(),
+        ];
+        cl.extend(args.iter().map(|a| a.to_string()));
+        driver(Cli::parse_from(cl)).unwrap();
+        (private_path, public_path)
+    }
+
+    fn string_attr(var: &netcdf::Variable, name: &str) -> String {
+        nc_utils::get_string_attr(var, name).unwrap()
+    }
+
+    #[test]
+    fn test_private_units_round_trip() {
+        let (private_path, public_path) =
+            write_benchmark_public_file("write-public-netcdf-units", &[]);
+        let private_ds = netcdf::open(&private_path).unwrap();
+        let public_ds = netcdf::open(&public_path).unwrap();
+
+        // The private file has the CF units from the GGG units, with what they were
+        // kept in the long name when the unit alone loses it.
+        let sia = private_ds.variable("sia").unwrap();
+        assert_eq!(string_attr(&sia, "units"), "1");
+        assert_eq!(
+            string_attr(&sia, "long_name"),
+            "solar intensity average (arbitrary units)"
+        );
+
+        // The public variables keep those units, and the public long name keeps the note.
+        for (varname, units, long_name) in [
+            ("solzen", "degree", "solar zenith angle"),
+            ("sia", "1", "average solar intensity (arbitrary units)"),
+            (
+                "vsw_luft_6146",
+                "cm-2",
+                "dry air column density from the 6146 cm-1 window (molecules per square centimeter)",
+            ),
+            ("xluft", "1", "column average dry air mole fraction"),
+            ("xco2", "ppm", "column average carbon dioxide mole fraction"),
+        ] {
+            let var = public_ds.variable(varname).unwrap();
+            assert_eq!(string_attr(&var, "units"), units, "units of {varname}");
+            assert_eq!(
+                string_attr(&var, "long_name"),
+                long_name,
+                "long_name of {varname}"
+            );
+        }
+        // The Xgas values must be unchanged, and still convert correctly between units.
+        for varname in ["xluft", "xluft_error", "xco2", "xco2_error"] {
+            let private_values = private_ds
+                .variable(varname)
+                .unwrap()
+                .get_values::<f32, _>(Extents::All)
+                .unwrap();
+            let public_var = public_ds.variable(varname).unwrap();
+            let public_values = public_var.get_values::<f32, _>(Extents::All).unwrap();
+            assert_eq!(public_values, private_values, "values of {varname}");
+
+            let to_ppb =
+                unit_conv_factor(&string_attr(&public_var, "units"), "ppb", Quantity::DMF).unwrap();
+            let expected_to_ppb = if varname.starts_with("xco2") {
+                1e3
+            } else {
+                1e9
+            };
+            assert_eq!(to_ppb, expected_to_ppb, "{varname} conversion to ppb");
+        }
+    }
+
+    #[test]
+    fn test_global_attributes() {
+        let (_, public_path) = write_benchmark_public_file("write-public-netcdf-global-attrs", &[]);
+        let public_ds = netcdf::open(&public_path).unwrap();
+        let global_attr = |name: &str| nc_utils::get_string_attr(&public_ds, name).unwrap();
+
+        assert_eq!(global_attr("Conventions"), "CF-1.8");
+        assert_eq!(
+            global_attr("title"),
+            "TCCON data from pa, 2004-07-21 to 2004-12-22"
+        );
+        assert_eq!(
+            global_attr("source"),
+            format!(
+                "write_public_netcdf from GGG-RS v{}",
+                env!("CARGO_PKG_VERSION")
+            )
+        );
+        assert!(
+            global_attr("history").contains(
+                "generated public file from private/engineering file pa_ggg_benchmark.private.nc"
+            ),
+            "{}",
+            global_attr("history")
+        );
+
+        // The summaries of the per-variable traceability scales and a priori sources
+        assert_eq!(
+            global_attr("wmo_or_analogous_scales"),
+            "xco2: WMO CO2 X2019"
+        );
+        assert_eq!(
+            global_attr("apriori_data_sources"),
+            "all a priori from GEOS FP-IT (2 spectra); all a priori from GEOS IT (2 spectra)"
+        );
+    }
+
+    #[test]
+    fn test_public_file_cf_compliance() {
+        let (_, public_path) = write_benchmark_public_file("write-public-netcdf-cf-check", &[]);
+        let public_ds = netcdf::open(&public_path).unwrap();
+        let report = nc_utils::check_cf_compliance(&public_ds).unwrap();
+        assert!(
+            report.is_empty(),
+            "public file should pass the CF compliance check, found:\n{}",
+            report.findings.iter().join("\n")
+        );
+    }
+
+    #[test]
+    fn test_public_file_classic_format() {
+        use crate::copying::check_classic_attr_value;
+        use netcdf::types::{FloatType, IntType, NcVariableType};
+
+        let (_, public_path) = write_benchmark_public_file(
+            "write-public-netcdf-classic",
+            &["--netcdf-format", "classic"],
+        );
+
+        {
+            let public_ds = netcdf::open(&public_path).unwrap();
+            assert_eq!(public_ds.groups().unwrap().count(), 0);
+            assert_eq!(public_ds.types().unwrap().count(), 0);
+            for attr in public_ds.attributes() {
+                check_classic_attr_value(attr.name(), &attr.value().unwrap()).unwrap();
+            }
+            for var in public_ds.variables() {
+                assert!(
+                    matches!(
+                        var.vartype(),
+                        NcVariableType::Char
+                            | NcVariableType::Int(IntType::I8 | IntType::I16 | IntType::I32)
+                            | NcVariableType::Float(FloatType::F32 | FloatType::F64)
+                    ),
+                    "variable '{}' does not have a classic type",
+                    var.name()
+                );
+                for attr in var.attributes() {
+                    check_classic_attr_value(attr.name(), &attr.value().unwrap()).unwrap();
+                }
+            }
+        }
+
+        // The classic model is recorded in the file itself, so netCDF must refuse
+        // to add a netCDF-4-only type to it.
+        let mut public_ds = netcdf::append(&public_path).unwrap();
+        assert!(public_ds
+            .add_variable::<u16>("not_classic", &[TIME_DIM_NAME])
+            .is_err());
+    }
+}
@@ -36,8 +36,11 @@ use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
 use error_stack::ResultExt;
+use indexmap::IndexMap;
 use itertools::Itertools;
 use log::{info, warn};
+use ndarray::Array2;
+use serde::Serialize;
 
 use crate::error::FileLocation;
 use crate::o2_dmf::O2DmfProvider;
@@ -49,7 +52,7 @@ use crate::readers::postproc_files::{iter_tabular_file, AuxData, AuxDataBuilder,
 use crate::readers::runlogs::RunlogDataRec;
 use crate::readers::{ProgramVersion, POSTPROC_FILL_VALUE};
 use crate::utils::{self, FileBuf, GggCompatibility};
-use crate::writers::postproc_files::write_postproc_header;
+use crate::writers::postproc_files::{PostprocHeaderInfo, PostprocWriter};
 
 pub type CollationResult<T> = Result<T, CollationError>;
 
@@ -276,13 +279,25 @@ pub enum CollationMode {
     VerticalColumns,
     /// Write the VMR scale factors only (i.e. VSF)
     VmrScaleFactors,
+    /// Write only the uncertainties of the retrieved vertical columns (i.e. VSF error * OVC)
+    ColumnErrors,
 }
 
 impl CollationMode {
-    fn ext_char(&self) -> char {
+    /// The extension of the output file (without the leading period).
+    fn file_extension(&self) -> &'static str {
         match self {
-            CollationMode::VerticalColumns => 'v',
-            CollationMode::VmrScaleFactors => 't',
+            CollationMode::VerticalColumns => "vsw",
+            CollationMode::VmrScaleFactors => "tsw",
+            CollationMode::ColumnErrors => "vse",
+        }
+    }
+
+    /// Whether this mode writes the value for each window in addition to its error.
+    fn writes_values(&self) -> bool {
+        match self {
+            CollationMode::VerticalColumns | CollationMode::VmrScaleFactors => true,
+            CollationMode::ColumnErrors => false,
         }
     }
 }
@@ -298,55 +313,194 @@ impl FromStr for CollationMode {
     /// strings are also recognized:
     ///
     /// - "v" or "vertical-columns" returns `Self::VerticalColumns`,
-    /// - "t" or "vmr-scale-factors" returns `Self::VmrScaleFactors`.
+    /// - "t" or "vmr-scale-factors" returns `Self::VmrScaleFactors`,
+    /// - "e" or "column-errors" returns `Self::ColumnErrors`.
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s.to_ascii_lowercase().as_str() {
             "v" | "vertical-columns" => Ok(Self::VerticalColumns),
             "t" | "vmr-scale-factors" => Ok(Self::VmrScaleFactors),
+            "e" | "column-errors" => Ok(Self::ColumnErrors),
             _ => Err(CollationError::UnknownMode(s.to_string())),
         }
     }
 }
 
-/// The primary entry point for this module.
+/// Return the Fortran format string for a `.vsw` or `.tsw` file with `naux`
+/// auxiliary columns (including the spectrum name) and `ngas` retrieved columns.
 ///
-/// Given a path to a multiggg.sh file, i.e. one with a series of calls to `gfit` such as:
-///
-/// ```text
-/// /home/jlaugh/GGG/ggg-my-devel/bin/gfit luft_6146.pa_ggg_benchmark.ggg>/dev/null
-/// /home/jlaugh/GGG/ggg-my-devel/bin/gfit hf_4038.pa_ggg_benchmark.ggg>/dev/null
-/// /home/jlaugh/GGG/ggg-my-devel/bin/gfit h2o_4565.pa_ggg_benchmark.ggg>/dev/null
-/// /home/jlaugh/GGG/ggg-my-devel/bin/gfit h2o_4570.pa_ggg_benchmark.ggg>/dev/null
-/// ...
-/// ```
+/// This includes the "a1" column after the spectrum name expected by the Fortran
+/// programs, so it should be written in the header but not used to write the data.
+pub fn xsw_format_str(naux: usize, ngas: usize) -> String {
+    format!("(a57,a1,f13.8,{}f13.5,{ngas}(1pe13.5))", naux - 2)
+}
+
+/// A summary of one run of [`collate_results`], meant to be written as JSON for automated pipelines.
 ///
-/// this will find the `.col` files for each line of this file *not* beginning with
-/// a colon and combine their data into a single `.Xsw` file. The `.col` files must
-/// be present in the same directory, and must all reference the same `.ray` file
-/// and runlog in their headers (and those files must exist as well). Other inputs:
+/// The JSON object has one key per field, named as the field is. Its layout is versioned by
+/// `schema_version`: fields may be added without changing the version, but removing, renaming,
+/// or changing the meaning of a field must increment it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CollationSummary {
+    /// Version of this summary's layout, currently 1.
+    pub schema_version: u32,
+    /// Path to the `.Xsw` file written.
+    pub output_file: PathBuf,
+    /// Number of windows (i.e. `.col` files) collated.
+    pub num_windows: usize,
+    /// Number of spectra (data rows) in the output file. Spectra from different detectors
+    /// that the indexer puts in the same row count once.
+    pub num_spectra: usize,
+    /// Number of times a spectrum in the runlog had an earlier ZPD time than the one before it.
+    pub num_negative_timesteps: usize,
+    /// Number of window values found in the `.col` files.
+    pub num_values_found: usize,
+    /// Windows (as named in the output file) that are missing a value for at least one row,
+    /// with the number of rows missing a value, in the order the windows were collated.
+    pub missing_windows: IndexMap<String, usize>,
+}
+
+impl CollationSummary {
+    pub const SCHEMA_VERSION: u32 = 1;
+
+    /// Whether every window had a value for every row.
+    pub fn is_complete(&self) -> bool {
+        self.missing_windows.is_empty()
+    }
+}
+
+/// Collated data held in memory, as returned by [`collate_data`].
+#[derive(Debug)]
+pub struct CollatedData {
+    /// All column names, auxiliary columns (starting with "spectrum") first, then the retrieved
+    /// columns in the order the windows were collated. These are the keys for each row.
+    pub columns: Vec<String>,
+    /// How many of `columns` are auxiliary columns.
+    pub naux: usize,
+    /// The windows collated, in the order their columns appear.
+    pub windows: Vec<String>,
+    /// One row per output line, with the auxiliary data and retrieved values. Values that were
+    /// missing from the `.col` files are [`POSTPROC_FILL_VALUE`].
+    pub rows: Vec<PostprocRow>,
+    /// The runlog that the spectrum order and auxiliary data came from.
+    pub runlog: PathBuf,
+    /// The GSETUP version recorded in the `.col` files.
+    pub gsetup_version: ProgramVersion,
+    /// The GFIT version recorded in the `.col` files.
+    pub gfit_version: ProgramVersion,
+    /// The scale factor for each window, if any of the `.col` files recorded one.
+    pub window_sfs: Option<Vec<String>>,
+    missing: MissingValues,
+}
+
+impl CollatedData {
+    /// The names of the retrieved (i.e. non-auxiliary) columns.
+    pub fn retrieved_columns(&self) -> &[String] {
+        &self.columns[self.naux..]
+    }
+
+    /// The retrieved values as an array with one row per output line and one column per
+    /// entry in [`CollatedData::retrieved_columns`].
+    pub fn retrieved_array(&self) -> Array2<f64> {
+        let colnames = self.retrieved_columns();
+        Array2::from_shape_fn((self.rows.len(), colnames.len()), |(i, j)| {
+            self.rows[i]
+                .retrieved
+                .get(&colnames[j])
+                .copied()
+                .unwrap_or(POSTPROC_FILL_VALUE)
+        })
+    }
+
+    /// The auxiliary data (spectrum name, time, location, met data, etc.) of each output line.
+    pub fn aux_records(&self) -> impl Iterator<Item = &AuxData> {
+        self.rows.iter().map(|row| &row.auxiliary)
+    }
+
+    /// Each (column, spectrum) pair for which the `.col` files did not have a value.
+    pub fn missing_values(&self) -> &[(String, String)] {
+        &self.missing.missing_window_spec
+    }
+}
+
+/// Which `.col` files to collate and how, used by both [`collate_data`] and [`collate_results`].
+#[derive(Debug, Clone, Copy)]
+pub struct CollationOptions<'a> {
+    /// The multiggg.sh file listing the windows to collate; the `.col` files must be in
+    /// the same directory.
+    pub multiggg_file: &'a Path,
+    /// Overrides the runlog given in the `.col` file headers, if provided. It must exist.
+    /// The spectrum order, negative time steps, and output file name are all taken from
+    /// whichever runlog is used.
+    pub runlog: Option<&'a Path>,
+    /// Restricts collation to the named windows (e.g. "co2_6220"), if provided. Every
+    /// window named must be in `multiggg_file`. All spectra in the runlog are still included.
+    pub windows: Option<&'a [String]>,
+    /// Controls what values are written from each `.col` file.
+    pub mode: CollationMode,
+    /// Which GGG version's set of auxiliary columns to include.
+    pub compatibility: GggCompatibility,
+}
+
+impl<'a> CollationOptions<'a> {
+    /// Collate all windows in `multiggg_file`, using the runlog from the `.col` file headers.
+    pub fn new(
+        multiggg_file: &'a Path,
+        mode: CollationMode,
+        compatibility: GggCompatibility,
+    ) -> Self {
+        Self {
+            multiggg_file,
+            runlog: None,
+            windows: None,
+            mode,
+            compatibility,
+        }
+    }
+
+    /// Use `runlog` instead of the runlog given in the `.col` file headers.
+    pub fn with_runlog(mut self, runlog: Option<&'a Path>) -> Self {
+        self.runlog = runlog;
+        self
+    }
+
+    /// Only collate the windows named in `windows`.
+    pub fn with_windows(mut self, windows: Option<&'a [String]>) -> Self {
+        self.windows = windows;
+        self
+    }
+}
+
+/// Where and how [`collate_results`] writes its outputs.
+#[derive(Debug, Clone)]
+pub struct CollationOutput<'a> {
+    /// The program version to put in the header of the output file.
+    pub collate_version: ProgramVersion,
+    /// The directory to write the output file to; defaults to the directory containing
+    /// the multiggg.sh file.
+    pub output_dir: Option<&'a Path>,
+    /// If given, a report of any negative time steps in the runlog is written to this file.
+    pub neg_timesteps_file: Option<&'a Path>,
+}
+
+/// Collate the `.col` files listed in a multiggg.sh file in memory, without writing any output.
 ///
-/// - `indexer` is an instance of a struct that implements [`CollationIndexer`]; this
-///   controls what row of the `.Xsw` file values from the runlog, `.ray` file, and
-///   `.col` files go into.
-/// -  `mode` controls what values are written from each `.col` file.
-/// - `collate_version` specifies what program version to put in the header of the output file.
-pub fn collate_results<I: CollationIndexer, P: CollationPrefixer>(
-    multiggg_file: &Path,
-    mut indexer: I,
+/// This does all of the work of [`collate_results`] except writing the files; see that function
+/// for the meaning of the inputs. The indexer is borrowed so that the caller can still query it
+/// afterwards, e.g. for negative time steps.
+pub fn collate_data<I: CollationIndexer, P: CollationPrefixer>(
+    opts: &CollationOptions,
+    indexer: &mut I,
     mut prefixer: Option<P>,
-    o2_dmf_provider: Box<dyn O2DmfProvider>,
-    mode: CollationMode,
-    collate_version: ProgramVersion,
-    output_dir: Option<&Path>,
-    write_neg_timesteps: bool,
-    compatibility: GggCompatibility,
-) -> error_stack::Result<(), CollationError> {
-    let run_dir = multiggg_file.parent().ok_or_else(|| {
-        CollationError::could_not_find(format!(
-            "run directory (could not get parent directory of the given multiggg file, {})",
-            multiggg_file.display()
-        ))
-    })?;
+    o2_dmf_provider: &dyn O2DmfProvider,
+) -> error_stack::Result<CollatedData, CollationError> {
+    let CollationOptions {
+        multiggg_file,
+        runlog,
+        windows,
+        mode,
+        compatibility,
+    } = *opts;
+    let run_dir = get_run_dir(multiggg_file)?;
 
     info!("Collating results in {}", run_dir.display());
     let mut missing = MissingValues::default();
@@ -358,14 +512,26 @@ pub fn collate_results<I: CollationIndexer, P: CollationPrefixer>(
             run_dir.display()
         ))
     })?;
-    let runlog = get_file_from_col_header(&col_files, run_dir, |h| h.runlog_file.path)
-        .change_context_lazy(|| CollationError::could_not_find("runlog"))?;
+    let col_files = if let Some(windows) = windows {
+        select_window_col_files(col_files, windows, multiggg_file)?
+    } else {
+        col_files
+    };
+    let runlog = if let Some(runlog) = runlog {
+        if !runlog.exists() {
+            return Err(CollationError::missing_input(format!(
+                "the runlog given explicitly, {}, does not exist",
+                runlog.display()
+            ))
+            .into());
+        }
+        runlog.to_path_buf()
+    } else {
+        get_file_from_col_header(&col_files, run_dir, |h| h.runlog_file.path)
+            .change_context_lazy(|| CollationError::could_not_find("runlog"))?
+    };
     let ray_file = get_file_from_col_header(&col_files, run_dir, |h| h.ray_file.path)
         .change_context_lazy(|| CollationError::could_not_find(".ray file"))?;
-    let runlog_name = runlog
-        .file_stem()
-        .ok_or_else(|| CollationError::could_not_find("file stem of the runlog"))?
-        .to_string_lossy();
 
     info!("{} .col files will be collated", col_files.len());
     info!("Spectrum order taken from {}", runlog.display());
@@ -393,9 +559,9 @@ pub fn collate_results<I: CollationIndexer, P: CollationPrefixer>(
     // Also add the mean O2 DMF - it's easier to add it here and carry it
     // through the post processing than to add an aux column partway through
     // the processing.
-    add_zmin(&mut aux_data_builders, &mut indexer, &ray_file)?;
+    add_zmin(&mut aux_data_builders, indexer, &ray_file)?;
     add_run(&mut aux_data_builders);
-    add_o2dmf(&mut aux_data_builders, o2_dmf_provider.as_ref(), &indexer)?;
+    add_o2dmf(&mut aux_data_builders, o2_dmf_provider, indexer)?;
 
     // Now we can make our proper postprocessing rows, because we have successfully
     // filled in all the values that the auxiliary data needs.
@@ -409,6 +575,7 @@ pub fn collate_results<I: CollationIndexer, P: CollationPrefixer>(
 
     // Get values from the .col files
     let ncol = col_files.len();
+    let mut collated_windows = Vec::with_capacity(ncol);
     for (idx, cfile) in col_files.into_iter().enumerate() {
         let window = get_window_from_col_file(&cfile)?;
         info!("Reading .col file {}/{ncol}: {window}", idx + 1);
@@ -422,7 +589,7 @@ pub fn collate_results<I: CollationIndexer, P: CollationPrefixer>(
 
         add_col_value(
             &mut rows,
-            &mut indexer,
+            indexer,
             &cfile,
             mode,
             &val_colname,
@@ -430,10 +597,79 @@ pub fn collate_results<I: CollationIndexer, P: CollationPrefixer>(
             &mut missing,
         )
         .change_context_lazy(|| CollationError::col_file_error(&cfile))?;
-        columns.push(val_colname.to_string());
+        if mode.writes_values() {
+            columns.push(val_colname.to_string());
+        }
         columns.push(val_err_colname);
+        collated_windows.push(window.to_string());
     }
 
+    Ok(CollatedData {
+        columns,
+        naux,
+        windows: collated_windows,
+        rows,
+        runlog,
+        gsetup_version,
+        gfit_version,
+        window_sfs,
+        missing,
+    })
+}
+
+/// The primary entry point for this module.
+///
+/// Given a path to a multiggg.sh file, i.e. one with a series of calls to `gfit` such as:
+///
+/// ```text
+/// /home/jlaugh/GGG/ggg-my-devel/bin/gfit luft_6146.pa_ggg_benchmark.ggg>/dev/null
+/// /home/jlaugh/GGG/ggg-my-devel/bin/gfit hf_4038.pa_ggg_benchmark.ggg>/dev/null
+/// /home/jlaugh/GGG/ggg-my-devel/bin/gfit h2o_4565.pa_ggg_benchmark.ggg>/dev/null
+/// /home/jlaugh/GGG/ggg-my-devel/bin/gfit h2o_4570.pa_ggg_benchmark.ggg>/dev/null
+/// ...
+/// ```
+///
+/// this will find the `.col` files for each line of this file *not* beginning with
+/// a colon and combine their data into a single `.Xsw` file. The `.col` files must
+/// be present in the same directory, and must all reference the same `.ray` file
+/// and runlog in their headers (and those files must exist as well). Other inputs:
+///
+/// - `opts` gives the multiggg.sh file and controls which windows and runlog are used and
+///   what values are written from each `.col` file; see [`CollationOptions`].
+/// - `output` controls where the output files go and the program version put in the
+///   output file header; see [`CollationOutput`].
+/// - `indexer` is an instance of a struct that implements [`CollationIndexer`]; this
+///   controls what row of the `.Xsw` file values from the runlog, `.ray` file, and
+///   `.col` files go into.
+///
+/// To get the collated data in memory instead of written to a file, use [`collate_data`].
+///
+/// Returns a [`CollationSummary`] describing what was collated.
+pub fn collate_results<I: CollationIndexer, P: CollationPrefixer>(
+    opts: &CollationOptions,
+    output: CollationOutput,
+    mut indexer: I,
+    prefixer: Option<P>,
+    o2_dmf_provider: Box<dyn O2DmfProvider>,
+) -> error_stack::Result<CollationSummary, CollationError> {
+    let run_dir = get_run_dir(opts.multiggg_file)?;
+    let data = collate_data(opts, &mut indexer, prefixer, o2_dmf_provider.as_ref())?;
+    let CollatedData {
+        columns,
+        naux,
+        windows: collated_windows,
+        rows,
+        runlog,
+        gsetup_version,
+        gfit_version,
+        window_sfs,
+        missing,
+    } = data;
+    let runlog_name = runlog
+        .file_stem()
+        .ok_or_else(|| CollationError::could_not_find("file stem of the runlog"))?
+        .to_string_lossy();
+
     // Write the output file
     let extra_lines = if let Some(sfs) = window_sfs {
         vec![
@@ -443,49 +679,33 @@ pub fn collate_results<I: CollationIndexer, P: CollationPrefixer>(
     } else {
         vec![o2_dmf_provider.header_line()]
     };
-    let output_dir = output_dir.unwrap_or(run_dir);
-    let xsw_file = output_dir.join(format!("{runlog_name}.{}sw", mode.ext_char()));
+    let output_dir = output.output_dir.unwrap_or(run_dir);
+    let xsw_file = output_dir.join(format!("{runlog_name}.{}", opts.mode.file_extension()));
     let f = std::fs::File::create(&xsw_file)
         .change_context_lazy(|| CollationError::could_not_write(&xsw_file))?;
-    let mut writer = std::io::BufWriter::new(f);
-    let format_str = format!(
-        "(a57,a1,f13.8,{}f13.5,{}(1pe13.5))",
-        naux - 2,
-        columns.len() - naux
-    );
-    write_postproc_header(
-        &mut writer,
-        columns.len(),
-        rows.len(),
-        naux,
-        &[collate_version, gfit_version, gsetup_version],
-        &extra_lines,
-        POSTPROC_FILL_VALUE,
-        &format_str,
-        &columns,
+    let writer = std::io::BufWriter::new(f);
+    let format_str = xsw_format_str(naux, columns.len() - naux);
+    let mut writer = PostprocWriter::new(
+        writer,
+        PostprocHeaderInfo {
+            nrow: rows.len(),
+            naux,
+            program_versions: &[output.collate_version, gfit_version, gsetup_version],
+            extra_lines: &extra_lines,
+            missing_value: POSTPROC_FILL_VALUE,
+            format_str: &format_str,
+            column_names: &columns,
+        },
     )
     .change_context_lazy(|| CollationError::could_not_write(&xsw_file))?;
 
-    // We don't write the "a1" column that has the colon/semicolon
-    let writer_format_str = format_str.replace("a1,", "1x");
-    let write_format = fortformat::FortFormat::parse(&writer_format_str).map_err(|e| {
-        CollationError::parsing_error(format!(
-            "Could not parse format .xsw format string '{writer_format_str}': {e}"
-        ))
-    })?;
-
     info!("Writing results to {}...", xsw_file.display());
-    let ser_settings = fortformat::ser::SerSettings::default()
-        .align_left_str(true)
-        .allow_skipped_fields(true);
-    fortformat::ser::many_to_writer_custom(
-        &rows,
-        &write_format,
-        Some(&columns),
-        &ser_settings,
-        &mut writer,
-    )
-    .change_context_lazy(|| CollationError::could_not_write(&xsw_file))?;
+    writer
+        .write_rows(&rows)
+        .change_context_lazy(|| CollationError::could_not_write(&xsw_file))?;
+    writer
+        .finish()
+        .change_context_lazy(|| CollationError::could_not_write(&xsw_file))?;
     info!("Results written to {}.", xsw_file.display());
 
     missing
@@ -499,11 +719,68 @@ pub fn collate_results<I: CollationIndexer, P: CollationPrefixer>(
             log::error!("Writing the percentage of found/missing values to stdout failed: {e}")
         });
 
-    if write_neg_timesteps {
-        report_negative_time_steps(&output_dir.join("collate_results.nts"), indexer)
+    let summary = CollationSummary {
+        schema_version: CollationSummary::SCHEMA_VERSION,
+        output_file: xsw_file,
+        num_windows: collated_windows.len(),
+        num_spectra: rows.len(),
+        num_negative_timesteps: indexer.get_negative_runlog_timesteps()?.len(),
+        num_values_found: missing.nfound,
+        missing_windows: missing.missing_counts_by_window(),
+    };
+
+    if let Some(nts_file) = output.neg_timesteps_file {
+        report_negative_time_steps(nts_file, indexer)
             .unwrap_or_else(|e| log::error!("Writing the negative time steps report failed: {e}"));
     }
-    Ok(())
+    Ok(summary)
+}
+
+/// The directory containing the multiggg.sh file, which is where the `.col` files are expected.
+fn get_run_dir(multiggg_file: &Path) -> error_stack::Result<&Path, CollationError> {
+    let run_dir = multiggg_file.parent().ok_or_else(|| {
+        CollationError::could_not_find(format!(
+            "run directory (could not get parent directory of the given multiggg file, {})",
+            multiggg_file.display()
+        ))
+    })?;
+    Ok(run_dir)
+}
+
+/// Keep only the `.col` files for the windows named in `windows`, in the order they appear
+/// in the multiggg file. Returns an error if any of the windows has no `.col` file.
+fn select_window_col_files(
+    col_files: Vec<PathBuf>,
+    windows: &[String],
+    multiggg_file: &Path,
+) -> error_stack::Result<Vec<PathBuf>, CollationError> {
+    let available: Vec<&str> = col_files
+        .iter()
+        .map(|f| get_window_from_col_file(f))
+        .try_collect()?;
+
+    let not_found = windows
+        .iter()
+        .filter(|w| !available.contains(&w.as_str()))
+        .collect_vec();
+    if !not_found.is_empty() {
+        return Err(CollationError::missing_input(format!(
+            "window(s) {} requested but not present in {}",
+            not_found.iter().join(", "),
+            multiggg_file.display()
+        ))
+        .into());
+    }
+
+    let selected = col_files
+        .into_iter()
+        .filter(|f| get_window_from_col_file(f).is_ok_and(|w| windows.iter().any(|req| req == w)))
+        .collect_vec();
+    info!(
+        "Collating {} of the windows in the multiggg file",
+        selected.len()
+    );
+    Ok(selected)
 }
 
 pub fn get_window_from_col_file(col_file: &Path) -> Result<&str, CollationError> {
@@ -715,7 +992,7 @@ fn add_col_value<I: CollationIndexer>(
         log::trace!("irow = {irow}: col_row = {col_row:?}");
 
         let (val, val_err) = match mode {
-            CollationMode::VerticalColumns => {
+            CollationMode::VerticalColumns | CollationMode::ColumnErrors => {
                 let vsf = col_row
                     .get_primary_gas_quantity(ColRetQuantity::Vsf)
                     .ok_or_else(|| CollationError::missing_column(col_file, "primary gas VSF"))?;
@@ -812,6 +1089,14 @@ impl MissingValues {
         self.nfound += n;
     }
 
+    fn missing_counts_by_window(&self) -> IndexMap<String, usize> {
+        let mut counts = IndexMap::new();
+        for (window, _) in self.missing_window_spec.iter() {
+            *counts.entry(window.clone()).or_default() += 1;
+        }
+        counts
+    }
+
     fn write_missing_report(&self, path: &Path) -> std::io::Result<()> {
         let f = std::fs::File::create(path)?;
         let mut writer = std::io::BufWriter::new(f);
@@ -848,13 +1133,38 @@ fn report_negative_time_steps<I: CollationIndexer>(
     })?;
     let mut writer = std::io::BufWriter::new(f);
     for (prev_rec, next_rec) in indexer.get_negative_runlog_timesteps()? {
-        let this_spec = &next_rec.spectrum_name;
-        let (prev_dec_year, _, _) =
-            utils::to_decimal_year_day_hour(prev_rec.year, prev_rec.day, prev_rec.hour);
-        let (this_dec_year, _, _) =
-            utils::to_decimal_year_day_hour(next_rec.year, next_rec.day, next_rec.hour);
-        writeln!(&mut writer, "  Negative time step (runlog unsorted?) {this_spec} {prev_dec_year:12.6} {this_dec_year:12.6}")
-            .change_context_lazy(|| CollationError::CouldNotWrite { path: report_file.to_path_buf() })?;
+        writeln!(
+            &mut writer,
+            "{}",
+            format_negative_time_step(prev_rec, next_rec)
+        )
+        .change_context_lazy(|| CollationError::CouldNotWrite {
+            path: report_file.to_path_buf(),
+        })?;
     }
     Ok(())
 }
+
+/// Format one line of the negative time step report.
+///
+/// The line starts as in the standard `collate_results` (the later spectrum and both times
+/// as decimal years), then adds the name of the earlier spectrum and both ZPD times.
+fn format_negative_time_step(prev_rec: &RunlogDataRec, next_rec: &RunlogDataRec) -> String {
+    let zpd_str = |rec: &RunlogDataRec| {
+        rec.zpd_time()
+            .map(|t| t.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string())
+            .unwrap_or_else(|| "invalid_time".to_string())
+    };
+
+    let this_spec = &next_rec.spectrum_name;
+    let prev_spec = &prev_rec.spectrum_name;
+    let (prev_dec_year, _, _) =
+        utils::to_decimal_year_day_hour(prev_rec.year, prev_rec.day, prev_rec.hour);
+    let (this_dec_year, _, _) =
+        utils::to_decimal_year_day_hour(next_rec.year, next_rec.day, next_rec.hour);
+    format!(
+        "  Negative time step (runlog unsorted?) {this_spec} {prev_dec_year:12.6} {this_dec_year:12.6} after {prev_spec} ZPD times {} {}",
+        zpd_str(prev_rec),
+        zpd_str(next_rec)
+    )
+}
@@ -222,10 +222,19 @@ pub enum WriteError {
     /// [`error_stack::Report`] with the actual error as another part of the stack.
     #[error("I/O error")]
     IoError,
+
+    /// An input file needed to produce the output could not be read or parsed. Like
+    /// `IoError`, the underlying error is expected to be lower in the [`error_stack::Report`].
+    #[error("Error reading input file {}", .0.display())]
+    ReadError(PathBuf),
 }
 
 impl WriteError {
     pub fn convert_error<S: Into<String>>(reason: S) -> Self {
         Self::ConvertError(reason.into())
     }
+
+    pub fn read_error<P: Into<PathBuf>>(path: P) -> Self {
+        Self::ReadError(path.into())
+    }
 }
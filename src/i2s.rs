@@ -17,6 +17,9 @@ pub type CatalogueEntryResult<T> = Result<T, CatalogueEntryError>;
 pub const I2S2014_NUM_HEADER_PARAMS: usize = 27;
 /// Number of header parameters in an I2S 2020 file
 pub const I2S2020_NUM_HEADER_PARAMS: usize = 28;
+/// Header parameters that are paths in both I2S 2014 and 2020 files: the interferogram
+/// input directory (#1), the spectrum output directory (#2), and the frequency limits file (#4)
+pub const I2S_PATH_HEADER_PARAMS: [usize; 3] = [1, 2, 4];
 
 /// Error indicating an unknown I2S version
 #[derive(Debug)]
@@ -52,6 +55,11 @@ impl I2SVersion {
         }
     }
 
+    /// Return how many lines the given (1-based) header parameter takes up in an I2S input file.
+    ///
+    /// Note that `param_num` is the number of the parameter about to be read, not the number
+    /// of parameters read so far. The parameter and line iterators in this module count the
+    /// parameters already read, so must pass that count plus one.
     pub fn num_lines_for_param(&self, param_num: usize) -> usize {
         match self {
             I2SVersion::I2S2014 => 1,
@@ -66,6 +74,18 @@ impl I2SVersion {
             },
         }
     }
+
+    /// Return `true` if the given (1-based) header parameter is a path to a file or directory.
+    ///
+    /// See [`I2S_PATH_HEADER_PARAMS`] for which parameters these are.
+    pub fn param_is_path(&self, param_num: usize) -> bool {
+        // The path parameters did not move between I2S 2014 and 2020
+        match self {
+            I2SVersion::I2S2014 | I2SVersion::I2S2020 => {
+                I2S_PATH_HEADER_PARAMS.contains(&param_num)
+            }
+        }
+    }
 }
 
 impl Display for I2SVersion {
@@ -229,10 +249,11 @@ impl Iterator for I2SParamIter {
     fn next(&mut self) -> Option<Self::Item> {
         let max_n_params = self.max_n_param();
         loop {
+            // curr_param counts the parameters already read, so the next one is curr_param + 1
             let (is_param, param) = match iter_i2s_lines_inner(
                 &mut self.file,
                 self.curr_param,
-                self.i2s_version.num_lines_for_param(self.curr_param),
+                self.i2s_version.num_lines_for_param(self.curr_param + 1),
                 max_n_params,
             ) {
                 Some(Ok(line)) => line,
@@ -373,10 +394,11 @@ impl<R: BufRead> Iterator for I2SLineIter<R> {
     type Item = std::io::Result<(I2SLineType, String)>;
 
     fn next(&mut self) -> Option<Self::Item> {
+        // curr_param counts the parameters already read, so the next one is curr_param + 1
         let (is_param, line) = match iter_i2s_lines_inner(
             &mut self.file,
             self.curr_param,
-            self.i2s_version.num_lines_for_param(self.curr_param),
+            self.i2s_version.num_lines_for_param(self.curr_param + 1),
             None,
         ) {
             Some(Ok(v)) => v,
@@ -681,6 +703,23 @@ impl OpusCatalogueEntry {
     }
 }
 
+/// Split a catalog row from an I2S input file into its columns.
+///
+/// This splits on whitespace, except that a spectrum name containing spaces, which
+/// [`OpusCatalogueEntry`] writes in double quotes, is kept as one column (without the
+/// quotes). Any comment should already be removed from `row`. A complete row has
+/// `<OpusCatalogueEntry as tabled::Tabled>::LENGTH` columns.
+pub fn split_catalog_row(row: &str) -> Vec<&str> {
+    let row = row.trim_start();
+    if let Some((name, rest)) = row.strip_prefix('"').and_then(|r| r.split_once('"')) {
+        let mut columns = vec![name];
+        columns.extend(rest.split_whitespace());
+        columns
+    } else {
+        row.split_whitespace().collect()
+    }
+}
+
 impl tabled::Tabled for OpusCatalogueEntry {
     const LENGTH: usize = 18;
 
@@ -878,3 +917,110 @@ impl OpusCatalogueEntryBuilder {
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// Make an I2S 2020 input file where every header parameter's value is its number,
+    /// except #17, whose minimum and maximum take two lines.
+    fn make_i2s2020_input() -> String {
+        let mut input = String::from(": A comment before the header\n");
+        for i in 1..=I2S2020_NUM_HEADER_PARAMS {
+            if i == 17 {
+                input.push_str("0.005 0.005 : Min allowed igram values\n");
+                input.push_str("1.000 1.000 : Max allowed igram values\n");
+            } else {
+                input.push_str(&format!("{i} : parameter {i}\n"));
+            }
+        }
+        input.push_str(": Catalog\n");
+        input.push_str("spectrum.001 2020 1 1 0\n");
+        input
+    }
+
+    #[test]
+    fn test_split_catalog_row() {
+        let entry = OpusCatalogueEntry::build("pa 20040721 saaaaa.043".to_string())
+            .with_time(2004, 7, 21, 43)
+            .unwrap()
+            .finalize(-999.0)
+            .unwrap();
+        let mut row = vec![];
+        entry.write(&mut row).unwrap();
+        let row = String::from_utf8(row).unwrap();
+
+        let columns = split_catalog_row(&row);
+        assert_eq!(
+            columns.len(),
+            <OpusCatalogueEntry as tabled::Tabled>::LENGTH
+        );
+        assert_eq!(
+            columns[..5],
+            ["pa 20040721 saaaaa.043", "2004", "7", "21", "43"]
+        );
+
+        let columns = split_catalog_row("  pa20040721saaaaa.043 2004 7 21 43");
+        assert_eq!(columns, ["pa20040721saaaaa.043", "2004", "7", "21", "43"]);
+    }
+
+    #[test]
+    fn test_num_lines_for_param() {
+        // Parameter numbers are 1-based: the two-line min/max check is parameter 17, so passing
+        // the number of parameters already read (16 at that point) would read it as one line
+        // and parameter 16 as two.
+        let nlines: Vec<_> = (15..=18)
+            .map(|i| I2SVersion::I2S2020.num_lines_for_param(i))
+            .collect();
+        assert_eq!(nlines, [1, 1, 2, 1]);
+        assert!((1..=I2S2014_NUM_HEADER_PARAMS)
+            .all(|i| I2SVersion::I2S2014.num_lines_for_param(i) == 1));
+    }
+
+    #[test]
+    fn test_param_iter_multiline_param() {
+        let output_dir = crate::test_utils::output_dir("i2s-multiline-param");
+        let input_file = output_dir.join("i2s-multiline-param-test.in");
+        std::fs::write(&input_file, make_i2s2020_input()).unwrap();
+        let params: Vec<(usize, String)> =
+            iter_i2s_header_params_with_number(&input_file, I2SVersion::I2S2020)
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap();
+
+        assert_eq!(params.len(), I2S2020_NUM_HEADER_PARAMS);
+        for (i, value) in params {
+            let value = value.split_whitespace().collect::<Vec<_>>().join(" ");
+            if i == 17 {
+                assert_eq!(value, "0.005 0.005 1.000 1.000");
+            } else {
+                assert_eq!(value, i.to_string(), "wrong value for parameter {i}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_line_iter_multiline_param() {
+        let input = make_i2s2020_input();
+        let lines: Vec<(I2SLineType, String)> =
+            I2SLineIter::new(Cursor::new(input), I2SVersion::I2S2020)
+                .collect::<Result<_, _>>()
+                .unwrap();
+
+        // The leading comment, one entry per parameter, the catalog comment, and one row
+        assert_eq!(lines.len(), I2S2020_NUM_HEADER_PARAMS + 3);
+        assert!(matches!(lines[0].0, I2SLineType::HeaderLine));
+        for (i, (line_type, line)) in lines[1..=I2S2020_NUM_HEADER_PARAMS].iter().enumerate() {
+            let param = i + 1;
+            assert_eq!(line_type.header_param(), Some(param));
+            let nlines = if param == 17 { 2 } else { 1 };
+            assert_eq!(line.lines().count(), nlines, "for parameter {param}");
+        }
+        assert!(matches!(
+            lines[I2S2020_NUM_HEADER_PARAMS + 2].0,
+            I2SLineType::CatalogRow
+        ));
+    }
+}
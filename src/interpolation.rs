@@ -137,6 +137,71 @@ impl InterpolationMethod for ConstantValueInterp {
     }
 }
 
+/// Linear interpolation between the two input points bracketing each output coordinate.
+///
+/// Unlike [`ConstantValueInterp`], this requires that the input x-coordinates be sorted
+/// in ascending order, which allows each interpolation to use a binary search rather
+/// than a scan of the full input. This makes it suitable for long inputs, such as spectra.
+/// When extrapolation is allowed, the first or last two points are used to extend the line.
+pub struct LinearInterp {
+    allow_extrapolation: bool,
+}
+
+impl LinearInterp {
+    pub fn new(allow_extrapolation: bool) -> Self {
+        Self {
+            allow_extrapolation,
+        }
+    }
+}
+
+impl InterpolationMethod for LinearInterp {
+    fn interp1d<F: Float + Debug>(
+        &self,
+        input_x: &[F],
+        input_y: &[F],
+        output_x: F,
+    ) -> Result<F, InterpolationError> {
+        // We do not use check_1d_inputs here because its bounds check must scan the whole input
+        // to handle unsorted coordinates; since we require sorted coordinates, the ends suffice.
+        if input_x.len() != input_y.len() {
+            return Err(InterpolationError::InputLengthMismatch {
+                x_len: input_x.len(),
+                y_len: input_y.len(),
+            });
+        }
+
+        if input_x.len() < 2 {
+            return Err(InterpolationError::InputTooShort {
+                req_len: 2,
+                actual_len: input_x.len(),
+            });
+        }
+
+        let n = input_x.len();
+        if !self.allow_extrapolation && (output_x < input_x[0] || output_x > input_x[n - 1]) {
+            return Err(InterpolationError::OutOfDomain {
+                left: format!("{:?}", input_x[0]),
+                right: format!("{:?}", input_x[n - 1]),
+                out: format!("{output_x:?}"),
+            });
+        }
+
+        // Find the index of the first point greater than the output coordinate, then keep
+        // the bracketing pair inside the array so that extrapolation uses the end segments.
+        let i_right = input_x.partition_point(|&x| x <= output_x).clamp(1, n - 1);
+        let i_left = i_right - 1;
+
+        let (x0, x1) = (input_x[i_left], input_x[i_right]);
+        let (y0, y1) = (input_y[i_left], input_y[i_right]);
+        if x1 == x0 {
+            return Ok(y0);
+        }
+
+        Ok(y0 + (y1 - y0) * (output_x - x0) / (x1 - x0))
+    }
+}
+
 fn datetime_to_float<Z: TimeZone>(t: &DateTime<Z>) -> f64 {
     let ts = t.timestamp() as f64;
     let ts_frac = t.timestamp_subsec_nanos() as f64;
@@ -266,6 +331,51 @@ mod tests {
         assert_abs_diff_eq!(y_out, 6.0);
     }
 
+    #[test]
+    fn test_linear_no_extrap() {
+        let interpolator = LinearInterp::new(false);
+        let x = [1.0, 2.0, 4.0];
+        let y = [2.0, 4.0, 0.0];
+
+        let y_out = interpolator
+            .interp1d(x.as_slice(), y.as_slice(), 1.25)
+            .unwrap();
+        assert_abs_diff_eq!(y_out, 2.5);
+
+        let y_out = interpolator
+            .interp1d(x.as_slice(), y.as_slice(), 3.0)
+            .unwrap();
+        assert_abs_diff_eq!(y_out, 2.0);
+
+        // The end points must be included in the domain
+        let y_out = interpolator
+            .interp1d(x.as_slice(), y.as_slice(), 4.0)
+            .unwrap();
+        assert_abs_diff_eq!(y_out, 0.0);
+
+        let err = interpolator
+            .interp1d(x.as_slice(), y.as_slice(), 4.5)
+            .unwrap_err();
+        assert!(matches!(err, InterpolationError::OutOfDomain { .. }));
+    }
+
+    #[test]
+    fn test_linear_with_extrap() {
+        let interpolator = LinearInterp::new(true);
+        let x = [1.0, 2.0, 4.0];
+        let y = [2.0, 4.0, 0.0];
+
+        let y_out = interpolator
+            .interp1d(x.as_slice(), y.as_slice(), 0.0)
+            .unwrap();
+        assert_abs_diff_eq!(y_out, 0.0);
+
+        let y_out = interpolator
+            .interp1d(x.as_slice(), y.as_slice(), 5.0)
+            .unwrap();
+        assert_abs_diff_eq!(y_out, -2.0);
+    }
+
     fn make_test_datetimes() -> [DateTime<Utc>; 3] {
         let fmt = "%Y-%m-%d %H:%M";
         [
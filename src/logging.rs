@@ -1,17 +1,39 @@
 use log4rs::{
     append::console::{ConsoleAppender, Target},
     config::{Appender, Root},
-    encode::pattern::PatternEncoder,
+    encode::{json::JsonEncoder, pattern::PatternEncoder, Encode},
     Config,
 };
 
+/// Format for log messages written to the terminal.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogFormat {
+    /// Human-readable text, one message per line.
+    #[default]
+    Text,
+    /// Newline-delimited JSON, one object per message.
+    Json,
+}
+
+/// Use as a field in a CLI with `#[command(flatten)]` to include the
+/// `--log-format` argument in that program's CLI.
+#[derive(Debug, clap::Args, Clone, Copy)]
+pub struct LogFormatCli {
+    /// Format for log messages printed to stderr. "json" writes one JSON object
+    /// per line, with the level, timestamp, target, and message.
+    #[arg(long, value_enum, default_value_t = LogFormat::Text)]
+    pub log_format: LogFormat,
+}
+
 pub fn init_logging(level: log::LevelFilter) {
+    init_logging_with_format(level, LogFormat::Text)
+}
+
+pub fn init_logging_with_format(level: log::LevelFilter, format: LogFormat) {
     // Eventually it might make sense to log to a file as well, so that
     // ALL of the issues that happened during post processing are captured.
     let stderr = ConsoleAppender::builder()
-        .encoder(Box::new(PatternEncoder::new(
-            "{h({d(%Y-%m-%d %H:%M:%S)} [{l}] from line {L} in {M})} - {m}{n}",
-        )))
+        .encoder(make_encoder(format))
         .target(Target::Stderr)
         .build();
 
@@ -22,3 +44,47 @@ pub fn init_logging(level: log::LevelFilter) {
 
     log4rs::init_config(config).expect("Failed to initialize logger");
 }
+
+fn make_encoder(format: LogFormat) -> Box<dyn Encode> {
+    match format {
+        LogFormat::Text => Box::new(PatternEncoder::new(
+            "{h({d(%Y-%m-%d %H:%M:%S)} [{l}] from line {L} in {M})} - {m}{n}",
+        )),
+        LogFormat::Json => Box::new(JsonEncoder::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use log4rs::encode::writer::simple::SimpleWriter;
+
+    use super::*;
+
+    #[test]
+    fn test_json_log_line() {
+        let encoder = make_encoder(LogFormat::Json);
+        let mut writer = SimpleWriter(Vec::new());
+        encoder
+            .encode(
+                &mut writer,
+                &log::Record::builder()
+                    .level(log::Level::Warn)
+                    .target("ggg_rs::logging")
+                    .args(format_args!("window {} not found", "co2_6220"))
+                    .build(),
+            )
+            .unwrap();
+
+        let line = String::from_utf8(writer.0).unwrap();
+        assert_eq!(line.lines().count(), 1, "expected one line of JSON: {line}");
+        let record: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(record["level"], "WARN");
+        assert_eq!(record["target"], "ggg_rs::logging");
+        assert_eq!(record["message"], "window co2_6220 not found");
+        let time = record["time"].as_str().unwrap();
+        assert!(
+            chrono::DateTime::parse_from_rfc3339(time).is_ok(),
+            "unexpected timestamp: {time}"
+        );
+    }
+}
@@ -4,13 +4,16 @@ use error_stack::ResultExt;
 use indexmap::IndexMap;
 use interp::interp_slice;
 use itertools::Itertools;
-use ndarray::{Array1, Array2, ArrayD, ArrayView1, ArrayView2, ArrayViewD};
+use ndarray::{Array1, Array2, ArrayD, ArrayView1, ArrayView2, ArrayViewD, Axis, Dimension};
 use netcdf::{
     types::{FloatType, IntType},
-    Extents,
+    Extent, Extents,
 };
 use num_traits::Zero;
-use serde::{de::Error, Deserialize};
+use serde::{
+    de::{DeserializeOwned, Error},
+    Deserialize,
+};
 
 use crate::{
     units::{unit_conv_factor, Quantity},
@@ -32,29 +35,37 @@ pub enum NcArray {
     F32(ArrayD<f32>),
     F64(ArrayD<f64>),
     Char(ArrayD<u8>),
+    String(ArrayD<String>),
 }
 
 impl NcArray {
     /// Retrieve data from a netCDF variable and construct the appropriate variant.
     ///
-    /// # Panics
-    /// Compound, opaque, enum, and variable length types are not supported, and
-    /// may never be, due to their rarity.
+    /// # Errors
+    /// In addition to errors reading the data, compound, opaque, enum, and variable
+    /// length types return an error, as they are not supported (and may never be,
+    /// due to their rarity).
     pub fn get_from(var: &netcdf::Variable) -> netcdf::Result<Self> {
+        let unsupported = |kind: &str| {
+            netcdf::Error::Str(format!(
+                "variable '{}' has a {kind} type, which cannot be read as a generic array",
+                var.name()
+            ))
+        };
+
         match var.vartype() {
-            netcdf::types::NcVariableType::Compound(_) => {
-                unimplemented!("reading netCDF Compound types as a generic array")
+            netcdf::types::NcVariableType::Compound(_) => Err(unsupported("compound")),
+            netcdf::types::NcVariableType::Opaque(_) => Err(unsupported("opaque")),
+            netcdf::types::NcVariableType::Enum(_) => Err(unsupported("enum")),
+            netcdf::types::NcVariableType::Vlen(_) => Err(unsupported("variable length")),
+            netcdf::types::NcVariableType::String => {
+                let shape = var.dimensions().iter().map(|d| d.len()).collect_vec();
+                let mut values = ArrayD::from_elem(shape, String::new());
+                for (idx, value) in values.indexed_iter_mut() {
+                    *value = var.get_string(idx.slice())?;
+                }
+                Ok(Self::String(values))
             }
-            netcdf::types::NcVariableType::Opaque(_) => {
-                unimplemented!("reading netCDF Opaque types as a generic array")
-            }
-            netcdf::types::NcVariableType::Enum(_) => {
-                unimplemented!("reading netCDF Enum types as a generic array")
-            }
-            netcdf::types::NcVariableType::Vlen(_) => {
-                unimplemented!("reading netCDF variable length types as a generic array")
-            }
-            netcdf::types::NcVariableType::String => todo!(),
             netcdf::types::NcVariableType::Int(IntType::I8) => {
                 let values = var.get::<i8, _>(Extents::All)?;
                 Ok(Self::I8(values))
@@ -96,12 +107,93 @@ impl NcArray {
                 Ok(Self::F64(values))
             }
             netcdf::types::NcVariableType::Char => {
-                let values = var.get::<u8, _>(Extents::All)?;
-                Ok(Self::Char(values))
+                // netCDF will not convert NC_CHAR to u8, so this must be read as `NcChar`
+                let values = var.get::<NcChar, _>(Extents::All)?;
+                Ok(Self::Char(values.mapv(u8::from)))
             }
         }
     }
 
+    /// Convert the data to `f64`, e.g. for numeric comparisons that should not
+    /// depend on how the variable was stored. Returns `None` for character and string data.
+    /// Note that 64-bit integers larger than 2^53 lose precision in the conversion.
+    pub fn to_f64(&self) -> Option<ArrayD<f64>> {
+        let values = match self {
+            NcArray::I8(arr) => arr.mapv(f64::from),
+            NcArray::I16(arr) => arr.mapv(f64::from),
+            NcArray::I32(arr) => arr.mapv(f64::from),
+            NcArray::I64(arr) => arr.mapv(|v| v as f64),
+            NcArray::U8(arr) => arr.mapv(f64::from),
+            NcArray::U16(arr) => arr.mapv(f64::from),
+            NcArray::U32(arr) => arr.mapv(f64::from),
+            NcArray::U64(arr) => arr.mapv(|v| v as f64),
+            NcArray::F32(arr) => arr.mapv(f64::from),
+            NcArray::F64(arr) => arr.clone(),
+            NcArray::Char(_) | NcArray::String(_) => return None,
+        };
+        Some(values)
+    }
+
+    /// Select `indices` (in that order) along `axis`, like [`ndarray::ArrayBase::select`].
+    ///
+    /// # Panics
+    /// If `axis` or any of the indices are out of bounds.
+    pub fn select(&self, axis: usize, indices: &[usize]) -> Self {
+        let axis = Axis(axis);
+        match self {
+            NcArray::I8(arr) => NcArray::I8(arr.select(axis, indices)),
+            NcArray::I16(arr) => NcArray::I16(arr.select(axis, indices)),
+            NcArray::I32(arr) => NcArray::I32(arr.select(axis, indices)),
+            NcArray::I64(arr) => NcArray::I64(arr.select(axis, indices)),
+            NcArray::U8(arr) => NcArray::U8(arr.select(axis, indices)),
+            NcArray::U16(arr) => NcArray::U16(arr.select(axis, indices)),
+            NcArray::U32(arr) => NcArray::U32(arr.select(axis, indices)),
+            NcArray::U64(arr) => NcArray::U64(arr.select(axis, indices)),
+            NcArray::F32(arr) => NcArray::F32(arr.select(axis, indices)),
+            NcArray::F64(arr) => NcArray::F64(arr.select(axis, indices)),
+            NcArray::Char(arr) => NcArray::Char(arr.select(axis, indices)),
+            NcArray::String(arr) => NcArray::String(arr.select(axis, indices)),
+        }
+    }
+
+    /// The shape of the contained array.
+    pub fn shape(&self) -> &[usize] {
+        match self {
+            NcArray::I8(arr) => arr.shape(),
+            NcArray::I16(arr) => arr.shape(),
+            NcArray::I32(arr) => arr.shape(),
+            NcArray::I64(arr) => arr.shape(),
+            NcArray::U8(arr) => arr.shape(),
+            NcArray::U16(arr) => arr.shape(),
+            NcArray::U32(arr) => arr.shape(),
+            NcArray::U64(arr) => arr.shape(),
+            NcArray::F32(arr) => arr.shape(),
+            NcArray::F64(arr) => arr.shape(),
+            NcArray::Char(arr) => arr.shape(),
+            NcArray::String(arr) => arr.shape(),
+        }
+    }
+
+    /// Write this data into `extents` of an existing variable. Unlike [`NcArray::put_to`],
+    /// this allows attributes (such as `_FillValue`) to be set on the variable before
+    /// the data is written.
+    pub fn put_into(&self, var: &mut netcdf::VariableMut, extents: Extents) -> netcdf::Result<()> {
+        match self {
+            NcArray::I8(arr) => var.put(arr.view(), extents),
+            NcArray::I16(arr) => var.put(arr.view(), extents),
+            NcArray::I32(arr) => var.put(arr.view(), extents),
+            NcArray::I64(arr) => var.put(arr.view(), extents),
+            NcArray::U8(arr) => var.put(arr.view(), extents),
+            NcArray::U16(arr) => var.put(arr.view(), extents),
+            NcArray::U32(arr) => var.put(arr.view(), extents),
+            NcArray::U64(arr) => var.put(arr.view(), extents),
+            NcArray::F32(arr) => var.put(arr.view(), extents),
+            NcArray::F64(arr) => var.put(arr.view(), extents),
+            NcArray::Char(arr) => var.put(arr.mapv(NcChar).view(), extents),
+            NcArray::String(arr) => put_strings(var, arr, &extents),
+        }
+    }
+
     /// Create a variable in a netCDF group and write this data to it.
     /// Since this writes data, if you need to set options on the variable
     /// that must be done pre-write (e.g., compression), you must match
@@ -112,63 +204,105 @@ impl NcArray {
         name: &str,
         dims: &[&str],
     ) -> netcdf::Result<netcdf::VariableMut<'g>> {
-        match self {
-            NcArray::I8(arr) => {
-                let mut var = grp.add_variable::<i8>(name, dims)?;
-                var.put(arr.view(), Extents::All)?;
-                Ok(var)
-            }
-            NcArray::I16(arr) => {
-                let mut var = grp.add_variable::<i16>(name, dims)?;
-                var.put(arr.view(), Extents::All)?;
-                Ok(var)
-            }
-            NcArray::I32(arr) => {
-                let mut var = grp.add_variable::<i32>(name, dims)?;
-                var.put(arr.view(), Extents::All)?;
-                Ok(var)
-            }
-            NcArray::I64(arr) => {
-                let mut var = grp.add_variable::<i64>(name, dims)?;
-                var.put(arr.view(), Extents::All)?;
-                Ok(var)
-            }
-            NcArray::U8(arr) => {
-                let mut var = grp.add_variable::<u8>(name, dims)?;
-                var.put(arr.view(), Extents::All)?;
-                Ok(var)
-            }
-            NcArray::U16(arr) => {
-                let mut var = grp.add_variable::<u16>(name, dims)?;
-                var.put(arr.view(), Extents::All)?;
-                Ok(var)
-            }
-            NcArray::U32(arr) => {
-                let mut var = grp.add_variable::<u32>(name, dims)?;
-                var.put(arr.view(), Extents::All)?;
-                Ok(var)
-            }
-            NcArray::U64(arr) => {
-                let mut var = grp.add_variable::<u64>(name, dims)?;
-                var.put(arr.view(), Extents::All)?;
-                Ok(var)
-            }
-            NcArray::F32(arr) => {
-                let mut var = grp.add_variable::<f32>(name, dims)?;
-                var.put(arr.view(), Extents::All)?;
-                Ok(var)
-            }
-            NcArray::F64(arr) => {
-                let mut var = grp.add_variable::<f64>(name, dims)?;
-                var.put(arr.view(), Extents::All)?;
-                Ok(var)
-            }
-            NcArray::Char(arr) => {
-                let mut var = grp.add_variable::<u8>(name, dims)?;
-                var.put(arr.view(), Extents::All)?;
-                Ok(var)
+        let mut var = match self {
+            NcArray::I8(_) => grp.add_variable::<i8>(name, dims)?,
+            NcArray::I16(_) => grp.add_variable::<i16>(name, dims)?,
+            NcArray::I32(_) => grp.add_variable::<i32>(name, dims)?,
+            NcArray::I64(_) => grp.add_variable::<i64>(name, dims)?,
+            NcArray::U8(_) => grp.add_variable::<u8>(name, dims)?,
+            NcArray::U16(_) => grp.add_variable::<u16>(name, dims)?,
+            NcArray::U32(_) => grp.add_variable::<u32>(name, dims)?,
+            NcArray::U64(_) => grp.add_variable::<u64>(name, dims)?,
+            NcArray::F32(_) => grp.add_variable::<f32>(name, dims)?,
+            NcArray::F64(_) => grp.add_variable::<f64>(name, dims)?,
+            NcArray::Char(_) => grp.add_variable::<NcChar>(name, dims)?,
+            NcArray::String(_) => grp.add_string_variable(name, dims)?,
+        };
+        self.put_into(&mut var, Extents::All)?;
+        Ok(var)
+    }
+}
+
+/// Write strings into `extents` of a string variable one element at a time, since
+/// netCDF has no way to write an array of strings at once.
+fn put_strings(
+    var: &mut netcdf::VariableMut,
+    arr: &ArrayD<String>,
+    extents: &Extents,
+) -> netcdf::Result<()> {
+    for (idx, value) in arr.indexed_iter() {
+        let index = match extents {
+            Extents::All => idx.slice().to_vec(),
+            Extents::Extent(extents) => {
+                let mut array_idx = idx.slice().iter();
+                extents
+                    .iter()
+                    .map(|ext| match *ext {
+                        Extent::Index(i) => i,
+                        Extent::Slice { start, stride }
+                        | Extent::SliceEnd { start, stride, .. }
+                        | Extent::SliceCount { start, stride, .. } => {
+                            let i = array_idx.next().copied().unwrap_or(0);
+                            (start as isize + i as isize * stride) as usize
+                        }
+                    })
+                    .collect()
             }
-        }
+        };
+        var.put_string(value, index.as_slice())?;
+    }
+    Ok(())
+}
+
+/// Wrapper around unsigned bytes to represent a netCDF character type
+///
+/// From <https://docs.rs/netcdf/0.11.0/netcdf/trait.NcTypeDescriptor.html#char-type>,
+/// in netCDF v0.11, i8 and u8 are not considered equivalent to an NC_CHAR type.
+/// Therefore, to read an NC_CHAR-type variable, we create this structure to
+/// hold a byte as a character.
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NcChar(pub u8);
+
+unsafe impl netcdf::NcTypeDescriptor for NcChar {
+    fn type_descriptor() -> netcdf::types::NcVariableType {
+        netcdf::types::NcVariableType::Char
+    }
+}
+
+impl From<NcChar> for u8 {
+    fn from(value: NcChar) -> Self {
+        value.0
+    }
+}
+
+impl From<&NcChar> for u8 {
+    fn from(value: &NcChar) -> Self {
+        value.0
+    }
+}
+
+impl From<&NcChar> for char {
+    fn from(value: &NcChar) -> Self {
+        char::from(value.0)
+    }
+}
+
+impl Zero for NcChar {
+    fn zero() -> Self {
+        Self(0)
+    }
+
+    fn is_zero(&self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl std::ops::Add for NcChar {
+    type Output = NcChar;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Self(self.0 + rhs.0)
     }
 }
 
@@ -545,10 +679,171 @@ where
 /// # Errors
 /// Returns a [`GggError::CouldNotRead`] if either the file
 /// had the wrong structure or it does not have a `.toml` or
-/// `.json` extension.
+/// `.json` extension. If any site's entry does not follow the
+/// schema, the error lists all the problems found by
+/// [`validate_site_metadata`].
 pub fn read_nc_site_metadata(
     site_info_file: &Path,
 ) -> Result<IndexMap<String, NcSiteMetadata>, GggError> {
+    let sites: IndexMap<String, serde_json::Value> = read_site_metadata_file(site_info_file)?;
+    let problems = check_site_metadata(&sites);
+    if !problems.is_empty() {
+        return Err(GggError::could_not_read(
+            site_info_file.to_path_buf(),
+            format!(
+                "{} problem(s) in the site metadata: {}",
+                problems.len(),
+                problems.iter().join("; ")
+            ),
+        ));
+    }
+
+    sites
+        .into_iter()
+        .map(|(site_id, value)| {
+            let meta = serde_json::from_value(value)
+                .map_err(|e| GggError::could_not_read(site_info_file.to_path_buf(), e))?;
+            Ok((site_id, meta))
+        })
+        .collect()
+}
+
+/// A problem with one site's entry in a site metadata file, found by [`validate_site_metadata`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SiteMetadataError {
+    /// The site's entry is not a table.
+    NotATable { site_id: String },
+    /// A required key is missing.
+    MissingKey { site_id: String, key: &'static str },
+    /// A key has the wrong type of value, e.g. a number where a string is required.
+    WrongType {
+        site_id: String,
+        key: &'static str,
+        expected: &'static str,
+    },
+    /// A key has the right type, but an invalid value.
+    InvalidValue {
+        site_id: String,
+        key: &'static str,
+        reason: String,
+    },
+}
+
+impl std::fmt::Display for SiteMetadataError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SiteMetadataError::NotATable { site_id } => {
+                write!(f, "site '{site_id}': entry must be a table")
+            }
+            SiteMetadataError::MissingKey { site_id, key } => {
+                write!(f, "site '{site_id}': missing required key '{key}'")
+            }
+            SiteMetadataError::WrongType {
+                site_id,
+                key,
+                expected,
+            } => write!(f, "site '{site_id}': '{key}' must be {expected}"),
+            SiteMetadataError::InvalidValue {
+                site_id,
+                key,
+                reason,
+            } => write!(f, "site '{site_id}': invalid '{key}', {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for SiteMetadataError {}
+
+/// Check every site in a TOML or JSON site metadata file against the schema
+/// defined by [`NcSiteMetadata`].
+///
+/// This checks that each site has all the required keys, that each key has the right
+/// type of value, that `release_lag` is a non-negative integer (or a string containing one),
+/// and that `data_doi`, if given, starts with "10.". Keys not in [`NcSiteMetadata`] are ignored.
+/// All problems found are returned, in the order of the sites in the file; an empty
+/// vector means the file is valid.
+///
+/// # Errors
+/// Returns a [`GggError::CouldNotRead`] if the file cannot be read or parsed at all,
+/// including if it does not have a `.toml` or `.json` extension.
+pub fn validate_site_metadata(site_info_file: &Path) -> Result<Vec<SiteMetadataError>, GggError> {
+    let sites: IndexMap<String, serde_json::Value> = read_site_metadata_file(site_info_file)?;
+    Ok(check_site_metadata(&sites))
+}
+
+fn check_site_metadata(sites: &IndexMap<String, serde_json::Value>) -> Vec<SiteMetadataError> {
+    const REQUIRED_STR_KEYS: [&str; 4] = ["long_name", "location", "contact", "data_revision"];
+    const OPTIONAL_STR_KEYS: [&str; 3] = ["data_doi", "data_reference", "site_reference"];
+
+    let mut problems = vec![];
+    for (site_id, entry) in sites.iter() {
+        let site_id = site_id.to_string();
+        let Some(entry) = entry.as_object() else {
+            problems.push(SiteMetadataError::NotATable { site_id });
+            continue;
+        };
+
+        for key in REQUIRED_STR_KEYS.into_iter().chain(OPTIONAL_STR_KEYS) {
+            match entry.get(key) {
+                Some(v) if !v.is_string() => problems.push(SiteMetadataError::WrongType {
+                    site_id: site_id.clone(),
+                    key,
+                    expected: "a string",
+                }),
+                Some(_) => (),
+                None if REQUIRED_STR_KEYS.contains(&key) => {
+                    problems.push(SiteMetadataError::MissingKey {
+                        site_id: site_id.clone(),
+                        key,
+                    })
+                }
+                None => (),
+            }
+        }
+
+        // Same rules as `release_lag_de_helper`, but checked up front so we can say which site is wrong.
+        let key = "release_lag";
+        match entry.get(key) {
+            None => problems.push(SiteMetadataError::MissingKey {
+                site_id: site_id.clone(),
+                key,
+            }),
+            Some(serde_json::Value::Number(n)) if n.as_u64().is_none() => {
+                problems.push(SiteMetadataError::InvalidValue {
+                    site_id: site_id.clone(),
+                    key,
+                    reason: format!("{n} is not an unsigned integer"),
+                })
+            }
+            Some(serde_json::Value::String(s)) if s.trim().parse::<u32>().is_err() => problems
+                .push(SiteMetadataError::InvalidValue {
+                    site_id: site_id.clone(),
+                    key,
+                    reason: format!("'{s}' cannot be parsed as an unsigned integer"),
+                }),
+            Some(serde_json::Value::Number(_) | serde_json::Value::String(_)) => (),
+            Some(_) => problems.push(SiteMetadataError::WrongType {
+                site_id: site_id.clone(),
+                key,
+                expected: "an integer or a string",
+            }),
+        }
+
+        if let Some(doi) = entry.get("data_doi").and_then(|v| v.as_str()) {
+            if !doi.starts_with("10.") {
+                problems.push(SiteMetadataError::InvalidValue {
+                    site_id: site_id.clone(),
+                    key: "data_doi",
+                    reason: format!("'{doi}' does not start with \"10.\""),
+                });
+            }
+        }
+    }
+    problems
+}
+
+/// Deserialize a TOML or JSON site metadata file, choosing the format based on the extension.
+fn read_site_metadata_file<T: DeserializeOwned>(site_info_file: &Path) -> Result<T, GggError> {
     match site_info_file.extension() {
         Some(ext) => {
             if ext == "json" {
@@ -576,9 +871,9 @@ pub fn read_nc_site_metadata(
     ))
 }
 
-fn read_nc_site_metadata_toml(
+fn read_nc_site_metadata_toml<T: DeserializeOwned>(
     site_info_file: &Path,
-) -> Result<IndexMap<String, NcSiteMetadata>, toml::de::Error> {
+) -> Result<T, toml::de::Error> {
     let mut f = std::fs::File::open(site_info_file).map_err(|e| {
         toml::de::Error::custom(format!(
             "Error opening metadata file, {}: {e}",
@@ -595,9 +890,9 @@ fn read_nc_site_metadata_toml(
     toml::from_str(&buf)
 }
 
-fn read_nc_site_metadata_json(
+fn read_nc_site_metadata_json<T: DeserializeOwned>(
     site_info_file: &Path,
-) -> Result<IndexMap<String, NcSiteMetadata>, serde_json::Error> {
+) -> Result<T, serde_json::Error> {
     let f = std::fs::File::open(site_info_file).map_err(|e| {
         serde_json::Error::custom(format!(
             "error opening metadata file, {}: {e}",
@@ -696,6 +991,417 @@ impl GetNcAttr for &netcdf::File {
     }
 }
 
+// ---------------------------- //
+// Variable copying helpers     //
+// ---------------------------- //
+
+/// Make sure that `dst_ds` has each of `src_var`'s dimensions, returning their names.
+///
+/// `lens` gives the length each dimension should have in `dst_ds`, in the same order as the
+/// dimensions of `src_var`. Dimensions are looked up starting from `group` (the root group if
+/// `None`), so one in a parent group will be found, and missing ones are created in `group`.
+///
+/// # Errors
+/// Returns [`GggNcError::DimLenMismatch`] if a dimension already exists with a different length.
+pub fn add_needed_dims(
+    dst_ds: &mut netcdf::FileMut,
+    src_var: &netcdf::Variable,
+    group: Option<&str>,
+    lens: &[usize],
+) -> error_stack::Result<Vec<String>, GggNcError> {
+    let varname = src_var.name();
+    let mut dimnames = vec![];
+    for (dim, &len) in src_var.dimensions().iter().zip(lens) {
+        let dimname = dim.name();
+        let path = match group {
+            Some(group) => format!("{group}/{dimname}"),
+            None => dimname.clone(),
+        };
+        add_dim_if_needed(dst_ds, &path, len, dim.is_unlimited(), &varname)?;
+        dimnames.push(dimname);
+    }
+    Ok(dimnames)
+}
+
+/// Create dimension `path` (which may include a group) with length `len` if it does not exist,
+/// or check that its length is `len` if it does. Unlimited dimensions in `dst_ds` match any
+/// length. `varname` is the variable that needs the dimension, and is only used in errors.
+pub fn add_dim_if_needed(
+    dst_ds: &mut netcdf::FileMut,
+    path: &str,
+    len: usize,
+    unlimited: bool,
+    varname: &str,
+) -> error_stack::Result<(), GggNcError> {
+    match dst_ds.dimension(path) {
+        Some(dst_dim) if !dst_dim.is_unlimited() && dst_dim.len() != len => {
+            Err(GggNcError::DimLenMismatch {
+                dimname: path.to_string(),
+                varname: varname.to_string(),
+                len_in_file: dst_dim.len(),
+                len_in_var: len,
+            }
+            .into())
+        }
+        Some(_) => Ok(()),
+        None => {
+            let res = if unlimited {
+                dst_ds.add_unlimited_dimension(path)
+            } else {
+                dst_ds.add_dimension(path, len)
+            };
+            res.change_context_lazy(|| {
+                GggNcError::context(format!("creating dimension '{path}'"))
+            })?;
+            Ok(())
+        }
+    }
+}
+
+/// Copy all attributes from `src_var` to `dst_var`. This must be called before writing
+/// data to `dst_var` if `src_var` has a `_FillValue` attribute.
+pub fn copy_attributes(
+    src_var: &netcdf::Variable,
+    dst_var: &mut netcdf::VariableMut,
+) -> error_stack::Result<(), GggNcError> {
+    copy_attributes_except(src_var, dst_var, |_| false)
+}
+
+/// Like [`copy_attributes`], but skip any attribute for which `skip` returns `true`.
+pub fn copy_attributes_except<F: Fn(&str) -> bool>(
+    src_var: &netcdf::Variable,
+    dst_var: &mut netcdf::VariableMut,
+    skip: F,
+) -> error_stack::Result<(), GggNcError> {
+    let varname = src_var.name();
+    for attr in src_var.attributes() {
+        if skip(attr.name()) {
+            continue;
+        }
+        let value = attr.value().change_context_lazy(|| {
+            GggNcError::context(format!(
+                "reading attribute '{}' on variable '{varname}'",
+                attr.name()
+            ))
+        })?;
+        dst_var
+            .put_attribute(attr.name(), value)
+            .change_context_lazy(|| {
+                GggNcError::context(format!(
+                    "writing attribute '{}' on variable '{varname}'",
+                    attr.name()
+                ))
+            })?;
+    }
+    Ok(())
+}
+
+// ----------------------- //
+// CF compliance helpers   //
+// ----------------------- //
+
+/// How important a [`CfFinding`] is, following the categories used by the IOOS
+/// compliance checker: high priority findings violate a requirement of the CF conventions,
+/// medium priority ones go against a recommendation, and low priority ones are suggestions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CfPriority {
+    High,
+    Medium,
+    Low,
+}
+
+impl std::fmt::Display for CfPriority {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CfPriority::High => write!(f, "High priority"),
+            CfPriority::Medium => write!(f, "Medium priority"),
+            CfPriority::Low => write!(f, "Low priority"),
+        }
+    }
+}
+
+/// One problem found by [`check_cf_compliance`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CfFinding {
+    /// The file does not have a global `Conventions` attribute naming a CF version.
+    MissingConventions,
+    /// A variable has neither a `standard_name` nor a `long_name` attribute.
+    MissingName { variable: String },
+    /// A variable's `units` attribute is not a unit UDUNITS can parse.
+    InvalidUnits { variable: String, units: String },
+    /// A vertical coordinate whose units are not pressure is missing the `positive` attribute.
+    MissingPositive { variable: String },
+}
+
+impl CfFinding {
+    /// The priority of this finding, as the IOOS compliance checker would report it.
+    pub fn priority(&self) -> CfPriority {
+        match self {
+            CfFinding::MissingConventions => CfPriority::High,
+            CfFinding::MissingName { .. } => CfPriority::Medium,
+            CfFinding::InvalidUnits { .. } => CfPriority::High,
+            CfFinding::MissingPositive { .. } => CfPriority::High,
+        }
+    }
+}
+
+impl std::fmt::Display for CfFinding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: ", self.priority())?;
+        match self {
+            CfFinding::MissingConventions => {
+                write!(
+                    f,
+                    "global attribute 'Conventions' is missing or does not name a CF version"
+                )
+            }
+            CfFinding::MissingName { variable } => write!(
+                f,
+                "variable '{variable}' has neither a 'standard_name' nor a 'long_name' attribute"
+            ),
+            CfFinding::InvalidUnits { variable, units } => write!(
+                f,
+                "variable '{variable}' has units '{units}', which are not recognized by UDUNITS"
+            ),
+            CfFinding::MissingPositive { variable } => write!(
+                f,
+                "vertical coordinate '{variable}' is missing the 'positive' attribute"
+            ),
+        }
+    }
+}
+
+/// The result of [`check_cf_compliance`] on one file.
+#[derive(Debug, Clone, Default)]
+pub struct CfReport {
+    /// All problems found, with the global attribute findings first, then the findings
+    /// for each variable in the order the variables are in the file.
+    pub findings: Vec<CfFinding>,
+}
+
+impl CfReport {
+    /// Whether any problems were found at all.
+    pub fn is_empty(&self) -> bool {
+        self.findings.is_empty()
+    }
+
+    /// Iterate over the findings with a given priority.
+    pub fn with_priority(&self, priority: CfPriority) -> impl Iterator<Item = &CfFinding> {
+        self.findings
+            .iter()
+            .filter(move |finding| finding.priority() == priority)
+    }
+}
+
+/// Check a netCDF file against a subset of the CF conventions.
+///
+/// This checks for:
+///
+/// - a global `Conventions` attribute that names a CF version (e.g. "CF-1.8"),
+/// - a `standard_name` or `long_name` on every variable,
+/// - `units` attributes that UDUNITS can parse (see [`crate::units::is_udunits_unit`]), and
+/// - a `positive` attribute on vertical coordinates, unless their units are a pressure.
+///
+/// A variable is considered a vertical coordinate if it has `axis = "Z"`, a vertical
+/// `standard_name` (e.g. "altitude"), or is a coordinate variable (1D, named the same as
+/// its dimension) whose name includes "altitude", "height", or "depth". Only variables
+/// in the root group are checked.
+///
+/// # Errors
+/// Returns an error if an attribute cannot be read.
+pub fn check_cf_compliance(ds: &netcdf::File) -> error_stack::Result<CfReport, GggNcError> {
+    let mut report = CfReport::default();
+    let conventions = match ds.attribute("Conventions") {
+        Some(attr) => Some(attr.value().map_err(GggNcError::from)?),
+        None => None,
+    };
+    let has_cf_conventions =
+        matches!(conventions, Some(netcdf::AttributeValue::Str(c)) if c.contains("CF-"));
+    if !has_cf_conventions {
+        report.findings.push(CfFinding::MissingConventions);
+    }
+
+    for var in ds.variables() {
+        let variable = var.name();
+        let standard_name = get_optional_str_attr(&var, "standard_name")?;
+        let long_name = get_optional_str_attr(&var, "long_name")?;
+        let units = get_optional_str_attr(&var, "units")?;
+
+        if standard_name.is_none() && long_name.is_none() {
+            report.findings.push(CfFinding::MissingName {
+                variable: variable.clone(),
+            });
+        }
+
+        if let Some(units) = &units {
+            if !crate::units::is_udunits_unit(units) {
+                report.findings.push(CfFinding::InvalidUnits {
+                    variable: variable.clone(),
+                    units: units.clone(),
+                });
+            }
+        }
+
+        if is_vertical_coordinate(&var, standard_name.as_deref())?
+            && !units.as_deref().is_some_and(is_pressure_unit)
+            && var.attribute("positive").is_none()
+        {
+            report
+                .findings
+                .push(CfFinding::MissingPositive { variable });
+        }
+    }
+
+    Ok(report)
+}
+
+/// Get a string attribute from `var`, returning `None` if it is missing or not a string.
+fn get_optional_str_attr(
+    var: &netcdf::Variable,
+    attr: &str,
+) -> error_stack::Result<Option<String>, GggNcError> {
+    match var
+        .attribute_value(attr)
+        .transpose()
+        .map_err(GggNcError::from)?
+    {
+        Some(netcdf::AttributeValue::Str(s)) => Ok(Some(s)),
+        _ => Ok(None),
+    }
+}
+
+fn is_vertical_coordinate(
+    var: &netcdf::Variable,
+    standard_name: Option<&str>,
+) -> error_stack::Result<bool, GggNcError> {
+    const VERTICAL_STANDARD_NAMES: [&str; 5] = [
+        "altitude",
+        "height",
+        "height_above_reference_ellipsoid",
+        "depth",
+        "air_pressure",
+    ];
+
+    if get_optional_str_attr(var, "axis")?.is_some_and(|axis| axis == "Z") {
+        return Ok(true);
+    }
+    if standard_name.is_some_and(|name| VERTICAL_STANDARD_NAMES.contains(&name)) {
+        return Ok(true);
+    }
+
+    let name = var.name();
+    let is_coordinate = matches!(var.dimensions(), [dim] if dim.name() == name);
+    Ok(is_coordinate
+        && ["altitude", "height", "depth"]
+            .iter()
+            .any(|v| name.contains(v)))
+}
+
+fn is_pressure_unit(units: &str) -> bool {
+    crate::units::Quantity::of_unit(units) == Some(crate::units::Quantity::Pressure)
+}
+
+// ----------------- //
+// Migration helpers //
+// ----------------- //
+
+/// The global attribute that records which version of the file format a netCDF file uses.
+pub const FILE_FORMAT_VERSION_ATTR: &str = "file_format_version";
+
+/// One step in upgrading an existing netCDF file to a newer file format version.
+///
+/// Migrations are run by [`run_migrations`], which checks each one against the file's
+/// current `file_format_version` and updates that attribute after each migration applied.
+pub trait Migration {
+    /// A short description of what this migration does, used in error messages.
+    fn description(&self) -> String;
+
+    /// Whether this migration should be applied to a file with the given format version.
+    /// `version` will be `None` if the file has no `file_format_version` attribute.
+    fn applies_to(&self, version: Option<&str>) -> bool;
+
+    /// The format version a file will have once this migration is applied.
+    fn new_version(&self) -> &str;
+
+    /// Modify the file. This does not need to update the `file_format_version` attribute.
+    fn apply(&self, ds: &mut netcdf::FileMut) -> error_stack::Result<(), GggNcError>;
+}
+
+/// Apply each migration in `migrations` that applies to `ds`, in order.
+///
+/// The `file_format_version` global attribute is reread before each migration and set
+/// to the migration's [`Migration::new_version`] after it is applied, so a list of
+/// migrations can take a file through several versions in one call, and running the same
+/// list a second time does nothing. Returns the descriptions of the migrations applied.
+///
+/// # Errors
+/// Returns an error if `file_format_version` is not a string or if any migration fails.
+pub fn run_migrations(
+    ds: &mut netcdf::FileMut,
+    migrations: &[&dyn Migration],
+) -> error_stack::Result<Vec<String>, GggNcError> {
+    let mut applied = vec![];
+    for migration in migrations {
+        let version = if ds.attribute(FILE_FORMAT_VERSION_ATTR).is_some() {
+            Some(get_string_attr(&**ds, FILE_FORMAT_VERSION_ATTR)?)
+        } else {
+            None
+        };
+
+        if !migration.applies_to(version.as_deref()) {
+            continue;
+        }
+
+        migration.apply(ds).change_context_lazy(|| {
+            GggNcError::context(format!("migration '{}' failed", migration.description()))
+        })?;
+        ds.add_attribute(FILE_FORMAT_VERSION_ATTR, migration.new_version())
+            .change_context_lazy(|| {
+                GggNcError::context(format!(
+                    "updating '{FILE_FORMAT_VERSION_ATTR}' after migration '{}'",
+                    migration.description()
+                ))
+            })?;
+        applied.push(migration.description());
+    }
+    Ok(applied)
+}
+
+/// A [`Migration`] that adds a global attribute to files at one format version,
+/// if they do not already have it.
+pub struct AddGlobalAttribute {
+    /// The version this migration upgrades from; `None` means files with no version.
+    pub from_version: Option<String>,
+    /// The version this migration upgrades to.
+    pub to_version: String,
+    /// The name of the attribute to add.
+    pub attribute: String,
+    /// The value to give the attribute.
+    pub value: netcdf::AttributeValue,
+}
+
+impl Migration for AddGlobalAttribute {
+    fn description(&self) -> String {
+        format!("add global attribute '{}'", self.attribute)
+    }
+
+    fn applies_to(&self, version: Option<&str>) -> bool {
+        self.from_version.as_deref() == version
+    }
+
+    fn new_version(&self) -> &str {
+        &self.to_version
+    }
+
+    fn apply(&self, ds: &mut netcdf::FileMut) -> error_stack::Result<(), GggNcError> {
+        if ds.attribute(&self.attribute).is_none() {
+            ds.add_attribute(&self.attribute, self.value.clone())
+                .map_err(GggNcError::from)?;
+        }
+        Ok(())
+    }
+}
+
 // ------------- //
 // Value helpers //
 // ------------- //
@@ -705,3 +1411,195 @@ pub fn convert_nc_timestamp(ts: f64) -> chrono::DateTime<chrono::Utc> {
     let nanos = (ts * 1e9).trunc() as i64;
     chrono::DateTime::from_timestamp_nanos(nanos)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_site_metadata(name: &str, contents: &str) -> std::path::PathBuf {
+        let output_dir = crate::test_utils::output_dir("nc-utils-site-metadata");
+        let path = output_dir.join(name);
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_validate_bundled_site_metadata() {
+        let site_info_file = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("src")
+            .join("etc")
+            .join("site_info.toml");
+        let problems = validate_site_metadata(&site_info_file).unwrap();
+        assert!(problems.is_empty(), "{problems:?}");
+        let sites = read_nc_site_metadata(&site_info_file).unwrap();
+        assert_eq!(sites["pa"].release_lag, 120);
+    }
+
+    #[test]
+    fn test_validate_malformed_site_metadata() {
+        let path = write_site_metadata(
+            "site-metadata-test.toml",
+            r#"
+[pa]
+long_name = "parkfalls01"
+release_lag = 120
+location = "Park Falls, Wisconsin, USA"
+contact = "Paul Wennberg <wennberg@gps.caltech.edu>"
+data_revision = "R1"
+
+[xx]
+long_name = "bad01"
+release_lag = "soon"
+location = 42
+data_revision = "R0"
+data_doi = "https://doi.org/10.14291/tccon.bad01"
+"#,
+        );
+        let problems = validate_site_metadata(&path).unwrap();
+        let err = read_nc_site_metadata(&path).unwrap_err();
+
+        let site_id = "xx".to_string();
+        assert_eq!(
+            problems,
+            [
+                SiteMetadataError::WrongType {
+                    site_id: site_id.clone(),
+                    key: "location",
+                    expected: "a string"
+                },
+                SiteMetadataError::MissingKey {
+                    site_id: site_id.clone(),
+                    key: "contact"
+                },
+                SiteMetadataError::InvalidValue {
+                    site_id: site_id.clone(),
+                    key: "release_lag",
+                    reason: "'soon' cannot be parsed as an unsigned integer".to_string()
+                },
+                SiteMetadataError::InvalidValue {
+                    site_id,
+                    key: "data_doi",
+                    reason: "'https://doi.org/10.14291/tccon.bad01' does not start with \"10.\""
+                        .to_string()
+                },
+            ]
+        );
+        assert!(err.to_string().contains("4 problem(s)"), "{err}");
+        assert!(err.to_string().contains("site 'xx'"), "{err}");
+    }
+
+    #[test]
+    fn test_validate_site_metadata_json() {
+        // JSON files historically give the release lag as a string
+        let path = write_site_metadata(
+            "site-metadata-test.json",
+            r#"{"pa": {"long_name": "parkfalls01", "release_lag": "120", "location": "Park Falls", "contact": "PW", "data_revision": "R1"}, "xx": "not a table"}"#,
+        );
+        let problems = validate_site_metadata(&path).unwrap();
+        assert_eq!(
+            problems,
+            [SiteMetadataError::NotATable {
+                site_id: "xx".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_check_cf_compliance() {
+        let output_dir = crate::test_utils::output_dir("nc-utils-cf-compliance");
+        let nc_path = output_dir.join("cf-compliance-test.nc");
+        {
+            let mut ds = netcdf::create(&nc_path).unwrap();
+            ds.add_dimension("time", 2).unwrap();
+            ds.add_dimension("prior_altitude", 3).unwrap();
+
+            let mut var = ds.add_variable::<f64>("time", &["time"]).unwrap();
+            var.put_attribute("standard_name", "time").unwrap();
+            var.put_attribute("units", "seconds since 1970-01-01 00:00:00")
+                .unwrap();
+
+            let mut var = ds
+                .add_variable::<f32>("prior_altitude", &["prior_altitude"])
+                .unwrap();
+            var.put_attribute("long_name", "altitude of the a priori levels")
+                .unwrap();
+            var.put_attribute("units", "km").unwrap();
+
+            let mut var = ds.add_variable::<f32>("sia", &["time"]).unwrap();
+            var.put_attribute("units", "AU").unwrap();
+
+            let mut var = ds
+                .add_variable::<f32>("prior_pressure", &["time", "prior_altitude"])
+                .unwrap();
+            var.put_attribute("long_name", "a priori pressure").unwrap();
+            var.put_attribute("units", "atm").unwrap();
+        }
+
+        let ds = netcdf::open(&nc_path).unwrap();
+        let report = check_cf_compliance(&ds).unwrap();
+
+        assert_eq!(
+            report.findings,
+            [
+                CfFinding::MissingConventions,
+                CfFinding::MissingPositive {
+                    variable: "prior_altitude".to_string()
+                },
+                CfFinding::MissingName {
+                    variable: "sia".to_string()
+                },
+                CfFinding::InvalidUnits {
+                    variable: "sia".to_string(),
+                    units: "AU".to_string()
+                },
+            ]
+        );
+        assert_eq!(report.with_priority(CfPriority::High).count(), 3);
+        assert_eq!(report.with_priority(CfPriority::Medium).count(), 1);
+    }
+
+    #[test]
+    fn test_run_migrations() {
+        let output_dir = crate::test_utils::output_dir("nc-utils-migrations");
+        let nc_path = output_dir.join("migration-test.nc");
+        {
+            let mut ds = netcdf::create(&nc_path).unwrap();
+            ds.add_attribute("title", "migration test").unwrap();
+        }
+
+        let add_conventions = AddGlobalAttribute {
+            from_version: None,
+            to_version: "1.0".to_string(),
+            attribute: "Conventions".to_string(),
+            value: "CF-1.8".into(),
+        };
+        let add_completed = AddGlobalAttribute {
+            from_version: Some("1.0".to_string()),
+            to_version: "1.1".to_string(),
+            attribute: "writing_was_completed".to_string(),
+            value: 1i32.into(),
+        };
+        let migrations: [&dyn Migration; 2] = [&add_conventions, &add_completed];
+
+        let mut ds = netcdf::append(&nc_path).unwrap();
+        let applied = run_migrations(&mut ds, &migrations).unwrap();
+        assert_eq!(applied.len(), 2);
+        // Running the migrations again should be a no-op
+        let applied = run_migrations(&mut ds, &migrations).unwrap();
+        assert!(applied.is_empty());
+        drop(ds);
+
+        let ds = netcdf::open(&nc_path).unwrap();
+        let version = get_string_attr(&ds, FILE_FORMAT_VERSION_ATTR).unwrap();
+        let conventions = get_string_attr(&ds, "Conventions").unwrap();
+        let completed = ds
+            .attribute("writing_was_completed")
+            .unwrap()
+            .value()
+            .unwrap();
+
+        assert_eq!(version, "1.1");
+        assert_eq!(conventions, "CF-1.8");
+        assert_eq!(completed, netcdf::AttributeValue::Int(1));
+    }
+}
@@ -215,6 +215,45 @@ pub mod bruker {
         }
     }
 
+    /// Meanings of the codes stored in common Bruker ENUM/SENUM parameters,
+    /// as (parameter name, code, meaning). Used by [`BrukerParValue::as_enum_str`].
+    pub const ENUM_MEANINGS: &[(&str, &str, &str)] = &[
+        // Data x-units; DXU2 is the same parameter for the second channel
+        ("DXU", "WN", "wavenumber"),
+        ("DXU", "MI", "micrometers"),
+        ("DXU", "LGW", "log wavenumber"),
+        ("DXU", "MIN", "minutes"),
+        ("DXU", "PNT", "points"),
+        ("DXU2", "WN", "wavenumber"),
+        ("DXU2", "MI", "micrometers"),
+        ("DXU2", "LGW", "log wavenumber"),
+        ("DXU2", "MIN", "minutes"),
+        ("DXU2", "PNT", "points"),
+        // Apodization function
+        ("APF", "BX", "boxcar"),
+        ("APF", "TR", "triangular"),
+        ("APF", "HG", "Happ-Genzel"),
+        ("APF", "B3", "Blackman-Harris 3-term"),
+        ("APF", "B4", "Blackman-Harris 4-term"),
+        ("APF", "NBW", "Norton-Beer weak"),
+        ("APF", "NBM", "Norton-Beer medium"),
+        ("APF", "NBS", "Norton-Beer strong"),
+        ("APF", "4P", "four-point"),
+        // Phase correction mode
+        ("PHZ", "ML", "Mertz"),
+        ("PHZ", "MS", "Mertz signed"),
+        ("PHZ", "MM", "Mertz, no peak search"),
+        ("PHZ", "PW", "power spectrum"),
+        ("PHZ", "ID", "identical"),
+        ("PHZ", "NONE", "none"),
+        // Detector
+        ("DTC", "LN-InSb", "liquid nitrogen cooled InSb"),
+        ("DTC", "LN-MCT", "liquid nitrogen cooled MCT"),
+        ("DTC", "RT-InGaAs", "room temperature InGaAs"),
+        ("DTC", "RT-Si", "room temperature Si diode"),
+        ("DTC", "RT-DLaTGS", "room temperature DLaTGS"),
+    ];
+
     #[derive(Debug)]
     pub enum BrukerParValue {
         Integer(i32),
@@ -287,6 +326,38 @@ pub mod bruker {
             }
         }
 
+        /// For an ENUM or SENUM value, the code stored in it (the bytes up to the first null).
+        /// Returns `None` for other types or if the code is not valid UTF-8.
+        pub fn enum_code(&self) -> Option<&str> {
+            match self {
+                BrukerParValue::Enum(b) | BrukerParValue::Senum(b) => {
+                    let inull = b.iter().position(|&c| c == 0).unwrap_or(b.len());
+                    std::str::from_utf8(&b[..inull]).ok()
+                }
+                _ => None,
+            }
+        }
+
+        /// Translate an ENUM or SENUM value of the parameter `param_name` into a readable
+        /// string, e.g. "wavenumber" for a `DXU` value of "WN".
+        ///
+        /// Detector codes often carry the detector position after the name (e.g.
+        /// "RT-InGaAs [Internal Pos.2]"), so only the part before the first space is
+        /// matched for `DTC`. Returns `None` if this is not an ENUM/SENUM value or the
+        /// parameter/code combination is not in [`ENUM_MEANINGS`].
+        pub fn as_enum_str(&self, param_name: &str) -> Option<&'static str> {
+            let code = self.enum_code()?;
+            let code = if param_name == "DTC" {
+                code.split_whitespace().next().unwrap_or(code)
+            } else {
+                code.trim()
+            };
+            ENUM_MEANINGS
+                .iter()
+                .find(|(p, c, _)| *p == param_name && *c == code)
+                .map(|(_, _, meaning)| *meaning)
+        }
+
         pub fn opus_type(&self) -> BrukerParType {
             match self {
                 BrukerParValue::Integer(_) => BrukerParType::Integer,
@@ -301,7 +372,7 @@ pub mod bruker {
 }
 
 pub mod i2s {
-    #[derive(Debug, PartialEq, Clone, Copy)]
+    #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
     pub enum I2sSpectrumHeaderBlockType {
         DstatSpec,
         DstatIgram,
@@ -16,7 +16,10 @@ use crate::{
 use itertools::Itertools;
 use ndarray::Array1;
 
-use self::constants::bruker::{BrukerBlockType, BrukerParValue};
+use self::constants::{
+    bruker::{BrukerBlockType, BrukerParValue},
+    i2s::I2sSpectrumHeaderBlockType,
+};
 
 pub mod constants;
 
@@ -63,7 +66,7 @@ pub enum OpusTypeError {
 
 #[derive(Debug)]
 pub struct MissingOpusParameterError {
-    block: BrukerBlockType,
+    block: String,
     parameter: String,
     block_missing: bool,
 }
@@ -71,15 +74,11 @@ pub struct MissingOpusParameterError {
 impl Display for MissingOpusParameterError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         if self.block_missing {
-            write!(
-                f,
-                "Requested block {:?} missing from Opus header",
-                self.block
-            )
+            write!(f, "Requested block {} missing from Opus header", self.block)
         } else {
             write!(
                 f,
-                "Requested parameter {} from block {:?} missing from Opus header",
+                "Requested parameter {} from block {} missing from Opus header",
                 self.parameter, self.block
             )
         }
@@ -167,12 +166,113 @@ pub fn read_spectrum_from_runlog_rec(
     )
 }
 
+/// What a [`SpectrumIter`] should do when a spectrum listed in the runlog cannot be found
+/// in the data partition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MissingSpectrumAction {
+    /// Log a warning and move on to the next runlog record.
+    Skip,
+    /// Yield an `Err` for that record. Iteration may continue afterwards.
+    #[default]
+    Error,
+}
+
+/// An iterator over all the spectra referenced by a runlog.
+///
+/// Each item is the runlog data record paired with the [`Spectrum`] it points to.
+/// Unlike iterating over a [`runlogs::Runlog`] directly, errors reading the runlog
+/// are returned as `Err` items rather than causing a panic. Spectra that cannot be
+/// found in the data partition either produce an `Err` (the default) or are skipped,
+/// depending on the [`MissingSpectrumAction`] set with [`SpectrumIter::on_missing`].
+/// Errors reading a spectrum that *was* found are always returned.
+///
+/// # Example
+/// ```no_run
+/// use ggg_rs::opus::{MissingSpectrumAction, SpectrumIter};
+/// use ggg_rs::readers::runlogs::Runlog;
+/// use ggg_rs::utils::DataPartition;
+///
+/// let runlog = Runlog::open(std::path::Path::new("pa_ggg_benchmark.grl")).unwrap();
+/// let data_part = DataPartition::new_from_ggg_path().unwrap();
+/// for item in SpectrumIter::new(runlog, &data_part).on_missing(MissingSpectrumAction::Skip) {
+///     let (data_rec, spectrum) = item.unwrap();
+///     println!("{} has {} points", data_rec.spectrum_name, spectrum.spec.len());
+/// }
+/// ```
+pub struct SpectrumIter<'d> {
+    runlog: runlogs::FallibleRunlog,
+    data_part: &'d utils::DataPartition,
+    on_missing: MissingSpectrumAction,
+}
+
+impl<'d> SpectrumIter<'d> {
+    /// Create an iterator over the spectra in `runlog`, searching for them in `data_part`.
+    /// Missing spectra will produce an `Err`; use [`SpectrumIter::on_missing`] to change that.
+    pub fn new(runlog: runlogs::Runlog, data_part: &'d utils::DataPartition) -> Self {
+        Self {
+            runlog: runlog.into(),
+            data_part,
+            on_missing: MissingSpectrumAction::default(),
+        }
+    }
+
+    /// Set what to do when a spectrum cannot be found in the data partition.
+    pub fn on_missing(mut self, action: MissingSpectrumAction) -> Self {
+        self.on_missing = action;
+        self
+    }
+}
+
+impl<'d> Iterator for SpectrumIter<'d> {
+    type Item = Result<(runlogs::RunlogDataRec, Spectrum), GggError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let data_rec = match self.runlog.next()? {
+                Ok(rec) => rec,
+                Err(e) => return Some(Err(e)),
+            };
+
+            let spec_file = match (
+                self.data_part.find_spectrum(&data_rec.spectrum_name),
+                self.on_missing,
+            ) {
+                (Some(f), _) => f,
+                (None, MissingSpectrumAction::Skip) => {
+                    log::warn!(
+                        "Spectrum {} not found in the data partition, skipping it",
+                        data_rec.spectrum_name
+                    );
+                    continue;
+                }
+                (None, MissingSpectrumAction::Error) => {
+                    return Some(Err(GggError::CouldNotOpen {
+                        descr: "spectrum".to_owned(),
+                        path: PathBuf::from(&data_rec.spectrum_name),
+                        reason: "spectrum not found".to_owned(),
+                    }))
+                }
+            };
+
+            let spectrum = read_spectrum(
+                spec_file,
+                data_rec.bpw,
+                data_rec.ifirst,
+                data_rec.delta_nu,
+                data_rec.pointer,
+            );
+            return Some(spectrum.map(|spec| (data_rec, spec)));
+        }
+    }
+}
+
 /// Read an Opus-format binary spectrum.
 ///
 /// # Parameters
 /// * `spec_file` - path to the spectrum file
 /// * `bpw` - the bytes-per-word value from the runlog/spectrum header. Used to interpret how the binary data is converted;
-///   +/- 2 means it is interpreted as a series of `i16` values, +/- 4 means it is interpreted as a series of `f32` values.
+///   +/- 2 means it is interpreted as a series of `i16` values, +/- 4 means it is interpreted as a series of `f32` values,
+///   and +/- 8 means it is interpreted as a series of `f64` values (which are downcast to `f32`).
 ///   Positive means big endian, negative means little endian.
 /// * `ifirst` - the number of spectral points between 0 and the first of the spectrum, i.e. the frequency of the first point
 ///   will be `ifirst * delta_nu`.
@@ -226,13 +326,7 @@ pub fn read_spectrum(
     })?;
 
     let spec = SpecBytesToFloat::convert_spectrum(&buf, bpw)?;
-    let npts = spec.len();
-
-    let mut freq = ndarray::Array1::zeros(npts);
-
-    for i in 0..npts {
-        freq[i] = (delta_nu as f32) * (i + ifirst) as f32;
-    }
+    let freq = compute_frequency_grid(spec.len(), ifirst, delta_nu);
 
     Ok(Spectrum {
         path: spec_file,
@@ -241,6 +335,20 @@ pub fn read_spectrum(
     })
 }
 
+/// Compute the frequency axis for a spectrum.
+///
+/// # Parameters
+/// * `npts` - the number of spectral points.
+/// * `ifirst` - the number of spectral points between 0 and the first of the spectrum, as in [`read_spectrum`].
+/// * `delta_nu` - the wavenumber spacing between adjacent spectral points.
+///
+/// # Returns
+/// An array of length `npts` where element `i` is `delta_nu * (i + ifirst)`. That is, the first
+/// point is at `ifirst * delta_nu`, not `(ifirst + 1) * delta_nu`.
+pub fn compute_frequency_grid(npts: usize, ifirst: usize, delta_nu: f64) -> Array1<f32> {
+    Array1::from_iter((0..npts).map(|i| (delta_nu as f32) * (i + ifirst) as f32))
+}
+
 /// A converter that handles the various Opus spectrum formats
 ///
 /// To use: call `convert_spectrum` with the raw bytes read from the Opus spectrum.
@@ -249,6 +357,8 @@ enum SpecBytesToFloat {
     IntLittleEndian,
     FloatBigEndian,
     FloatLittleEndian,
+    FloatBigEndian64,
+    FloatLittleEndian64,
 }
 
 impl SpecBytesToFloat {
@@ -256,8 +366,9 @@ impl SpecBytesToFloat {
     ///
     /// # Parameters
     /// * `buf` - the slice of bytes read from the spectrum
-    /// * `bpw` - the number of bytes per spectrum point. Only +/- 2 and +/- 4 currently implemented.
-    ///   Negative is treated as little endian, 2 BPW values are interpreted as i16s and 4 BPWs as f32s.
+    /// * `bpw` - the number of bytes per spectrum point. Only +/- 2, +/- 4, and +/- 8 currently implemented.
+    ///   Negative is treated as little endian, 2 BPW values are interpreted as i16s, 4 BPWs as f32s, and
+    ///   8 BPWs as f64s (downcast to f32).
     fn convert_spectrum(buf: &[u8], bpw: i8) -> Result<ndarray::Array1<f32>, GggError> {
         let (me, chunks) = Self::new_from_buf(buf, bpw)?;
         let npts = buf.len() / (bpw.abs() as usize);
@@ -281,6 +392,8 @@ impl SpecBytesToFloat {
             -2 => Self::IntLittleEndian,
             4 => Self::FloatBigEndian,
             -4 => Self::FloatLittleEndian,
+            8 => Self::FloatBigEndian64,
+            -8 => Self::FloatLittleEndian64,
             _ => {
                 return Err(GggError::NotImplemented(format!(
                     "reading spectra with bpw = {bpw}"
@@ -295,7 +408,8 @@ impl SpecBytesToFloat {
     /// Convert one set of bytes to an f32 value
     ///
     /// # Panics
-    /// Will panic if given a slice of bytes with a different length than the variant is expecting (2 for the ints and 4 for the floats).
+    /// Will panic if given a slice of bytes with a different length than the variant is expecting (2 for the ints, 4 for the
+    /// 32-bit floats, and 8 for the 64-bit floats).
     /// It is not recommended to call this method directly but instead to use `convert_spectrum`, which ensures the correct chunking is done.
     fn convert(&self, bytes: &[u8]) -> f32 {
         match self {
@@ -325,10 +439,260 @@ impl SpecBytesToFloat {
                     bytes.len()
                 )))
             }
+            SpecBytesToFloat::FloatBigEndian64 => {
+                f64::from_be_bytes(bytes.try_into().unwrap_or_else(|_| {
+                    panic!(
+                        "Passed the wrong number of bytes to BytesToFloat::convert, expected 8, got {}",
+                        bytes.len()
+                    )
+                })) as f32
+            }
+            SpecBytesToFloat::FloatLittleEndian64 => {
+                f64::from_le_bytes(bytes.try_into().unwrap_or_else(|_| {
+                    panic!(
+                        "Passed the wrong number of bytes to BytesToFloat::convert, expected 8, got {}",
+                        bytes.len()
+                    )
+                })) as f32
+            }
         }
     }
 }
 
+/// Write a [`Spectrum`] as an Opus-format binary file.
+///
+/// The header is minimal: a directory with one data status block and one data block. The data
+/// status block holds the first and last frequencies (FXV, LXV) and number of points (NPT), from
+/// which [`SpectrumHeader::ifirst_and_delta_nu`] recovers the `ifirst` and `delta_nu` that
+/// [`read_spectrum`] needs to reproduce the frequency grid. The grid must be of the form that
+/// [`compute_frequency_grid`] creates, i.e. `delta_nu * (i + ifirst)`, and span enough points that
+/// `ifirst` can be determined despite the single precision frequencies; a full spectrum easily does.
+///
+/// # Parameters
+/// * `spec_file` - path to write the spectrum to; overwritten if it exists.
+/// * `spectrum` - the spectrum to write. It must have at least two points.
+/// * `bpw` - how to encode the spectral values, with the same meaning as in [`read_spectrum`].
+/// * `pointer` - the byte offset at which to start the spectral data. Must be a multiple of 4
+///   and large enough to fit the header.
+///
+/// # Returns
+/// An `Err` if the spectrum has fewer than two points, `ifirst` cannot be determined from the
+/// frequency grid, `bpw` is not implemented, `pointer` is invalid, the spectral values are too
+/// large (or not finite) for a two-byte `bpw`, or writing the file fails.
+pub fn write_spectrum(
+    spec_file: &Path,
+    spectrum: &Spectrum,
+    bpw: i8,
+    pointer: i32,
+) -> Result<(), GggError> {
+    write_spectrum_with_params(spec_file, spectrum, bpw, pointer, &[])
+}
+
+/// Like [`write_spectrum`], but with additional header parameters.
+///
+/// Each element of `extra_params` is a block type (as in the Opus directory, e.g. 48 for
+/// acquisition parameters), parameter name, and value. Parameters for the spectrum data status
+/// block (1047) are written after the ones [`write_spectrum`] always includes; the others are
+/// grouped into one block per type, placed before the data status block in the order their types
+/// first appear.
+pub(crate) fn write_spectrum_with_params(
+    spec_file: &Path,
+    spectrum: &Spectrum,
+    bpw: i8,
+    pointer: i32,
+    extra_params: &[(i32, &str, BrukerParValue)],
+) -> Result<(), GggError> {
+    // Block types that I2S recognizes as the spectrum data status and data blocks,
+    // see `I2sSpectrumHeaderBlockType`.
+    const DSTAT_SPEC_BLOCK: i32 = 1047;
+    const DATA_BLOCK: i32 = 1031;
+    // The directory immediately follows the fixed part of the header, which is 24 bytes
+    // and so already on a word boundary.
+    const DIR_POINTER: usize = 24;
+
+    let data_error = |cause: String| GggError::DataError {
+        path: spec_file.to_path_buf(),
+        cause,
+    };
+
+    let npts = spectrum.spec.len();
+    if npts < 2 || spectrum.freq.len() != npts {
+        return Err(data_error(format!(
+            "Spectrum must have at least two points and equal length frequency and spectrum arrays (got {} and {})",
+            spectrum.freq.len(),
+            npts
+        )));
+    }
+
+    // The header only stores the first and last frequency, so we need to find the integer ifirst
+    // and the spacing such that the grid is delta_nu * (i + ifirst). The spacing from the endpoints
+    // is only good enough to find ifirst; once we have that, using both endpoints and ifirst gives a
+    // more precise spacing. Store the frequencies as ifirst * delta_nu so that ifirst is recovered
+    // exactly.
+    let first_freq = spectrum.freq[0] as f64;
+    let last_freq = spectrum.freq[npts - 1] as f64;
+    let ifirst = first_freq * (npts - 1) as f64 / (last_freq - first_freq);
+    if !ifirst.is_finite() || ifirst < 0.0 || (ifirst - ifirst.round()).abs() > 0.25 {
+        return Err(data_error(format!(
+            "Could not determine an integer ifirst for the frequency grid (got {ifirst}); the grid must be evenly spaced, start at a positive integer multiple of its spacing, and span enough points to determine that multiple"
+        )));
+    }
+    let ifirst = ifirst.round();
+    let delta_nu = (first_freq + last_freq) / (2.0 * ifirst + (npts - 1) as f64);
+
+    let mut dstat_block = vec![];
+    encode_param(
+        &mut dstat_block,
+        "NPT",
+        &BrukerParValue::Integer(npts as i32),
+    );
+    encode_param(
+        &mut dstat_block,
+        "FXV",
+        &BrukerParValue::Float(ifirst * delta_nu),
+    );
+    encode_param(
+        &mut dstat_block,
+        "LXV",
+        &BrukerParValue::Float((ifirst + (npts - 1) as f64) * delta_nu),
+    );
+
+    let mut header_blocks: Vec<(i32, Vec<u8>)> = vec![];
+    for (block_type, name, value) in extra_params {
+        if *block_type == DSTAT_SPEC_BLOCK {
+            encode_param(&mut dstat_block, name, value);
+        } else if let Some((_, block)) = header_blocks.iter_mut().find(|(t, _)| t == block_type) {
+            encode_param(block, name, value);
+        } else {
+            let mut block = vec![];
+            encode_param(&mut block, name, value);
+            header_blocks.push((*block_type, block));
+        }
+    }
+    for (_, block) in header_blocks.iter_mut() {
+        encode_block_end(block);
+    }
+    encode_block_end(&mut dstat_block);
+    header_blocks.push((DSTAT_SPEC_BLOCK, dstat_block));
+
+    // One directory entry for each header block plus the data block
+    let num_blocks = header_blocks.len() + 1;
+    let mut header_len = DIR_POINTER + num_blocks * 12;
+    let mut directory = vec![];
+    for (block_type, block) in header_blocks.iter() {
+        directory.push((*block_type, block.len(), header_len));
+        header_len += block.len();
+    }
+    let data_pointer = usize::try_from(pointer).unwrap_or(0);
+    if data_pointer < header_len || data_pointer % 4 != 0 {
+        return Err(data_error(format!(
+            "Data pointer must be a multiple of 4 and at least {header_len} to fit the header, got {pointer}"
+        )));
+    }
+
+    // Two byte integers are scaled by a fixed factor (the same one `read_spectrum` divides by),
+    // so values outside the range of an i16 after scaling cannot be stored.
+    if bpw.abs() == 2 {
+        let max_abs = i16::MAX as f32 / 15000.0;
+        if let Some(v) = spectrum
+            .spec
+            .iter()
+            .find(|v| !v.is_finite() || v.abs() > max_abs)
+        {
+            return Err(data_error(format!(
+                "Spectral value {v} cannot be stored with bpw = {bpw}, values must be within +/-{max_abs}; use bpw = 4 or 8 instead"
+            )));
+        }
+    }
+
+    let data_block: Vec<u8> = match bpw {
+        2 => spectrum
+            .spec
+            .iter()
+            .flat_map(|&v| ((v * 15000.0).round() as i16).to_be_bytes())
+            .collect(),
+        -2 => spectrum
+            .spec
+            .iter()
+            .flat_map(|&v| ((v * 15000.0).round() as i16).to_le_bytes())
+            .collect(),
+        4 => spectrum.spec.iter().flat_map(|v| v.to_be_bytes()).collect(),
+        -4 => spectrum.spec.iter().flat_map(|v| v.to_le_bytes()).collect(),
+        8 => spectrum
+            .spec
+            .iter()
+            .flat_map(|&v| (v as f64).to_be_bytes())
+            .collect(),
+        -8 => spectrum
+            .spec
+            .iter()
+            .flat_map(|&v| (v as f64).to_le_bytes())
+            .collect(),
+        _ => {
+            return Err(GggError::NotImplemented(format!(
+                "writing spectra with bpw = {bpw}"
+            )))
+        }
+    };
+
+    let mut buf = Vec::with_capacity(data_pointer + data_block.len());
+    buf.extend(constants::bruker::MAGIC.to_le_bytes());
+    buf.extend(constants::bruker::PRGM_VERS.to_le_bytes());
+    buf.extend((DIR_POINTER as i32).to_le_bytes());
+    buf.extend(constants::bruker::MDB.to_le_bytes());
+    buf.extend((num_blocks as i32).to_le_bytes());
+    directory.push((DATA_BLOCK, data_block.len(), data_pointer));
+    // Block lengths are given in 4-byte words
+    for (itype, len, ipoint) in directory {
+        buf.extend(itype.to_le_bytes());
+        buf.extend((len.div_ceil(4) as i32).to_le_bytes());
+        buf.extend((ipoint as i32).to_le_bytes());
+    }
+    for (_, block) in header_blocks {
+        buf.extend(block);
+    }
+    buf.resize(data_pointer, 0);
+    buf.extend(data_block);
+
+    std::fs::write(spec_file, buf).map_err(|e| GggError::CouldNotWrite {
+        path: spec_file.to_path_buf(),
+        reason: e.to_string(),
+    })
+}
+
+/// Append one Opus header parameter (4-byte name, type, length in 2-byte words, then value) to `buf`.
+/// Values are written little endian, which is what [`HeaderByteReader`] expects by default.
+pub(crate) fn encode_param(buf: &mut Vec<u8>, name: &str, value: &BrukerParValue) {
+    let mut key = name.as_bytes().to_vec();
+    key.resize(4, 0);
+    buf.extend(key);
+    let (ptype, mut bytes) = match value {
+        BrukerParValue::Integer(i) => (constants::bruker::TYPE_I4, i.to_le_bytes().to_vec()),
+        BrukerParValue::Float(f) => (constants::bruker::TYPE_R8, f.to_le_bytes().to_vec()),
+        BrukerParValue::String(s) => {
+            // Strings must be null terminated
+            let mut b = s.as_bytes().to_vec();
+            b.push(0);
+            (constants::bruker::TYPE_STRING, b)
+        }
+        BrukerParValue::Enum(b) => (constants::bruker::TYPE_ENUM, b.clone()),
+        BrukerParValue::Senum(b) => (constants::bruker::TYPE_SENUM, b.clone()),
+        BrukerParValue::Unknown(b, i) => (*i, b.clone()),
+    };
+    // Pad to a whole number of 2-byte words
+    bytes.resize(bytes.len().div_ceil(2) * 2, 0);
+    buf.extend((ptype as i16).to_le_bytes());
+    buf.extend(((bytes.len() / 2) as i16).to_le_bytes());
+    buf.extend(bytes);
+}
+
+/// Append the zero-length parameter that marks the end of an Opus header block to `buf`.
+pub(crate) fn encode_block_end(buf: &mut Vec<u8>) {
+    buf.extend(b"END\0");
+    buf.extend(0i16.to_le_bytes());
+    buf.extend(0i16.to_le_bytes());
+}
+
 /// Calculates the number of points in an Opus binary spectrum
 ///
 /// # Parameters
@@ -362,7 +726,34 @@ pub fn get_spectrum_num_points(
     Ok((file_length - pointer) / abpw)
 }
 
-fn read_spectrum_header(spec_name: &str) {}
+/// Read the Opus header of a processed spectrum.
+///
+/// All parameter blocks are read; the data block is skipped. Use [`SpectrumHeader::get_value`]
+/// to access any parameter, or the convenience methods for common acquisition parameters.
+///
+/// # Errors
+/// If the spectrum cannot be opened or read, or if any of the parameter blocks are malformed.
+pub fn read_spectrum_header(spectrum: &Path) -> OpusResult<SpectrumHeader> {
+    let mut f = std::fs::File::open(spectrum)?;
+    let mut byte_reader = HeaderByteReader::default();
+    let metadata = SpectrumHeaderMetadata::read_from_file(&mut f, &mut byte_reader)?;
+
+    let mut parameter_blocks = HashMap::new();
+    for block_definition in metadata.blocks.iter() {
+        if let I2sSpectrumHeaderBlockType::Data | I2sSpectrumHeaderBlockType::Unknown(_) =
+            block_definition.itype
+        {
+            continue;
+        }
+        let bv = IgramHeader::read_param_block(block_definition, &byte_reader, &mut f)?;
+        parameter_blocks.insert(block_definition.itype, bv);
+    }
+
+    Ok(SpectrumHeader {
+        metadata,
+        parameter_blocks,
+    })
+}
 
 struct HeaderByteReader {
     is_big_endian: bool,
@@ -583,8 +974,8 @@ impl IgramHeader {
         })
     }
 
-    fn read_param_block(
-        header_def: &HeaderBlockDef<constants::bruker::BrukerBlockType>,
+    fn read_param_block<T: Copy>(
+        header_def: &HeaderBlockDef<T>,
         reader: &HeaderByteReader,
         f: &mut std::fs::File,
     ) -> OpusResult<HashMap<String, BrukerParValue>> {
@@ -695,13 +1086,13 @@ impl IgramHeader {
         self.parameter_blocks
             .get(&block)
             .ok_or_else(|| MissingOpusParameterError {
-                block,
+                block: format!("{block:?}"),
                 parameter: parameter.to_string(),
                 block_missing: true,
             })?
             .get(parameter)
             .ok_or_else(|| MissingOpusParameterError {
-                block,
+                block: format!("{block:?}"),
                 parameter: parameter.to_string(),
                 block_missing: false,
             })
@@ -759,6 +1150,174 @@ impl IgramHeader {
     }
 }
 
+/// The parameters from the Opus header of a processed spectrum, see [`read_spectrum_header`].
+#[derive(Debug)]
+pub struct SpectrumHeader {
+    metadata: SpectrumHeaderMetadata,
+    parameter_blocks: HashMap<I2sSpectrumHeaderBlockType, HashMap<String, BrukerParValue>>,
+}
+
+impl SpectrumHeader {
+    /// Retrieve a value from a given block in this header
+    ///
+    /// # Inputs
+    /// - `block`: which block the parameter can be found in
+    /// - `parameter`: the parameter name, usually a 3-4 character string; see [`IgramHeader::get_value`]
+    ///   for how to find the available parameters.
+    ///
+    /// # Errors
+    /// If the block cannot be found in the header or the parameter is not present in the block.
+    pub fn get_value(
+        &self,
+        block: I2sSpectrumHeaderBlockType,
+        parameter: &str,
+    ) -> Result<&BrukerParValue, MissingOpusParameterError> {
+        self.parameter_blocks
+            .get(&block)
+            .ok_or_else(|| MissingOpusParameterError {
+                block: format!("{block:?}"),
+                parameter: parameter.to_string(),
+                block_missing: true,
+            })?
+            .get(parameter)
+            .ok_or_else(|| MissingOpusParameterError {
+                block: format!("{block:?}"),
+                parameter: parameter.to_string(),
+                block_missing: false,
+            })
+    }
+
+    /// The number of header blocks listed in the spectrum's directory, including the data block.
+    pub fn num_blocks(&self) -> usize {
+        self.metadata.blocks.len()
+    }
+
+    /// Spectral resolution (RES) in cm-1, from the acquisition parameters.
+    pub fn resolution(&self) -> Option<f64> {
+        self.get_value(I2sSpectrumHeaderBlockType::AqPar, "RES")
+            .ok()
+            .and_then(|v| v.as_float().ok())
+    }
+
+    /// The `ifirst` and `delta_nu` values to pass to [`read_spectrum`] for this spectrum, computed
+    /// from the first and last frequencies (FXV, LXV) and number of points (NPT) in the spectrum
+    /// data status. Returns `None` if any of these are missing or there are fewer than two points.
+    pub fn ifirst_and_delta_nu(&self) -> Option<(usize, f64)> {
+        let npts = self
+            .get_value(I2sSpectrumHeaderBlockType::DstatSpec, "NPT")
+            .ok()?
+            .as_integer()
+            .ok()?;
+        if npts < 2 {
+            return None;
+        }
+        let first = self.first_frequency()?;
+        let last = self.last_frequency()?;
+        let delta_nu = (last - first) / (npts - 1) as f64;
+        let ifirst = (first / delta_nu).round();
+        if ifirst < 0.0 {
+            return None;
+        }
+        Some((ifirst as usize, delta_nu))
+    }
+
+    /// Frequency of the first point (FXV) in cm-1, from the spectrum data status.
+    pub fn first_frequency(&self) -> Option<f64> {
+        self.get_value(I2sSpectrumHeaderBlockType::DstatSpec, "FXV")
+            .ok()
+            .and_then(|v| v.as_float().ok())
+    }
+
+    /// Frequency of the last point (LXV) in cm-1, from the spectrum data status.
+    pub fn last_frequency(&self) -> Option<f64> {
+        self.get_value(I2sSpectrumHeaderBlockType::DstatSpec, "LXV")
+            .ok()
+            .and_then(|v| v.as_float().ok())
+    }
+
+    /// Apodization function (APF), from the Fourier transform parameters.
+    pub fn apodization(&self) -> Option<String> {
+        self.get_value(I2sSpectrumHeaderBlockType::FtPar, "APF")
+            .ok()
+            .and_then(par_value_to_string)
+    }
+
+    /// Detector signal gain (SGN), from the acquisition parameters.
+    pub fn detector_gain(&self) -> Option<String> {
+        self.get_value(I2sSpectrumHeaderBlockType::AqPar, "SGN")
+            .ok()
+            .and_then(par_value_to_string)
+    }
+
+    /// Number of sample scans (NSS), from the acquisition parameters.
+    pub fn scan_count(&self) -> Option<i32> {
+        self.get_value(I2sSpectrumHeaderBlockType::AqPar, "NSS")
+            .ok()
+            .and_then(|v| v.as_integer().ok())
+    }
+
+    /// The time the measurement started, from the DAT and TIM parameters of the spectrum data status.
+    ///
+    /// Note that this is when Opus started the measurement, not the ZPD time, so it will be
+    /// earlier than the runlog time by up to the duration of the scans. Opus records the time
+    /// with its offset from GMT after it, e.g. "12:34:56.789 (GMT+2)"; the returned time keeps
+    /// that offset. A time without a zone is assumed to be UTC. Returns `None` if either
+    /// parameter is missing or cannot be parsed.
+    pub fn measurement_time(&self) -> Option<chrono::DateTime<chrono::FixedOffset>> {
+        let date = self
+            .get_value(I2sSpectrumHeaderBlockType::DstatSpec, "DAT")
+            .ok()?
+            .as_str()
+            .ok()?;
+        let time = self
+            .get_value(I2sSpectrumHeaderBlockType::DstatSpec, "TIM")
+            .ok()?
+            .as_str()
+            .ok()?;
+        let date = chrono::NaiveDate::parse_from_str(date.trim(), "%d/%m/%Y").ok()?;
+        let (time, zone) = time
+            .trim()
+            .split_once(char::is_whitespace)
+            .unwrap_or((time.trim(), ""));
+        let time = chrono::NaiveTime::parse_from_str(time, "%H:%M:%S%.f").ok()?;
+        let offset = if zone.trim().is_empty() {
+            chrono::FixedOffset::east_opt(0)?
+        } else {
+            parse_gmt_offset(zone)?
+        };
+        date.and_time(time).and_local_timezone(offset).single()
+    }
+}
+
+/// Parse the zone Opus writes after the TIM parameter, e.g. "(GMT+2)" or "(GMT-5:30)".
+fn parse_gmt_offset(zone: &str) -> Option<chrono::FixedOffset> {
+    let zone = zone.trim().strip_prefix('(')?.strip_suffix(')')?.trim();
+    let offset = zone
+        .strip_prefix("GMT")
+        .or_else(|| zone.strip_prefix("UTC"))?;
+    if offset.is_empty() {
+        return chrono::FixedOffset::east_opt(0);
+    }
+
+    let (sign, offset) = if let Some(o) = offset.strip_prefix('+') {
+        (1, o)
+    } else {
+        (-1, offset.strip_prefix('-')?)
+    };
+    let (hours, minutes) = offset.split_once(':').unwrap_or((offset, "0"));
+    let seconds = hours.parse::<i32>().ok()? * 3600 + minutes.parse::<i32>().ok()? * 60;
+    chrono::FixedOffset::east_opt(sign * seconds)
+}
+
+/// Interpret string, enum, or senum parameters as a string, stopping at the first null byte.
+fn par_value_to_string(value: &BrukerParValue) -> Option<String> {
+    match value {
+        BrukerParValue::String(s) => Some(s.clone()),
+        BrukerParValue::Enum(_) | BrukerParValue::Senum(_) => value.enum_code().map(String::from),
+        _ => None,
+    }
+}
+
 #[derive(Debug, PartialEq)]
 struct SpectrumHeaderMetadata {
     magic: i32,
@@ -781,6 +1340,7 @@ impl SpectrumHeaderMetadata {
         let max_size = byte_reader.read_i32(f)? as usize;
         let curr_size = byte_reader.read_i32(f)? as usize;
 
+        f.seek(std::io::SeekFrom::Start(pointer as u64))?;
         let mut blocks = vec![];
         for _ in 0..curr_size {
             let itype = byte_reader.read_i32(f)?;
@@ -807,6 +1367,240 @@ impl SpectrumHeaderMetadata {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_utils::write_test_opus_spectrum;
+
+    #[test]
+    fn test_compute_frequency_grid() {
+        let freq = compute_frequency_grid(4, 10, 0.5);
+        assert_eq!(freq, ndarray::arr1(&[5.0f32, 5.5, 6.0, 6.5]));
+
+        let freq = compute_frequency_grid(3, 0, 2.0);
+        assert_eq!(freq, ndarray::arr1(&[0.0f32, 2.0, 4.0]));
+
+        assert!(compute_frequency_grid(0, 100, 0.01).is_empty());
+    }
+
+    #[test]
+    fn test_convert_8_byte_spectrum() {
+        let values = [1.0f64, -0.25, 1e-3, 12345.5];
+        let be_buf = values.iter().flat_map(|v| v.to_be_bytes()).collect_vec();
+        let le_buf = values.iter().flat_map(|v| v.to_le_bytes()).collect_vec();
+        let expected = ndarray::Array1::from_iter(values.iter().map(|&v| v as f32));
+
+        assert_eq!(
+            SpecBytesToFloat::convert_spectrum(&be_buf, 8).unwrap(),
+            expected
+        );
+        assert_eq!(
+            SpecBytesToFloat::convert_spectrum(&le_buf, -8).unwrap(),
+            expected
+        );
+
+        // A partial word at the end should be an error, not a silent truncation
+        assert!(SpecBytesToFloat::convert_spectrum(&be_buf[..28], 8).is_err());
+    }
+
+    #[test]
+    fn test_write_spectrum_round_trip() {
+        // Similar to the InGaAs detector range of a TCCON spectrum
+        let ifirst = 531_000;
+        let delta_nu = 0.00753;
+        let npts = 400_000;
+        let freq = compute_frequency_grid(npts, ifirst, delta_nu);
+        let spectrum = Spectrum {
+            path: PathBuf::new(),
+            freq: freq.clone(),
+            spec: ndarray::Array1::from_iter((0..npts).map(|i| (i as f32 * 0.01).sin())),
+        };
+
+        let output_dir = crate::test_utils::output_dir("opus-write-spectrum");
+        for bpw in [2i8, -2, 4, -4, 8, -8] {
+            let spec_path = output_dir.join(format!("round_trip_{bpw}.0001"));
+            write_spectrum(&spec_path, &spectrum, bpw, 128).unwrap();
+
+            let header = read_spectrum_header(&spec_path).unwrap();
+            let (ifirst_rt, delta_nu_rt) = header.ifirst_and_delta_nu().unwrap();
+            assert_eq!(ifirst_rt, ifirst);
+            approx::assert_relative_eq!(delta_nu_rt, delta_nu, max_relative = 1e-7);
+
+            let spectrum_rt =
+                read_spectrum(spec_path.clone(), bpw, ifirst_rt, delta_nu_rt, 128).unwrap();
+            approx::assert_relative_eq!(spectrum_rt.freq, freq, max_relative = 1e-6);
+            // The 2-byte integer encoding quantizes the values
+            let eps = if bpw.abs() == 2 { 1e-4 } else { 0.0 };
+            approx::assert_abs_diff_eq!(spectrum_rt.spec, spectrum.spec, epsilon = eps);
+        }
+    }
+
+    #[test]
+    fn test_spectrum_iter_missing() {
+        let runlog_path = crate::test_utils::test_data_dir()
+            .join("inputs/collate-tccon-results/pa_ggg_benchmark.grl");
+        let nrec = runlogs::Runlog::open(&runlog_path).unwrap().count();
+
+        // Place only the first spectrum in the data partition; its header is
+        // just padding since reading relies on the runlog's pointer.
+        let data_dir = crate::test_utils::output_dir("opus-spectrum-iter");
+        let first_rec = runlogs::Runlog::open(&runlog_path).unwrap().next().unwrap();
+        let values = [1.0f32, 0.5, 0.25];
+        let mut buf = vec![0u8; first_rec.pointer as usize];
+        for v in values {
+            buf.extend(v.to_le_bytes());
+        }
+        std::fs::write(data_dir.join(&first_rec.spectrum_name), buf).unwrap();
+        let mut data_part = utils::DataPartition::new_empty();
+        data_part.add_path(data_dir.clone());
+
+        let runlog = runlogs::Runlog::open(&runlog_path).unwrap();
+        let skipped = SpectrumIter::new(runlog, &data_part)
+            .on_missing(MissingSpectrumAction::Skip)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(skipped.len(), 1);
+        let (data_rec, spectrum) = &skipped[0];
+        assert_eq!(data_rec.spectrum_name, first_rec.spectrum_name);
+        assert_eq!(spectrum.spec.to_vec(), values);
+
+        let runlog = runlogs::Runlog::open(&runlog_path).unwrap();
+        let results = SpectrumIter::new(runlog, &data_part).collect_vec();
+        assert_eq!(results.len(), nrec);
+        assert!(results[0].is_ok());
+        assert!(results[1..]
+            .iter()
+            .all(|r| matches!(r, Err(GggError::CouldNotOpen { .. }))));
+    }
+
+    #[test]
+    fn test_enum_as_str() {
+        let dxu = BrukerParValue::Enum(b"WN\0\0".to_vec());
+        assert_eq!(dxu.as_enum_str("DXU"), Some("wavenumber"));
+        assert_eq!(dxu.as_enum_str("DXU2"), Some("wavenumber"));
+        let dxu = BrukerParValue::Enum(b"PNT\0".to_vec());
+        assert_eq!(dxu.as_enum_str("DXU2"), Some("points"));
+
+        let apf = BrukerParValue::Enum(b"B3\0\0".to_vec());
+        assert_eq!(apf.as_enum_str("APF"), Some("Blackman-Harris 3-term"));
+        // The same code means nothing for a different parameter
+        assert_eq!(apf.as_enum_str("DXU"), None);
+
+        let dtc = BrukerParValue::Senum(b"RT-InGaAs [Internal Pos.2]\0\0".to_vec());
+        assert_eq!(dtc.as_enum_str("DTC"), Some("room temperature InGaAs"));
+
+        assert_eq!(
+            BrukerParValue::Enum(b"XYZ\0".to_vec()).as_enum_str("APF"),
+            None
+        );
+        assert_eq!(
+            BrukerParValue::String("WN".to_string()).as_enum_str("DXU"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_write_spectrum_errors() {
+        let spectrum = Spectrum {
+            path: PathBuf::new(),
+            freq: compute_frequency_grid(2, 10, 1.0),
+            spec: ndarray::arr1(&[1.0f32, 1.0]),
+        };
+        let output_dir = crate::test_utils::output_dir("opus-write-spectrum");
+        let spec_path = output_dir.join("bad_pointer.0001");
+        crate::test_utils::remove_file_if_exists(&spec_path).unwrap();
+        // Too small for the header
+        assert!(write_spectrum(&spec_path, &spectrum, 4, 24).is_err());
+        // Not on a word boundary
+        assert!(write_spectrum(&spec_path, &spectrum, 4, 130).is_err());
+        assert!(!spec_path.exists());
+
+        // The first frequency is not an integer multiple of the spacing
+        let spectrum = Spectrum {
+            path: PathBuf::new(),
+            freq: ndarray::arr1(&[10.5f32, 11.5]),
+            spec: ndarray::arr1(&[1.0f32, 1.0]),
+        };
+        assert!(write_spectrum(&spec_path, &spectrum, 4, 128).is_err());
+
+        // Values too large to scale into an i16 must not be silently saturated
+        let spectrum = Spectrum {
+            path: PathBuf::new(),
+            freq: compute_frequency_grid(2, 10, 1.0),
+            spec: ndarray::arr1(&[1.0f32, -2.5]),
+        };
+        for bpw in [2, -2] {
+            let err = write_spectrum(&spec_path, &spectrum, bpw, 128).unwrap_err();
+            assert!(matches!(err, GggError::DataError { .. }), "{err:?}");
+        }
+        assert!(!spec_path.exists());
+        // The same values are fine with floating point encodings
+        write_spectrum(&spec_path, &spectrum, 4, 128).unwrap();
+    }
+
+    #[test]
+    fn test_read_spectrum_header() {
+        let output_dir = crate::test_utils::output_dir("opus-spectrum-header");
+        let spec_path = output_dir.join("header_test.0001");
+        write_test_opus_spectrum(&spec_path, "16:40:12.500 (GMT+0)");
+        let header = read_spectrum_header(&spec_path).unwrap();
+
+        assert_eq!(header.num_blocks(), 3);
+        assert_eq!(header.resolution(), Some(0.02));
+        assert_eq!(header.scan_count(), Some(2));
+        assert_eq!(header.detector_gain().as_deref(), Some("4"));
+        assert_eq!(header.first_frequency(), Some(3800.0));
+        assert_eq!(header.last_frequency(), Some(11000.0));
+        // Not in the test spectrum
+        assert_eq!(header.apodization(), None);
+        assert!(header
+            .get_value(I2sSpectrumHeaderBlockType::FtPar, "APF")
+            .is_err());
+
+        let expected_time = chrono::NaiveDate::from_ymd_opt(2009, 2, 6)
+            .unwrap()
+            .and_hms_milli_opt(16, 40, 12, 500)
+            .unwrap()
+            .and_utc();
+        assert_eq!(
+            header.measurement_time(),
+            Some(expected_time.fixed_offset())
+        );
+    }
+
+    #[test]
+    fn test_measurement_time_offsets() {
+        let output_dir = crate::test_utils::output_dir("opus-spectrum-header");
+        let spec_path = output_dir.join("time_offset_test.0001");
+        let utc_time = chrono::NaiveDate::from_ymd_opt(2009, 2, 6)
+            .unwrap()
+            .and_hms_milli_opt(16, 40, 12, 500)
+            .unwrap()
+            .and_utc();
+        for (tim, expected) in [
+            ("18:40:12.500 (GMT+2)", Some(utc_time)),
+            ("11:10:12.500 (GMT-5:30)", Some(utc_time)),
+            // No zone given is assumed to be UTC
+            ("16:40:12.500", Some(utc_time)),
+            ("16:40:12.500 (PST)", None),
+        ] {
+            write_test_opus_spectrum(&spec_path, tim);
+            let header = read_spectrum_header(&spec_path).unwrap();
+            assert_eq!(
+                header.measurement_time().map(|t| t.to_utc()),
+                expected,
+                "for TIM = {tim}"
+            );
+        }
+    }
+
+    #[test]
+    #[ignore = "Requires GGGPATH with the I2S sample data"]
+    fn test_sample_spectrum_header() {
+        let gggpath = utils::get_ggg_path().unwrap();
+        let header =
+            read_spectrum_header(&gggpath.join("src/i2s/raw_data/wg20090206_1640NIR_DC.0"))
+                .unwrap();
+        assert!(header.resolution().is_some());
+        assert!(header.scan_count().is_some());
+    }
 
     #[test]
     #[ignore = "This test is not complete yet"]
@@ -347,6 +347,25 @@ impl ColRow {
         let key = quantity.column_name(gas);
         self.ret_fields.get(&key).map(|v| *v)
     }
+
+    /// Get the value of any numeric column by its name in the `.col` file, e.g. "Nit",
+    /// "RMS/CL", or "VSF_co2".
+    ///
+    /// Returns `None` if there is no such column or it is not numeric (i.e. "Spectrum").
+    pub fn get(&self, column: &str) -> Option<f64> {
+        match column {
+            "Nit" => Some(self.num_iter as f64),
+            "CL" => Some(self.cont_level),
+            "CT" => Some(self.cont_tilt),
+            "CC" => Some(self.cont_curve),
+            "FS" => Some(self.freq_shift),
+            "SG" => Some(self.sg_stretch),
+            "ZO" => Some(self.zlo),
+            "RMS/CL" => Some(self.rms_over_cl),
+            "Zpres" => Some(self.zpres),
+            _ => self.ret_fields.get(column).copied(),
+        }
+    }
 }
 
 /// An iterator over data rows in a `.col` file; holds the
@@ -392,54 +411,168 @@ impl Iterator for ColRowIter {
 /// Convenience function to open a `.col` file at `path` and return an iterator over
 /// its data rows.
 pub fn open_and_iter_col_file(path: &Path) -> error_stack::Result<ColRowIter, BodyError> {
-    let mut fbuf = FileBuf::open(path).change_context_lazy(|| {
-        BodyError::could_not_read("error opening .col file", Some(path.into()), None, None)
-    })?;
-    let header = read_col_file_header(&mut fbuf).change_context_lazy(|| {
-        BodyError::could_not_read(
-            "error getting information from .col file header",
-            Some(path.into()),
-            None,
-            None,
-        )
-    })?;
+    Ok(ColFile::open(path)?.rows)
+}
 
-    let fmt = fortformat::FortFormat::parse(&header.format).map_err(|e| {
-        BodyError::unexpected_format(
-            format!("unable to parse Fortran format spec: {e}"),
-            Some(path.into()),
-            None,
-            None,
-        )
-    })?;
+/// A `.col` file opened for reading.
+///
+/// This gives access to the header and to the data rows, which can be iterated over
+/// by using this as an iterator. Values in each row can be accessed by column name
+/// with [`ColRow::get`]:
+///
+/// ```no_run
+/// use std::path::Path;
+/// use ggg_rs::readers::col_files::ColFile;
+///
+/// let col_file = ColFile::open(Path::new("co2_6220.pa_ggg_benchmark.col")).unwrap();
+/// println!("Retrieved with {}", col_file.header().gfit_version);
+/// for row in col_file {
+///     let row = row.unwrap();
+///     println!("{}: RMS/CL = {:?}", row.spectrum, row.get("RMS/CL"));
+/// }
+/// ```
+pub struct ColFile {
+    header: ColFileHeader,
+    column_indices: HashMap<String, usize>,
+    rows: ColRowIter,
+}
 
-    let (_, ret_gases) = header.command_line.split_once(':').ok_or_else(|| {
-        BodyError::unexpected_format(
-            "command line is expected to have one colon",
-            Some(path.into()),
-            None,
-            Some(header.command_line.clone()),
-        )
-    })?;
+impl ColFile {
+    /// Open the `.col` file at `path` and read its header.
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be opened, the header cannot be read,
+    /// the Fortran format cannot be parsed, or the primary gas cannot be found
+    /// in the command line in the header.
+    pub fn open(path: &Path) -> error_stack::Result<Self, BodyError> {
+        let mut fbuf = FileBuf::open(path).change_context_lazy(|| {
+            BodyError::could_not_read("error opening .col file", Some(path.into()), None, None)
+        })?;
+        let header = read_col_file_header(&mut fbuf).change_context_lazy(|| {
+            BodyError::could_not_read(
+                "error getting information from .col file header",
+                Some(path.into()),
+                None,
+                None,
+            )
+        })?;
+
+        let fmt = fortformat::FortFormat::parse(&header.format).map_err(|e| {
+            BodyError::unexpected_format(
+                format!("unable to parse Fortran format spec: {e}"),
+                Some(path.into()),
+                None,
+                None,
+            )
+        })?;
 
-    let primary_gas = ret_gases
-        .split_whitespace()
-        .next()
-        .ok_or_else(|| {
+        let (_, ret_gases) = header.command_line.split_once(':').ok_or_else(|| {
             BodyError::unexpected_format(
-                "command line is expected to have at least one gas after the colon",
+                "command line is expected to have one colon",
                 Some(path.into()),
                 None,
                 Some(header.command_line.clone()),
             )
-        })?
-        .to_string();
-
-    Ok(ColRowIter {
-        lines: fbuf.lines(),
-        fmt,
-        colnames: header.column_names,
-        primary_gas,
-        src_path: path.to_path_buf(),
-    })
+        })?;
+
+        let primary_gas = ret_gases
+            .split_whitespace()
+            .next()
+            .ok_or_else(|| {
+                BodyError::unexpected_format(
+                    "command line is expected to have at least one gas after the colon",
+                    Some(path.into()),
+                    None,
+                    Some(header.command_line.clone()),
+                )
+            })?
+            .to_string();
+
+        let column_indices = header
+            .column_names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| (name.clone(), i))
+            .collect();
+
+        let rows = ColRowIter {
+            lines: fbuf.lines(),
+            fmt,
+            colnames: header.column_names.clone(),
+            primary_gas,
+            src_path: path.to_path_buf(),
+        };
+
+        Ok(Self {
+            header,
+            column_indices,
+            rows,
+        })
+    }
+
+    /// The header of this `.col` file
+    pub fn header(&self) -> &ColFileHeader {
+        &self.header
+    }
+
+    /// The names of the data columns, in the order they appear in the file
+    pub fn column_names(&self) -> &[String] {
+        &self.header.column_names
+    }
+
+    /// The 0-based index of the data column named `column`, or `None` if there is no such column
+    pub fn column_index(&self, column: &str) -> Option<usize> {
+        self.column_indices.get(column).copied()
+    }
+
+    /// The primary (i.e. target) gas of this window
+    pub fn primary_gas(&self) -> &str {
+        &self.rows.primary_gas
+    }
+}
+
+impl Iterator for ColFile {
+    type Item = Result<ColRow, GggError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.rows.next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::test_data_dir;
+
+    #[test]
+    fn test_read_col_file() {
+        let col_path = test_data_dir()
+            .join("inputs")
+            .join("collate-tccon-results")
+            .join("hcl_5702.pa_ggg_benchmark.col");
+        let col_file = ColFile::open(&col_path).unwrap();
+
+        assert_eq!(col_file.header().gfit_version.program, "GFIT");
+        assert_eq!(col_file.header().gsetup_version.program, "GSETUP");
+        assert_eq!(col_file.primary_gas(), "hcl");
+        assert_eq!(col_file.column_names().len(), col_file.header().ncol);
+        assert_eq!(col_file.column_index("Spectrum"), Some(0));
+        assert_eq!(col_file.column_index("VSF_hcl"), Some(12));
+        assert_eq!(col_file.column_index("VSF_co2"), None);
+
+        let rows: Vec<_> = col_file.map(|r| r.unwrap()).collect();
+        assert_eq!(rows.len(), 4);
+        let row = &rows[0];
+        assert_eq!(row.spectrum, "pa20040721saaaaa.043");
+        assert_eq!(row.get("Nit"), Some(6.0));
+        assert_eq!(row.get("RMS/CL"), Some(0.1303));
+        assert_eq!(row.get("VSF_hcl"), Some(1.02433));
+        assert_eq!(row.get("VSF_ch4_error"), Some(7.9e-2));
+        assert_eq!(
+            row.get("VSF_hcl"),
+            row.get_primary_gas_quantity(ColRetQuantity::Vsf)
+        );
+        assert_eq!(row.get("Spectrum"), None);
+        assert_eq!(row.get("VSF_co2"), None);
+    }
 }
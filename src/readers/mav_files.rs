@@ -52,12 +52,12 @@ impl MavBlockHeader {
         buf.clear();
         rdr.read_line(&mut buf)
             .map_err(|e| GggError::could_not_read(mav_path.to_path_buf(), e.to_string()))?;
-        let vmr_file = PathBuf::from(&buf);
+        let vmr_file = PathBuf::from(buf.trim());
 
         buf.clear();
         rdr.read_line(&mut buf)
             .map_err(|e| GggError::could_not_read(mav_path.to_path_buf(), e.to_string()))?;
-        let mod_file = PathBuf::from(&buf);
+        let mod_file = PathBuf::from(buf.trim());
 
         let n_extra_lines = nhead.saturating_sub(6);
         for _ in 0..n_extra_lines {
@@ -104,6 +104,12 @@ impl MavBlockHeader {
     }
 }
 
+/// Names of the columns in a `.mav` block that are not gas profiles.
+const MAV_NON_GAS_COLUMNS: [&str; 4] = ["Height", "Temp", "Pres", "Density"];
+
+/// Levels with altitudes below this (in km) are the instrument cell levels, not part of the atmosphere.
+const MAV_CELL_MAX_ALTITUDE: f32 = -1.0;
+
 pub struct MavBlock {
     pub header: MavBlockHeader,
     pub data: HashMap<String, Array1<f32>>,
@@ -111,6 +117,55 @@ pub struct MavBlock {
 }
 
 impl MavBlock {
+    /// Get the profile for the column named `name`, or `None` if there is no such column.
+    ///
+    /// Note that profiles include the instrument cell levels at the start;
+    /// use [`MavBlock::num_cell_levels`] to slice them off.
+    pub fn column(&self, name: &str) -> Option<&Array1<f32>> {
+        self.data.get(name)
+    }
+
+    /// The altitude grid (in km), or `None` if the block has no "Height" column.
+    pub fn altitude(&self) -> Option<&Array1<f32>> {
+        self.column("Height")
+    }
+
+    /// The pressure profile (in atm), or `None` if the block has no "Pres" column.
+    pub fn pressure(&self) -> Option<&Array1<f32>> {
+        self.column("Pres")
+    }
+
+    /// The temperature profile (in K), or `None` if the block has no "Temp" column.
+    pub fn temperature(&self) -> Option<&Array1<f32>> {
+        self.column("Temp")
+    }
+
+    /// The number density profile (in molec. cm-3), or `None` if the block has no "Density" column.
+    pub fn density(&self) -> Option<&Array1<f32>> {
+        self.column("Density")
+    }
+
+    /// The names of the gas profile columns, in the order they appear in the file.
+    pub fn gas_names(&self) -> impl Iterator<Item = &str> {
+        self.column_order
+            .iter()
+            .map(|c| c.as_str())
+            .filter(|c| !MAV_NON_GAS_COLUMNS.contains(c))
+    }
+
+    /// The number of levels at the start of each profile that are for the instrument
+    /// cells rather than the atmosphere. These are identified by their altitudes being
+    /// below -1 km. Returns `None` if the block has no "Height" column.
+    pub fn num_cell_levels(&self) -> Option<usize> {
+        let height = self.altitude()?;
+        Some(
+            height
+                .iter()
+                .filter(|&&z| z < MAV_CELL_MAX_ALTITUDE)
+                .count(),
+        )
+    }
+
     fn parse_from_reader(
         rdr: &mut FileBuf<BufReader<std::fs::File>>,
         mav_path: &Path,
@@ -162,12 +217,30 @@ impl MavBlock {
     }
 }
 
-pub struct MavIterator {
+/// A reader that iterates over the per-spectrum blocks of a `.mav` file.
+///
+/// ```no_run
+/// use std::path::Path;
+/// use ggg_rs::readers::mav_files::MavReader;
+///
+/// let reader = MavReader::open(Path::new("pa_ggg_benchmark.mav")).unwrap();
+/// for block in reader {
+///     let block = block.unwrap();
+///     let co2 = block.column("co2").unwrap();
+///     println!("{}: {} levels", block.header.next_spectrum, co2.len());
+/// }
+/// ```
+pub struct MavReader {
     mav_file_path: PathBuf,
     rdr: FileBuf<BufReader<std::fs::File>>,
 }
 
-impl MavIterator {
+impl MavReader {
+    /// Open the `.mav` file at `mav_file`, ready to iterate over its blocks.
+    pub fn open(mav_file: &Path) -> Result<Self, GggError> {
+        Self::new(mav_file.to_path_buf())
+    }
+
     fn new(mav_file: PathBuf) -> Result<Self, GggError> {
         let mut rdr = FileBuf::open(&mav_file)?;
         let mut buf = String::new();
@@ -180,7 +253,7 @@ impl MavIterator {
     }
 }
 
-impl Iterator for MavIterator {
+impl Iterator for MavReader {
     type Item = error_stack::Result<MavBlock, GggError>;
 
     fn next(&mut self) -> Option<Self::Item> {
@@ -208,6 +281,43 @@ impl Iterator for MavIterator {
     }
 }
 
-pub fn open_and_iter_mav_file(mav_file: PathBuf) -> Result<MavIterator, GggError> {
-    MavIterator::new(mav_file)
+pub fn open_and_iter_mav_file(mav_file: PathBuf) -> Result<MavReader, GggError> {
+    MavReader::new(mav_file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::test_data_dir;
+
+    #[test]
+    fn test_read_mav_blocks() {
+        let mav_path = test_data_dir()
+            .join("inputs")
+            .join("mav-files")
+            .join("pa20040721_two_blocks.mav");
+        let blocks: Vec<_> = MavReader::open(&mav_path)
+            .unwrap()
+            .map(|b| b.unwrap())
+            .collect();
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0].header.next_spectrum, "pa20040721saaaaa.043");
+        assert_eq!(blocks[1].header.next_spectrum, "pa20040721saaaaa.119");
+
+        let block = &blocks[0];
+        assert_eq!(block.header.nlev, 10);
+        assert_eq!(
+            block.header.mod_file,
+            PathBuf::from("/home/tccon/ggg/models/gnd/pa_45N_090W_20040721.mod")
+        );
+        assert_eq!(block.altitude().unwrap().len(), block.header.nlev);
+        assert_eq!(block.num_cell_levels(), Some(2));
+        assert_eq!(block.altitude().unwrap()[2], 0.0);
+        assert_eq!(block.pressure().unwrap()[2], 0.98);
+        assert_eq!(block.temperature().unwrap()[3], 230.0);
+        assert!(block.density().is_some());
+        assert_eq!(block.gas_names().collect::<Vec<_>>(), ["h2o", "co2", "ch4"]);
+        assert_eq!(block.column("co2").unwrap()[5], 3.76e-4);
+        assert!(block.column("n2o").is_none());
+    }
 }
@@ -44,6 +44,8 @@ pub struct AuxData {
     pub osds: f64,
     pub opd: f64,
     pub fovi: f64,
+    // COMPATIBILITY: GGG2014 does not include the angular misalignment
+    #[serde(default = "default_amal")]
     pub amal: f64,
     pub graw: f64,
     pub tins: f64,
@@ -60,6 +62,10 @@ pub struct AuxData {
     pub o2dmf: f64,
 }
 
+fn default_amal() -> f64 {
+    POSTPROC_FILL_VALUE
+}
+
 impl AuxData {
     /// The names of the fields for this struct.
     ///
@@ -79,6 +85,14 @@ impl AuxData {
                 "azim", "osds", "opd", "fovi", "amal", "graw", "tins", "pins", "tout", "pout",
                 "hout", "sia", "fvsi", "wspd", "wdir",
             ],
+
+            // GGG2014 did not have the angular misalignment column, and called the solar
+            // zenith angle "asza" (see [`PostprocFileHeader::read_postproc_file_header`]).
+            GggCompatibility::GGG2014 => &[
+                "spectrum", "year", "day", "hour", "run", "lat", "long", "zobs", "zmin", "solzen",
+                "azim", "osds", "opd", "fovi", "graw", "tins", "pins", "tout", "pout", "hout",
+                "sia", "fvsi", "wspd", "wdir",
+            ],
         }
     }
 
@@ -122,6 +136,14 @@ impl AuxData {
         }
     }
 
+    /// Get the value of one of the non-numeric fields (currently only "spectrum").
+    pub fn get_string_field(&self, field: &str) -> Option<&str> {
+        match field {
+            "spectrum" => Some(&self.spectrum),
+            _ => None,
+        }
+    }
+
     pub fn build_from_runlog_rec(rec: &RunlogDataRec) -> AuxDataBuilder {
         let (dec_year, dec_doy, dec_hour) =
             utils::to_decimal_year_day_hour(rec.year, rec.day, rec.hour);
@@ -331,6 +353,10 @@ impl PostprocRow {
             self.retrieved.get(field).map(|v| *v)
         }
     }
+    /// Get the value of one of the non-numeric fields from the row
+    pub fn get_string_field(&self, field: &str) -> Option<&str> {
+        self.auxiliary.get_string_field(field)
+    }
 }
 
 /// An iterator over data rows in a postprocessing text file; holds the
@@ -562,9 +588,23 @@ pub struct PostprocFileHeader {
     pub missing_value: f64,
     pub fformat: FortFormat,
     pub column_names: Vec<String>,
+    /// Which GGG version's header layout this file follows. Files from GGG2020 and later
+    /// that include the O2 DMF column are [`GggCompatibility::Current`].
+    pub compat: GggCompatibility,
 }
 
+/// The name of the solar zenith angle column in GGG2014 post processing files.
+const GGG2014_SZA_COLUMN: &str = "asza";
+
 impl PostprocFileHeader {
+    /// Read the header of a post processing file from `file`.
+    ///
+    /// This also handles files with the GGG2014 header layout, which is detected by the
+    /// solar zenith angle column being named "asza". For those files:
+    ///
+    /// - the "asza" column name is changed to "solzen", so that rows can be read as [`AuxData`],
+    /// - if there is no "missing:" line, the missing value is assumed to be [`POSTPROC_FILL_VALUE`], and
+    /// - `compat` is set to [`GggCompatibility::GGG2014`].
     pub fn read_postproc_file_header<F: BufRead>(
         file: &mut FileBuf<F>,
     ) -> error_stack::Result<Self, HeaderError> {
@@ -618,21 +658,50 @@ impl PostprocFileHeader {
             }
         }
 
-        let missing_value = missing_value.ok_or_else(|| HeaderError::ParseError {
-            location: file.path.as_path().into(),
-            cause: "The 'missing:' line was not found".into(),
-        })?;
-
         let fformat = fformat.ok_or_else(|| HeaderError::ParseError {
             location: file.path.as_path().into(),
             cause: "The 'format:' line was not found".into(),
         })?;
 
-        let column_names = column_names.ok_or_else(|| HeaderError::ParseError {
+        let mut column_names = column_names.ok_or_else(|| HeaderError::ParseError {
             location: file.path.as_path().into(),
             cause: "The column names were not found".into(),
         })?;
 
+        let sza_index = column_names[..naux.min(column_names.len())]
+            .iter()
+            .position(|c| c == GGG2014_SZA_COLUMN);
+        let compat = if sza_index.is_some() {
+            GggCompatibility::GGG2014
+        } else if column_names[..naux.min(column_names.len())]
+            .iter()
+            .any(|c| c == "o2dmf")
+        {
+            GggCompatibility::Current
+        } else {
+            GggCompatibility::GGG2020
+        };
+
+        if let Some(i) = sza_index {
+            column_names[i] = "solzen".to_string();
+        }
+
+        // GGG2014 files did not always have a "missing:" line, later files must.
+        let missing_value = match (missing_value, compat) {
+            (Some(v), _) => v,
+            (None, GggCompatibility::GGG2014) => {
+                debug!("No 'missing:' line in the GGG2014 header, assuming the missing value is {POSTPROC_FILL_VALUE}");
+                POSTPROC_FILL_VALUE
+            }
+            (None, _) => {
+                return Err(HeaderError::ParseError {
+                    location: file.path.as_path().into(),
+                    cause: "The 'missing:' line was not found".into(),
+                }
+                .into())
+            }
+        };
+
         Ok(Self {
             nhead,
             ncol,
@@ -643,6 +712,7 @@ impl PostprocFileHeader {
             extra_lines,
             fformat,
             column_names,
+            compat,
         })
     }
 
@@ -1036,4 +1106,69 @@ mod tests {
         let rec = f.next_data_record().unwrap();
         approx::assert_abs_diff_eq!(rec, ex_rec_1)
     }
+
+    #[test]
+    fn test_read_ggg2014_header() {
+        let vav_file = test_data_dir()
+            .join("inputs")
+            .join("postproc-files")
+            .join("pa_ggg2014_style.vav");
+        let (header, rows) = open_and_iter_postproc_file(&vav_file).unwrap();
+
+        assert_eq!(header.compat, GggCompatibility::GGG2014);
+        assert_eq!(header.naux, 24);
+        assert_eq!(
+            &header.column_names[..header.naux],
+            AuxData::postproc_fields_str(GggCompatibility::GGG2014)
+        );
+        assert_eq!(
+            header.program_versions.keys().collect_vec(),
+            ["collate_results", "GFIT", "GSETUP"]
+        );
+        assert_eq!(header.program_versions["GFIT"].version, "Version 4.8.6");
+        approx::assert_abs_diff_eq!(header.missing_value, POSTPROC_FILL_VALUE);
+
+        let rows: Vec<_> = rows.map(|r| r.unwrap()).collect();
+        assert_eq!(rows.len(), header.nrec);
+        let row = &rows[1];
+        assert_eq!(row.auxiliary.spectrum, "pa20040721saaaaa.119");
+        approx::assert_abs_diff_eq!(row.auxiliary.solzen, 63.799);
+        approx::assert_abs_diff_eq!(row.auxiliary.wdir, 139.0);
+        assert!(is_postproc_fill(row.auxiliary.amal));
+        approx::assert_abs_diff_eq!(row.retrieved["xco2_6220"], 3.7702e-4);
+        assert!(is_postproc_fill(row.retrieved["xch4_5938"]));
+    }
+
+    #[test]
+    fn test_missing_value_required_after_ggg2014() {
+        // Remove the "missing:" line from a current file; only GGG2014 files may omit it.
+        let vsw_file = test_data_dir()
+            .join("expected")
+            .join("collate-tccon-results")
+            .join("pa_ggg_benchmark.vsw");
+        let contents = std::fs::read_to_string(&vsw_file).unwrap();
+        let mut lines = contents.lines();
+        let mut shape: Vec<usize> = lines
+            .next()
+            .unwrap()
+            .split_whitespace()
+            .map(|v| v.parse().unwrap())
+            .collect();
+        shape[0] -= 1;
+        let mut new_contents = format!("{}\n", shape.iter().join(" "));
+        for line in lines.filter(|l| !l.starts_with("missing:")) {
+            new_contents.push_str(line);
+            new_contents.push('\n');
+        }
+
+        let output_dir = crate::test_utils::output_dir("postproc-no-missing-value");
+        let no_missing_file = output_dir.join("postproc-no-missing-value.vsw");
+        std::fs::write(&no_missing_file, new_contents).unwrap();
+        let mut fbuf = FileBuf::open(&no_missing_file).unwrap();
+        let err = PostprocFileHeader::read_postproc_file_header(&mut fbuf).unwrap_err();
+        assert!(
+            err.to_string().contains("'missing:' line was not found"),
+            "{err:?}"
+        );
+    }
 }
@@ -1,12 +1,14 @@
 //! Utilities for reading runlogs and iterating over their data records.
-use std::{fs::File, io::BufReader, path::Path};
+use std::{collections::HashMap, fs::File, io::BufReader, path::Path};
 
-use fortformat::de::from_str_with_fields;
+use fortformat::{de::from_str_with_fields, FortFormat};
 use itertools::Itertools;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 use crate::{
+    cit_spectrum_name::NoDetectorSpecName,
     error::HeaderError,
+    readers::ProgramVersion,
     utils::{self, GggError},
 };
 
@@ -91,7 +93,8 @@ pub struct RunlogDataRec {
     /// Apodization function
     #[serde(
         rename = "APF",
-        deserialize_with = "utils::ApodizationFxn::deserialize"
+        deserialize_with = "utils::ApodizationFxn::deserialize",
+        serialize_with = "ser_apf"
     )]
     pub apf: utils::ApodizationFxn,
     /// Instrument internal temperature in deg. C
@@ -290,6 +293,109 @@ where
     }
 }
 
+fn ser_apf<S>(value: &utils::ApodizationFxn, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_str(&value.to_string())
+}
+
+/// The full header of a runlog.
+///
+/// Unlike the [`utils::CommonHeader`] in [`Runlog::header`], this keeps the header
+/// lines that are not needed to read the data, such as the `create_runlog` version.
+#[derive(Debug, Clone)]
+pub struct RunlogHeader {
+    /// The number of header lines, according to the first line
+    pub nhead: usize,
+    /// The number of data columns, according to the first line
+    pub ncol: usize,
+    /// The header lines between the first line and the format line, without their newlines.
+    pub extra_lines: Vec<String>,
+    /// The versions of any programs listed in `extra_lines`, e.g. `create_runlog`
+    pub program_versions: Vec<ProgramVersion>,
+    /// The data format string, without the leading "format="
+    pub format_str: String,
+    /// The Fortran format that describes each line of data
+    pub fformat: FortFormat,
+    /// The data column names
+    pub column_names: Vec<String>,
+}
+
+impl RunlogHeader {
+    /// Read the header from the runlog at `runlog`.
+    ///
+    /// # Returns
+    /// A [`Result`] containing the header. An `Err` is returned if the file could not be
+    /// read, the first line could not be parsed, there is no format line, or the format
+    /// cannot be parsed.
+    pub fn read(runlog: &Path) -> Result<Self, GggError> {
+        let mut f = utils::FileBuf::open(runlog)?;
+        Self::read_from(&mut f)
+    }
+
+    /// Read the header from an open runlog, leaving `f` at the first line of data.
+    fn read_from(f: &mut utils::FileBuf<BufReader<File>>) -> Result<Self, GggError> {
+        let (nhead, ncol) = utils::get_nhead_ncol(f)?;
+
+        let mut extra_lines = vec![];
+        let mut format_str = None;
+        // The first line has already been read, and the last is the column names.
+        for _ in 1..nhead.saturating_sub(1) {
+            let line = f.read_header_line()?;
+            let line = line.trim_end_matches(['\n', '\r']);
+            if let Some(fmt) = line.strip_prefix("format=") {
+                format_str = Some(fmt.trim().to_string());
+            } else {
+                extra_lines.push(line.to_string());
+            }
+        }
+
+        let format_str = format_str.ok_or_else(|| HeaderError::ParseError {
+            location: f.path.as_path().into(),
+            cause: "No format line found in the header".to_string(),
+        })?;
+        let fformat = FortFormat::parse(&format_str).map_err(|e| HeaderError::ParseError {
+            location: f.path.as_path().into(),
+            cause: format!("Error parsing format line: {e}"),
+        })?;
+
+        let column_names = f
+            .read_header_line()?
+            .split_whitespace()
+            .map(|s| s.to_string())
+            .collect();
+
+        let program_versions = extra_lines
+            .iter()
+            .filter_map(|l| l.parse::<ProgramVersion>().ok())
+            .collect();
+
+        Ok(Self {
+            nhead,
+            ncol,
+            extra_lines,
+            program_versions,
+            format_str,
+            fformat,
+            column_names,
+        })
+    }
+
+    /// The parts of this header that [`utils::read_common_header`] would return, so that
+    /// [`Runlog::open`] does not need to parse the header twice.
+    fn common_header(&self) -> utils::CommonHeader {
+        utils::CommonHeader {
+            nhead: self.nhead,
+            ncol: self.ncol,
+            // Runlogs do not have a "missing:" header line
+            missing: None,
+            fformat: Some(self.fformat.clone()),
+            column_names: self.column_names.clone(),
+        }
+    }
+}
+
 /// An iterator over lines in a runlog.
 ///
 /// Use the `open` method to create an instance of this struct. The common
@@ -338,10 +444,38 @@ where
 /// ```
 ///
 /// Alternatively, use a [`FallibleRunlog`] instead.
+///
+/// The full runlog header, including lines not needed to read the data, is available
+/// from [`Runlog::runlog_header`].
 pub struct Runlog {
     pub header: utils::CommonHeader,
+    runlog_header: RunlogHeader,
     rl_handle: utils::FileBuf<BufReader<File>>,
     data_line_index: usize,
+    record_index: Option<RunlogIndex>,
+    duplicate_check: Option<DuplicateCheck>,
+}
+
+/// What a [`Runlog`] opened with [`Runlog::open_checked`] should do when it finds
+/// a spectrum name that was already in the runlog.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicateAction {
+    /// Return a [`GggError::DuplicateSpectrum`] error for the duplicate record.
+    Error,
+    /// Log a warning and return the duplicate record as normal.
+    Warn,
+}
+
+/// The spectrum names seen so far in a runlog and the line each was first seen on.
+struct DuplicateCheck {
+    action: DuplicateAction,
+    seen: HashMap<String, usize>,
+}
+
+/// In-memory copy of a runlog's (uncommented) data records, used by [`Runlog::get_record`].
+struct RunlogIndex {
+    records: Vec<RunlogDataRec>,
+    by_name: HashMap<String, usize>,
 }
 
 impl Runlog {
@@ -359,36 +493,72 @@ impl Runlog {
     /// * the number of columns specified in the header does not match the expected number, [`NUM_RUNLOG_COLS`]
     pub fn open(runlog: &Path) -> Result<Runlog, GggError> {
         let mut rl = utils::FileBuf::open(runlog)?;
-        let header = utils::read_common_header(&mut rl)?;
-        if header.ncol != NUM_RUNLOG_COLS {
-            return Err(HeaderError::ParseError { 
-                location: runlog.into(), 
+        let runlog_header = RunlogHeader::read_from(&mut rl)?;
+        if runlog_header.ncol != NUM_RUNLOG_COLS {
+            return Err(HeaderError::ParseError {
+                location: runlog.into(),
                 cause: format!("Number of columns specified in the header of runlog {} is not the expected number, {}",
-                               header.ncol, NUM_RUNLOG_COLS)
+                               runlog_header.ncol, NUM_RUNLOG_COLS)
             }.into());
         }
 
-        if header.fformat.is_none() {
-            return Err(GggError::HeaderError(HeaderError::ParseError {
+        let ncol_names = runlog_header.column_names.len();
+        if ncol_names != runlog_header.ncol {
+            return Err(HeaderError::ParseError {
                 location: runlog.into(),
-                cause: "No format line found in the header".to_string(),
-            }));
+                cause: format!("number of column names ({ncol_names}) does not equal the number of columns listed in the first line of the header ({})", runlog_header.ncol),
+            }.into());
         }
+        let header = runlog_header.common_header();
 
         // At this point, the file handle will be pointing to the first line of data in the runlog
         Ok(Runlog {
             rl_handle: rl,
             header,
+            runlog_header,
             data_line_index: 0,
+            record_index: None,
+            duplicate_check: None,
         })
     }
 
+    /// Open a runlog file that checks for duplicate spectrum names while iterating.
+    ///
+    /// This is the same as [`Runlog::open`], except that each uncommented record returned by
+    /// [`Runlog::next_data_record`] (or the iterators built on it) is checked against the
+    /// spectrum names already read. If a name repeats, `action` determines whether that
+    /// is an error or a warning. Only the names (not the records) are kept, so this does not
+    /// require holding the whole runlog in memory.
+    ///
+    /// ```no_run
+    /// use std::path::PathBuf;
+    /// use ggg_rs::readers::runlogs::{DuplicateAction, Runlog};
+    /// let runlog_path = PathBuf::from("~/ggg/runlogs/gnd/pa_ggg_benchmark.grl");
+    /// let mut runlog = Runlog::open_checked(&runlog_path, DuplicateAction::Error).unwrap();
+    /// while let Some(rec) = runlog.next_data_record(false).unwrap() {
+    ///     // each rec.spectrum_name is guaranteed to be unique
+    /// }
+    /// ```
+    pub fn open_checked(runlog: &Path, action: DuplicateAction) -> Result<Runlog, GggError> {
+        let mut rl = Self::open(runlog)?;
+        rl.duplicate_check = Some(DuplicateCheck {
+            action,
+            seen: HashMap::new(),
+        });
+        Ok(rl)
+    }
+
+    /// Return the full header of this runlog.
+    pub fn runlog_header(&self) -> &RunlogHeader {
+        &self.runlog_header
+    }
+
     /// Return which line in the file was last read.
     ///
     /// This is 1-based, and counts from the top of the file (including the header). It is meant
     /// for error messages to help the user identify where their runlog is ill-formatted.
     pub fn curr_line(&self) -> usize {
-        self.header.nhead + self.data_line_index
+        self.runlog_header.nhead + self.data_line_index
     }
 
     /// Get the next data record from the runlog, if one exists.
@@ -399,7 +569,9 @@ impl Runlog {
     ///
     /// * the next data line could not be read,
     /// * the number of elements in the line does not match the number of columns,
-    /// * any of the elements in the line could not be converted to the proper Rust type
+    /// * any of the elements in the line could not be converted to the proper Rust type,
+    /// * this runlog was opened with [`Runlog::open_checked`] using [`DuplicateAction::Error`]
+    ///   and the record's spectrum name is a duplicate.
     pub fn next_data_record(
         &mut self,
         keep_commented: bool,
@@ -439,10 +611,106 @@ impl Runlog {
                     cause: format!("Error deserializing line #{}: {e}", self.curr_line()),
                 })?;
             data_rec.file_line_num = self.curr_line();
+            if !data_rec.commented {
+                self.check_duplicate(&data_rec)?;
+            }
 
             return Ok(Some(data_rec));
         }
     }
+
+    fn check_duplicate(&mut self, rec: &RunlogDataRec) -> Result<(), GggError> {
+        let Some(check) = self.duplicate_check.as_mut() else {
+            return Ok(());
+        };
+
+        let Some(&first_line) = check.seen.get(&rec.spectrum_name) else {
+            check
+                .seen
+                .insert(rec.spectrum_name.clone(), rec.file_line_num);
+            return Ok(());
+        };
+
+        match check.action {
+            DuplicateAction::Error => Err(GggError::DuplicateSpectrum {
+                path: self.rl_handle.path.clone(),
+                spectrum: rec.spectrum_name.clone(),
+                first_line,
+                line: rec.file_line_num,
+            }),
+            DuplicateAction::Warn => {
+                log::warn!(
+                    "Spectrum {} on line {} of runlog {} is a duplicate of line {first_line}",
+                    rec.spectrum_name,
+                    rec.file_line_num,
+                    self.rl_handle.path.display()
+                );
+                Ok(())
+            }
+        }
+    }
+
+    /// Look up the data record for a single spectrum by name.
+    ///
+    /// If `ignore_detector` is `true`, then any spectrum whose name matches `spectrum_name`
+    /// except for the detector character will match, and the first such spectrum in the
+    /// runlog is returned. This requires both names to follow the CIT spectrum naming
+    /// convention; if `spectrum_name` does not, this returns `Ok(None)`.
+    ///
+    /// The first call reads all uncommented data records into memory and indexes them by
+    /// spectrum name, so that later calls do not need to read the file again. The index is
+    /// built from a separate handle to the runlog, so calling this does not affect where
+    /// iteration over this `Runlog` is, nor vice versa. If a spectrum is listed more than
+    /// once, the first occurrence is returned.
+    ///
+    /// # Returns
+    /// A [`Result`] containing the matching record, or `None` if no record matches. An `Err` is
+    /// returned if building the index fails for any of the reasons [`Runlog::open`] or
+    /// [`Runlog::next_data_record`] can fail.
+    pub fn get_record(
+        &mut self,
+        spectrum_name: &str,
+        ignore_detector: bool,
+    ) -> Result<Option<RunlogDataRec>, GggError> {
+        if self.record_index.is_none() {
+            self.record_index = Some(self.build_index()?);
+        }
+        let index = self
+            .record_index
+            .as_ref()
+            .expect("runlog record index should have been built");
+
+        if let Some(&i) = index.by_name.get(spectrum_name) {
+            return Ok(Some(index.records[i].clone()));
+        }
+
+        if !ignore_detector {
+            return Ok(None);
+        }
+
+        let Ok(target) = NoDetectorSpecName::new(spectrum_name) else {
+            return Ok(None);
+        };
+        let rec = index.records.iter().find(|rec| {
+            NoDetectorSpecName::new(&rec.spectrum_name)
+                .map(|name| name == target)
+                .unwrap_or(false)
+        });
+        Ok(rec.cloned())
+    }
+
+    fn build_index(&self) -> Result<RunlogIndex, GggError> {
+        let mut rl = Runlog::open(&self.rl_handle.path)?;
+        let mut records = vec![];
+        let mut by_name = HashMap::new();
+        while let Some(rec) = rl.next_data_record(false)? {
+            by_name
+                .entry(rec.spectrum_name.clone())
+                .or_insert(records.len());
+            records.push(rec);
+        }
+        Ok(RunlogIndex { records, by_name })
+    }
 }
 
 impl Iterator for Runlog {
@@ -455,7 +723,7 @@ impl Iterator for Runlog {
             Ok(rec) => rec,
             Err(e) => panic!(
                 "Error while reading line {} of runlog at {}: {e}",
-                self.header.nhead + self.data_line_index,
+                self.curr_line(),
                 self.rl_handle.path.display()
             ),
         }
@@ -499,10 +767,24 @@ impl FallibleRunlog {
         Ok(Self { runlog: rl })
     }
 
+    /// Open a runlog file as a `FallibleRunlog` iterator that checks for duplicate spectrum
+    /// names. See [`Runlog::open_checked`] for details.
+    pub fn open_checked(
+        runlog: &Path,
+        action: DuplicateAction,
+    ) -> Result<FallibleRunlog, GggError> {
+        let rl = Runlog::open_checked(runlog, action)?;
+        Ok(Self { runlog: rl })
+    }
+
     pub fn header(&self) -> &crate::utils::CommonHeader {
         &self.runlog.header
     }
 
+    pub fn runlog_header(&self) -> &RunlogHeader {
+        self.runlog.runlog_header()
+    }
+
     pub fn into_line_iter(self) -> FallibleRunlogLineIter {
         FallibleRunlogLineIter {
             runlog: self.runlog,
@@ -554,6 +836,80 @@ mod tests {
             .join("pa_ggg_benchmark.grl")
     }
 
+    #[rstest]
+    fn test_runlog_header(benchmark_rl_path: PathBuf) {
+        let runlog = Runlog::open(&benchmark_rl_path).unwrap();
+        let header = runlog.runlog_header();
+        assert_eq!(header.nhead, 4);
+        assert_eq!(header.ncol, NUM_RUNLOG_COLS);
+        assert_eq!(
+            header.extra_lines,
+            [" CREATE_RUNLOG    Version 8.78     2019-08-22    GCT            "]
+        );
+        assert_eq!(
+            header.program_versions,
+            [ProgramVersion {
+                program: "CREATE_RUNLOG".to_string(),
+                version: "Version 8.78".to_string(),
+                date: "2019-08-22".to_string(),
+                authors: "GCT".to_string(),
+            }]
+        );
+        assert!(header.format_str.starts_with("(a1,a57,1x,2i4,f8.4,"));
+        assert_eq!(header.fformat.non_pos_len(), NUM_RUNLOG_COLS + 1);
+        assert_eq!(header.column_names.len(), NUM_RUNLOG_COLS);
+        assert_eq!(header.column_names[0], "Spectrum_File_Name");
+        assert_eq!(header.column_names[NUM_RUNLOG_COLS - 1], "aipl");
+
+        // The common header is derived from the full one rather than parsed separately
+        let common = &runlog.header;
+        assert_eq!(common.nhead, header.nhead);
+        assert_eq!(common.ncol, header.ncol);
+        assert_eq!(common.column_names, header.column_names);
+        assert!(common.fformat.is_some());
+        assert_eq!(runlog.curr_line(), 4);
+    }
+
+    #[rstest]
+    fn test_runlog_duplicate_check(benchmark_rl_path: PathBuf) {
+        // Make a copy of the benchmark runlog with the first data line repeated at the end
+        let mut contents = std::fs::read_to_string(&benchmark_rl_path).unwrap();
+        let first_data_line = contents.lines().nth(4).unwrap().to_string();
+        contents.push_str(&first_data_line);
+        contents.push('\n');
+        let output_dir = crate::test_utils::output_dir("runlog-duplicate-check");
+        let dup_rl_path = output_dir.join("duplicate-spectrum-test.grl");
+        std::fs::write(&dup_rl_path, contents).unwrap();
+
+        // Unchecked and warning runlogs should return all records
+        let n_unchecked = Runlog::open(&dup_rl_path).unwrap().count();
+        let n_warn = Runlog::open_checked(&dup_rl_path, DuplicateAction::Warn)
+            .unwrap()
+            .count();
+
+        let results: Vec<_> = FallibleRunlog::open_checked(&dup_rl_path, DuplicateAction::Error)
+            .unwrap()
+            .collect();
+
+        assert_eq!(n_unchecked, 9);
+        assert_eq!(n_warn, 9);
+        assert_eq!(results.len(), 9);
+        assert!(results[..8].iter().all(|r| r.is_ok()));
+        match results.last().unwrap() {
+            Err(GggError::DuplicateSpectrum {
+                spectrum,
+                first_line,
+                line,
+                ..
+            }) => {
+                assert_eq!(spectrum, "pa20040721saaaaa.043");
+                assert_eq!(*first_line, 5);
+                assert_eq!(*line, 13);
+            }
+            other => panic!("Expected a duplicate spectrum error, got {other:?}"),
+        }
+    }
+
     #[rstest]
     fn test_runlog_data(benchmark_rl_path: PathBuf) {
         let data_rec_1a = RunlogDataRec {
@@ -619,6 +975,41 @@ mod tests {
         approx::assert_abs_diff_eq!(test_rec, data_rec_1b);
     }
 
+    #[rstest]
+    fn test_get_record(benchmark_rl_path: PathBuf) {
+        let mut rl = Runlog::open(&benchmark_rl_path).unwrap();
+
+        let rec = rl
+            .get_record("pa20041222saaaab.019", false)
+            .expect("looking up a record should not error")
+            .expect("record should be found");
+        assert_eq!(rec.spectrum_name, "pa20041222saaaab.019");
+        assert_eq!(rec.year, 2004);
+        assert_eq!(rec.day, 357);
+
+        // There is no "c" spectrum, but ignoring the detector should match the first
+        // spectrum from the same observation.
+        assert!(rl
+            .get_record("pa20041222saaaac.019", false)
+            .unwrap()
+            .is_none());
+        let rec = rl
+            .get_record("pa20041222saaaac.019", true)
+            .expect("looking up a record should not error")
+            .expect("record should be found when ignoring the detector");
+        assert_eq!(rec.spectrum_name, "pa20041222saaaaa.019");
+
+        assert!(rl
+            .get_record("pa20041223saaaaa.019", true)
+            .unwrap()
+            .is_none());
+        assert!(rl.get_record("not_a_spectrum", true).unwrap().is_none());
+
+        // Lookups should not have affected iteration
+        let first = rl.next_data_record(false).unwrap().unwrap();
+        assert_eq!(first.spectrum_name, "pa20040721saaaaa.043");
+    }
+
     #[rstest]
     fn test_zpd_time_conversion() {
         let mut data_rec = RunlogDataRec {
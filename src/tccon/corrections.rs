@@ -0,0 +1,701 @@
+//! Airmass and in situ corrections applied to Xgas values during post processing.
+//!
+//! These are the core of the `apply_tccon_airmass_correction` and `apply_tccon_insitu_correction`
+//! programs, kept here so that they can also be chained in memory (as `apply_tccon_corrections`
+//! does) without writing an intermediate file.
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use error_stack::ResultExt;
+use fortformat::FortFormat;
+use indexmap::IndexMap;
+
+use crate::readers::{postproc_files::PostprocFileHeader, ProgramVersion};
+
+use super::input_config::{AdcfRow, AicfRow};
+
+/// Value of the airmass correction `g` parameter to use if nothing else specifies one.
+pub const DEFAULT_G: f64 = 0.0;
+/// Value of the airmass correction `p` parameter to use if nothing else specifies one.
+pub const DEFAULT_P: f64 = 0.0;
+
+/// Program that writes window-averaged (`.Xav`) files.
+const AVERAGING_PROGRAM: &str = "average_results";
+/// Programs that write un-averaged (`.Xsw`) files.
+const COLLATION_PROGRAMS: [&str; 2] = ["collate_tccon_results", "collate_results"];
+
+/// Minimum width of the gas name field in the AICF header lines, matching the Fortran
+/// `apply_insitu_correction`. Longer gas names widen the field for all lines.
+const MIN_AICF_GAS_WIDTH: usize = 23;
+
+#[derive(Debug, thiserror::Error)]
+pub enum CorrectionError {
+    /// A file needed for the corrections could not be read.
+    #[error("Error reading {}", .0.display())]
+    ReadError(PathBuf),
+    /// Any other problem, described by the message.
+    #[error("{0}")]
+    Custom(String),
+}
+
+impl CorrectionError {
+    fn custom<S: ToString>(msg: S) -> Self {
+        Self::Custom(msg.to_string())
+    }
+}
+
+/// The program version that `apply_tccon_airmass_correction` records in its output header.
+pub fn airmass_correction_version() -> ProgramVersion {
+    ProgramVersion {
+        program: "apply_tccon_airmass_correction".to_string(),
+        version: "Version 1.0".to_string(),
+        date: "2024-09-30".to_string(),
+        authors: "JLL".to_string(),
+    }
+}
+
+/// The program version that `apply_tccon_insitu_correction` records in its output header.
+pub fn insitu_correction_version() -> ProgramVersion {
+    ProgramVersion {
+        program: "apply_tccon_insitu_correction".to_string(),
+        version: "Version 1.0".to_string(),
+        date: "2025-03-31".to_string(),
+        authors: "JLL".to_string(),
+    }
+}
+
+/// Default `g` and `p` values as read from a shape defaults TOML file.
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ShapeDefaultsFile {
+    /// Defaults for the O2 window used to compute the dry air column, whatever its name.
+    o2: Option<ShapeDefaultValues>,
+    /// Defaults keyed by Xgas window name (e.g. "xco2_6220") or Xgas (e.g. "xco2").
+    #[serde(default)]
+    gases: HashMap<String, ShapeDefaultValues>,
+}
+
+#[derive(Debug, Clone, Copy, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ShapeDefaultValues {
+    g: f64,
+    p: f64,
+}
+
+impl ShapeDefaultsFile {
+    pub fn read(toml_file: &Path) -> error_stack::Result<Self, CorrectionError> {
+        let buf = std::fs::read_to_string(toml_file)
+            .change_context_lazy(|| CorrectionError::ReadError(toml_file.to_path_buf()))?;
+        let defaults = toml::from_str(&buf).change_context_lazy(|| {
+            CorrectionError::custom(format!(
+                "Error parsing shape defaults file: {}",
+                toml_file.display()
+            ))
+        })?;
+        Ok(defaults)
+    }
+}
+
+/// Default `g` and `p` values for one gas or window.
+#[derive(Debug, Clone)]
+pub struct GasShapeDefault {
+    pub gas: String,
+    pub g: f64,
+    pub p: f64,
+}
+
+impl FromStr for GasShapeDefault {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (gas, values) = s
+            .split_once('=')
+            .ok_or_else(|| format!("Gas default '{s}' must be given as GAS=G,P"))?;
+        let (g, p) = values
+            .split_once(',')
+            .ok_or_else(|| format!("Gas default '{s}' must be given as GAS=G,P"))?;
+        let g = g
+            .trim()
+            .parse::<f64>()
+            .map_err(|e| format!("Could not parse g value '{g}' in '{s}': {e}"))?;
+        let p = p
+            .trim()
+            .parse::<f64>()
+            .map_err(|e| format!("Could not parse p value '{p}' in '{s}': {e}"))?;
+        Ok(Self {
+            gas: gas.trim().to_string(),
+            g,
+            p,
+        })
+    }
+}
+
+/// The `g` and `p` values to use for windows whose ADCF row omits them.
+#[derive(Debug, Clone)]
+pub struct ShapeDefaults {
+    g: f64,
+    p: f64,
+    per_gas: HashMap<String, (f64, f64)>,
+    /// Default for the O2 window, and that window's Xgas name once it is known.
+    o2: Option<(f64, f64)>,
+    o2_xgas_key: Option<String>,
+}
+
+impl ShapeDefaults {
+    pub fn new(g: f64, p: f64, gas_defaults: &[GasShapeDefault]) -> Self {
+        let per_gas = gas_defaults
+            .iter()
+            .map(|d| (d.gas.clone(), (d.g, d.p)))
+            .collect();
+        Self {
+            g,
+            p,
+            per_gas,
+            o2: None,
+            o2_xgas_key: None,
+        }
+    }
+
+    /// Add the defaults from a shape defaults file. Any gas or window that
+    /// already has a default keeps it.
+    pub fn with_file_defaults(mut self, file_defaults: ShapeDefaultsFile) -> Self {
+        for (gas, v) in file_defaults.gases {
+            self.per_gas.entry(gas).or_insert((v.g, v.p));
+        }
+        self.o2 = file_defaults.o2.map(|v| (v.g, v.p));
+        self
+    }
+
+    /// Record which window is the O2 window, given its name in the upstream file
+    /// (e.g. "o2_7885"), so that the O2 default can be applied to it.
+    pub fn set_o2_window(&mut self, o2_window: &str) {
+        self.o2_xgas_key = Some(format!("x{o2_window}"));
+    }
+
+    /// Get the `(g, p)` values for `xgas_key`, preferring those in its ADCF row,
+    /// then the O2 default if this is the O2 window, then a default for that
+    /// window, then for its gas, then the global defaults.
+    pub fn g_p(&self, xgas_key: &str, adcf: Option<&AdcfRow>) -> (f64, f64) {
+        let gas = xgas_key.split('_').next().unwrap_or(xgas_key);
+        let o2_default = self
+            .o2
+            .filter(|_| self.o2_xgas_key.as_deref() == Some(xgas_key));
+        let (default_g, default_p) = o2_default
+            .or_else(|| self.per_gas.get(xgas_key).copied())
+            .or_else(|| self.per_gas.get(gas).copied())
+            .unwrap_or((self.g, self.p));
+        let g = adcf.and_then(|a| a.g).unwrap_or(default_g);
+        let p = adcf.and_then(|a| a.p).unwrap_or(default_p);
+        (g, p)
+    }
+}
+
+/// Find the O2 window among the column names of a post processing file with `naux`
+/// auxiliary columns, before the "x" has been prepended to the retrieved column names.
+pub fn find_o2_window(column_names: &[String], naux: usize) -> Result<String, CorrectionError> {
+    // This looks complicated, but all it's doing is finding the first window name that starts
+    // with "o2" and is not a column error amount or the O2 mean DMF auxiliary column.
+    column_names[naux - 1..]
+        .iter()
+        .fold(None, |acc, name| {
+            if !name.starts_with("o2") | name.ends_with("_error") | name.ends_with("dmf") {
+                acc
+            } else if name.starts_with("o2") && acc.is_none() {
+                Some(Ok(name.to_string()))
+            } else if acc.as_ref().is_some_and(|r| r.is_ok()) {
+                let other = acc.unwrap().unwrap();
+                Some(Err(CorrectionError::custom(format!(
+                    "multiple O2 windows found: '{name}' and '{other}'"
+                ))))
+            } else {
+                acc
+            }
+        })
+        .ok_or_else(|| CorrectionError::custom("could not find O2 window"))?
+}
+
+/// Determine whether the upstream file has window-averaged values, which controls whether
+/// the O2 column uncertainty is included in the Xgas errors.
+///
+/// The program versions in the header are checked first: if `average_results` has been run,
+/// the file is averaged, otherwise if it was collated, it is not. Only if neither program is
+/// listed does this fall back on the file extension ending in "sw" or "av". The header takes
+/// precedence because renamed files may have a misleading extension.
+pub fn input_is_averaged(
+    header: &PostprocFileHeader,
+    upstream_file: &Path,
+) -> Result<bool, CorrectionError> {
+    let from_header = if header.program_versions.contains_key(AVERAGING_PROGRAM) {
+        Some(true)
+    } else if COLLATION_PROGRAMS
+        .iter()
+        .any(|p| header.program_versions.contains_key(*p))
+    {
+        Some(false)
+    } else {
+        None
+    };
+
+    let extension = upstream_file
+        .extension()
+        .unwrap_or_default()
+        .to_str()
+        .unwrap_or_default();
+    let from_extension = if extension.ends_with("sw") {
+        Some(false)
+    } else if extension.ends_with("av") {
+        Some(true)
+    } else {
+        None
+    };
+
+    let describe = |is_avg: bool| if is_avg { "averaged" } else { "un-averaged" };
+    match (from_header, from_extension) {
+        (Some(h), Some(e)) => {
+            if h != e {
+                log::warn!(
+                    "the header of {} indicates it has {} values, but its extension ('{extension}') indicates {} values. Using the header.",
+                    upstream_file.display(), describe(h), describe(e)
+                );
+            }
+            Ok(h)
+        }
+        (Some(h), None) => Ok(h),
+        (None, Some(e)) => Ok(e),
+        (None, None) => Err(CorrectionError::custom(format!(
+            "could not determine whether {} has window-averaged values: its header does not list {AVERAGING_PROGRAM} or a collation program, and its extension ('{extension}') does not end in 'sw' or 'av'",
+            upstream_file.display()
+        ))),
+    }
+}
+
+/// Return an error listing any of the Xgas windows in `xgas_columns` (which may include
+/// error columns, which are ignored) that do not have an entry in `adcfs`.
+pub fn check_all_adcfs_present(
+    xgas_columns: &[String],
+    adcfs: &IndexMap<String, AdcfRow>,
+) -> Result<(), CorrectionError> {
+    let missing = xgas_columns
+        .iter()
+        .filter(|name| !name.ends_with("_error") && !adcfs.contains_key(name.as_str()))
+        .map(|name| name.as_str())
+        .collect::<Vec<_>>();
+
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(CorrectionError::custom(format!(
+            "{} window(s) do not have an airmass correction: {}",
+            missing.len(),
+            missing.join(", ")
+        )))
+    }
+}
+
+/// Return the gases in `aicfs` that are not among `xgas_columns`.
+pub fn unused_aicf_entries<'a>(
+    xgas_columns: &[String],
+    aicfs: &'a IndexMap<String, AicfRow>,
+) -> Vec<&'a str> {
+    aicfs
+        .keys()
+        .filter(|gas| !xgas_columns.contains(gas))
+        .map(|gas| gas.as_str())
+        .collect()
+}
+
+/// Add the lines listing the airmass correction factors (with the `g` and `p` values
+/// actually used) to the extra header lines of a post processing file.
+pub fn add_adcf_header_lines(
+    lines_out: &mut Vec<String>,
+    adcfs: &IndexMap<String, AdcfRow>,
+    shape_defaults: &ShapeDefaults,
+) -> Result<(), fortformat::SError> {
+    let nrow = adcfs.len();
+    lines_out.push(format!(" Airmass-Dependent Correction Factors: {nrow} 5"));
+
+    let ff = FortFormat::parse("(a22,2(1x,f9.5),2(1x,f6.2))").unwrap();
+    let settings = fortformat::ser::SerSettings::default().align_left_str(true);
+    for corr in adcfs.values() {
+        let (g, p) = shape_defaults.g_p(&corr.gas_or_window, Some(corr));
+        let values = (&corr.gas_or_window, corr.adcf, corr.adcf_error, g, p);
+        let s = fortformat::ser::to_string_custom::<_, &str>(values, &ff, None, &settings)?;
+        lines_out.push(s);
+    }
+
+    Ok(())
+}
+
+/// Add the lines listing the in situ correction factors and their WMO scales to the
+/// extra header lines of a post processing file.
+pub fn add_aicf_header_lines(
+    lines_out: &mut Vec<String>,
+    aicfs: &IndexMap<String, AicfRow>,
+) -> Result<(), fortformat::SError> {
+    let nrow = aicfs.len();
+    lines_out.push(format!(
+        " Airmass-Independent/In-Situ Correction Factors: {nrow} 4"
+    ));
+    // Keep at least one space between the gas name and the first value, so that
+    // the lines can still be split on whitespace.
+    let gas_width = aicfs
+        .values()
+        .map(|corr| corr.gas.len() + 1)
+        .fold(MIN_AICF_GAS_WIDTH, usize::max);
+    let ff = FortFormat::parse(&format!("(a{gas_width},2f9.4,1x,a1,a,a1)")).unwrap();
+    let settings = fortformat::ser::SerSettings::default().align_left_str(true);
+    for corr in aicfs.values() {
+        let values = (
+            &corr.gas,
+            corr.aicf,
+            corr.aicf_error,
+            '"',
+            &corr.wmo_scale,
+            '"',
+        );
+        let s = fortformat::ser::to_string_custom::<_, &str>(values, &ff, None, &settings)?;
+        lines_out.push(s);
+    }
+
+    Ok(())
+}
+
+/// Settings for [`apply_airmass_correction`] that are the same for every row of a file.
+#[derive(Debug, Clone, Copy)]
+pub struct AirmassCorrectionOptions<'a> {
+    /// The airmass correction factors, keyed by Xgas window name.
+    pub adcfs: &'a IndexMap<String, AdcfRow>,
+    /// The `g` and `p` values to use for windows whose ADCF row omits them.
+    pub shape_defaults: &'a ShapeDefaults,
+    /// The name of the O2 window used to compute the dry air column (e.g. "o2_7885").
+    pub o2_window: &'a str,
+    /// The fill value for missing data.
+    pub missing_value: f64,
+    /// Whether the values are window averages (see [`input_is_averaged`]).
+    pub is_avg: bool,
+}
+
+/// Convert the column densities in `row` to column averages and apply the airmass
+/// corrections, returning a new row keyed by the Xgas names (i.e. with an "x" prepended).
+/// `o2_dmf` and `sza` are the O2 mean mole fraction and solar zenith angle for this row.
+pub fn apply_airmass_correction(
+    row: &HashMap<String, f64>,
+    o2_dmf: f64,
+    sza: f64,
+    opts: &AirmassCorrectionOptions,
+) -> Result<HashMap<String, f64>, CorrectionError> {
+    let AirmassCorrectionOptions {
+        adcfs,
+        shape_defaults,
+        o2_window,
+        missing_value,
+        is_avg,
+    } = *opts;
+    let o2_window_error = format!("{o2_window}_error");
+
+    let o2_col = *row.get(o2_window).ok_or_else(|| {
+        CorrectionError::custom(format!(
+            "row does not contain the {o2_window} window for O2"
+        ))
+    })?;
+    let o2_col_err = *row.get(&o2_window_error).ok_or_else(|| {
+        CorrectionError::custom(format!(
+            "row does not contain the O2 column error, '{o2_window_error}'"
+        ))
+    })?;
+
+    let all_windows = row.keys().filter(|k| !k.ends_with("_error"));
+
+    let mut new_row = HashMap::new();
+    let col_dry_air = o2_col / o2_dmf;
+
+    for window in all_windows {
+        let window_error = format!("{window}_error");
+        let col_val = *row.get(window).unwrap(); // we know this will be in the row, b/c we're iterating over the row's keys
+        let col_err_val = *row.get(&window_error).ok_or_else(|| {
+            CorrectionError::custom(format!("row does not contain the column '{window_error}' for the error value corresponding to '{window}'"))
+        })?;
+
+        // For most gases, if we're doing individual windows (opposed to window averages), we don't
+        // want to add in the O2 uncertainty to the Xgas value just yet. If we did, then it would
+        // get counted multiple times when average_results operates on the Xgas values. However,
+        // we want to calculate the proper XO2 error here, so that average_results can use it.
+        let gas_frac_uncert = if window == o2_window || !is_avg {
+            col_err_val
+        } else {
+            let v = col_err_val.powi(2) + (col_val * o2_col_err / o2_col).powi(2);
+            f64::sqrt(v)
+        };
+
+        let xgas_key = format!("x{window}");
+        let xgas_error_key = format!("x{window}_error");
+
+        let xgas_adcf = adcfs.get(&xgas_key);
+        let cf = xgas_adcf.map(|a| a.adcf).unwrap_or(0.0);
+        let (g, p) = shape_defaults.g_p(&xgas_key, xgas_adcf);
+        let sbf = symmetric_basis_function(sza, p, g);
+
+        if approx::abs_diff_eq!(col_val, missing_value) {
+            new_row.insert(xgas_key, missing_value);
+        } else {
+            let xgas = col_val / col_dry_air / (1.0 + cf * sbf);
+            new_row.insert(xgas_key, xgas);
+        }
+
+        if approx::abs_diff_eq!(col_err_val, missing_value) {
+            new_row.insert(xgas_error_key, missing_value);
+        } else {
+            let xgas_error = gas_frac_uncert / col_dry_air / (1.0 + cf * sbf);
+            new_row.insert(xgas_error_key, xgas_error);
+        }
+    }
+
+    Ok(new_row)
+}
+
+/// Divide the Xgas values in `row` (and their errors) by their in situ correction factors,
+/// returning the new row. Gases without an entry in `aicfs` are not changed.
+pub fn apply_insitu_correction(
+    row: &HashMap<String, f64>,
+    aicfs: &IndexMap<String, AicfRow>,
+    missing_value: f64,
+) -> Result<HashMap<String, f64>, CorrectionError> {
+    let all_xgases = row.keys().filter(|k| !k.ends_with("_error"));
+
+    let mut new_row = HashMap::new();
+    for xgas in all_xgases {
+        let xgas_error = format!("{xgas}_error");
+        let col_val = *row.get(xgas).unwrap(); // we know this will be in the row, b/c we're iterating over the row's keys
+        let col_err_val = *row.get(&xgas_error).ok_or_else(|| {
+            CorrectionError::custom(format!("row does not contain the column '{xgas_error}' for the error value corresponding to '{xgas}'"))
+        })?;
+
+        let cf = aicfs.get(xgas).map(|r| r.aicf).unwrap_or(1.0);
+        if approx::abs_diff_eq!(col_val, missing_value) {
+            new_row.insert(xgas.to_owned(), missing_value);
+        } else {
+            new_row.insert(xgas.to_owned(), col_val / cf);
+        }
+
+        if approx::abs_diff_eq!(col_err_val, missing_value) {
+            new_row.insert(xgas_error, missing_value);
+        } else {
+            new_row.insert(xgas_error, col_err_val / cf);
+        }
+    }
+    Ok(new_row)
+}
+
+pub fn symmetric_basis_function(sza: f64, p: f64, g: f64) -> f64 {
+    ((sza + g) / (90.0 + g)).powf(p) - ((45.0 + g) / (90.0 + g)).powf(p)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::readers::postproc_files::open_and_iter_postproc_file;
+
+    use super::*;
+
+    #[test]
+    fn test_gas_default_shape() {
+        let mut adcfs = IndexMap::new();
+        for window in ["xco2_6220", "xch4_5938"] {
+            adcfs.insert(
+                window.to_string(),
+                AdcfRow {
+                    gas_or_window: window.to_string(),
+                    adcf: -0.01,
+                    adcf_error: 0.001,
+                    g: None,
+                    p: None,
+                },
+            );
+        }
+
+        let row = HashMap::from([
+            ("o2_7885".to_string(), 4.0e24),
+            ("o2_7885_error".to_string(), 1.0e22),
+            ("co2_6220".to_string(), 8.0e21),
+            ("co2_6220_error".to_string(), 1.0e19),
+            ("ch4_5938".to_string(), 3.6e19),
+            ("ch4_5938_error".to_string(), 1.0e17),
+        ]);
+
+        let gas_default: GasShapeDefault = "xco2=15,4".parse().unwrap();
+        let shape_defaults = ShapeDefaults::new(DEFAULT_G, DEFAULT_P, &[gas_default]);
+        let sza = 70.0;
+        let opts = AirmassCorrectionOptions {
+            adcfs: &adcfs,
+            shape_defaults: &shape_defaults,
+            o2_window: "o2_7885",
+            missing_value: -999.0,
+            is_avg: false,
+        };
+        let new_row = apply_airmass_correction(&row, 0.2095, sza, &opts).unwrap();
+
+        let col_dry_air = 4.0e24 / 0.2095;
+        // CO2 should pick up the per-gas default...
+        let sbf = symmetric_basis_function(sza, 4.0, 15.0);
+        let expected = 8.0e21 / col_dry_air / (1.0 - 0.01 * sbf);
+        approx::assert_relative_eq!(new_row["xco2_6220"], expected);
+        // ...while CH4 falls back on p = 0, which gives no correction.
+        approx::assert_relative_eq!(new_row["xch4_5938"], 3.6e19 / col_dry_air);
+
+        // Values given in the correction file take precedence over the defaults
+        adcfs.get_mut("xco2_6220").unwrap().g = Some(-5.0);
+        assert_eq!(
+            shape_defaults.g_p("xco2_6220", adcfs.get("xco2_6220")),
+            (-5.0, 4.0)
+        );
+    }
+
+    #[test]
+    fn test_file_shape_defaults() {
+        let file_defaults: ShapeDefaultsFile = toml::from_str(
+            r#"
+            [o2]
+            g = 10.0
+            p = 2.0
+
+            [gases]
+            xco2 = { g = 15.0, p = 4.0 }
+            xch4 = { g = 25.0, p = 4.0 }
+            "#,
+        )
+        .unwrap();
+
+        let cli_default: GasShapeDefault = "xch4=20,3".parse().unwrap();
+        let mut shape_defaults = ShapeDefaults::new(DEFAULT_G, DEFAULT_P, &[cli_default])
+            .with_file_defaults(file_defaults);
+        shape_defaults.set_o2_window("o2_7885");
+
+        let mut adcfs = IndexMap::new();
+        for window in ["xco2_6220", "xo2_7885"] {
+            adcfs.insert(
+                window.to_string(),
+                AdcfRow {
+                    gas_or_window: window.to_string(),
+                    adcf: -0.01,
+                    adcf_error: 0.001,
+                    g: None,
+                    p: None,
+                },
+            );
+        }
+        let row = HashMap::from([
+            ("o2_7885".to_string(), 4.0e24),
+            ("o2_7885_error".to_string(), 1.0e22),
+            ("co2_6220".to_string(), 8.0e21),
+            ("co2_6220_error".to_string(), 1.0e19),
+        ]);
+        let sza = 70.0;
+        let opts = AirmassCorrectionOptions {
+            adcfs: &adcfs,
+            shape_defaults: &shape_defaults,
+            o2_window: "o2_7885",
+            missing_value: -999.0,
+            is_avg: false,
+        };
+        let new_row = apply_airmass_correction(&row, 0.2095, sza, &opts).unwrap();
+
+        // CO2 uses its non-zero default from the file
+        let col_dry_air = 4.0e24 / 0.2095;
+        let sbf = symmetric_basis_function(sza, 4.0, 15.0);
+        approx::assert_relative_eq!(
+            new_row["xco2_6220"],
+            8.0e21 / col_dry_air / (1.0 - 0.01 * sbf)
+        );
+        // The O2 window uses the O2 default
+        let sbf = symmetric_basis_function(sza, 2.0, 10.0);
+        approx::assert_relative_eq!(new_row["xo2_7885"], 0.2095 / (1.0 - 0.01 * sbf));
+        // The command line default takes precedence over the file
+        assert_eq!(shape_defaults.g_p("xch4_5938", None), (20.0, 3.0));
+        // Other gases fall back on the global defaults
+        assert_eq!(
+            shape_defaults.g_p("xn2o_4395", None),
+            (DEFAULT_G, DEFAULT_P)
+        );
+    }
+
+    #[test]
+    fn test_averaged_input_detection() {
+        let crate_root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let vsw_file =
+            crate_root.join("test-data/inputs/apply-tccon-airmass-correction/pa_ggg_benchmark.vsw");
+        let vav_file = crate_root
+            .join("test-data/inputs/apply-tccon-insitu-correction/pa_ggg_benchmark.vav.ada");
+        let is_avg = |path: &std::path::Path| {
+            let (header, _) = open_and_iter_postproc_file(path).unwrap();
+            input_is_averaged(&header, path)
+        };
+
+        assert!(!is_avg(&vsw_file).unwrap());
+        // The .vav.ada extension does not say if it is averaged, but the header does
+        assert!(is_avg(&vav_file).unwrap());
+
+        // A renamed file should go by its header, not its extension
+        let output_dir = crate::test_utils::output_dir("tccon-averaged-input-detection");
+        let renamed = output_dir.join("airmass-renamed.vav");
+        std::fs::copy(&vsw_file, &renamed).unwrap();
+        assert!(!is_avg(&renamed).unwrap());
+
+        // With no collation or averaging program in the header, the extension must decide
+        let text = std::fs::read_to_string(&vsw_file).unwrap().replacen(
+            "collate_tccon_results",
+            "some_other_program___",
+            1,
+        );
+        let unknown = output_dir.join("airmass-unknown.dat");
+        std::fs::write(&unknown, text).unwrap();
+        assert!(is_avg(&unknown)
+            .unwrap_err()
+            .to_string()
+            .contains("could not determine"));
+    }
+
+    #[test]
+    fn test_aicf_header_alignment() {
+        let long_scale = "NOAA WMO-X2019 CO2 scale with a long description of the calibration";
+        let mut aicfs = IndexMap::new();
+        for (gas, scale) in [
+            ("xco2", long_scale),
+            ("xco2_with_a_very_long_name", "WMO CO2 X2007"),
+            ("xco", "N/A"),
+        ] {
+            aicfs.insert(
+                gas.to_string(),
+                AicfRow {
+                    gas: gas.to_string(),
+                    aicf: 1.0101,
+                    aicf_error: 0.0005,
+                    wmo_scale: scale.to_string(),
+                },
+            );
+        }
+
+        let mut lines = vec![];
+        add_aicf_header_lines(&mut lines, &aicfs).unwrap();
+        assert_eq!(
+            lines[0],
+            " Airmass-Independent/In-Situ Correction Factors: 3 4"
+        );
+
+        // Every line should have its values start in the same column, with at least
+        // one space after the longest gas name.
+        let value_start = "xco2_with_a_very_long_name".len() + 1;
+        for (line, (gas, corr)) in lines[1..].iter().zip(aicfs.iter()) {
+            assert!(line.starts_with(gas.as_str()), "{line}");
+            assert!(line[gas.len()..value_start].trim().is_empty(), "{line}");
+            assert_eq!(&line[value_start..value_start + 18], "   1.0101   0.0005");
+            assert_eq!(
+                &line[value_start + 18..],
+                format!(" \"{}\"", corr.wmo_scale)
+            );
+        }
+    }
+}
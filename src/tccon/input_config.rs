@@ -212,24 +212,49 @@ fn read_correction_file<'de, T: RowWithKey + serde::de::DeserializeOwned>(
     corr_file: &Path,
 ) -> Result<IndexMap<String, T>, GggError> {
     let mut f = FileBuf::open(corr_file)?;
-    let nhead = utils::get_nhead(&mut f)?;
+    let (nhead, ncol) = utils::get_nhead_ncol(&mut f)?;
     for _ in 1..nhead - 1 {
         f.read_header_line()?;
     }
 
     let colname_line = f.read_header_line()?;
     let colnames = colname_line.split_ascii_whitespace().collect_vec();
+    if colnames.len() != ncol {
+        return Err(GggError::DataError {
+            path: corr_file.to_path_buf(),
+            cause: format!(
+                "The first line declares {ncol} columns, but the column name line (line {nhead}) has {} names: '{}'",
+                colnames.len(),
+                colname_line.trim()
+            ),
+        });
+    }
     let mut corrections = IndexMap::new();
 
-    for line in f.lines() {
+    for (iline, line) in f.lines().enumerate() {
+        let line_num = nhead + iline + 1;
         let line = line.map_err(|e| GggError::CouldNotRead {
             path: corr_file.to_path_buf(),
             reason: e.to_string(),
         })?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let nfield = count_list_directed_fields(&line);
+        if nfield != ncol {
+            return Err(GggError::DataError {
+                path: corr_file.to_path_buf(),
+                cause: format!(
+                    "Line {line_num} has {nfield} values, but the header declares {ncol} columns: '{line}'"
+                ),
+            });
+        }
+
         let row: T = fortformat::from_str_with_fields(&line, &FortFormat::ListDirected, &colnames)
             .map_err(|e| GggError::DataError {
                 path: corr_file.to_path_buf(),
-                cause: format!("While deserializing the line '{line}', error was: {e}"),
+                cause: format!("While deserializing line {line_num} ('{line}'), error was: {e}"),
             })?;
         let key = row.key();
         corrections.insert(key, row);
@@ -237,3 +262,113 @@ fn read_correction_file<'de, T: RowWithKey + serde::de::DeserializeOwned>(
 
     Ok(corrections)
 }
+
+/// Count the whitespace-separated values in a list-directed line, treating
+/// quoted strings (which may contain spaces) as one value.
+fn count_list_directed_fields(line: &str) -> usize {
+    let mut n = 0;
+    let mut in_value = false;
+    let mut quote = None;
+    for c in line.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => {
+                if !in_value {
+                    n += 1;
+                    in_value = true;
+                }
+                quote = Some(c);
+            }
+            None if c.is_ascii_whitespace() => in_value = false,
+            None => {
+                if !in_value {
+                    n += 1;
+                    in_value = true;
+                }
+            }
+        }
+    }
+    n
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_temp_file(name: &str, contents: &str) -> std::path::PathBuf {
+        let output_dir = crate::test_utils::output_dir("tccon-input-config");
+        let path = output_dir.join(name);
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_count_list_directed_fields() {
+        assert_eq!(
+            count_list_directed_fields(r#""xco2_6220"  -0.00903  0.00025   15   4"#),
+            5
+        );
+        assert_eq!(
+            count_list_directed_fields(r#""xco2"   1.0101  0.0005  "WMO CO2 X2007""#),
+            4
+        );
+        assert_eq!(count_list_directed_fields("  "), 0);
+    }
+
+    #[test]
+    fn test_read_good_correction_files() {
+        let adcf_file = write_temp_file(
+            "good-adcf.dat",
+            "2 5\n Gas ADCF ADCF_Err g p\n\"xco2_6220\" -0.00903 0.00025 15 4\n",
+        );
+        let adcfs = read_adcf_file(&adcf_file).unwrap();
+        assert_eq!(adcfs["xco2_6220"].g, Some(15.0));
+
+        let aicf_file = write_temp_file(
+            "good-aicf.dat",
+            "2 4\n Gas AICF AICF_Err WMO_Scale\n\"xco2\" 1.0101 0.0005 \"WMO CO2 X2007\"\n",
+        );
+        let aicfs = read_aicf_file(&aicf_file).unwrap();
+        assert_eq!(aicfs["xco2"].wmo_scale, "WMO CO2 X2007");
+    }
+
+    #[test]
+    fn test_malformed_adcf_file() {
+        let adcf_file = write_temp_file(
+            "malformed-adcf.dat",
+            "2 5\n Gas ADCF ADCF_Err g p\n\"xco2_6220\" -0.00903 0.00025 15 4\n\"xco2_6339\" -0.00512 0.00025 45\n",
+        );
+        let err = read_adcf_file(&adcf_file).unwrap_err();
+        let msg = err.to_string();
+        assert!(
+            msg.contains("Line 4 has 4 values"),
+            "unexpected error: {msg}"
+        );
+        assert!(msg.contains("xco2_6339"), "unexpected error: {msg}");
+    }
+
+    #[test]
+    fn test_malformed_aicf_file() {
+        // The unquoted scale name splits into several values
+        let aicf_file = write_temp_file(
+            "malformed-aicf.dat",
+            "2 4\n Gas AICF AICF_Err WMO_Scale\n\"xco2\" 1.0101 0.0005 WMO CO2 X2007\n",
+        );
+        let err = read_aicf_file(&aicf_file).unwrap_err();
+        let msg = err.to_string();
+        assert!(
+            msg.contains("Line 3 has 6 values"),
+            "unexpected error: {msg}"
+        );
+
+        // The column names must also match the declared number of columns
+        let aicf_file = write_temp_file(
+            "malformed-aicf-header.dat",
+            "2 4\n Gas AICF AICF_Err\n\"xco2\" 1.0101 0.0005 \"WMO CO2 X2007\"\n",
+        );
+        let err = read_aicf_file(&aicf_file).unwrap_err();
+        let msg = err.to_string();
+        assert!(msg.contains("has 3 names"), "unexpected error: {msg}");
+    }
+}
@@ -1,2 +1,3 @@
+pub mod corrections;
 pub mod input_config;
 pub mod sort_spectra;
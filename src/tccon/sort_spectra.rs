@@ -15,9 +15,37 @@ pub enum NameError {
     IoError(#[from] std::io::Error),
 }
 
+/// How to order spectra from the same site and date.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SpectrumOrder {
+    /// Order by scan number, with the detector considered last, so that all
+    /// detectors' spectra for one scan are together.
+    #[default]
+    ScanThenDetector,
+    /// Order by detector, then scan number, so that all of one detector's
+    /// spectra come before the next detector's.
+    DetectorThenScan,
+}
+
+/// Return the base names of `paths`, sorted so that spectra from the same scan
+/// are together (see [`SpectrumOrder::ScanThenDetector`]).
 pub fn sort_spectrum_names<P: AsRef<Path>>(paths: &[P]) -> Result<Vec<String>, NameError> {
+    sort_spectrum_names_by(paths, SpectrumOrder::ScanThenDetector)
+}
+
+/// Return the base names of `paths`, sorted in the given `order`. In either
+/// order, spectra are grouped by site and date first.
+pub fn sort_spectrum_names_by<P: AsRef<Path>>(
+    paths: &[P],
+    order: SpectrumOrder,
+) -> Result<Vec<String>, NameError> {
     let mut spectra_names = get_spectrum_names(paths)?;
-    spectra_names.sort_unstable();
+    match order {
+        SpectrumOrder::ScanThenDetector => spectra_names.sort_unstable(),
+        SpectrumOrder::DetectorThenScan => {
+            spectra_names.sort_unstable_by(|a, b| a.cmp_detector_first(b))
+        }
+    }
     Ok(spectra_names.into_iter().map(|s| s.to_string()).collect())
 }
 
@@ -51,8 +79,13 @@ fn get_spectrum_names<P: AsRef<Path>>(paths: &[P]) -> Result<Vec<SortingSpec<'_>
     Ok(names)
 }
 
+/// Number of characters at the start of a spectrum name giving the site and date,
+/// e.g. "pa20040721".
+const SITE_DATE_LEN: usize = 10;
+
 #[derive(Debug, PartialEq, Eq)]
 struct SortingSpec<'s> {
+    site_date: &'s str,
     head: &'s str,
     detector: char,
     tail: &'s str,
@@ -67,8 +100,14 @@ impl<'s> SortingSpec<'s> {
         // The detector should be an ASCII character, so we assume it is one byte in the string
         let head = &spectrum_name[..i];
         let tail = &spectrum_name[i + 1..];
+        let (j, _) = spectrum_name
+            .char_indices()
+            .nth(SITE_DATE_LEN)
+            .expect("spectrum name should be longer than the site and date if it has a detector");
+        let site_date = &spectrum_name[..j];
 
         Ok(Self {
+            site_date,
             head,
             detector,
             tail,
@@ -76,6 +115,17 @@ impl<'s> SortingSpec<'s> {
     }
 }
 
+impl<'s> SortingSpec<'s> {
+    /// The alternate ordering to [`Ord`], which puts the detector before the run number.
+    fn cmp_detector_first(&self, other: &Self) -> std::cmp::Ordering {
+        self.site_date
+            .cmp(other.site_date)
+            .then_with(|| self.detector.cmp(&other.detector))
+            .then_with(|| self.tail.cmp(other.tail))
+            .then_with(|| self.head.cmp(other.head))
+    }
+}
+
 impl<'s> Display for SortingSpec<'s> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}{}{}", self.head, self.detector, self.tail)
@@ -84,25 +134,19 @@ impl<'s> Display for SortingSpec<'s> {
 
 impl<'s> PartialOrd for SortingSpec<'s> {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        match self.head.partial_cmp(other.head) {
-            Some(core::cmp::Ordering::Equal) => {}
-            ord => return ord,
-        }
-
-        match self.tail.partial_cmp(other.tail) {
-            Some(core::cmp::Ordering::Equal) => {}
-            ord => return ord,
-        }
-
-        self.detector.partial_cmp(&other.detector)
+        Some(self.cmp(other))
     }
 }
 
 impl<'s> Ord for SortingSpec<'s> {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        // Because we're most often dealing with spectra from the same site, which
-        // will have the same head, we can do a small optimization by comparing the
-        // tail first (which will be the run number).
+        // Compare the site and date first so that spectra from different days
+        // stay grouped together, then the tail (which will be the run number),
+        // then the rest of the head and the detector.
+        match self.site_date.cmp(other.site_date) {
+            core::cmp::Ordering::Equal => {}
+            ord => return ord,
+        }
 
         match self.tail.cmp(&other.tail) {
             core::cmp::Ordering::Equal => {}
@@ -117,3 +161,74 @@ impl<'s> Ord for SortingSpec<'s> {
         self.detector.cmp(&other.detector)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sort_single_day() {
+        let names = [
+            "pa20040721saaaab.002",
+            "pa20040721saaaaa.002",
+            "pa20040721saaaab.001",
+            "pa20040721saaaaa.001",
+        ];
+        let sorted = sort_spectrum_names(&names).unwrap();
+        assert_eq!(
+            sorted,
+            [
+                "pa20040721saaaaa.001",
+                "pa20040721saaaab.001",
+                "pa20040721saaaaa.002",
+                "pa20040721saaaab.002",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sort_grouped_by_detector() {
+        let names = [
+            "pa20040722saaaaa.001",
+            "pa20040721saaaab.002",
+            "pa20040721saaaaa.002",
+            "pa20040721saaaab.001",
+            "pa20040721saaaaa.001",
+        ];
+        let sorted = sort_spectrum_names_by(&names, SpectrumOrder::DetectorThenScan).unwrap();
+        assert_eq!(
+            sorted,
+            [
+                "pa20040721saaaaa.001",
+                "pa20040721saaaaa.002",
+                "pa20040721saaaab.001",
+                "pa20040721saaaab.002",
+                "pa20040722saaaaa.001",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sort_mixed_sites() {
+        let names = [
+            "pa20040722saaaaa.001",
+            "oc20040721saaaab.002",
+            "pa20040721saaaaa.002",
+            "pa20040722saaaaa.002",
+            "oc20040721saaaaa.001",
+            "pa20040721saaaaa.001",
+        ];
+        let sorted = sort_spectrum_names(&names).unwrap();
+        assert_eq!(
+            sorted,
+            [
+                "oc20040721saaaaa.001",
+                "oc20040721saaaab.002",
+                "pa20040721saaaaa.001",
+                "pa20040721saaaaa.002",
+                "pa20040722saaaaa.001",
+                "pa20040722saaaaa.002",
+            ]
+        );
+    }
+}
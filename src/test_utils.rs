@@ -4,12 +4,43 @@ use std::{
     process::{Command, Stdio},
 };
 
+use fortformat::FortFormat;
+use itertools::Itertools;
+
+use crate::{
+    collation::xsw_format_str,
+    opus::{
+        compute_frequency_grid, constants::bruker::BrukerParValue, write_spectrum_with_params,
+        Spectrum,
+    },
+    readers::postproc_files::{AuxData, PostprocFileHeader},
+    utils::{FileBuf, GggCompatibility},
+};
+
 #[allow(dead_code)]
 pub(crate) fn test_data_dir() -> PathBuf {
     let crate_root = env!("CARGO_MANIFEST_DIR");
     PathBuf::from(crate_root).join("test-data")
 }
 
+/// Return the directory `test-data/outputs/<name>` for a test to write into, creating it if needed.
+///
+/// Everything under `test-data/outputs` is ignored by git, so tests should not
+/// need to commit anything to use a new output directory.
+pub fn output_dir(name: &str) -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("test-data")
+        .join("outputs")
+        .join(name);
+    std::fs::create_dir_all(&dir).unwrap_or_else(|e| {
+        panic!(
+            "could not create test output directory {}: {e}",
+            dir.display()
+        )
+    });
+    dir
+}
+
 pub fn remove_file_if_exists(file: &Path) -> std::io::Result<()> {
     if file.exists() {
         std::fs::remove_file(file)
@@ -34,6 +65,93 @@ pub fn compare_output_text_files(expected_dir: &Path, output_dir: &Path, out_fil
     assert!(is_same, "{out_file_name} did not match expected.");
 }
 
+/// Assert that the auxiliary fields for a compatibility level are internally consistent.
+///
+/// This checks that the field list starts with the spectrum name, has no duplicates,
+/// keeps the same order as the [`GggCompatibility::Current`] fields, and that the
+/// `.vsw`/`.tsw` format string built from it has one column per field.
+pub fn assert_compatibility_consistent(compat: GggCompatibility) {
+    let fields = AuxData::postproc_fields_str(compat);
+    assert_eq!(
+        fields.first(),
+        Some(&"spectrum"),
+        "{compat:?} auxiliary fields must start with 'spectrum'"
+    );
+    assert!(
+        fields.iter().all_unique(),
+        "{compat:?} auxiliary fields contain duplicates"
+    );
+
+    // Other levels may omit newer columns, but must not reorder them.
+    let mut current_fields = AuxData::postproc_fields_str(GggCompatibility::Current).iter();
+    for field in fields {
+        assert!(
+            current_fields.any(|f| f == field),
+            "{compat:?} auxiliary field '{field}' is missing from or out of order relative to the current fields"
+        );
+    }
+
+    let ngas = 2;
+    let format_str = xsw_format_str(fields.len(), ngas);
+    let fformat = FortFormat::parse(&format_str)
+        .unwrap_or_else(|e| panic!("{compat:?} format string '{format_str}' is invalid: {e}"));
+    assert_eq!(
+        count_data_columns(&fformat),
+        fields.len() + ngas,
+        "{compat:?} format string '{format_str}' does not match the number of columns"
+    );
+}
+
+/// Assert that the header of a post processing file is consistent with a compatibility level.
+///
+/// This checks [`assert_compatibility_consistent`] for `compat`, then that the file's
+/// auxiliary columns are exactly the fields for `compat`, in order, and that the number of
+/// columns agrees among the first header line, format string, and column names.
+pub fn assert_postproc_file_consistent(file: &Path, compat: GggCompatibility) {
+    assert_compatibility_consistent(compat);
+
+    let mut fbuf =
+        FileBuf::open(file).unwrap_or_else(|e| panic!("could not open {}: {e}", file.display()));
+    let header = PostprocFileHeader::read_postproc_file_header(&mut fbuf)
+        .unwrap_or_else(|e| panic!("could not read the header of {}: {e:?}", file.display()));
+
+    let fields = AuxData::postproc_fields_str(compat);
+    assert_eq!(
+        header.naux,
+        fields.len(),
+        "{} has the wrong number of auxiliary columns for {compat:?}",
+        file.display()
+    );
+    let aux_columns = header.column_names.iter().take(header.naux).collect_vec();
+    assert_eq!(
+        aux_columns,
+        fields,
+        "{} has different auxiliary columns than expected for {compat:?}",
+        file.display()
+    );
+    assert_eq!(
+        header.column_names.len(),
+        header.ncol,
+        "{} has a different number of column names than columns",
+        file.display()
+    );
+    assert_eq!(
+        count_data_columns(&header.fformat),
+        header.ncol,
+        "{} has a format string that does not match its number of columns",
+        file.display()
+    );
+}
+
+/// Count the fields in a format that hold data, skipping the 1-character "a1" or "1x"
+/// field that follows the spectrum name.
+fn count_data_columns(fformat: &FortFormat) -> usize {
+    fformat
+        .iter_fields()
+        .filter(|field| field.width().is_some_and(|w| w > 1))
+        .count()
+}
+
 /// Iterate over fenced blocks in a Markdown file.
 ///
 /// Fenced blocks are the blocks that start and end with three backticks.
@@ -172,12 +290,52 @@ where
     }
 }
 
+/// Write a minimal Opus spectrum with acquisition parameter, data status, and data blocks.
+///
+/// The spectrum covers 3800 to 11000 cm-1 with a spacing of 200 cm-1, the measurement date
+/// is 2009-02-06, and `time` is written as the TIM parameter, e.g. "16:40:12.500 (GMT+0)".
+pub fn write_test_opus_spectrum(path: &Path, time: &str) {
+    // Block types for the acquisition parameters and spectrum data status
+    const AQ_PAR: i32 = 48;
+    const DSTAT_SPEC: i32 = 1047;
+
+    let npts = 37;
+    let spectrum = Spectrum {
+        path: path.to_path_buf(),
+        freq: compute_frequency_grid(npts, 19, 200.0),
+        spec: ndarray::Array1::ones(npts),
+    };
+    let params = [
+        (AQ_PAR, "RES", BrukerParValue::Float(0.02)),
+        (AQ_PAR, "NSS", BrukerParValue::Integer(2)),
+        (AQ_PAR, "SGN", BrukerParValue::Enum(b"4\0\0\0".to_vec())),
+        (
+            DSTAT_SPEC,
+            "DAT",
+            BrukerParValue::String("06/02/2009".to_string()),
+        ),
+        (DSTAT_SPEC, "TIM", BrukerParValue::String(time.to_string())),
+    ];
+    write_spectrum_with_params(path, &spectrum, -4, 512, &params).unwrap();
+}
+
 #[cfg(test)]
 mod tests {
     use itertools::Itertools;
 
     use super::*;
 
+    #[test]
+    fn test_compatibility_levels_consistent() {
+        for compat in [
+            GggCompatibility::Current,
+            GggCompatibility::GGG2020,
+            GggCompatibility::GGG2014,
+        ] {
+            assert_compatibility_consistent(compat);
+        }
+    }
+
     #[test]
     fn test_fenced_iter() {
         let data_root = test_data_dir();
@@ -1,4 +1,6 @@
-use std::fmt::Display;
+use std::{fmt::Display, sync::OnceLock};
+
+static UNIT_TERM_REGEX: OnceLock<regex::Regex> = OnceLock::new();
 
 #[derive(Debug)]
 pub struct UnknownUnitError {
@@ -23,13 +25,49 @@ impl Display for UnknownUnitError {
 
 impl std::error::Error for UnknownUnitError {}
 
+/// An error returned by [`convert`].
+#[derive(Debug)]
+pub enum ConvertError {
+    /// One of the units was not recognized.
+    Unknown(UnknownUnitError),
+    /// Both units were recognized, but are for different quantities (e.g. "ppm" and "hPa").
+    Incompatible { from_unit: String, to_unit: String },
+}
+
+impl Display for ConvertError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConvertError::Unknown(e) => write!(f, "{e}"),
+            ConvertError::Incompatible { from_unit, to_unit } => {
+                write!(f, "Cannot convert from '{from_unit}' to '{to_unit}', they are units of different quantities")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConvertError {}
+
+impl From<UnknownUnitError> for ConvertError {
+    fn from(value: UnknownUnitError) -> Self {
+        Self::Unknown(value)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Quantity {
     DMF,
     Pressure,
 }
 
 impl Quantity {
-    fn from_base_unit(&self, unit: &str) -> Result<f32, UnknownUnitError> {
+    /// Return the quantity that `unit` measures, or `None` if it is not a known unit.
+    pub fn of_unit(unit: &str) -> Option<Self> {
+        [Quantity::DMF, Quantity::Pressure]
+            .into_iter()
+            .find(|q| q.from_base_unit(unit).is_ok())
+    }
+
+    fn from_base_unit(&self, unit: &str) -> Result<f64, UnknownUnitError> {
         match self {
             Quantity::DMF => parts_to(unit),
             Quantity::Pressure => pascals_to(unit),
@@ -44,13 +82,91 @@ pub fn unit_conv_factor(
 ) -> Result<f32, UnknownUnitError> {
     let fac1 = quantity.from_base_unit(old_unit)?;
     let fac2 = quantity.from_base_unit(new_unit)?;
-    Ok(fac2 / fac1)
+    Ok((fac2 / fac1) as f32)
+}
+
+/// Convert `value` from `from_unit` to `to_unit`.
+///
+/// The units may be any that [`unit_conv_factor`] understands for either mole fractions
+/// or pressures. Unlike [`unit_conv_factor`], the quantity does not need to be given;
+/// instead, this returns an error if the two units do not measure the same quantity.
+///
+/// ```
+/// # use ggg_rs::units::convert;
+/// assert_eq!(convert(400.0, "ppm", "ppb").unwrap(), 400_000.0);
+/// assert!(convert(400.0, "ppm", "hPa").is_err());
+/// ```
+pub fn convert(value: f64, from_unit: &str, to_unit: &str) -> Result<f64, ConvertError> {
+    let unknown = |unit| UnknownUnitError::new("mole fraction or pressure", unit);
+    let from_quantity = Quantity::of_unit(from_unit).ok_or_else(|| unknown(from_unit))?;
+    let to_quantity = Quantity::of_unit(to_unit).ok_or_else(|| unknown(to_unit))?;
+    if from_quantity != to_quantity {
+        return Err(ConvertError::Incompatible {
+            from_unit: from_unit.to_string(),
+            to_unit: to_unit.to_string(),
+        });
+    }
+
+    let fac1 = from_quantity.from_base_unit(from_unit)?;
+    let fac2 = to_quantity.from_base_unit(to_unit)?;
+    Ok(value * fac2 / fac1)
+}
+
+// Mole fraction conversions. Throughout GGG, a "mole fraction" is a dry air mole fraction
+// in mol/mol, so these do not account for water vapor. 1 ppm = 1 umol/mol = 1e-6 mol/mol
+// and 1 ppb = 1 nmol/mol = 1e-9 mol/mol.
+
+/// Convert a dry mole fraction in parts per million to mol/mol, e.g. 400 ppm -> 4e-4.
+pub fn ppm_to_mol_fraction(value: f64) -> f64 {
+    value * 1e-6
+}
+
+/// Convert a dry mole fraction in mol/mol to parts per million, e.g. 4e-4 -> 400 ppm.
+pub fn mol_fraction_to_ppm(value: f64) -> f64 {
+    value * 1e6
+}
+
+/// Convert a dry mole fraction in parts per billion to mol/mol, e.g. 1900 ppb -> 1.9e-6.
+pub fn ppb_to_mol_fraction(value: f64) -> f64 {
+    value * 1e-9
+}
+
+/// Convert a dry mole fraction in mol/mol to parts per billion, e.g. 1.9e-6 -> 1900 ppb.
+pub fn mol_fraction_to_ppb(value: f64) -> f64 {
+    value * 1e9
+}
+
+/// Compute the geometric airmass for a solar zenith angle in degrees.
+///
+/// This is the plane-parallel approximation, i.e. the secant of the zenith angle. It
+/// ignores the curvature of the Earth and refraction, so overestimates the airmass at large
+/// zenith angles (by about 3% at 80 degrees); use [`airmass_from_sza_refracted`] when that
+/// matters. Returns NaN for zenith angles outside [0, 90).
+pub fn airmass_from_sza(sza_deg: f64) -> f64 {
+    if !(0.0..90.0).contains(&sza_deg) {
+        return f64::NAN;
+    }
+    1.0 / sza_deg.to_radians().cos()
+}
+
+/// Compute the airmass for a solar zenith angle in degrees, accounting for refraction and
+/// the curvature of the atmosphere.
+///
+/// This uses the empirical formula from Kasten and Young (1989), Applied Optics 28(22),
+/// pp. 4735-4738, which gives an airmass of about 0.9997 at zenith and 37.92 at the horizon.
+/// Returns NaN for zenith angles outside [0, 90].
+pub fn airmass_from_sza_refracted(sza_deg: f64) -> f64 {
+    if !(0.0..=90.0).contains(&sza_deg) {
+        return f64::NAN;
+    }
+    1.0 / (sza_deg.to_radians().cos() + 0.50572 * (96.07995 - sza_deg).powf(-1.6364))
 }
 
-fn parts_to(dmf_unit: &str) -> Result<f32, UnknownUnitError> {
+fn parts_to(dmf_unit: &str) -> Result<f64, UnknownUnitError> {
     match dmf_unit {
         "parts" => Ok(1.0),
         "1" => Ok(1.0),
+        "mol/mol" | "mol mol-1" | "mol mol^-1" => Ok(1.0),
         "ppm" => Ok(1e6),
         "ppb" => Ok(1e9),
         "ppt" => Ok(1e12),
@@ -68,10 +184,363 @@ pub fn dmf_long_name(dmf_unit: &str) -> Result<&'static str, UnknownUnitError> {
     }
 }
 
-fn pascals_to(pres_unit: &str) -> Result<f32, UnknownUnitError> {
+fn pascals_to(pres_unit: &str) -> Result<f64, UnknownUnitError> {
     match pres_unit {
-        "hPa" => Ok(1e-2),
+        "Pa" => Ok(1.0),
+        "hPa" | "mbar" => Ok(1e-2),
         "atm" => Ok(1.0 / 101325.0),
         _ => Err(UnknownUnitError::new("pressure", pres_unit)),
     }
 }
+
+/// GGG unit strings, with the CF unit and (if the CF unit loses information) the unit long
+/// name that [`cf_unit`] converts them to.
+const CF_UNIT_TABLE: &[(&[&str], &str, Option<&str>)] = &[
+    (&["", "none", "unitless"], "1", None),
+    (
+        &["AU", "au", "arbitrary", "arbitrary units"],
+        "1",
+        Some("arbitrary units"),
+    ),
+    (&["parts"], "1", Some("parts")),
+    (
+        &["utc_hour", "UT hour", "UTC hour"],
+        "hours",
+        Some("hours since midnight UTC"),
+    ),
+    (&["deg"], "degree", None),
+    (&["deg_C"], "degC", None),
+    (&["flag"], "1", Some("flag")),
+    (&["?"], "1", Some("unknown units")),
+    (
+        &["molec cm^-3", "molec. cm-3"],
+        "cm-3",
+        Some("molecules per cubic centimeter"),
+    ),
+    (
+        &["molecules.cm^-2", "molec cm^-2"],
+        "cm-2",
+        Some("molecules per square centimeter"),
+    ),
+];
+
+/// Convert a unit string used in GGG files to one that CF conventions and UDUNITS accept.
+///
+/// Returns the unit to write in the "units" attribute and, for units that lose information
+/// in the conversion, a suggested long name for the original unit (e.g. "arbitrary units"
+/// for "AU", which becomes "1"). [`long_name_with_unit`] will add the latter to a variable's
+/// long name. Units not in the table (including mole fraction units like "ppm", which other
+/// GGG programs rely on) are returned unchanged.
+pub fn cf_unit(ggg_unit: &str) -> (&str, Option<&'static str>) {
+    let trimmed = ggg_unit.trim();
+    CF_UNIT_TABLE
+        .iter()
+        .find(|(ggg_units, _, _)| ggg_units.contains(&trimmed))
+        .map(|&(_, unit, unit_long_name)| (unit, unit_long_name))
+        .unwrap_or((ggg_unit, None))
+}
+
+/// Unit symbols that UDUNITS recognizes, which may also take an SI prefix symbol (e.g. "km", "hPa").
+const UDUNITS_SYMBOLS: &[&str] = &[
+    "m", "g", "s", "K", "A", "mol", "cd", "Pa", "bar", "atm", "Hz", "N", "J", "W", "V", "rad",
+    "sr", "L", "l", "h", "min", "d",
+];
+
+/// Unit names that UDUNITS recognizes, which may also be plural or take an SI prefix name
+/// (e.g. "kilometers").
+const UDUNITS_NAMES: &[&str] = &[
+    "meter",
+    "metre",
+    "gram",
+    "second",
+    "kelvin",
+    "mole",
+    "pascal",
+    "radian",
+    "hour",
+    "minute",
+    "day",
+    "year",
+    "degree",
+    "degree_north",
+    "degree_east",
+    "degrees_north",
+    "degrees_east",
+    "degree_Celsius",
+    "celsius",
+    "degC",
+    "percent",
+    "%",
+    "ppm",
+    "ppb",
+    "ppt",
+];
+
+const SI_PREFIX_SYMBOLS: &[&str] = &[
+    "Y", "Z", "E", "P", "T", "G", "M", "k", "h", "da", "d", "c", "m", "u", "µ", "n", "p", "f",
+];
+
+const SI_PREFIX_NAMES: &[&str] = &[
+    "tera", "giga", "mega", "kilo", "hecto", "deca", "deci", "centi", "milli", "micro", "nano",
+    "pico",
+];
+
+/// Check whether `unit` looks like a unit string that UDUNITS can parse, as required by CF.
+///
+/// This does not use the UDUNITS library, so it only recognizes common units: products and
+/// quotients of the symbols and names in its table (with SI prefixes and integer exponents,
+/// e.g. "molec cm^-2" would be rejected for "molec", but "mol m-2" is accepted), plain
+/// numbers (e.g. "1" or "1e-6"), and time units with a reference time (e.g.
+/// "seconds since 1970-01-01"). It may reject some units that UDUNITS accepts, but should
+/// not accept units that UDUNITS rejects, like "AU" or "utc_hour".
+pub fn is_udunits_unit(unit: &str) -> bool {
+    let unit = unit.trim();
+    if let Some((time_unit, reference)) = unit.split_once(" since ") {
+        return is_udunits_unit(time_unit)
+            && reference.trim().starts_with(|c: char| c.is_ascii_digit());
+    }
+
+    let re = UNIT_TERM_REGEX.get_or_init(|| {
+        regex::Regex::new(
+            r"^(?:([A-Za-z_%µ]+)(?:\^?-?[0-9]+)?|[-+]?[0-9]+(?:\.[0-9]+)?(?:[eE][-+]?[0-9]+)?)$",
+        )
+        .expect("Could not compile unit term regex")
+    });
+
+    let mut nterms = 0;
+    for term in unit.split(|c: char| c.is_whitespace() || c == '*' || c == '/') {
+        // Numbers can contain a ".", so only treat it as multiplication between unit names
+        for term in split_unit_product(term) {
+            if term.is_empty() {
+                continue;
+            }
+            let Some(caps) = re.captures(term) else {
+                return false;
+            };
+            if let Some(name) = caps.get(1) {
+                if !is_udunits_name(name.as_str()) {
+                    return false;
+                }
+            }
+            nterms += 1;
+        }
+    }
+    nterms > 0
+}
+
+/// Split a term on "." used as multiplication (e.g. "m.s-1"), but not a decimal point.
+fn split_unit_product(term: &str) -> Vec<&str> {
+    if term.starts_with(|c: char| c.is_ascii_digit() || c == '-' || c == '+') {
+        vec![term]
+    } else {
+        term.split('.').collect()
+    }
+}
+
+fn is_udunits_name(name: &str) -> bool {
+    let is_symbol = |s: &str| UDUNITS_SYMBOLS.contains(&s);
+    let is_name = |s: &str| {
+        UDUNITS_NAMES.contains(&s)
+            || s.strip_suffix('s')
+                .is_some_and(|singular| UDUNITS_NAMES.contains(&singular))
+    };
+
+    is_symbol(name)
+        || is_name(name)
+        || SI_PREFIX_SYMBOLS
+            .iter()
+            .any(|p| name.strip_prefix(p).is_some_and(is_symbol))
+        || SI_PREFIX_NAMES
+            .iter()
+            .any(|p| name.strip_prefix(p).is_some_and(is_name))
+}
+
+/// Add the long name of a unit from [`cf_unit`] in parentheses to `long_name`, unless it
+/// is `None` or `long_name` already mentions it.
+pub fn long_name_with_unit(long_name: &str, unit_long_name: Option<&str>) -> String {
+    match unit_long_name {
+        Some(unit) if !long_name.to_lowercase().contains(unit) => format!("{long_name} ({unit})"),
+        _ => long_name.to_string(),
+    }
+}
+
+/// Return the unit long name that [`long_name_with_unit`] added to the end of `long_name`, if
+/// any. Programs that replace the long name of a variable written with a unit from [`cf_unit`]
+/// can use this to keep the original unit in the new long name.
+pub fn unit_from_long_name(long_name: &str) -> Option<&'static str> {
+    CF_UNIT_TABLE
+        .iter()
+        .filter_map(|&(_, _, unit_long_name)| unit_long_name)
+        .find(|unit| long_name.ends_with(&format!("({unit})")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cf_unit() {
+        assert_eq!(cf_unit("AU"), ("1", Some("arbitrary units")));
+        assert_eq!(cf_unit(""), ("1", None));
+        assert_eq!(cf_unit("utc_hour").0, "hours");
+        assert_eq!(cf_unit("ppm"), ("ppm", None));
+        assert_eq!(cf_unit("degrees_north"), ("degrees_north", None));
+
+        // Mole fractions in "parts" must still be convertible after normalizing
+        let (unit, _) = cf_unit("parts");
+        assert_eq!(unit_conv_factor(unit, "ppm", Quantity::DMF).unwrap(), 1e6);
+
+        assert_eq!(
+            long_name_with_unit("solar intensity average", cf_unit("AU").1),
+            "solar intensity average (arbitrary units)"
+        );
+        assert_eq!(
+            long_name_with_unit("intensity in arbitrary units", cf_unit("AU").1),
+            "intensity in arbitrary units"
+        );
+        assert_eq!(long_name_with_unit("latitude", None), "latitude");
+
+        assert_eq!(
+            unit_from_long_name("solar intensity average (arbitrary units)"),
+            Some("arbitrary units")
+        );
+        assert_eq!(unit_from_long_name("intensity in arbitrary units"), None);
+        assert_eq!(unit_from_long_name("latitude"), None);
+    }
+
+    #[test]
+    fn test_is_udunits_unit() {
+        for unit in [
+            "1",
+            "1e-6",
+            "K",
+            "hPa",
+            "mbar",
+            "km",
+            "cm-1",
+            "m s-1",
+            "m.s-1",
+            "mol mol^-1",
+            "mol m-2",
+            "kilometers",
+            "degrees_north",
+            "ppm",
+            "%",
+            "seconds since 1970-01-01 00:00:00",
+            "hours",
+        ] {
+            assert!(is_udunits_unit(unit), "'{unit}' should be accepted");
+        }
+
+        for unit in [
+            "",
+            "AU",
+            "utc_hour",
+            "molec cm^-3",
+            "Julian day",
+            "?",
+            "hours since",
+        ] {
+            assert!(!is_udunits_unit(unit), "'{unit}' should be rejected");
+        }
+
+        // Whatever cf_unit returns should be acceptable
+        for unit in ["AU", "", "utc_hour", "parts"] {
+            assert!(is_udunits_unit(cf_unit(unit).0), "{unit}");
+        }
+    }
+
+    #[test]
+    fn test_cf_unit_program_units() {
+        // Units that bin2nc writes
+        let bin2nc_units = [
+            "AU",
+            "seconds since 1970-01-01",
+            "year",
+            "day",
+            "utc_hour",
+            "degrees_north",
+            "degrees_east",
+            "km",
+            "deg",
+            "ppm",
+            "radians",
+            "",
+            "flag",
+            "deg_C",
+            "mbar",
+            "%",
+            "m s-1",
+            "cm-1",
+        ];
+        // Units that write_private_netcdf writes that do not come from the qc.dat file
+        let private_nc_units = [
+            "seconds since 1970-01-01 00:00:00",
+            "km",
+            "atm",
+            "molec cm^-3",
+            "K",
+            "mol mol^-1",
+            "molecules.cm^-2",
+            "1",
+            "?",
+            "",
+        ];
+
+        for unit in bin2nc_units.into_iter().chain(private_nc_units) {
+            let (cf, _) = cf_unit(unit);
+            assert!(
+                is_udunits_unit(cf),
+                "'{unit}' was converted to '{cf}', which is not a UDUNITS unit"
+            );
+        }
+
+        assert_eq!(cf_unit("deg"), ("degree", None));
+        assert_eq!(cf_unit("deg_C"), ("degC", None));
+        assert_eq!(cf_unit("flag"), ("1", Some("flag")));
+    }
+
+    #[test]
+    fn test_mol_fraction_round_trip() {
+        approx::assert_relative_eq!(ppm_to_mol_fraction(400.0), 4e-4);
+        approx::assert_relative_eq!(ppb_to_mol_fraction(1900.0), 1.9e-6);
+        for v in [0.0, 1.0, 412.5, 1875.3] {
+            approx::assert_relative_eq!(mol_fraction_to_ppm(ppm_to_mol_fraction(v)), v);
+            approx::assert_relative_eq!(mol_fraction_to_ppb(ppb_to_mol_fraction(v)), v);
+        }
+    }
+
+    #[test]
+    fn test_convert() {
+        approx::assert_relative_eq!(convert(400.0, "ppm", "mol/mol").unwrap(), 4e-4);
+        approx::assert_relative_eq!(convert(4e-4, "parts", "ppm").unwrap(), 400.0);
+        approx::assert_relative_eq!(convert(1.9, "ppm", "ppb").unwrap(), 1900.0);
+        approx::assert_relative_eq!(convert(1013.25, "hPa", "atm").unwrap(), 1.0);
+        let v = convert(convert(1875.3, "ppb", "1").unwrap(), "1", "ppb").unwrap();
+        approx::assert_relative_eq!(v, 1875.3);
+
+        assert!(matches!(
+            convert(400.0, "ppm", "hPa"),
+            Err(ConvertError::Incompatible { .. })
+        ));
+        assert!(matches!(
+            convert(400.0, "ppm", "furlongs"),
+            Err(ConvertError::Unknown(_))
+        ));
+    }
+
+    #[test]
+    fn test_airmass_from_sza() {
+        approx::assert_relative_eq!(airmass_from_sza(0.0), 1.0);
+        approx::assert_relative_eq!(airmass_from_sza(60.0), 2.0, epsilon = 1e-12);
+        approx::assert_relative_eq!(airmass_from_sza(80.0), 5.7588, epsilon = 1e-4);
+        assert!(airmass_from_sza(90.0).is_nan());
+        assert!(airmass_from_sza(-1.0).is_nan());
+
+        approx::assert_relative_eq!(airmass_from_sza_refracted(0.0), 0.9997, epsilon = 1e-4);
+        approx::assert_relative_eq!(airmass_from_sza_refracted(60.0), 1.9943, epsilon = 1e-4);
+        approx::assert_relative_eq!(airmass_from_sza_refracted(80.0), 5.5860, epsilon = 1e-4);
+        approx::assert_relative_eq!(airmass_from_sza_refracted(90.0), 37.920, epsilon = 1e-3);
+        assert!(airmass_from_sza_refracted(91.0).is_nan());
+    }
+}
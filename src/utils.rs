@@ -8,6 +8,7 @@ use std::num::NonZeroU8;
 use std::ops::{Deref, DerefMut};
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::{env, f64};
 
 use chrono::{DateTime, Datelike, TimeZone, Utc};
@@ -65,6 +66,15 @@ pub enum GggError {
         path: PathBuf,
         cause: String,
     },
+    /// Used when a spectrum appears more than once in a file that requires unique spectrum names,
+    /// such as a runlog. `first_line` and `line` are the 1-based line numbers of the first and
+    /// duplicate occurrences of `spectrum` in the file at `path`.
+    DuplicateSpectrum {
+        path: PathBuf,
+        spectrum: String,
+        first_line: usize,
+        line: usize,
+    },
     /// A generic error for an unimplemented case in the code
     NotImplemented(String),
     /// A general error for one-off cases that don't need their own variant
@@ -110,6 +120,18 @@ impl Display for GggError {
             Self::DataError { path, cause } => {
                 write!(f, "Error in data format of {}: {cause}", path.display())
             }
+            Self::DuplicateSpectrum {
+                path,
+                spectrum,
+                first_line,
+                line,
+            } => {
+                write!(
+                    f,
+                    "Spectrum {spectrum} on line {line} of {} is a duplicate of line {first_line}",
+                    path.display()
+                )
+            }
             Self::NotImplemented(case) => {
                 write!(f, "Not implemented: {case}")
             }
@@ -200,6 +222,12 @@ pub enum GggNcError {
         variable: String,
         group: Option<String>,
     },
+    DimLenMismatch {
+        dimname: String,
+        varname: String,
+        len_in_file: usize,
+        len_in_var: usize,
+    },
     NcErr(netcdf::Error),
     Context(String),
 }
@@ -242,6 +270,14 @@ impl Display for GggNcError {
                     write!(f, "Variable '{variable}' not found")
                 }
             }
+            GggNcError::DimLenMismatch {
+                dimname,
+                varname,
+                len_in_file,
+                len_in_var,
+            } => {
+                write!(f, "Dimension '{dimname}' has length {len_in_file} in the file, but the variable '{varname}' expects it to have length {len_in_var}")
+            }
             GggNcError::NcErr(error) => {
                 write!(f, "{error}")
             }
@@ -370,6 +406,90 @@ impl Display for ApodizationFxn {
     }
 }
 
+/// How to combine a lower and upper limit when checking whether a value falls
+/// in a range, used by [`GreaterLess`].
+///
+/// For [`FromStr`], the conversion ignores case and accepts "in" or "inside" for
+/// [`Combination::Inside`] and "out" or "outside" for [`Combination::Outside`].
+/// The older keywords "and" and "or" are also accepted, but log a deprecation warning.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String")]
+pub enum Combination {
+    #[default]
+    Inside,
+    Outside,
+}
+
+impl FromStr for Combination {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "in" | "inside" => Ok(Self::Inside),
+            "and" => {
+                log::warn!("'and' is deprecated as a keyword to combine limits, use 'in' or 'inside' instead");
+                Ok(Self::Inside)
+            }
+            "out" | "outside" => Ok(Self::Outside),
+            "or" => {
+                log::warn!("'or' is deprecated as a keyword to combine limits, use 'out' or 'outside' instead");
+                Ok(Self::Outside)
+            }
+            _ => Err(format!("'{s}' is not a valid combination variant")),
+        }
+    }
+}
+
+impl TryFrom<String> for Combination {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl Display for Combination {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Combination::Inside => write!(f, "inside"),
+            Combination::Outside => write!(f, "outside"),
+        }
+    }
+}
+
+/// A pair of optional limits to check values against.
+///
+/// If only one limit is given, a value matches if it is `<= less_than` or `>= greater_than`,
+/// whichever was given. If both are given, `combination` determines whether a value
+/// must be between them (`greater_than <= v <= less_than`) or only satisfy one of
+/// them (`v >= greater_than || v <= less_than`). Note that the latter only matches
+/// values outside a range when `greater_than` is the upper end of that range.
+pub struct GreaterLess<T: PartialOrd> {
+    pub less_than: Option<T>,
+    pub greater_than: Option<T>,
+    pub combination: Combination,
+}
+
+impl<T: PartialOrd> GreaterLess<T> {
+    /// Returns `true` if neither limit was given.
+    pub fn no_limits(&self) -> bool {
+        self.greater_than.is_none() && self.less_than.is_none()
+    }
+
+    /// Returns `true` if `value` matches the limits. Always `false` if there are no limits.
+    pub fn do_flag(&self, value: &T) -> bool {
+        match (&self.greater_than, &self.less_than) {
+            (None, None) => false,
+            (None, Some(lim)) => value <= lim,
+            (Some(lim), None) => value >= lim,
+            (Some(gt_lim), Some(lt_lim)) => match self.combination {
+                Combination::Inside => value >= gt_lim && value <= lt_lim,
+                Combination::Outside => value >= gt_lim || value <= lt_lim,
+            },
+        }
+    }
+}
+
 /// Get the GGG path as defined in the environment
 ///
 /// This will check for the environmental variables "GGGPATH" and "gggpath"
@@ -407,13 +527,16 @@ pub fn get_ggg_path() -> Result<PathBuf, GggError> {
 /// GGG itself, we may need to retain backwards compatibility
 /// with previous version of GGG output files. This enum
 /// defines the available compatibility settings.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum GggCompatibility {
     /// Indicates that GGG-RS will make no special effort to be
     /// compatible with any particular GGG version.
     Current,
     /// GGG-RS will maintain compatibility with GGG2020.
     GGG2020,
+    /// Used when reading files from GGG2014. GGG-RS does not write
+    /// GGG2014 files, so this cannot be chosen as a [`GggCompatibilityInput`].
+    GGG2014,
 }
 
 impl From<GggCompatibilityInput> for GggCompatibility {
@@ -722,21 +845,26 @@ impl<F: BufRead> BufRead for FileBuf<F> {
 /// }
 /// ```
 ///
-/// This will put the arguments `--in-place` and `-o`/`--output-file` into your CLI.
+/// This will put the arguments `--in-place`, `-o`/`--output-file`, and `--dry-run` into your CLI.
 /// Then, you can use the `setup_output` method to get an [`OptInplaceWriter`] which
 /// will help with writing to the correct output file.
 #[derive(Debug, clap::Args)]
-#[group(required = true)]
+#[group(required = true, multiple = true)]
 pub struct OutputOptCli {
     /// Provide this flag to modify the output/destination file directly.
-    /// Mutually exclusive with --output-file, but one of this and --output-file
-    /// must be given.
-    #[clap(long)]
+    /// Mutually exclusive with --output-file, but one of this, --output-file,
+    /// or --dry-run must be given.
+    #[clap(long, conflicts_with = "output_file")]
     in_place: bool,
     /// Provide this argument with the path to write the output to. Mutually
-    /// exclusive with --in-place, but one of this and --in-place must be given.
+    /// exclusive with --in-place, but one of this, --in-place, or --dry-run
+    /// must be given.
     #[clap(short, long)]
     output_file: Option<PathBuf>,
+    /// Provide this flag to print the output to stdout instead of writing
+    /// or modifying any file. May be combined with --in-place or --output-file.
+    #[clap(long)]
+    dry_run: bool,
 }
 
 impl OutputOptCli {
@@ -746,7 +874,10 @@ impl OutputOptCli {
             panic!("Incorrect use of OptOutputCli - in_place and output_file should never both be given");
         }
 
-        let writer = if let Some(ref out_file) = self.output_file {
+        let writer = if self.dry_run {
+            let out_file = self.output_file.as_deref().unwrap_or(input_file);
+            OptInplaceWriter::new_dry_run(out_file.to_path_buf())
+        } else if let Some(ref out_file) = self.output_file {
             OptInplaceWriter::new_separate(out_file.to_path_buf())?
         } else {
             OptInplaceWriter::new_in_place(input_file.to_path_buf())?
@@ -790,7 +921,8 @@ impl OutputOptCli {
 /// ```
 ///
 /// If not doing an in-place modification, then this writes directly to the output file
-/// as if you used `std::fs::File`.
+/// as if you used `std::fs::File`. A third variant, created with `new_dry_run`, writes
+/// everything to stdout instead and never touches the output file.
 ///
 /// **Note: you *must* call `finalize` for the in-place modification to complete! Otherwise
 /// the changes will only be in a hidden file.**
@@ -801,7 +933,8 @@ pub struct OptInplaceWriter {
     in_place: bool,
     out_path: PathBuf,
     final_path: PathBuf,
-    file: std::fs::File,
+    /// The file to write to, or `None` to write to stdout for a dry run.
+    file: Option<std::fs::File>,
 }
 
 impl OptInplaceWriter {
@@ -830,7 +963,7 @@ impl OptInplaceWriter {
             in_place: true,
             out_path,
             final_path: path,
-            file,
+            file: Some(file),
         })
     }
 
@@ -841,17 +974,34 @@ impl OptInplaceWriter {
             in_place: false,
             out_path: path,
             final_path: PathBuf::new(),
-            file,
+            file: Some(file),
         })
     }
 
+    /// Create a new writer that prints to stdout instead of writing to `path`.
+    ///
+    /// `path` is not created or modified; it is only kept to be returned by `output_path`.
+    pub fn new_dry_run(path: PathBuf) -> Self {
+        Self {
+            in_place: false,
+            out_path: path,
+            final_path: PathBuf::new(),
+            file: None,
+        }
+    }
+
+    /// Returns `true` if this writer prints to stdout rather than writing a file.
+    pub fn is_dry_run(&self) -> bool {
+        self.file.is_none()
+    }
+
     /// Perform any finalization.
     ///
     /// Consumes the writer, since after this call, no further data should be written.
     /// For all writers, this flushes any remaining data to disk. For in-place writers,
     /// this moves the temporary file into the final output location.
     pub fn finalize(mut self) -> std::io::Result<()> {
-        self.file.flush()?;
+        self.flush()?;
         if self.in_place {
             std::fs::rename(self.out_path, self.final_path)
         } else {
@@ -867,11 +1017,17 @@ impl OptInplaceWriter {
 
 impl Write for OptInplaceWriter {
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        self.file.write(buf)
+        match self.file.as_mut() {
+            Some(f) => f.write(buf),
+            None => std::io::stdout().write(buf),
+        }
     }
 
     fn flush(&mut self) -> std::io::Result<()> {
-        self.file.flush()
+        match self.file.as_mut() {
+            Some(f) => f.flush(),
+            None => std::io::stdout().flush(),
+        }
     }
 }
 
@@ -1109,9 +1265,12 @@ pub fn read_common_header<F: BufRead>(f: &mut FileBuf<F>) -> Result<CommonHeader
 }
 
 /// A structure representing the list of directories where spectra may be stored.
+///
+/// This is safe to share between threads; the cached index of the directory where
+/// the last spectrum was found is updated atomically.
 pub struct DataPartition {
     paths: Vec<PathBuf>,
-    previous_index: std::cell::Cell<usize>,
+    previous_index: AtomicUsize,
 }
 
 impl From<Vec<PathBuf>> for DataPartition {
@@ -1126,7 +1285,7 @@ impl From<Vec<PathBuf>> for DataPartition {
         let paths = value.into_iter().map(|p| make_path_abs(p)).collect_vec();
         Self {
             paths,
-            previous_index: std::cell::Cell::new(0),
+            previous_index: AtomicUsize::new(0),
         }
     }
 }
@@ -1136,7 +1295,7 @@ impl DataPartition {
     pub fn new_empty() -> Self {
         Self {
             paths: vec![],
-            previous_index: std::cell::Cell::new(0),
+            previous_index: AtomicUsize::new(0),
         }
     }
 
@@ -1198,7 +1357,7 @@ impl DataPartition {
 
         Ok(Self {
             paths,
-            previous_index: std::cell::Cell::new(0),
+            previous_index: AtomicUsize::new(0),
         })
     }
 
@@ -1219,7 +1378,7 @@ impl DataPartition {
         // since runlogs normally keep spectra from the same location together,
         // each call to this function has a good chance of needing the same
         // path as the previous.
-        let prev_idx = self.previous_index.get();
+        let prev_idx = self.previous_index.load(Ordering::Relaxed);
         let search_path = self.paths.get(prev_idx)?;
         let spec_path = search_path.join(specname);
         if spec_path.exists() {
@@ -1236,7 +1395,7 @@ impl DataPartition {
 
             let spec_path = search_path.join(specname);
             if spec_path.exists() {
-                self.previous_index.set(path_idx);
+                self.previous_index.store(path_idx, Ordering::Relaxed);
                 return Some(spec_path);
             }
         }
@@ -1641,6 +1800,66 @@ pub fn file_sha256_hexdigest(path: &Path) -> std::io::Result<String> {
     Ok(hex::encode(digest.as_ref()))
 }
 
+/// The kinds of file that [`sniff_file_kind`] can distinguish.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    /// A binary file beginning with the Bruker OPUS magic number,
+    /// i.e. an OPUS interferogram or spectrum.
+    OpusBinary,
+    /// Some other binary file.
+    OtherBinary,
+    /// A file that looks like plain text, as most GGG input/output files are.
+    Text,
+}
+
+impl Display for FileKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FileKind::OpusBinary => write!(f, "binary OPUS file"),
+            FileKind::OtherBinary => write!(f, "binary file"),
+            FileKind::Text => write!(f, "text file"),
+        }
+    }
+}
+
+/// Guess whether `path` is a binary OPUS file, some other binary file, or a text file.
+///
+/// This only inspects the first few hundred bytes of the file. A file is considered
+/// OPUS if it starts with the Bruker magic number (read as a little-endian `i32`);
+/// otherwise it is considered text unless it contains a NUL or other control
+/// character that would not appear in a GGG text file. Bytes outside the ASCII
+/// range are allowed in text files, since some GGG files are not UTF-8.
+pub fn sniff_file_kind(path: &Path) -> std::io::Result<FileKind> {
+    let mut f = std::fs::File::open(path)?;
+    let mut buf = [0u8; 512];
+    let mut nread = 0;
+    while nread < buf.len() {
+        let n = f.read(&mut buf[nread..])?;
+        if n == 0 {
+            break;
+        }
+        nread += n;
+    }
+    Ok(file_kind_from_bytes(&buf[..nread]))
+}
+
+fn file_kind_from_bytes(bytes: &[u8]) -> FileKind {
+    if let Some(magic) = bytes.first_chunk::<4>() {
+        if i32::from_le_bytes(*magic) == crate::opus::constants::bruker::MAGIC {
+            return FileKind::OpusBinary;
+        }
+    }
+
+    let is_text = bytes
+        .iter()
+        .all(|&b| !b.is_ascii_control() || matches!(b, b'\t' | b'\n' | b'\r' | 0x0c));
+    if is_text {
+        FileKind::Text
+    } else {
+        FileKind::OtherBinary
+    }
+}
+
 fn sha256_digest<R: Read>(mut reader: R) -> std::io::Result<ring::digest::Digest> {
     let mut context = ring::digest::Context::new(&ring::digest::SHA256);
     let mut buffer = [0; 1024];
@@ -1713,6 +1932,33 @@ fn make_path_abs(p: PathBuf) -> PathBuf {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_utils::test_data_dir;
+
+    #[test]
+    fn test_output_opt_cli_dry_run() {
+        use clap::Parser;
+
+        #[derive(Debug, clap::Parser)]
+        struct Cli {
+            #[clap(flatten)]
+            output: OutputOptCli,
+        }
+
+        let input = Path::new("unused-input.txt");
+        let cli = Cli::try_parse_from(["test", "--dry-run"]).unwrap();
+        let writer = cli.output.setup_output(input).unwrap();
+        assert!(writer.is_dry_run());
+        assert_eq!(writer.output_path(), input);
+
+        let cli = Cli::try_parse_from(["test", "--dry-run", "-o", "unused-output.txt"]).unwrap();
+        let writer = cli.output.setup_output(input).unwrap();
+        assert!(writer.is_dry_run());
+        assert_eq!(writer.output_path(), Path::new("unused-output.txt"));
+        assert!(!Path::new("unused-output.txt").exists());
+
+        assert!(Cli::try_parse_from(["test"]).is_err());
+        assert!(Cli::try_parse_from(["test", "--in-place", "-o", "out.txt"]).is_err());
+    }
 
     #[test]
     fn test_nth_day_of_week() {
@@ -1800,6 +2046,30 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_sniff_opus_spectrum() {
+        // There are no binary spectra in the test data, so make a file that starts
+        // the way an OPUS spectrum does: the magic number followed by the program version.
+        let mut bytes = crate::opus::constants::bruker::MAGIC.to_le_bytes().to_vec();
+        bytes.extend(crate::opus::constants::bruker::PRGM_VERS.to_le_bytes());
+        bytes.extend([0u8; 64]);
+        let output_dir = crate::test_utils::output_dir("utils-sniff-file-kind");
+        let spec_file = output_dir.join("sniff_test.0001");
+        std::fs::write(&spec_file, &bytes).unwrap();
+        let kind = sniff_file_kind(&spec_file).unwrap();
+        assert_eq!(kind, FileKind::OpusBinary);
+
+        assert_eq!(file_kind_from_bytes(&bytes[4..]), FileKind::OtherBinary);
+    }
+
+    #[test]
+    fn test_sniff_text_file() {
+        let grl_file = test_data_dir().join("inputs/collate-tccon-results/pa_ggg_benchmark.grl");
+        let kind = sniff_file_kind(&grl_file).unwrap();
+        assert_eq!(kind, FileKind::Text);
+        assert_eq!(file_kind_from_bytes(b""), FileKind::Text);
+    }
+
     struct VpathCase {
         prof_num: i32,
         zmin: f64,
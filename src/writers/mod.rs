@@ -1 +1,2 @@
 pub mod postproc_files;
+pub mod runlogs;
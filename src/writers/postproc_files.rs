@@ -1,8 +1,12 @@
 use std::io::Write;
 
 use error_stack::ResultExt;
+use fortformat::{ser::SerSettings, FortField, FortFormat};
 
-use crate::{error::WriteError, readers::ProgramVersion};
+use crate::{
+    error::WriteError,
+    readers::{postproc_files::PostprocRow, ProgramVersion},
+};
 
 /// Write the header of a postprocessing file.
 ///
@@ -92,3 +96,233 @@ pub fn write_postproc_header<W: Write>(
 
     Ok(())
 }
+
+/// The contents of a post processing file header, for [`PostprocWriter::new`].
+///
+/// The fields have the same meaning as the inputs to [`write_postproc_header`], except that
+/// `nrow` must be the number of rows that will be written with the writer. The number of
+/// columns is taken from `column_names`.
+#[derive(Debug, Clone, Copy)]
+pub struct PostprocHeaderInfo<'a> {
+    pub nrow: usize,
+    pub naux: usize,
+    pub program_versions: &'a [ProgramVersion],
+    pub extra_lines: &'a [String],
+    pub missing_value: f64,
+    pub format_str: &'a str,
+    pub column_names: &'a [String],
+}
+
+/// A writer that outputs a post processing file (e.g. `.vsw`, `.vav`, or `.vav.ada.aia`)
+/// one row at a time.
+///
+/// The header is written when the writer is created, then rows are serialized with the
+/// data format derived from the header format string. For example, to copy a post
+/// processing file with one more program version in the header:
+///
+/// ```no_run
+/// use std::path::Path;
+/// use ggg_rs::readers::{postproc_files::open_and_iter_postproc_file, ProgramVersion};
+/// use ggg_rs::writers::postproc_files::{PostprocHeaderInfo, PostprocWriter};
+///
+/// let (header, rows) = open_and_iter_postproc_file(Path::new("pa_ggg_benchmark.vav")).unwrap();
+/// let mut program_versions = Vec::from_iter(header.program_versions.values().cloned());
+/// program_versions.insert(0, ProgramVersion {
+///     program: "copy_vav".to_string(),
+///     version: "Version 1.0".to_string(),
+///     date: "2024-01-01".to_string(),
+///     authors: "JLL".to_string(),
+/// });
+///
+/// let f = std::fs::File::create("pa_ggg_benchmark_copy.vav").unwrap();
+/// let mut writer = PostprocWriter::new(
+///     f,
+///     PostprocHeaderInfo {
+///         nrow: header.nrec,
+///         naux: header.naux,
+///         program_versions: &program_versions,
+///         extra_lines: &header.extra_lines,
+///         missing_value: header.missing_value,
+///         format_str: &header.fformat.fmt_string(1),
+///         column_names: &header.column_names,
+///     },
+/// ).unwrap();
+/// for row in rows {
+///     writer.write_row(&row.unwrap()).unwrap();
+/// }
+/// writer.finish().unwrap();
+/// ```
+pub struct PostprocWriter<W: Write> {
+    writer: W,
+    fformat: FortFormat,
+    column_names: Vec<String>,
+    settings: SerSettings,
+    nrow: usize,
+    nwritten: usize,
+}
+
+impl<W: Write> PostprocWriter<W> {
+    /// Create a new writer and write the post processing file header described by `header`
+    /// to `writer`. The data lines are written with `header.format_str`, but with the
+    /// 1-character comment column after the spectrum name (if present) skipped.
+    ///
+    /// # Errors
+    /// Returns an error if `format_str` cannot be parsed or writing the header fails.
+    pub fn new(mut writer: W, header: PostprocHeaderInfo) -> error_stack::Result<Self, WriteError> {
+        let PostprocHeaderInfo {
+            nrow,
+            naux,
+            program_versions,
+            extra_lines,
+            missing_value,
+            format_str,
+            column_names,
+        } = header;
+        let mut fields = FortFormat::parse(format_str)
+            .map_err(|e| {
+                WriteError::convert_error(format!(
+                    "Could not parse post processing format string: {e}"
+                ))
+            })?
+            .into_fields()
+            .ok_or_else(|| {
+                WriteError::convert_error(
+                    "Post processing format string must have fixed width fields",
+                )
+            })?;
+
+        // Same as with reading, we do not serialize the comment character after the spectrum name.
+        if fields.get(1) == Some(&FortField::Char { width: Some(1) }) {
+            fields[1] = FortField::Skip;
+        }
+
+        write_postproc_header(
+            &mut writer,
+            column_names.len(),
+            nrow,
+            naux,
+            program_versions,
+            extra_lines,
+            missing_value,
+            format_str,
+            column_names,
+        )?;
+
+        // Skipped fields allow writing files that omitted auxiliary columns with defaults.
+        let settings = SerSettings::default()
+            .align_left_str(true)
+            .allow_skipped_fields(true);
+        Ok(Self {
+            writer,
+            fformat: FortFormat::Fixed(fields),
+            column_names: column_names.to_vec(),
+            settings,
+            nrow,
+            nwritten: 0,
+        })
+    }
+
+    /// Write one row as a data line in the file.
+    pub fn write_row(&mut self, row: &PostprocRow) -> error_stack::Result<(), WriteError> {
+        fortformat::ser::to_writer_custom(
+            row,
+            &self.fformat,
+            Some(&self.column_names),
+            &self.settings,
+            &mut self.writer,
+        )
+        .change_context_lazy(|| {
+            WriteError::convert_error(format!(
+                "Could not write post processing row for {}",
+                row.auxiliary.spectrum
+            ))
+        })?;
+        self.nwritten += 1;
+        Ok(())
+    }
+
+    /// Write each row from `rows` as a data line in the file.
+    pub fn write_rows<'a, I: IntoIterator<Item = &'a PostprocRow>>(
+        &mut self,
+        rows: I,
+    ) -> error_stack::Result<(), WriteError> {
+        for row in rows {
+            self.write_row(row)?;
+        }
+        Ok(())
+    }
+
+    /// Flush any remaining output and return the inner writer.
+    ///
+    /// # Errors
+    /// Returns an error if the number of rows written does not match the number given
+    /// in the header, or if flushing fails.
+    pub fn finish(mut self) -> error_stack::Result<W, WriteError> {
+        if self.nwritten != self.nrow {
+            return Err(WriteError::convert_error(format!(
+                "The post processing file header gave {} rows, but {} were written",
+                self.nrow, self.nwritten
+            ))
+            .into());
+        }
+        self.writer
+            .flush()
+            .change_context_lazy(|| WriteError::IoError)?;
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::readers::postproc_files::{open_and_iter_postproc_file, PostprocFileHeader};
+    use crate::test_utils::test_data_dir;
+
+    fn benchmark_vsw() -> PathBuf {
+        test_data_dir()
+            .join("inputs")
+            .join("apply-tccon-airmass-correction")
+            .join("pa_ggg_benchmark.vsw")
+    }
+
+    fn writer_from_header(header: &PostprocFileHeader) -> PostprocWriter<Vec<u8>> {
+        let program_versions = Vec::from_iter(header.program_versions.values().cloned());
+        PostprocWriter::new(
+            vec![],
+            PostprocHeaderInfo {
+                nrow: header.nrec,
+                naux: header.naux,
+                program_versions: &program_versions,
+                extra_lines: &header.extra_lines,
+                missing_value: header.missing_value,
+                format_str: &header.fformat.fmt_string(1),
+                column_names: &header.column_names,
+            },
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_postproc_round_trip() {
+        let vsw_file = benchmark_vsw();
+        let (header, rows) = open_and_iter_postproc_file(&vsw_file).unwrap();
+        let rows: Vec<_> = rows.map(|r| r.unwrap()).collect();
+
+        let mut writer = writer_from_header(&header);
+        writer.write_rows(&rows).unwrap();
+        let written = String::from_utf8(writer.finish().unwrap()).unwrap();
+
+        let original = std::fs::read_to_string(&vsw_file).unwrap();
+        assert_eq!(written, original);
+    }
+
+    #[test]
+    fn test_postproc_writer_row_count() {
+        let (header, mut rows) = open_and_iter_postproc_file(&benchmark_vsw()).unwrap();
+        let mut writer = writer_from_header(&header);
+        writer.write_row(&rows.next().unwrap().unwrap()).unwrap();
+        assert!(writer.finish().is_err());
+    }
+}
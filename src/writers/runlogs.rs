@@ -0,0 +1,498 @@
+//! Utilities for writing runlogs.
+use std::{io::Write, path::Path};
+
+use error_stack::ResultExt;
+use fortformat::{ser::SerSettings, FortField, FortFormat};
+use serde::{Serialize, Serializer};
+
+use crate::{
+    error::WriteError,
+    readers::runlogs::{Runlog, RunlogDataRec, RunlogHeader, NUM_RUNLOG_COLS},
+};
+
+/// The Fortran format used for data lines in GGG2020 runlogs.
+pub const RUNLOG_FORMAT: &str = "(a1,a57,1x,2i4,f8.4,f8.3,f9.3,2f8.3,1x,f6.4,f8.3,f7.3,f7.2,3(1x,f5.4),2i9,1x,f14.11,i9,i3,1x,f5.3,i5,1x,a2,2(f6.1,f8.2,f5.1),f7.1,f7.4,f6.1,f6.0,f10.3,f7.0,f7.3)";
+
+/// The line of column names written by `create_runlog` for GGG2020 runlogs.
+pub const RUNLOG_COLUMN_LINE: &str = "    Spectrum_File_Name                                     Year  Day  Hour   oblat    oblon   obalt    ASZA   POFF    AZIM   OSDS    OPD   FOVI  FOVO  AMAL   IFIRST    ILAST     DELTA_NU    POINTER BPW ZOFF  SNR APF  tins  pins  hins   tout   pout  hout  sia    fvsi   wspd  wdir  lasf    wavtkr  aipl";
+
+/// A writer that outputs a GGG2020 runlog one data record at a time.
+///
+/// The header is written when the writer is created, so records can be streamed
+/// to the output without knowing how many there will be. For example, to copy
+/// only the uncommented records from one runlog to another:
+///
+/// ```no_run
+/// use std::path::Path;
+/// use ggg_rs::readers::runlogs::Runlog;
+/// use ggg_rs::writers::runlogs::RunlogWriter;
+///
+/// let runlog = Runlog::open(Path::new("pa_ggg_benchmark.grl")).unwrap();
+/// let f = std::fs::File::create("pa_ggg_benchmark_copy.grl").unwrap();
+/// let mut writer = RunlogWriter::from_header(f, runlog.runlog_header()).unwrap();
+/// for rec in runlog {
+///     writer.write_record(&rec).unwrap();
+/// }
+/// writer.finish().unwrap();
+/// ```
+pub struct RunlogWriter<W: Write> {
+    writer: W,
+    fields: Vec<FortField>,
+    settings: SerSettings,
+}
+
+/// One value from a runlog data record, to be written with the next field of the format.
+enum RunlogValue<'a> {
+    Char(&'a str),
+    Int(i64),
+    Real(f64),
+}
+
+impl<'a> RunlogValue<'a> {
+    /// Return the values of `rec` in the order of the runlog columns, with the
+    /// comment character first.
+    fn from_record(rec: &'a RunlogDataRec, apf: &'a str) -> [Self; NUM_RUNLOG_COLS + 1] {
+        use RunlogValue::{Char, Int, Real};
+        [
+            Char(if rec.commented { ":" } else { " " }),
+            Char(&rec.spectrum_name),
+            Int(rec.year.into()),
+            Int(rec.day.into()),
+            Real(rec.hour),
+            Real(rec.obs_lat),
+            Real(rec.obs_lon),
+            Real(rec.obs_alt),
+            Real(rec.asza),
+            Real(rec.poff),
+            Real(rec.azim),
+            Real(rec.osds),
+            Real(rec.opd),
+            Real(rec.fovi),
+            Real(rec.fovo),
+            Real(rec.amal),
+            Int(rec.ifirst as i64),
+            Int(rec.ilast as i64),
+            Real(rec.delta_nu),
+            Int(rec.pointer.into()),
+            Int(rec.bpw.into()),
+            Real(rec.zoff),
+            Int(rec.snr.into()),
+            Char(apf),
+            Real(rec.tins),
+            Real(rec.pins),
+            Real(rec.hins),
+            Real(rec.tout),
+            Real(rec.pout),
+            Real(rec.hout),
+            Real(rec.sia),
+            Real(rec.fvsi),
+            Real(rec.wspd),
+            Real(rec.wdir),
+            Real(rec.lasf),
+            Real(rec.wavtkr),
+            Real(rec.aipl),
+        ]
+    }
+}
+
+impl Serialize for RunlogValue<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            RunlogValue::Char(v) => serializer.serialize_str(v),
+            RunlogValue::Int(v) => serializer.serialize_i64(*v),
+            RunlogValue::Real(v) => serializer.serialize_f64(*v),
+        }
+    }
+}
+
+/// Format `value` the way Fortran writes it with an `fW.D` edit descriptor.
+///
+/// `fortformat` does not match Fortran for two kinds of fields in runlogs: it cannot write
+/// a value less than 1 to a field like `f5.4` (Fortran writes ".0000", dropping the leading
+/// zero), and it leaves off the trailing decimal point for fields like `f6.0`. So we format
+/// `f` fields ourselves. Returns `None` if `value` does not fit in `width` characters, where
+/// Fortran would write asterisks instead.
+fn format_fortran_f(value: f64, width: usize, precision: usize) -> Option<String> {
+    if !value.is_finite() {
+        return None;
+    }
+
+    let mut s = format!("{value:.precision$}");
+    if precision == 0 {
+        s.push('.');
+    }
+    if s.len() > width {
+        if let Some(frac) = s.strip_prefix("0.") {
+            s = format!(".{frac}");
+        } else if let Some(frac) = s.strip_prefix("-0.") {
+            s = format!("-.{frac}");
+        }
+    }
+
+    (s.len() <= width).then(|| format!("{s:>width$}"))
+}
+
+/// Format the line of column names for a runlog. Each name is right-aligned to where
+/// `create_runlog` ends the name of that column in [`RUNLOG_COLUMN_LINE`], with at least
+/// one space between names, so the standard names give that line exactly.
+fn format_column_line<S: AsRef<str>>(column_names: &[S]) -> String {
+    let std_line = RUNLOG_COLUMN_LINE.as_bytes();
+    let name_ends = (1..=std_line.len()).filter(|&i| {
+        !std_line[i - 1].is_ascii_whitespace()
+            && std_line.get(i).is_none_or(|b| b.is_ascii_whitespace())
+    });
+
+    let mut line = String::new();
+    for (name, end) in column_names.iter().zip(name_ends) {
+        let name = name.as_ref();
+        let min_start = if line.is_empty() { 0 } else { line.len() + 1 };
+        let start = end.saturating_sub(name.len()).max(min_start);
+        line.push_str(&" ".repeat(start - line.len()));
+        line.push_str(name);
+    }
+    line
+}
+
+impl<W: Write> RunlogWriter<W> {
+    /// Create a new writer and write the runlog header to `writer`.
+    ///
+    /// # Inputs
+    /// - `writer`: the handle to write to, usually a [`std::io::BufWriter`] or similar.
+    /// - `extra_lines`: header lines to write between the first line and the format line,
+    ///   usually the program version line(s) from the runlog being copied. Trailing newlines
+    ///   are removed, but other whitespace is kept.
+    /// - `format_str`: the Fortran format for the data lines. This will almost always be
+    ///   [`RUNLOG_FORMAT`]; it must have a field for the comment character at the start
+    ///   of each line, then one for each of the [`NUM_RUNLOG_COLS`] columns.
+    ///
+    /// The column names written are the standard ones, [`RUNLOG_COLUMN_LINE`].
+    ///
+    /// # Errors
+    /// Returns an error if `format_str` cannot be parsed or does not have the right
+    /// number of fields, or writing the header fails.
+    pub fn new(
+        writer: W,
+        extra_lines: &[String],
+        format_str: &str,
+    ) -> error_stack::Result<Self, WriteError> {
+        let column_names = RUNLOG_COLUMN_LINE.split_whitespace().collect::<Vec<_>>();
+        Self::new_with_columns(writer, extra_lines, format_str, &column_names)
+    }
+
+    /// Create a new writer that writes the same header as `header`.
+    ///
+    /// This is the usual way to create a writer when the records come from another runlog,
+    /// since it keeps that runlog's extra header lines, data format, and column names.
+    pub fn from_header(writer: W, header: &RunlogHeader) -> error_stack::Result<Self, WriteError> {
+        Self::new_with_columns(
+            writer,
+            &header.extra_lines,
+            &header.format_str,
+            &header.column_names,
+        )
+    }
+
+    fn new_with_columns<S: AsRef<str>>(
+        mut writer: W,
+        extra_lines: &[String],
+        format_str: &str,
+        column_names: &[S],
+    ) -> error_stack::Result<Self, WriteError> {
+        let runlog_format = FortFormat::parse(format_str).map_err(|e| {
+            WriteError::convert_error(format!("Could not parse runlog format string: {e}"))
+        })?;
+        let fields = runlog_format.iter_fields().copied().collect::<Vec<_>>();
+        let nvalues = fields.iter().filter(|f| !f.is_positional()).count();
+        if nvalues != NUM_RUNLOG_COLS + 1 {
+            return Err(WriteError::convert_error(format!(
+                "The runlog format must have {} non-spacing fields (the comment character and {NUM_RUNLOG_COLS} columns), found {nvalues}",
+                NUM_RUNLOG_COLS + 1
+            ))
+            .into());
+        }
+        if column_names.len() != NUM_RUNLOG_COLS {
+            return Err(WriteError::convert_error(format!(
+                "A runlog must have {NUM_RUNLOG_COLS} column names, got {}",
+                column_names.len()
+            ))
+            .into());
+        }
+
+        // The extra 3 = line with nhead and ncol + format + column names
+        let nhead = extra_lines.len() + 3;
+        let first_line_format = FortFormat::parse("(2i12)").expect(
+            "The (hard coded) Fortran format for the first line of a runlog should be valid",
+        );
+        fortformat::to_writer((nhead, NUM_RUNLOG_COLS), &first_line_format, &mut writer)
+            .change_context_lazy(|| WriteError::IoError)?;
+
+        for line in extra_lines {
+            writeln!(writer, "{}", line.trim_end_matches(['\n', '\r']))
+                .change_context_lazy(|| WriteError::IoError)?;
+        }
+        writeln!(writer, "format={format_str}").change_context_lazy(|| WriteError::IoError)?;
+        writeln!(writer, "{}", format_column_line(column_names))
+            .change_context_lazy(|| WriteError::IoError)?;
+
+        let settings = SerSettings::default().align_left_str(true);
+        Ok(Self {
+            writer,
+            fields,
+            settings,
+        })
+    }
+
+    /// Write one data record as a line in the runlog.
+    pub fn write_record(&mut self, rec: &RunlogDataRec) -> error_stack::Result<(), WriteError> {
+        let apf = rec.apf.to_string();
+        let values = RunlogValue::from_record(rec, &apf);
+        let mut values = values.iter();
+        let mut line = String::new();
+        for field in self.fields.iter() {
+            if field.is_positional() {
+                // Runlog formats only use "x" for spacing
+                line.push(' ');
+                continue;
+            }
+
+            let value = values.next().expect(
+                "the number of fields should have been checked when the writer was created",
+            );
+            match (field, value) {
+                (
+                    FortField::Real {
+                        width,
+                        precision: Some(p),
+                        fmt,
+                        ..
+                    },
+                    RunlogValue::Real(v),
+                ) if fmt.is_f() => {
+                    let s =
+                        format_fortran_f(*v, *width as usize, *p as usize).ok_or_else(|| {
+                            WriteError::convert_error(format!(
+                                "A value in the runlog record for {} is too large for its field",
+                                rec.spectrum_name
+                            ))
+                        })?;
+                    line.push_str(&s);
+                }
+                _ => line.push_str(&self.serialize_value(value, field, rec)?),
+            }
+        }
+        line.push('\n');
+
+        self.writer
+            .write_all(line.as_bytes())
+            .change_context_lazy(|| WriteError::IoError)
+    }
+
+    fn serialize_value(
+        &self,
+        value: &RunlogValue,
+        field: &FortField,
+        rec: &RunlogDataRec,
+    ) -> error_stack::Result<String, WriteError> {
+        let fformat = FortFormat::Fixed(vec![*field]);
+        let s = fortformat::ser::to_string_custom::<_, &str>(value, &fformat, None, &self.settings)
+            .change_context_lazy(|| {
+                WriteError::convert_error(format!(
+                    "Could not write runlog record for {}",
+                    rec.spectrum_name
+                ))
+            })?;
+        Ok(s.trim_end_matches('\n').to_string())
+    }
+
+    /// Flush any remaining output and return the inner writer.
+    pub fn finish(mut self) -> error_stack::Result<W, WriteError> {
+        self.writer
+            .flush()
+            .change_context_lazy(|| WriteError::IoError)?;
+        Ok(self.writer)
+    }
+}
+
+/// Write a new runlog containing only the records in `runlog` for which `predicate` returns `true`.
+///
+/// The new runlog keeps the extra header lines and data format of the original. Commented
+/// records are passed to `predicate` as well, and remain commented if kept. For example, to
+/// keep only spectra with a solar zenith angle below 80 degrees:
+///
+/// ```no_run
+/// use std::path::Path;
+/// use ggg_rs::writers::runlogs::filter_runlog;
+///
+/// let f = std::fs::File::create("pa_ggg_benchmark_sza80.grl").unwrap();
+/// let nrec = filter_runlog(Path::new("pa_ggg_benchmark.grl"), f, |rec| rec.asza < 80.0).unwrap();
+/// println!("Kept {nrec} records");
+/// ```
+///
+/// # Returns
+/// The number of records written to `writer`. If reading or parsing any part of `runlog`
+/// fails, a [`WriteError::ReadError`] is returned; errors writing to `writer` are
+/// returned as [`WriteError::IoError`].
+pub fn filter_runlog<W, P>(
+    runlog: &Path,
+    writer: W,
+    mut predicate: P,
+) -> error_stack::Result<usize, WriteError>
+where
+    W: Write,
+    P: FnMut(&RunlogDataRec) -> bool,
+{
+    let mut rl = Runlog::open(runlog).change_context_lazy(|| WriteError::read_error(runlog))?;
+
+    let mut writer = RunlogWriter::from_header(writer, rl.runlog_header())?;
+    let mut nrec = 0;
+    while let Some(rec) = rl
+        .next_data_record(true)
+        .change_context_lazy(|| WriteError::read_error(runlog))?
+    {
+        if predicate(&rec) {
+            writer.write_record(&rec)?;
+            nrec += 1;
+        }
+    }
+    writer.finish()?;
+    Ok(nrec)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::test_data_dir;
+
+    fn benchmark_runlog() -> std::path::PathBuf {
+        test_data_dir()
+            .join("inputs")
+            .join("collate-tccon-results")
+            .join("pa_ggg_benchmark.grl")
+    }
+
+    /// Filter the benchmark runlog and return the spectrum names in the filtered runlog.
+    fn filtered_spectra<P: FnMut(&RunlogDataRec) -> bool>(
+        test_name: &str,
+        predicate: P,
+    ) -> Vec<String> {
+        let runlog_path = benchmark_runlog();
+        let mut buf = vec![];
+        let nrec = filter_runlog(&runlog_path, &mut buf, predicate).unwrap();
+
+        // The header should be unchanged
+        let original = std::fs::read_to_string(&runlog_path).unwrap();
+        let filtered = String::from_utf8(buf).unwrap();
+        assert_eq!(
+            filtered.lines().take(4).collect::<Vec<_>>(),
+            original.lines().take(4).collect::<Vec<_>>()
+        );
+
+        let output_dir = crate::test_utils::output_dir("runlog-filter");
+        let filtered_path = output_dir.join(format!("{test_name}.grl"));
+        std::fs::write(&filtered_path, filtered).unwrap();
+        let names: Vec<_> = Runlog::open(&filtered_path)
+            .unwrap()
+            .map(|rec| rec.spectrum_name)
+            .collect();
+        assert_eq!(names.len(), nrec);
+        names
+    }
+
+    #[test]
+    fn test_filter_runlog_by_asza() {
+        let names = filtered_spectra("filter-runlog-asza", |rec| rec.asza < 70.0);
+        assert_eq!(
+            names,
+            [
+                "pa20040721saaaaa.043",
+                "pa20040721saaaab.043",
+                "pa20040721saaaaa.119",
+                "pa20040721saaaab.119"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_filter_runlog_by_time() {
+        let start = chrono::NaiveDate::from_ymd_opt(2004, 12, 22)
+            .unwrap()
+            .and_hms_opt(14, 42, 0)
+            .unwrap()
+            .and_utc();
+        let names = filtered_spectra("filter-runlog-time", |rec| {
+            rec.zpd_time().is_some_and(|t| t >= start)
+        });
+        assert_eq!(names, ["pa20041222saaaaa.020", "pa20041222saaaab.020"]);
+    }
+
+    #[test]
+    fn test_filter_runlog_read_error() {
+        let runlog_path = test_data_dir().join("inputs").join("no_such_runlog.grl");
+        let err = filter_runlog(&runlog_path, vec![], |_| true).unwrap_err();
+        assert!(
+            matches!(err.current_context(), WriteError::ReadError(p) if p == &runlog_path),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn test_runlog_round_trip() {
+        let runlog_path = benchmark_runlog();
+        let original = std::fs::read_to_string(&runlog_path).unwrap();
+        let extra_lines = vec![original.lines().nth(1).unwrap().to_string()];
+
+        let runlog = Runlog::open(&runlog_path).unwrap();
+        let mut writer = RunlogWriter::new(vec![], &extra_lines, RUNLOG_FORMAT).unwrap();
+        for rec in runlog {
+            writer.write_record(&rec).unwrap();
+        }
+        let written = writer.finish().unwrap();
+
+        // Formatting the f fields explicitly should reproduce the runlog exactly
+        let output_dir = crate::test_utils::output_dir("runlog-writer");
+        let written_path = output_dir.join("runlog-writer-test.grl");
+        std::fs::write(&written_path, &written).unwrap();
+        assert_eq!(std::str::from_utf8(&written).unwrap(), original);
+
+        let expected: Vec<_> = Runlog::open(&runlog_path).unwrap().collect();
+        let actual: Vec<_> = Runlog::open(&written_path).unwrap().collect();
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_format_fortran_f() {
+        assert_eq!(format_fortran_f(0.0, 5, 4).as_deref(), Some(".0000"));
+        assert_eq!(format_fortran_f(0.25, 6, 4).as_deref(), Some("0.2500"));
+        assert_eq!(format_fortran_f(-0.5, 6, 4).as_deref(), Some("-.5000"));
+        assert_eq!(format_fortran_f(1.5, 5, 4), None);
+        assert_eq!(format_fortran_f(270.0, 6, 0).as_deref(), Some("  270."));
+        assert_eq!(format_fortran_f(123456.0, 6, 0), None);
+        assert_eq!(format_fortran_f(f64::NAN, 8, 2), None);
+    }
+
+    #[test]
+    fn test_writer_uses_header_column_names() {
+        let mut header = RunlogHeader::read(&benchmark_runlog()).unwrap();
+        header.column_names[0] = "Spectrum".to_string();
+        header.column_names[35] = "aipl_custom".to_string();
+
+        let writer = RunlogWriter::from_header(vec![], &header).unwrap();
+        let written = String::from_utf8(writer.finish().unwrap()).unwrap();
+        let column_line = written.lines().nth(3).unwrap();
+        assert_eq!(
+            column_line.split_whitespace().collect::<Vec<_>>(),
+            header.column_names
+        );
+        assert!(
+            column_line.starts_with("              Spectrum  "),
+            "{column_line}"
+        );
+        assert!(
+            column_line.ends_with(" lasf    wavtkr aipl_custom"),
+            "{column_line}"
+        );
+
+        header.column_names.pop();
+        assert!(RunlogWriter::from_header(vec![], &header).is_err());
+    }
+}